@@ -0,0 +1,90 @@
+//! Benchmarks of the hot paths of waveform generation.
+//!
+//! Run with `cargo bench --features bench`. Criterion arguments follow `--`,
+//! e.g. `cargo bench --features bench -- compile` to run one group.
+//!
+//! - `envelope`: sampling a Hann envelope.
+//! - `mix`: sampling pulse lists of a single channel.
+//! - `crosstalk`: merging and sampling pulse lists through a full crosstalk
+//!   matrix.
+//! - `compile`: the whole pipeline from a randomized benchmarking like
+//!   schedule to waveforms.
+//! - `build`: building and dropping a schedule of many pulses with the
+//!   builders, without measuring it. Compiling converts the builder-side
+//!   arena to an element tree, which `compile` covers.
+use bosing::{
+    bench_crosstalk, bench_envelope, bench_mix,
+    builder::{channel, compile, play, shift_phase, stack, CompileOptions, Schedule, Shapes},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// Sizes of the cases, the meaning depends on the group.
+const SIZES: [usize; 3] = [100, 1000, 10000];
+
+/// Number of pulses in the `build` group.
+const BUILD_SIZE: usize = 100_000;
+
+const SAMPLE_RATE: f64 = 2e9;
+const PULSE_WIDTH: f64 = 40e-9;
+
+fn hooks(c: &mut Criterion) {
+    hook(c, "envelope", bench_envelope);
+    hook(c, "mix", bench_mix);
+    hook(c, "crosstalk", bench_crosstalk);
+}
+
+/// Time the closures prepared by `prepare` for every size.
+fn hook<F: FnMut()>(c: &mut Criterion, name: &str, prepare: impl Fn(usize) -> F) {
+    let mut group = c.benchmark_group(name);
+    for size in SIZES {
+        let mut run = prepare(size);
+        group.bench_function(BenchmarkId::from_parameter(size), |b| b.iter(&mut run));
+    }
+    group.finish();
+}
+
+/// Compile a schedule of `size` gates spread over four channels.
+///
+/// Every gate is a virtual Z followed by one of a few pulses, like randomized
+/// benchmarking sequences.
+fn compile_schedule(c: &mut Criterion) {
+    let names: Vec<_> = (0..4).map(|i| format!("xy{}", i)).collect();
+    let shapes = Shapes::new().hann("hann");
+    let options = CompileOptions::new();
+    let mut group = c.benchmark_group("compile");
+    for size in SIZES {
+        let schedule = gates(&names, size);
+        let length = ((size / names.len() + 2) as f64 * PULSE_WIDTH * SAMPLE_RATE).ceil() as usize;
+        let channels: Vec<_> = names
+            .iter()
+            .map(|n| channel(n, SAMPLE_RATE, length + 16).base_freq(100e6))
+            .collect();
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| compile(&schedule, &channels, &shapes, &options).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// Build and drop a stack of `BUILD_SIZE` pulses.
+fn build_schedule(c: &mut Criterion) {
+    let names: Vec<_> = (0..4).map(|i| format!("xy{}", i)).collect();
+    let mut group = c.benchmark_group("build");
+    group.bench_function(BenchmarkId::from_parameter(BUILD_SIZE), |b| {
+        b.iter(|| gates(&names, BUILD_SIZE))
+    });
+    group.finish();
+}
+
+fn gates(names: &[String], size: usize) -> Schedule {
+    let mut schedule = stack();
+    for i in 0..size {
+        let channel = &names[i % names.len()];
+        schedule.push(shift_phase(channel, (i % 4) as f64 * 0.25));
+        schedule.push(play(channel, Some("hann"), [0.5, 0.25][i % 2], PULSE_WIDTH));
+    }
+    schedule.build().unwrap()
+}
+
+criterion_group!(benches, hooks, compile_schedule, build_schedule);
+criterion_main!(benches);
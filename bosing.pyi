@@ -1,5 +1,5 @@
-from collections.abc import Iterable, Mapping, Sequence
-from typing import ClassVar, Literal, Self, TypeAlias, final
+from collections.abc import Callable, Iterable, Mapping, Sequence
+from typing import Any, ClassVar, Literal, Self, TypeAlias, final
 
 import numpy as np
 import numpy.typing as npt
@@ -14,6 +14,12 @@ class Channel:
         *,
         delay: float = ...,
         align_level: int = ...,
+        baseline: complex = ...,
+        ringdown: Ringdown | None = ...,
+        net_zero: bool = ...,
+        idle_tone: IdleTone | None = ...,
+        time_grid: TimeGrid | None = ...,
+        predistortion: Predistortion | None = ...,
         iq_matrix: npt.ArrayLike | None = ...,
         offset: npt.ArrayLike | None = ...,
         iir: npt.ArrayLike | None = ...,
@@ -32,6 +38,18 @@ class Channel:
     @property
     def align_level(self) -> int: ...
     @property
+    def baseline(self) -> complex: ...
+    @property
+    def ringdown(self) -> Ringdown | None: ...
+    @property
+    def net_zero(self) -> bool: ...
+    @property
+    def idle_tone(self) -> IdleTone | None: ...
+    @property
+    def time_grid(self) -> TimeGrid | None: ...
+    @property
+    def predistortion(self) -> Predistortion | None: ...
+    @property
     def iq_matrix(self) -> np.ndarray | None: ...
     @property
     def offset(self) -> np.ndarray | None: ...
@@ -69,6 +87,50 @@ class Interp(Shape):
     @property
     def degree(self) -> float: ...
 
+@final
+class Expr(Shape):
+    def __new__(cls, expr: str) -> Self: ...
+    @property
+    def expr(self) -> str: ...
+
+@final
+class Exponential(Shape):
+    def __new__(
+        cls,
+        rise: float | Sequence[tuple[float, float]],
+        decay: float | Sequence[tuple[float, float]] | None = ...,
+    ) -> Self: ...
+    @property
+    def rise(self) -> list[tuple[float, float]]: ...
+    @property
+    def decay(self) -> list[tuple[float, float]]: ...
+
+@final
+class Normalized(Shape):
+    def __new__(cls, shape: Shape, normalization: Literal["peak", "area", "rms"]) -> Self: ...
+    @property
+    def shape(self) -> Shape: ...
+    @property
+    def normalization(self) -> Literal["peak", "area", "rms"]: ...
+
+@final
+class Sinc(Shape):
+    def __new__(cls, num_lobes: int) -> Self: ...
+    @property
+    def num_lobes(self) -> int: ...
+
+@final
+class ErfSquare(Shape):
+    def __new__(cls, rise_time: float) -> Self: ...
+    @property
+    def rise_time(self) -> float: ...
+
+@final
+class Custom(Shape):
+    def __new__(cls, func: Callable[[npt.NDArray[np.float64]], npt.ArrayLike]) -> Self: ...
+    @property
+    def func(self) -> Callable[[npt.NDArray[np.float64]], npt.ArrayLike]: ...
+
 class Element:
     @property
     def margin(self) -> tuple[float, float]: ...
@@ -82,6 +144,37 @@ class Element:
     def max_duration(self) -> float: ...
     @property
     def min_duration(self) -> float: ...
+    @property
+    def allow_oversize(self) -> bool | None: ...
+    def render_ascii(self, width: int = ...) -> str: ...
+    def timing_report(self) -> TimingReport: ...
+    def reserved_intervals(self) -> dict[str, list[tuple[float, float]]]: ...
+    def to_dot(self) -> str: ...
+    def to_mermaid(self) -> str: ...
+    def to_json(self, *, library: PulseLibrary | None = ...) -> str: ...
+    @staticmethod
+    def from_json(s: str) -> Element: ...
+    def to_msgpack(self, *, version: int | None = ...) -> bytes: ...
+    @staticmethod
+    def from_msgpack(data: bytes) -> Element: ...
+    def replace(self, path: Sequence[int], element: Element) -> Element: ...
+    def remap_channels(
+        self,
+        mapping: Mapping[str, str],
+        *,
+        channels: Iterable[str] | None = ...,
+    ) -> Element: ...
+    def expand_groups(self, groups: Mapping[str, Sequence[str]]) -> Element: ...
+    def slots(self) -> dict[str, list[str]]: ...
+    def fill_slots(self, fills: Mapping[str, Element]) -> Element: ...
+    def fill_idle(
+        self,
+        channels: Iterable[str],
+        fill: Play,
+        *,
+        min_gap: float = ...,
+    ) -> Absolute: ...
+    def resolve_calibrations(self, calibrations: Calibrations) -> Element: ...
 
 @final
 class Play(Element):
@@ -97,12 +190,18 @@ class Play(Element):
         frequency: float = ...,
         phase: float = ...,
         flexible: bool = ...,
+        hold: bool = ...,
+        phase_reference: Literal["absolute", "pulse", "iteration"] = ...,
+        calibration: str | None = ...,
+        fall_shape_id: str | None = ...,
+        fall_width: float | None = ...,
         margin: float | tuple[float, float] | None = ...,
         alignment: Literal["end", "start", "center", "stretch"] | Alignment | None = ...,
         phantom: bool = ...,
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     @property
     def channel_id(self) -> str: ...
@@ -122,6 +221,133 @@ class Play(Element):
     def phase(self) -> float: ...
     @property
     def flexible(self) -> bool: ...
+    @property
+    def hold(self) -> bool: ...
+    @property
+    def phase_reference(self) -> Literal["absolute", "pulse", "iteration"]: ...
+    @property
+    def calibration(self) -> str | None: ...
+    @property
+    def fall_shape_id(self) -> str | None: ...
+    @property
+    def fall_width(self) -> float: ...
+    @property
+    def pulse(self) -> tuple[str, int] | None: ...
+
+@final
+class PlaySamples(Element):
+    def __new__(
+        cls,
+        channel_id: str,
+        samples: npt.ArrayLike,
+        sample_rate: float,
+        *,
+        amplitude: float = ...,
+        frequency: float = ...,
+        phase: float = ...,
+        interpolate: bool = ...,
+        margin: float | tuple[float, float] | None = ...,
+        alignment: Literal["end", "start", "center", "stretch"] | Alignment | None = ...,
+        phantom: bool = ...,
+        duration: float | None = ...,
+        max_duration: float = ...,
+        min_duration: float = ...,
+        allow_oversize: bool | None = ...,
+    ) -> Self: ...
+    @property
+    def channel_id(self) -> str: ...
+    @property
+    def samples(self) -> npt.NDArray[np.complex128]: ...
+    @property
+    def sample_rate(self) -> float: ...
+    @property
+    def amplitude(self) -> float: ...
+    @property
+    def frequency(self) -> float: ...
+    @property
+    def phase(self) -> float: ...
+    @property
+    def interpolate(self) -> bool: ...
+
+@final
+class PulseLibrary:
+    def __new__(cls) -> Self: ...
+    def with_pulse(
+        self,
+        name: str,
+        shape_id: str | None,
+        amplitude: float,
+        width: float,
+        *,
+        plateau: float = ...,
+        drag_coef: float = ...,
+        version: int | None = ...,
+    ) -> PulseLibrary: ...
+    def pulses(self) -> list[tuple[str, int]]: ...
+    def play(
+        self,
+        name: str,
+        channel_id: str,
+        *,
+        version: int | None = ...,
+        frequency: float = ...,
+        phase: float = ...,
+        flexible: bool = ...,
+        margin: float | tuple[float, float] | None = ...,
+        alignment: Literal["end", "start", "center", "stretch"] | Alignment | None = ...,
+        phantom: bool = ...,
+        duration: float | None = ...,
+        max_duration: float = ...,
+        min_duration: float = ...,
+        allow_oversize: bool | None = ...,
+    ) -> Play: ...
+    def to_json(self) -> str: ...
+    @staticmethod
+    def from_json(s: str) -> PulseLibrary: ...
+
+@final
+class Calibration:
+    def __new__(
+        cls,
+        *,
+        amplitude: float | None = ...,
+        drag_coef: float | None = ...,
+        frequency: float | None = ...,
+        timestamp: float = ...,
+    ) -> Self: ...
+    @property
+    def amplitude(self) -> float | None: ...
+    @property
+    def drag_coef(self) -> float | None: ...
+    @property
+    def frequency(self) -> float | None: ...
+    @property
+    def timestamp(self) -> float: ...
+
+@final
+class Calibrations:
+    def __new__(
+        cls,
+        calibrations: Mapping[tuple[str, str], Calibration] | None = ...,
+    ) -> Self: ...
+    def with_calibration(
+        self, channel_id: str, gate: str, calibration: Calibration
+    ) -> Calibrations: ...
+    def get(self, channel_id: str, gate: str) -> Calibration | None: ...
+
+@final
+class ElementCache:
+    def __new__(cls) -> Self: ...
+    def intern(self, element: Element) -> Element: ...
+    def __len__(self) -> int: ...
+
+@final
+class CompileCache:
+    def __new__(cls, maxsize: int = ...) -> Self: ...
+    @property
+    def maxsize(self) -> int: ...
+    def clear(self) -> None: ...
+    def __len__(self) -> int: ...
 
 @final
 class ShiftPhase(Element):
@@ -136,6 +362,7 @@ class ShiftPhase(Element):
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     @property
     def channel_id(self) -> str: ...
@@ -155,6 +382,7 @@ class SetPhase(Element):
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     @property
     def channel_id(self) -> str: ...
@@ -174,6 +402,7 @@ class ShiftFreq(Element):
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     @property
     def channel_id(self) -> str: ...
@@ -193,6 +422,7 @@ class SetFreq(Element):
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     @property
     def channel_id(self) -> str: ...
@@ -212,6 +442,7 @@ class SwapPhase(Element):
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     @property
     def channel_id1(self) -> str: ...
@@ -223,15 +454,52 @@ class Barrier(Element):
     def __new__(
         cls,
         *channel_ids: str,
+        flex: float = ...,
+        slot: str | None = ...,
         margin: float | tuple[float, float] | None = ...,
         alignment: Literal["end", "start", "center", "stretch"] | Alignment | None = ...,
         phantom: bool = ...,
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     @property
     def channel_ids(self) -> Sequence[str]: ...
+    @property
+    def flex(self) -> float: ...
+    @property
+    def slot(self) -> str | None: ...
+
+@final
+class Experiment:
+    def __new__(
+        cls,
+        schedule: Element,
+        shots: int,
+        relaxation: float = ...,
+        *,
+        shots_var: str | None = ...,
+    ) -> Self: ...
+    @property
+    def schedule(self) -> Element: ...
+    @property
+    def shots(self) -> int: ...
+    @property
+    def relaxation(self) -> float: ...
+    @property
+    def shots_var(self) -> str | None: ...
+    def to_element(self) -> Repeat: ...
+
+@final
+class SequenceTable:
+    def __new__(cls, schedules: Sequence[Element], order: Sequence[int]) -> Self: ...
+    @property
+    def segments(self) -> list[Element]: ...
+    @property
+    def entries(self) -> list[tuple[int, int]]: ...
+    @property
+    def shots(self) -> int: ...
 
 @final
 class Repeat(Element):
@@ -241,20 +509,44 @@ class Repeat(Element):
         count: int,
         spacing: float = ...,
         *,
+        count_var: str | None = ...,
         margin: float | tuple[float, float] | None = ...,
         alignment: Literal["end", "start", "center", "stretch"] | Alignment | None = ...,
         phantom: bool = ...,
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     @property
     def child(self) -> Element: ...
     @property
     def count(self) -> int: ...
     @property
+    def count_var(self) -> str | None: ...
+    @property
     def spacing(self) -> float: ...
 
+@final
+class ScaleAmplitude(Element):
+    def __new__(
+        cls,
+        child: Element,
+        factor: float,
+        *,
+        margin: float | tuple[float, float] | None = ...,
+        alignment: Literal["end", "start", "center", "stretch"] | Alignment | None = ...,
+        phantom: bool = ...,
+        duration: float | None = ...,
+        max_duration: float = ...,
+        min_duration: float = ...,
+        allow_oversize: bool | None = ...,
+    ) -> Self: ...
+    @property
+    def child(self) -> Element: ...
+    @property
+    def factor(self) -> float: ...
+
 @final
 class Direction:
     Forward: ClassVar[Direction]
@@ -268,28 +560,37 @@ class Stack(Element):
         cls,
         *children: Element,
         direction: Literal["forward", "backward"] | Direction = ...,
+        padding: float | tuple[float, float] | None = ...,
+        child_margin: float | tuple[float, float] | None = ...,
         margin: float | tuple[float, float] | None = ...,
         alignment: Literal["end", "start", "center", "stretch"] | Alignment | None = ...,
         phantom: bool = ...,
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     def with_children(self, *children: Element) -> Stack: ...
     @property
     def direction(self) -> Direction: ...
     @property
+    def padding(self) -> tuple[float, float]: ...
+    @property
+    def child_margin(self) -> tuple[float, float]: ...
+    @property
     def children(self) -> Sequence[Element]: ...
 
 _AbsoluteEntryLike: TypeAlias = Element | tuple[float, Element] | AbsoluteEntry
 
 @final
 class AbsoluteEntry:
-    def __new__(cls, time: float, element: Element) -> Self: ...
+    def __new__(cls, time: float, element: Element, *, from_end: bool = ...) -> Self: ...
     @property
     def time(self) -> float: ...
     @property
     def element(self) -> Element: ...
+    @property
+    def from_end(self) -> bool: ...
     @staticmethod
     def convert(obj: _AbsoluteEntryLike) -> AbsoluteEntry: ...
 
@@ -304,6 +605,7 @@ class Absolute(Element):
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     def with_children(self, *children: _AbsoluteEntryLike) -> Absolute: ...
     @property
@@ -351,12 +653,15 @@ class Grid(Element):
         cls,
         *children: _GridEntryLike,
         columns: Sequence[str | float | GridLength] = ...,
+        padding: float | tuple[float, float] | None = ...,
+        child_margin: float | tuple[float, float] | None = ...,
         margin: float | tuple[float, float] | None = ...,
         alignment: Literal["end", "start", "center", "stretch"] | Alignment | None = ...,
         phantom: bool = ...,
         duration: float | None = ...,
         max_duration: float = ...,
         min_duration: float = ...,
+        allow_oversize: bool | None = ...,
     ) -> Self: ...
     def with_children(
         self,
@@ -366,6 +671,10 @@ class Grid(Element):
     def children(self) -> Sequence[GridEntry]: ...
     @property
     def columns(self) -> Sequence[GridLength]: ...
+    @property
+    def padding(self) -> tuple[float, float]: ...
+    @property
+    def child_margin(self) -> tuple[float, float]: ...
 
 def generate_waveforms(
     channels: Mapping[str, Channel],
@@ -376,4 +685,240 @@ def generate_waveforms(
     amp_tolerance: float = ...,
     allow_oversize: bool = ...,
     crosstalk: tuple[npt.ArrayLike, Sequence[str]] | None = ...,
+    allow_truncate: bool = ...,
+    time_grid: TimeGrid | None = ...,
+    fractional_delay: bool = ...,
+    oversampling: int = ...,
+    observer: Callable[[Element, float, float], None] | None = ...,
+    cache: CompileCache | None = ...,
+) -> dict[str, np.ndarray]: ...
+def generate_sequence_waveforms(
+    channels: Mapping[str, Channel],
+    shapes: Mapping[str, Shape],
+    table: SequenceTable,
+    *,
+    time_tolerance: float = ...,
+    amp_tolerance: float = ...,
+    allow_oversize: bool = ...,
+    crosstalk: tuple[npt.ArrayLike, Sequence[str]] | None = ...,
+    allow_truncate: bool = ...,
+    time_grid: TimeGrid | None = ...,
+    fractional_delay: bool = ...,
+    oversampling: int = ...,
+) -> list[dict[str, np.ndarray]]: ...
+
+@final
+class TimeGrid:
+    def __new__(
+        cls,
+        step: float,
+        *,
+        min_length: float = ...,
+        rounding: Literal["nearest", "floor", "ceil"] = ...,
+    ) -> Self: ...
+    @property
+    def step(self) -> float: ...
+    @property
+    def min_length(self) -> float: ...
+    @property
+    def rounding(self) -> Literal["nearest", "floor", "ceil"]: ...
+
+@final
+class Ringdown:
+    def __new__(cls, tau: float, length: float) -> Self: ...
+    @property
+    def tau(self) -> float: ...
+    @property
+    def length(self) -> float: ...
+
+@final
+class IdleTone:
+    def __new__(cls, amplitude: float, freq: float, *, phase: float = ...) -> Self: ...
+    @property
+    def amplitude(self) -> float: ...
+    @property
+    def freq(self) -> float: ...
+    @property
+    def phase(self) -> float: ...
+
+@final
+class Predistortion:
+    def __new__(cls, input: Sequence[float], output: Sequence[float]) -> Self: ...
+    @staticmethod
+    def polynomial(coefficients: Sequence[float]) -> Predistortion: ...
+    @property
+    def input(self) -> list[float] | None: ...
+    @property
+    def output(self) -> list[float] | None: ...
+    @property
+    def coefficients(self) -> list[float] | None: ...
+    def __call__(self, amplitude: float) -> float: ...
+
+@final
+class PulseList:
+    def to_json(self) -> str: ...
+    @staticmethod
+    def from_json(s: str) -> PulseList: ...
+    def to_msgpack(self, *, version: int | None = ...) -> bytes: ...
+    @staticmethod
+    def from_msgpack(data: bytes) -> PulseList: ...
+    def bins(self) -> list[PulseBin]: ...
+    def sample_budget(self, sample_rate: float) -> tuple[int, int]: ...
+    def merge_adjacent(
+        self,
+        *,
+        time_tolerance: float = ...,
+        amp_tolerance: float = ...,
+    ) -> tuple[PulseList, int]: ...
+
+@final
+class PulseBin:
+    @property
+    def shape(self) -> str | None: ...
+    @property
+    def width(self) -> float: ...
+    @property
+    def plateau(self) -> float: ...
+    @property
+    def fall_shape(self) -> str | None: ...
+    @property
+    def fall_width(self) -> float: ...
+    @property
+    def global_freq(self) -> float: ...
+    @property
+    def local_freq(self) -> float: ...
+    @property
+    def count(self) -> int: ...
+    def samples(self, sample_rate: float) -> int: ...
+
+def flatten_schedule(
+    schedule: Element, *, keep_loops: bool = ...
+) -> list[dict[str, Any]]: ...
+def generate_pulse_lists(
+    channels: Mapping[str, Channel],
+    shapes: Mapping[str, Shape],
+    schedule: Element,
+    *,
+    time_tolerance: float = ...,
+    amp_tolerance: float = ...,
+    allow_oversize: bool = ...,
+    time_grid: TimeGrid | None = ...,
+    observer: Callable[[Element, float, float], None] | None = ...,
+    cache: CompileCache | None = ...,
+) -> dict[str, PulseList]: ...
+def schedule_fingerprint(
+    channels: Mapping[str, Channel],
+    shapes: Mapping[str, Shape],
+    schedule: Element,
+    *,
+    time_tolerance: float = ...,
+    amp_tolerance: float = ...,
+    allow_oversize: bool = ...,
+    time_grid: TimeGrid | None = ...,
+) -> int: ...
+def sample_pulse_lists(
+    channels: Mapping[str, Channel],
+    pulse_lists: Mapping[str, PulseList],
+    *,
+    time_tolerance: float = ...,
+    amp_tolerance: float = ...,
+    crosstalk: tuple[npt.ArrayLike, Sequence[str]] | None = ...,
+    allow_truncate: bool = ...,
+    fractional_delay: bool = ...,
+    oversampling: int = ...,
+) -> dict[str, np.ndarray]: ...
+def prewarm_pulse_lists(
+    channels: Mapping[str, Channel],
+    pulse_lists: Mapping[str, PulseList],
+    *,
+    crosstalk: tuple[npt.ArrayLike, Sequence[str]] | None = ...,
+    fractional_delay: bool = ...,
+    oversampling: int = ...,
+) -> int: ...
+
+@final
+class ChannelTiming:
+    @property
+    def channel(self) -> str: ...
+    @property
+    def pulses(self) -> int: ...
+    @property
+    def busy(self) -> float: ...
+    @property
+    def idle(self) -> float: ...
+    @property
+    def longest_gap(self) -> float: ...
+    @property
+    def barrier_wait(self) -> float: ...
+
+@final
+class TimingReport:
+    @property
+    def duration(self) -> float: ...
+    @property
+    def channels(self) -> list[ChannelTiming]: ...
+
+@final
+class WaveformStats:
+    @property
+    def energy(self) -> float: ...
+    @property
+    def area(self) -> complex: ...
+
+def waveform_stats(
+    channels: Mapping[str, Channel],
+    waveforms: Mapping[str, np.ndarray],
+) -> dict[str, WaveformStats]: ...
+
+@final
+class WaveformComparison:
+    @property
+    def max_error(self) -> float: ...
+    @property
+    def rms_error(self) -> float: ...
+    @property
+    def shift(self) -> int: ...
+    @property
+    def within_tolerance(self) -> bool: ...
+
+def compare_waveforms(
+    a: np.ndarray,
+    b: np.ndarray,
+    amp_tol: float,
+    time_shift_search: int = ...,
+) -> WaveformComparison: ...
+def integration_weights(
+    channels: Mapping[str, Channel],
+    waveforms: Mapping[str, np.ndarray],
+    *,
+    decay: float | None = ...,
 ) -> dict[str, np.ndarray]: ...
+def resample(
+    waveform: npt.ArrayLike, sample_rate: float, target_rate: float
+) -> np.ndarray: ...
+
+def plan_lo(
+    drive_freqs: Mapping[str, float],
+    max_if: float,
+    guard: float = ...,
+) -> tuple[float, dict[str, float]]: ...
+def plan_triggers(
+    devices: Mapping[str, tuple[float, float]],
+    *,
+    time_tolerance: float = ...,
+) -> dict[str, tuple[int, float]]: ...
+def locate_times(
+    channels: Mapping[str, Channel],
+    times: Iterable[tuple[str, float]],
+    *,
+    devices: Mapping[str, tuple[float, float, Sequence[str]]] | None = ...,
+    time_tolerance: float = ...,
+) -> list[dict[str, Any]]: ...
+def multiplex_readout(
+    channel_id: str,
+    tones: Iterable[tuple[float, float, float, str | None]],
+    width: float,
+    *,
+    plateau: float = ...,
+    drag_coef: float = ...,
+) -> Absolute: ...
@@ -0,0 +1,22 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        use tonic_build::manual::{Builder, Method, Service};
+
+        let sample = Method::builder()
+            .name("sample")
+            .route_name("Sample")
+            .input_type("crate::grpc::SampleRequest")
+            .output_type("crate::grpc::WaveformChunk")
+            .codec_path("tonic::codec::ProstCodec")
+            .server_streaming()
+            .build();
+        let service = Service::builder()
+            .name("PulseGen")
+            .package("bosing")
+            .method(sample)
+            .build();
+        Builder::new().build_client(false).compile(&[service]);
+    }
+}
@@ -0,0 +1,160 @@
+/* Generated by cbindgen from src/ffi.rs. Do not edit manually. */
+
+#ifndef BOSING_H
+#define BOSING_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Pulse sampler with pulse lists of multiple channels.
+typedef struct BosingSampler BosingSampler;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create a new sampler.
+//
+// Returns null if the tolerances are invalid. The sampler must be released by
+// [`bosing_sampler_free`].
+struct BosingSampler *bosing_sampler_new(double amp_tolerance, double time_tolerance);
+
+// Release a sampler created by [`bosing_sampler_new`].
+//
+// # Safety
+//
+// `sampler` must be null or a pointer returned by [`bosing_sampler_new`] that
+// has not been released.
+void bosing_sampler_free(struct BosingSampler *sampler);
+
+// Add a channel. Existing channel with the same name is replaced.
+//
+// The sampled waveform has `length` samples with 1 row if `is_real` and 2
+// rows (I and Q) otherwise.
+//
+// # Safety
+//
+// `sampler` must be a valid sampler and `name` a null-terminated UTF-8
+// string.
+int32_t bosing_sampler_add_channel(struct BosingSampler *sampler,
+                                   const char *name,
+                                   double base_freq,
+                                   double sample_rate,
+                                   double delay,
+                                   int32_t align_level,
+                                   size_t length,
+                                   bool is_real);
+
+// Add a Hann shape. Existing shape with the same name is replaced.
+//
+// # Safety
+//
+// `sampler` must be a valid sampler and `name` a null-terminated UTF-8
+// string.
+int32_t bosing_sampler_add_hann_shape(struct BosingSampler *sampler, const char *name);
+
+// Add an interpolated shape. Existing shape with the same name is replaced.
+//
+// See `Interp` in the python API for the meaning of the parameters.
+//
+// # Safety
+//
+// `sampler` must be a valid sampler, `name` a null-terminated UTF-8 string,
+// and `knots` and `controls` must point to `n_knots` and `n_controls`
+// values respectively.
+int32_t bosing_sampler_add_interp_shape(struct BosingSampler *sampler,
+                                        const char *name,
+                                        const double *knots,
+                                        size_t n_knots,
+                                        const double *controls,
+                                        size_t n_controls,
+                                        size_t degree);
+
+// Push a pulse to the pulse list of a channel.
+//
+// `shape` may be null for a rectangular pulse. `frequency` is relative to
+// the base frequency of the channel and `phase` is in cycles.
+//
+// # Safety
+//
+// `sampler` must be a valid sampler, `channel` a null-terminated UTF-8
+// string, and `shape` null or a null-terminated UTF-8 string.
+int32_t bosing_sampler_push(struct BosingSampler *sampler,
+                            const char *channel,
+                            const char *shape,
+                            double time,
+                            double width,
+                            double plateau,
+                            double amplitude,
+                            double drag_coef,
+                            double frequency,
+                            double phase);
+
+// Push a pulse with a user ID to the pulse list of a channel.
+//
+// Same as [`bosing_sampler_push`], the ID is reported by
+// [`bosing_sampler_contributors`] for the samples the pulse contributes to.
+//
+// # Safety
+//
+// Same as [`bosing_sampler_push`].
+int32_t bosing_sampler_push_with_id(struct BosingSampler *sampler,
+                                    const char *channel,
+                                    const char *shape,
+                                    double time,
+                                    double width,
+                                    double plateau,
+                                    double amplitude,
+                                    double drag_coef,
+                                    double frequency,
+                                    double phase,
+                                    uint64_t id);
+
+// IDs of the pulses contributing to `count` samples of a channel starting
+// from sample `first`, in ascending order.
+//
+// At most `ids_len` IDs are written to `ids`, and the number of all found
+// IDs is written to `n_ids`. Only pulses pushed by
+// [`bosing_sampler_push_with_id`] are reported.
+//
+// # Safety
+//
+// `sampler` must be a valid sampler, `channel` a null-terminated UTF-8
+// string, `ids` must point to `ids_len` writable values, and `n_ids` must be
+// a valid pointer.
+int32_t bosing_sampler_contributors(struct BosingSampler *sampler,
+                                    const char *channel,
+                                    size_t first,
+                                    size_t count,
+                                    uint64_t *ids,
+                                    size_t ids_len,
+                                    size_t *n_ids);
+
+// Sample the pulses of a channel into a caller provided buffer.
+//
+// The buffer is overwritten with the row-major waveform and must hold
+// `length` values for real channels and `2 * length` values otherwise.
+//
+// # Safety
+//
+// `sampler` must be a valid sampler, `channel` a null-terminated UTF-8
+// string, and `buffer` must point to `buffer_len` writable values.
+int32_t bosing_sampler_sample(struct BosingSampler *sampler,
+                              const char *channel,
+                              double *buffer,
+                              size_t buffer_len);
+
+// Message of the last failure on the calling thread.
+//
+// Returns null if no call has failed. The string is valid until the next
+// failing call on the same thread.
+const char *bosing_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BOSING_H */
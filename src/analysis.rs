@@ -0,0 +1,203 @@
+//! Statistics of sampled waveforms.
+#[cfg(feature = "spectrum")]
+pub(crate) mod spectrum;
+
+#[cfg(any(feature = "python", test))]
+use ndarray::Array2;
+use ndarray::{ArrayView2, Axis};
+use num::complex::Complex64;
+
+use crate::quant::Frequency;
+#[cfg(any(feature = "python", test))]
+use crate::quant::Time;
+
+/// Time integrals of a waveform with rows `[I]` or `[I, Q]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct WaveformStats {
+    /// Integral of the squared magnitude, in full scale squared times
+    /// seconds.
+    pub(crate) energy: f64,
+    /// Signed integral, in full scale times seconds. The imaginary part is
+    /// zero for real waveforms.
+    pub(crate) area: Complex64,
+}
+
+pub(crate) fn waveform_stats(waveform: ArrayView2<f64>, sample_rate: Frequency) -> WaveformStats {
+    let dt = sample_rate.dt().value();
+    let mut area = [0.0; 2];
+    let mut energy = 0.0;
+    for (row, area) in waveform.axis_iter(Axis(0)).zip(&mut area) {
+        *area = row.sum() * dt;
+        energy += row.dot(&row) * dt;
+    }
+    WaveformStats {
+        energy,
+        area: Complex64::new(area[0], area[1]),
+    }
+}
+
+/// Difference of two waveforms with rows `[I]` or `[I, Q]`.
+#[cfg(any(feature = "python", test))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct WaveformComparison {
+    /// Largest magnitude of the sample difference, in full scale.
+    pub(crate) max_error: f64,
+    /// Root mean square of the magnitude of the sample difference.
+    pub(crate) rms_error: f64,
+    /// Delay of `b` relative to `a` in samples.
+    pub(crate) shift: isize,
+}
+
+/// Compare `b` with `a` delayed by up to `max_shift` samples in each
+/// direction.
+///
+/// The shift with the smallest squared error is used, preferring smaller
+/// shifts on ties. Samples shifted out of either waveform are compared with
+/// zero, so waveforms of different lengths can be compared.
+///
+/// # Panics
+///
+/// Panics if the waveforms have different numbers of rows.
+#[cfg(any(feature = "python", test))]
+pub(crate) fn compare_waveforms(
+    a: ArrayView2<f64>,
+    b: ArrayView2<f64>,
+    max_shift: usize,
+) -> WaveformComparison {
+    assert_eq!(a.nrows(), b.nrows(), "Waveforms should have the same rows");
+    let max_shift = max_shift as isize;
+    let shift = (-max_shift..=max_shift)
+        .map(|s| (s, squared_errors(a.view(), b.view(), s).sum::<f64>()))
+        .min_by(|(s1, e1), (s2, e2)| e1.total_cmp(e2).then(s1.abs().cmp(&s2.abs())))
+        .map_or(0, |(s, _)| s);
+    let (mut max, mut sum, mut len) = (0.0f64, 0.0, 0);
+    for e in squared_errors(a.view(), b.view(), shift) {
+        max = max.max(e);
+        sum += e;
+        len += 1;
+    }
+    WaveformComparison {
+        max_error: max.sqrt(),
+        rms_error: if len > 0 {
+            (sum / len as f64).sqrt()
+        } else {
+            0.0
+        },
+        shift,
+    }
+}
+
+/// Matched filter weights of a waveform with rows `[I]` or `[I, Q]`.
+///
+/// The weights are the complex conjugate of the waveform, so integrating the
+/// product of the weights and the signal projects it onto the expected
+/// waveform. With `decay`, the weights are multiplied by `exp(-t / decay)`
+/// with `t` the time from the start of the waveform. The weights are scaled to
+/// a largest magnitude of one and are zero for a zero waveform.
+#[cfg(any(feature = "python", test))]
+pub(crate) fn integration_weights(
+    waveform: ArrayView2<f64>,
+    sample_rate: Frequency,
+    decay: Option<Time>,
+) -> Array2<f64> {
+    let dt = sample_rate.dt().value();
+    let mut weights = waveform.to_owned();
+    if let Some(mut q) = weights.rows_mut().into_iter().nth(1) {
+        q.mapv_inplace(|x| -x);
+    }
+    if let Some(decay) = decay {
+        for (n, mut column) in weights.columns_mut().into_iter().enumerate() {
+            column *= (-(n as f64) * dt / decay.value()).exp();
+        }
+    }
+    let max = weights
+        .columns()
+        .into_iter()
+        .map(|c| c.dot(&c).sqrt())
+        .fold(0.0, f64::max);
+    if max > 0.0 {
+        weights /= max;
+    }
+    weights
+}
+
+/// Squared magnitude of `b[n] - a[n - shift]` over the union of both
+/// waveforms, with zero outside of them.
+#[cfg(any(feature = "python", test))]
+fn squared_errors<'a>(
+    a: ArrayView2<'a, f64>,
+    b: ArrayView2<'a, f64>,
+    shift: isize,
+) -> impl Iterator<Item = f64> + 'a {
+    let (len_a, len_b) = (a.ncols() as isize, b.ncols() as isize);
+    let sample = |w: ArrayView2<'a, f64>, row: usize, n: isize| {
+        usize::try_from(n)
+            .ok()
+            .and_then(|n| w.get((row, n)).copied())
+            .unwrap_or(0.0)
+    };
+    (shift.min(0)..(len_a + shift).max(len_b)).map(move |n| {
+        (0..a.nrows())
+            .map(|row| (sample(b, row, n) - sample(a, row, n - shift)).powi(2))
+            .sum()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let sample_rate = Frequency::new(1e9).unwrap();
+        let complex = array![[0.5, 0.5, -0.25, 0.0], [0.0, 0.5, 0.5, 0.0]];
+        let stats = waveform_stats(complex.view(), sample_rate);
+        assert!((stats.energy - 1.0625e-9).abs() < 1e-20);
+        assert!((stats.area - Complex64::new(0.75e-9, 1e-9)).norm() < 1e-20);
+
+        let real = array![[0.5, -0.5, 0.25]];
+        let stats = waveform_stats(real.view(), sample_rate);
+        assert!((stats.energy - 0.5625e-9).abs() < 1e-20);
+        assert_eq!(stats.area.im, 0.0);
+        assert!((stats.area.re - 0.25e-9).abs() < 1e-20);
+    }
+
+    #[test]
+    fn weights() {
+        let sample_rate = Frequency::new(1e9).unwrap();
+        let waveform = array![[0.2, 0.0, -0.1], [0.0, 0.1, 0.0]];
+
+        let weights = integration_weights(waveform.view(), sample_rate, None);
+        assert_eq!(weights, array![[1.0, 0.0, -0.5], [0.0, -0.5, 0.0]]);
+
+        let decay = Time::new(1e-9).unwrap();
+        let weights = integration_weights(waveform.view(), sample_rate, Some(decay));
+        assert!((weights[(1, 1)] + 0.5 / 1f64.exp()).abs() < 1e-15);
+        assert!((weights[(0, 2)] + 0.5 / 2f64.exp()).abs() < 1e-15);
+
+        let zero = Array2::zeros((1, 4));
+        let weights = integration_weights(zero.view(), sample_rate, Some(decay));
+        assert_eq!(weights, zero);
+    }
+
+    #[test]
+    fn compare() {
+        let a = array![[0.0, 0.5, 1.0, 0.5, 0.0]];
+        let b = array![[0.0, 0.0, 0.5, 1.0, 0.5, 0.1]];
+
+        let aligned = compare_waveforms(a.view(), b.view(), 0);
+        assert_eq!(aligned.shift, 0);
+        assert!((aligned.max_error - 0.5).abs() < 1e-15);
+
+        let shifted = compare_waveforms(a.view(), b.view(), 3);
+        assert_eq!(shifted.shift, 1);
+        assert!((shifted.max_error - 0.1).abs() < 1e-15);
+        assert!((shifted.rms_error - (0.01f64 / 6.0).sqrt()).abs() < 1e-15);
+
+        let same = compare_waveforms(a.view(), a.view(), 2);
+        assert_eq!(same.shift, 0);
+        assert_eq!(same.max_error, 0.0);
+    }
+}
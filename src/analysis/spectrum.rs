@@ -0,0 +1,330 @@
+//! Power spectra of sampled waveforms for leakage checks.
+use std::f64::consts::{PI, TAU};
+
+use anyhow::{bail, Result};
+use ndarray::ArrayView2;
+use num::complex::Complex64;
+
+use crate::{
+    diagnostics::{Diagnostics, Warning},
+    quant::{ChannelId, Frequency},
+};
+
+/// Window applied to the waveform before the transform.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+    Blackman,
+}
+
+impl Window {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Window::Rectangular => "rectangular",
+            Window::Hann => "hann",
+            Window::Blackman => "blackman",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        [Window::Rectangular, Window::Hann, Window::Blackman]
+            .into_iter()
+            .find(|w| w.name() == name)
+    }
+
+    /// Symmetric window of `length` points.
+    fn coefficients(&self, length: usize) -> Vec<f64> {
+        if length < 2 {
+            return vec![1.0; length];
+        }
+        let n = (length - 1) as f64;
+        (0..length)
+            .map(|i| {
+                let x = i as f64 / n;
+                match self {
+                    Window::Rectangular => 1.0,
+                    Window::Hann => 0.5 - 0.5 * (TAU * x).cos(),
+                    Window::Blackman => 0.42 - 0.5 * (TAU * x).cos() + 0.08 * (2.0 * TAU * x).cos(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Two-sided power spectrum sorted by frequency.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Spectrum {
+    pub(crate) frequencies: Vec<f64>,
+    /// Power in dB relative to a full scale tone, so a complex tone of
+    /// amplitude 1 peaks at 0 dB.
+    pub(crate) power_db: Vec<f64>,
+}
+
+/// Compute the power spectrum of a waveform with rows `[I]` or `[I, Q]`.
+///
+/// The waveform is windowed and zero-padded to `nfft` points, which defaults
+/// to the next power of two of the waveform length. The power is normalized by
+/// the window gain, so the peak of a tone doesn't depend on the window or the
+/// padding.
+pub(crate) fn spectrum(
+    waveform: ArrayView2<f64>,
+    sample_rate: Frequency,
+    window: Window,
+    nfft: Option<usize>,
+) -> Result<Spectrum> {
+    let length = waveform.shape()[1];
+    if length == 0 {
+        bail!("Waveform is empty");
+    }
+    let nfft = nfft.unwrap_or(length.next_power_of_two());
+    if !nfft.is_power_of_two() || nfft < length {
+        bail!(
+            "nfft should be a power of two not less than the waveform length {}, got {}",
+            length,
+            nfft
+        );
+    }
+    let coefficients = window.coefficients(length);
+    let gain: f64 = coefficients.iter().sum();
+    let mut buffer = vec![Complex64::ZERO; nfft];
+    for (i, (b, w)) in buffer.iter_mut().zip(&coefficients).enumerate() {
+        let q = if waveform.shape()[0] > 1 {
+            waveform[(1, i)]
+        } else {
+            0.0
+        };
+        *b = Complex64::new(waveform[(0, i)], q) * *w;
+    }
+    fft_inplace(&mut buffer);
+    // Shift the zero frequency to the center.
+    buffer.rotate_left(nfft / 2);
+    let df = sample_rate.value() / nfft as f64;
+    let half = (nfft / 2) as f64;
+    let frequencies = (0..nfft).map(|i| (i as f64 - half) * df).collect();
+    let power_db = buffer
+        .iter()
+        .map(|x| 10.0 * (x.norm_sqr() / (gain * gain)).log10())
+        .collect();
+    Ok(Spectrum {
+        frequencies,
+        power_db,
+    })
+}
+
+/// Peak power near a frequency that exceeds the threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Leakage {
+    pub(crate) frequency: f64,
+    pub(crate) power_db: f64,
+}
+
+impl Spectrum {
+    /// Flag the `frequencies` where the peak power within `bandwidth / 2`, or
+    /// at the nearest bin, exceeds `threshold_db`.
+    pub(crate) fn leakage(
+        &self,
+        frequencies: &[f64],
+        bandwidth: f64,
+        threshold_db: f64,
+    ) -> Vec<Leakage> {
+        frequencies
+            .iter()
+            .filter_map(|&frequency| {
+                let nearest = self
+                    .frequencies
+                    .iter()
+                    .map(|f| (f - frequency).abs())
+                    .enumerate()
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(i, _)| i)?;
+                let power_db = self
+                    .frequencies
+                    .iter()
+                    .zip(&self.power_db)
+                    .filter(|(f, _)| (*f - frequency).abs() <= bandwidth / 2.0)
+                    .map(|(_, p)| *p)
+                    .fold(self.power_db[nearest], f64::max);
+                (power_db > threshold_db).then_some(Leakage {
+                    frequency,
+                    power_db,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Sensitive frequencies checked against the spectra of the waveforms, e.g.
+/// neighbor qubits or two-level systems.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LeakageCheck {
+    /// Name and frequency before the up-conversion of the channels.
+    pub(crate) frequencies: Vec<(String, Frequency)>,
+    pub(crate) threshold_db: f64,
+    pub(crate) bandwidth: Frequency,
+    pub(crate) window: Window,
+}
+
+impl LeakageCheck {
+    /// Warn about the sensitive frequencies where the waveform of `channel`,
+    /// up-converted by `lo_freq`, exceeds the threshold.
+    ///
+    /// Frequencies outside the Nyquist band of the channel around the LO are
+    /// skipped.
+    pub(crate) fn check(
+        &self,
+        channel: ChannelId,
+        waveform: ArrayView2<f64>,
+        sample_rate: Frequency,
+        lo_freq: Frequency,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<()> {
+        if waveform.ncols() == 0 {
+            return Ok(());
+        }
+        let (names, frequencies): (Vec<_>, Vec<_>) = self
+            .frequencies
+            .iter()
+            .map(|(n, f)| (n, (*f - lo_freq).value()))
+            .filter(|(_, f)| f.abs() <= sample_rate.value() / 2.0)
+            .unzip();
+        let spectrum = spectrum(waveform, sample_rate, self.window, None)?;
+        let leakage = spectrum.leakage(&frequencies, self.bandwidth.value(), self.threshold_db);
+        for l in leakage {
+            let i = frequencies
+                .iter()
+                .position(|f| *f == l.frequency)
+                .expect("Leakage should be at a sensitive frequency");
+            diagnostics.warn(Warning::Leakage {
+                channel,
+                name: names[i].clone(),
+                frequency: Frequency::new(l.frequency)? + lo_freq,
+                power_db: l.power_db,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// In-place iterative radix-2 FFT, the length has to be a power of two.
+fn fft_inplace(buffer: &mut [Complex64]) {
+    let n = buffer.len();
+    debug_assert!(n.is_power_of_two());
+    let bits = n.trailing_zeros();
+    if bits == 0 {
+        return;
+    }
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+    let mut size = 2;
+    while size <= n {
+        let step = Complex64::from_polar(1.0, -2.0 * PI / size as f64);
+        for chunk in buffer.chunks_exact_mut(size) {
+            let (lower, upper) = chunk.split_at_mut(size / 2);
+            let mut twiddle = Complex64::new(1.0, 0.0);
+            for (a, b) in lower.iter_mut().zip(upper) {
+                let t = *b * twiddle;
+                *b = *a - t;
+                *a += t;
+                twiddle *= step;
+            }
+        }
+        size *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::*;
+
+    #[test]
+    fn fft_matches_dft() {
+        let input: Vec<_> = (0..16)
+            .map(|i| Complex64::new((i as f64 * 0.7).sin(), (i as f64 * 0.3).cos()))
+            .collect();
+        let mut output = input.clone();
+        fft_inplace(&mut output);
+        for (k, x) in output.iter().enumerate() {
+            let expected: Complex64 = input
+                .iter()
+                .enumerate()
+                .map(|(i, v)| v * Complex64::from_polar(1.0, -TAU * (i * k) as f64 / 16.0))
+                .sum();
+            assert!((x - expected).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn tone() {
+        let sample_rate = Frequency::new(1e9).unwrap();
+        // Complex tone of amplitude 0.5 at 125 MHz, on a bin of the transform.
+        let waveform = Array2::from_shape_fn((2, 64), |(r, i)| {
+            let phase = TAU * 125e6 * i as f64 / 1e9;
+            0.5 * if r == 0 { phase.cos() } else { phase.sin() }
+        });
+        let spectrum = spectrum(waveform.view(), sample_rate, Window::Rectangular, None).unwrap();
+        assert_eq!(spectrum.frequencies.len(), 64);
+        let (peak, power) = spectrum
+            .frequencies
+            .iter()
+            .zip(&spectrum.power_db)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        assert_eq!(*peak, 125e6);
+        assert!((power - 20.0 * 0.5f64.log10()).abs() < 1e-9);
+
+        let leakage = spectrum.leakage(&[125e6, -125e6, 300e6], 20e6, -40.0);
+        assert_eq!(leakage.len(), 1);
+        assert_eq!(leakage[0].frequency, 125e6);
+
+        assert!(super::spectrum(waveform.view(), sample_rate, Window::Hann, Some(48)).is_err());
+    }
+
+    #[test]
+    fn leakage_check() {
+        let sample_rate = Frequency::new(1e9).unwrap();
+        let waveform = Array2::from_shape_fn((2, 64), |(r, i)| {
+            let phase = TAU * 125e6 * i as f64 / 1e9;
+            0.5 * if r == 0 { phase.cos() } else { phase.sin() }
+        });
+        let frequency = |f| Frequency::new(f).unwrap();
+        let check = LeakageCheck {
+            frequencies: vec![
+                ("q1".to_string(), frequency(5.125e9)),
+                ("q2".to_string(), frequency(4.875e9)),
+                ("tls".to_string(), frequency(8e9)),
+            ],
+            threshold_db: -40.0,
+            bandwidth: frequency(20e6),
+            window: Window::Rectangular,
+        };
+        let mut diagnostics = Diagnostics::new();
+
+        check
+            .check(
+                ChannelId::new("xy"),
+                waveform.view(),
+                sample_rate,
+                frequency(5e9),
+                &mut diagnostics,
+            )
+            .unwrap();
+
+        match diagnostics.warnings() {
+            [Warning::Leakage {
+                name, frequency, ..
+            }] => {
+                assert_eq!(name, "q1");
+                assert_eq!(frequency.value(), 5.125e9);
+            }
+            warnings => panic!("Unexpected warnings {:?}", warnings),
+        }
+    }
+}
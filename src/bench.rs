@@ -0,0 +1,109 @@
+//! Hooks of the benchmarks in `benches/` into the internal hot paths.
+//!
+//! The samplers are internal to the crate, so each hook prepares its inputs
+//! once and returns a closure that runs only the timed part. Whole schedules
+//! are benchmarked through the public [`builder`](crate::builder) API instead.
+use hashbrown::HashMap;
+use ndarray::Array2;
+use num::complex::Complex64;
+
+use crate::{
+    pulse::{Envelope, PulseList, PulseListBuilder, PushArgs, Sampler},
+    quant::{Amplitude, ChannelId, Frequency, Phase, Time},
+    shape::Shape,
+};
+
+const SAMPLE_RATE: f64 = 2e9;
+const PULSE_WIDTH: f64 = 40e-9;
+
+/// Sample a Hann envelope of `size` samples.
+pub fn bench_envelope(size: usize) -> impl FnMut() {
+    let shape = Shape::new_hann();
+    let mut array = vec![0.0; size];
+    let dx = 1.0 / size as f64;
+    move || shape.sample_array(-0.5 + dx / 2.0, dx, &mut array)
+}
+
+/// Sample `size` back to back pulses on one channel.
+pub fn bench_mix(size: usize) -> impl FnMut() {
+    let name = ChannelId::new("xy0");
+    let list = pulse_list(size, Time::ZERO);
+    let length = waveform_length(size);
+    move || {
+        let mut waveform = Array2::zeros((2, length));
+        let mut sampler = Sampler::new([(name, list.clone())].into());
+        sampler.add_channel(
+            name,
+            waveform.view_mut(),
+            sample_rate(),
+            Time::ZERO,
+            -10,
+            Complex64::ZERO,
+        );
+        sampler.sample(time_tolerance()).unwrap();
+    }
+}
+
+/// Sample `size` pulses on each of four channels coupled by a full crosstalk
+/// matrix.
+pub fn bench_crosstalk(size: usize) -> impl FnMut() {
+    let names: Vec<_> = (0..4).map(|i| ChannelId::new(format!("xy{}", i))).collect();
+    let lists: HashMap<_, _> = names
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| (n, pulse_list(size, Time::new(i as f64 * 1e-9).unwrap())))
+        .collect();
+    let matrix = Array2::from_shape_fn((4, 4), |(i, j)| if i == j { 1.0 } else { 0.01 });
+    let length = waveform_length(size);
+    move || {
+        let mut waveforms = vec![Array2::zeros((2, length)); names.len()];
+        let mut sampler = Sampler::new(lists.clone());
+        for (&n, w) in names.iter().zip(&mut waveforms) {
+            sampler.add_channel(
+                n,
+                w.view_mut(),
+                sample_rate(),
+                Time::ZERO,
+                -10,
+                Complex64::ZERO,
+            );
+        }
+        sampler.set_crosstalk(matrix.view(), names.clone());
+        sampler.sample(time_tolerance()).unwrap();
+    }
+}
+
+/// Back to back Hann pulses alternating between two amplitudes.
+fn pulse_list(size: usize, offset: Time) -> PulseList {
+    let mut builder = PulseListBuilder::new(Amplitude::ZERO, time_tolerance());
+    for i in 0..size {
+        builder.push(PushArgs {
+            envelope: Envelope::new(
+                Some(Shape::new_hann()),
+                Time::new(PULSE_WIDTH).unwrap(),
+                Time::ZERO,
+            ),
+            global_freq: Frequency::new(100e6).unwrap(),
+            local_freq: Frequency::ZERO,
+            time: offset + Time::new(i as f64 * PULSE_WIDTH).unwrap(),
+            amplitude: Amplitude::new([0.5, 0.25][i % 2]).unwrap(),
+            drag_coef: 0.0,
+            phase: Phase::new((i % 4) as f64 * 0.25).unwrap(),
+            id: None,
+        });
+    }
+    builder.build_with(|_| {})
+}
+
+/// Length of a waveform holding `pulses` back to back pulses with some slack.
+fn waveform_length(pulses: usize) -> usize {
+    ((pulses + 1) as f64 * PULSE_WIDTH * SAMPLE_RATE).ceil() as usize + 16
+}
+
+fn sample_rate() -> Frequency {
+    Frequency::new(SAMPLE_RATE).unwrap()
+}
+
+fn time_tolerance() -> Time {
+    Time::new(1e-12).unwrap()
+}
@@ -0,0 +1,17 @@
+//! gRPC pulse generation server.
+//!
+//! Usage: `bosing-server [ADDRESS]`, where `ADDRESS` defaults to
+//! `[::1]:50051`.
+use std::net::SocketAddr;
+
+use anyhow::Result;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let addr: SocketAddr = std::env::args()
+        .nth(1)
+        .as_deref()
+        .unwrap_or("[::1]:50051")
+        .parse()?;
+    bosing::grpc::serve(addr).await
+}
@@ -0,0 +1,8 @@
+//! Command line schedule compiler, see `bosing::cli` for the usage.
+use anyhow::Result;
+use bosing::cli::{run, Args};
+use clap::Parser;
+
+fn main() -> Result<()> {
+    run(Args::parse())
+}
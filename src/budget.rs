@@ -0,0 +1,202 @@
+//! Waveform memory estimate of the deduplicated pulse envelopes.
+//!
+//! Backends that store each unique envelope once, see [`PulseList::bins`],
+//! need one allocation per bin. The allocations are rounded up to the
+//! granularity of the waveform memory, so the estimate can be checked against
+//! the limit of each channel before the schedule is sampled.
+use std::{cmp::Reverse, fmt};
+
+use anyhow::{bail, Result};
+use hashbrown::HashMap;
+use serde::Deserialize;
+
+use crate::{
+    pulse::{BinSummary, PulseList},
+    quant::{ChannelId, Frequency},
+};
+
+/// Number of envelopes listed when the memory is exceeded.
+const MAX_CONSUMERS: usize = 5;
+
+/// Waveform memory of a channel in samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MemoryLimit {
+    pub(crate) samples: usize,
+    /// Allocations are rounded up to a multiple of `granularity` samples.
+    #[serde(default = "default_granularity")]
+    pub(crate) granularity: usize,
+}
+
+/// A unique envelope and its allocation in the waveform memory.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Consumer {
+    pub(crate) bin: BinSummary,
+    pub(crate) samples: usize,
+}
+
+/// Estimated waveform memory of a channel, the largest consumers first.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MemoryUsage {
+    pub(crate) channel: ChannelId,
+    pub(crate) limit: MemoryLimit,
+    pub(crate) total: usize,
+    pub(crate) consumers: Vec<Consumer>,
+}
+
+fn default_granularity() -> usize {
+    1
+}
+
+impl MemoryUsage {
+    pub(crate) fn new(
+        channel: ChannelId,
+        pulse_list: &PulseList,
+        sample_rate: Frequency,
+        limit: MemoryLimit,
+    ) -> Self {
+        let granularity = limit.granularity.max(1);
+        let mut consumers: Vec<_> = pulse_list
+            .bins()
+            .into_iter()
+            .map(|bin| {
+                let samples = bin.samples(sample_rate).div_ceil(granularity) * granularity;
+                Consumer { bin, samples }
+            })
+            .collect();
+        consumers.sort_by_key(|c| Reverse(c.samples));
+        let total = consumers.iter().map(|c| c.samples).sum();
+        Self {
+            channel,
+            limit,
+            total,
+            consumers,
+        }
+    }
+
+    pub(crate) fn is_exceeded(&self) -> bool {
+        self.total > self.limit.samples
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Channel '{}' uses {} of {} samples in {} envelope(s)",
+            self.channel,
+            self.total,
+            self.limit.samples,
+            self.consumers.len()
+        )
+    }
+}
+
+impl fmt::Display for Consumer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shape = match &self.bin.shape {
+            Some(shape) => serde_json::to_string(shape).unwrap_or_else(|_| "custom".to_string()),
+            None => "rectangular".to_string(),
+        };
+        write!(
+            f,
+            "{} samples: shape {}, width {}, plateau {}, frequency {} + {}, {} pulse(s)",
+            self.samples,
+            shape,
+            self.bin.width,
+            self.bin.plateau,
+            self.bin.global_freq,
+            self.bin.local_freq,
+            self.bin.count
+        )
+    }
+}
+
+/// Estimate the waveform memory of the channels with a limit, channels
+/// without a pulse list are skipped.
+pub(crate) fn estimate_memory(
+    pulse_lists: &HashMap<ChannelId, PulseList>,
+    channels: impl IntoIterator<Item = (ChannelId, Frequency, MemoryLimit)>,
+) -> Vec<MemoryUsage> {
+    channels
+        .into_iter()
+        .filter_map(|(channel, sample_rate, limit)| {
+            let pulse_list = pulse_lists.get(&channel)?;
+            Some(MemoryUsage::new(channel, pulse_list, sample_rate, limit))
+        })
+        .collect()
+}
+
+/// Fail with a breakdown of the largest envelopes if any channel exceeds its
+/// waveform memory.
+pub(crate) fn check_memory(usages: &[MemoryUsage]) -> Result<()> {
+    let exceeded: Vec<_> = usages.iter().filter(|u| u.is_exceeded()).collect();
+    if exceeded.is_empty() {
+        return Ok(());
+    }
+    let mut message = String::from("Waveform memory exceeded");
+    for usage in exceeded {
+        message.push_str(&format!("\n{}, largest envelopes:", usage));
+        for consumer in usage.consumers.iter().take(MAX_CONSUMERS) {
+            message.push_str(&format!("\n  {}", consumer));
+        }
+        if usage.consumers.len() > MAX_CONSUMERS {
+            message.push_str(&format!(
+                "\n  ... and {} more",
+                usage.consumers.len() - MAX_CONSUMERS
+            ));
+        }
+    }
+    bail!(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pulse::{Envelope, PulseListBuilder, PushArgs},
+        quant::{Amplitude, Phase, Time},
+        shape::Shape,
+    };
+
+    #[test]
+    fn memory() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        for (width, time) in [(20e-9, 0.0), (20e-9, 100e-9), (50e-9, 200e-9)] {
+            builder.push(PushArgs {
+                envelope: Envelope::new(
+                    Some(Shape::new_hann()),
+                    Time::new(width).unwrap(),
+                    Time::ZERO,
+                ),
+                global_freq: Frequency::ZERO,
+                local_freq: Frequency::ZERO,
+                time: Time::new(time).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::ZERO,
+                id: None,
+            });
+        }
+        let channel = ChannelId::new("xy");
+        let pulse_lists = HashMap::from([(channel, builder.build_with(|_| {}))]);
+        let sample_rate = Frequency::new(1e9).unwrap();
+        let limit = |samples| MemoryLimit {
+            samples,
+            granularity: 16,
+        };
+
+        let usages = estimate_memory(&pulse_lists, [(channel, sample_rate, limit(96))]);
+
+        assert_eq!(usages[0].total, 32 + 64);
+        assert_eq!(usages[0].consumers[0].samples, 64);
+        assert_eq!(usages[0].consumers[0].bin.count, 1);
+        assert!(check_memory(&usages).is_ok());
+
+        let usages = estimate_memory(&pulse_lists, [(channel, sample_rate, limit(80))]);
+        let message = check_memory(&usages).unwrap_err().to_string();
+
+        assert!(message.contains("Channel 'xy' uses 96 of 80 samples"));
+        assert!(message.contains("64 samples: shape"));
+    }
+}
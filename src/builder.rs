@@ -0,0 +1,1336 @@
+//! Construction of schedules from rust.
+//!
+//! The builders mirror the python classes of the same names. Invalid values
+//! are reported by `build`, so calls can be chained without intermediate
+//! error handling. Builders of children can be passed to containers directly.
+//!
+//! Adding a child with `push` returns a [`Handle`] that stays valid in every
+//! schedule containing the child, e.g. to look up where a pulse landed after
+//! arrangement or to [`rebind`](Schedule::rebind) it with new parameters:
+//!
+//! ```
+//! use bosing::builder::{play, stack};
+//!
+//! let mut block = stack();
+//! block.push(play("q0", None, 0.5, 20e-9));
+//! let probe = block.push(play("q0", None, 0.2, 40e-9));
+//! let schedule = stack().child(block.margin(5e-9, 0.0)).build()?;
+//! assert!((schedule.placements(probe)[0].start - 25e-9).abs() < 1e-15);
+//!
+//! let schedule = schedule.rebind(probe, play("q0", None, 0.3, 10e-9))?;
+//! assert!((schedule.duration() - 35e-9).abs() < 1e-15);
+//! # Ok::<(), bosing::ScheduleError>(())
+//! ```
+//!
+//! ```
+//! use bosing::{builder::{barrier, play, stack}, Direction};
+//!
+//! let schedule = stack()
+//!     .direction(Direction::Forward)
+//!     .child(play("q0", Some("hann"), 0.5, 20e-9).plateau(100e-9))
+//!     .child(barrier(&[]).duration(10e-9))
+//!     .child(play("q1", None, 0.2, 40e-9))
+//!     .build()?;
+//! assert!((schedule.duration() - 170e-9).abs() < 1e-15);
+//! # Ok::<(), bosing::ScheduleError>(())
+//! ```
+//!
+//! A built schedule is turned into waveforms by [`compile`]:
+//!
+//! ```
+//! use bosing::builder::{channel, compile, play, stack, CompileOptions, Shapes};
+//!
+//! let schedule = stack().child(play("q0", Some("hann"), 0.5, 20e-9)).build()?;
+//! let channels = [channel("q0", 2e9, 100).base_freq(100e6)];
+//! let shapes = Shapes::new().hann("hann");
+//! let waveforms = compile(&schedule, &channels, &shapes, &CompileOptions::new())?;
+//! assert_eq!(waveforms.get("q0").unwrap().shape(), [2, 100]);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, OnceLock,
+};
+
+use hashbrown::HashMap;
+use ndarray::{Array1, Array2};
+use num::complex::Complex64;
+use smallvec::{smallvec, SmallVec};
+
+use crate::{
+    pipeline::{self, ChannelSpec, Filters, Options},
+    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    schedule::{
+        self, arrange_children, Absolute, AbsoluteEntry, Arena, ArenaTree, Arranged, Barrier,
+        Element, ElementCommonBuilder, ElementRef, ElementVariant, Grid, Measure as _, Node,
+        NodeId, Play, Repeat, Result, ScaleAmplitude, ScheduleError, SetFreq, SetPhase, ShiftFreq,
+        ShiftPhase, Slot, Stack, SwapPhase, TimeRange,
+    },
+    shape::Shape,
+    Alignment, Direction, GridLength,
+};
+
+/// Immutable element tree built by the builders of this module.
+///
+/// The builders store the elements contiguously. The first measurement,
+/// arrangement, or compilation converts them to an element tree with one
+/// allocation per element, which is kept for later calls. Clones of a
+/// measured schedule added to several containers share the subtree together
+/// with its measurement. Equality and
+/// hashing only consider the element tree, not the handles.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    tree: ArenaTree,
+    element: OnceLock<ElementRef>,
+    handles: Handles,
+}
+
+impl Schedule {
+    fn new(element: ElementRef) -> Self {
+        Self {
+            tree: ArenaTree::leaf(Node::Tree(element.clone())),
+            element: element.into(),
+            handles: Handles::default(),
+        }
+    }
+
+    fn from_tree(tree: ArenaTree, handles: Handles) -> Self {
+        Self {
+            tree,
+            element: OnceLock::new(),
+            handles,
+        }
+    }
+
+    /// The element tree, converted from the arena on first use.
+    fn element(&self) -> &ElementRef {
+        self.element.get_or_init(|| self.tree.to_element())
+    }
+
+    /// Arena tree to add to a container, sharing the element tree if the
+    /// schedule was already measured.
+    fn into_tree(self) -> ArenaTree {
+        match self.element.into_inner() {
+            Some(element) => ArenaTree::leaf(Node::Tree(element)),
+            None => self.tree,
+        }
+    }
+
+    /// Minimum duration of the schedule in seconds, including margins.
+    pub fn duration(&self) -> f64 {
+        self.element().measure().value()
+    }
+
+    /// Where the element of `handle` is arranged when the schedule starts at
+    /// zero.
+    ///
+    /// There is one placement per occurrence, e.g. per iteration of a
+    /// `repeat` ancestor, in arrangement order. Phantom elements and unknown
+    /// handles have no placement.
+    pub fn placements(&self, handle: Handle) -> Vec<Placement> {
+        let mut placements = Vec::new();
+        let element = self.element();
+        let root = Arranged {
+            item: element,
+            time_range: TimeRange {
+                start: Time::ZERO,
+                span: element.measure(),
+            },
+        };
+        for path in self.handles.paths(handle) {
+            collect_placements(root, path, &mut placements);
+        }
+        placements
+    }
+
+    /// Copy of the schedule with the element of `handle` replaced by `child`.
+    ///
+    /// The handles of the replaced element are dropped, the handles of `child`
+    /// are added, and `handle` refers to `child` afterwards.
+    pub fn rebind(&self, handle: Handle, child: impl IntoSchedule) -> Result<Schedule> {
+        let paths = self.handles.paths(handle);
+        if paths.is_empty() {
+            return Err(ScheduleError::UnknownHandle(handle.0));
+        }
+        let child = child.into_schedule()?;
+        let mut element = self.element().clone();
+        let mut handles = self.handles.clone();
+        for path in paths {
+            element = replace(&element, path, child.element())?;
+            handles.remove_below(path);
+            handles.insert_child(path, &child.handles);
+            handles.insert(handle, path.clone());
+        }
+        Ok(Schedule {
+            handles,
+            ..Schedule::new(element)
+        })
+    }
+
+    /// Serialize to the JSON document read by `Element.from_json` in python.
+    pub fn to_json(&self) -> Result<String> {
+        self.element().to_json(None)
+    }
+
+    /// Deserialize a JSON document created by [`to_json`](Self::to_json).
+    pub fn from_json(s: &str) -> Result<Self> {
+        Ok(Self::new(Arc::new(Element::from_json(s)?)))
+    }
+
+    /// Serialize to the MessagePack document read by `Element.from_msgpack`.
+    pub fn to_msgpack(&self) -> Result<Vec<u8>> {
+        self.element().to_msgpack(None)
+    }
+
+    /// Plain text timeline with `width` columns, see `Element.render_ascii`.
+    pub fn render_ascii(&self, width: usize) -> String {
+        schedule::render_ascii(self.element(), width)
+    }
+
+    /// Gantt chart in an SVG document, see `render_schedule_svg` in python.
+    #[cfg(feature = "svg")]
+    pub fn render_svg(&self) -> String {
+        crate::io::svg::render_gantt(&schedule::flatten(self.element()))
+    }
+}
+
+impl PartialEq for Schedule {
+    fn eq(&self, other: &Self) -> bool {
+        self.element() == other.element()
+    }
+}
+
+impl Eq for Schedule {}
+
+impl std::hash::Hash for Schedule {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.element().hash(state);
+    }
+}
+
+/// Reference to a child added with `push`, see the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle(u64);
+
+impl Handle {
+    fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Arranged time of an element in seconds, excluding its margins.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub start: f64,
+    pub duration: f64,
+}
+
+/// Paths of child indices from the root to the elements of the handles.
+///
+/// A handle has several paths if its subtree is added more than once. Most
+/// handles have one short path, which is stored inline.
+#[derive(Debug, Clone, Default)]
+struct Handles(HashMap<Handle, SmallVec<[Path; 1]>>);
+
+type Path = SmallVec<[usize; 4]>;
+
+impl Handles {
+    fn paths(&self, handle: Handle) -> &[Path] {
+        self.0.get(&handle).map_or(&[], SmallVec::as_slice)
+    }
+
+    fn insert(&mut self, handle: Handle, path: Path) {
+        let paths = self.0.entry(handle).or_default();
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+
+    /// Add the handles of a child at `prefix`.
+    fn insert_child(&mut self, prefix: &[usize], child: &Handles) {
+        for (&handle, paths) in &child.0 {
+            for path in paths {
+                self.insert(handle, prefix.iter().chain(path).copied().collect());
+            }
+        }
+    }
+
+    /// Remove the paths in the subtree at `prefix`, including `prefix`.
+    fn remove_below(&mut self, prefix: &[usize]) {
+        self.0.retain(|_, paths| {
+            paths.retain(|p| !p.starts_with(prefix));
+            !paths.is_empty()
+        });
+    }
+}
+
+/// Children of a layout in an arena with the handles of their subtrees.
+#[derive(Debug, Clone, Default)]
+struct Children {
+    arena: Arena,
+    entries: Vec<(NodeId, Slot)>,
+    handles: Handles,
+    error: Option<ScheduleError>,
+}
+
+impl Children {
+    fn push(
+        &mut self,
+        child: impl IntoSchedule,
+        slot: impl FnOnce(&Schedule) -> Result<Slot>,
+    ) -> Handle {
+        let handle = Handle::new();
+        let child = child.into_schedule().and_then(|c| Ok((slot(&c)?, c)));
+        match child {
+            Ok((slot, child)) => {
+                let index = self.entries.len();
+                self.handles.insert_child(&[index], &child.handles);
+                self.handles.insert(handle, smallvec![index]);
+                let node = self.arena.insert(child.into_tree());
+                self.entries.push((node, slot));
+            }
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        handle
+    }
+}
+
+/// Add the placements of the element at `path` below `arranged`.
+fn collect_placements(arranged: Arranged<&ElementRef>, path: &[usize], out: &mut Vec<Placement>) {
+    let Some((&index, rest)) = path.split_first() else {
+        if !arranged.item.common.phantom() {
+            let TimeRange { start, span } = arranged.item.inner_time_range(arranged.time_range);
+            out.push(Placement {
+                start: start.value(),
+                duration: span.value(),
+            });
+        }
+        return;
+    };
+    let is_repeat = matches!(arranged.item.variant, ElementVariant::Repeat(_));
+    let Some(children) = arrange_children(arranged) else {
+        return;
+    };
+    // Repeat yields its only child once per iteration.
+    for (i, child) in children.enumerate() {
+        if is_repeat || i == index {
+            collect_placements(child, rest, out);
+        }
+    }
+}
+
+/// Copy of `element` with the element at `path` replaced by `child`.
+fn replace(element: &ElementRef, path: &[usize], child: &ElementRef) -> Result<ElementRef> {
+    let Some((&index, rest)) = path.split_first() else {
+        return Ok(child.clone());
+    };
+    let invalid = || ScheduleError::InvalidPath { index };
+    let old = match &element.variant {
+        ElementVariant::Stack(v) => v.children().get(index),
+        ElementVariant::Absolute(v) => v.children().get(index).map(|e| e.element()),
+        ElementVariant::Grid(v) => v.children().get(index).map(|e| e.element()),
+        ElementVariant::Repeat(v) => (index == 0).then(|| v.child()),
+        ElementVariant::ScaleAmplitude(v) => (index == 0).then(|| v.child()),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+    let new = replace(old, rest, child)?;
+    // A child placed from the end has to end inside the layout with its new
+    // duration.
+    let variant = element
+        .variant
+        .with_child(index, new)?
+        .ok_or_else(invalid)?;
+    Ok(Arc::new(Element::new(element.common.clone(), variant)))
+}
+
+/// Conversion of builders and built schedules to [`Schedule`].
+pub trait IntoSchedule {
+    fn into_schedule(self) -> Result<Schedule>;
+}
+
+impl IntoSchedule for Schedule {
+    fn into_schedule(self) -> Result<Schedule> {
+        Ok(self)
+    }
+}
+
+/// Properties shared by all elements, see `Element` in the python API.
+#[derive(Debug, Clone, Default)]
+struct Common {
+    builder: ElementCommonBuilder,
+    error: Option<ScheduleError>,
+}
+
+impl Common {
+    fn set<T>(&mut self, value: Result<T>, f: impl FnOnce(&mut ElementCommonBuilder, T)) {
+        match value {
+            Ok(v) => f(&mut self.builder, v),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+    }
+
+    fn build(self, variant: Result<impl Into<ElementVariant>>) -> Result<Schedule> {
+        self.build_with_handles(variant.map(|v| (v, Handles::default())))
+    }
+
+    fn build_with_handles(
+        self,
+        variant: Result<(impl Into<ElementVariant>, Handles)>,
+    ) -> Result<Schedule> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let common = self.builder.build()?;
+        let (variant, handles) = variant?;
+        Ok(Schedule::from_tree(
+            ArenaTree::leaf(Node::new(common, variant)),
+            handles,
+        ))
+    }
+
+    fn build_layout(
+        self,
+        layout: Result<impl Into<ElementVariant>>,
+        children: Children,
+    ) -> Result<Schedule> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let common = self.builder.build()?;
+        let layout = layout?;
+        if let Some(e) = children.error {
+            return Err(e);
+        }
+        let tree = children.arena.into_tree(common, layout, &children.entries);
+        Ok(Schedule::from_tree(tree, children.handles))
+    }
+}
+
+macro_rules! impl_common {
+    ($t:ty) => {
+        impl $t {
+            /// Margin before and after the element in seconds.
+            pub fn margin(mut self, start: f64, end: f64) -> Self {
+                let margin = time("margin", start).and_then(|s| Ok((s, time("margin", end)?)));
+                self.common.set(margin, |b, v| {
+                    b.margin(v);
+                });
+                self
+            }
+
+            pub fn alignment(mut self, alignment: Alignment) -> Self {
+                self.common.builder.alignment(alignment);
+                self
+            }
+
+            /// Arrange the element without adding it to the waveforms.
+            pub fn phantom(mut self, phantom: bool) -> Self {
+                self.common.builder.phantom(phantom);
+                self
+            }
+
+            pub fn duration(mut self, duration: f64) -> Self {
+                self.common.set(time("duration", duration), |b, v| {
+                    b.duration(Some(v));
+                });
+                self
+            }
+
+            pub fn min_duration(mut self, min_duration: f64) -> Self {
+                self.common.set(time("min_duration", min_duration), |b, v| {
+                    b.min_duration(v);
+                });
+                self
+            }
+
+            pub fn max_duration(mut self, max_duration: f64) -> Self {
+                self.common.set(time("max_duration", max_duration), |b, v| {
+                    b.max_duration(v);
+                });
+                self
+            }
+
+            /// Override of the global `allow_oversize` option.
+            pub fn allow_oversize(mut self, allow_oversize: bool) -> Self {
+                self.common.builder.allow_oversize(Some(allow_oversize));
+                self
+            }
+        }
+
+        impl IntoSchedule for $t {
+            fn into_schedule(self) -> Result<Schedule> {
+                self.build()
+            }
+        }
+    };
+}
+
+/// Builder of a pulse, see [`play`].
+#[derive(Debug, Clone)]
+pub struct PlayBuilder {
+    common: Common,
+    play: Result<Play>,
+}
+
+/// Play a pulse with `amplitude` and `width` in seconds on a channel.
+///
+/// `shape` is the name of the shape given to the sampler, or `None` for a
+/// rectangular pulse.
+pub fn play(channel: &str, shape: Option<&str>, amplitude: f64, width: f64) -> PlayBuilder {
+    let play = (|| {
+        Play::new(
+            ChannelId::new(channel),
+            shape.map(ShapeId::new),
+            quantity("amplitude", amplitude, Amplitude::new)?,
+            time("width", width)?,
+        )
+    })();
+    PlayBuilder {
+        common: Common::default(),
+        play,
+    }
+}
+
+impl PlayBuilder {
+    pub fn plateau(mut self, plateau: f64) -> Self {
+        self.play = self
+            .play
+            .and_then(|p| p.with_plateau(time("plateau", plateau)?));
+        self
+    }
+
+    pub fn drag_coef(mut self, drag_coef: f64) -> Self {
+        self.play = self.play.and_then(|p| p.with_drag_coef(drag_coef));
+        self
+    }
+
+    /// Frequency relative to the channel frequency in Hz.
+    pub fn frequency(mut self, frequency: f64) -> Self {
+        self.play = self
+            .play
+            .and_then(|p| p.with_frequency(quantity("frequency", frequency, Frequency::new)?));
+        self
+    }
+
+    /// Phase in cycles.
+    pub fn phase(mut self, phase: f64) -> Self {
+        self.play = self
+            .play
+            .and_then(|p| p.with_phase(quantity("phase", phase, Phase::new)?));
+        self
+    }
+
+    /// Stretch the plateau to fill the arranged duration.
+    pub fn flexible(mut self, flexible: bool) -> Self {
+        self.play = self.play.map(|p| p.with_flexible(flexible));
+        self
+    }
+
+    /// Hold the final value of the envelope until the next pulse or barrier
+    /// on the channel. Not supported on net-zero channels.
+    pub fn hold(mut self, hold: bool) -> Self {
+        self.play = self.play.map(|p| p.with_hold(hold));
+        self
+    }
+
+    pub fn build(self) -> Result<Schedule> {
+        self.common.build(self.play)
+    }
+}
+
+impl_common!(PlayBuilder);
+
+/// Builder of phase, frequency, and barrier instructions.
+#[derive(Debug, Clone)]
+pub struct InstructionBuilder {
+    common: Common,
+    variant: Result<ElementVariant>,
+}
+
+impl InstructionBuilder {
+    fn new(variant: Result<impl Into<ElementVariant>>) -> Self {
+        Self {
+            common: Common::default(),
+            variant: variant.map(Into::into),
+        }
+    }
+
+    pub fn build(self) -> Result<Schedule> {
+        self.common.build(self.variant)
+    }
+}
+
+impl_common!(InstructionBuilder);
+
+/// Shift the phase of a channel by `phase` cycles.
+pub fn shift_phase(channel: &str, phase: f64) -> InstructionBuilder {
+    InstructionBuilder::new(
+        quantity("phase", phase, Phase::new)
+            .and_then(|p| ShiftPhase::new(ChannelId::new(channel), p)),
+    )
+}
+
+/// Set the phase of a channel to `phase` cycles.
+pub fn set_phase(channel: &str, phase: f64) -> InstructionBuilder {
+    InstructionBuilder::new(
+        quantity("phase", phase, Phase::new)
+            .and_then(|p| SetPhase::new(ChannelId::new(channel), p)),
+    )
+}
+
+/// Shift the frequency of a channel by `frequency` Hz.
+pub fn shift_freq(channel: &str, frequency: f64) -> InstructionBuilder {
+    InstructionBuilder::new(
+        quantity("frequency", frequency, Frequency::new)
+            .and_then(|f| ShiftFreq::new(ChannelId::new(channel), f)),
+    )
+}
+
+/// Set the frequency of a channel to `frequency` Hz.
+pub fn set_freq(channel: &str, frequency: f64) -> InstructionBuilder {
+    InstructionBuilder::new(
+        quantity("frequency", frequency, Frequency::new)
+            .and_then(|f| SetFreq::new(ChannelId::new(channel), f)),
+    )
+}
+
+/// Swap the phases of two channels.
+pub fn swap_phase(channel1: &str, channel2: &str) -> InstructionBuilder {
+    InstructionBuilder::new(Ok(SwapPhase::new(
+        ChannelId::new(channel1),
+        ChannelId::new(channel2),
+    )))
+}
+
+/// Align the channels, or all channels of the parent if `channels` is empty.
+pub fn barrier(channels: &[&str]) -> InstructionBuilder {
+    InstructionBuilder::new(Ok(Barrier::new(
+        channels.iter().map(ChannelId::new).collect(),
+    )))
+}
+
+/// Builder of a stack layout, see [`stack`].
+#[derive(Debug, Clone)]
+pub struct StackBuilder {
+    common: Common,
+    stack: Result<Stack>,
+    children: Children,
+}
+
+/// Stack layout, arranging the children one after another.
+pub fn stack() -> StackBuilder {
+    StackBuilder {
+        common: Common::default(),
+        stack: Ok(Stack::new()),
+        children: Children::default(),
+    }
+}
+
+impl StackBuilder {
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.stack = self.stack.map(|s| s.with_direction(direction));
+        self
+    }
+
+    pub fn child(mut self, child: impl IntoSchedule) -> Self {
+        self.push(child);
+        self
+    }
+
+    /// Append `child` and return its handle.
+    pub fn push(&mut self, child: impl IntoSchedule) -> Handle {
+        self.children.push(child, |_| Ok(Slot::Stack))
+    }
+
+    pub fn build(self) -> Result<Schedule> {
+        self.common.build_layout(self.stack, self.children)
+    }
+}
+
+impl_common!(StackBuilder);
+
+/// Builder of an absolute layout, see [`absolute`].
+#[derive(Debug, Clone)]
+pub struct AbsoluteBuilder {
+    common: Common,
+    children: Children,
+}
+
+/// Absolute layout, placing the children at given times.
+pub fn absolute() -> AbsoluteBuilder {
+    AbsoluteBuilder {
+        common: Common::default(),
+        children: Children::default(),
+    }
+}
+
+impl AbsoluteBuilder {
+    /// Place `child` at `time` seconds after the start of the layout.
+    pub fn child(mut self, time: f64, child: impl IntoSchedule) -> Self {
+        self.push(time, child);
+        self
+    }
+
+    /// Place `child` at `time` seconds relative to the end of the layout.
+    ///
+    /// `time` has to be at most the negated duration of `child`, so that it
+    /// ends inside the layout.
+    pub fn child_from_end(mut self, time: f64, child: impl IntoSchedule) -> Self {
+        self.push_from_end(time, child);
+        self
+    }
+
+    /// Same as [`child`](Self::child), returning the handle of `child`.
+    pub fn push(&mut self, time: f64, child: impl IntoSchedule) -> Handle {
+        self.children.push(child, |c| absolute_slot(c, time, false))
+    }
+
+    /// Same as [`child_from_end`](Self::child_from_end), returning the handle
+    /// of `child`.
+    pub fn push_from_end(&mut self, time: f64, child: impl IntoSchedule) -> Handle {
+        self.children.push(child, |c| absolute_slot(c, time, true))
+    }
+
+    pub fn build(self) -> Result<Schedule> {
+        self.common.build_layout(Ok(Absolute::new()), self.children)
+    }
+}
+
+impl_common!(AbsoluteBuilder);
+
+/// Slot of `child` in an absolute layout. Children placed from the end are
+/// measured to check that they end inside the layout.
+fn absolute_slot(child: &Schedule, time_: f64, from_end: bool) -> Result<Slot> {
+    if from_end {
+        absolute_entry(child.element().clone(), time_, from_end)?;
+    }
+    Slot::absolute(time("time", time_)?, from_end)
+}
+
+fn absolute_entry(child: ElementRef, time_: f64, from_end: bool) -> Result<AbsoluteEntry> {
+    AbsoluteEntry::new(child)
+        .with_time(time("time", time_)?)?
+        .with_from_end(from_end)
+}
+
+/// Builder of a grid layout, see [`grid`].
+#[derive(Debug, Clone)]
+pub struct GridBuilder {
+    common: Common,
+    columns: Vec<GridLength>,
+    children: Children,
+}
+
+/// Grid layout, placing the children in columns.
+pub fn grid() -> GridBuilder {
+    GridBuilder {
+        common: Common::default(),
+        columns: Vec::new(),
+        children: Children::default(),
+    }
+}
+
+impl GridBuilder {
+    /// Append a column, e.g. `"2*".parse()?` or `"auto".parse()?`.
+    pub fn column(mut self, length: GridLength) -> Self {
+        self.columns.push(length);
+        self
+    }
+
+    /// Place `child` in `span` columns starting at `column`.
+    pub fn child(mut self, child: impl IntoSchedule, column: usize, span: usize) -> Self {
+        self.push(child, column, span);
+        self
+    }
+
+    /// Same as [`child`](Self::child), returning the handle of `child`.
+    pub fn push(&mut self, child: impl IntoSchedule, column: usize, span: usize) -> Handle {
+        self.children.push(child, |_| Slot::grid(column, span))
+    }
+
+    pub fn build(self) -> Result<Schedule> {
+        let grid = Grid::new().with_columns(self.columns);
+        self.common.build_layout(Ok(grid), self.children)
+    }
+}
+
+impl_common!(GridBuilder);
+
+/// Builder of a repetition, see [`repeat`].
+#[derive(Debug, Clone)]
+pub struct RepeatBuilder {
+    common: Common,
+    repeat: Result<(Repeat, Handles)>,
+}
+
+/// Repeat `child` `count` times.
+pub fn repeat(child: impl IntoSchedule, count: usize) -> RepeatBuilder {
+    RepeatBuilder {
+        common: Common::default(),
+        repeat: child.into_schedule().map(|c| {
+            (
+                Repeat::new(c.element().clone(), count),
+                only_child(&c.handles),
+            )
+        }),
+    }
+}
+
+impl RepeatBuilder {
+    /// Spacing between the repetitions in seconds.
+    pub fn spacing(mut self, spacing: f64) -> Self {
+        self.repeat = self
+            .repeat
+            .and_then(|(r, h)| Ok((r.with_spacing(time("spacing", spacing)?)?, h)));
+        self
+    }
+
+    pub fn build(self) -> Result<Schedule> {
+        self.common.build_with_handles(self.repeat)
+    }
+}
+
+impl_common!(RepeatBuilder);
+
+/// Builder of an amplitude scaling, see [`scale_amplitude`].
+#[derive(Debug, Clone)]
+pub struct ScaleAmplitudeBuilder {
+    common: Common,
+    scale: Result<(ScaleAmplitude, Handles)>,
+}
+
+/// Scale the amplitudes of the pulses in `child` by `factor`.
+pub fn scale_amplitude(child: impl IntoSchedule, factor: f64) -> ScaleAmplitudeBuilder {
+    ScaleAmplitudeBuilder {
+        common: Common::default(),
+        scale: child.into_schedule().and_then(|c| {
+            Ok((
+                ScaleAmplitude::new(c.element().clone(), factor)?,
+                only_child(&c.handles),
+            ))
+        }),
+    }
+}
+
+impl ScaleAmplitudeBuilder {
+    pub fn build(self) -> Result<Schedule> {
+        self.common.build_with_handles(self.scale)
+    }
+}
+
+impl_common!(ScaleAmplitudeBuilder);
+
+/// Builder of a channel given to [`compile`], see [`channel`].
+#[derive(Debug, Clone)]
+pub struct ChannelBuilder {
+    spec: Result<ChannelSpec>,
+}
+
+/// Channel with `length` samples at `sample_rate` Hz, see `Channel` in the
+/// python API.
+///
+/// The channel is complex with rows `[I, Q]` unless [`real`] is set.
+///
+/// [`real`]: ChannelBuilder::real
+pub fn channel(name: &str, sample_rate: f64, length: usize) -> ChannelBuilder {
+    let spec = quantity("sample_rate", sample_rate, Frequency::new)
+        .and_then(|f| {
+            if f.value() > 0.0 {
+                Ok(f)
+            } else {
+                Err(ScheduleError::InvalidValue {
+                    property: "sample_rate",
+                    value: sample_rate,
+                })
+            }
+        })
+        .map(|sample_rate| ChannelSpec {
+            name: ChannelId::new(name),
+            base_freq: Frequency::ZERO,
+            sample_rate,
+            delay: Time::ZERO,
+            align_level: -10,
+            baseline: Complex64::default(),
+            ringdown: None,
+            net_zero: false,
+            idle_tone: None,
+            length,
+            is_real: false,
+            time_grid: None,
+            memory: None,
+            filters: Filters::default(),
+        });
+    ChannelBuilder { spec }
+}
+
+impl ChannelBuilder {
+    /// Carrier frequency of the channel in Hz.
+    pub fn base_freq(mut self, base_freq: f64) -> Self {
+        self.spec = self.spec.and_then(|mut s| {
+            s.base_freq = quantity("base_freq", base_freq, Frequency::new)?;
+            Ok(s)
+        });
+        self
+    }
+
+    /// Delay of the waveform in seconds.
+    pub fn delay(mut self, delay: f64) -> Self {
+        self.spec = self.spec.and_then(|mut s| {
+            s.delay = quantity("delay", delay, Time::new)?;
+            Ok(s)
+        });
+        self
+    }
+
+    /// Pulses are aligned to `2 ** align_level` samples.
+    pub fn align_level(mut self, align_level: i32) -> Self {
+        self.spec = self.spec.map(|mut s| {
+            s.align_level = align_level;
+            s
+        });
+        self
+    }
+
+    /// Keep only the in-phase row of the waveform.
+    pub fn real(mut self, is_real: bool) -> Self {
+        self.spec = self.spec.map(|mut s| {
+            s.is_real = is_real;
+            s
+        });
+        self
+    }
+
+    /// IQ mixing matrix of shape `(2, 2)`.
+    pub fn iq_matrix(mut self, iq_matrix: Array2<f64>) -> Self {
+        self.spec = self.spec.map(|mut s| {
+            s.filters.iq_matrix = Some(iq_matrix);
+            s
+        });
+        self
+    }
+
+    /// DC offset of each row.
+    pub fn offset(mut self, offset: Array1<f64>) -> Self {
+        self.spec = self.spec.map(|mut s| {
+            s.filters.offset = Some(offset);
+            s
+        });
+        self
+    }
+
+    /// IIR filter in second-order sections of shape `(n, 6)`.
+    pub fn iir(mut self, sos: Array2<f64>) -> Self {
+        self.spec = self.spec.map(|mut s| {
+            s.filters.iir = Some(sos);
+            s
+        });
+        self
+    }
+
+    /// FIR filter taps.
+    pub fn fir(mut self, taps: Array1<f64>) -> Self {
+        self.spec = self.spec.map(|mut s| {
+            s.filters.fir = Some(taps);
+            s
+        });
+        self
+    }
+}
+
+/// Envelope shapes referred to by name in [`play`].
+#[derive(Debug, Clone, Default)]
+pub struct Shapes(HashMap<ShapeId, Shape>);
+
+impl Shapes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hann window.
+    pub fn hann(mut self, name: &str) -> Self {
+        self.0.insert(ShapeId::new(name), Shape::new_hann());
+        self
+    }
+
+    /// B-spline with the given knots, control points, and degree, defined in
+    /// \[-0.5, 0.5\].
+    pub fn interp(
+        mut self,
+        name: &str,
+        knots: Vec<f64>,
+        controls: Vec<f64>,
+        degree: usize,
+    ) -> anyhow::Result<Self> {
+        let shape = Shape::new_interp(knots, controls, degree)?;
+        self.0.insert(ShapeId::new(name), shape);
+        Ok(self)
+    }
+
+    /// Shape through equally spaced samples, held or linearly interpolated.
+    pub fn samples(
+        mut self,
+        name: &str,
+        values: &[f64],
+        interpolate: bool,
+    ) -> anyhow::Result<Self> {
+        let shape = Shape::new_samples(values, interpolate)?;
+        self.0.insert(ShapeId::new(name), shape);
+        Ok(self)
+    }
+}
+
+/// Options of [`compile`], the defaults are those of the python API.
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    options: Result<Options>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            options: Ok(Options::default()),
+        }
+    }
+}
+
+impl CompileOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tolerance of time comparisons in seconds.
+    pub fn time_tolerance(mut self, time_tolerance: f64) -> Self {
+        self.options = self.options.and_then(|mut o| {
+            o.time_tolerance = time("time_tolerance", time_tolerance)?;
+            Ok(o)
+        });
+        self
+    }
+
+    /// Pulses with smaller amplitudes are dropped.
+    pub fn amp_tolerance(mut self, amp_tolerance: f64) -> Self {
+        self.options = self.options.and_then(|mut o| {
+            o.amp_tolerance = quantity("amp_tolerance", amp_tolerance, Amplitude::new)?;
+            Ok(o)
+        });
+        self
+    }
+
+    /// Place elements in spans shorter than their content instead of failing.
+    pub fn allow_oversize(mut self, allow_oversize: bool) -> Self {
+        self.options = self.options.map(|mut o| {
+            o.allow_oversize = allow_oversize;
+            o
+        });
+        self
+    }
+
+    /// Clip pulses outside the waveforms instead of failing.
+    pub fn allow_truncate(mut self, allow_truncate: bool) -> Self {
+        self.options = self.options.map(|mut o| {
+            o.allow_truncate = allow_truncate;
+            o
+        });
+        self
+    }
+}
+
+/// Waveforms generated by [`compile`].
+#[derive(Debug, Clone)]
+pub struct Waveforms {
+    waveforms: HashMap<ChannelId, Array2<f64>>,
+    warnings: Vec<String>,
+}
+
+impl Waveforms {
+    /// Waveform of a channel with shape `(1, length)` for real channels and
+    /// `(2, length)` otherwise.
+    pub fn get(&self, channel: &str) -> Option<&Array2<f64>> {
+        self.waveforms.get(&ChannelId::new(channel))
+    }
+
+    /// Non-fatal issues found while compiling, e.g. truncated pulses.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+/// Measure, arrange, sample, and post process `schedule` on `channels`.
+///
+/// This is `generate_waveforms` of the python API without crosstalk.
+pub fn compile(
+    schedule: &Schedule,
+    channels: &[ChannelBuilder],
+    shapes: &Shapes,
+    options: &CompileOptions,
+) -> anyhow::Result<Waveforms> {
+    let channels = channels
+        .iter()
+        .map(|c| c.spec.clone())
+        .collect::<Result<Vec<_>>>()?;
+    let options = options.options.clone()?;
+    let (waveforms, diagnostics) =
+        pipeline::generate_waveforms(schedule.element(), &channels, &shapes.0, None, options)?;
+    Ok(Waveforms {
+        waveforms,
+        warnings: diagnostics
+            .warnings()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+/// Conversions to the units taken by the builders, seconds and hertz.
+///
+/// ```
+/// use bosing::builder::{play, units::{ns, us}};
+///
+/// let schedule = play("q0", None, 0.5, ns(20.0)).plateau(us(1.0)).build()?;
+/// assert!((schedule.duration() - 1.02e-6).abs() < 1e-15);
+/// # Ok::<(), bosing::ScheduleError>(())
+/// ```
+pub mod units {
+    /// Seconds in `value` nanoseconds.
+    pub fn ns(value: f64) -> f64 {
+        value / 1e9
+    }
+
+    /// Seconds in `value` microseconds.
+    pub fn us(value: f64) -> f64 {
+        value / 1e6
+    }
+
+    /// Seconds in `value` milliseconds.
+    pub fn ms(value: f64) -> f64 {
+        value / 1e3
+    }
+
+    /// Hertz in `value` megahertz.
+    pub fn mhz(value: f64) -> f64 {
+        value * 1e6
+    }
+
+    /// Hertz in `value` gigahertz.
+    pub fn ghz(value: f64) -> f64 {
+        value * 1e9
+    }
+}
+
+/// Handles of a container with `child` as its only child.
+fn only_child(child: &Handles) -> Handles {
+    let mut handles = Handles::default();
+    handles.insert_child(&[0], child);
+    handles
+}
+
+fn time(property: &'static str, value: f64) -> Result<Time> {
+    Time::new(value).map_err(|_| ScheduleError::InvalidTime { property, value })
+}
+
+fn quantity<T, E>(
+    property: &'static str,
+    value: f64,
+    new: impl FnOnce(f64) -> std::result::Result<T, E>,
+) -> Result<T> {
+    new(value).map_err(|_| ScheduleError::InvalidValue { property, value })
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::assert_approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn build() {
+        let block = stack()
+            .child(play("q0", Some("hann"), 0.5, 20e-9).plateau(10e-9))
+            .child(shift_phase("q0", 0.25))
+            .margin(1e-9, 1e-9)
+            .build()
+            .unwrap();
+        let schedule = grid()
+            .column("auto".parse().unwrap())
+            .column("*".parse().unwrap())
+            .child(repeat(block.clone(), 2).spacing(5e-9), 0, 1)
+            .child(
+                absolute()
+                    .child(0.0, scale_amplitude(block, 0.5))
+                    .child_from_end(-10e-9, barrier(&["q1"])),
+                1,
+                1,
+            )
+            .build()
+            .unwrap();
+
+        assert_approx_eq!(f64, schedule.duration(), 2.0 * 32e-9 + 5e-9 + 32e-9);
+        let json = schedule.to_json().unwrap();
+        assert_eq!(Schedule::from_json(&json).unwrap(), schedule);
+
+        assert!(matches!(
+            stack().child(play("q0", None, f64::NAN, 1e-9)).build(),
+            Err(ScheduleError::InvalidValue {
+                property: "amplitude",
+                ..
+            })
+        ));
+    }
+
+    // The examples of the module docs, which are not run as doctests.
+    #[test]
+    fn module_docs() {
+        let mut block = stack();
+        block.push(play("q0", None, 0.5, 20e-9));
+        let probe = block.push(play("q0", None, 0.2, 40e-9));
+        let schedule = stack().child(block.margin(5e-9, 0.0)).build().unwrap();
+        assert_approx_eq!(f64, schedule.placements(probe)[0].start, 25e-9);
+
+        let schedule = schedule
+            .rebind(probe, play("q0", None, 0.3, 10e-9))
+            .unwrap();
+        assert_approx_eq!(f64, schedule.duration(), 35e-9);
+
+        let schedule = stack()
+            .direction(Direction::Forward)
+            .child(play("q0", Some("hann"), 0.5, 20e-9).plateau(100e-9))
+            .child(barrier(&[]).duration(10e-9))
+            .child(play("q1", None, 0.2, 40e-9))
+            .build()
+            .unwrap();
+        assert_approx_eq!(f64, schedule.duration(), 170e-9);
+
+        let schedule = stack()
+            .child(play("q0", Some("hann"), 0.5, 20e-9))
+            .build()
+            .unwrap();
+        let channels = [channel("q0", 2e9, 100).base_freq(100e6)];
+        let shapes = Shapes::new().hann("hann");
+        let waveforms = compile(&schedule, &channels, &shapes, &CompileOptions::new()).unwrap();
+        assert_eq!(waveforms.get("q0").unwrap().shape(), [2, 100]);
+    }
+
+    #[test]
+    fn units() {
+        use units::{ghz, mhz, ms, ns, us};
+
+        let schedule = play("q0", None, 0.5, ns(20.0))
+            .plateau(us(1.0))
+            .build()
+            .unwrap();
+        assert_approx_eq!(f64, schedule.duration(), 1.02e-6);
+        assert_approx_eq!(f64, ms(2.0), 2e-3);
+        assert_eq!(mhz(100.0), 1e8);
+        assert_eq!(ghz(2.0), 2e9);
+    }
+
+    #[test]
+    fn compile_waveforms() {
+        use units::{ghz, ns};
+
+        let schedule = stack()
+            .direction(Direction::Forward)
+            .child(play("xy", None, 0.5, ns(10.0)))
+            .child(play("z", Some("ramp"), 0.4, ns(10.0)))
+            .child(play("z", None, 0.2, ns(20.0)))
+            .build()
+            .unwrap();
+        let channels = [
+            channel("xy", ghz(1.0), 40).align_level(0),
+            channel("z", ghz(1.0), 40).real(true).align_level(0),
+        ];
+        let shapes = Shapes::new()
+            .samples("ramp", &[0.0, 0.5, 1.0], false)
+            .unwrap();
+
+        let waveforms = compile(&schedule, &channels, &shapes, &CompileOptions::new()).unwrap();
+
+        let xy = waveforms.get("xy").unwrap();
+        assert_eq!(xy.shape(), [2, 40]);
+        assert!(xy.row(0).slice(ndarray::s![..10]).iter().all(|&v| v == 0.5));
+        assert!(xy.row(0).slice(ndarray::s![10..]).iter().all(|&v| v == 0.0));
+        let z = waveforms.get("z").unwrap();
+        assert_eq!(z.shape(), [1, 40]);
+        assert_approx_eq!(f64, z[[0, 15]], 0.2);
+        assert_eq!(z[[0, 25]], 0.2);
+        assert_eq!(z[[0, 35]], 0.0);
+        assert!(waveforms.get("q0").is_none());
+        assert!(waveforms.warnings().is_empty());
+
+        let truncated = [
+            channel("xy", 1e9, 5).align_level(0),
+            channel("z", 1e9, 40).real(true).align_level(0),
+        ];
+        assert!(compile(&schedule, &truncated, &shapes, &CompileOptions::new()).is_err());
+        let options = CompileOptions::new().allow_truncate(true);
+        let waveforms = compile(&schedule, &truncated, &shapes, &options).unwrap();
+        assert_eq!(waveforms.warnings().len(), 1);
+
+        let invalid = [channel("xy", 0.0, 40)];
+        assert!(compile(&schedule, &invalid, &shapes, &CompileOptions::new()).is_err());
+        let invalid = CompileOptions::new().time_tolerance(f64::NAN);
+        assert!(compile(&schedule, &channels, &shapes, &invalid).is_err());
+    }
+
+    #[test]
+    fn handles() {
+        let mut block = stack();
+        let x = block.push(play("q0", None, 0.5, 20e-9));
+        let y = block.push(play("q0", None, 0.5, 10e-9).margin(1e-9, 0.0));
+        let mut root = absolute();
+        let first = root.push(10e-9, repeat(block, 2).spacing(5e-9));
+        let phantom = root.push(0.0, play("q1", None, 0.5, 10e-9).phantom(true));
+        let schedule = root.build().unwrap();
+
+        let starts = |s: &Schedule, h| s.placements(h).iter().map(|p| p.start).collect::<Vec<_>>();
+        assert_eq!(starts(&schedule, first), [10e-9]);
+        assert_approx_eq!(f64, schedule.placements(first)[0].duration, 67e-9);
+        let ys = starts(&schedule, y);
+        assert_eq!(ys.len(), 2);
+        assert_approx_eq!(f64, ys[0], 31e-9);
+        assert_approx_eq!(f64, ys[1], 67e-9);
+        assert!(schedule.placements(phantom).is_empty());
+
+        let schedule = schedule.rebind(first, play("q0", None, 0.5, 5e-9)).unwrap();
+        assert_eq!(schedule.placements(first)[0].duration, 5e-9);
+        assert!(schedule.placements(x).is_empty());
+        assert_eq!(
+            schedule.rebind(y, barrier(&[])),
+            Err(ScheduleError::UnknownHandle(y.0))
+        );
+
+        // A child placed from the end can't grow past the end of the layout.
+        let mut root = absolute();
+        let last = root.push_from_end(-10e-9, play("q0", None, 0.5, 10e-9));
+        let schedule = root.build().unwrap();
+        assert!(schedule.rebind(last, play("q0", None, 0.5, 5e-9)).is_ok());
+        assert!(matches!(
+            schedule.rebind(last, play("q0", None, 0.5, 20e-9)),
+            Err(ScheduleError::InvalidTime {
+                property: "time",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn shared_measurement() {
+        let block = stack().child(play("q0", None, 0.5, 20e-9)).build().unwrap();
+        assert_approx_eq!(f64, block.duration(), 20e-9);
+        let schedule = stack()
+            .child(block.clone())
+            .child(block.clone())
+            .build()
+            .unwrap();
+        let ElementVariant::Stack(v) = &schedule.element().variant else {
+            panic!("Expected a stack");
+        };
+        assert!(v.children().iter().all(|c| Arc::ptr_eq(c, block.element())));
+    }
+}
@@ -0,0 +1,571 @@
+//! Command line schedule compiler.
+//!
+//! The `pulsegen` binary reads a schedule document created by
+//! `Element.to_json` (or the same structure in TOML) and a hardware
+//! configuration in TOML, and writes the sampled waveforms to an NPZ or HDF5
+//! file. With `--check`, the schedule is only validated against the
+//! configuration and the issues are printed without sampling, together with
+//! the waveform memory of the channels with a `memory` limit.
+//!
+//! If the configuration has `devices`, the waveforms of each device are
+//! written to a separate file with the device name appended, and the triggers
+//! of the devices to a CSV file with `_triggers` appended.
+//!
+//! The hardware configuration format is described in `bosing::config`, which
+//! is available with the `config` feature.
+use std::{
+    borrow::Cow,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use hashbrown::HashMap;
+use ndarray::{arr2, Array1, Array2};
+use num::complex::Complex64;
+
+use crate::{
+    budget::check_memory,
+    config::{self, ChannelConfig, HardwareConfig, ShapeConfig},
+    io::{self, resample, ChannelInfo, Precision},
+    pipeline::{estimate_waveform_memory, generate_waveforms, ChannelSpec, Filters, Options},
+    quant::{ChannelId, ShapeId},
+    schedule::{expand_groups, Element, ElementRef},
+    shape::Shape,
+    validate::validate,
+};
+
+/// Compile a schedule into sampled waveforms.
+#[derive(Debug, Parser)]
+#[command(name = "pulsegen", version)]
+pub struct Args {
+    /// Schedule document in JSON or TOML format.
+    schedule: PathBuf,
+    /// Hardware configuration in TOML format.
+    #[arg(short, long)]
+    config: PathBuf,
+    /// Output file with extension `.npz`, or `.h5` if built with HDF5 support.
+    #[arg(short, long, required_unless_present = "check")]
+    output: Option<PathBuf>,
+    /// Validate the schedule against the configuration without sampling.
+    #[arg(long, conflicts_with_all = ["output", "sweep"])]
+    check: bool,
+    /// Sweep a value of the schedule given by a JSON pointer, e.g.
+    /// `/children/0/amplitude=0:1:11`. One output file is written per point
+    /// with the index appended to the file name.
+    #[arg(long, value_parser = parse_sweep)]
+    sweep: Option<Sweep>,
+    /// Floating point precision of the output.
+    #[arg(long, value_enum, default_value_t = PrecisionArg::Double)]
+    precision: PrecisionArg,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PrecisionArg {
+    Single,
+    Double,
+}
+
+impl From<PrecisionArg> for Precision {
+    fn from(value: PrecisionArg) -> Self {
+        match value {
+            PrecisionArg::Single => Precision::Single,
+            PrecisionArg::Double => Precision::Double,
+        }
+    }
+}
+
+/// Linear sweep of a value in the schedule document.
+#[derive(Debug, Clone, PartialEq)]
+struct Sweep {
+    pointer: String,
+    start: f64,
+    stop: f64,
+    count: usize,
+}
+
+impl Sweep {
+    fn points(&self) -> impl Iterator<Item = f64> + '_ {
+        let step = if self.count > 1 {
+            (self.stop - self.start) / (self.count - 1) as f64
+        } else {
+            0.0
+        };
+        (0..self.count).map(move |i| self.start + step * i as f64)
+    }
+}
+
+/// Run the compiler with parsed arguments.
+pub fn run(args: Args) -> Result<()> {
+    let config = config::load(&args.config)?;
+    let document = read_document(&args.schedule)?;
+    let Some(output) = &args.output else {
+        return check(&document, &config);
+    };
+    let precision = args.precision.into();
+    match &args.sweep {
+        None => compile(&document, &config, output, precision),
+        Some(sweep) => {
+            for (i, value) in sweep.points().enumerate() {
+                let mut document = document.clone();
+                let target = document
+                    .get_mut("schedule")
+                    .and_then(|s| s.pointer_mut(&sweep.pointer))
+                    .with_context(|| format!("Sweep target '{}' not found", sweep.pointer))?;
+                *target = value.into();
+                compile(&document, &config, &suffixed_path(output, i), precision)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn read_document(path: &Path) -> Result<serde_json::Value> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let document = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&content)?,
+        _ => serde_json::from_str(&content)?,
+    };
+    Ok(document)
+}
+
+/// Parse the schedule of the document and expand the channel groups.
+fn parse_schedule(document: &serde_json::Value, config: &HardwareConfig) -> Result<ElementRef> {
+    let schedule = Arc::new(Element::from_json(&document.to_string())?);
+    if config.groups.is_empty() {
+        return Ok(schedule);
+    }
+    let groups = config
+        .groups
+        .iter()
+        .map(|(n, m)| {
+            let members = m.iter().map(|c| ChannelId::new(c.as_str())).collect();
+            (ChannelId::new(n.as_str()), members)
+        })
+        .collect();
+    Ok(expand_groups(&schedule, &groups)?)
+}
+
+fn check(document: &serde_json::Value, config: &HardwareConfig) -> Result<()> {
+    let schedule = parse_schedule(document, config)?;
+    let diagnostics = validate(&schedule, config);
+    for d in &diagnostics {
+        eprintln!("{}", d);
+    }
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    if errors > 0 {
+        bail!("Validation failed with {} error(s)", errors);
+    }
+    let channels = channel_specs(config);
+    if channels.iter().any(|c| c.memory.is_some()) {
+        let usages =
+            estimate_waveform_memory(&schedule, &channels, &shapes(config)?, options(config))?;
+        for usage in &usages {
+            println!("{}", usage);
+        }
+        check_memory(&usages)?;
+    }
+    for (name, lo) in &config.lo_freqs {
+        println!("LO of upconverter {}: {}", name, lo);
+    }
+    for (name, d) in &config.devices {
+        println!(
+            "Trigger of device {}: wait {} cycles, delay {}",
+            name, d.trigger.wait_cycles, d.trigger.delay
+        );
+    }
+    Ok(())
+}
+
+fn compile(
+    document: &serde_json::Value,
+    config: &HardwareConfig,
+    output: &Path,
+    precision: Precision,
+) -> Result<()> {
+    let schedule = parse_schedule(document, config)?;
+    let channels = channel_specs(config);
+    let shapes = shapes(config)?;
+    let options = options(config);
+    let crosstalk = config
+        .crosstalk
+        .as_ref()
+        .map(|(m, n)| (m.view(), n.as_slice()));
+    let (waveforms, mut diagnostics) =
+        generate_waveforms(&schedule, &channels, &shapes, crosstalk, options)?;
+    if let Some(leakage) = &config.leakage {
+        for (name, c) in &config.channels {
+            let channel = ChannelId::new(name.as_str());
+            let lo_freq = config
+                .channel_lo_freqs
+                .get(name)
+                .copied()
+                .unwrap_or_default();
+            leakage.check(
+                channel,
+                waveforms[&channel].view(),
+                c.sample_rate,
+                lo_freq,
+                &mut diagnostics,
+            )?;
+        }
+    }
+    for w in diagnostics.warnings() {
+        eprintln!("warning: {}", w);
+    }
+    for (channel, wait) in diagnostics.barrier_wait_totals() {
+        println!("Barrier wait of channel {}: {}", channel, wait);
+    }
+    let mut groups: Vec<_> = config
+        .devices
+        .iter()
+        .map(|(n, d)| {
+            (
+                suffixed_path(output, n),
+                d.channels.iter().collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+    let unassigned: Vec<_> = config
+        .channels
+        .keys()
+        .filter(|n| !config.devices.values().any(|d| d.channels.contains(n)))
+        .collect();
+    if !unassigned.is_empty() {
+        groups.push((output.to_path_buf(), unassigned));
+    }
+    for (path, names) in &groups {
+        write_output(document, config, &waveforms, names, path, precision)?;
+    }
+    if !config.devices.is_empty() {
+        write_trigger_table(
+            config,
+            &suffixed_path(output, "triggers").with_extension("csv"),
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the waveforms of the channels `names` to `output`.
+#[cfg_attr(not(feature = "hdf5"), allow(unused_variables))]
+fn write_output(
+    document: &serde_json::Value,
+    config: &HardwareConfig,
+    waveforms: &HashMap<ChannelId, Array2<f64>>,
+    names: &[&String],
+    output: &Path,
+    precision: Precision,
+) -> Result<()> {
+    let info = |c: &ChannelConfig| ChannelInfo {
+        sample_rate: c.export_sample_rate.unwrap_or(c.sample_rate).value(),
+        delay: c.delay.value(),
+        align_level: c.align_level,
+    };
+    // Waveforms at the export rate of the channels.
+    let waveforms = names
+        .iter()
+        .map(|&n| {
+            let c = &config.channels[n];
+            let w = &waveforms[&ChannelId::new(n.as_str())];
+            let w = match c.export_sample_rate {
+                Some(rate) => Cow::Owned(
+                    resample::resample(w.view(), c.sample_rate.value(), rate.value())
+                        .with_context(|| format!("Failed to resample channel {}", n))?,
+                ),
+                None => Cow::Borrowed(w),
+            };
+            Ok((n, w))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    match output.extension().and_then(|e| e.to_str()) {
+        Some("npz") => {
+            let data = names
+                .iter()
+                .map(|n| {
+                    let samples = waveforms[n]
+                        .columns()
+                        .into_iter()
+                        .map(|c| Complex64::new(c[0], c.get(1).copied().unwrap_or_default()))
+                        .collect();
+                    (n.to_string(), samples)
+                })
+                .collect();
+            let metadata = names
+                .iter()
+                .map(|&n| (n.clone(), info(&config.channels[n])))
+                .collect();
+            io::npz::write_waveforms(output, &data, &metadata, precision)
+        }
+        #[cfg(feature = "hdf5")]
+        Some("h5" | "hdf5") => {
+            let waveforms = names
+                .iter()
+                .map(|&n| io::ChannelWaveform {
+                    name: n,
+                    info: info(&config.channels[n]),
+                    data: waveforms[n].view(),
+                })
+                .collect::<Vec<_>>();
+            let provenance = document["schedule"].to_string();
+            io::hdf5::write_waveforms(output, &waveforms, Some(&provenance), precision)
+        }
+        _ => bail!("Unsupported output file {}", output.display()),
+    }
+}
+
+/// Write the trigger of each device as CSV, with times in seconds.
+fn write_trigger_table(config: &HardwareConfig, path: &Path) -> Result<()> {
+    let mut table = String::from("device,channels,trigger_latency,clock,wait_cycles,delay\n");
+    for (name, d) in &config.devices {
+        table.push_str(&format!(
+            "{},{},{:e},{:e},{},{:e}\n",
+            name,
+            d.channels.join(" "),
+            d.device.latency.value(),
+            d.device.clock.value(),
+            d.trigger.wait_cycles,
+            d.trigger.delay.value()
+        ));
+    }
+    fs::write(path, table).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn channel_specs(config: &HardwareConfig) -> Vec<ChannelSpec> {
+    config
+        .channels
+        .iter()
+        .map(|(n, c)| ChannelSpec {
+            name: ChannelId::new(n.as_str()),
+            base_freq: c.base_freq,
+            sample_rate: c.sample_rate,
+            delay: c.delay,
+            align_level: c.align_level,
+            baseline: c.baseline,
+            ringdown: c.ringdown,
+            net_zero: c.net_zero,
+            idle_tone: c.idle_tone,
+            length: c.length,
+            is_real: c.is_real,
+            time_grid: c.time_grid,
+            memory: c.memory,
+            filters: Filters {
+                predistortion: c.predistortion.clone(),
+                iq_matrix: c.iq_matrix.map(|m| arr2(&m)),
+                offset: c.offset.clone().map(Array1::from),
+                iir: c.iir.as_ref().map(|x| arr2(x)),
+                fir: c.fir.clone().map(Array1::from),
+                filter_offset: c.filter_offset,
+            },
+        })
+        .collect()
+}
+
+fn shapes(config: &HardwareConfig) -> Result<HashMap<ShapeId, Shape>> {
+    config
+        .shapes
+        .iter()
+        .map(|(n, s)| {
+            let shape = shape_from_config(s)?;
+            Ok((ShapeId::new(n.as_str()), shape))
+        })
+        .collect()
+}
+
+fn options(config: &HardwareConfig) -> Options {
+    Options {
+        time_tolerance: config.time_tolerance,
+        amp_tolerance: config.amp_tolerance,
+        allow_oversize: config.allow_oversize,
+        allow_truncate: config.allow_truncate,
+        fractional_delay: config.fractional_delay,
+        oversampling: config.oversampling,
+        time_grid: config.time_grid,
+    }
+}
+
+fn shape_from_config(config: &ShapeConfig) -> Result<Shape> {
+    let shape = match config {
+        ShapeConfig::Hann => Shape::new_hann(),
+        ShapeConfig::Interp {
+            knots,
+            controls,
+            degree,
+        } => Shape::new_interp(knots.clone(), controls.clone(), *degree)?,
+        ShapeConfig::Expr { expr } => Shape::new_expr(expr)?,
+        ShapeConfig::Sinc { num_lobes } => Shape::new_sinc(*num_lobes)?,
+        ShapeConfig::ErfSquare { rise_time } => Shape::new_erf_square(*rise_time)?,
+        ShapeConfig::Exponential { rise, decay } => {
+            Shape::new_exponential(rise, decay.as_deref().unwrap_or(rise))?
+        }
+        ShapeConfig::Normalized {
+            shape,
+            normalization,
+        } => shape_from_config(shape)?.with_normalization(*normalization)?,
+    };
+    Ok(shape)
+}
+
+fn parse_sweep(s: &str) -> Result<Sweep> {
+    let (pointer, range) = s
+        .rsplit_once('=')
+        .context("Expected POINTER=START:STOP:COUNT")?;
+    let parts: Vec<_> = range.split(':').collect();
+    let [start, stop, count] = parts[..] else {
+        bail!("Expected START:STOP:COUNT, got '{}'", range);
+    };
+    Ok(Sweep {
+        pointer: pointer.to_string(),
+        start: start.parse()?,
+        stop: stop.parse()?,
+        count: count.parse()?,
+    })
+}
+
+fn suffixed_path(path: &Path, suffix: impl fmt::Display) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep() {
+        let sweep = parse_sweep("/children/0/amplitude=0:1:3").unwrap();
+
+        assert_eq!(sweep.pointer, "/children/0/amplitude");
+        assert_eq!(sweep.points().collect::<Vec<_>>(), [0.0, 0.5, 1.0]);
+        assert!(parse_sweep("/amplitude=0:1").is_err());
+        assert_eq!(
+            suffixed_path(Path::new("out/wave.npz"), 2),
+            Path::new("out/wave_2.npz")
+        );
+    }
+
+    #[test]
+    fn compile_npz() {
+        let dir = std::env::temp_dir().join(format!("bosing-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let schedule = dir.join("schedule.toml");
+        let config = dir.join("config.toml");
+        fs::write(
+            &schedule,
+            indoc::indoc! {r#"
+                version = 1
+
+                [schedule]
+                type = "Play"
+                channel_id = "xy"
+                shape_id = "hann"
+                amplitude = 0.5
+                width = 4e-9
+            "#},
+        )
+        .unwrap();
+        fs::write(
+            &config,
+            indoc::indoc! {r#"
+                [channels.xy]
+                sample_rate = 1e9
+                length = 8
+
+                [shapes.hann]
+                type = "hann"
+            "#},
+        )
+        .unwrap();
+        let args = Args::parse_from([
+            "pulsegen".as_ref(),
+            schedule.as_os_str(),
+            "--config".as_ref(),
+            config.as_os_str(),
+            "--output".as_ref(),
+            dir.join("out.npz").as_os_str(),
+            "--sweep".as_ref(),
+            "/amplitude=0.1:0.2:2".as_ref(),
+        ]);
+
+        run(args).unwrap();
+
+        assert!(dir.join("out_0.npz").exists());
+        assert!(dir.join("out_1.npz").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compile_devices() {
+        let dir = std::env::temp_dir().join(format!("bosing-cli-devices-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let schedule = dir.join("schedule.toml");
+        let config = dir.join("config.toml");
+        fs::write(
+            &schedule,
+            indoc::indoc! {r#"
+                version = 1
+
+                [schedule]
+                type = "Play"
+                channel_id = "xy"
+                shape_id = "hann"
+                amplitude = 0.5
+                width = 4e-9
+            "#},
+        )
+        .unwrap();
+        fs::write(
+            &config,
+            indoc::indoc! {r#"
+                [channels.xy]
+                sample_rate = 1e9
+                length = 8
+
+                [channels.z]
+                sample_rate = 1e9
+                length = 8
+
+                [shapes.hann]
+                type = "hann"
+
+                [devices.awg0]
+                channels = ["xy"]
+                trigger_latency = 200e-9
+                clock = 250e6
+
+                [devices.awg1]
+                channels = ["z"]
+                trigger_latency = 150e-9
+                clock = 250e6
+            "#},
+        )
+        .unwrap();
+        let args = Args::parse_from([
+            "pulsegen".as_ref(),
+            schedule.as_os_str(),
+            "--config".as_ref(),
+            config.as_os_str(),
+            "--output".as_ref(),
+            dir.join("out.npz").as_os_str(),
+        ]);
+
+        run(args).unwrap();
+
+        assert!(dir.join("out_awg0.npz").exists());
+        assert!(dir.join("out_awg1.npz").exists());
+        assert!(!dir.join("out.npz").exists());
+        let table = fs::read_to_string(dir.join("out_triggers.csv")).unwrap();
+        let rows: Vec<_> = table.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[1].starts_with("awg0,xy,"));
+        // 50 ns lead is 12 cycles of 4 ns and 2 ns of channel delay.
+        assert!(rows[2].starts_with("awg1,z,1.5e-7,2.5e8,12,"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
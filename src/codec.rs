@@ -0,0 +1,203 @@
+//! Versioned envelopes for serialized schedules and pulse lists.
+//!
+//! Every document carries a format version. Writers may request an older
+//! version supported by the reader, and readers reject versions outside
+//! [`MIN_VERSION`]..=[`CURRENT_VERSION`] before decoding the payload.
+//!
+//! The version is bumped whenever a field is added that changes the meaning
+//! of a document, because older readers ignore fields they don't know. Fields
+//! of newer versions are only written if they differ from their default, and
+//! a payload using them can't be written with an older version.
+//!
+//! Versions:
+//!
+//! 1. Initial schema.
+//! 2. Pulse phase reference and hold, asymmetric edges, sampled pulses,
+//!    amplitude scaling, loop count variables, barrier flex and slots, stack
+//!    and grid padding, absolute entries placed from the end, and pulse
+//!    library references.
+use anyhow::{bail, Result};
+#[cfg(any(feature = "python", feature = "grpc", test))]
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Latest format version written by default.
+pub(crate) const CURRENT_VERSION: u32 = 2;
+/// Oldest format version that can still be read and written.
+pub(crate) const MIN_VERSION: u32 = 1;
+
+/// Payload whose encoding depends on the format version.
+pub(crate) trait Versioned {
+    /// Oldest format version that can represent the payload.
+    fn required_version(&self) -> u32;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    payload: T,
+}
+
+#[cfg(any(feature = "python", feature = "grpc", test))]
+#[derive(Debug, Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// Choose the version to write given the version requested by the reader
+/// and the version required by the payload.
+pub(crate) fn negotiate_version(requested: Option<u32>, required: u32) -> Result<u32> {
+    let version = requested.unwrap_or(CURRENT_VERSION);
+    check_version(version)?;
+    check_required(version, required)?;
+    Ok(version)
+}
+
+/// Reject a payload that uses features newer than the document version.
+pub(crate) fn check_required(version: u32, required: u32) -> Result<()> {
+    if required > version {
+        bail!(
+            "Format version {} is required, the document has version {}",
+            required,
+            version
+        );
+    }
+    Ok(())
+}
+
+pub(crate) fn check_version(version: u32) -> Result<()> {
+    if !(MIN_VERSION..=CURRENT_VERSION).contains(&version) {
+        bail!(
+            "Unsupported format version {}, supported versions are {}..={}",
+            version,
+            MIN_VERSION,
+            CURRENT_VERSION
+        );
+    }
+    Ok(())
+}
+
+/// Encode the payload as MessagePack with named fields.
+pub(crate) fn to_msgpack<T: Serialize + Versioned>(
+    payload: &T,
+    version: Option<u32>,
+) -> Result<Vec<u8>> {
+    let envelope = Envelope {
+        version: negotiate_version(version, payload.required_version())?,
+        payload,
+    };
+    Ok(rmp_serde::to_vec_named(&envelope)?)
+}
+
+/// Decode a MessagePack document created by [`to_msgpack`].
+#[cfg(any(feature = "python", feature = "grpc", test))]
+pub(crate) fn from_msgpack<T: DeserializeOwned + Versioned>(bytes: &[u8]) -> Result<T> {
+    let header: Header = rmp_serde::from_slice(bytes)?;
+    check_version(header.version)?;
+    let envelope: Envelope<T> = rmp_serde::from_slice(bytes)?;
+    check_required(header.version, envelope.payload.required_version())?;
+    Ok(envelope.payload)
+}
+
+/// Encode the payload as a JSON document with the current version.
+#[cfg(any(feature = "python", feature = "test-utils", test))]
+pub(crate) fn to_json<T: Serialize + Versioned>(payload: &T) -> Result<String> {
+    let envelope = Envelope {
+        version: negotiate_version(None, payload.required_version())?,
+        payload,
+    };
+    Ok(serde_json::to_string(&envelope)?)
+}
+
+/// Decode a JSON document created by [`to_json`].
+#[cfg(any(feature = "python", test))]
+pub(crate) fn from_json<T: DeserializeOwned + Versioned>(s: &str) -> Result<T> {
+    let envelope: Envelope<serde_json::Value> = serde_json::from_str(s)?;
+    check_version(envelope.version)?;
+    let payload: T = serde_json::from_value(envelope.payload)?;
+    check_required(envelope.version, payload.required_version())?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Versioned for Vec<(f64, String)> {
+        // Pretend that infinite values need the current version.
+        fn required_version(&self) -> u32 {
+            if self.iter().any(|(v, _)| v.is_infinite()) {
+                CURRENT_VERSION
+            } else {
+                MIN_VERSION
+            }
+        }
+    }
+
+    impl Versioned for i32 {
+        fn required_version(&self) -> u32 {
+            MIN_VERSION
+        }
+    }
+
+    #[test]
+    fn msgpack_round_trip() {
+        let value = vec![(1.0, "a".to_string()), (f64::INFINITY, "b".to_string())];
+
+        let bytes = to_msgpack(&value, None).unwrap();
+        let decoded: Vec<(f64, String)> = from_msgpack(&bytes).unwrap();
+
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let value = vec![(1.0, "a".to_string()), (0.5, "b".to_string())];
+
+        let json = to_json(&value).unwrap();
+        let decoded: Vec<(f64, String)> = from_json(&json).unwrap();
+
+        assert_eq!(decoded, value);
+        assert!(from_json::<i32>(r#"{"version": 0, "payload": 1}"#).is_err());
+        assert!(from_json::<i32>("1").is_err());
+    }
+
+    #[test]
+    fn version_negotiation() {
+        assert_eq!(
+            negotiate_version(None, MIN_VERSION).unwrap(),
+            CURRENT_VERSION
+        );
+        assert_eq!(
+            negotiate_version(Some(MIN_VERSION), MIN_VERSION).unwrap(),
+            MIN_VERSION
+        );
+        assert!(negotiate_version(Some(CURRENT_VERSION + 1), MIN_VERSION).is_err());
+        assert!(negotiate_version(Some(0), MIN_VERSION).is_err());
+        assert!(negotiate_version(Some(MIN_VERSION), CURRENT_VERSION).is_err());
+    }
+
+    #[test]
+    fn reject_newer_features() {
+        let value = vec![(f64::INFINITY, "a".to_string())];
+
+        assert!(to_msgpack(&value, Some(MIN_VERSION)).is_err());
+        let old = Envelope {
+            version: MIN_VERSION,
+            payload: &value,
+        };
+        let bytes = rmp_serde::to_vec_named(&old).unwrap();
+        assert!(from_msgpack::<Vec<(f64, String)>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn reject_unsupported_version() {
+        let envelope = Envelope {
+            version: CURRENT_VERSION + 1,
+            payload: 1,
+        };
+        let bytes = rmp_serde::to_vec_named(&envelope).unwrap();
+
+        assert!(from_msgpack::<i32>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,820 @@
+//! Hardware configuration loader.
+//!
+//! The configuration describes the channels, shapes, and crosstalk of the
+//! setup in TOML (`.toml`) or YAML (`.yaml`/`.yml`) format:
+//!
+//! ```toml
+//! time_tolerance = 1e-12 # optional
+//! amp_tolerance = 1.52587890625e-6 # optional, 0.1 / 2^16
+//! allow_oversize = false # optional
+//! allow_truncate = false # optional, clip pulses outside the waveforms
+//! fractional_delay = false # optional, interpolate sub-sample offsets
+//! oversampling = 1 # optional, envelope oversampling factor before decimation
+//!
+//! [time_grid] # optional, snap instructions to the grid
+//! step = 8e-9
+//! min_length = 32e-9 # optional
+//! rounding = "nearest" # optional, "nearest", "floor", or "ceil"
+//!
+//! [channels.xy0]
+//! base_freq = 100e6 # optional
+//! sample_rate = 2e9
+//! length = 1000
+//! delay = 0.0 # optional
+//! align_level = -10 # optional
+//! baseline = [0.0, 0.0] # optional, resting level [re, im] under the pulses
+//! ringdown = { tau = 100e-9, length = 20e-9 } # optional, tail after pulses
+//! net_zero = false # optional, follow pulses by inverted copies
+//! idle_tone = { amplitude = 0.01, freq = 100e6, phase = 0.0 } # optional, phase in cycles
+//! predistortion = { input = [0.5, 1.0], output = [0.52, 1.1] } # optional, or { coefficients = [1.0, 0.1] }
+//! is_real = false # optional
+//! iq_matrix = [[1.0, 0.0], [0.0, 1.0]] # optional
+//! offset = [0.0, 0.0] # optional, 1 value if is_real
+//! iir = [[1.0, 0.0, 0.0, 1.0, 0.0, 0.0]] # optional, second-order sections
+//! fir = [1.0] # optional
+//! filter_offset = false # optional
+//! time_grid = { step = 16e-9 } # optional, overrides the global time grid
+//! memory = { samples = 65536, granularity = 16 } # optional, waveform memory
+//! export_sample_rate = 2.4e9 # optional, resample the written waveform
+//!
+//! [shapes.hann]
+//! type = "hann"
+//!
+//! [shapes.drag]
+//! type = "interp"
+//! knots = [0.0, 0.0, 0.0, 0.0, 0.5, 1.0, 1.0, 1.0, 1.0]
+//! controls = [0.0, 0.0, 1.0, 0.0, 0.0]
+//! degree = 3
+//!
+//! [shapes.gauss]
+//! type = "expr"
+//! expr = "exp(-x^2 / (2 * 0.15^2))"
+//!
+//! [shapes.flux]
+//! type = "exponential"
+//! rise = [[0.02, 1.0]] # [tau, weight] terms relative to the width
+//! decay = [[0.02, 0.8], [0.1, 0.2]] # optional, same as rise by default
+//!
+//! [shapes.sinc]
+//! type = "sinc"
+//! num_lobes = 3
+//!
+//! [shapes.readout]
+//! type = "erf_square"
+//! rise_time = 0.05 # relative to the width
+//!
+//! [shapes.hann_area]
+//! type = "normalized"
+//! normalization = "area" # or "peak", "rms"
+//! shape = { type = "hann" }
+//!
+//! [crosstalk]
+//! path = "crosstalk.csv" # relative to the configuration file
+//! channels = ["xy0"]
+//!
+//! [upconverters.xy] # optional, plan the LO shared by channels
+//! drive_freqs = { xy0 = 5.1e9 } # overrides base_freq of the channels
+//! max_if = 400e6 # optional, half the smallest sample rate by default
+//! guard = 20e6 # optional, minimum distance of spurs to drive frequencies
+//!
+//! [devices.awg0] # optional, align AWGs started by a shared trigger
+//! channels = ["xy0"]
+//! trigger_latency = 200e-9 # from the trigger to the start of the output
+//! clock = 250e6 # sequencer clock, waits are whole cycles
+//!
+//! [groups] # optional, broadcast pulses and frame operations to the members
+//! all_flux = ["z0", "z1"]
+//!
+//! [leakage] # optional, warn about power at sensitive frequencies
+//! threshold = -40.0 # dB relative to a full scale tone
+//! bandwidth = 2e6 # optional, band around each frequency
+//! window = "hann" # optional, "rectangular", "hann", or "blackman"
+//! frequencies = { q1 = 5.2e9, tls0 = 5.05e9 } # before the up-converters
+//! ```
+//!
+//! The LO of each up-converter is chosen such that the LO leakage and the
+//! images of the channels are at least `guard` away from every drive
+//! frequency, and `base_freq` of the channels is set to the intermediate
+//! frequency.
+//!
+//! Devices with a shorter trigger latency wait after the trigger so that the
+//! outputs of all devices start together. The part of the wait shorter than
+//! one clock cycle is added to the `delay` of the channels of the device.
+//!
+//! Elements targeting a group in the schedule are expanded to its member
+//! channels before compiling. Group names can't be channel names, and members
+//! have to be channels.
+//!
+//! The leakage frequencies are compared with the spectrum of each waveform
+//! shifted by the planned LO of its up-converter, so they are the physical
+//! frequencies of neighbor qubits or two-level systems. Channels without an
+//! up-converter are compared directly.
+//!
+//! The crosstalk matrix file has one row per line with values separated by
+//! commas or whitespace, as written by `numpy.savetxt`. Empty lines and lines
+//! starting with `#` are ignored.
+//!
+//! Syntax errors and invalid values are reported with the line and column in
+//! the configuration file.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use ndarray::Array2;
+use num::complex::Complex64;
+use serde::Deserialize;
+
+use crate::{
+    analysis::spectrum::{LeakageCheck, Window},
+    budget::MemoryLimit,
+    executor::{Ringdown, TimeGrid},
+    io::resample,
+    lo,
+    pipeline::Options,
+    pulse::{IdleTone, Predistortion},
+    quant::{Amplitude, ChannelId, Frequency, Time},
+    shape::Normalization,
+    trigger::{self, Device, Trigger},
+};
+
+/// Validated hardware configuration.
+#[derive(Debug, Clone)]
+pub(crate) struct HardwareConfig {
+    pub(crate) time_tolerance: Time,
+    pub(crate) amp_tolerance: Amplitude,
+    pub(crate) allow_oversize: bool,
+    pub(crate) allow_truncate: bool,
+    pub(crate) fractional_delay: bool,
+    pub(crate) oversampling: usize,
+    pub(crate) channels: BTreeMap<String, ChannelConfig>,
+    pub(crate) shapes: BTreeMap<String, ShapeConfig>,
+    pub(crate) crosstalk: Option<(Array2<f64>, Vec<ChannelId>)>,
+    pub(crate) time_grid: Option<TimeGrid>,
+    /// Planned LO frequency of each up-converter.
+    pub(crate) lo_freqs: BTreeMap<String, Frequency>,
+    /// Planned LO frequency of each channel in an up-converter.
+    pub(crate) channel_lo_freqs: BTreeMap<String, Frequency>,
+    pub(crate) devices: BTreeMap<String, DeviceConfig>,
+    /// Member channels of each channel group.
+    pub(crate) groups: BTreeMap<String, Vec<String>>,
+    pub(crate) leakage: Option<LeakageCheck>,
+}
+
+/// AWG with its channels and the planned trigger.
+#[derive(Debug, Clone)]
+pub(crate) struct DeviceConfig {
+    pub(crate) channels: Vec<String>,
+    pub(crate) device: Device,
+    pub(crate) trigger: Trigger,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "ChannelData")]
+pub(crate) struct ChannelConfig {
+    pub(crate) base_freq: Frequency,
+    pub(crate) sample_rate: Frequency,
+    pub(crate) length: usize,
+    pub(crate) delay: Time,
+    pub(crate) align_level: i32,
+    pub(crate) baseline: Complex64,
+    pub(crate) ringdown: Option<Ringdown>,
+    pub(crate) net_zero: bool,
+    pub(crate) idle_tone: Option<IdleTone>,
+    pub(crate) predistortion: Option<Predistortion>,
+    pub(crate) iq_matrix: Option<[[f64; 2]; 2]>,
+    pub(crate) offset: Option<Vec<f64>>,
+    pub(crate) iir: Option<Vec<[f64; 6]>>,
+    pub(crate) fir: Option<Vec<f64>>,
+    pub(crate) filter_offset: bool,
+    pub(crate) is_real: bool,
+    pub(crate) time_grid: Option<TimeGrid>,
+    pub(crate) memory: Option<MemoryLimit>,
+    /// Rate of the written waveform if the hardware runs at a different rate
+    /// than `sample_rate`.
+    pub(crate) export_sample_rate: Option<Frequency>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum ShapeConfig {
+    Hann,
+    Interp {
+        knots: Vec<f64>,
+        controls: Vec<f64>,
+        degree: usize,
+    },
+    Expr {
+        expr: String,
+    },
+    Exponential {
+        rise: Vec<(f64, f64)>,
+        decay: Option<Vec<(f64, f64)>>,
+    },
+    Sinc {
+        num_lobes: usize,
+    },
+    ErfSquare {
+        rise_time: f64,
+    },
+    Normalized {
+        shape: Box<ShapeConfig>,
+        normalization: Normalization,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigData {
+    #[serde(default = "default_time_tolerance")]
+    time_tolerance: Time,
+    #[serde(default = "default_amp_tolerance")]
+    amp_tolerance: Amplitude,
+    #[serde(default)]
+    allow_oversize: bool,
+    #[serde(default)]
+    allow_truncate: bool,
+    #[serde(default)]
+    fractional_delay: bool,
+    #[serde(default = "default_oversampling")]
+    oversampling: usize,
+    channels: BTreeMap<String, ChannelConfig>,
+    #[serde(default)]
+    shapes: BTreeMap<String, ShapeConfig>,
+    crosstalk: Option<CrosstalkData>,
+    time_grid: Option<TimeGrid>,
+    #[serde(default)]
+    upconverters: BTreeMap<String, UpconverterData>,
+    #[serde(default)]
+    devices: BTreeMap<String, DeviceData>,
+    #[serde(default)]
+    groups: BTreeMap<String, Vec<String>>,
+    leakage: Option<LeakageData>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChannelData {
+    #[serde(default)]
+    base_freq: Frequency,
+    sample_rate: Frequency,
+    length: usize,
+    #[serde(default)]
+    delay: Time,
+    #[serde(default = "default_align_level")]
+    align_level: i32,
+    #[serde(default)]
+    baseline: [f64; 2],
+    ringdown: Option<Ringdown>,
+    #[serde(default)]
+    net_zero: bool,
+    idle_tone: Option<IdleTone>,
+    predistortion: Option<Predistortion>,
+    iq_matrix: Option<[[f64; 2]; 2]>,
+    offset: Option<Vec<f64>>,
+    iir: Option<Vec<[f64; 6]>>,
+    fir: Option<Vec<f64>>,
+    #[serde(default)]
+    filter_offset: bool,
+    #[serde(default)]
+    is_real: bool,
+    time_grid: Option<TimeGrid>,
+    memory: Option<MemoryLimit>,
+    export_sample_rate: Option<Frequency>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UpconverterData {
+    drive_freqs: BTreeMap<String, Frequency>,
+    max_if: Option<Frequency>,
+    #[serde(default)]
+    guard: Frequency,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeviceData {
+    channels: Vec<String>,
+    trigger_latency: Time,
+    clock: Frequency,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LeakageData {
+    threshold: f64,
+    #[serde(default)]
+    bandwidth: Frequency,
+    window: Option<String>,
+    frequencies: BTreeMap<String, Frequency>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CrosstalkData {
+    path: PathBuf,
+    channels: Vec<ChannelId>,
+}
+
+fn default_time_tolerance() -> Time {
+    Options::default().time_tolerance
+}
+
+fn default_amp_tolerance() -> Amplitude {
+    Options::default().amp_tolerance
+}
+
+fn default_oversampling() -> usize {
+    Options::default().oversampling
+}
+
+fn default_align_level() -> i32 {
+    -10
+}
+
+impl TryFrom<ChannelData> for ChannelConfig {
+    type Error = String;
+
+    fn try_from(data: ChannelData) -> Result<Self, Self::Error> {
+        if data.sample_rate.value() <= 0.0 {
+            return Err("sample_rate should be positive".to_string());
+        }
+        if let Some(offset) = &data.offset {
+            let expected = if data.is_real { 1 } else { 2 };
+            if offset.len() != expected {
+                return Err(format!(
+                    "offset should have {} values for is_real = {}",
+                    expected, data.is_real
+                ));
+            }
+        }
+        if data.memory.is_some_and(|m| m.granularity == 0) {
+            return Err("memory granularity should be positive".to_string());
+        }
+        if let Some(rate) = data.export_sample_rate {
+            resample::ratio(data.sample_rate.value(), rate.value()).map_err(|e| e.to_string())?;
+        }
+        Ok(Self {
+            base_freq: data.base_freq,
+            sample_rate: data.sample_rate,
+            length: data.length,
+            delay: data.delay,
+            align_level: data.align_level,
+            baseline: Complex64::new(data.baseline[0], data.baseline[1]),
+            ringdown: data.ringdown,
+            net_zero: data.net_zero,
+            idle_tone: data.idle_tone,
+            predistortion: data.predistortion,
+            // Same as the python API, IQ matrix is ignored for real channels.
+            iq_matrix: if data.is_real { None } else { data.iq_matrix },
+            offset: data.offset,
+            iir: data.iir,
+            fir: data.fir,
+            filter_offset: data.filter_offset,
+            is_real: data.is_real,
+            time_grid: data.time_grid,
+            memory: data.memory,
+            export_sample_rate: data.export_sample_rate,
+        })
+    }
+}
+
+/// Load the configuration from a TOML or YAML file depending on the extension.
+pub(crate) fn load(path: impl AsRef<Path>) -> Result<HardwareConfig> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let data: ConfigData = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(anyhow::Error::from),
+        Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+        _ => bail!(
+            "Unsupported hardware config {}, expected .toml or .yaml",
+            path.display()
+        ),
+    }
+    .with_context(|| format!("Invalid hardware config {}", path.display()))?;
+    let mut channels = data.channels;
+    let mut shared = data
+        .upconverters
+        .values()
+        .flat_map(|u| u.drive_freqs.keys())
+        .collect::<Vec<_>>();
+    shared.sort_unstable();
+    if let Some(w) = shared.windows(2).find(|w| w[0] == w[1]) {
+        bail!("Channel '{}' is in more than one upconverter", w[0]);
+    }
+    let lo_freqs: BTreeMap<_, _> = data
+        .upconverters
+        .iter()
+        .map(|(name, u)| {
+            let lo = plan_upconverter(u, &mut channels)
+                .with_context(|| format!("Invalid upconverter '{}'", name))?;
+            Ok((name.clone(), lo))
+        })
+        .collect::<Result<_>>()?;
+    let channel_lo_freqs = data
+        .upconverters
+        .iter()
+        .flat_map(|(name, u)| {
+            let lo = lo_freqs[name];
+            u.drive_freqs.keys().map(move |c| (c.clone(), lo))
+        })
+        .collect();
+    let devices = plan_devices(data.devices, data.time_tolerance, &mut channels)?;
+    for (name, members) in &data.groups {
+        if channels.contains_key(name) {
+            bail!("Group '{}' has the name of a channel", name);
+        }
+        if members.is_empty() {
+            bail!("Group '{}' has no channel", name);
+        }
+        if let Some(m) = members.iter().find(|m| !channels.contains_key(*m)) {
+            bail!("Channel '{}' of group '{}' not found", m, name);
+        }
+    }
+    let leakage = data
+        .leakage
+        .map(|l| {
+            let window = match &l.window {
+                Some(name) => Window::from_name(name)
+                    .with_context(|| format!("Invalid leakage window '{}'", name))?,
+                None => Window::default(),
+            };
+            anyhow::Ok(LeakageCheck {
+                frequencies: l.frequencies.into_iter().collect(),
+                threshold_db: l.threshold,
+                bandwidth: l.bandwidth,
+                window,
+            })
+        })
+        .transpose()?;
+    let crosstalk = data
+        .crosstalk
+        .map(|c| {
+            let matrix_path = path.parent().unwrap_or(Path::new("")).join(&c.path);
+            load_crosstalk(&matrix_path, c.channels, &channels)
+                .with_context(|| format!("Invalid crosstalk matrix {}", matrix_path.display()))
+        })
+        .transpose()?;
+    Ok(HardwareConfig {
+        time_tolerance: data.time_tolerance,
+        amp_tolerance: data.amp_tolerance,
+        allow_oversize: data.allow_oversize,
+        allow_truncate: data.allow_truncate,
+        fractional_delay: data.fractional_delay,
+        oversampling: data.oversampling,
+        channels,
+        shapes: data.shapes,
+        crosstalk,
+        time_grid: data.time_grid,
+        lo_freqs,
+        channel_lo_freqs,
+        devices,
+        groups: data.groups,
+        leakage,
+    })
+}
+
+/// Plan the LO and set the intermediate frequencies of the channels.
+fn plan_upconverter(
+    upconverter: &UpconverterData,
+    channels: &mut BTreeMap<String, ChannelConfig>,
+) -> Result<Frequency> {
+    let mut sample_rates = Vec::new();
+    for name in upconverter.drive_freqs.keys() {
+        let Some(c) = channels.get(name) else {
+            bail!("Channel '{}' not found", name);
+        };
+        sample_rates.push(c.sample_rate);
+    }
+    let max_if = match upconverter.max_if {
+        Some(max_if) => max_if,
+        None => sample_rates.into_iter().min().unwrap_or_default() / 2.0,
+    };
+    let drive_freqs: Vec<_> = upconverter.drive_freqs.values().copied().collect();
+    let plan = lo::plan_lo(&drive_freqs, max_if, upconverter.guard)?;
+    for (name, if_freq) in upconverter.drive_freqs.keys().zip(plan.if_freqs) {
+        channels
+            .get_mut(name)
+            .expect("Channel should exist")
+            .base_freq = if_freq;
+    }
+    Ok(plan.lo_freq)
+}
+
+/// Plan the triggers and add the lead delays to the channels of the devices.
+fn plan_devices(
+    devices: BTreeMap<String, DeviceData>,
+    time_tolerance: Time,
+    channels: &mut BTreeMap<String, ChannelConfig>,
+) -> Result<BTreeMap<String, DeviceConfig>> {
+    let mut assigned = devices
+        .values()
+        .flat_map(|d| &d.channels)
+        .collect::<Vec<_>>();
+    assigned.sort_unstable();
+    if let Some(w) = assigned.windows(2).find(|w| w[0] == w[1]) {
+        bail!("Channel '{}' is in more than one device", w[0]);
+    }
+    if let Some(n) = assigned.iter().find(|n| !channels.contains_key(n.as_str())) {
+        bail!("Channel '{}' of a device not found", n);
+    }
+    let models: Vec<_> = devices
+        .values()
+        .map(|d| Device {
+            latency: d.trigger_latency,
+            clock: d.clock,
+        })
+        .collect();
+    let triggers = trigger::plan_triggers(&models, time_tolerance)?;
+    let mut planned = BTreeMap::new();
+    for ((name, data), (device, trigger)) in
+        devices.into_iter().zip(models.into_iter().zip(triggers))
+    {
+        for n in &data.channels {
+            let channel = channels.get_mut(n).expect("Channel should exist");
+            channel.delay += trigger.delay;
+        }
+        planned.insert(
+            name,
+            DeviceConfig {
+                channels: data.channels,
+                device,
+                trigger,
+            },
+        );
+    }
+    Ok(planned)
+}
+
+fn load_crosstalk(
+    path: &Path,
+    names: Vec<ChannelId>,
+    channels: &BTreeMap<String, ChannelConfig>,
+) -> Result<(Array2<f64>, Vec<ChannelId>)> {
+    if let Some(n) = names.iter().find(|n| !channels.contains_key(n.as_ref())) {
+        bail!("Channel '{}' not found", n);
+    }
+    let content = fs::read_to_string(path)?;
+    let matrix = parse_matrix(&content)?;
+    if matrix.shape() != [names.len(), names.len()] {
+        bail!(
+            "The matrix should be {0}x{0} for {0} channels, got {1}x{2}",
+            names.len(),
+            matrix.nrows(),
+            matrix.ncols()
+        );
+    }
+    Ok((matrix, names))
+}
+
+fn parse_matrix(content: &str) -> Result<Array2<f64>> {
+    let mut values = Vec::new();
+    let mut ncols = None;
+    let mut nrows = 0;
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let row = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<f64>()
+                    .with_context(|| format!("Invalid number '{}' at line {}", s, i + 1))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        match ncols {
+            Some(n) if n != row.len() => {
+                bail!("Expected {} values at line {}, got {}", n, i + 1, row.len())
+            }
+            _ => ncols = Some(row.len()),
+        }
+        values.extend(row);
+        nrows += 1;
+    }
+    Ok(Array2::from_shape_vec((nrows, ncols.unwrap_or(0)), values)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp(name: &str, content: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bosing-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_toml_and_yaml() {
+        write_temp("crosstalk.csv", "# xy0, xy1\n1.0, 0.1\n0.2 1.0\n");
+        let toml_path = write_temp(
+            "hardware.toml",
+            indoc::indoc! {r#"
+                [channels.xy0]
+                sample_rate = 2e9
+                length = 100
+                offset = [0.1, 0.2]
+
+                [channels.xy1]
+                sample_rate = 2e9
+                length = 100
+                is_real = true
+                iq_matrix = [[1.0, 0.0], [0.0, 1.0]]
+                predistortion = { coefficients = [1.0, 0.1] }
+
+                [shapes.hann]
+                type = "hann"
+
+                [crosstalk]
+                path = "crosstalk.csv"
+                channels = ["xy0", "xy1"]
+            "#},
+        );
+        let yaml_path = write_temp(
+            "hardware.yaml",
+            indoc::indoc! {r#"
+                channels:
+                  xy0:
+                    sample_rate: 2.0e+9
+                    length: 100
+                    offset: [0.1, 0.2]
+                  xy1:
+                    sample_rate: 2.0e+9
+                    length: 100
+                    is_real: true
+                    iq_matrix: [[1.0, 0.0], [0.0, 1.0]]
+                    predistortion: { coefficients: [1.0, 0.1] }
+                shapes:
+                  hann:
+                    type: hann
+                crosstalk:
+                  path: crosstalk.csv
+                  channels: [xy0, xy1]
+            "#},
+        );
+
+        for path in [toml_path, yaml_path] {
+            let config = load(&path).unwrap();
+
+            assert_eq!(config.channels["xy0"].align_level, -10);
+            assert_eq!(config.channels["xy0"].offset, Some(vec![0.1, 0.2]));
+            assert!(config.channels["xy1"].iq_matrix.is_none());
+            assert_eq!(
+                config.channels["xy1"].predistortion,
+                Some(Predistortion::Polynomial {
+                    coefficients: vec![1.0, 0.1]
+                })
+            );
+            assert!(matches!(config.shapes["hann"], ShapeConfig::Hann));
+            let (matrix, names) = config.crosstalk.unwrap();
+            assert_eq!(matrix, ndarray::array![[1.0, 0.1], [0.2, 1.0]]);
+            assert_eq!(names, [ChannelId::new("xy0"), ChannelId::new("xy1")]);
+        }
+    }
+
+    #[test]
+    fn plan_upconverters() {
+        let path = write_temp(
+            "upconverter.toml",
+            indoc::indoc! {r#"
+                [channels.xy0]
+                sample_rate = 1e9
+                length = 100
+
+                [channels.xy1]
+                sample_rate = 1e9
+                length = 100
+
+                [upconverters.xy]
+                drive_freqs = { xy0 = 5.0e9, xy1 = 5.3e9 }
+                guard = 50e6
+            "#},
+        );
+        let config = load(&path).unwrap();
+
+        let lo = config.lo_freqs["xy"].value();
+        assert_eq!(lo, 4.8e9);
+        assert_eq!(config.channels["xy0"].base_freq.value(), 5.0e9 - lo);
+        assert_eq!(config.channels["xy1"].base_freq.value(), 5.3e9 - lo);
+
+        let path = write_temp(
+            "upconverter-shared.toml",
+            indoc::indoc! {r#"
+                [channels.xy0]
+                sample_rate = 1e9
+                length = 100
+
+                [upconverters.a]
+                drive_freqs = { xy0 = 5.0e9 }
+
+                [upconverters.b]
+                drive_freqs = { xy0 = 5.0e9 }
+            "#},
+        );
+        assert!(load(&path).is_err());
+    }
+
+    #[test]
+    fn groups() {
+        let config = |groups| {
+            let path = write_temp(
+                "groups.toml",
+                &format!(
+                    indoc::indoc! {r#"
+                        [channels.z0]
+                        sample_rate = 1e9
+                        length = 100
+
+                        [channels.z1]
+                        sample_rate = 1e9
+                        length = 100
+
+                        [groups]
+                        {}
+                    "#},
+                    groups
+                ),
+            );
+            load(&path)
+        };
+
+        let loaded = config(r#"all_flux = ["z0", "z1"]"#).unwrap();
+        assert_eq!(loaded.groups["all_flux"], ["z0", "z1"]);
+        assert!(config(r#"z0 = ["z1"]"#).is_err());
+        assert!(config(r#"all_flux = ["z0", "z2"]"#).is_err());
+        assert!(config("all_flux = []").is_err());
+    }
+
+    #[test]
+    fn leakage() {
+        let config = |window| {
+            let path = write_temp(
+                "leakage.toml",
+                &format!(
+                    indoc::indoc! {r#"
+                        [channels.xy0]
+                        sample_rate = 1e9
+                        length = 100
+
+                        [upconverters.xy]
+                        drive_freqs = {{ xy0 = 5.0e9 }}
+
+                        [leakage]
+                        threshold = -40.0
+                        window = "{}"
+                        frequencies = {{ q1 = 5.2e9 }}
+                    "#},
+                    window
+                ),
+            );
+            load(&path)
+        };
+
+        let loaded = config("blackman").unwrap();
+        let leakage = loaded.leakage.unwrap();
+        assert_eq!(leakage.window, Window::Blackman);
+        assert_eq!(leakage.frequencies[0].0, "q1");
+        assert_eq!(loaded.channel_lo_freqs["xy0"], loaded.lo_freqs["xy"]);
+        assert!(config("kaiser").is_err());
+    }
+
+    #[test]
+    fn error_position() {
+        let path = write_temp(
+            "invalid.toml",
+            indoc::indoc! {r#"
+                [channels.xy0]
+                sample_rate = 2e9
+                length = 100
+                offset = [0.1]
+            "#},
+        );
+
+        let message = format!("{:#}", load(&path).unwrap_err());
+
+        assert!(message.contains("line 1"), "{}", message);
+        assert!(
+            message.contains("offset should have 2 values"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn matrix() {
+        assert_eq!(
+            parse_matrix("1 2\n\n3 4\n").unwrap(),
+            ndarray::array![[1.0, 2.0], [3.0, 4.0]]
+        );
+        let message = parse_matrix("1, 2\n3\n").unwrap_err().to_string();
+        assert_eq!(message, "Expected 2 values at line 2, got 1");
+    }
+}
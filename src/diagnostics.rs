@@ -0,0 +1,143 @@
+//! Non-fatal warnings and timing records collected while generating
+//! waveforms.
+use std::fmt;
+
+#[cfg(feature = "spectrum")]
+use crate::quant::Frequency;
+use crate::quant::{ChannelId, Time};
+
+/// A non-fatal issue that may cause the output to differ from the intention.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Warning {
+    /// An element is placed in a span shorter than its content because
+    /// `allow_oversize` is set.
+    Oversize { required: Time, available: Time },
+    /// A pulse with amplitude within the amplitude tolerance is dropped.
+    ZeroAmplitude { channel: ChannelId, time: Time },
+    /// Pulses with the same envelope and frequency starting at the same time
+    /// are merged into one.
+    Coalesced { channel: ChannelId, time: Time },
+    /// A pulse outside the waveform is clipped because `allow_truncate` is set.
+    Truncated { channel: ChannelId, time: Time },
+    /// An instruction is moved or resized by more than the time tolerance to
+    /// fit the time grid.
+    Snapped { time: Time, adjustment: Time },
+    /// The time integral of the pulses of a net-zero channel is not zero, e.g.
+    /// because of crosstalk or truncated pulses.
+    NetArea { channel: ChannelId, area: f64 },
+    /// The waveform has power above the threshold at a sensitive frequency,
+    /// e.g. a neighbor qubit.
+    #[cfg(feature = "spectrum")]
+    Leakage {
+        channel: ChannelId,
+        name: String,
+        frequency: Frequency,
+        power_db: f64,
+    },
+}
+
+/// Idle time a barrier adds to one of its channels.
+///
+/// The channel is idle from its last use before the barrier until the
+/// barrier, because it waits for the other channels. Channels without wait
+/// are on the critical path of the barrier.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BarrierWait {
+    pub(crate) channel: ChannelId,
+    /// Arranged time of the barrier.
+    pub(crate) time: Time,
+    pub(crate) wait: Time,
+}
+
+/// Sink of warnings and barrier waits threaded through executing and
+/// sampling.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Diagnostics {
+    warnings: Vec<Warning>,
+    barrier_waits: Vec<BarrierWait>,
+}
+
+impl Diagnostics {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+
+    pub(crate) fn record_barrier_wait(&mut self, wait: BarrierWait) {
+        self.barrier_waits.push(wait);
+    }
+
+    pub(crate) fn extend(&mut self, other: Diagnostics) {
+        self.warnings.extend(other.warnings);
+        self.barrier_waits.extend(other.barrier_waits);
+    }
+
+    pub(crate) fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Total barrier wait of each channel with a nonzero wait, sorted by
+    /// channel. Barriers of repeated elements count once per iteration.
+    #[cfg(feature = "_frontend")]
+    pub(crate) fn barrier_wait_totals(&self) -> Vec<(ChannelId, Time)> {
+        let mut totals: Vec<(ChannelId, Time)> = Vec::new();
+        for w in &self.barrier_waits {
+            match totals.iter_mut().find(|(c, _)| *c == w.channel) {
+                Some((_, total)) => *total += w.wait,
+                None => totals.push((w.channel, w.wait)),
+            }
+        }
+        totals.sort_unstable_by_key(|(c, _)| *c);
+        totals
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::Oversize {
+                required,
+                available,
+            } => write!(
+                f,
+                "Oversize element allowed, required duration: {}, available duration: {}",
+                required, available
+            ),
+            Warning::ZeroAmplitude { channel, time } => write!(
+                f,
+                "Zero amplitude pulse dropped on channel '{}' at {}",
+                channel, time
+            ),
+            Warning::Coalesced { channel, time } => {
+                write!(f, "Pulses coalesced on channel '{}' at {}", channel, time)
+            }
+            Warning::Truncated { channel, time } => {
+                write!(f, "Pulse truncated on channel '{}' at {}", channel, time)
+            }
+            Warning::Snapped { time, adjustment } => write!(
+                f,
+                "Element at {} snapped to the time grid, adjusted by {}",
+                time, adjustment
+            ),
+            Warning::NetArea { channel, area } => write!(
+                f,
+                "Residual area {:e} on net-zero channel '{}'",
+                area, channel
+            ),
+            #[cfg(feature = "spectrum")]
+            Warning::Leakage {
+                channel,
+                name,
+                frequency,
+                power_db,
+            } => write!(
+                f,
+                "Power {:.1} dB at '{}' ({}) on channel '{}'",
+                power_db, name, frequency, channel
+            ),
+        }
+    }
+}
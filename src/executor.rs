@@ -1,14 +1,18 @@
-use std::iter;
+use std::hash::Hash;
+#[cfg(feature = "python")]
+use std::hash::{DefaultHasher, Hasher};
 
 use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
+    diagnostics::{Diagnostics, Warning},
     pulse::{Envelope, PulseList, PulseListBuilder, PushArgs},
-    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    quant::{self, Amplitude, ChannelId, Frequency, Phase, Rounding, ShapeId, Time},
     schedule::{
-        Arrange as _, Arranged, ElementRef, ElementVariant, Measure, Play, SetFreq, SetPhase,
-        ShiftFreq, ShiftPhase, SwapPhase, TimeRange,
+        arrange_tree_inherited, Arranged, ElementRef, ElementVariant, Measure, PhaseReference,
+        Play, PlaySamples, SetFreq, SetPhase, ShiftFreq, ShiftPhase, SwapPhase, TimeRange,
     },
     shape::Shape,
 };
@@ -20,6 +24,63 @@ pub(crate) struct Executor {
     amp_tolerance: Amplitude,
     time_tolerance: Time,
     allow_oversize: bool,
+    time_grid: Option<TimeGrid>,
+    /// Grids of the channels, overriding `time_grid`.
+    channel_grids: HashMap<ChannelId, TimeGrid>,
+    diagnostics: Diagnostics,
+    /// Original and snapped ranges of the instructions on each channel, used
+    /// to check that retiming keeps their order.
+    retimed: HashMap<ChannelId, Vec<(TimeRange, TimeRange)>>,
+    /// Start times of the barriers with their channels, empty for all
+    /// channels.
+    barriers: Vec<(Vec<ChannelId>, Time)>,
+    holds: Vec<Hold>,
+    /// Start times of the pulses and barriers of each channel, which end the
+    /// holds on the channel.
+    hold_stops: HashMap<ChannelId, Vec<Time>>,
+    /// Start times of the barriers without channels.
+    global_stops: Vec<Time>,
+}
+
+/// Grid that the start and span of instructions are snapped to, e.g. the
+/// waveform granularity of an AWG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "TimeGridData")]
+pub(crate) struct TimeGrid {
+    step: Time,
+    min_length: Time,
+    rounding: Rounding,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeGridData {
+    step: Time,
+    #[serde(default)]
+    min_length: Time,
+    #[serde(default)]
+    rounding: Rounding,
+}
+
+/// Single pole response of a line or cavity that rings down after each pulse.
+///
+/// A rectangular tail of `length` is appended to every pulse of the channel.
+/// Its amplitude brings the ring-down state, which decays with time constant
+/// `tau`, back to zero at the end of the tail. The tail is outside the
+/// arranged span of the pulse, and no later pulse of the channel may start
+/// before it ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "RingdownData")]
+pub(crate) struct Ringdown {
+    tau: Time,
+    length: Time,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RingdownData {
+    tau: Time,
+    length: Time,
 }
 
 #[derive(Error, Debug)]
@@ -32,6 +93,18 @@ pub(crate) enum Error {
     NegativePlateau(Time),
     #[error("Not enough duration: required {required:?}, available {available:?}")]
     NotEnoughDuration { required: Time, available: Time },
+    #[error("Invalid time grid: step {step:?}, min_length {min_length:?}")]
+    InvalidTimeGrid { step: Time, min_length: Time },
+    #[error("Invalid ring-down: tau {tau:?}, length {length:?}")]
+    InvalidRingdown { tau: Time, length: Time },
+    #[error("Snapping to the time grid breaks the order on channel {channel:?} at {time:?}")]
+    Misordered { channel: ChannelId, time: Time },
+    #[error("Ring-down tail on channel {channel:?} at {time:?} overlaps a later pulse")]
+    TailOverlap { channel: ChannelId, time: Time },
+    #[error("Invalid pulse timing: {0}")]
+    Arithmetic(#[from] quant::Error),
+    #[error("Held pulse on net-zero channel {channel:?} at {time:?}")]
+    NetZeroHold { channel: ChannelId, time: Time },
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -41,7 +114,153 @@ struct Channel {
     base_freq: Frequency,
     delta_freq: Frequency,
     phase: Phase,
+    ringdown: Option<Ringdown>,
+    net_zero: bool,
     pulses: PulseListBuilder,
+    /// Start times of the pulses, excluding holds and tails.
+    starts: Vec<Time>,
+    /// Ring-down tails, which assume silence after their pulses.
+    tails: Vec<TimeRange>,
+}
+
+/// Plateau level after a [`Play`] with hold, which replaces the falling edge
+/// and continues the carrier of the pulse.
+#[derive(Debug, Clone)]
+struct Hold {
+    channel: ChannelId,
+    /// Start of the pulse, stops at this time are from the pulse itself.
+    pulse_start: Time,
+    time: Time,
+    amplitude: Amplitude,
+    global_freq: Frequency,
+    local_freq: Frequency,
+    phase: Phase,
+}
+
+/// Retimed instructions of a channel indexed by their original times, so that
+/// each barrier is checked with binary searches.
+#[derive(Debug, Clone)]
+struct RetimedIndex {
+    /// Original starts in ascending order.
+    starts: Vec<Time>,
+    /// Minimum snapped start of the instructions from each index on.
+    min_starts: Vec<Time>,
+    /// Original ends in ascending order.
+    ends: Vec<Time>,
+    /// Maximum snapped end of the instructions up to each index.
+    max_ends: Vec<Time>,
+}
+
+impl RetimedIndex {
+    /// `items` are the original and snapped ranges sorted by original start.
+    fn new(items: &[(TimeRange, TimeRange)]) -> Self {
+        let starts = items.iter().map(|(o, _)| o.start).collect();
+        let mut min_starts: Vec<_> = items.iter().map(|(_, s)| s.start).collect();
+        for i in (1..min_starts.len()).rev() {
+            min_starts[i - 1] = min_starts[i - 1].min(min_starts[i]);
+        }
+        let mut ends: Vec<_> = items
+            .iter()
+            .map(|(o, s)| (o.start + o.span, s.start + s.span))
+            .collect();
+        ends.sort_unstable_by_key(|&(end, _)| end);
+        let mut max_ends: Vec<_> = ends.iter().map(|&(_, end)| end).collect();
+        for i in 1..max_ends.len() {
+            max_ends[i] = max_ends[i].max(max_ends[i - 1]);
+        }
+        Self {
+            starts,
+            min_starts,
+            ends: ends.into_iter().map(|(end, _)| end).collect(),
+            max_ends,
+        }
+    }
+
+    /// Latest snapped end of the instructions originally ending at or before
+    /// `time`.
+    fn end_before(&self, time: Time) -> Option<Time> {
+        let count = self.ends.partition_point(|&t| t <= time);
+        count.checked_sub(1).map(|i| self.max_ends[i])
+    }
+
+    /// Earliest snapped start of the instructions originally starting at or
+    /// after `time`.
+    fn start_after(&self, time: Time) -> Option<Time> {
+        let first = self.starts.partition_point(|&t| t < time);
+        self.min_starts.get(first).copied()
+    }
+}
+
+/// Inputs of a compilation, see [`Executor::compile_key`].
+///
+/// Trees and shapes are compared by content, so separately built but equal
+/// inputs give equal keys.
+#[cfg(feature = "python")]
+#[derive(Debug, Clone)]
+pub(crate) struct CompileKey {
+    root: ElementRef,
+    amp_tolerance: Amplitude,
+    time_tolerance: Time,
+    allow_oversize: bool,
+    time_grid: Option<TimeGrid>,
+    channels: Vec<ChannelKey>,
+    shapes: Vec<(ShapeId, Shape)>,
+}
+
+#[cfg(feature = "python")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ChannelKey {
+    name: ChannelId,
+    base_freq: Frequency,
+    ringdown: Option<Ringdown>,
+    net_zero: bool,
+    time_grid: Option<TimeGrid>,
+}
+
+#[cfg(feature = "python")]
+impl CompileKey {
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        self.hash(&mut state);
+        state.finish()
+    }
+}
+
+#[cfg(feature = "python")]
+impl PartialEq for CompileKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root
+            && self.amp_tolerance == other.amp_tolerance
+            && self.time_tolerance == other.time_tolerance
+            && self.allow_oversize == other.allow_oversize
+            && self.time_grid == other.time_grid
+            && self.channels == other.channels
+            && self.shapes.len() == other.shapes.len()
+            && self
+                .shapes
+                .iter()
+                .zip(&other.shapes)
+                .all(|((n1, s1), (n2, s2))| n1 == n2 && s1.order_key() == s2.order_key())
+    }
+}
+
+#[cfg(feature = "python")]
+impl Eq for CompileKey {}
+
+#[cfg(feature = "python")]
+impl Hash for CompileKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.root.hash(state);
+        self.amp_tolerance.hash(state);
+        self.time_tolerance.hash(state);
+        self.allow_oversize.hash(state);
+        self.time_grid.hash(state);
+        self.channels.hash(state);
+        for (n, s) in &self.shapes {
+            n.hash(state);
+            s.hash_content(state);
+        }
+    }
 }
 
 struct AddPulseArgs {
@@ -49,18 +268,21 @@ struct AddPulseArgs {
     time: Time,
     width: Time,
     plateau: Time,
+    /// Shape and width of the falling edge if it differs from the rising one.
+    fall: Option<(Option<Shape>, Time)>,
     amplitude: Amplitude,
     drag_coef: f64,
     freq: Frequency,
     phase: Phase,
 }
 
-#[derive(Debug)]
-enum IterVariant<S, A, G, R> {
-    Stack(S),
-    Absolute(A),
-    Grid(G),
-    Repeat(R),
+impl AddPulseArgs {
+    fn duration(&self) -> Time {
+        match &self.fall {
+            Some((_, fall_width)) => (self.width + *fall_width) / 2.0 + self.plateau,
+            None => self.width + self.plateau,
+        }
+    }
 }
 
 impl Executor {
@@ -75,13 +297,56 @@ impl Executor {
             amp_tolerance,
             time_tolerance,
             allow_oversize,
+            time_grid: None,
+            channel_grids: HashMap::new(),
+            diagnostics: Diagnostics::new(),
+            retimed: HashMap::new(),
+            barriers: Vec::new(),
+            holds: Vec::new(),
+            hold_stops: HashMap::new(),
+            global_stops: Vec::new(),
         }
     }
 
-    pub(crate) fn add_channel(&mut self, name: ChannelId, base_freq: Frequency) {
+    pub(crate) fn set_time_grid(&mut self, time_grid: Option<TimeGrid>) {
+        self.time_grid = time_grid;
+    }
+
+    /// Snap the instructions on `channel` to `time_grid` instead of the grid
+    /// of [`Executor::set_time_grid`].
+    pub(crate) fn set_channel_time_grid(
+        &mut self,
+        channel: ChannelId,
+        time_grid: Option<TimeGrid>,
+    ) {
+        match time_grid {
+            Some(time_grid) => self.channel_grids.insert(channel, time_grid),
+            None => self.channel_grids.remove(&channel),
+        };
+    }
+
+    /// Add a channel, `ringdown` appends a compensating tail to every pulse.
+    ///
+    /// If `net_zero` is set, every pulse is followed by an inverted copy so
+    /// that the time integral of the waveform stays at zero. The copy is in
+    /// the arranged span of the pulse, which has to hold both. With
+    /// `ringdown`, only the copy is followed by a tail.
+    pub(crate) fn add_channel(
+        &mut self,
+        name: ChannelId,
+        base_freq: Frequency,
+        ringdown: Option<Ringdown>,
+        net_zero: bool,
+    ) {
         self.channels.insert(
             name,
-            Channel::new(base_freq, self.amp_tolerance, self.time_tolerance),
+            Channel::new(
+                base_freq,
+                ringdown,
+                net_zero,
+                self.amp_tolerance,
+                self.time_tolerance,
+            ),
         );
     }
 
@@ -89,26 +354,155 @@ impl Executor {
         self.shapes.insert(name, shape);
     }
 
-    pub(crate) fn into_result(self) -> HashMap<ChannelId, PulseList> {
-        self.channels
+    /// Everything that determines the result of [`execute`] on `root`: the
+    /// tree, the channels and shapes, and the options.
+    ///
+    /// [`execute`]: Self::execute
+    #[cfg(feature = "python")]
+    pub(crate) fn compile_key(&self, root: &ElementRef) -> CompileKey {
+        let mut channels: Vec<_> = self
+            .channels
+            .iter()
+            .map(|(n, c)| ChannelKey {
+                name: *n,
+                base_freq: c.base_freq,
+                ringdown: c.ringdown,
+                net_zero: c.net_zero,
+                time_grid: self.channel_grids.get(n).copied(),
+            })
+            .collect();
+        channels.sort_unstable_by_key(|c| c.name);
+        let mut shapes: Vec<_> = self
+            .shapes
+            .iter()
+            .map(|(n, s)| (n.clone(), s.clone()))
+            .collect();
+        shapes.sort_unstable_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+        CompileKey {
+            root: root.clone(),
+            amp_tolerance: self.amp_tolerance,
+            time_tolerance: self.time_tolerance,
+            allow_oversize: self.allow_oversize,
+            time_grid: self.time_grid,
+            channels,
+            shapes,
+        }
+    }
+
+    /// Hash of [`compile_key`](Self::compile_key).
+    ///
+    /// Equal inputs give equal fingerprints within a process, so the result can
+    /// be reused for a repeated schedule without measuring and arranging it
+    /// again.
+    #[cfg(feature = "python")]
+    pub(crate) fn fingerprint(&self, root: &ElementRef) -> u64 {
+        self.compile_key(root).fingerprint()
+    }
+
+    /// Build the pulse lists and return them with the collected warnings.
+    pub(crate) fn into_result(self) -> (HashMap<ChannelId, PulseList>, Diagnostics) {
+        let mut diagnostics = self.diagnostics;
+        let lists = self
+            .channels
             .into_iter()
-            .map(|(n, b)| (n, b.pulses.build()))
-            .collect()
+            .map(|(n, b)| {
+                let list = b
+                    .pulses
+                    .build_with(|time| diagnostics.warn(Warning::Coalesced { channel: n, time }));
+                (n, list)
+            })
+            .collect();
+        (lists, diagnostics)
     }
 
     pub(crate) fn execute(&mut self, root: &ElementRef) -> Result<()> {
+        self.execute_observed(root, |_, _| {})
+    }
+
+    /// Same as [`execute`](Self::execute), but `observer` is called with each
+    /// non-phantom element and its resolved absolute time range, in tree
+    /// traversal order.
+    ///
+    /// The time range excludes the margins of the element. Instructions are
+    /// reported after being snapped to the time grid.
+    pub(crate) fn execute_observed(
+        &mut self,
+        root: &ElementRef,
+        mut observer: impl FnMut(&ElementRef, TimeRange),
+    ) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("execute").entered();
         let time_range = TimeRange {
             start: Time::ZERO,
             span: root.measure(),
         };
-        for Arranged { item, time_range } in arrange_tree(root, time_range) {
+        for (Arranged { item, time_range }, inherited) in arrange_tree_inherited(root, time_range) {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("element", kind = item.variant.name()).entered();
             let time_range = item.inner_time_range(time_range);
-            if !self.allow_oversize {
-                let required = item.variant.measure();
-                check_duration(required, time_range.span, self.time_tolerance)?;
+            let required = item.variant.measure();
+            if let Err(e) = check_duration(required, time_range.span, self.time_tolerance) {
+                if !item.common.allow_oversize().unwrap_or(self.allow_oversize) {
+                    return Err(e);
+                }
+                self.diagnostics.warn(Warning::Oversize {
+                    required,
+                    available: time_range.span,
+                });
+            }
+            let time_range = match &item.variant {
+                ElementVariant::Play(_)
+                | ElementVariant::PlaySamples(_)
+                | ElementVariant::ShiftPhase(_)
+                | ElementVariant::SetPhase(_)
+                | ElementVariant::ShiftFreq(_)
+                | ElementVariant::SetFreq(_)
+                | ElementVariant::SwapPhase(_) => self.snap(time_range, item.variant.channels()),
+                _ => time_range,
+            };
+            observer(item, time_range);
+            match &item.variant {
+                // Children of phantom elements are not executed.
+                ElementVariant::Stack(v) if !item.common.phantom() => {
+                    for wait in v.barrier_waits(time_range) {
+                        self.diagnostics.record_barrier_wait(wait);
+                    }
+                }
+                _ => {}
+            }
+            match &item.variant {
+                ElementVariant::Barrier(_) if self.has_time_grid() => {
+                    self.barriers
+                        .push((item.variant.channels().to_vec(), time_range.start));
+                }
+                _ => {}
+            }
+            match &item.variant {
+                ElementVariant::Barrier(_) if item.variant.channels().is_empty() => {
+                    self.global_stops.push(time_range.start);
+                }
+                ElementVariant::Play(_)
+                | ElementVariant::PlaySamples(_)
+                | ElementVariant::Barrier(_) => {
+                    for &channel in item.variant.channels() {
+                        self.hold_stops
+                            .entry(channel)
+                            .or_default()
+                            .push(time_range.start);
+                    }
+                }
+                _ => {}
             }
             match &item.variant {
-                ElementVariant::Play(variant) => self.execute_play(variant, time_range),
+                ElementVariant::Play(variant) => self.execute_play(
+                    variant,
+                    time_range,
+                    inherited.scale,
+                    inherited.iteration_start,
+                ),
+                ElementVariant::PlaySamples(variant) => {
+                    self.execute_play_samples(variant, time_range, inherited.scale)
+                }
                 ElementVariant::ShiftPhase(variant) => self.execute_shift_phase(variant),
                 ElementVariant::SetPhase(variant) => {
                     self.execute_set_phase(variant, time_range.start)
@@ -125,43 +519,319 @@ impl Executor {
                 _ => Ok(()),
             }?;
         }
+        self.end_holds(root.measure());
+        self.check_tails()?;
+        self.check_retiming()
+    }
+
+    /// Check that no pulse starts during a ring-down tail of its channel.
+    fn check_tails(&mut self) -> Result<()> {
+        let tolerance = self.time_tolerance;
+        for (&channel, c) in &mut self.channels {
+            c.starts.sort_unstable();
+            for tail in &c.tails {
+                let end = tail.start + tail.span;
+                // The first start in or after the tail.
+                let first = c.starts.partition_point(|&t| t < tail.start - tolerance);
+                if c.starts.get(first).is_some_and(|&t| t < end - tolerance) {
+                    return Err(Error::TailOverlap {
+                        channel,
+                        time: tail.start,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Push the holds, each ends at the first pulse or barrier on its channel
+    /// at or after its start, or at `end`. Events starting together with the
+    /// held pulse do not end the hold.
+    ///
+    /// The stops are collected over the whole schedule because the traversal
+    /// is not in time order.
+    fn end_holds(&mut self, end: Time) {
+        for stops in self.hold_stops.values_mut() {
+            stops.sort_unstable();
+        }
+        self.global_stops.sort_unstable();
+        // The first stop after the pulse start and not before the hold.
+        let first_stop = |stops: &[Time], hold: &Hold| {
+            let i = if hold.time > hold.pulse_start {
+                stops.partition_point(|&t| t < hold.time)
+            } else {
+                stops.partition_point(|&t| t <= hold.pulse_start)
+            };
+            stops.get(i).copied()
+        };
+        for hold in self.holds.drain(..) {
+            let stops = self
+                .hold_stops
+                .get(&hold.channel)
+                .map_or(&[][..], Vec::as_slice);
+            let stop = [
+                first_stop(stops, &hold),
+                first_stop(&self.global_stops, &hold),
+            ]
+            .into_iter()
+            .flatten()
+            .fold(end, Time::min);
+            if stop <= hold.time {
+                continue;
+            }
+            let channel = self
+                .channels
+                .get_mut(&hold.channel)
+                .expect("Hold should be on an existing channel");
+            channel.pulses.push(PushArgs {
+                envelope: Envelope::new(None, Time::ZERO, stop - hold.time),
+                global_freq: hold.global_freq,
+                local_freq: hold.local_freq,
+                time: hold.time,
+                amplitude: hold.amplitude,
+                drag_coef: 0.0,
+                phase: hold.phase,
+                id: None,
+            });
+        }
+    }
+
+    fn has_time_grid(&self) -> bool {
+        self.time_grid.is_some() || !self.channel_grids.is_empty()
+    }
+
+    /// Snap to the grid of the first channel with its own grid, or to the
+    /// global grid. Instructions without a grid are recorded unchanged when
+    /// other channels have one.
+    fn snap(&mut self, time_range: TimeRange, channels: &[ChannelId]) -> TimeRange {
+        if !self.has_time_grid() {
+            return time_range;
+        }
+        let snapped = match channels
+            .iter()
+            .find_map(|c| self.channel_grids.get(c))
+            .or(self.time_grid.as_ref())
+        {
+            Some(time_grid) => time_grid.snap(time_range),
+            None => time_range,
+        };
+        for &channel in channels {
+            self.retimed
+                .entry(channel)
+                .or_default()
+                .push((time_range, snapped));
+        }
+        let abs = |t: Time| if t < Time::ZERO { -t } else { t };
+        let adjustment =
+            abs(snapped.start - time_range.start).max(abs(snapped.span - time_range.span));
+        if adjustment > self.time_tolerance {
+            self.diagnostics.warn(Warning::Snapped {
+                time: time_range.start,
+                adjustment,
+            });
+        }
+        snapped
+    }
+
+    /// Check that snapping kept the start order of the instructions on each
+    /// channel, and that the instructions before each barrier still end
+    /// before the instructions after it start, within the time tolerance.
+    fn check_retiming(&mut self) -> Result<()> {
+        let tolerance = self.time_tolerance;
+        let mut indices = HashMap::with_capacity(self.retimed.len());
+        for (&channel, items) in &mut self.retimed {
+            items.sort_by_key(|(original, _)| original.start);
+            let mut latest = Vec::with_capacity(items.len());
+            for (original, snapped) in items.iter() {
+                let earlier = items.partition_point(|(o, _)| o.start < original.start - tolerance);
+                if earlier > 0 && latest[earlier - 1] > snapped.start + tolerance {
+                    return Err(Error::Misordered {
+                        channel,
+                        time: original.start,
+                    });
+                }
+                let last = latest.last().copied().unwrap_or(snapped.start);
+                latest.push(last.max(snapped.start));
+            }
+            indices.insert(channel, RetimedIndex::new(items));
+        }
+        for (channels, time) in &self.barriers {
+            let indices: Vec<_> = if channels.is_empty() {
+                indices.iter().collect()
+            } else {
+                channels
+                    .iter()
+                    .filter_map(|c| indices.get_key_value(c))
+                    .collect()
+            };
+            let end_before = indices
+                .iter()
+                .filter_map(|(_, i)| i.end_before(*time + tolerance))
+                .max();
+            let start_after = indices
+                .iter()
+                .filter_map(|(&c, i)| Some((i.start_after(*time - tolerance)?, c)))
+                .min();
+            if let (Some(end), Some((start, channel))) = (end_before, start_after) {
+                if end > start + tolerance {
+                    return Err(Error::Misordered {
+                        channel,
+                        time: *time,
+                    });
+                }
+            }
+        }
         Ok(())
     }
 
-    fn execute_play(&mut self, variant: &Play, time_range: TimeRange) -> Result<()> {
+    /// `scale` is the product of the factors of the `ScaleAmplitude` ancestors
+    /// and `iteration_start` the start of the innermost `Repeat` iteration.
+    fn execute_play(
+        &mut self,
+        variant: &Play,
+        time_range: TimeRange,
+        scale: f64,
+        iteration_start: Time,
+    ) -> Result<()> {
         let shape = match variant.shape_id() {
-            Some(id) => Some(
-                self.shapes
-                    .get(id)
-                    .ok_or(Error::ShapeNotFound(id.clone()))?
-                    .clone(),
-            ),
+            Some(id) => Some(self.get_shape(id)?),
+            None => None,
+        };
+        let fall = match variant.fall() {
+            Some(fall) => {
+                let fall_shape = match &fall.shape_id {
+                    Some(id) => Some(self.get_shape(id)?),
+                    None => shape.clone(),
+                };
+                Some((fall_shape, fall.width))
+            }
             None => None,
         };
+        // A held pulse stays at the plateau level instead of falling,
+        // rectangular edges are part of the plateau.
+        let fall_shape = fall.as_ref().map_or(&shape, |(fall_shape, _)| fall_shape);
+        let fall = if variant.hold() && fall_shape.is_some() {
+            Some((None, Time::ZERO))
+        } else {
+            fall
+        };
         let width = variant.width();
+        let time_tolerance = self.time_tolerance;
+        let channel = self.get_mut_channel(variant.channel_id())?;
+        // The inverted copy ends at the negated level, so holding would break
+        // the zero area of the channel.
+        if channel.net_zero && variant.hold() {
+            return Err(Error::NetZeroHold {
+                channel: *variant.channel_id(),
+                time: time_range.start,
+            });
+        }
+        // The pulse and its inverted copy share the span on net-zero channels.
+        let copies = if channel.net_zero { 2.0 } else { 1.0 };
         let plateau = if variant.flexible() {
-            time_range.span - width
+            (time_range.span / copies).try_sub(variant.edge_width())?
         } else {
             variant.plateau()
         };
         if plateau < Time::ZERO {
             return Err(Error::NegativePlateau(plateau));
         }
-        let amplitude = variant.amplitude();
+        let duration = variant.edge_width().try_add(plateau)?;
+        if channel.net_zero {
+            check_duration(duration.try_mul(copies)?, time_range.span, time_tolerance)?;
+        }
+        let amplitude = variant.amplitude() * scale;
         let drag_coef = variant.drag_coef();
         let freq = variant.frequency();
-        let phase = variant.phase();
-        let channel = self.get_mut_channel(variant.channel_id())?;
-        channel.add_pulse(AddPulseArgs {
+        // The sampler references the channel carrier to time zero, so a later
+        // reference is a phase offset of the pulse.
+        let reference_time = match variant.phase_reference() {
+            PhaseReference::Absolute => Time::ZERO,
+            PhaseReference::Pulse => time_range.start,
+            PhaseReference::Iteration => iteration_start,
+        };
+        let phase = variant
+            .phase()
+            .wrapping_sub(accumulated_phase(channel.total_freq(), reference_time)?);
+        let args = AddPulseArgs {
             shape,
             time: time_range.start,
             width,
             plateau,
+            fall,
             amplitude,
             drag_coef,
             freq,
             phase,
+        };
+        let hold = variant.hold().then(|| {
+            let duration = args.duration();
+            Hold {
+                channel: *variant.channel_id(),
+                pulse_start: time_range.start,
+                time: time_range.start + duration,
+                amplitude,
+                global_freq: channel.total_freq(),
+                local_freq: freq,
+                phase: phase + freq * duration,
+            }
         });
+        let added = channel.add_pulse(args);
+        if !added {
+            self.diagnostics.warn(Warning::ZeroAmplitude {
+                channel: *variant.channel_id(),
+                time: time_range.start,
+            });
+        }
+        self.holds.extend(hold);
+        Ok(())
+    }
+
+    /// The real and imaginary parts are played as separate pulses with sampled
+    /// shapes, the imaginary part with an additional quarter cycle phase.
+    fn execute_play_samples(
+        &mut self,
+        variant: &PlaySamples,
+        time_range: TimeRange,
+        scale: f64,
+    ) -> Result<()> {
+        let samples = variant.samples();
+        let parts = [
+            (samples.iter().map(|s| s.re).collect::<Vec<_>>(), 0.0),
+            (samples.iter().map(|s| s.im).collect(), 0.25),
+        ];
+        let width = variant.width();
+        let amplitude = variant.amplitude() * scale;
+        let time_tolerance = self.time_tolerance;
+        let channel = self.get_mut_channel(variant.channel_id())?;
+        if channel.net_zero {
+            check_duration(width.try_mul(2.0)?, time_range.span, time_tolerance)?;
+        }
+        let mut added = false;
+        for (values, quarter) in parts {
+            if values.iter().all(|&v| v == 0.0) {
+                continue;
+            }
+            let shape = Shape::new_samples(&values, variant.interpolate())
+                .expect("Samples should be finite and non-empty");
+            added |= channel.add_pulse(AddPulseArgs {
+                shape: Some(shape),
+                time: time_range.start,
+                width,
+                plateau: Time::ZERO,
+                fall: None,
+                amplitude,
+                drag_coef: 0.0,
+                freq: variant.frequency(),
+                phase: variant.phase() + Phase::new(quarter).expect("Should be a valid phase"),
+            });
+        }
+        if !added {
+            self.diagnostics.warn(Warning::ZeroAmplitude {
+                channel: *variant.channel_id(),
+                time: time_range.start,
+            });
+        }
         Ok(())
     }
 
@@ -175,22 +845,19 @@ impl Executor {
     fn execute_set_phase(&mut self, variant: &SetPhase, time: Time) -> Result<()> {
         let phase = variant.phase();
         let channel = self.get_mut_channel(variant.channel_id())?;
-        channel.set_phase(phase, time);
-        Ok(())
+        channel.set_phase(phase, time)
     }
 
     fn execute_shift_freq(&mut self, variant: &ShiftFreq, time: Time) -> Result<()> {
         let delta_freq = variant.frequency();
         let channel = self.get_mut_channel(variant.channel_id())?;
-        channel.shift_freq(delta_freq, time);
-        Ok(())
+        channel.shift_freq(delta_freq, time)
     }
 
     fn execute_set_freq(&mut self, variant: &SetFreq, time: Time) -> Result<()> {
         let freq = variant.frequency();
         let channel = self.get_mut_channel(variant.channel_id())?;
-        channel.set_freq(freq, time);
-        Ok(())
+        channel.set_freq(freq, time)
     }
 
     fn execute_swap_phase(&mut self, variant: &SwapPhase, time: Time) -> Result<()> {
@@ -202,78 +869,144 @@ impl Executor {
         let [channel, other] = self
             .channels
             .get_many_mut([ch1, ch2])
-            .ok_or(Error::ChannelNotFound(vec![ch1.clone(), ch2.clone()]))?;
-        channel.swap_phase(other, time);
-        Ok(())
+            .ok_or(Error::ChannelNotFound(vec![*ch1, *ch2]))?;
+        channel.swap_phase(other, time)
     }
 
     fn get_mut_channel(&mut self, id: &ChannelId) -> Result<&mut Channel> {
         self.channels
             .get_mut(id)
-            .ok_or(Error::ChannelNotFound(vec![id.clone()]))
+            .ok_or(Error::ChannelNotFound(vec![*id]))
+    }
+
+    fn get_shape(&self, id: &ShapeId) -> Result<Shape> {
+        self.shapes
+            .get(id)
+            .cloned()
+            .ok_or(Error::ShapeNotFound(id.clone()))
     }
 }
 
 impl Channel {
-    fn new(base_freq: Frequency, amp_tolerance: Amplitude, time_tolerance: Time) -> Self {
+    fn new(
+        base_freq: Frequency,
+        ringdown: Option<Ringdown>,
+        net_zero: bool,
+        amp_tolerance: Amplitude,
+        time_tolerance: Time,
+    ) -> Self {
         Self {
             base_freq,
             delta_freq: Frequency::ZERO,
             phase: Phase::ZERO,
+            ringdown,
+            net_zero,
             pulses: PulseListBuilder::new(amp_tolerance, time_tolerance),
+            starts: Vec::new(),
+            tails: Vec::new(),
         }
     }
 
-    fn shift_freq(&mut self, delta_freq: Frequency, time: Time) {
-        let delta_phase = -delta_freq * time;
-        self.delta_freq += delta_freq;
-        self.phase += delta_phase;
+    fn shift_freq(&mut self, delta_freq: Frequency, time: Time) -> Result<()> {
+        let freq = self.delta_freq.try_add(delta_freq)?;
+        self.update_freq(freq, delta_freq, time)
     }
 
-    fn set_freq(&mut self, freq: Frequency, time: Time) {
-        let delta_freq = freq - self.delta_freq;
-        let delta_phase = -delta_freq * time;
+    fn set_freq(&mut self, freq: Frequency, time: Time) -> Result<()> {
+        let delta_freq = freq.try_sub(self.delta_freq)?;
+        self.update_freq(freq, delta_freq, time)
+    }
+
+    /// Change the frequency shift to `freq` at `time`. The total frequency
+    /// has to stay finite, so that the phases accumulated from it are valid.
+    fn update_freq(&mut self, freq: Frequency, delta_freq: Frequency, time: Time) -> Result<()> {
+        self.base_freq.try_add(freq)?.try_finite()?;
+        let delta_phase = accumulated_phase(delta_freq, time)?;
         self.delta_freq = freq;
-        self.phase += delta_phase;
+        self.phase = self.phase.wrapping_sub(delta_phase);
+        Ok(())
     }
 
     fn shift_phase(&mut self, delta_phase: Phase) {
-        self.phase += delta_phase;
+        self.phase = self.phase.wrapping_add(delta_phase);
     }
 
-    fn set_phase(&mut self, phase: Phase, time: Time) {
-        self.phase = phase - self.delta_freq * time;
+    fn set_phase(&mut self, phase: Phase, time: Time) -> Result<()> {
+        self.phase = phase.wrapping_sub(accumulated_phase(self.delta_freq, time)?);
+        Ok(())
     }
 
     fn total_freq(&self) -> Frequency {
         self.base_freq + self.delta_freq
     }
 
-    fn swap_phase(&mut self, other: &mut Self, time: Time) {
-        let delta_freq = self.total_freq() - other.total_freq();
+    fn swap_phase(&mut self, other: &mut Self, time: Time) -> Result<()> {
+        let delta_freq = self.total_freq().try_sub(other.total_freq())?;
+        let delta_phase = accumulated_phase(delta_freq, time)?;
         let phase1 = self.phase;
         let phase2 = other.phase;
-        self.phase = phase2 - delta_freq * time;
-        other.phase = phase1 + delta_freq * time;
+        self.phase = phase2.wrapping_sub(delta_phase);
+        other.phase = phase1.wrapping_add(delta_phase);
+        Ok(())
     }
 
-    fn add_pulse(
-        &mut self,
-        AddPulseArgs {
+    fn add_pulse(&mut self, args: AddPulseArgs) -> bool {
+        self.starts.push(args.time);
+        if !self.net_zero {
+            return self.push_pulse(args, Some(1.0));
+        }
+        // The inverted copy starts at the end of the pulse and continues its
+        // carrier. At the end of the copy, the ring-down state of the copy is
+        // reduced by the decayed state of the pulse, so a single tail after
+        // the copy, scaled by `1 - exp(-duration / tau)`, cancels both.
+        let duration = args.duration();
+        let inverted = AddPulseArgs {
+            shape: args.shape.clone(),
+            fall: args.fall.clone(),
+            time: args.time + duration,
+            amplitude: args.amplitude * -1.0,
+            phase: args.phase + args.freq * duration,
+            ..args
+        };
+        let tail_scale = self
+            .ringdown
+            .map_or(1.0, |r| 1.0 - (-duration.value() / r.tau.value()).exp());
+        let added = self.push_pulse(args, None);
+        self.push_pulse(inverted, Some(tail_scale));
+        added
+    }
+
+    /// Push the pulse and, with `tail_scale`, its ring-down tail with the
+    /// amplitude scaled by `tail_scale`.
+    fn push_pulse(&mut self, args: AddPulseArgs, tail_scale: Option<f64>) -> bool {
+        let duration = args.duration();
+        let AddPulseArgs {
             shape,
             time,
             width,
             plateau,
+            fall,
             amplitude,
             drag_coef,
             freq,
             phase,
-        }: AddPulseArgs,
-    ) {
-        let envelope = Envelope::new(shape, width, plateau);
+        } = args;
+        let fall_edge = fall.as_ref().map(|(s, w)| (s.as_ref(), *w));
+        let tail = self.ringdown.zip(tail_scale).map(|(r, scale)| {
+            (
+                r.length,
+                r.tail_amplitude(shape.as_ref(), width, plateau, fall_edge) * scale,
+            )
+        });
+        let envelope = match fall {
+            Some((fall_shape, fall_width)) => {
+                Envelope::asymmetric(shape, width, plateau, fall_shape, fall_width)
+            }
+            None => Envelope::new(shape, width, plateau),
+        };
         let global_freq = self.total_freq();
         let local_freq = freq;
-        self.pulses.push(PushArgs {
+        let added = self.pulses.push(PushArgs {
             envelope,
             global_freq,
             local_freq,
@@ -281,29 +1014,164 @@ impl Channel {
             amplitude,
             drag_coef,
             phase,
+            id: None,
+        });
+        if let Some((length, factor)) = tail.filter(|_| added) {
+            self.tails.push(TimeRange {
+                start: time + duration,
+                span: length,
+            });
+            // The tail continues the carrier of the pulse.
+            self.pulses.push(PushArgs {
+                envelope: Envelope::new(None, Time::ZERO, length),
+                global_freq,
+                local_freq,
+                time: time + duration,
+                amplitude: amplitude * factor,
+                drag_coef: 0.0,
+                phase: phase + local_freq * duration,
+                id: None,
+            });
+        }
+        added
+    }
+}
+
+impl TryFrom<TimeGridData> for TimeGrid {
+    type Error = Error;
+
+    fn try_from(data: TimeGridData) -> Result<Self> {
+        Self::new(data.step, data.min_length, data.rounding)
+    }
+}
+
+impl TimeGrid {
+    pub(crate) fn new(step: Time, min_length: Time, rounding: Rounding) -> Result<Self> {
+        if !(step.is_finite() && step > Time::ZERO && min_length >= Time::ZERO) {
+            return Err(Error::InvalidTimeGrid { step, min_length });
+        }
+        Ok(Self {
+            step,
+            min_length,
+            rounding,
         })
     }
+
+    #[cfg(feature = "python")]
+    pub(crate) fn step(&self) -> Time {
+        self.step
+    }
+
+    #[cfg(feature = "python")]
+    pub(crate) fn min_length(&self) -> Time {
+        self.min_length
+    }
+
+    #[cfg(feature = "python")]
+    pub(crate) fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+
+    fn round(&self, time: Time) -> Time {
+        let n = self.rounding.apply(time.value() / self.step.value());
+        self.step * n
+    }
+
+    /// Snap the start and end to the grid. Non-empty spans are extended to
+    /// `min_length`.
+    fn snap(&self, time_range: TimeRange) -> TimeRange {
+        let start = self.round(time_range.start);
+        let end = self.round(time_range.start + time_range.span);
+        let mut span = (end - start).max(Time::ZERO);
+        if span > Time::ZERO && span < self.min_length {
+            span = self.min_length;
+        }
+        TimeRange { start, span }
+    }
+}
+
+impl TryFrom<RingdownData> for Ringdown {
+    type Error = Error;
+
+    fn try_from(data: RingdownData) -> Result<Self> {
+        Self::new(data.tau, data.length)
+    }
 }
 
-impl<S, A, G, R, T> Iterator for IterVariant<S, A, G, R>
-where
-    S: Iterator<Item = T>,
-    A: Iterator<Item = T>,
-    G: Iterator<Item = T>,
-    R: Iterator<Item = T>,
-{
-    type Item = T;
+impl Ringdown {
+    /// Number of points to integrate the rising and falling edges of shaped
+    /// pulses.
+    const EDGE_POINTS: usize = 128;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self {
-            IterVariant::Stack(s) => s.next(),
-            IterVariant::Absolute(a) => a.next(),
-            IterVariant::Grid(g) => g.next(),
-            IterVariant::Repeat(r) => r.next(),
+    pub(crate) fn new(tau: Time, length: Time) -> Result<Self> {
+        if !(tau.is_finite() && tau > Time::ZERO && length.is_finite() && length > Time::ZERO) {
+            return Err(Error::InvalidRingdown { tau, length });
         }
+        Ok(Self { tau, length })
+    }
+
+    #[cfg(feature = "python")]
+    pub(crate) fn tau(&self) -> Time {
+        self.tau
+    }
+
+    #[cfg(feature = "python")]
+    pub(crate) fn length(&self) -> Time {
+        self.length
+    }
+
+    /// Amplitude of the tail relative to the amplitude of the pulse.
+    ///
+    /// The ring-down state at the end of the pulse is the envelope integrated
+    /// with the weight `exp((t - duration) / tau) / tau`. A constant tail of
+    /// amplitude `c` and length `l` adds `c * (1 - q)` to the state decayed by
+    /// `q = exp(-l / tau)`, so the state vanishes for `c = -s * q / (1 - q)`.
+    fn tail_amplitude(
+        &self,
+        shape: Option<&Shape>,
+        width: Time,
+        plateau: Time,
+        fall: Option<(Option<&Shape>, Time)>,
+    ) -> f64 {
+        let tau = self.tau.value();
+        let (fall_shape, fall_width) = fall.unwrap_or((shape, width));
+        let (rise_width, fall_width) = (width.value(), fall_width.value());
+        let plateau = plateau.value();
+        let duration = (rise_width + fall_width) / 2.0 + plateau;
+        let weight = |t: f64| ((t - duration) / tau).exp();
+        // Half of an edge starting at `x0` in the shape coordinates and at
+        // `start` in time.
+        let half_edge = |shape: Option<&Shape>, x0: f64, width: f64, start: f64| match shape {
+            None => weight(start + width / 2.0) - weight(start),
+            Some(shape) => {
+                let n = Self::EDGE_POINTS / 2;
+                let dx = 0.5 / n as f64;
+                let mut values = vec![0.0; n];
+                shape.sample_array(x0 - 0.5 + dx / 2.0, dx, &mut values);
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| v * weight(start + (i as f64 + 0.5) * dx * width))
+                    .sum::<f64>()
+                    * width
+                    * dx
+                    / tau
+            }
+        };
+        let state = half_edge(shape, 0.0, rise_width, 0.0) + weight(rise_width / 2.0 + plateau)
+            - weight(rise_width / 2.0)
+            + half_edge(fall_shape, 0.5, fall_width, rise_width / 2.0 + plateau);
+        let q = (-self.length.value() / tau).exp();
+        -state * q / (1.0 - q)
     }
 }
 
+/// Phase accumulated at `freq` from time zero to `time`.
+fn accumulated_phase(freq: Frequency, time: Time) -> Result<Phase> {
+    let cycles = freq.try_mul(time.value())?.try_finite()?;
+    Ok(Phase::new(cycles.value())?)
+}
+
 fn check_duration(required: Time, available: Time, time_tolerance: Time) -> Result<()> {
     if required > available + time_tolerance {
         return Err(Error::NotEnoughDuration {
@@ -314,80 +1182,617 @@ fn check_duration(required: Time, available: Time, time_tolerance: Time) -> Resu
     Ok(())
 }
 
-fn arrange_tree(
-    root: &ElementRef,
-    time_range: TimeRange,
-) -> impl Iterator<Item = Arranged<&ElementRef>> {
-    pre_order_iter(
-        Arranged {
-            item: root,
-            time_range,
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ndarray::Array2;
+    use num::complex::Complex64;
+
+    use super::*;
+    use crate::{
+        pulse::Sampler,
+        schedule::{
+            Absolute, AbsoluteEntry, Barrier, Element, ElementCommonBuilder, Repeat, ShiftFreq,
         },
-        arrange_children,
-    )
-    .filter(|Arranged { item, .. }| !item.common.phantom())
-}
+    };
 
-fn arrange_children(
-    Arranged { item, time_range }: Arranged<&ElementRef>,
-) -> Option<impl Iterator<Item = Arranged<&ElementRef>>> {
-    if item.common.phantom() {
-        return None;
+    fn executor() -> Executor {
+        Executor::new(
+            Amplitude::new(1e-5).unwrap(),
+            Time::new(1e-12).unwrap(),
+            false,
+        )
     }
-    let time_range = item.inner_time_range(time_range);
-    match &item.variant {
-        ElementVariant::Repeat(r) => Some(IterVariant::Repeat(r.arrange(time_range))),
-        ElementVariant::Stack(s) => Some(IterVariant::Stack(s.arrange(time_range))),
-        ElementVariant::Absolute(a) => Some(IterVariant::Absolute(a.arrange(time_range))),
-        ElementVariant::Grid(g) => Some(IterVariant::Grid(g.arrange(time_range))),
-        _ => None,
+
+    /// Execute `root` and sample `len` points of `channel` at `rate` Hz with
+    /// rows `[I, Q]`, returning the warnings of both steps.
+    fn sample_schedule(
+        mut executor: Executor,
+        root: &ElementRef,
+        channel: ChannelId,
+        len: usize,
+        rate: f64,
+    ) -> (Array2<f64>, Diagnostics) {
+        let net_zero = executor.channels[&channel].net_zero;
+        executor.execute(root).unwrap();
+        let (lists, mut diagnostics) = executor.into_result();
+        let mut waveform = Array2::zeros((2, len));
+        let mut sampler = Sampler::new(lists);
+        sampler.add_channel(
+            channel,
+            waveform.view_mut(),
+            Frequency::new(rate).unwrap(),
+            Time::ZERO,
+            0,
+            Complex64::ZERO,
+        );
+        if net_zero {
+            sampler.set_net_zero([channel], Amplitude::new(1e-6).unwrap());
+        }
+        diagnostics.extend(sampler.sample(Time::new(1e-12).unwrap()).unwrap());
+        (waveform, diagnostics)
+    }
+
+    fn play(amplitude: f64) -> ElementRef {
+        let play = Play::new(
+            ChannelId::new("xy"),
+            None,
+            Amplitude::new(amplitude).unwrap(),
+            Time::new(10e-9).unwrap(),
+        )
+        .unwrap();
+        Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            play,
+        ))
+    }
+
+    #[test]
+    fn warnings() {
+        let common = ElementCommonBuilder::new()
+            .duration(Some(Time::new(5e-9).unwrap()))
+            .build()
+            .unwrap();
+        // All plays start at 0 in a span shorter than their width.
+        let absolute = Absolute::new().with_children(
+            [play(0.0), play(0.5), play(0.5)]
+                .into_iter()
+                .map(AbsoluteEntry::new)
+                .collect(),
+        );
+        let root = Arc::new(Element::new(common, absolute));
+        let amp_tolerance = Amplitude::new(1e-5).unwrap();
+        let time_tolerance = Time::new(1e-12).unwrap();
+
+        let mut executor = Executor::new(amp_tolerance, time_tolerance, false);
+        executor.add_channel(ChannelId::new("xy"), Frequency::ZERO, None, false);
+        assert!(executor.execute(&root).is_err());
+
+        let mut executor = Executor::new(amp_tolerance, time_tolerance, true);
+        executor.add_channel(ChannelId::new("xy"), Frequency::ZERO, None, false);
+        executor.execute(&root).unwrap();
+        let (_, diagnostics) = executor.into_result();
+        let warnings = diagnostics.warnings();
+        assert!(matches!(warnings[0], Warning::Oversize { .. }));
+        assert!(matches!(warnings[1], Warning::ZeroAmplitude { .. }));
+        assert!(matches!(warnings.last(), Some(Warning::Coalesced { .. })));
     }
-}
 
-fn pre_order_iter<T, F, I>(root: T, mut children: F) -> impl Iterator<Item = T>
-where
-    F: FnMut(T) -> Option<I>,
-    I: Iterator<Item = T>,
-    T: Clone + Copy,
-{
-    let mut stack = Vec::with_capacity(16);
-    stack.extend(children(root));
-    iter::once(root).chain(iter::from_fn(move || loop {
-        let current_iter = stack.last_mut()?;
-        match current_iter.next() {
-            Some(i) => {
-                stack.extend(children(i));
-                return Some(i);
+    #[test]
+    fn allow_oversize_override() {
+        let element = |allow_oversize| {
+            let common = ElementCommonBuilder::new()
+                .duration(Some(Time::new(5e-9).unwrap()))
+                .allow_oversize(allow_oversize)
+                .build()
+                .unwrap();
+            let absolute = Absolute::new().with_children(vec![AbsoluteEntry::new(play(0.5))]);
+            Arc::new(Element::new(common, absolute))
+        };
+        let amp_tolerance = Amplitude::new(1e-5).unwrap();
+        let time_tolerance = Time::new(1e-12).unwrap();
+
+        for (allow_oversize, element_override, ok) in [
+            (false, None, false),
+            (false, Some(true), true),
+            (true, Some(false), false),
+        ] {
+            let mut executor = Executor::new(amp_tolerance, time_tolerance, allow_oversize);
+            executor.add_channel(ChannelId::new("xy"), Frequency::ZERO, None, false);
+            assert_eq!(executor.execute(&element(element_override)).is_ok(), ok);
+        }
+    }
+
+    #[test]
+    fn time_grid() {
+        let t = |v| Time::new(v).unwrap();
+        let range = TimeRange {
+            start: t(1.2),
+            span: t(0.2),
+        };
+        for (rounding, start, span) in [
+            (Rounding::Nearest, 1.0, 1.0),
+            (Rounding::Floor, 1.0, 0.0),
+            (Rounding::Ceil, 1.5, 0.0),
+        ] {
+            let grid = TimeGrid::new(t(0.5), t(1.0), rounding).unwrap();
+            let snapped = grid.snap(range);
+            assert_eq!((snapped.start, snapped.span), (t(start), t(span)));
+        }
+        assert!(TimeGrid::new(Time::ZERO, Time::ZERO, Rounding::Nearest).is_err());
+
+        let grid = TimeGrid::new(t(1e-9), t(4e-9), Rounding::Ceil).unwrap();
+        let json = serde_json::to_string(&grid).unwrap();
+        assert_eq!(serde_json::from_str::<TimeGrid>(&json).unwrap(), grid);
+        assert_eq!(
+            serde_json::from_str::<TimeGrid>(r#"{"step": 1e-9}"#).unwrap(),
+            TimeGrid::new(t(1e-9), Time::ZERO, Rounding::Nearest).unwrap()
+        );
+        assert!(serde_json::from_str::<TimeGrid>(r#"{"step": 0}"#).is_err());
+
+        let common = ElementCommonBuilder::new()
+            .margin((t(1.2e-9), Time::ZERO))
+            .build()
+            .unwrap();
+        let root = Arc::new(Element::new(
+            common,
+            Absolute::new().with_children(vec![AbsoluteEntry::new(play(0.5))]),
+        ));
+        let mut executor = Executor::new(Amplitude::new(1e-5).unwrap(), t(1e-12), false);
+        executor.add_channel(ChannelId::new("xy"), Frequency::ZERO, None, false);
+        executor.set_time_grid(Some(
+            TimeGrid::new(t(1e-9), Time::ZERO, Rounding::Nearest).unwrap(),
+        ));
+        executor.execute(&root).unwrap();
+        let (_, diagnostics) = executor.into_result();
+        assert!(matches!(diagnostics.warnings(), [Warning::Snapped { .. }]));
+    }
+
+    #[test]
+    fn channel_time_grid() {
+        let t = |v| Time::new(v).unwrap();
+        let (a, b) = (ChannelId::new("a"), ChannelId::new("b"));
+        let element = |variant: ElementVariant| {
+            Arc::new(Element::new(
+                ElementCommonBuilder::new().build().unwrap(),
+                variant,
+            ))
+        };
+        let play = |channel, width| {
+            let play = Play::new(channel, None, Amplitude::new(0.5).unwrap(), t(width)).unwrap();
+            element(play.into())
+        };
+        // The barrier at 3 ns orders the play on `a` before the play on `b`.
+        let root = element(
+            Absolute::new()
+                .with_children(vec![
+                    AbsoluteEntry::new(play(a, 3e-9)),
+                    AbsoluteEntry::new(element(Barrier::new(vec![]).into()))
+                        .with_time(t(3e-9))
+                        .unwrap(),
+                    AbsoluteEntry::new(play(b, 4e-9))
+                        .with_time(t(3e-9))
+                        .unwrap(),
+                ])
+                .into(),
+        );
+        let grid = TimeGrid::new(t(2e-9), Time::ZERO, Rounding::Ceil).unwrap();
+        let execute = |channels: &[ChannelId]| {
+            let mut executor = Executor::new(Amplitude::new(1e-5).unwrap(), t(1e-12), false);
+            executor.add_channel(a, Frequency::ZERO, None, false);
+            executor.add_channel(b, Frequency::ZERO, None, false);
+            for &c in channels {
+                executor.set_channel_time_grid(c, Some(grid));
             }
-            None => {
-                stack.pop();
+            executor.execute(&root)
+        };
+
+        assert!(execute(&[]).is_ok());
+        assert!(matches!(
+            execute(&[a]),
+            Err(Error::Misordered { channel, .. }) if channel == b
+        ));
+        assert!(execute(&[a, b]).is_ok());
+    }
+
+    #[test]
+    fn ringdown() {
+        let t = |x: f64| Time::new(x * 1e-9).unwrap();
+        let ringdown = Ringdown::new(t(30.0), t(10.0)).unwrap();
+        let shape = Shape::new_hann();
+        let (width, plateau, length) = (40.0, 20.0, 10.0);
+        let factor = ringdown.tail_amplitude(Some(&shape), t(width), t(plateau), None);
+        // Integrate the state of the single pole response numerically over the
+        // pulse and the tail.
+        let envelope = |time: f64| {
+            let x = if time < width / 2.0 {
+                time / width - 0.5
+            } else if time < width / 2.0 + plateau {
+                return 1.0;
+            } else if time < width + plateau {
+                (time - plateau) / width - 0.5
+            } else {
+                return factor;
+            };
+            let mut value = [0.0];
+            shape.sample_array(x, 0.0, &mut value);
+            value[0]
+        };
+        let end = width + plateau + length;
+        let n = 100_000;
+        let dt = end / n as f64;
+        let state: f64 = (0..n)
+            .map(|i| {
+                let time = (i as f64 + 0.5) * dt;
+                envelope(time) * ((time - end) / 30.0).exp() * dt / 30.0
+            })
+            .sum();
+        assert!(factor < 0.0);
+        assert!(state.abs() < 1e-4, "{}", state);
+
+        let rect = ringdown.tail_amplitude(None, t(width), Time::ZERO, None);
+        let q = (-length / 30.0f64).exp();
+        let expected = -(1.0 - (-width / 30.0f64).exp()) * q / (1.0 - q);
+        assert!((rect - expected).abs() < 1e-12);
+
+        assert!(Ringdown::new(t(30.0), Time::ZERO).is_err());
+        assert!(serde_json::from_str::<Ringdown>(r#"{"tau": 1e-8, "length": -1e-9}"#).is_err());
+    }
+
+    #[test]
+    fn ringdown_overlap() {
+        let name = ChannelId::new("xy");
+        let ringdown = Ringdown::new(Time::new(30e-9).unwrap(), Time::new(5e-9).unwrap()).unwrap();
+        let root = |second: f64| {
+            let entry = |time: f64| {
+                AbsoluteEntry::new(play(0.5))
+                    .with_time(Time::new(time).unwrap())
+                    .unwrap()
+            };
+            Arc::new(Element::new(
+                ElementCommonBuilder::new().build().unwrap(),
+                Absolute::new().with_children(vec![entry(0.0), entry(second)]),
+            ))
+        };
+        let executor = || {
+            let mut executor = executor();
+            executor.add_channel(name, Frequency::ZERO, Some(ringdown), false);
+            executor
+        };
+
+        for second in [10e-9, 14e-9] {
+            assert!(matches!(
+                executor().execute(&root(second)),
+                Err(Error::TailOverlap { channel, .. }) if channel == name
+            ));
+        }
+        let (waveform, _) = sample_schedule(executor(), &root(15e-9), name, 40, 1e9);
+        let factor = ringdown.tail_amplitude(None, Time::new(10e-9).unwrap(), Time::ZERO, None);
+        let row = waveform.row(0);
+        assert_eq!(row.slice(ndarray::s![..10]).to_vec(), [0.5; 10]);
+        assert!(row
+            .slice(ndarray::s![10..15])
+            .iter()
+            .all(|&v| (v - 0.5 * factor).abs() < 1e-12));
+        assert_eq!(row.slice(ndarray::s![15..25]).to_vec(), [0.5; 10]);
+    }
+
+    #[test]
+    fn net_zero() {
+        let name = ChannelId::new("xy");
+        let t = |v| Time::new(v).unwrap();
+        let play = |duration: f64, flexible| {
+            let play = Play::new(name, None, Amplitude::new(0.5).unwrap(), t(10e-9))
+                .unwrap()
+                .with_flexible(flexible);
+            let common = ElementCommonBuilder::new()
+                .duration(Some(t(duration)))
+                .build()
+                .unwrap();
+            Arc::new(Element::new(common, play))
+        };
+        let executor = |ringdown| {
+            let mut executor = executor();
+            executor.add_channel(name, Frequency::ZERO, ringdown, true);
+            executor
+        };
+
+        let (waveform, diagnostics) =
+            sample_schedule(executor(None), &play(20e-9, false), name, 30, 1e9);
+        assert!(diagnostics.warnings().is_empty());
+        assert_eq!(
+            waveform.row(0).to_vec(),
+            [[0.5; 10].as_slice(), &[-0.5; 10], &[0.0; 10]].concat()
+        );
+        let (waveform, _) = sample_schedule(executor(None), &play(30e-9, true), name, 30, 1e9);
+        assert_eq!(waveform.row(0).to_vec(), [[0.5; 15], [-0.5; 15]].concat());
+        // The copy doesn't fit in the span of the pulse.
+        assert!(matches!(
+            executor(None).execute(&play(15e-9, false)),
+            Err(Error::NotEnoughDuration { .. })
+        ));
+        // Holding would end the channel at the inverted level.
+        let held = Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            Play::new(name, None, Amplitude::new(0.5).unwrap(), t(10e-9))
+                .unwrap()
+                .with_hold(true),
+        ));
+        assert!(matches!(
+            executor(None).execute(&held),
+            Err(Error::NetZeroHold { channel, .. }) if channel == name
+        ));
+
+        // A single tail after the copy cancels the ring-down of both.
+        let (tau, length) = (30.0, 5.0);
+        let ringdown = Ringdown::new(t(tau * 1e-9), t(length * 1e-9)).unwrap();
+        let root = Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            Absolute::new().with_children(vec![AbsoluteEntry::new(play(20e-9, false))]),
+        ));
+        let (waveform, _) = sample_schedule(executor(Some(ringdown)), &root, name, 30, 1e9);
+        let row = waveform.row(0);
+        assert_eq!(
+            row.slice(ndarray::s![..20]).to_vec(),
+            [[0.5; 10], [-0.5; 10]].concat()
+        );
+        let tail = row[20];
+        assert!(tail > 0.0);
+        assert!(row.slice(ndarray::s![20..25]).iter().all(|&v| v == tail));
+        assert!(row.slice(ndarray::s![25..]).iter().all(|&v| v == 0.0));
+        // Exact state of the single pole response at the end of the tail for
+        // the sample and hold waveform.
+        let end = 20.0 + length;
+        let state: f64 = row
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let weight = |t: f64| ((t.min(end) - end) / tau).exp();
+                v * (weight(i as f64 + 1.0) - weight(i as f64))
+            })
+            .sum();
+        assert!(state.abs() < 1e-12, "{}", state);
+    }
+
+    #[test]
+    fn play_samples() {
+        let name = ChannelId::new("xy");
+        let samples: Arc<[Complex64]> = [0.1, 0.2, 0.0, -0.3]
+            .iter()
+            .zip([0.0, 0.4, 0.5, 0.0])
+            .map(|(&re, im)| Complex64::new(re, im))
+            .collect();
+        let rate = Frequency::new(1e9).unwrap();
+        let play = PlaySamples::new(name, samples.clone(), rate)
+            .unwrap()
+            .with_amplitude(Amplitude::new(2.0).unwrap())
+            .unwrap();
+        let root = Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            play,
+        ));
+        let mut executor = executor();
+        executor.add_channel(name, Frequency::ZERO, None, false);
+        let (waveform, _) = sample_schedule(executor, &root, name, 6, 1e9);
+        for (i, s) in samples.iter().enumerate() {
+            assert!((waveform[[0, i]] - 2.0 * s.re).abs() < 1e-12);
+            assert!((waveform[[1, i]] - 2.0 * s.im).abs() < 1e-12);
+        }
+        assert_eq!(waveform.column(4).to_vec(), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn phase_reference() {
+        let name = ChannelId::new("xy");
+        let sample = |phase_reference| {
+            let play = Play::new(
+                name,
+                None,
+                Amplitude::new(0.5).unwrap(),
+                Time::new(10e-9).unwrap(),
+            )
+            .unwrap()
+            .with_phase_reference(phase_reference);
+            let common = ElementCommonBuilder::new().build().unwrap();
+            let play = Arc::new(Element::new(common.clone(), play));
+            let repeat = Repeat::new(play, 2)
+                .with_spacing(Time::new(3e-9).unwrap())
+                .unwrap();
+            let root = Arc::new(Element::new(common, repeat));
+            let mut executor = executor();
+            executor.add_channel(name, Frequency::new(100e6).unwrap(), None, false);
+            sample_schedule(executor, &root, name, 23, 1e9).0
+        };
+        let same_iterations = |waveform: &Array2<f64>| {
+            (0..10).all(|i| (0..2).all(|c| (waveform[[c, i]] - waveform[[c, i + 13]]).abs() < 1e-9))
+        };
+
+        assert!(!same_iterations(&sample(PhaseReference::Absolute)));
+        let waveform = sample(PhaseReference::Iteration);
+        assert!(same_iterations(&waveform));
+        assert!((waveform[[0, 0]] - 0.5).abs() < 1e-9);
+        assert_eq!(sample(PhaseReference::Pulse), waveform);
+    }
+
+    #[test]
+    fn frequency_overflow() {
+        let name = ChannelId::new("xy");
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let shift = ShiftFreq::new(name, Frequency::new(1e308).unwrap()).unwrap();
+        let shift = Arc::new(Element::new(common.clone(), shift));
+        let root = Arc::new(Element::new(common, Repeat::new(shift, 2)));
+        let mut executor = executor();
+        executor.add_channel(name, Frequency::ZERO, None, false);
+        assert!(matches!(
+            executor.execute(&root),
+            Err(Error::Arithmetic(quant::Error::InfiniteValue))
+        ));
+    }
+
+    #[test]
+    fn hold() {
+        let name = ChannelId::new("xy");
+        let entry = |time: f64, element: ElementRef| {
+            AbsoluteEntry::new(element)
+                .with_time(Time::new(time).unwrap())
+                .unwrap()
+        };
+        let sample = |barrier: bool| {
+            let common = ElementCommonBuilder::new().build().unwrap();
+            let held = Play::new(
+                name,
+                None,
+                Amplitude::new(0.5).unwrap(),
+                Time::new(10e-9).unwrap(),
+            )
+            .unwrap()
+            .with_hold(true);
+            let mut children = vec![
+                entry(0.0, Arc::new(Element::new(common.clone(), held.clone()))),
+                entry(30e-9, play(0.2)),
+                entry(40e-9, Arc::new(Element::new(common.clone(), held))),
+            ];
+            if barrier {
+                let barrier = Barrier::new(vec![name]);
+                children.push(entry(
+                    20e-9,
+                    Arc::new(Element::new(common.clone(), barrier)),
+                ));
             }
+            let root = Arc::new(Element::new(
+                common,
+                Absolute::new().with_children(children),
+            ));
+            let mut executor = executor();
+            executor.add_channel(name, Frequency::ZERO, None, false);
+            let (waveform, _) = sample_schedule(executor, &root, name, 60, 1e9);
+            waveform.row(0).to_vec()
+        };
+
+        // The last hold ends with the schedule at 50 ns.
+        let tail = [[0.5; 10].as_slice(), &[0.0; 10]].concat();
+        assert_eq!(
+            sample(false),
+            [[0.5; 30].as_slice(), &[0.2; 10], &tail].concat()
+        );
+        assert_eq!(
+            sample(true),
+            [[0.5; 20].as_slice(), &[0.0; 10], &[0.2; 10], &tail].concat()
+        );
+    }
+
+    #[test]
+    fn hold_shaped_edge() {
+        let name = ChannelId::new("xy");
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let held = Play::new(
+            name,
+            Some(ShapeId::new("hann")),
+            Amplitude::new(0.5).unwrap(),
+            Time::new(20e-9).unwrap(),
+        )
+        .unwrap()
+        .with_plateau(Time::new(10e-9).unwrap())
+        .unwrap()
+        .with_hold(true);
+        let root = Arc::new(Element::new(
+            common.clone(),
+            Absolute::new().with_children(vec![
+                AbsoluteEntry::new(Arc::new(Element::new(common, held))),
+                AbsoluteEntry::new(play(0.2))
+                    .with_time(Time::new(40e-9).unwrap())
+                    .unwrap(),
+            ]),
+        ));
+        let mut executor = executor();
+        executor.add_channel(name, Frequency::ZERO, None, false);
+        executor.add_shape(ShapeId::new("hann"), Shape::new_hann());
+        let (waveform, _) = sample_schedule(executor, &root, name, 50, 1e9);
+
+        // The pulse rises with the first half of the shape and stays at the
+        // plateau amplitude until the next pulse.
+        let shape = Shape::new_hann();
+        for (i, &value) in waveform.row(0).iter().enumerate() {
+            let expected = match i {
+                0..10 => {
+                    let mut rise = [0.0];
+                    shape.sample_array((i as f64 - 10.0) / 20.0, 0.0, &mut rise);
+                    0.5 * rise[0]
+                }
+                10..40 => 0.5,
+                _ => 0.2,
+            };
+            assert!((value - expected).abs() < 1e-12, "{} {}", i, value);
         }
-    }))
-}
+    }
 
-#[cfg(test)]
-mod tests {
+    // Reports the variant by its python name.
+    #[cfg(feature = "python")]
     #[test]
-    fn pre_order() {
-        let node_children = vec![
-            vec![1, 2, 3],
-            vec![4, 5],
-            vec![6, 7],
-            vec![],
-            vec![8, 9],
-            vec![],
-            vec![10, 11],
-            vec![],
-            vec![],
-            vec![],
-            vec![],
-        ];
-        let expected = vec![0, 1, 4, 8, 9, 5, 2, 6, 10, 11, 7, 3];
+    fn observer() {
+        let t = |v| Time::new(v).unwrap();
+        let common = ElementCommonBuilder::new()
+            .margin((t(1.2e-9), Time::ZERO))
+            .build()
+            .unwrap();
+        let root = Arc::new(Element::new(
+            common,
+            Absolute::new().with_children(vec![AbsoluteEntry::new(play(0.5))]),
+        ));
+        let mut executor = Executor::new(Amplitude::new(1e-5).unwrap(), t(1e-12), false);
+        executor.add_channel(ChannelId::new("xy"), Frequency::ZERO, None, false);
+        executor.set_time_grid(Some(
+            TimeGrid::new(t(1e-9), Time::ZERO, Rounding::Nearest).unwrap(),
+        ));
+        let mut observed = vec![];
+        executor
+            .execute_observed(&root, |item, range| {
+                observed.push((item.variant.name(), range.start, range.span));
+            })
+            .unwrap();
+        // The play is reported at its snapped time.
+        assert_eq!(
+            observed,
+            [
+                ("Absolute", t(1.2e-9), t(10e-9)),
+                ("Play", t(1e-9), t(10e-9)),
+            ]
+        );
+    }
 
-        let result = super::pre_order_iter(0, |i| node_children.get(i).map(|c| c.iter().copied()))
-            .collect::<Vec<_>>();
+    #[cfg(feature = "python")]
+    #[test]
+    fn fingerprint() {
+        let t = |v| Time::new(v).unwrap();
+        let root = |amplitude| {
+            let common = ElementCommonBuilder::new().build().unwrap();
+            Arc::new(Element::new(
+                common,
+                Absolute::new().with_children(vec![AbsoluteEntry::new(play(amplitude))]),
+            ))
+        };
+        let executor = |time_grid| {
+            let mut executor = Executor::new(Amplitude::new(1e-5).unwrap(), t(1e-12), false);
+            executor.add_channel(ChannelId::new("xy"), Frequency::ZERO, None, false);
+            executor.add_shape(ShapeId::new("hann"), Shape::new_hann());
+            executor.set_time_grid(time_grid);
+            executor
+        };
+        let grid = TimeGrid::new(t(1e-9), Time::ZERO, Rounding::Nearest).unwrap();
 
-        assert_eq!(result, expected);
+        // Separately built schedules and shapes give the same fingerprint.
+        let a = executor(None).fingerprint(&root(0.5));
+        assert_eq!(executor(None).fingerprint(&root(0.5)), a);
+        assert_ne!(executor(None).fingerprint(&root(0.25)), a);
+        assert_ne!(executor(Some(grid)).fingerprint(&root(0.5)), a);
+        assert_eq!(
+            executor(None).compile_key(&root(0.5)),
+            executor(None).compile_key(&root(0.5))
+        );
+        assert_ne!(
+            executor(None).compile_key(&root(0.5)),
+            executor(None).compile_key(&root(0.25))
+        );
     }
 }
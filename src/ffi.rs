@@ -0,0 +1,606 @@
+//! C API of the pulse sampler.
+//!
+//! Build the shared library with `cargo build --release --features ffi`. The
+//! header `include/bosing.h` is generated by `cbindgen --config
+//! cbindgen.toml --output include/bosing.h`.
+//!
+//! Functions returning `int32_t` return `0` on success and `-1` on failure.
+//! The message of the last failure on the calling thread can be retrieved by
+//! [`bosing_last_error`]. Internal panics are caught and reported as
+//! failures instead of unwinding into the caller.
+use std::{
+    any::Any,
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use hashbrown::HashMap;
+use ndarray::{Array2, ArrayViewMut2};
+use num::complex::Complex64;
+
+use crate::{
+    pulse::{Envelope, PulseListBuilder, PushArgs, Sampler},
+    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    shape::Shape,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Pulse sampler with pulse lists of multiple channels.
+#[derive(Debug)]
+pub struct BosingSampler {
+    channels: HashMap<ChannelId, Channel>,
+    shapes: HashMap<ShapeId, Shape>,
+    amp_tolerance: Amplitude,
+    time_tolerance: Time,
+}
+
+#[derive(Debug)]
+struct Channel {
+    base_freq: Frequency,
+    sample_rate: Frequency,
+    delay: Time,
+    align_level: i32,
+    length: usize,
+    is_real: bool,
+    pulses: PulseListBuilder,
+}
+
+/// Create a new sampler.
+///
+/// Returns null if the tolerances are invalid. The sampler must be released by
+/// [`bosing_sampler_free`].
+#[no_mangle]
+pub extern "C" fn bosing_sampler_new(
+    amp_tolerance: f64,
+    time_tolerance: f64,
+) -> *mut BosingSampler {
+    let sampler = catch_panic(|| {
+        Ok(BosingSampler {
+            channels: HashMap::new(),
+            shapes: HashMap::new(),
+            amp_tolerance: Amplitude::new(amp_tolerance)?,
+            time_tolerance: Time::new(time_tolerance)?,
+        })
+    });
+    match sampler {
+        Ok(sampler) => Box::into_raw(Box::new(sampler)),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Release a sampler created by [`bosing_sampler_new`].
+///
+/// # Safety
+///
+/// `sampler` must be null or a pointer returned by [`bosing_sampler_new`] that
+/// has not been released.
+#[no_mangle]
+pub unsafe extern "C" fn bosing_sampler_free(sampler: *mut BosingSampler) {
+    if !sampler.is_null() {
+        drop(Box::from_raw(sampler));
+    }
+}
+
+/// Add a channel. Existing channel with the same name is replaced.
+///
+/// The sampled waveform has `length` samples with 1 row if `is_real` and 2
+/// rows (I and Q) otherwise.
+///
+/// # Safety
+///
+/// `sampler` must be a valid sampler and `name` a null-terminated UTF-8
+/// string.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn bosing_sampler_add_channel(
+    sampler: *mut BosingSampler,
+    name: *const c_char,
+    base_freq: f64,
+    sample_rate: f64,
+    delay: f64,
+    align_level: i32,
+    length: usize,
+    is_real: bool,
+) -> i32 {
+    wrap(|| {
+        let sampler = sampler_mut(sampler)?;
+        let name = ChannelId::new(to_str(name)?);
+        let channel = Channel {
+            base_freq: Frequency::new(base_freq)?,
+            sample_rate: Frequency::new(sample_rate)?,
+            delay: Time::new(delay)?,
+            align_level,
+            length,
+            is_real,
+            pulses: PulseListBuilder::new(sampler.amp_tolerance, sampler.time_tolerance),
+        };
+        sampler.channels.insert(name, channel);
+        Ok(())
+    })
+}
+
+/// Add a Hann shape. Existing shape with the same name is replaced.
+///
+/// # Safety
+///
+/// `sampler` must be a valid sampler and `name` a null-terminated UTF-8
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn bosing_sampler_add_hann_shape(
+    sampler: *mut BosingSampler,
+    name: *const c_char,
+) -> i32 {
+    wrap(|| {
+        let sampler = sampler_mut(sampler)?;
+        let name = ShapeId::new(to_str(name)?);
+        sampler.shapes.insert(name, Shape::new_hann());
+        Ok(())
+    })
+}
+
+/// Add an interpolated shape. Existing shape with the same name is replaced.
+///
+/// See `Interp` in the python API for the meaning of the parameters.
+///
+/// # Safety
+///
+/// `sampler` must be a valid sampler, `name` a null-terminated UTF-8 string,
+/// and `knots` and `controls` must point to `n_knots` and `n_controls`
+/// values respectively.
+#[no_mangle]
+pub unsafe extern "C" fn bosing_sampler_add_interp_shape(
+    sampler: *mut BosingSampler,
+    name: *const c_char,
+    knots: *const f64,
+    n_knots: usize,
+    controls: *const f64,
+    n_controls: usize,
+    degree: usize,
+) -> i32 {
+    wrap(|| {
+        let sampler = sampler_mut(sampler)?;
+        let name = ShapeId::new(to_str(name)?);
+        let knots = to_slice(knots, n_knots)?.to_vec();
+        let controls = to_slice(controls, n_controls)?.to_vec();
+        let shape = Shape::new_interp(knots, controls, degree)?;
+        sampler.shapes.insert(name, shape);
+        Ok(())
+    })
+}
+
+/// Push a pulse to the pulse list of a channel.
+///
+/// `shape` may be null for a rectangular pulse. `frequency` is relative to
+/// the base frequency of the channel and `phase` is in cycles.
+///
+/// # Safety
+///
+/// `sampler` must be a valid sampler, `channel` a null-terminated UTF-8
+/// string, and `shape` null or a null-terminated UTF-8 string.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn bosing_sampler_push(
+    sampler: *mut BosingSampler,
+    channel: *const c_char,
+    shape: *const c_char,
+    time: f64,
+    width: f64,
+    plateau: f64,
+    amplitude: f64,
+    drag_coef: f64,
+    frequency: f64,
+    phase: f64,
+) -> i32 {
+    wrap(|| {
+        push(
+            sampler, channel, shape, time, width, plateau, amplitude, drag_coef, frequency, phase,
+            None,
+        )
+    })
+}
+
+/// Push a pulse with a user ID to the pulse list of a channel.
+///
+/// Same as [`bosing_sampler_push`], the ID is reported by
+/// [`bosing_sampler_contributors`] for the samples the pulse contributes to.
+///
+/// # Safety
+///
+/// Same as [`bosing_sampler_push`].
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn bosing_sampler_push_with_id(
+    sampler: *mut BosingSampler,
+    channel: *const c_char,
+    shape: *const c_char,
+    time: f64,
+    width: f64,
+    plateau: f64,
+    amplitude: f64,
+    drag_coef: f64,
+    frequency: f64,
+    phase: f64,
+    id: u64,
+) -> i32 {
+    wrap(|| {
+        push(
+            sampler,
+            channel,
+            shape,
+            time,
+            width,
+            plateau,
+            amplitude,
+            drag_coef,
+            frequency,
+            phase,
+            Some(id),
+        )
+    })
+}
+
+/// IDs of the pulses contributing to `count` samples of a channel starting
+/// from sample `first`, in ascending order.
+///
+/// At most `ids_len` IDs are written to `ids`, and the number of all found
+/// IDs is written to `n_ids`. Only pulses pushed by
+/// [`bosing_sampler_push_with_id`] are reported.
+///
+/// # Safety
+///
+/// `sampler` must be a valid sampler, `channel` a null-terminated UTF-8
+/// string, `ids` must point to `ids_len` writable values, and `n_ids` must be
+/// a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bosing_sampler_contributors(
+    sampler: *mut BosingSampler,
+    channel: *const c_char,
+    first: usize,
+    count: usize,
+    ids: *mut u64,
+    ids_len: usize,
+    n_ids: *mut usize,
+) -> i32 {
+    wrap(|| {
+        let sampler = sampler_mut(sampler)?;
+        let name = ChannelId::new(to_str(channel)?);
+        let channel = sampler
+            .channels
+            .get(&name)
+            .with_context(|| format!("Channel '{}' not found", name))?;
+        if n_ids.is_null() || (ids.is_null() && ids_len > 0) {
+            bail!("Output pointer is null");
+        }
+        // Only the time spans of the pulses are needed.
+        let mut waveform = Array2::zeros((1, 0));
+        let pulse_list = channel.pulses.clone().build_with(|_| {});
+        let mut inner = Sampler::new([(name, pulse_list)].into());
+        inner.add_channel(
+            name,
+            waveform.view_mut(),
+            channel.sample_rate,
+            channel.delay,
+            channel.align_level,
+            Complex64::ZERO,
+        );
+        let last = first
+            .checked_add(count)
+            .context("Sample range is out of bounds")?;
+        let found = inner.contributors(name, first..last);
+        if ids_len > 0 {
+            let ids = slice::from_raw_parts_mut(ids, ids_len);
+            ids.iter_mut().zip(&found).for_each(|(a, b)| *a = *b);
+        }
+        *n_ids = found.len();
+        Ok(())
+    })
+}
+
+/// Sample the pulses of a channel into a caller provided buffer.
+///
+/// The buffer is overwritten with the row-major waveform and must hold
+/// `length` values for real channels and `2 * length` values otherwise.
+///
+/// # Safety
+///
+/// `sampler` must be a valid sampler, `channel` a null-terminated UTF-8
+/// string, and `buffer` must point to `buffer_len` writable values.
+#[no_mangle]
+pub unsafe extern "C" fn bosing_sampler_sample(
+    sampler: *mut BosingSampler,
+    channel: *const c_char,
+    buffer: *mut f64,
+    buffer_len: usize,
+) -> i32 {
+    wrap(|| {
+        let sampler = sampler_mut(sampler)?;
+        let name = ChannelId::new(to_str(channel)?);
+        let channel = sampler
+            .channels
+            .get(&name)
+            .with_context(|| format!("Channel '{}' not found", name))?;
+        let rows = if channel.is_real { 1 } else { 2 };
+        if buffer.is_null() || buffer_len != rows * channel.length {
+            bail!(
+                "Buffer length {} doesn't match the waveform length {}",
+                buffer_len,
+                rows * channel.length
+            );
+        }
+        let buffer = slice::from_raw_parts_mut(buffer, buffer_len);
+        buffer.fill(0.0);
+        let waveform = ArrayViewMut2::from_shape((rows, channel.length), buffer)?;
+        let pulse_list = channel.pulses.clone().build_with(|_| {});
+        let mut inner = Sampler::new([(name, pulse_list)].into());
+        inner.add_channel(
+            name,
+            waveform,
+            channel.sample_rate,
+            channel.delay,
+            channel.align_level,
+            Complex64::ZERO,
+        );
+        inner.sample(sampler.time_tolerance)?;
+        Ok(())
+    })
+}
+
+/// Message of the last failure on the calling thread.
+///
+/// Returns null if no call has failed. The string is valid until the next
+/// failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn bosing_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+fn wrap(f: impl FnOnce() -> Result<()>) -> i32 {
+    match catch_panic(f) {
+        Ok(()) => 0,
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(anyhow!("Internal error: {}", panic_message(&*payload))))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "panic"
+    }
+}
+
+fn set_last_error(e: anyhow::Error) {
+    let message =
+        CString::new(format!("{:#}", e).replace('\0', "")).expect("Nul bytes should be removed");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn push(
+    sampler: *mut BosingSampler,
+    channel: *const c_char,
+    shape: *const c_char,
+    time: f64,
+    width: f64,
+    plateau: f64,
+    amplitude: f64,
+    drag_coef: f64,
+    frequency: f64,
+    phase: f64,
+    id: Option<u64>,
+) -> Result<()> {
+    let sampler = sampler_mut(sampler)?;
+    let shape = if shape.is_null() {
+        None
+    } else {
+        let name = to_str(shape)?;
+        let shape = sampler
+            .shapes
+            .get(&ShapeId::new(name))
+            .with_context(|| format!("Shape '{}' not found", name))?;
+        Some(shape.clone())
+    };
+    let name = to_str(channel)?;
+    let channel = sampler
+        .channels
+        .get_mut(&ChannelId::new(name))
+        .with_context(|| format!("Channel '{}' not found", name))?;
+    let envelope = Envelope::new(shape, Time::new(width)?, Time::new(plateau)?);
+    channel.pulses.push(PushArgs {
+        envelope,
+        global_freq: channel.base_freq,
+        local_freq: Frequency::new(frequency)?,
+        time: Time::new(time)?,
+        amplitude: Amplitude::new(amplitude)?,
+        drag_coef,
+        phase: Phase::from_turns(phase)?,
+        id,
+    });
+    Ok(())
+}
+
+unsafe fn sampler_mut<'a>(sampler: *mut BosingSampler) -> Result<&'a mut BosingSampler> {
+    sampler.as_mut().ok_or_else(|| anyhow!("Sampler is null"))
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str> {
+    if s.is_null() {
+        bail!("String is null");
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+unsafe fn to_slice<'a>(data: *const f64, len: usize) -> Result<&'a [f64]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        bail!("Array is null");
+    }
+    Ok(slice::from_raw_parts(data, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_rect() {
+        let sampler = bosing_sampler_new(1e-5, 1e-12);
+        let mut buffer = [1.0; 8];
+        unsafe {
+            assert_eq!(
+                bosing_sampler_add_channel(sampler, c"xy".as_ptr(), 0.0, 1e9, 0.0, -10, 4, false),
+                0
+            );
+            assert_eq!(
+                bosing_sampler_push(
+                    sampler,
+                    c"xy".as_ptr(),
+                    ptr::null(),
+                    1e-9,
+                    2e-9,
+                    0.0,
+                    0.5,
+                    0.0,
+                    0.0,
+                    0.25,
+                ),
+                0
+            );
+            assert_eq!(
+                bosing_sampler_sample(sampler, c"xy".as_ptr(), buffer.as_mut_ptr(), 8),
+                0
+            );
+            bosing_sampler_free(sampler);
+        }
+
+        // A quarter cycle phase puts the pulse on the Q row.
+        let expected = [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.0];
+        for (a, b) in buffer.iter().zip(&expected) {
+            assert!((a - b).abs() < 1e-9, "{:?}", buffer);
+        }
+    }
+
+    #[test]
+    fn contributors() {
+        let sampler = bosing_sampler_new(1e-5, 1e-12);
+        let mut ids = [0; 4];
+        let mut n_ids = 0;
+        unsafe {
+            assert_eq!(
+                bosing_sampler_add_channel(sampler, c"xy".as_ptr(), 0.0, 1e9, 0.0, -10, 16, false),
+                0
+            );
+            for (id, time) in [(7, 2e-9), (3, 4e-9), (5, 10e-9)] {
+                assert_eq!(
+                    bosing_sampler_push_with_id(
+                        sampler,
+                        c"xy".as_ptr(),
+                        ptr::null(),
+                        time,
+                        4e-9,
+                        0.0,
+                        0.5,
+                        0.0,
+                        0.0,
+                        0.0,
+                        id,
+                    ),
+                    0
+                );
+            }
+            assert_eq!(
+                bosing_sampler_contributors(
+                    sampler,
+                    c"xy".as_ptr(),
+                    5,
+                    1,
+                    ids.as_mut_ptr(),
+                    4,
+                    &mut n_ids,
+                ),
+                0
+            );
+            assert_eq!(&ids[..n_ids], [3, 7]);
+            assert_eq!(
+                bosing_sampler_contributors(
+                    sampler,
+                    c"xy".as_ptr(),
+                    8,
+                    2,
+                    ids.as_mut_ptr(),
+                    4,
+                    &mut n_ids,
+                ),
+                0
+            );
+            assert_eq!(n_ids, 0);
+            bosing_sampler_free(sampler);
+        }
+    }
+
+    #[test]
+    fn catch_panics() {
+        assert_eq!(wrap(|| panic!("Should not unwind")), -1);
+        let message = unsafe { CStr::from_ptr(bosing_last_error()) };
+        assert_eq!(
+            message.to_str().unwrap(),
+            "Internal error: Should not unwind"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let sampler = bosing_sampler_new(1e-5, 1e-12);
+        let mut buffer = [0.0; 3];
+        unsafe {
+            assert_eq!(
+                bosing_sampler_sample(sampler, c"xy".as_ptr(), buffer.as_mut_ptr(), 3),
+                -1
+            );
+            let message = CStr::from_ptr(bosing_last_error()).to_str().unwrap();
+            assert_eq!(message, "Channel 'xy' not found");
+
+            assert_eq!(
+                bosing_sampler_add_channel(sampler, c"xy".as_ptr(), 0.0, 1e9, 0.0, -10, 4, false),
+                0
+            );
+            let mut n_ids = 0;
+            assert_eq!(
+                bosing_sampler_contributors(
+                    sampler,
+                    c"xy".as_ptr(),
+                    usize::MAX,
+                    2,
+                    ptr::null_mut(),
+                    0,
+                    &mut n_ids,
+                ),
+                -1
+            );
+            let message = CStr::from_ptr(bosing_last_error()).to_str().unwrap();
+            assert_eq!(message, "Sample range is out of bounds");
+            bosing_sampler_free(sampler);
+        }
+    }
+}
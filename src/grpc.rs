@@ -0,0 +1,271 @@
+//! gRPC pulse generation service.
+//!
+//! The service is described in `proto/bosing.proto`. The server is started by
+//! the `bosing-server` binary.
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+
+use anyhow::{Context, Result};
+use hashbrown::HashMap;
+use ndarray::s;
+use num::complex::Complex64;
+use tokio_stream::Stream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{
+    pipeline::{generate_waveforms, ChannelSpec, Filters, Options},
+    quant::{Amplitude, ChannelId, Frequency, ShapeId, Time},
+    schedule::Element,
+    shape::Shape,
+};
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/bosing.PulseGen.rs"));
+}
+
+use generated::pulse_gen_server::{PulseGen, PulseGenServer};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SampleRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub schedule: Vec<u8>,
+    #[prost(message, repeated, tag = "2")]
+    pub channels: Vec<ChannelConfig>,
+    #[prost(message, repeated, tag = "3")]
+    pub shapes: Vec<ShapeConfig>,
+    #[prost(double, tag = "4")]
+    pub time_tolerance: f64,
+    #[prost(double, tag = "5")]
+    pub amp_tolerance: f64,
+    #[prost(bool, tag = "6")]
+    pub allow_oversize: bool,
+    #[prost(uint64, tag = "7")]
+    pub chunk_size: u64,
+    #[prost(bool, tag = "8")]
+    pub allow_truncate: bool,
+    #[prost(bool, tag = "9")]
+    pub fractional_delay: bool,
+    #[prost(uint32, tag = "10")]
+    pub oversampling: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChannelConfig {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(double, tag = "2")]
+    pub base_freq: f64,
+    #[prost(double, tag = "3")]
+    pub sample_rate: f64,
+    #[prost(double, tag = "4")]
+    pub delay: f64,
+    #[prost(int32, tag = "5")]
+    pub align_level: i32,
+    #[prost(uint64, tag = "6")]
+    pub length: u64,
+    #[prost(bool, tag = "7")]
+    pub is_real: bool,
+    #[prost(double, tag = "8")]
+    pub baseline_i: f64,
+    #[prost(double, tag = "9")]
+    pub baseline_q: f64,
+    #[prost(bool, tag = "10")]
+    pub net_zero: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ShapeConfig {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(oneof = "ShapeKind", tags = "2, 3")]
+    pub kind: Option<ShapeKind>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum ShapeKind {
+    #[prost(message, tag = "2")]
+    Hann(Hann),
+    #[prost(message, tag = "3")]
+    Interp(Interp),
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Hann {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Interp {
+    #[prost(double, repeated, tag = "1")]
+    pub knots: Vec<f64>,
+    #[prost(double, repeated, tag = "2")]
+    pub controls: Vec<f64>,
+    #[prost(uint64, tag = "3")]
+    pub degree: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WaveformChunk {
+    #[prost(string, tag = "1")]
+    pub channel: String,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    #[prost(double, repeated, tag = "3")]
+    pub i: Vec<f64>,
+    #[prost(double, repeated, tag = "4")]
+    pub q: Vec<f64>,
+}
+
+/// Implementation of the `PulseGen` service.
+#[derive(Debug, Default)]
+pub struct PulseGenService;
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<WaveformChunk, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl PulseGen for PulseGenService {
+    type SampleStream = ChunkStream;
+
+    async fn sample(
+        &self,
+        request: Request<SampleRequest>,
+    ) -> Result<Response<Self::SampleStream>, Status> {
+        let request = request.into_inner();
+        let chunks = tokio::task::spawn_blocking(move || sample(&request))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+        let stream = tokio_stream::iter(chunks.into_iter().map(Ok));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Serve the `PulseGen` service on `addr` until the process is terminated.
+pub async fn serve(addr: SocketAddr) -> Result<()> {
+    Server::builder()
+        .add_service(PulseGenServer::new(PulseGenService))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+fn sample(request: &SampleRequest) -> Result<Vec<WaveformChunk>> {
+    let schedule = Arc::new(Element::from_msgpack(&request.schedule)?);
+    let channels = request
+        .channels
+        .iter()
+        .map(|c| {
+            Ok(ChannelSpec {
+                name: ChannelId::new(c.name.as_str()),
+                base_freq: Frequency::new(c.base_freq)?,
+                sample_rate: Frequency::new(c.sample_rate)?,
+                delay: Time::new(c.delay)?,
+                align_level: c.align_level,
+                baseline: Complex64::new(c.baseline_i, c.baseline_q),
+                ringdown: None,
+                net_zero: c.net_zero,
+                idle_tone: None,
+                length: c.length as usize,
+                is_real: c.is_real,
+                time_grid: None,
+                memory: None,
+                filters: Filters::default(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let shapes = request
+        .shapes
+        .iter()
+        .map(|s| {
+            let shape = match s.kind.as_ref().context("Shape kind is missing")? {
+                ShapeKind::Hann(_) => Shape::new_hann(),
+                ShapeKind::Interp(i) => {
+                    Shape::new_interp(i.knots.clone(), i.controls.clone(), i.degree as usize)?
+                }
+            };
+            Ok((ShapeId::new(s.name.as_str()), shape))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    let options = Options {
+        time_tolerance: Time::new(request.time_tolerance)?,
+        amp_tolerance: Amplitude::new(request.amp_tolerance)?,
+        allow_oversize: request.allow_oversize,
+        allow_truncate: request.allow_truncate,
+        fractional_delay: request.fractional_delay,
+        oversampling: request.oversampling as usize,
+        time_grid: None,
+    };
+    let (waveforms, _) = generate_waveforms(&schedule, &channels, &shapes, None, options)?;
+    let mut chunks = Vec::new();
+    for c in &request.channels {
+        let waveform = &waveforms[&ChannelId::new(c.name.as_str())];
+        let length = waveform.ncols();
+        let chunk_size = match request.chunk_size as usize {
+            0 => length.max(1),
+            n => n,
+        };
+        for offset in (0..length.max(1)).step_by(chunk_size) {
+            let end = (offset + chunk_size).min(length);
+            let row = |i: usize| waveform.slice(s![i, offset..end]).to_vec();
+            chunks.push(WaveformChunk {
+                channel: c.name.clone(),
+                offset: offset as u64,
+                i: row(0),
+                q: if c.is_real { Vec::new() } else { row(1) },
+            });
+        }
+    }
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::{ElementCommonBuilder, Play};
+
+    #[test]
+    fn sample_chunks() {
+        let play = Play::new(
+            ChannelId::new("xy"),
+            Some(ShapeId::new("hann")),
+            Amplitude::new(0.5).unwrap(),
+            Time::new(4e-9).unwrap(),
+        )
+        .unwrap();
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let schedule = Element::new(common, play).to_msgpack(None).unwrap();
+        let request = SampleRequest {
+            schedule,
+            channels: vec![ChannelConfig {
+                name: "xy".to_string(),
+                base_freq: 0.0,
+                sample_rate: 1e9,
+                delay: 0.0,
+                align_level: -10,
+                length: 10,
+                is_real: false,
+                baseline_i: 0.0,
+                baseline_q: 0.0,
+                net_zero: false,
+            }],
+            shapes: vec![ShapeConfig {
+                name: "hann".to_string(),
+                kind: Some(ShapeKind::Hann(Hann {})),
+            }],
+            time_tolerance: 1e-12,
+            amp_tolerance: 1e-5,
+            allow_oversize: false,
+            chunk_size: 4,
+            allow_truncate: false,
+            fractional_delay: false,
+            oversampling: 0,
+        };
+
+        let chunks = sample(&request).unwrap();
+
+        let offsets: Vec<_> = chunks.iter().map(|c| c.offset).collect();
+        let lengths: Vec<_> = chunks.iter().map(|c| c.i.len()).collect();
+        assert_eq!(offsets, [0, 4, 8]);
+        assert_eq!(lengths, [4, 4, 2]);
+        assert!(chunks[0].i.iter().any(|&v| v > 0.0));
+        assert!(chunks[1].i.iter().all(|&v| v == 0.0));
+        assert_eq!(chunks[0].q.len(), 4);
+    }
+}
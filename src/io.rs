@@ -0,0 +1,56 @@
+//! Import and export of schedules and sampled waveforms.
+//!
+//! Readers and writers are gated behind cargo features so that the default build doesn't
+//! depend on native libraries.
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "awg")]
+pub mod awg;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+#[cfg(feature = "npz")]
+pub mod npz;
+#[cfg(feature = "qiskit")]
+pub mod qiskit;
+pub mod resample;
+#[cfg(feature = "seqc")]
+pub mod seqc;
+// The Gantt chart is the HTML repr of python elements, so `python` enables
+// `svg`.
+#[cfg(feature = "svg")]
+pub mod svg;
+
+use ndarray::ArrayView2;
+use serde::Serialize;
+
+/// Metadata of an exported channel.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ChannelInfo {
+    /// Sample rate in Hz.
+    pub sample_rate: f64,
+    /// Delay of the channel in seconds.
+    pub delay: f64,
+    /// Time axis alignment granularity, see `Channel` in the python API.
+    pub align_level: i32,
+}
+
+/// Floating point precision of exported samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// 32-bit floats.
+    Single,
+    /// 64-bit floats.
+    #[default]
+    Double,
+}
+
+/// Sampled waveform of a channel with its metadata.
+///
+/// The shape of `data` is `(n, length)`, where `n` is 2 for complex waveform
+/// and 1 for real waveform.
+#[derive(Debug, Clone)]
+pub struct ChannelWaveform<'a> {
+    pub name: &'a str,
+    pub info: ChannelInfo,
+    pub data: ArrayView2<'a, f64>,
+}
@@ -0,0 +1,228 @@
+//! Arrow and Parquet output of flattened schedules.
+//!
+//! Each row of the table is an [`Instruction`]. Columns that don't apply to
+//! the instruction kind are null.
+use std::{fs::File, path::Path, sync::Arc};
+
+use anyhow::Result;
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+
+use crate::schedule::{Instruction, InstructionKind};
+
+#[derive(Debug, Default)]
+struct Columns<'a> {
+    kind: Vec<&'static str>,
+    channel_id: Vec<&'a str>,
+    channel_id2: Vec<Option<&'a str>>,
+    time: Vec<f64>,
+    duration: Vec<f64>,
+    shape_id: Vec<Option<&'a str>>,
+    amplitude: Vec<Option<f64>>,
+    width: Vec<Option<f64>>,
+    plateau: Vec<Option<f64>>,
+    fall_shape_id: Vec<Option<&'a str>>,
+    fall_width: Vec<Option<f64>>,
+    drag_coef: Vec<Option<f64>>,
+    frequency: Vec<Option<f64>>,
+    phase: Vec<Option<f64>>,
+    phase_reference: Vec<Option<&'static str>>,
+}
+
+impl<'a> Columns<'a> {
+    fn push(&mut self, instruction: &'a Instruction) {
+        let kind = &instruction.kind;
+        self.kind.push(kind.name());
+        self.time.push(instruction.time.value());
+        self.duration.push(instruction.duration.value());
+        let mut channel_id2 = None;
+        let mut shape_id = None;
+        let mut amplitude = None;
+        let mut width = None;
+        let mut plateau = None;
+        let mut fall_shape_id = None;
+        let mut fall_width = None;
+        let mut drag_coef = None;
+        let mut frequency = None;
+        let mut phase = None;
+        let mut phase_reference = None;
+        let channel_id = match kind {
+            InstructionKind::Play {
+                channel_id,
+                shape_id: s,
+                amplitude: a,
+                width: w,
+                plateau: p,
+                drag_coef: d,
+                frequency: f,
+                phase: ph,
+                phase_reference: r,
+                fall,
+            } => {
+                shape_id = s.as_ref().map(|s| s.as_ref());
+                if let Some(fall) = fall {
+                    fall_shape_id = fall.shape_id.as_ref().or(s.as_ref()).map(|s| s.as_ref());
+                    fall_width = Some(fall.width.value());
+                }
+                amplitude = Some(a.value());
+                width = Some(w.value());
+                plateau = Some(p.value());
+                drag_coef = Some(*d);
+                frequency = Some(f.value());
+                phase = Some(ph.value());
+                phase_reference = Some(r.name());
+                channel_id
+            }
+            InstructionKind::PlaySamples {
+                channel_id,
+                amplitude: a,
+                frequency: f,
+                phase: ph,
+                ..
+            } => {
+                amplitude = Some(a.value());
+                width = Some(instruction.duration.value());
+                plateau = Some(0.0);
+                frequency = Some(f.value());
+                phase = Some(ph.value());
+                channel_id
+            }
+            InstructionKind::ShiftPhase {
+                channel_id,
+                phase: ph,
+            }
+            | InstructionKind::SetPhase {
+                channel_id,
+                phase: ph,
+            } => {
+                phase = Some(ph.value());
+                channel_id
+            }
+            InstructionKind::ShiftFreq {
+                channel_id,
+                frequency: f,
+            }
+            | InstructionKind::SetFreq {
+                channel_id,
+                frequency: f,
+            } => {
+                frequency = Some(f.value());
+                channel_id
+            }
+            InstructionKind::SwapPhase {
+                channel_id1,
+                channel_id2: c2,
+            } => {
+                channel_id2 = Some(c2.as_ref());
+                channel_id1
+            }
+        };
+        self.channel_id.push(channel_id.as_ref());
+        self.channel_id2.push(channel_id2);
+        self.shape_id.push(shape_id);
+        self.amplitude.push(amplitude);
+        self.width.push(width);
+        self.plateau.push(plateau);
+        self.fall_shape_id.push(fall_shape_id);
+        self.fall_width.push(fall_width);
+        self.drag_coef.push(drag_coef);
+        self.frequency.push(frequency);
+        self.phase.push(phase);
+        self.phase_reference.push(phase_reference);
+    }
+}
+
+/// Convert instructions to an arrow record batch.
+pub(crate) fn to_record_batch(instructions: &[Instruction]) -> Result<RecordBatch> {
+    let mut columns = Columns::default();
+    for instruction in instructions {
+        columns.push(instruction);
+    }
+    let utf8 = |name, nullable| Field::new(name, DataType::Utf8, nullable);
+    let float = |name, nullable| Field::new(name, DataType::Float64, nullable);
+    let schema = Schema::new(vec![
+        utf8("kind", false),
+        utf8("channel_id", false),
+        utf8("channel_id2", true),
+        float("time", false),
+        float("duration", false),
+        utf8("shape_id", true),
+        float("amplitude", true),
+        float("width", true),
+        float("plateau", true),
+        utf8("fall_shape_id", true),
+        float("fall_width", true),
+        float("drag_coef", true),
+        float("frequency", true),
+        float("phase", true),
+        utf8("phase_reference", true),
+    ]);
+    let arrays: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(columns.kind)),
+        Arc::new(StringArray::from(columns.channel_id)),
+        Arc::new(StringArray::from(columns.channel_id2)),
+        Arc::new(Float64Array::from(columns.time)),
+        Arc::new(Float64Array::from(columns.duration)),
+        Arc::new(StringArray::from(columns.shape_id)),
+        Arc::new(Float64Array::from(columns.amplitude)),
+        Arc::new(Float64Array::from(columns.width)),
+        Arc::new(Float64Array::from(columns.plateau)),
+        Arc::new(StringArray::from(columns.fall_shape_id)),
+        Arc::new(Float64Array::from(columns.fall_width)),
+        Arc::new(Float64Array::from(columns.drag_coef)),
+        Arc::new(Float64Array::from(columns.frequency)),
+        Arc::new(Float64Array::from(columns.phase)),
+        Arc::new(StringArray::from(columns.phase_reference)),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(schema), arrays)?)
+}
+
+/// Write instructions into a new Parquet file, truncating any existing file.
+pub(crate) fn write_parquet(path: impl AsRef<Path>, instructions: &[Instruction]) -> Result<()> {
+    let batch = to_record_batch(instructions)?;
+    let file = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::Array;
+
+    use super::*;
+    use crate::quant::{ChannelId, Phase, Time};
+
+    #[test]
+    fn record_batch() {
+        let instructions = vec![
+            Instruction {
+                time: Time::new(1.0).unwrap(),
+                duration: Time::ZERO,
+                kind: InstructionKind::ShiftPhase {
+                    channel_id: ChannelId::new("q0"),
+                    phase: Phase::new(0.5).unwrap(),
+                },
+            },
+            Instruction {
+                time: Time::new(2.0).unwrap(),
+                duration: Time::ZERO,
+                kind: InstructionKind::SwapPhase {
+                    channel_id1: ChannelId::new("q0"),
+                    channel_id2: ChannelId::new("q1"),
+                },
+            },
+        ];
+
+        let batch = to_record_batch(&instructions).unwrap();
+
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 15);
+        let phase = batch.column_by_name("phase").unwrap();
+        assert_eq!(phase.null_count(), 1);
+        let channel_id2 = batch.column_by_name("channel_id2").unwrap();
+        assert!(channel_id2.is_null(0));
+    }
+}
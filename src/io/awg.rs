@@ -0,0 +1,285 @@
+//! Waveform file writers for arbitrary waveform generators.
+//!
+//! Supported formats:
+//!
+//! - [`AwgFormat::KeysightCsv`]: Text file with a `SampleRate` header, one
+//!   column per waveform row (`Y1`, `Y2`) and one column per marker
+//!   (`SampleMarker1`, `SampleMarker2`, ...).
+//! - [`AwgFormat::KeysightBin`]: 14-bit samples packed into little-endian
+//!   `i16` with the sample marker in bit 0 and the sync marker in bit 1, as
+//!   used by the M8190A.
+//! - [`AwgFormat::TektronixWfmx`]: XML header followed by little-endian `f32`
+//!   samples and one marker byte per sample, as used by the AWG70000 series.
+//!
+//! Binary formats only support real waveforms, i.e. a single waveform row.
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Result};
+use ndarray::{ArrayView1, ArrayView2};
+
+/// File format of the waveform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwgFormat {
+    KeysightCsv,
+    KeysightBin,
+    TektronixWfmx,
+}
+
+/// Write a sampled waveform with optional markers to a new file.
+///
+/// `waveform` has shape `(n, length)` and `markers` has shape
+/// `(n_markers, length)` with at most 8 markers. Sample values must be in
+/// the range \[-1, 1\].
+pub fn write_waveform(
+    path: impl AsRef<Path>,
+    format: AwgFormat,
+    waveform: ArrayView2<f64>,
+    markers: Option<ArrayView2<bool>>,
+    sample_rate: f64,
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_to(&mut writer, format, waveform, markers, sample_rate)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_to<W: Write>(
+    writer: &mut W,
+    format: AwgFormat,
+    waveform: ArrayView2<f64>,
+    markers: Option<ArrayView2<bool>>,
+    sample_rate: f64,
+) -> Result<()> {
+    let length = waveform.ncols();
+    if let Some(markers) = &markers {
+        if markers.nrows() > 8 {
+            bail!("At most 8 markers are supported, got {}", markers.nrows());
+        }
+        if markers.ncols() != length {
+            bail!(
+                "Marker length {} doesn't match waveform length {}",
+                markers.ncols(),
+                length
+            );
+        }
+    }
+    if waveform.iter().any(|v| !(-1.0..=1.0).contains(v)) {
+        bail!("Waveform values must be in the range [-1, 1]");
+    }
+    if format != AwgFormat::KeysightCsv && waveform.nrows() != 1 {
+        bail!("{:?} only supports real waveforms", format);
+    }
+    let n_markers = markers.as_ref().map_or(0, |m| m.nrows());
+    let marker_bits = |i: usize| -> u8 {
+        markers.as_ref().map_or(0, |m| {
+            m.column(i)
+                .iter()
+                .enumerate()
+                .fold(0, |bits, (j, &on)| bits | (u8::from(on) << j))
+        })
+    };
+    match format {
+        AwgFormat::KeysightCsv => {
+            writeln!(writer, "SampleRate = {}", sample_rate)?;
+            let header = (1..=waveform.nrows())
+                .map(|i| format!("Y{}", i))
+                .chain((1..=n_markers).map(|i| format!("SampleMarker{}", i)))
+                .collect::<Vec<_>>();
+            writeln!(writer, "{}", header.join(","))?;
+            for i in 0..length {
+                let row = waveform
+                    .column(i)
+                    .iter()
+                    .map(|v| v.to_string())
+                    .chain((0..n_markers).map(|j| ((marker_bits(i) >> j) & 1).to_string()))
+                    .collect::<Vec<_>>();
+                writeln!(writer, "{}", row.join(","))?;
+            }
+        }
+        AwgFormat::KeysightBin => {
+            if n_markers > 2 {
+                bail!("KeysightBin supports at most 2 markers");
+            }
+            for (i, v) in waveform.row(0).iter().enumerate() {
+                let sample = (v * 8191.0).round() as i16;
+                let word = (sample << 2) | i16::from(marker_bits(i));
+                writer.write_all(&word.to_le_bytes())?;
+            }
+        }
+        AwgFormat::TektronixWfmx => {
+            if n_markers > 4 {
+                bail!("TektronixWfmx supports at most 4 markers");
+            }
+            write_wfmx(writer, waveform.row(0), n_markers > 0, sample_rate)?;
+            if n_markers > 0 {
+                let bytes: Vec<_> = (0..length).map(marker_bits).collect();
+                writer.write_all(&bytes)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the WFMX header and the samples.
+fn write_wfmx<W: Write>(
+    writer: &mut W,
+    samples: ArrayView1<f64>,
+    markers_included: bool,
+    sample_rate: f64,
+) -> Result<()> {
+    // The header starts with the byte offset of the data as a fixed width
+    // number, so the length of the header doesn't depend on the offset.
+    const OFFSET_DIGITS: usize = 9;
+    let body = format!(
+        concat!(
+            r#"<DataSetsCollection xmlns="http://www.tektronix.com">"#,
+            r#"<DataSets version="1" xmlns="http://www.tektronix.com">"#,
+            "<DataDescription>",
+            "<NumberSamples>{}</NumberSamples>",
+            "<SamplesType>AWGWaveformSample</SamplesType>",
+            "<MarkersIncluded>{}</MarkersIncluded>",
+            "<NumberFormat>Single</NumberFormat>",
+            "<Endian>Little</Endian>",
+            "</DataDescription>",
+            r#"<ProductSpecific name="">"#,
+            r#"<ReccSamplingRate units="Hz">{}</ReccSamplingRate>"#,
+            "<ReccAmplitude>1</ReccAmplitude>",
+            "<ReccOffset>0</ReccOffset>",
+            "<SignalFormat>Real</SignalFormat>",
+            "</ProductSpecific>",
+            "</DataSets>",
+            "<Setup />",
+            "</DataSetsCollection>",
+        ),
+        samples.len(),
+        markers_included,
+        sample_rate,
+    );
+    let prefix_len = r#"<DataFile offset="" version="0.1">"#.len() + OFFSET_DIGITS;
+    let suffix = "</DataFile>";
+    let offset = prefix_len + body.len() + suffix.len();
+    write!(
+        writer,
+        r#"<DataFile offset="{:0width$}" version="0.1">{}{}"#,
+        offset,
+        body,
+        suffix,
+        width = OFFSET_DIGITS
+    )?;
+    for &v in samples {
+        writer.write_all(&(v as f32).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array2};
+
+    use super::*;
+
+    #[test]
+    fn keysight_csv() {
+        let waveform = array![[0.0, 0.5], [1.0, -1.0]];
+        let markers = array![[true, false]];
+        let mut buf = Vec::new();
+
+        write_to(
+            &mut buf,
+            AwgFormat::KeysightCsv,
+            waveform.view(),
+            Some(markers.view()),
+            1e9,
+        )
+        .unwrap();
+
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "SampleRate = 1000000000",
+                "Y1,Y2,SampleMarker1",
+                "0,1,1",
+                "0.5,-1,0"
+            ]
+        );
+    }
+
+    #[test]
+    fn keysight_bin() {
+        let waveform = array![[1.0, -1.0]];
+        let markers = array![[false, true], [true, true]];
+        let mut buf = Vec::new();
+
+        write_to(
+            &mut buf,
+            AwgFormat::KeysightBin,
+            waveform.view(),
+            Some(markers.view()),
+            1e9,
+        )
+        .unwrap();
+
+        let words: Vec<_> = buf
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(words, [(8191 << 2) | 2, (-8191 << 2) | 3]);
+    }
+
+    #[test]
+    fn tektronix_wfmx() {
+        let waveform = array![[0.25, -0.5, 1.0]];
+        let markers = array![[true, false, true]];
+        let mut buf = Vec::new();
+
+        write_to(
+            &mut buf,
+            AwgFormat::TektronixWfmx,
+            waveform.view(),
+            Some(markers.view()),
+            1e9,
+        )
+        .unwrap();
+
+        let offset: usize = std::str::from_utf8(&buf[18..27]).unwrap().parse().unwrap();
+        assert!(std::str::from_utf8(&buf[..offset])
+            .unwrap()
+            .ends_with("</DataFile>"));
+        assert_eq!(buf.len(), offset + 3 * 4 + 3);
+        assert_eq!(buf[offset..offset + 4], 0.25f32.to_le_bytes());
+        assert_eq!(buf[offset + 12..], [1, 0, 1]);
+    }
+
+    #[test]
+    fn too_many_markers() {
+        let waveform = array![[0.0]];
+        let markers = Array2::from_elem((9, 1), true);
+        let mut buf = Vec::new();
+
+        let result = write_to(
+            &mut buf,
+            AwgFormat::KeysightCsv,
+            waveform.view(),
+            Some(markers.view()),
+            1e9,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn out_of_range() {
+        let waveform = array![[1.5]];
+        let mut buf = Vec::new();
+
+        let result = write_to(&mut buf, AwgFormat::KeysightCsv, waveform.view(), None, 1e9);
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! HDF5 waveform writer.
+//!
+//! Layout of the file:
+//!
+//! - `/waveforms/<channel>`: One `f64` or `f32` dataset of shape
+//!   `(n, length)` per channel with attributes `sample_rate`, `delay`, and
+//!   `align_level`.
+//! - `/` attribute `schedule`: JSON document of the schedule if provided.
+use std::path::Path;
+
+use anyhow::Result;
+use hdf5::{types::VarLenUnicode, File, Location};
+
+use super::{ChannelWaveform, Precision};
+
+/// Write waveforms into a new HDF5 file, truncating any existing file.
+///
+/// `provenance` is stored as a string attribute of the root group, usually the
+/// JSON serialized schedule that produced the waveforms.
+pub fn write_waveforms(
+    path: impl AsRef<Path>,
+    waveforms: &[ChannelWaveform],
+    provenance: Option<&str>,
+    precision: Precision,
+) -> Result<()> {
+    let file = File::create(path)?;
+    let group = file.create_group("waveforms")?;
+    for w in waveforms {
+        let builder = group.new_dataset_builder();
+        let dataset = match precision {
+            Precision::Single => builder
+                .with_data(&w.data.mapv(|x| x as f32))
+                .create(w.name)?,
+            Precision::Double => builder.with_data(w.data).create(w.name)?,
+        };
+        write_attr(&dataset, "sample_rate", w.info.sample_rate)?;
+        write_attr(&dataset, "delay", w.info.delay)?;
+        write_attr(&dataset, "align_level", w.info.align_level)?;
+    }
+    if let Some(provenance) = provenance {
+        let value: VarLenUnicode = provenance.parse()?;
+        write_attr(&file, "schedule", value)?;
+    }
+    Ok(())
+}
+
+fn write_attr<T: hdf5::H5Type>(location: &Location, name: &str, value: T) -> Result<()> {
+    location
+        .new_attr::<T>()
+        .create(name)?
+        .write_scalar(&value)?;
+    Ok(())
+}
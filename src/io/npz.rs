@@ -0,0 +1,157 @@
+//! NPZ waveform writer.
+//!
+//! The archive contains one complex128 or complex64 array per channel named
+//! after the
+//! channel, and a `metadata.json` member with the [`ChannelInfo`] of each
+//! channel. It can be loaded with `numpy.load`.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use hashbrown::HashMap;
+use num::complex::Complex64;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use super::{ChannelInfo, Precision};
+
+/// Write waveforms into a new `.npz` archive, truncating any existing file.
+pub fn write_waveforms(
+    path: impl AsRef<Path>,
+    waveforms: &HashMap<String, Vec<Complex64>>,
+    metadata: &HashMap<String, ChannelInfo>,
+    precision: Precision,
+) -> Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    write_npz(file, waveforms, metadata, precision)?;
+    Ok(())
+}
+
+fn write_npz<W: Write + Seek>(
+    writer: W,
+    waveforms: &HashMap<String, Vec<Complex64>>,
+    metadata: &HashMap<String, ChannelInfo>,
+    precision: Precision,
+) -> Result<W> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(writer);
+    let mut names: Vec<_> = waveforms.keys().collect();
+    names.sort();
+    for name in names {
+        zip.start_file(format!("{}.npy", name), options)?;
+        write_npy(&mut zip, &waveforms[name], precision)?;
+    }
+    let metadata: BTreeMap<_, _> = metadata.iter().collect();
+    zip.start_file("metadata.json", options)?;
+    serde_json::to_writer(&mut zip, &metadata)?;
+    Ok(zip.finish()?)
+}
+
+/// Write a 1-D complex array in `.npy` format version 1.0.
+fn write_npy<W: Write>(
+    writer: &mut W,
+    data: &[Complex64],
+    precision: Precision,
+) -> std::io::Result<()> {
+    const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+    const ALIGN: usize = 64;
+    let descr = match precision {
+        Precision::Single => "<c8",
+        Precision::Double => "<c16",
+    };
+    let dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({},), }}",
+        descr,
+        data.len()
+    );
+    // Header is padded with spaces and terminated by a newline so that the
+    // data starts at an aligned offset.
+    let unpadded = MAGIC.len() + 2 + dict.len() + 1;
+    let header_len = dict.len() + 1 + (ALIGN - unpadded % ALIGN) % ALIGN;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(header_len as u16).to_le_bytes())?;
+    write!(writer, "{:<width$}", dict, width = header_len - 1)?;
+    writer.write_all(b"\n")?;
+    for x in data {
+        match precision {
+            Precision::Single => {
+                writer.write_all(&(x.re as f32).to_le_bytes())?;
+                writer.write_all(&(x.im as f32).to_le_bytes())?;
+            }
+            Precision::Double => {
+                writer.write_all(&x.re.to_le_bytes())?;
+                writer.write_all(&x.im.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    #[test]
+    fn npy_layout() {
+        let data = [Complex64::new(1.0, -2.0), Complex64::new(0.5, 0.0)];
+        let mut buf = Vec::new();
+
+        write_npy(&mut buf, &data, Precision::Double).unwrap();
+
+        let header_len = u16::from_le_bytes([buf[8], buf[9]]) as usize;
+        let data_start = 10 + header_len;
+        assert_eq!(data_start % 64, 0);
+        assert_eq!(buf[data_start - 1], b'\n');
+        assert!(std::str::from_utf8(&buf[10..data_start])
+            .unwrap()
+            .contains("'shape': (2,)"));
+        assert_eq!(buf.len(), data_start + 2 * 16);
+        assert_eq!(buf[data_start..data_start + 8], 1.0f64.to_le_bytes());
+        assert_eq!(
+            buf[data_start + 8..data_start + 16],
+            (-2.0f64).to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn npz_members() {
+        let waveforms = HashMap::from([
+            ("q1".to_string(), vec![Complex64::new(1.0, 0.0)]),
+            ("q0".to_string(), vec![]),
+        ]);
+        let info = ChannelInfo {
+            sample_rate: 2e9,
+            delay: 0.0,
+            align_level: -10,
+        };
+        let metadata = HashMap::from([("q0".to_string(), info), ("q1".to_string(), info)]);
+
+        let buf = write_npz(
+            Cursor::new(Vec::new()),
+            &waveforms,
+            &metadata,
+            Precision::Single,
+        )
+        .unwrap();
+        let mut archive = ZipArchive::new(buf).unwrap();
+
+        let names: Vec<_> = archive.file_names().collect();
+        assert_eq!(names.len(), 3);
+        let mut json = String::new();
+        archive
+            .by_name("metadata.json")
+            .unwrap()
+            .read_to_string(&mut json)
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["q1"]["align_level"], -10);
+        assert!(archive.by_name("q0.npy").is_ok());
+    }
+}
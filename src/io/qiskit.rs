@@ -0,0 +1,466 @@
+//! Importer of Qiskit pulse schedules.
+//!
+//! The input is the pulse Qobj JSON form of a single experiment: a document
+//! with the sample interval `dt`, an optional `pulse_library`, and a list of
+//! `instructions` whose `t0` and durations are in units of `dt`. Instructions
+//! are placed in an [`Absolute`] container at `t0 * dt`.
+//!
+//! Conversions:
+//!
+//! - Parametric `gaussian`, `drag`, and `gaussian_square` pulses use lifted
+//!   gaussian [`Interp`](crate::shape::Shape) shapes. `constant` pulses use
+//!   rectangular envelopes. Complex amplitudes are split into magnitude and
+//!   phase.
+//! - Sampled pulses from the library are split into real and imaginary parts,
+//!   each played with its own shape. The imaginary part has an additional
+//!   quarter-turn phase.
+//! - Phases in radians are converted to cycles, frequencies in GHz to Hz.
+//! - `setf` sets the frequency relative to the channel base frequency, so the
+//!   channels should be created with zero base frequency to get the absolute
+//!   frequencies of Qiskit.
+//! - `delay`, `acquire` and other instructions without waveforms only extend
+//!   the duration of the schedule.
+use std::{f64::consts::TAU, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use hashbrown::HashMap;
+use num::complex::Complex64;
+use serde::Deserialize;
+
+use crate::{
+    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    schedule::{
+        Absolute, AbsoluteEntry, Element, ElementCommonBuilder, ElementVariant, Play, SetFreq,
+        SetPhase, ShiftFreq, ShiftPhase,
+    },
+};
+
+/// Number of points used to interpolate parametric shapes.
+const N_INTERP_POINTS: usize = 257;
+
+/// Parameters of an interpolated shape created by the importer.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct InterpParams {
+    pub(crate) knots: Vec<f64>,
+    pub(crate) controls: Vec<f64>,
+    pub(crate) degree: usize,
+}
+
+/// Result of importing a Qiskit schedule.
+#[derive(Debug, Clone)]
+pub(crate) struct Imported {
+    pub(crate) schedule: Element,
+    pub(crate) shapes: Vec<(ShapeId, InterpParams)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QobjSchedule {
+    dt: f64,
+    #[serde(default)]
+    pulse_library: Vec<LibraryPulse>,
+    instructions: Vec<QobjInstruction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibraryPulse {
+    name: String,
+    samples: Vec<ComplexValue>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+enum ComplexValue {
+    Real(f64),
+    Complex([f64; 2]),
+}
+
+#[derive(Debug, Deserialize)]
+struct QobjInstruction {
+    name: String,
+    t0: u64,
+    ch: Option<String>,
+    phase: Option<f64>,
+    frequency: Option<f64>,
+    duration: Option<u64>,
+    pulse_shape: Option<String>,
+    parameters: Option<PulseParameters>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PulseParameters {
+    duration: u64,
+    amp: ComplexValue,
+    #[serde(default)]
+    angle: f64,
+    sigma: Option<f64>,
+    width: Option<f64>,
+    beta: Option<f64>,
+}
+
+#[derive(Debug)]
+struct Importer {
+    dt: f64,
+    library: HashMap<String, Vec<Complex64>>,
+    shapes: Vec<(ShapeId, InterpParams)>,
+    shape_ids: HashMap<String, ShapeId>,
+    entries: Vec<AbsoluteEntry>,
+    end: u64,
+}
+
+struct PlayArgs {
+    shape_id: Option<ShapeId>,
+    amplitude: f64,
+    width: u64,
+    plateau: u64,
+    drag_coef: f64,
+    phase: f64,
+}
+
+/// Import a Qiskit pulse Qobj experiment from a JSON string.
+pub(crate) fn import_qobj(s: &str) -> Result<Imported> {
+    let qobj: QobjSchedule = serde_json::from_str(s)?;
+    if !(qobj.dt.is_finite() && qobj.dt > 0.0) {
+        bail!("Invalid dt {}", qobj.dt);
+    }
+    let mut importer = Importer {
+        dt: qobj.dt,
+        library: qobj
+            .pulse_library
+            .into_iter()
+            .map(|p| (p.name, p.samples.into_iter().map(Complex64::from).collect()))
+            .collect(),
+        shapes: Vec::new(),
+        shape_ids: HashMap::new(),
+        entries: Vec::new(),
+        end: 0,
+    };
+    for instruction in &qobj.instructions {
+        importer.add_instruction(instruction)?;
+    }
+    importer.finish()
+}
+
+impl Importer {
+    fn add_instruction(&mut self, inst: &QobjInstruction) -> Result<()> {
+        let t0 = inst.t0;
+        let channel = || -> Result<ChannelId> {
+            let ch = inst
+                .ch
+                .as_deref()
+                .ok_or_else(|| anyhow!("Missing channel of instruction '{}'", inst.name))?;
+            Ok(ChannelId::new(ch))
+        };
+        let phase = || -> Result<Phase> {
+            let phase = inst
+                .phase
+                .ok_or_else(|| anyhow!("Missing phase of instruction '{}'", inst.name))?;
+            Ok(Phase::from_radians(phase)?)
+        };
+        let frequency = || -> Result<Frequency> {
+            let frequency = inst
+                .frequency
+                .ok_or_else(|| anyhow!("Missing frequency of instruction '{}'", inst.name))?;
+            Ok(Frequency::new(frequency * 1e9)?)
+        };
+        match inst.name.as_str() {
+            "parametric_pulse" => self.add_parametric(inst, channel()?)?,
+            "fc" => self.push(t0, ShiftPhase::new(channel()?, phase()?)?)?,
+            "setp" => self.push(t0, SetPhase::new(channel()?, phase()?)?)?,
+            "shiftf" => self.push(t0, ShiftFreq::new(channel()?, frequency()?)?)?,
+            "setf" => self.push(t0, SetFreq::new(channel()?, frequency()?)?)?,
+            name => {
+                if let Some(samples) = self.library.get(name).cloned() {
+                    self.add_sampled(name, t0, channel()?, &samples)?;
+                } else {
+                    // Instructions without waveform output, e.g. delay and
+                    // acquire.
+                    self.extend_end(t0 + inst.duration.unwrap_or(0));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_parametric(&mut self, inst: &QobjInstruction, channel_id: ChannelId) -> Result<()> {
+        let pulse_shape = inst
+            .pulse_shape
+            .as_deref()
+            .ok_or_else(|| anyhow!("Missing pulse_shape of parametric pulse"))?;
+        let p = inst
+            .parameters
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing parameters of parametric pulse"))?;
+        let amp = Complex64::from(p.amp) * Complex64::from_polar(1.0, p.angle);
+        let sigma = || {
+            p.sigma
+                .ok_or_else(|| anyhow!("Missing sigma of {}", pulse_shape))
+        };
+        let (shape_id, width, plateau, drag_coef) = match pulse_shape {
+            "constant" => (None, p.duration, 0, 0.0),
+            "gaussian" | "drag" => {
+                let shape_id = self.gaussian_shape(sigma()? / p.duration as f64)?;
+                let beta = if pulse_shape == "drag" {
+                    p.beta.ok_or_else(|| anyhow!("Missing beta of drag"))?
+                } else {
+                    0.0
+                };
+                (Some(shape_id), p.duration, 0, beta * self.dt)
+            }
+            "gaussian_square" => {
+                let flat = p.width.ok_or_else(|| anyhow!("Missing width"))?;
+                if !(0.0..=p.duration as f64).contains(&flat) || flat.fract() != 0.0 {
+                    bail!("Invalid width {} of gaussian_square", flat);
+                }
+                let flat = flat as u64;
+                let rise = p.duration - flat;
+                let shape_id = self.gaussian_shape(sigma()? / rise as f64)?;
+                (Some(shape_id), rise, flat, 0.0)
+            }
+            _ => bail!("Unsupported parametric pulse shape '{}'", pulse_shape),
+        };
+        self.push_play(
+            inst.t0,
+            channel_id,
+            PlayArgs {
+                shape_id,
+                amplitude: amp.norm(),
+                width,
+                plateau,
+                drag_coef,
+                phase: amp.arg() / TAU,
+            },
+        )
+    }
+
+    fn add_sampled(
+        &mut self,
+        name: &str,
+        t0: u64,
+        channel_id: ChannelId,
+        samples: &[Complex64],
+    ) -> Result<()> {
+        let n = samples.len() as u64;
+        let parts = [
+            ("re", samples.iter().map(|s| s.re).collect::<Vec<_>>(), 0.0),
+            ("im", samples.iter().map(|s| s.im).collect(), 0.25),
+        ];
+        for (suffix, values, phase) in parts {
+            let amplitude = values.iter().fold(0.0, |m: f64, v| m.max(v.abs()));
+            if amplitude == 0.0 {
+                continue;
+            }
+            let key = format!("{}_{}", name, suffix);
+            let shape_id = match self.shape_ids.get(&key) {
+                Some(id) => id.clone(),
+                None => {
+                    let normalized: Vec<_> = values.iter().map(|v| v / amplitude).collect();
+                    self.add_shape(key, sampled_shape(&normalized))
+                }
+            };
+            self.push_play(
+                t0,
+                channel_id,
+                PlayArgs {
+                    shape_id: Some(shape_id),
+                    amplitude,
+                    width: n,
+                    plateau: 0,
+                    drag_coef: 0.0,
+                    phase,
+                },
+            )?;
+        }
+        self.extend_end(t0 + n);
+        Ok(())
+    }
+
+    fn gaussian_shape(&mut self, sigma: f64) -> Result<ShapeId> {
+        if !(sigma.is_finite() && sigma > 0.0) {
+            bail!("Invalid relative sigma {}", sigma);
+        }
+        let key = format!("gaussian_{}", sigma);
+        if let Some(id) = self.shape_ids.get(&key) {
+            return Ok(id.clone());
+        }
+        Ok(self.add_shape(key, lifted_gaussian_shape(sigma)))
+    }
+
+    fn add_shape(&mut self, key: String, params: InterpParams) -> ShapeId {
+        let id = ShapeId::new(format!("qiskit_{}", self.shapes.len()));
+        self.shapes.push((id.clone(), params));
+        self.shape_ids.insert(key, id.clone());
+        id
+    }
+
+    fn push_play(&mut self, t0: u64, channel_id: ChannelId, args: PlayArgs) -> Result<()> {
+        let play = Play::new(
+            channel_id,
+            args.shape_id,
+            Amplitude::new(args.amplitude)?,
+            self.time(args.width)?,
+        )?
+        .with_plateau(self.time(args.plateau)?)?
+        .with_drag_coef(args.drag_coef)?
+        .with_phase(Phase::new(args.phase)?)?;
+        self.extend_end(t0 + args.width + args.plateau);
+        self.push(t0, play)
+    }
+
+    fn push(&mut self, t0: u64, variant: impl Into<ElementVariant>) -> Result<()> {
+        let common = ElementCommonBuilder::new().build()?;
+        let element = Arc::new(Element::new(common, variant));
+        self.entries
+            .push(AbsoluteEntry::new(element).with_time(self.time(t0)?)?);
+        self.extend_end(t0);
+        Ok(())
+    }
+
+    fn extend_end(&mut self, end: u64) {
+        self.end = self.end.max(end);
+    }
+
+    fn time(&self, samples: u64) -> Result<Time> {
+        Ok(Time::new(samples as f64 * self.dt)?)
+    }
+
+    fn finish(self) -> Result<Imported> {
+        let common = ElementCommonBuilder::new()
+            .duration(Some(self.time(self.end)?))
+            .build()?;
+        let schedule = Element::new(common, Absolute::new().with_children(self.entries));
+        Ok(Imported {
+            schedule,
+            shapes: self.shapes,
+        })
+    }
+}
+
+impl From<ComplexValue> for Complex64 {
+    fn from(value: ComplexValue) -> Self {
+        match value {
+            ComplexValue::Real(re) => Complex64::new(re, 0.0),
+            ComplexValue::Complex([re, im]) => Complex64::new(re, im),
+        }
+    }
+}
+
+/// Gaussian lifted to zero at the edges and normalized to one at the center.
+///
+/// `sigma` is relative to the width of the shape.
+fn lifted_gaussian_shape(sigma: f64) -> InterpParams {
+    let gaussian = |x: f64| (-0.5 * (x / sigma).powi(2)).exp();
+    let edge = gaussian(0.5);
+    let xs: Vec<_> = (0..N_INTERP_POINTS)
+        .map(|i| i as f64 / (N_INTERP_POINTS - 1) as f64 - 0.5)
+        .collect();
+    let ys = xs.iter().map(|&x| (gaussian(x) - edge) / (1.0 - edge));
+    linear_interp(xs.clone(), ys.collect())
+}
+
+/// Piecewise linear shape through the centers of the samples.
+fn sampled_shape(values: &[f64]) -> InterpParams {
+    let n = values.len() as f64;
+    let centers = (0..values.len()).map(|i| (i as f64 + 0.5) / n - 0.5);
+    let xs = [-0.5].into_iter().chain(centers).chain([0.5]).collect();
+    let first = values.first().copied().unwrap_or_default();
+    let last = values.last().copied().unwrap_or_default();
+    let ys = [first]
+        .into_iter()
+        .chain(values.iter().copied())
+        .chain([last])
+        .collect();
+    linear_interp(xs, ys)
+}
+
+fn linear_interp(xs: Vec<f64>, ys: Vec<f64>) -> InterpParams {
+    let first = xs[0];
+    let last = xs[xs.len() - 1];
+    let knots = [first].into_iter().chain(xs).chain([last]).collect();
+    InterpParams {
+        knots,
+        controls: ys,
+        degree: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::assert_approx_eq;
+
+    use super::*;
+    use crate::schedule::{flatten, InstructionKind};
+
+    #[test]
+    fn import_schedule() {
+        let json = r#"{
+            "dt": 1e-9,
+            "pulse_library": [{"name": "wf", "samples": [[0.0, 0.0], [0.5, 0.0], [0.0, 0.0]]}],
+            "instructions": [
+                {"name": "parametric_pulse", "t0": 0, "ch": "d0", "pulse_shape": "drag",
+                 "parameters": {"duration": 160, "amp": [0.0, 0.2], "sigma": 40, "beta": 2.0}},
+                {"name": "fc", "t0": 160, "ch": "d0", "phase": 3.141592653589793},
+                {"name": "wf", "t0": 160, "ch": "d1"},
+                {"name": "parametric_pulse", "t0": 200, "ch": "d0", "pulse_shape": "gaussian_square",
+                 "parameters": {"duration": 100, "amp": 0.3, "sigma": 10, "width": 60}},
+                {"name": "acquire", "t0": 300, "duration": 50, "qubits": [0], "memory_slot": [0]}
+            ]
+        }"#;
+
+        let imported = import_qobj(json).unwrap();
+
+        // Both gaussian pulses have the same relative sigma.
+        assert_eq!(imported.shapes.len(), 2);
+        assert_approx_eq!(
+            f64,
+            imported.schedule.common.duration().unwrap().value(),
+            350e-9
+        );
+        let instructions = flatten(&Arc::new(imported.schedule));
+        assert_eq!(instructions.len(), 4);
+        let InstructionKind::Play {
+            amplitude,
+            drag_coef,
+            phase,
+            ..
+        } = &instructions[0].kind
+        else {
+            panic!("Expected Play instruction");
+        };
+        assert_approx_eq!(f64, amplitude.value(), 0.2);
+        assert_approx_eq!(f64, *drag_coef, 2e-9);
+        assert_approx_eq!(f64, phase.value(), 0.25);
+        let InstructionKind::ShiftPhase { phase, .. } = &instructions[1].kind else {
+            panic!("Expected ShiftPhase instruction");
+        };
+        assert_approx_eq!(f64, phase.value(), 0.5);
+        let InstructionKind::Play { width, plateau, .. } = &instructions[3].kind else {
+            panic!("Expected Play instruction");
+        };
+        assert_approx_eq!(f64, width.value(), 40e-9);
+        assert_approx_eq!(f64, plateau.value(), 60e-9);
+    }
+
+    #[test]
+    fn lifted_gaussian() {
+        let params = lifted_gaussian_shape(0.25);
+        let shape =
+            crate::shape::Shape::new_interp(params.knots, params.controls, params.degree).unwrap();
+        let mut y = [0.0; 3];
+
+        shape.sample_array(-0.5, 0.5, &mut y);
+
+        assert_approx_eq!(f64, y[0], 0.0, epsilon = 1e-12);
+        assert_approx_eq!(f64, y[1], 1.0, epsilon = 1e-12);
+        assert_approx_eq!(f64, y[2], 0.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn unsupported_shape() {
+        let json = r#"{"dt": 1e-9, "instructions": [
+            {"name": "parametric_pulse", "t0": 0, "ch": "d0", "pulse_shape": "sech",
+             "parameters": {"duration": 10, "amp": 0.1}}
+        ]}"#;
+
+        assert!(import_qobj(json).is_err());
+    }
+}
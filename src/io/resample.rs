@@ -0,0 +1,177 @@
+//! Rational sample rate conversion of sampled waveforms.
+//!
+//! The waveform is upsampled by `up`, filtered with a Kaiser windowed sinc
+//! low-pass at the lower of the two Nyquist frequencies, and downsampled by
+//! `down`. The filter is evaluated in polyphase form, so only the kept output
+//! samples are computed.
+//!
+//! The filter is symmetric and centered, so the conversion adds no delay:
+//! sample `n` of the result is at time `n / target_rate` like sample `k` of
+//! the input is at `k / sample_rate`. Samples outside of the waveform are
+//! taken as zero.
+use anyhow::{bail, Result};
+use ndarray::{Array2, ArrayView2};
+
+/// Zero crossings of the sinc on each side of the center.
+const ZERO_CROSSINGS: usize = 16;
+
+/// Shape parameter of the Kaiser window, about 80 dB stopband attenuation.
+const BETA: f64 = 8.0;
+
+/// Largest allowed `up` or `down` factor.
+const MAX_FACTOR: usize = 1000;
+
+/// Upsampling and downsampling factors from `sample_rate` to `target_rate`.
+///
+/// Fails if the ratio of the rates is not a fraction with both terms not
+/// larger than 1000.
+pub fn ratio(sample_rate: f64, target_rate: f64) -> Result<(usize, usize)> {
+    if !(sample_rate.is_finite() && sample_rate > 0.0) {
+        bail!("Invalid sample rate {}", sample_rate);
+    }
+    if !(target_rate.is_finite() && target_rate > 0.0) {
+        bail!("Invalid target sample rate {}", target_rate);
+    }
+    let x = target_rate / sample_rate;
+    // Convergents of the continued fraction of `x`.
+    let (mut p0, mut q0, mut p1, mut q1) = (0, 1, 1, 0);
+    let mut rest = x;
+    loop {
+        let a = rest.floor();
+        let p = a as usize * p1 + p0;
+        let q = a as usize * q1 + q0;
+        if p > MAX_FACTOR || q > MAX_FACTOR {
+            bail!(
+                "Ratio of sample rates {} and {} is not a small fraction",
+                sample_rate,
+                target_rate
+            );
+        }
+        if ((p as f64 / q as f64) - x).abs() <= 1e-12 * x {
+            return Ok((p, q));
+        }
+        (p0, q0, p1, q1) = (p1, q1, p, q);
+        rest = 1.0 / (rest - a);
+    }
+}
+
+/// Number of samples of a waveform of `length` samples after resampling by
+/// `up / down`.
+pub fn resampled_length(length: usize, up: usize, down: usize) -> usize {
+    (length * up).div_ceil(down)
+}
+
+/// Resample each row of `data` from `sample_rate` to `target_rate`.
+pub fn resample(data: ArrayView2<f64>, sample_rate: f64, target_rate: f64) -> Result<Array2<f64>> {
+    let (up, down) = ratio(sample_rate, target_rate)?;
+    if up == down {
+        return Ok(data.to_owned());
+    }
+    let taps = taps(up, down);
+    let half = (taps.len() - 1) / 2;
+    let length = resampled_length(data.ncols(), up, down);
+    let mut result = Array2::zeros((data.nrows(), length));
+    for (row, mut out) in data.rows().into_iter().zip(result.rows_mut()) {
+        for (n, y) in out.iter_mut().enumerate() {
+            // Input sample `k` is at `k * up` on the upsampled grid and output
+            // sample `n` at `n * down`.
+            let center = n * down;
+            let first = (center.saturating_sub(half)).div_ceil(up);
+            let last = ((center + half) / up).min(row.len().saturating_sub(1));
+            *y = (first..=last)
+                .map(|k| row[k] * taps[half + k * up - center])
+                .sum();
+        }
+    }
+    Ok(result)
+}
+
+/// Low-pass taps on the upsampled grid, normalized to a DC gain of `up`.
+fn taps(up: usize, down: usize) -> Vec<f64> {
+    let factor = up.max(down);
+    let half = ZERO_CROSSINGS * factor;
+    let mut taps: Vec<_> = (0..=2 * half)
+        .map(|k| {
+            let x = (k as f64 - half as f64) / factor as f64;
+            let r = (k as f64 - half as f64) / half as f64;
+            sinc(x) * kaiser(r)
+        })
+        .collect();
+    // Normalize each polyphase branch separately, so a constant input stays
+    // constant for every output phase.
+    for phase in 0..up {
+        let sum: f64 = taps.iter().skip(phase).step_by(up).sum();
+        taps.iter_mut()
+            .skip(phase)
+            .step_by(up)
+            .for_each(|w| *w /= sum);
+    }
+    taps
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+/// Kaiser window at `r` in \[-1, 1\].
+fn kaiser(r: f64) -> f64 {
+    bessel_i0(BETA * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(BETA)
+}
+
+/// Modified Bessel function of the first kind of order zero.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let y = x * x / 4.0;
+    for k in 1.. {
+        term *= y / (k * k) as f64;
+        sum += term;
+        if term < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array1;
+
+    use super::*;
+
+    #[test]
+    fn rate_ratio() {
+        assert_eq!(ratio(2.4e9, 2e9).unwrap(), (5, 6));
+        assert_eq!(ratio(1e9, 2e9).unwrap(), (2, 1));
+        assert_eq!(ratio(1e9, 1e9).unwrap(), (1, 1));
+        assert!(ratio(1e9, 1e9 + 1.0).is_err());
+        assert!(ratio(0.0, 1e9).is_err());
+    }
+
+    #[test]
+    fn tone() {
+        let (sample_rate, target_rate) = (2.4e9, 2e9);
+        let freq = 100e6;
+        let length = 600;
+        let signal = |rate: f64, n: usize| {
+            Array1::from_iter(
+                (0..n).map(|k| (std::f64::consts::TAU * freq * k as f64 / rate).cos()),
+            )
+        };
+        let data = signal(sample_rate, length).insert_axis(ndarray::Axis(0));
+
+        let resampled = resample(data.view(), sample_rate, target_rate).unwrap();
+
+        assert_eq!(resampled.ncols(), 500);
+        let expected = signal(target_rate, 500);
+        // The tone keeps its phase away from the edges.
+        for n in 50..450 {
+            assert!((resampled[(0, n)] - expected[n]).abs() < 1e-3);
+        }
+    }
+}
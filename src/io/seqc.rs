@@ -0,0 +1,653 @@
+//! Zurich Instruments SeqC code generation.
+//!
+//! The instructions of a single channel are segmented into a playlist: each
+//! distinct play becomes a baseband waveform, and each play is issued as a
+//! command table entry that also applies the phase increments accumulated from
+//! preceding [`ShiftPhase`](InstructionKind::ShiftPhase) instructions. Gaps
+//! between plays are filled with `playZero`, and [`Segment::Loop`] becomes a
+//! `repeat` block. Loop counts bound to a runtime variable are read from a
+//! user register.
+//!
+//! The carrier frequency is expected to be provided by the oscillator of the
+//! instrument, so the base frequency of the channel is ignored. Frequency and
+//! phase of the plays are baked into the waveforms.
+//!
+//! Wave and file names are derived from the channel id. Characters that are
+//! not allowed in SeqC identifiers are escaped as `_` followed by the hex code
+//! of each byte, and `_` itself becomes `__`, so different channels never share
+//! a name and the files stay inside the output directory.
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{bail, Result};
+use hashbrown::HashMap;
+use ndarray::Array2;
+use num::complex::Complex64;
+use serde_json::json;
+
+use crate::{
+    pulse::{Envelope, PulseListBuilder, PushArgs, Sampler},
+    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    schedule::{InstructionKind, PhaseReference, Segment},
+    shape::Shape,
+};
+
+/// Target instrument of the generated program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Device {
+    Hdawg,
+    Shfsg,
+}
+
+/// Generated sequencer program of a channel.
+#[derive(Debug, Clone)]
+pub(crate) struct SeqcProgram {
+    pub(crate) source: String,
+    pub(crate) command_table: String,
+    /// Waveforms with shape `(2, length)` keyed by wave file name.
+    pub(crate) waves: Vec<(String, Array2<f64>)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SeqcGenerator<'a> {
+    name: &'a str,
+    device: Device,
+    sample_rate: Frequency,
+    shapes: &'a HashMap<ShapeId, Shape>,
+    time_tolerance: Time,
+}
+
+/// Waveform of a play without the timing information.
+#[derive(Debug, Clone, PartialEq)]
+struct WaveKey<'a> {
+    shape_id: Option<&'a ShapeId>,
+    amplitude: Amplitude,
+    width: Time,
+    plateau: Time,
+    drag_coef: f64,
+    frequency: Frequency,
+    phase: Phase,
+}
+
+/// Waveforms, command table entries, and count variables collected while
+/// emitting code.
+#[derive(Debug, Default)]
+struct Program<'a> {
+    waves: Vec<WaveKey<'a>>,
+    entries: Vec<EntryKey>,
+    variables: Vec<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct EntryKey {
+    wave: Option<usize>,
+    phase_increment: Phase,
+}
+
+/// Minimum waveform length in samples.
+const MIN_LENGTH: usize = 32;
+/// Waveform length and start time granularity in samples.
+const GRANULARITY: usize = 16;
+
+impl<'a> SeqcGenerator<'a> {
+    pub(crate) fn new(
+        name: &'a str,
+        device: Device,
+        sample_rate: Frequency,
+        shapes: &'a HashMap<ShapeId, Shape>,
+        time_tolerance: Time,
+    ) -> Self {
+        Self {
+            name,
+            device,
+            sample_rate,
+            shapes,
+            time_tolerance,
+        }
+    }
+
+    /// Generate the program for instructions of `channel_id`.
+    ///
+    /// Plays must not overlap and must start on the 16-sample grid. Phase,
+    /// frequency and swap instructions other than phase shifts are not
+    /// supported. Loops become `repeat` blocks, so their start and period must
+    /// be on the grid as well. The count variables of loops are assigned to
+    /// user registers in order of appearance, and their runtime values must be
+    /// between 1 and the maximum count.
+    pub(crate) fn generate(
+        &self,
+        segments: &[Segment],
+        channel_id: &ChannelId,
+    ) -> Result<SeqcProgram> {
+        let segments = filter_segments(segments, channel_id);
+        let mut program = Program::default();
+        let mut body = String::new();
+        self.emit(&segments, channel_id, &mut program, &mut body, 0)?;
+        let Program {
+            waves,
+            entries,
+            variables,
+        } = program;
+
+        let mut source = format!(
+            "// Generated by bosing for channel '{}'\n",
+            channel_id.to_string().escape_debug()
+        );
+        for (i, variable) in variables.iter().enumerate() {
+            writeln!(source, "var {} = getUserReg({});", variable, i)?;
+        }
+        for i in 0..waves.len() {
+            let wave = self.wave_name(i);
+            writeln!(source, "wave {}_i = \"{}_i\";", wave, wave)?;
+            writeln!(source, "wave {}_q = \"{}_q\";", wave, wave)?;
+            writeln!(
+                source,
+                "assignWaveIndex(1, {}_i, 2, {}_q, {});",
+                wave, wave, i
+            )?;
+        }
+        source.push_str(&body);
+
+        let waves = waves
+            .iter()
+            .enumerate()
+            .map(|(i, w)| Ok((self.wave_name(i), self.sample_wave(w)?)))
+            .collect::<Result<_>>()?;
+        Ok(SeqcProgram {
+            source,
+            command_table: self.command_table(&entries)?,
+            waves,
+        })
+    }
+
+    /// Append the code of `segments` to `body` and return the end of the
+    /// last play in samples, relative to the start of `body`.
+    fn emit<'b>(
+        &self,
+        segments: &[&'b Segment],
+        channel_id: &ChannelId,
+        program: &mut Program<'b>,
+        body: &mut String,
+        indent: usize,
+    ) -> Result<usize> {
+        let pad = "    ".repeat(indent);
+        let mut cursor = 0;
+        let mut pending_phase = Phase::ZERO;
+        for segment in segments {
+            match segment {
+                Segment::Instruction(instruction) => match &instruction.kind {
+                    InstructionKind::ShiftPhase { phase, .. } => pending_phase += *phase,
+                    InstructionKind::Play {
+                        shape_id,
+                        amplitude,
+                        width,
+                        plateau,
+                        drag_coef,
+                        frequency,
+                        phase,
+                        phase_reference,
+                        fall,
+                        ..
+                    } => {
+                        // The oscillator of the AWG runs from the start of the
+                        // sequence.
+                        if *phase_reference != PhaseReference::Absolute {
+                            bail!(
+                                "Unsupported phase reference '{}' on channel '{}'",
+                                phase_reference.name(),
+                                channel_id
+                            );
+                        }
+                        if fall.is_some() {
+                            bail!("Unsupported asymmetric pulse on channel '{}'", channel_id);
+                        }
+                        let start = self.to_grid("Play at", instruction.time)?;
+                        if start < cursor {
+                            bail!(
+                                "Overlapping plays at {} on channel '{}'",
+                                instruction.time.value(),
+                                channel_id
+                            );
+                        }
+                        if start > cursor {
+                            writeln!(body, "{}playZero({});", pad, start - cursor)?;
+                        }
+                        let wave = WaveKey {
+                            shape_id: shape_id.as_ref(),
+                            amplitude: *amplitude,
+                            width: *width,
+                            plateau: *plateau,
+                            drag_coef: *drag_coef,
+                            frequency: *frequency,
+                            phase: *phase,
+                        };
+                        let wave_index = find_or_push(&mut program.waves, wave);
+                        let entry = EntryKey {
+                            wave: Some(wave_index),
+                            phase_increment: pending_phase,
+                        };
+                        let entry_index = find_or_push(&mut program.entries, entry);
+                        writeln!(body, "{}executeTableEntry({});", pad, entry_index)?;
+                        pending_phase = Phase::ZERO;
+                        cursor = start + self.wave_length(*width + *plateau);
+                    }
+                    kind => bail!(
+                        "Unsupported instruction {} on channel '{}'",
+                        kind.name(),
+                        channel_id
+                    ),
+                },
+                Segment::Loop {
+                    time,
+                    count,
+                    count_var,
+                    period,
+                    body: segments,
+                } => {
+                    let start = self.to_grid("Loop at", *time)?;
+                    let period = self.to_grid("Loop period", *period)?;
+                    if start < cursor {
+                        bail!(
+                            "Overlapping plays at {} on channel '{}'",
+                            time.value(),
+                            channel_id
+                        );
+                    }
+                    if start > cursor {
+                        writeln!(body, "{}playZero({});", pad, start - cursor)?;
+                    }
+                    self.emit_phase(program, body, &pad, pending_phase)?;
+                    pending_phase = Phase::ZERO;
+                    let segments = filter_segments(segments, channel_id);
+                    let mut iteration = String::new();
+                    let end =
+                        self.emit(&segments, channel_id, program, &mut iteration, indent + 1)?;
+                    if end > period {
+                        bail!(
+                            "Overlapping loop iterations at {} on channel '{}'",
+                            time.value(),
+                            channel_id
+                        );
+                    }
+                    let (all, all_but_last) = match count_var {
+                        Some(var) => {
+                            find_or_push(&mut program.variables, var.as_str());
+                            (var.clone(), format!("{} - 1", var))
+                        }
+                        None => (count.to_string(), (count - 1).to_string()),
+                    };
+                    if end == period {
+                        writeln!(body, "{}repeat ({}) {{", pad, all)?;
+                        body.push_str(&iteration);
+                        writeln!(body, "{}}}", pad)?;
+                    } else {
+                        // The last iteration is unrolled because the padding
+                        // to the period may overlap the following plays.
+                        writeln!(body, "{}repeat ({}) {{", pad, all_but_last)?;
+                        body.push_str(&iteration);
+                        writeln!(body, "{}    playZero({});", pad, period - end)?;
+                        writeln!(body, "{}}}", pad)?;
+                        self.emit(&segments, channel_id, program, body, indent)?;
+                    }
+                    cursor = start + period * (count - 1) + end;
+                }
+            }
+        }
+        self.emit_phase(program, body, &pad, pending_phase)?;
+        Ok(cursor)
+    }
+
+    /// Emit a table entry without waveform for a remaining phase increment.
+    fn emit_phase<'b>(
+        &self,
+        program: &mut Program<'b>,
+        body: &mut String,
+        pad: &str,
+        phase_increment: Phase,
+    ) -> Result<()> {
+        if phase_increment != Phase::ZERO {
+            let entry = EntryKey {
+                wave: None,
+                phase_increment,
+            };
+            let entry_index = find_or_push(&mut program.entries, entry);
+            writeln!(body, "{}executeTableEntry({});", pad, entry_index)?;
+        }
+        Ok(())
+    }
+
+    fn wave_name(&self, index: usize) -> String {
+        format!("{}_w{}", identifier(self.name), index)
+    }
+
+    fn to_grid(&self, what: &str, time: Time) -> Result<usize> {
+        let index = time.value() * self.sample_rate.value();
+        let rounded = (index / GRANULARITY as f64).round() * GRANULARITY as f64;
+        let tolerance = self.time_tolerance.value() * self.sample_rate.value();
+        if (index - rounded).abs() > tolerance || rounded < 0.0 {
+            bail!(
+                "{} {} is not aligned to the {}-sample grid",
+                what,
+                time.value(),
+                GRANULARITY
+            );
+        }
+        Ok(rounded as usize)
+    }
+
+    fn wave_length(&self, duration: Time) -> usize {
+        let tolerance = self.time_tolerance.value() * self.sample_rate.value();
+        let n = (duration.value() * self.sample_rate.value() - tolerance).ceil() as usize;
+        n.next_multiple_of(GRANULARITY).max(MIN_LENGTH)
+    }
+
+    fn sample_wave(&self, wave: &WaveKey) -> Result<Array2<f64>> {
+        let shape = match wave.shape_id {
+            Some(id) => match self.shapes.get(id) {
+                Some(s) => Some(s.clone()),
+                None => bail!("Shape not found: {:?}", id),
+            },
+            None => None,
+        };
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, self.time_tolerance);
+        builder.push(PushArgs {
+            envelope: Envelope::new(shape, wave.width, wave.plateau),
+            global_freq: Frequency::ZERO,
+            local_freq: wave.frequency,
+            time: Time::ZERO,
+            amplitude: wave.amplitude,
+            drag_coef: wave.drag_coef,
+            phase: wave.phase,
+            id: None,
+        });
+        let channel_id = ChannelId::new(self.name);
+        let length = self.wave_length(wave.width + wave.plateau);
+        let mut array = Array2::zeros((2, length));
+        let mut sampler = Sampler::new([(channel_id, builder.build_with(|_| {}))].into());
+        sampler.add_channel(
+            channel_id,
+            array.view_mut(),
+            self.sample_rate,
+            Time::ZERO,
+            0,
+            Complex64::ZERO,
+        );
+        sampler.sample(self.time_tolerance)?;
+        Ok(array)
+    }
+
+    fn command_table(&self, entries: &[EntryKey]) -> Result<String> {
+        let table: Vec<_> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let mut entry = json!({ "index": i });
+                if let Some(wave) = e.wave {
+                    entry["waveform"] = json!({ "index": wave });
+                }
+                if e.phase_increment != Phase::ZERO {
+                    let phase = json!({
+                        "value": e.phase_increment.value() * 360.0,
+                        "increment": true,
+                    });
+                    match self.device {
+                        Device::Hdawg => {
+                            entry["phase0"] = phase.clone();
+                            entry["phase1"] = phase;
+                        }
+                        Device::Shfsg => entry["phase"] = phase,
+                    }
+                }
+                entry
+            })
+            .collect();
+        let (schema, version) = match self.device {
+            Device::Hdawg => (
+                "https://docs.zhinst.com/hdawg/commandtable/v1_1/schema",
+                "1.1.0",
+            ),
+            Device::Shfsg => (
+                "https://docs.zhinst.com/shfsg/commandtable/v1_0/schema",
+                "1.0.0",
+            ),
+        };
+        let document = json!({
+            "$schema": schema,
+            "header": { "version": version },
+            "table": table,
+        });
+        Ok(serde_json::to_string_pretty(&document)?)
+    }
+}
+
+impl SeqcProgram {
+    /// Write `<name>.seqc`, `<name>_command_table.json`, and CSV files of the
+    /// waveforms under `waves/` into `dir`, with `name` escaped like the wave
+    /// names.
+    pub(crate) fn write_to_dir(&self, dir: impl AsRef<Path>, name: &str) -> Result<()> {
+        let dir = dir.as_ref();
+        let name = identifier(name);
+        let waves_dir = dir.join("waves");
+        fs::create_dir_all(&waves_dir)?;
+        fs::write(dir.join(format!("{}.seqc", name)), &self.source)?;
+        fs::write(
+            dir.join(format!("{}_command_table.json", name)),
+            &self.command_table,
+        )?;
+        for (wave, data) in &self.waves {
+            for (suffix, row) in ["i", "q"].into_iter().zip(data.rows()) {
+                let mut csv = String::new();
+                for v in row {
+                    writeln!(csv, "{}", v)?;
+                }
+                fs::write(waves_dir.join(format!("{}_{}.csv", wave, suffix)), csv)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Escape `name` to a valid SeqC identifier, see the module documentation.
+fn identifier(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for (i, b) in name.bytes().enumerate() {
+        match b {
+            b'_' => escaped.push_str("__"),
+            b'0'..=b'9' if i > 0 => escaped.push(b as char),
+            b'a'..=b'z' | b'A'..=b'Z' => escaped.push(b as char),
+            _ => write!(escaped, "_{:02x}", b).expect("Writing to a string should not fail"),
+        }
+    }
+    escaped
+}
+
+/// Segments involving `channel_id` sorted by time. Loops without such
+/// segments are dropped.
+fn filter_segments<'a>(segments: &'a [Segment], channel_id: &ChannelId) -> Vec<&'a Segment> {
+    let mut result: Vec<_> = segments
+        .iter()
+        .filter(|s| match s {
+            Segment::Instruction(i) => involves(&i.kind, channel_id),
+            Segment::Loop { body, .. } => !filter_segments(body, channel_id).is_empty(),
+        })
+        .collect();
+    result.sort_by_key(|s| match s {
+        Segment::Instruction(i) => i.time,
+        Segment::Loop { time, .. } => *time,
+    });
+    result
+}
+
+fn involves(kind: &InstructionKind, channel_id: &ChannelId) -> bool {
+    match kind {
+        InstructionKind::Play { channel_id: c, .. }
+        | InstructionKind::PlaySamples { channel_id: c, .. }
+        | InstructionKind::ShiftPhase { channel_id: c, .. }
+        | InstructionKind::SetPhase { channel_id: c, .. }
+        | InstructionKind::ShiftFreq { channel_id: c, .. }
+        | InstructionKind::SetFreq { channel_id: c, .. } => c == channel_id,
+        InstructionKind::SwapPhase {
+            channel_id1,
+            channel_id2,
+        } => channel_id1 == channel_id || channel_id2 == channel_id,
+    }
+}
+
+fn find_or_push<T: PartialEq>(items: &mut Vec<T>, item: T) -> usize {
+    items.iter().position(|x| *x == item).unwrap_or_else(|| {
+        items.push(item);
+        items.len() - 1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::Instruction;
+
+    fn play(time: f64, amplitude: f64) -> Segment {
+        Segment::Instruction(Instruction {
+            time: Time::new(time).unwrap(),
+            duration: Time::new(20e-9).unwrap(),
+            kind: InstructionKind::Play {
+                channel_id: ChannelId::new("xy"),
+                shape_id: Some(ShapeId::new("hann")),
+                amplitude: Amplitude::new(amplitude).unwrap(),
+                width: Time::new(20e-9).unwrap(),
+                plateau: Time::ZERO,
+                drag_coef: 0.0,
+                frequency: Frequency::ZERO,
+                phase: Phase::ZERO,
+                phase_reference: PhaseReference::Absolute,
+                fall: None,
+            },
+        })
+    }
+
+    fn shift_phase(time: f64, phase: f64) -> Segment {
+        Segment::Instruction(Instruction {
+            time: Time::new(time).unwrap(),
+            duration: Time::ZERO,
+            kind: InstructionKind::ShiftPhase {
+                channel_id: ChannelId::new("xy"),
+                phase: Phase::new(phase).unwrap(),
+            },
+        })
+    }
+
+    #[test]
+    fn playlist() {
+        let shapes = [(ShapeId::new("hann"), Shape::new_hann())].into();
+        let generator = SeqcGenerator::new(
+            "xy",
+            Device::Hdawg,
+            Frequency::new(2.4e9).unwrap(),
+            &shapes,
+            Time::new(1e-12).unwrap(),
+        );
+        let t = 16.0 / 2.4e9;
+        let segments = vec![
+            play(0.0, 0.5),
+            shift_phase(20e-9, 0.25),
+            play(8.0 * t, 0.5),
+            play(16.0 * t, 0.3),
+        ];
+
+        let program = generator
+            .generate(&segments, &ChannelId::new("xy"))
+            .unwrap();
+
+        assert_eq!(program.waves.len(), 2);
+        assert_eq!(program.waves[0].1.shape(), &[2, 48]);
+        assert!(program.source.contains("playZero(80);"));
+        assert_eq!(program.source.matches("executeTableEntry(").count(), 3);
+        let table: serde_json::Value = serde_json::from_str(&program.command_table).unwrap();
+        assert_eq!(table["table"].as_array().unwrap().len(), 3);
+        assert_eq!(table["table"][1]["phase0"]["value"], 90.0);
+    }
+
+    #[test]
+    fn repeat_block() {
+        let shapes = [(ShapeId::new("hann"), Shape::new_hann())].into();
+        let generator = SeqcGenerator::new(
+            "xy",
+            Device::Hdawg,
+            Frequency::new(2.4e9).unwrap(),
+            &shapes,
+            Time::new(1e-12).unwrap(),
+        );
+        let t = 16.0 / 2.4e9;
+        let body = vec![shift_phase(0.0, 0.25), play(0.0, 0.5)];
+        let segments = vec![
+            Segment::Loop {
+                time: Time::new(4.0 * t).unwrap(),
+                count: 10,
+                count_var: None,
+                period: Time::new(3.0 * t).unwrap(),
+                body: body.clone(),
+            },
+            Segment::Loop {
+                time: Time::new(44.0 * t).unwrap(),
+                count: 3,
+                count_var: Some("n".to_string()),
+                period: Time::new(8.0 * t).unwrap(),
+                body,
+            },
+        ];
+
+        let program = generator
+            .generate(&segments, &ChannelId::new("xy"))
+            .unwrap();
+
+        assert_eq!(program.waves.len(), 1);
+        assert!(program
+            .source
+            .contains("playZero(64);\nrepeat (10) {\n    executeTableEntry(0);\n}\n"));
+        assert!(program.source.contains(
+            "repeat (n - 1) {\n    executeTableEntry(0);\n    playZero(80);\n}\nexecuteTableEntry(0);\n"
+        ));
+        assert!(program.source.contains("var n = getUserReg(0);\n"));
+    }
+
+    #[test]
+    fn escape_channel_names() {
+        assert_eq!(identifier("xy"), "xy");
+        assert_eq!(identifier("q0.xy"), "q0_2exy");
+        assert_eq!(identifier("xy-0"), "xy_2d0");
+        assert_eq!(identifier("q0_xy"), "q0__xy");
+        assert_eq!(identifier("0xy"), "_30xy");
+        assert_eq!(identifier("../xy"), "_2e_2e_2fxy");
+
+        let shapes = [(ShapeId::new("hann"), Shape::new_hann())].into();
+        let generator = SeqcGenerator::new(
+            "q0.xy",
+            Device::Hdawg,
+            Frequency::new(2.4e9).unwrap(),
+            &shapes,
+            Time::new(1e-12).unwrap(),
+        );
+        let program = generator
+            .generate(&[play(0.0, 0.5)], &ChannelId::new("xy"))
+            .unwrap();
+
+        assert_eq!(program.waves[0].0, "q0_2exy_w0");
+        assert!(program
+            .source
+            .contains("wave q0_2exy_w0_i = \"q0_2exy_w0_i\";\n"));
+    }
+
+    #[test]
+    fn misaligned_play() {
+        let shapes = [(ShapeId::new("hann"), Shape::new_hann())].into();
+        let generator = SeqcGenerator::new(
+            "xy",
+            Device::Shfsg,
+            Frequency::new(2.0e9).unwrap(),
+            &shapes,
+            Time::new(1e-12).unwrap(),
+        );
+
+        let result = generator.generate(&[play(1e-9, 0.5)], &ChannelId::new("xy"));
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,336 @@
+//! SVG rendering of sampled waveforms and flattened schedules.
+//!
+//! The output is a standalone SVG document without external stylesheets, so
+//! it can be embedded in reports or opened in a browser directly.
+use std::fmt::Write as _;
+
+use hashbrown::HashMap;
+
+use super::ChannelWaveform;
+use crate::{
+    quant::ChannelId,
+    schedule::{Instruction, InstructionKind},
+};
+
+const WIDTH: f64 = 800.0;
+const LABEL_WIDTH: f64 = 120.0;
+const PLOT_WIDTH: f64 = WIDTH - LABEL_WIDTH - 10.0;
+const PANEL_HEIGHT: f64 = 120.0;
+const ROW_HEIGHT: f64 = 30.0;
+const AXIS_HEIGHT: f64 = 20.0;
+
+const I_COLOR: &str = "#1f77b4";
+const Q_COLOR: &str = "#ff7f0e";
+const AMPLITUDE_COLOR: &str = "#2ca02c";
+const PLAY_COLOR: &str = "#1f77b4";
+const PHASE_COLOR: &str = "#d62728";
+const FREQ_COLOR: &str = "#9467bd";
+
+/// Render waveforms as one panel per channel.
+///
+/// Real waveforms are drawn as a single trace. Complex waveforms are drawn as
+/// I, Q, and amplitude traces. Long waveforms are decimated to the minimum
+/// and maximum value per pixel column.
+pub fn render_waveforms(waveforms: &[ChannelWaveform]) -> String {
+    let height = PANEL_HEIGHT * waveforms.len() as f64;
+    let mut svg = header(height);
+    for (i, waveform) in waveforms.iter().enumerate() {
+        let top = PANEL_HEIGHT * i as f64;
+        let middle = top + PANEL_HEIGHT / 2.0;
+        let length = waveform.data.ncols();
+        let duration = length as f64 / waveform.info.sample_rate;
+        let label = format!("{} ({})", waveform.name, format_time(duration));
+        write_label(&mut svg, &label, middle);
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="#ccc"/>"##,
+            LABEL_WIDTH,
+            middle,
+            LABEL_WIDTH + PLOT_WIDTH,
+            middle
+        );
+        let mut traces: Vec<(Vec<f64>, &str)> = waveform
+            .data
+            .rows()
+            .into_iter()
+            .zip([I_COLOR, Q_COLOR])
+            .map(|(row, color)| (row.to_vec(), color))
+            .collect();
+        if waveform.data.nrows() == 2 {
+            let amplitude = waveform
+                .data
+                .columns()
+                .into_iter()
+                .map(|c| c[0].hypot(c[1]))
+                .collect();
+            traces.push((amplitude, AMPLITUDE_COLOR));
+        }
+        let scale = traces
+            .iter()
+            .flat_map(|(t, _)| t.iter())
+            .fold(0.0f64, |m, v| m.max(v.abs()));
+        let scale = if scale > 0.0 { scale } else { 1.0 };
+        let half_height = PANEL_HEIGHT / 2.0 - 10.0;
+        for (trace, color) in &traces {
+            let points = decimate(trace)
+                .into_iter()
+                .map(|(x, y)| {
+                    format!(
+                        "{:.2},{:.2}",
+                        LABEL_WIDTH + x * PLOT_WIDTH,
+                        middle - y / scale * half_height
+                    )
+                })
+                .collect::<Vec<_>>();
+            let _ = writeln!(
+                svg,
+                r#"<polyline points="{}" fill="none" stroke="{}"/>"#,
+                points.join(" "),
+                color
+            );
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Render a flattened schedule as a Gantt chart with one row per channel.
+///
+/// Plays are drawn as boxes and phase or frequency operations as vertical
+/// markers. Channels are ordered by first appearance.
+pub(crate) fn render_gantt(instructions: &[Instruction]) -> String {
+    let mut rows = HashMap::<&ChannelId, usize>::new();
+    let mut names = Vec::new();
+    for i in instructions {
+        for id in channel_ids(&i.kind) {
+            rows.entry(id).or_insert_with(|| {
+                names.push(id);
+                names.len() - 1
+            });
+        }
+    }
+    let total = instructions
+        .iter()
+        .map(|i| (i.time + i.duration).value())
+        .fold(0.0, f64::max);
+    let x = |t: f64| {
+        if total > 0.0 {
+            LABEL_WIDTH + t / total * PLOT_WIDTH
+        } else {
+            LABEL_WIDTH
+        }
+    };
+    let height = ROW_HEIGHT * names.len() as f64 + AXIS_HEIGHT;
+    let mut svg = header(height);
+    for (row, name) in names.iter().enumerate() {
+        write_label(&mut svg, name.as_ref(), ROW_HEIGHT * (row as f64 + 0.5));
+    }
+    for i in instructions {
+        let start = i.time.value();
+        let title = format!("{} @ {}", i.kind.name(), format_time(start));
+        for id in channel_ids(&i.kind) {
+            let top = ROW_HEIGHT * rows[id] as f64;
+            if let InstructionKind::Play { .. } | InstructionKind::PlaySamples { .. } = i.kind {
+                let width = (x(start + i.duration.value()) - x(start)).max(1.0);
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="{}" fill-opacity="0.6"><title>{}</title></rect>"#,
+                    x(start),
+                    top + 4.0,
+                    width,
+                    ROW_HEIGHT - 8.0,
+                    PLAY_COLOR,
+                    escape(&title)
+                );
+            } else {
+                let color = match i.kind {
+                    InstructionKind::ShiftFreq { .. } | InstructionKind::SetFreq { .. } => {
+                        FREQ_COLOR
+                    }
+                    _ => PHASE_COLOR,
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"<line x1="{0:.2}" y1="{1:.2}" x2="{0:.2}" y2="{2:.2}" stroke="{3}" stroke-width="2"><title>{4}</title></line>"#,
+                    x(start),
+                    top + 2.0,
+                    top + ROW_HEIGHT - 2.0,
+                    color,
+                    escape(&title)
+                );
+            }
+        }
+    }
+    let axis = ROW_HEIGHT * names.len() as f64 + AXIS_HEIGHT - 5.0;
+    let _ = writeln!(
+        svg,
+        r#"<text x="{:.2}" y="{:.2}">0</text><text x="{:.2}" y="{:.2}" text-anchor="end">{}</text>"#,
+        LABEL_WIDTH,
+        axis,
+        LABEL_WIDTH + PLOT_WIDTH,
+        axis,
+        format_time(total)
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn channel_ids(kind: &InstructionKind) -> Vec<&ChannelId> {
+    match kind {
+        InstructionKind::Play { channel_id, .. }
+        | InstructionKind::PlaySamples { channel_id, .. }
+        | InstructionKind::ShiftPhase { channel_id, .. }
+        | InstructionKind::SetPhase { channel_id, .. }
+        | InstructionKind::ShiftFreq { channel_id, .. }
+        | InstructionKind::SetFreq { channel_id, .. } => vec![channel_id],
+        InstructionKind::SwapPhase {
+            channel_id1,
+            channel_id2,
+        } => vec![channel_id1, channel_id2],
+    }
+}
+
+fn header(height: f64) -> String {
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" "#,
+            r#"font-family="sans-serif" font-size="12">"#,
+            "\n",
+            r#"<rect width="100%" height="100%" fill="white"/>"#,
+            "\n",
+        ),
+        WIDTH, height
+    )
+}
+
+fn write_label(svg: &mut String, label: &str, y: f64) {
+    let _ = writeln!(
+        svg,
+        r#"<text x="5" y="{:.2}" dominant-baseline="middle">{}</text>"#,
+        y,
+        escape(label)
+    );
+}
+
+/// Normalized `(x, y)` points of the trace with at most two points per pixel
+/// column.
+fn decimate(trace: &[f64]) -> Vec<(f64, f64)> {
+    let length = trace.len();
+    if length == 0 {
+        return Vec::new();
+    }
+    let buckets = PLOT_WIDTH as usize;
+    if length <= 2 * buckets {
+        let denom = (length - 1).max(1) as f64;
+        return trace
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| (i as f64 / denom, v))
+            .collect();
+    }
+    (0..buckets)
+        .flat_map(|b| {
+            let chunk = &trace[b * length / buckets..(b + 1) * length / buckets];
+            let min = chunk.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = chunk.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let x = b as f64 / (buckets - 1) as f64;
+            [(x, min), (x, max)]
+        })
+        .collect()
+}
+
+fn format_time(t: f64) -> String {
+    let (value, unit) = match t.abs() {
+        0.0 => (t, "s"),
+        v if v < 1e-6 => (t * 1e9, "ns"),
+        v if v < 1e-3 => (t * 1e6, "us"),
+        v if v < 1.0 => (t * 1e3, "ms"),
+        _ => (t, "s"),
+    };
+    let value = format!("{:.4}", value);
+    format!(
+        "{} {}",
+        value.trim_end_matches('0').trim_end_matches('.'),
+        unit
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+    use crate::{
+        io::ChannelInfo,
+        quant::{Amplitude, Frequency, Phase, Time},
+        schedule::PhaseReference,
+    };
+
+    #[test]
+    fn waveforms() {
+        let data = array![[0.0, 1.0, 0.0], [0.0, -1.0, 0.5]];
+        let waveforms = [ChannelWaveform {
+            name: "q<0>",
+            info: ChannelInfo {
+                sample_rate: 1e9,
+                delay: 0.0,
+                align_level: -10,
+            },
+            data: data.view(),
+        }];
+
+        let svg = render_waveforms(&waveforms);
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<polyline").count(), 3);
+        assert!(svg.contains("q&lt;0&gt; (3 ns)"));
+    }
+
+    #[test]
+    fn gantt() {
+        let ch = |s: &str| ChannelId::new(s);
+        let t = |v: f64| Time::new(v).unwrap();
+        let instructions = [
+            Instruction {
+                time: t(0.0),
+                duration: t(10e-9),
+                kind: InstructionKind::Play {
+                    channel_id: ch("xy"),
+                    shape_id: None,
+                    amplitude: Amplitude::new(0.5).unwrap(),
+                    width: t(10e-9),
+                    plateau: t(0.0),
+                    drag_coef: 0.0,
+                    frequency: Frequency::ZERO,
+                    phase: Phase::ZERO,
+                    phase_reference: PhaseReference::Absolute,
+                    fall: None,
+                },
+            },
+            Instruction {
+                time: t(10e-9),
+                duration: t(0.0),
+                kind: InstructionKind::SwapPhase {
+                    channel_id1: ch("xy"),
+                    channel_id2: ch("u"),
+                },
+            },
+        ];
+
+        let svg = render_gantt(&instructions);
+
+        assert_eq!(svg.matches("<rect x=").count(), 1);
+        assert_eq!(svg.matches("<line").count(), 2);
+        assert!(svg.contains(">xy</text>"));
+        assert!(svg.contains(">u</text>"));
+        assert!(svg.contains(">10 ns</text>"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Planning of local oscillator frequencies shared by several channels.
+//!
+//! Channels on one IQ up-converter share the LO, and each channel plays at
+//! `lo + if`. Besides the wanted tone, an imperfect mixer leaks the LO itself
+//! and the image at `lo - if` of every channel. The planner chooses the LO so
+//! that these spurs stay away from all drive frequencies.
+use anyhow::{bail, Result};
+
+use crate::quant::Frequency;
+
+/// LO frequency and intermediate frequencies of the channels.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LoPlan {
+    pub(crate) lo_freq: Frequency,
+    /// Intermediate frequencies in the order of the drive frequencies.
+    pub(crate) if_freqs: Vec<Frequency>,
+    /// Smallest distance between a spur and a drive frequency.
+    pub(crate) clearance: Frequency,
+}
+
+/// Choose the LO of channels with `drive_freqs` sharing an up-converter.
+///
+/// All intermediate frequencies are within `max_if` in magnitude. The LO is
+/// chosen to maximize the smallest distance from the LO and from the image of
+/// every channel to any drive frequency, and the result is rejected if that
+/// distance is less than `guard`.
+pub(crate) fn plan_lo(
+    drive_freqs: &[Frequency],
+    max_if: Frequency,
+    guard: Frequency,
+) -> Result<LoPlan> {
+    let freqs: Vec<f64> = drive_freqs.iter().map(|f| f.value()).collect();
+    let Some(low) = freqs.iter().copied().reduce(f64::min) else {
+        bail!("No drive frequencies to plan the LO for");
+    };
+    let high = freqs.iter().copied().fold(low, f64::max);
+    let max_if = max_if.value();
+    let (lo_min, lo_max) = (high - max_if, low + max_if);
+    if lo_min > lo_max {
+        bail!(
+            "Drive frequencies span {} which exceeds twice the max IF {}",
+            Frequency::new(high - low)?,
+            Frequency::new(max_if)?
+        );
+    }
+    // The clearance is the minimum of |lo - f| for the LO leakage and of
+    // |2 lo - f_i - f_j| = 2 |lo - (f_i + f_j) / 2| for the images, so it is
+    // piecewise linear in the LO with kinks at these points.
+    let mut kinks: Vec<(f64, f64)> = freqs.iter().map(|&f| (f, 1.0)).collect();
+    for (i, &fi) in freqs.iter().enumerate() {
+        for &fj in &freqs[i + 1..] {
+            kinks.push(((fi + fj) / 2.0, 2.0));
+        }
+    }
+    kinks.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let clearance = |lo: f64| {
+        kinks
+            .iter()
+            .map(|&(x, slope)| slope * (lo - x).abs())
+            .fold(f64::INFINITY, f64::min)
+    };
+    // The maximum is at a bound or where the slopes of adjacent kinks meet.
+    let candidates = kinks
+        .windows(2)
+        .map(|w| {
+            let ((a, sa), (b, sb)) = (w[0], w[1]);
+            (sa * a + sb * b) / (sa + sb)
+        })
+        .filter(|&lo| lo_min <= lo && lo <= lo_max)
+        .chain([lo_min, lo_max]);
+    let mut best = (lo_min, clearance(lo_min));
+    for lo in candidates {
+        let c = clearance(lo);
+        if c > best.1 {
+            best = (lo, c);
+        }
+    }
+    let (lo, c) = best;
+    if c < guard.value() {
+        bail!(
+            "No LO frequency keeps the spurs {} away from the drive frequencies, the best is {}",
+            guard,
+            Frequency::new(c)?
+        );
+    }
+    Ok(LoPlan {
+        lo_freq: Frequency::new(lo)?,
+        if_freqs: freqs
+            .iter()
+            .map(|&f| Frequency::new(f - lo))
+            .collect::<Result<_, _>>()?,
+        clearance: Frequency::new(c)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::assert_approx_eq;
+
+    use super::*;
+
+    fn freqs(values: &[f64]) -> Vec<Frequency> {
+        values.iter().map(|&v| Frequency::new(v).unwrap()).collect()
+    }
+
+    #[test]
+    fn plan() {
+        let max_if = Frequency::new(500e6).unwrap();
+        let guard = Frequency::new(50e6).unwrap();
+
+        let plan = plan_lo(&freqs(&[5.0e9, 5.3e9]), max_if, guard).unwrap();
+        // Images of the two channels fall on each other's drive frequency if
+        // the LO is at the midpoint, so the LO moves outside of the pair.
+        let lo = plan.lo_freq.value();
+        assert!(!(5.0e9..=5.3e9).contains(&lo));
+        assert_approx_eq!(f64, plan.if_freqs[0].value(), 5.0e9 - lo);
+        assert!(plan.if_freqs.iter().all(|f| f.value().abs() <= 500e6));
+        assert!(plan.clearance >= guard);
+
+        let single = plan_lo(&freqs(&[6e9]), max_if, guard).unwrap();
+        assert_approx_eq!(f64, single.if_freqs[0].value().abs(), 500e6);
+
+        assert!(plan_lo(&freqs(&[5e9, 6.1e9]), max_if, guard).is_err());
+        assert!(plan_lo(&[], max_if, guard).is_err());
+        let tight = Frequency::new(400e6).unwrap();
+        assert!(plan_lo(&freqs(&[5.0e9, 5.3e9]), max_if, tight).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Schedule to waveform pipeline without the python bindings.
+use anyhow::Result;
+use hashbrown::HashMap;
+use ndarray::{Array1, Array2, ArrayView2};
+use num::complex::Complex64;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    budget::{check_memory, estimate_memory, MemoryLimit, MemoryUsage},
+    diagnostics::Diagnostics,
+    executor::{Executor, Ringdown, TimeGrid},
+    pulse::{post_process_inplace, IdleTone, Predistortion, PulseList, Sampler},
+    quant::{Amplitude, ChannelId, Frequency, ShapeId, Time},
+    schedule::ElementRef,
+    shape::Shape,
+};
+
+/// Parameters of a channel needed to generate its waveform.
+#[derive(Debug, Clone)]
+pub(crate) struct ChannelSpec {
+    pub(crate) name: ChannelId,
+    pub(crate) base_freq: Frequency,
+    pub(crate) sample_rate: Frequency,
+    pub(crate) delay: Time,
+    pub(crate) align_level: i32,
+    /// Level of the waveform when no pulse is playing.
+    pub(crate) baseline: Complex64,
+    pub(crate) ringdown: Option<Ringdown>,
+    /// Follow every pulse by an inverted copy and report the residual area.
+    pub(crate) net_zero: bool,
+    /// Carrier played while no pulse of the channel is playing.
+    pub(crate) idle_tone: Option<IdleTone>,
+    pub(crate) length: usize,
+    pub(crate) is_real: bool,
+    /// Overrides the time grid of the options for the channel.
+    pub(crate) time_grid: Option<TimeGrid>,
+    /// Waveform memory of the unique envelopes, checked before sampling.
+    pub(crate) memory: Option<MemoryLimit>,
+    pub(crate) filters: Filters,
+}
+
+/// Post processing of a channel, see `Channel` in the python API.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Filters {
+    pub(crate) predistortion: Option<Predistortion>,
+    pub(crate) iq_matrix: Option<Array2<f64>>,
+    pub(crate) offset: Option<Array1<f64>>,
+    pub(crate) iir: Option<Array2<f64>>,
+    pub(crate) fir: Option<Array1<f64>>,
+    pub(crate) filter_offset: bool,
+}
+
+/// Options of [`generate_waveforms`], missing fields are filled with the
+/// defaults of the python API when deserialized.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Options {
+    pub(crate) time_tolerance: Time,
+    pub(crate) amp_tolerance: Amplitude,
+    pub(crate) allow_oversize: bool,
+    pub(crate) allow_truncate: bool,
+    pub(crate) fractional_delay: bool,
+    pub(crate) oversampling: usize,
+    pub(crate) time_grid: Option<TimeGrid>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            time_tolerance: Time::new(1e-12).unwrap(),
+            amp_tolerance: Amplitude::new(0.1 / 2f64.powi(16)).unwrap(),
+            allow_oversize: false,
+            allow_truncate: false,
+            fractional_delay: false,
+            oversampling: 1,
+            time_grid: None,
+        }
+    }
+}
+
+/// Measure, arrange, sample, and post process the schedule.
+///
+/// `crosstalk` is the crosstalk matrix with the corresponding channel ids. The
+/// waveform of each channel has shape `(1, length)` for real channels and
+/// `(2, length)` otherwise. Non-fatal warnings are returned with the waveforms.
+///
+/// Fails before sampling if the unique envelopes of a channel exceed its
+/// waveform memory.
+pub(crate) fn generate_waveforms(
+    schedule: &ElementRef,
+    channels: &[ChannelSpec],
+    shapes: &HashMap<ShapeId, Shape>,
+    crosstalk: Option<(ArrayView2<f64>, &[ChannelId])>,
+    options: Options,
+) -> Result<(HashMap<ChannelId, Array2<f64>>, Diagnostics)> {
+    let (pulse_lists, mut diagnostics) = execute(schedule, channels, shapes, options)?;
+    check_memory(&memory_usages(&pulse_lists, channels))?;
+    let mut waveforms: HashMap<ChannelId, Array2<f64>> = channels
+        .iter()
+        .map(|c| {
+            let rows = if c.is_real { 1 } else { 2 };
+            (c.name, Array2::zeros((rows, c.length)))
+        })
+        .collect();
+    let specs: HashMap<_, _> = channels.iter().map(|c| (&c.name, c)).collect();
+    let mut sampler = Sampler::new(pulse_lists);
+    for (n, w) in &mut waveforms {
+        let c = specs[n];
+        sampler.add_channel(
+            *n,
+            w.view_mut(),
+            c.sample_rate,
+            c.delay,
+            c.align_level,
+            c.baseline,
+        );
+    }
+    if let Some((matrix, names)) = &crosstalk {
+        sampler.set_crosstalk(matrix.view(), names.to_vec());
+    }
+    sampler.set_net_zero(
+        channels.iter().filter(|c| c.net_zero).map(|c| c.name),
+        options.amp_tolerance,
+    );
+    sampler.set_idle_tones(channels.iter().filter_map(|c| Some((c.name, c.idle_tone?))));
+    sampler.set_allow_truncate(options.allow_truncate);
+    sampler.set_fractional_delay(options.fractional_delay);
+    sampler.set_oversampling(options.oversampling);
+    diagnostics.extend(sampler.sample(options.time_tolerance)?);
+    for (n, w) in &mut waveforms {
+        let f = &specs[n].filters;
+        post_process_inplace(
+            &mut w.view_mut(),
+            f.predistortion.as_ref(),
+            f.iq_matrix.as_ref().map(|x| x.view()),
+            f.offset.as_ref().map(|x| x.view()),
+            f.iir.as_ref().map(|x| x.view()),
+            f.fir.as_ref().map(|x| x.view()),
+            f.filter_offset,
+        );
+    }
+    Ok((waveforms, diagnostics))
+}
+
+/// Estimate the waveform memory of the channels with a memory limit without
+/// sampling the schedule.
+#[cfg(feature = "cli")]
+pub(crate) fn estimate_waveform_memory(
+    schedule: &ElementRef,
+    channels: &[ChannelSpec],
+    shapes: &HashMap<ShapeId, Shape>,
+    options: Options,
+) -> Result<Vec<MemoryUsage>> {
+    let (pulse_lists, _) = execute(schedule, channels, shapes, options)?;
+    Ok(memory_usages(&pulse_lists, channels))
+}
+
+fn memory_usages(
+    pulse_lists: &HashMap<ChannelId, PulseList>,
+    channels: &[ChannelSpec],
+) -> Vec<MemoryUsage> {
+    estimate_memory(
+        pulse_lists,
+        channels
+            .iter()
+            .filter_map(|c| Some((c.name, c.sample_rate, c.memory?))),
+    )
+}
+
+fn execute(
+    schedule: &ElementRef,
+    channels: &[ChannelSpec],
+    shapes: &HashMap<ShapeId, Shape>,
+    options: Options,
+) -> Result<(HashMap<ChannelId, PulseList>, Diagnostics)> {
+    let mut executor = Executor::new(
+        options.amp_tolerance,
+        options.time_tolerance,
+        options.allow_oversize,
+    );
+    executor.set_time_grid(options.time_grid);
+    for c in channels {
+        executor.add_channel(c.name, c.base_freq, c.ringdown, c.net_zero);
+        executor.set_channel_time_grid(c.name, c.time_grid);
+    }
+    for (n, s) in shapes {
+        executor.add_shape(n.clone(), s.clone());
+    }
+    executor.execute(schedule)?;
+    Ok(executor.into_result())
+}
@@ -1,34 +1,56 @@
+mod decimate;
+mod delay;
 mod fir;
 mod iir;
+mod predistortion;
 
 use std::{
-    ops::{Add, Mul},
+    ops::{Add, Mul, Range},
     sync::Arc,
 };
 
 use anyhow::{bail, Context, Result};
 use cached::proc_macro::cached;
 use float_cmp::approx_eq;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use itertools::{izip, Itertools};
-use ndarray::{azip, s, ArrayView1, ArrayView2, ArrayViewMut2, Axis};
-use numpy::Complex64;
+use ndarray::{azip, ArrayView1};
+use ndarray::{s, ArrayView2, ArrayViewMut2, Axis};
+use num::complex::Complex64;
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    quant::{AlignedIndex, Amplitude, ChannelId, Frequency, Phase, Time},
-    shape::Shape,
+    analysis::waveform_stats,
+    diagnostics::{Diagnostics, Warning},
+    quant::{AlignedIndex, Amplitude, ChannelId, Frequency, Phase, Rounding, Time},
+    shape::{Shape, ShapeOrder},
 };
 
+pub(crate) use self::predistortion::Predistortion;
+
 /// A pulse envelope
 ///
 /// If `shape` is `None`, constructor will set `plateau` to `width + plateau`
 /// and `width` to `0`.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) struct Envelope {
     shape: Option<Shape>,
     width: Time,
     plateau: Time,
+    /// Falling edge if it differs from the rising edge given by `shape` and
+    /// `width`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fall: Option<FallingEdge>,
+}
+
+/// Falling edge of an asymmetric [`Envelope`], the second half of `shape`
+/// stretched to `width`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct FallingEdge {
+    shape: Option<Shape>,
+    width: Time,
 }
 
 impl Envelope {
@@ -44,18 +66,107 @@ impl Envelope {
             shape,
             width,
             plateau,
+            fall: None,
         }
     }
+
+    /// Envelope rising with the first half of `rise_shape` stretched to
+    /// `rise_width` and falling with the second half of `fall_shape` stretched
+    /// to `fall_width`.
+    ///
+    /// The duration is `(rise_width + fall_width) / 2 + plateau`. Rectangular
+    /// edges are part of the plateau, and equal edges give the same envelope
+    /// as [`Envelope::new`].
+    pub(crate) fn asymmetric(
+        rise_shape: Option<Shape>,
+        rise_width: Time,
+        plateau: Time,
+        fall_shape: Option<Shape>,
+        fall_width: Time,
+    ) -> Self {
+        let edge = |shape: Option<Shape>, width: Time| match shape {
+            Some(shape) if width != Time::ZERO => (Some(shape), width, Time::ZERO),
+            _ => (None, Time::ZERO, width / 2.0),
+        };
+        let (rise_shape, rise_width, rise_plateau) = edge(rise_shape, rise_width);
+        let (fall_shape, fall_width, fall_plateau) = edge(fall_shape, fall_width);
+        let plateau = plateau + rise_plateau + fall_plateau;
+        if rise_shape == fall_shape && rise_width == fall_width {
+            return Self::new(rise_shape, rise_width, plateau);
+        }
+        Self {
+            shape: rise_shape,
+            width: rise_width,
+            plateau,
+            fall: Some(FallingEdge {
+                shape: fall_shape,
+                width: fall_width,
+            }),
+        }
+    }
+
+    pub(crate) fn duration(&self) -> Time {
+        match &self.fall {
+            Some(fall) => (self.width + fall.width) / 2.0 + self.plateau,
+            None => self.width + self.plateau,
+        }
+    }
+
+    /// A plateau without shaped edges.
+    fn is_rectangular(&self) -> bool {
+        self.shape.is_none() && self.fall.is_none()
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 struct ListBin {
     envelope: Envelope,
     global_freq: Frequency,
     local_freq: Frequency,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Frequencies, width, plateau, shape, and falling edge of a bin.
+type BinOrder<'a> = (
+    Frequency,
+    Frequency,
+    Time,
+    Time,
+    Option<ShapeOrder<'a>>,
+    Option<(Time, Option<ShapeOrder<'a>>)>,
+);
+
+impl ListBin {
+    /// Key of the fixed order of the bins of a channel.
+    ///
+    /// Pulses of different bins overlap, so the order of the bins is the
+    /// order of the floating point summation into the waveform. Iterating the
+    /// hash map depends on its random seed and insertion history, sorting by
+    /// content keeps the waveforms bitwise identical between runs and thread
+    /// counts. See [`Shape::order_key`] for the order of custom shapes.
+    fn order_key(&self) -> BinOrder<'_> {
+        let envelope = &self.envelope;
+        (
+            self.global_freq,
+            self.local_freq,
+            envelope.width,
+            envelope.plateau,
+            envelope.shape.as_ref().map(Shape::order_key),
+            envelope
+                .fall
+                .as_ref()
+                .map(|f| (f.width, f.shape.as_ref().map(Shape::order_key))),
+        )
+    }
+}
+
+/// `bins` sorted by [`ListBin::order_key`].
+fn in_bin_order<'a, V>(bins: impl IntoIterator<Item = (&'a ListBin, V)>) -> Vec<(&'a ListBin, V)> {
+    let mut bins: Vec<_> = bins.into_iter().collect();
+    bins.sort_by(|(a, _), (b, _)| a.order_key().cmp(&b.order_key()));
+    bins
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 struct PulseAmplitude {
     // Amplitude of the pulse
     amp: Complex64,
@@ -85,9 +196,196 @@ impl Mul<f64> for PulseAmplitude {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Time span of a pulse pushed with a user ID.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PulseSource {
+    id: u64,
+    start: Time,
+    end: Time,
+}
+
+/// Pulses grouped by [`ListBin`] and sorted by time.
+///
+/// Serialized as a sequence of `(bin, pulses)` pairs because the bins can not
+/// be used as keys of a JSON object. The IDs of the pulses are debug
+/// information and not serialized.
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct PulseList {
     items: HashMap<ListBin, Vec<(Time, PulseAmplitude)>>,
+    sources: Vec<PulseSource>,
+}
+
+/// Pulses of a [`PulseList`] sharing an envelope and frequencies, which only
+/// differ in time and complex amplitude.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BinSummary {
+    /// `None` for rectangular envelopes.
+    pub(crate) shape: Option<Shape>,
+    pub(crate) width: Time,
+    pub(crate) plateau: Time,
+    /// Shape and width of the falling edge, `None` for symmetric envelopes.
+    pub(crate) fall: Option<(Option<Shape>, Time)>,
+    pub(crate) global_freq: Frequency,
+    pub(crate) local_freq: Frequency,
+    pub(crate) count: usize,
+}
+
+impl BinSummary {
+    /// Number of samples of one envelope at `sample_rate`, the sampled
+    /// envelope can be one sample longer depending on the sub-sample offset.
+    pub(crate) fn samples(&self, sample_rate: Frequency) -> usize {
+        let duration = match &self.fall {
+            Some((_, fall_width)) => (self.width + *fall_width) / 2.0 + self.plateau,
+            None => self.width + self.plateau,
+        };
+        (duration.value() * sample_rate.value()).ceil() as usize
+    }
+}
+
+impl PulseList {
+    /// IDs of the pulses overlapping the time range from `start` to `end`, in
+    /// ascending order.
+    ///
+    /// Only pulses pushed with an ID are reported. Pulses merged or coalesced
+    /// with others keep their IDs.
+    pub(crate) fn contributors(&self, start: Time, end: Time) -> Vec<u64> {
+        let mut ids: Vec<_> = self
+            .sources
+            .iter()
+            .filter(|s| s.start < end && s.end > start)
+            .map(|s| s.id)
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Unique envelopes and frequencies with their numbers of occurrences,
+    /// the most frequent first.
+    ///
+    /// Backends that store each envelope once and play it with per-pulse
+    /// amplitude and phase need memory for one envelope per bin instead of
+    /// one per pulse.
+    pub(crate) fn bins(&self) -> Vec<BinSummary> {
+        let mut bins: Vec<_> = self
+            .items
+            .iter()
+            .map(|(bin, items)| BinSummary {
+                shape: bin.envelope.shape.clone(),
+                width: bin.envelope.width,
+                plateau: bin.envelope.plateau,
+                fall: bin
+                    .envelope
+                    .fall
+                    .as_ref()
+                    .map(|f| (f.shape.clone(), f.width)),
+                global_freq: bin.global_freq,
+                local_freq: bin.local_freq,
+                count: items.len(),
+            })
+            .collect();
+        bins.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(a.width.cmp(&b.width))
+                .then(a.plateau.cmp(&b.plateau))
+                .then(a.global_freq.cmp(&b.global_freq))
+                .then(a.local_freq.cmp(&b.local_freq))
+        });
+        bins
+    }
+
+    /// Merge back-to-back rectangular pulses with the same frequencies into
+    /// one longer plateau, and return the number of merged pulses.
+    ///
+    /// A pulse is merged into the previous one if it starts at the end of the
+    /// previous one within `time_tolerance`, and its amplitude is that of the
+    /// previous one with the phase advanced by the local frequency over its
+    /// duration, within `amp_tolerance`. Shaped envelopes rise and fall at both
+    /// ends, so only rectangular pulses are merged.
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn merge_adjacent(
+        &mut self,
+        amp_tolerance: Amplitude,
+        time_tolerance: Time,
+    ) -> usize {
+        let mut plateaus: HashMap<_, Vec<_>> = HashMap::new();
+        self.items.retain(|bin, pulses| {
+            if !bin.envelope.is_rectangular() {
+                return true;
+            }
+            plateaus
+                .entry((bin.global_freq, bin.local_freq))
+                .or_default()
+                .extend(
+                    pulses
+                        .iter()
+                        .map(|&(time, amp)| (time, bin.envelope.plateau, amp)),
+                );
+            false
+        });
+        let mut merged = 0;
+        for ((global_freq, local_freq), mut pulses) in plateaus {
+            pulses.sort_unstable_by_key(|&(time, _, _)| time);
+            let mut chains: Vec<(Time, Time, PulseAmplitude)> = Vec::new();
+            for (time, plateau, amp) in pulses {
+                if let Some((start, length, first)) = chains.last_mut() {
+                    let expected = first.amp * (local_freq * *length).phaser();
+                    if approx_eq!(
+                        f64,
+                        (*start + *length).value(),
+                        time.value(),
+                        epsilon = time_tolerance.value()
+                    ) && (amp.amp - expected).norm() <= amp_tolerance.value()
+                    {
+                        *length = time + plateau - *start;
+                        merged += 1;
+                        continue;
+                    }
+                }
+                chains.push((time, plateau, amp));
+            }
+            for (time, plateau, amp) in chains {
+                let bin = ListBin {
+                    envelope: Envelope::new(None, Time::ZERO, plateau),
+                    global_freq,
+                    local_freq,
+                };
+                self.items.entry(bin).or_default().push((time, amp));
+            }
+        }
+        for pulses in self.items.values_mut() {
+            pulses.sort_unstable_by_key(|(time, _)| *time);
+        }
+        merged
+    }
+}
+
+impl Serialize for PulseList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(in_bin_order(&self.items))
+    }
+}
+
+impl crate::codec::Versioned for PulseList {
+    /// Version 2 if a pulse has an asymmetric envelope.
+    fn required_version(&self) -> u32 {
+        if self.items.keys().any(|bin| bin.envelope.fall.is_some()) {
+            2
+        } else {
+            crate::codec::MIN_VERSION
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PulseList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let items = Vec::<(ListBin, Vec<(Time, PulseAmplitude)>)>::deserialize(deserializer)?;
+        Ok(Self {
+            items: items.into_iter().collect(),
+            sources: Vec::new(),
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -102,11 +400,37 @@ impl<'a> Crosstalk<'a> {
     }
 }
 
+/// Continuous carrier on a channel while none of its pulses is playing, e.g.
+/// a phase reference or a tone to calibrate the LO leakage.
+///
+/// The phase follows `freq` from time zero of the channel, so the tone stays
+/// coherent across the idle periods.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct IdleTone {
+    pub(crate) amplitude: Amplitude,
+    pub(crate) freq: Frequency,
+    #[serde(default)]
+    pub(crate) phase: Phase,
+}
+
 #[derive(Debug)]
 pub(crate) struct Sampler<'a> {
     channels: HashMap<ChannelId, Channel<'a>>,
     pulse_lists: HashMap<ChannelId, PulseList>,
     crosstalk: Option<Crosstalk<'a>>,
+    net_zero: HashSet<ChannelId>,
+    area_tolerance: Amplitude,
+    idle_tones: HashMap<ChannelId, IdleTone>,
+    options: SampleOptions,
+}
+
+/// Options of [`Sampler`] shared by all channels.
+#[derive(Debug, Clone, Copy, Default)]
+struct SampleOptions {
+    allow_truncate: bool,
+    fractional_delay: bool,
+    oversampling: usize,
 }
 
 impl<'a> Sampler<'a> {
@@ -115,9 +439,18 @@ impl<'a> Sampler<'a> {
             channels: HashMap::new(),
             pulse_lists,
             crosstalk: None,
+            net_zero: HashSet::new(),
+            area_tolerance: Amplitude::ZERO,
+            idle_tones: HashMap::new(),
+            options: SampleOptions::default(),
         }
     }
 
+    /// Add an output channel.
+    ///
+    /// `baseline` is the level of the waveform when no pulse is playing, the
+    /// pulses are added on top of it. Only the real part is used if the
+    /// waveform has a single row.
     pub(crate) fn add_channel(
         &mut self,
         name: ChannelId,
@@ -125,14 +458,16 @@ impl<'a> Sampler<'a> {
         sample_rate: Frequency,
         delay: Time,
         align_level: i32,
+        baseline: Complex64,
     ) {
         self.channels.insert(
-            name.clone(),
+            name,
             Channel {
                 waveform,
                 sample_rate,
                 align_level,
                 delay,
+                baseline,
             },
         );
     }
@@ -141,51 +476,270 @@ impl<'a> Sampler<'a> {
         self.crosstalk = Some(Crosstalk::new(crosstalk, names));
     }
 
-    pub(crate) fn sample(self, time_tolerance: Time) -> Result<()> {
-        if let Some(crosstalk) = self.crosstalk {
-            let ct_lookup = crosstalk
+    /// Report the residual time integral of the pulses of `channels`.
+    ///
+    /// The baseline and the idle tone are not part of the integral. A warning
+    /// is emitted if the integral of the first row exceeds one sample at
+    /// `amp_tolerance`.
+    pub(crate) fn set_net_zero(
+        &mut self,
+        channels: impl IntoIterator<Item = ChannelId>,
+        amp_tolerance: Amplitude,
+    ) {
+        self.net_zero = channels.into_iter().collect();
+        self.area_tolerance = amp_tolerance;
+    }
+
+    /// Fill the idle periods of the channels with their tones. A channel is
+    /// idle when none of its own pulses is playing, pulses added by crosstalk
+    /// are not considered.
+    pub(crate) fn set_idle_tones(
+        &mut self,
+        tones: impl IntoIterator<Item = (ChannelId, IdleTone)>,
+    ) {
+        self.idle_tones = tones.into_iter().collect();
+    }
+
+    /// Clip pulses partially or fully outside the waveform instead of failing.
+    pub(crate) fn set_allow_truncate(&mut self, allow_truncate: bool) {
+        self.options.allow_truncate = allow_truncate;
+    }
+
+    /// Realize sub-sample pulse offsets by interpolating the envelope instead
+    /// of aligning the start of the pulse to the grid of the channel.
+    ///
+    /// The envelope is sampled on the integer sample grid and delayed by the
+    /// residual with a windowed sinc filter, so `align_level` of the channels
+    /// is ignored.
+    pub(crate) fn set_fractional_delay(&mut self, fractional_delay: bool) {
+        self.options.fractional_delay = fractional_delay;
+    }
+
+    /// Sample the envelopes of shaped pulses at `oversampling` times the rate
+    /// of the channel and decimate them with an anti-aliasing filter.
+    ///
+    /// This reduces the staircase error of pulses only a few samples wide.
+    /// The filter spreads the envelope by a few samples on each side, which
+    /// is clipped silently at the ends of the waveform. The DRAG slope is
+    /// derived from the decimated envelope. Factors below 2 disable it.
+    pub(crate) fn set_oversampling(&mut self, oversampling: usize) {
+        self.options.oversampling = oversampling;
+    }
+
+    /// Precompute the envelopes and carriers of the shaped pulses, and return
+    /// the number of distinct envelopes.
+    ///
+    /// Envelopes and carriers are cached per process, so a following
+    /// [`sample`](Self::sample) with the same channels and options only looks
+    /// them up, e.g. to keep the first shot of a sweep as fast as the rest.
+    /// Call it after all channels and options are set.
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn prewarm(&self) -> usize {
+        let mut envelopes = HashSet::new();
+        let mut carriers = HashSet::new();
+        for (n, c) in &self.channels {
+            let dt = c.sample_rate.dt();
+            for (bin, pulses) in self.source_lists(*n).into_iter().flat_map(|l| &l.items) {
+                if bin.envelope.is_rectangular() {
+                    continue;
+                }
+                let dphase = (bin.global_freq + bin.local_freq) * dt;
+                for &(time, _) in pulses {
+                    let t_start = time + c.delay;
+                    let index = start_index(t_start, c.sample_rate, c.align_level, self.options);
+                    let delayed = self.options.fractional_delay
+                        && t_start.value() * c.sample_rate.value() != index.value().ceil();
+                    envelopes.insert((
+                        &bin.envelope,
+                        index.index_offset().unwrap(),
+                        c.sample_rate,
+                        dphase,
+                        delayed,
+                    ));
+                }
+            }
+        }
+        let oversampling = self.options.oversampling;
+        let prewarm = |&(envelope, index_offset, sample_rate, _, delayed): &(
+            &Envelope,
+            AlignedIndex,
+            Frequency,
+            Phase,
+            bool,
+        )| {
+            let samples = get_envelope(envelope.clone(), index_offset, sample_rate, oversampling);
+            samples.len() + usize::from(delayed)
+        };
+        let keys: Vec<_> = envelopes.into_iter().collect();
+        #[cfg(feature = "parallel")]
+        let lengths: Vec<_> = keys.par_iter().map(prewarm).collect();
+        #[cfg(not(feature = "parallel"))]
+        let lengths: Vec<_> = keys.iter().map(prewarm).collect();
+        for (key, length) in keys.iter().zip(lengths) {
+            carriers.insert((key.3, length));
+        }
+        #[cfg(feature = "parallel")]
+        carriers.par_iter().for_each(|&(dphase, length)| {
+            get_carrier(dphase, length);
+        });
+        #[cfg(not(feature = "parallel"))]
+        carriers.iter().for_each(|&(dphase, length)| {
+            get_carrier(dphase, length);
+        });
+        keys.iter()
+            .map(|k| (k.0, k.1, k.2))
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// IDs of the pulses contributing to the `samples` of a channel, in
+    /// ascending order.
+    ///
+    /// This is a debug aid to trace a glitch in a waveform back to the
+    /// schedule. The pulses are found by their time spans, shifted by the
+    /// delay of the channel, and include the pulses of other channels mixed in
+    /// by crosstalk. Returns an empty list for unknown channels.
+    #[cfg_attr(not(feature = "ffi"), allow(dead_code))]
+    pub(crate) fn contributors(&self, channel: ChannelId, samples: Range<usize>) -> Vec<u64> {
+        let Some(c) = self.channels.get(&channel) else {
+            return Vec::new();
+        };
+        let time = |i: usize| Time::new(i as f64 / c.sample_rate.value()).unwrap() - c.delay;
+        let (start, end) = (time(samples.start), time(samples.end));
+        let mut ids: Vec<_> = self
+            .source_lists(channel)
+            .into_iter()
+            .flat_map(|l| l.contributors(start, end))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Pulse lists sampled into a channel, which are those of the crosstalk
+    /// sources if the channel has crosstalk.
+    fn source_lists(&self, channel: ChannelId) -> Vec<&PulseList> {
+        match &self.crosstalk {
+            Some(crosstalk) if crosstalk.names.contains(&channel) => {
+                let i = crosstalk.names.iter().position(|m| *m == channel).unwrap();
+                crosstalk
+                    .matrix
+                    .row(i)
+                    .iter()
+                    .zip(&crosstalk.names)
+                    .filter(|(&multiplier, _)| multiplier != 0.0)
+                    .filter_map(|(_, m)| self.pulse_lists.get(m))
+                    .collect()
+            }
+            _ => self.pulse_lists.get(&channel).into_iter().collect(),
+        }
+    }
+
+    /// Sample the pulse lists and return the collected warnings.
+    pub(crate) fn sample(self, time_tolerance: Time) -> Result<Diagnostics> {
+        let Self {
+            channels,
+            pulse_lists,
+            crosstalk,
+            net_zero,
+            area_tolerance,
+            idle_tones,
+            options,
+        } = self;
+        #[cfg(feature = "tracing")]
+        let sample_span = tracing::debug_span!("sample");
+        #[cfg(feature = "tracing")]
+        let _guard = sample_span.enter();
+        let ct_lookup = crosstalk.as_ref().map(|crosstalk| {
+            crosstalk
                 .names
                 .iter()
                 .enumerate()
                 .map(|(i, name)| (name, i))
-                .collect::<HashMap<_, _>>();
-            self.channels.into_par_iter().try_for_each(|(n, c)| {
-                let row_index = ct_lookup.get(&n).copied();
-                if let Some(row_index) = row_index {
-                    let row = crosstalk.matrix.slice(s![row_index, ..]);
-                    let lists = row
-                        .iter()
-                        .copied()
-                        .zip(&crosstalk.names)
-                        .map(|(multiplier, in_name)| (multiplier, &self.pulse_lists[in_name]));
-                    merge_and_sample(
-                        lists,
-                        c.waveform,
-                        c.sample_rate,
-                        c.delay,
-                        c.align_level,
-                        time_tolerance,
-                    )
-                    .with_context(|| format!("Failed to sample channel '{}'", n))
-                } else {
-                    let list = self.pulse_lists[&n]
-                        .items
-                        .iter()
-                        .map(|(bin, items)| (bin.clone(), items.iter().copied()));
-                    sample_pulse_list(list, c.waveform, c.sample_rate, c.delay, c.align_level)
-                        .with_context(|| format!("Failed to sample channel '{}'", n))
-                }
-            })
-        } else {
-            self.channels.into_par_iter().try_for_each(|(n, c)| {
-                let list = self.pulse_lists[&n]
-                    .items
+                .collect::<HashMap<_, _>>()
+        });
+        let sample_channel = |scratch: &mut _, (n, mut c): (ChannelId, Channel)| {
+            // Rayon workers don't inherit the current span, so the parent is
+            // set explicitly.
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!(parent: &sample_span, "channel", name = %n).entered();
+            let row_index = ct_lookup.as_ref().and_then(|l| l.get(&n).copied());
+            let truncated = if let (Some(crosstalk), Some(row_index)) = (&crosstalk, row_index) {
+                let row = crosstalk.matrix.slice(s![row_index, ..]);
+                let lists = row
                     .iter()
-                    .map(|(bin, items)| (bin.clone(), items.iter().copied()));
-                sample_pulse_list(list, c.waveform, c.sample_rate, c.delay, c.align_level)
-                    .with_context(|| format!("Failed to sample channel '{}'", n))
-            })
+                    .copied()
+                    .zip(&crosstalk.names)
+                    .map(|(multiplier, in_name)| (multiplier, &pulse_lists[in_name]));
+                merge_and_sample(
+                    lists,
+                    scratch,
+                    c.waveform.view_mut(),
+                    c.sample_rate,
+                    c.delay,
+                    c.align_level,
+                    time_tolerance,
+                    options,
+                )
+                .with_context(|| format!("Failed to sample channel '{}'", n))?
+            } else {
+                let list = in_bin_order(&pulse_lists[&n].items)
+                    .into_iter()
+                    .map(|(bin, items)| (bin, items.iter().copied()));
+                sample_pulse_list(
+                    list,
+                    c.waveform.view_mut(),
+                    c.sample_rate,
+                    c.delay,
+                    c.align_level,
+                    options,
+                )
+                .with_context(|| format!("Failed to sample channel '{}'", n))?
+            };
+            let mut diagnostics = Diagnostics::new();
+            for time in truncated {
+                diagnostics.warn(Warning::Truncated { channel: n, time });
+            }
+            if net_zero.contains(&n) {
+                let area = waveform_stats(c.waveform.view(), c.sample_rate).area.re;
+                if area.abs() > area_tolerance.value() * c.sample_rate.dt().value() {
+                    diagnostics.warn(Warning::NetArea { channel: n, area });
+                }
+            }
+            add_baseline(&mut c.waveform, c.baseline);
+            if let Some(tone) = idle_tones.get(&n) {
+                add_idle_tone(
+                    c.waveform.view_mut(),
+                    &pulse_lists[&n],
+                    tone,
+                    c.sample_rate,
+                    c.delay,
+                );
+            }
+            Ok(diagnostics)
+        };
+        // Channels are sampled independently, sorting them only fixes the
+        // order of the warnings.
+        let mut channels: Vec<_> = channels.into_iter().collect();
+        channels.sort_unstable_by_key(|(n, _)| *n);
+        #[cfg(feature = "parallel")]
+        let results: Vec<_> = channels
+            .into_par_iter()
+            .map_init(MergeScratch::new, sample_channel)
+            .collect::<Result<_>>()?;
+        #[cfg(not(feature = "parallel"))]
+        let results: Vec<_> = {
+            let mut scratch = MergeScratch::new();
+            channels
+                .into_iter()
+                .map(|c| sample_channel(&mut scratch, c))
+                .collect::<Result<_>>()?
+        };
+        let mut diagnostics = Diagnostics::new();
+        for d in results {
+            diagnostics.extend(d);
         }
+        Ok(diagnostics)
     }
 }
 
@@ -195,11 +749,66 @@ struct Channel<'a> {
     sample_rate: Frequency,
     align_level: i32,
     delay: Time,
+    baseline: Complex64,
+}
+
+/// Add the resting level of a channel, the imaginary part is dropped for real
+/// channels.
+fn add_baseline(waveform: &mut ArrayViewMut2<f64>, baseline: Complex64) {
+    for (mut row, level) in waveform
+        .axis_iter_mut(Axis(0))
+        .zip([baseline.re, baseline.im])
+    {
+        row += level;
+    }
+}
+
+/// Add the tone to the samples not covered by a pulse of `pulse_list`.
+fn add_idle_tone(
+    mut waveform: ArrayViewMut2<f64>,
+    pulse_list: &PulseList,
+    tone: &IdleTone,
+    sample_rate: Frequency,
+    delay: Time,
+) {
+    let length = waveform.ncols();
+    let index = |t: Time| {
+        ((t + delay).value() * sample_rate.value())
+            .ceil()
+            .clamp(0.0, length as f64) as usize
+    };
+    let mut busy: Vec<_> = pulse_list
+        .items
+        .iter()
+        .flat_map(|(bin, pulses)| {
+            let duration = bin.envelope.duration();
+            pulses
+                .iter()
+                .map(move |&(time, _)| (index(time), index(time + duration)))
+        })
+        .collect();
+    busy.sort_unstable();
+    let dt = sample_rate.dt();
+    let amplitude = Complex64::from(tone.amplitude.value());
+    let mut add = |begin: usize, end: usize| {
+        if begin < end {
+            let phase = (tone.freq * (dt * begin as f64 - delay) + tone.phase).wrapped();
+            let waveform = waveform.slice_mut(s![.., begin..end]);
+            mix_add_plateau(waveform, amplitude, phase, tone.freq * dt);
+        }
+    };
+    let mut idle_start = 0;
+    for (begin, end) in busy {
+        add(idle_start, begin);
+        idle_start = idle_start.max(end);
+    }
+    add(idle_start, length);
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct PulseListBuilder {
     items: HashMap<ListBin, Vec<(Time, PulseAmplitude)>>,
+    sources: Vec<PulseSource>,
     amp_tolerance: Amplitude,
     time_tolerance: Time,
 }
@@ -212,17 +821,21 @@ pub(crate) struct PushArgs {
     pub(crate) amplitude: Amplitude,
     pub(crate) drag_coef: f64,
     pub(crate) phase: Phase,
+    /// User ID to find the pulse with [`PulseList::contributors`].
+    pub(crate) id: Option<u64>,
 }
 
 impl PulseListBuilder {
     pub(crate) fn new(amp_tolerance: Amplitude, time_tolerance: Time) -> Self {
         Self {
             items: HashMap::new(),
+            sources: Vec::new(),
             amp_tolerance,
             time_tolerance,
         }
     }
 
+    /// Returns `false` if the pulse is dropped because of zero amplitude.
     pub(crate) fn push(
         &mut self,
         PushArgs {
@@ -233,15 +846,23 @@ impl PulseListBuilder {
             amplitude,
             drag_coef,
             phase,
+            id,
         }: PushArgs,
-    ) {
+    ) -> bool {
         if approx_eq!(
             f64,
             amplitude.value(),
             0.0,
             epsilon = self.amp_tolerance.value()
         ) {
-            return;
+            return false;
+        }
+        if let Some(id) = id {
+            self.sources.push(PulseSource {
+                id,
+                start: time,
+                end: time + envelope.duration(),
+            });
         }
         let bin = ListBin {
             envelope,
@@ -252,41 +873,71 @@ impl PulseListBuilder {
         let drag = amp * Complex64::i() * drag_coef;
         let amplitude = PulseAmplitude { amp, drag };
         self.items.entry(bin).or_default().push((time, amplitude));
+        true
     }
 
-    pub(crate) fn build(mut self) -> PulseList {
-        for pulses in self.items.values_mut() {
-            pulses.sort_unstable_by_key(|(time, _)| *time);
-            let mut i = 0;
-            for j in 1..pulses.len() {
-                if approx_eq!(
-                    f64,
-                    pulses[i].0.value(),
-                    pulses[j].0.value(),
-                    epsilon = self.time_tolerance.value()
-                ) {
-                    pulses[i].1 = pulses[i].1 + pulses[j].1;
-                } else {
-                    i += 1;
-                    pulses[i] = pulses[j];
-                }
-            }
-            pulses.truncate(i + 1);
+    /// Build the pulse list and call `on_coalesce` with the time of each pulse
+    /// merged into a previous one.
+    pub(crate) fn build_with(mut self, mut on_coalesce: impl FnMut(Time)) -> PulseList {
+        let time_tolerance = self.time_tolerance;
+        // Bins are independent, only the callback has to run serially.
+        #[cfg(feature = "parallel")]
+        let coalesced: Vec<_> = self
+            .items
+            .par_values_mut()
+            .map(|pulses| coalesce(pulses, time_tolerance))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let coalesced: Vec<_> = self
+            .items
+            .values_mut()
+            .map(|pulses| coalesce(pulses, time_tolerance))
+            .collect();
+        coalesced.into_iter().flatten().for_each(&mut on_coalesce);
+        PulseList {
+            items: self.items,
+            sources: self.sources,
+        }
+    }
+}
+
+/// Sort the pulses of a bin and merge pulses at the same time.
+///
+/// Returns the times of the pulses merged into a previous one.
+fn coalesce(pulses: &mut Vec<(Time, PulseAmplitude)>, time_tolerance: Time) -> Vec<Time> {
+    let mut coalesced = Vec::new();
+    pulses.sort_unstable_by_key(|(time, _)| *time);
+    let mut i = 0;
+    for j in 1..pulses.len() {
+        if approx_eq!(
+            f64,
+            pulses[i].0.value(),
+            pulses[j].0.value(),
+            epsilon = time_tolerance.value()
+        ) {
+            pulses[i].1 = pulses[i].1 + pulses[j].1;
+            coalesced.push(pulses[j].0);
+        } else {
+            i += 1;
+            pulses[i] = pulses[j];
         }
-        PulseList { items: self.items }
     }
+    pulses.truncate(i + 1);
+    coalesced
 }
 
+/// Add the envelope starting from the `skip`-th sample to the waveform.
+///
+/// `carrier` is the table returned by [`get_carrier`] for the envelope, the
+/// initial phase of the pulse is included in the amplitudes.
 fn mix_add_envelope(
     mut waveform: ArrayViewMut2<f64>,
     envelope: &[f64],
+    carrier: &[Complex64],
+    skip: usize,
     amplitude: Complex64,
     drag_amp: Complex64,
-    phase0: Phase,
-    dphase: Phase,
 ) {
-    let mut carrier = phase0.phaser();
-    let dcarrier = dphase.phaser();
     let slope_iter = (0..envelope.len()).map(|i| {
         let left = if i > 0 { envelope[i - 1] } else { 0.0 };
         let right = if i < envelope.len() - 1 {
@@ -296,13 +947,17 @@ fn mix_add_envelope(
         };
         (right - left) / 2.0
     });
-    for (mut y, env, slope) in izip!(waveform.columns_mut(), envelope.iter().copied(), slope_iter) {
+    for (mut y, env, slope, &carrier) in izip!(
+        waveform.columns_mut(),
+        envelope.iter().copied().skip(skip),
+        slope_iter.skip(skip),
+        carrier.iter().skip(skip)
+    ) {
         let w = carrier * (amplitude * env + drag_amp * slope);
         y[0] += w.re;
         if let Some(y1) = y.get_mut(1) {
             *y1 += w.im;
         }
-        carrier *= dcarrier;
     }
 }
 
@@ -323,14 +978,46 @@ fn mix_add_plateau(
     }
 }
 
+/// Carrier phasors `exp(i * dphase * k)` for `k` in `0..length`.
+///
+/// Shaped pulses of the same bin share the frequency and the envelope length,
+/// so dense sequences compute the table once per bin instead of rotating the
+/// carrier sample by sample for every pulse.
+#[cached(size = 1024)]
+fn get_carrier(dphase: Phase, length: usize) -> Arc<Vec<Complex64>> {
+    Arc::new(
+        (0..length)
+            .map(|k| (dphase * k as f64).wrapped().phaser())
+            .collect(),
+    )
+}
+
 #[cached(size = 1024)]
 fn get_envelope(
-    shape: Shape,
-    width: Time,
-    plateau: Time,
+    envelope: Envelope,
     index_offset: AlignedIndex,
     sample_rate: Frequency,
+    oversampling: usize,
 ) -> Arc<Vec<f64>> {
+    let Envelope {
+        shape,
+        width,
+        plateau,
+        fall,
+    } = envelope;
+    if let Some(fall) = fall {
+        return Arc::new(asymmetric_envelope(
+            shape.as_ref(),
+            width.value(),
+            plateau.value(),
+            fall.shape.as_ref(),
+            fall.width.value(),
+            index_offset.value() / sample_rate.value(),
+            1.0 / sample_rate.value(),
+            oversampling,
+        ));
+    }
+    let shape = shape.expect("Symmetric envelope should be shaped");
     let width = width.value();
     let plateau = plateau.value();
     let index_offset = index_offset.value();
@@ -343,6 +1030,17 @@ fn get_envelope(
     let length = (t3 * sample_rate).ceil() as usize;
     let plateau_start_index = (t1 * sample_rate).ceil() as usize;
     let plateau_end_index = (t2 * sample_rate).ceil() as usize;
+    if oversampling > 1 {
+        return Arc::new(oversampled_envelope(
+            &shape,
+            width,
+            plateau,
+            t_offset,
+            dt,
+            length,
+            oversampling,
+        ));
+    }
     let mut envelope = vec![0.0; length];
     let x0 = -t1 / width;
     let dx = dt / width;
@@ -357,109 +1055,321 @@ fn get_envelope(
     Arc::new(envelope)
 }
 
+/// Envelope sampled at `factor` times the rate and decimated, with
+/// [`decimate::PAD`] extra samples on each side.
+///
+/// Sample `PAD` of the result is at `t_offset` after the start of the pulse.
+fn oversampled_envelope(
+    shape: &Shape,
+    width: f64,
+    plateau: f64,
+    t_offset: f64,
+    dt: f64,
+    length: usize,
+    factor: usize,
+) -> Vec<f64> {
+    let length = length + 2 * decimate::PAD;
+    let margin = decimate::margin(factor);
+    let fine_dt = dt / factor as f64;
+    let t0 = t_offset - (decimate::PAD * factor + margin) as f64 * fine_dt;
+    let mut fine = vec![0.0; (length - 1) * factor + 2 * margin + 1];
+    let n = fine.len();
+    // Shapes are not necessarily zero outside of [-0.5, 0.5], so only the
+    // samples within the pulse are evaluated.
+    let index = |t: f64| (((t - t0) / fine_dt).ceil().max(0.0) as usize).min(n);
+    let rise = index(0.0);
+    let plateau_start = index(width / 2.0);
+    let plateau_end = index(width / 2.0 + plateau);
+    let end = index(width + plateau);
+    let x = |i: usize, shift: f64| (t0 + i as f64 * fine_dt - shift - width / 2.0) / width;
+    let dx = fine_dt / width;
+    shape.sample_array(x(rise, 0.0), dx, &mut fine[rise..plateau_start]);
+    fine[plateau_start..plateau_end].fill(1.0);
+    shape.sample_array(x(plateau_end, plateau), dx, &mut fine[plateau_end..end]);
+    decimate::decimate(&fine, factor, length)
+}
+
+/// Envelope with different rising and falling edges, see
+/// [`Envelope::asymmetric`].
+///
+/// Sample `k` is at `t_offset + k * dt` after the start of the pulse. With
+/// oversampling, the result has [`decimate::PAD`] extra samples on each side
+/// like [`oversampled_envelope`].
+#[allow(clippy::too_many_arguments)]
+fn asymmetric_envelope(
+    rise_shape: Option<&Shape>,
+    rise_width: f64,
+    plateau: f64,
+    fall_shape: Option<&Shape>,
+    fall_width: f64,
+    t_offset: f64,
+    dt: f64,
+    oversampling: usize,
+) -> Vec<f64> {
+    let duration = (rise_width + fall_width) / 2.0 + plateau;
+    let length = ((duration - t_offset) / dt).ceil() as usize;
+    let (t0, dt, mut samples) = if oversampling > 1 {
+        let length = length + 2 * decimate::PAD;
+        let margin = decimate::margin(oversampling);
+        let fine_dt = dt / oversampling as f64;
+        let t0 = t_offset - (decimate::PAD * oversampling + margin) as f64 * fine_dt;
+        (
+            t0,
+            fine_dt,
+            vec![0.0; (length - 1) * oversampling + 2 * margin + 1],
+        )
+    } else {
+        (t_offset, dt, vec![0.0; length])
+    };
+    let n = samples.len();
+    // Shapes are not necessarily zero outside of [-0.5, 0.5], so only the
+    // samples within the pulse are evaluated.
+    let index = |t: f64| (((t - t0) / dt).ceil().max(0.0) as usize).min(n);
+    let plateau_start = rise_width / 2.0;
+    let plateau_end = plateau_start + plateau;
+    let rise = index(0.0);
+    let (i1, i2, end) = (index(plateau_start), index(plateau_end), index(duration));
+    let x = |i: usize, center: f64, width: f64| (t0 + i as f64 * dt - center) / width;
+    if let Some(shape) = rise_shape {
+        let x0 = x(rise, plateau_start, rise_width);
+        shape.sample_array(x0, dt / rise_width, &mut samples[rise..i1]);
+    }
+    samples[i1..i2].fill(1.0);
+    if let Some(shape) = fall_shape {
+        let x0 = x(i2, plateau_end, fall_width);
+        shape.sample_array(x0, dt / fall_width, &mut samples[i2..end]);
+    }
+    if oversampling > 1 {
+        decimate::decimate(&samples, oversampling, length + 2 * decimate::PAD)
+    } else {
+        samples
+    }
+}
+
+/// Bins of the input channels of a crosstalk row with the multiplier of each
+/// input. Reused between the channels sampled by the same worker.
+type MergeScratch<'a> = HashMap<&'a ListBin, Vec<(f64, &'a [(Time, PulseAmplitude)])>>;
+
+#[allow(clippy::too_many_arguments)]
 fn merge_and_sample<'a>(
     lists: impl IntoIterator<Item = (f64, &'a PulseList)>,
+    scratch: &mut MergeScratch<'a>,
     waveform: ArrayViewMut2<f64>,
     sample_rate: Frequency,
     delay: Time,
     align_level: i32,
     time_tolerance: Time,
-) -> Result<()> {
-    let mut merged: HashMap<ListBin, Vec<_>> = HashMap::new();
+    options: SampleOptions,
+) -> Result<Vec<Time>> {
+    scratch.clear();
     for (multiplier, list) in lists {
         if multiplier == 0.0 {
             continue;
         }
         for (bin, items) in &list.items {
-            merged.entry(bin.clone()).or_default().push(
-                items
-                    .iter()
-                    .map(move |&(time, amp)| (time, amp * multiplier)),
-            )
+            scratch.entry(bin).or_default().push((multiplier, items));
         }
     }
-    let merged = merged.into_iter().map(|(bin, items)| {
-        (
-            bin,
-            items
-                .into_iter()
-                .kmerge_by(|a, b| a.0 < b.0)
-                .coalesce(|a, b| {
-                    if approx_eq!(
-                        f64,
-                        a.0.value(),
-                        b.0.value(),
-                        epsilon = time_tolerance.value()
-                    ) {
-                        Ok((a.0, a.1 + b.1))
-                    } else {
-                        Err((a, b))
-                    }
-                }),
-        )
-    });
-    sample_pulse_list(merged, waveform, sample_rate, delay, align_level)
+    let merged = in_bin_order(scratch.iter().map(|(&bin, lists)| (bin, lists)))
+        .into_iter()
+        .map(|(bin, lists)| {
+            (
+                bin,
+                lists
+                    .iter()
+                    .map(|&(multiplier, items)| {
+                        items
+                            .iter()
+                            .map(move |&(time, amp)| (time, amp * multiplier))
+                    })
+                    .kmerge_by(|a, b| a.0 < b.0)
+                    .coalesce(|a, b| {
+                        if approx_eq!(
+                            f64,
+                            a.0.value(),
+                            b.0.value(),
+                            epsilon = time_tolerance.value()
+                        ) {
+                            Ok((a.0, a.1 + b.1))
+                        } else {
+                            Err((a, b))
+                        }
+                    }),
+            )
+        });
+    sample_pulse_list(merged, waveform, sample_rate, delay, align_level, options)
 }
 
-fn sample_pulse_list<PL, L>(
+fn sample_pulse_list<'a, PL, L>(
     list: PL,
     mut waveform: ArrayViewMut2<f64>,
     sample_rate: Frequency,
     delay: Time,
     align_level: i32,
-) -> Result<()>
+    options: SampleOptions,
+) -> Result<Vec<Time>>
 where
-    PL: IntoIterator<Item = (ListBin, L)>,
+    PL: IntoIterator<Item = (&'a ListBin, L)>,
     L: IntoIterator<Item = (Time, PulseAmplitude)>,
 {
+    let length = waveform.shape()[1];
+    let mut truncated = Vec::new();
     for (bin, items) in list {
-        let ListBin {
-            envelope,
+        let &ListBin {
+            ref envelope,
             global_freq,
             local_freq,
         } = bin;
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "bin",
+            global_freq = global_freq.value(),
+            local_freq = local_freq.value()
+        )
+        .entered();
         for (time, PulseAmplitude { amp, drag }) in items {
             let t_start = time + delay;
-            let i_frac_start = AlignedIndex::new(t_start, sample_rate, align_level).unwrap();
-            if i_frac_start.value() < 0.0 {
-                bail!("The start time of a pulse is negative, try adjusting channel delay or schedule. start time: {}", t_start.value());
-            }
-            let i_start = i_frac_start.ceil_to_usize().unwrap();
+            let i_frac_start = start_index(t_start, sample_rate, align_level, options);
+            let i_start = i_frac_start.value().ceil();
             let index_offset = i_frac_start.index_offset().unwrap();
+            // Sub-sample start of the pulse after `i_start`, only non-zero with
+            // fractional delay.
+            let residual = if options.fractional_delay {
+                t_start.value() * sample_rate.value() - i_start
+            } else {
+                0.0
+            };
+            // Oversampled envelopes start `pad` samples before `i_start`.
+            let pad = if !envelope.is_rectangular() && options.oversampling > 1 {
+                decimate::PAD
+            } else {
+                0
+            };
+            let i_first = i_start - pad as f64;
             let total_freq = global_freq + local_freq;
             let dt = sample_rate.dt();
-            let phase0 = global_freq * (i_start as f64 * dt - delay)
-                + local_freq * index_offset.value() * dt;
+            let phase0 = (global_freq * (i_first * dt - delay)
+                + local_freq * (index_offset.value() - pad as f64 - residual) * dt)
+                .wrapped();
             let dphase = total_freq * dt;
-            if i_start >= waveform.shape()[1] {
-                bail!("The start index of a pulse is out of bounds, try adjusting channel delay, length or schedule. start index: {}, start time: {}", i_start, t_start.value());
-            }
-            let mut waveform = waveform.slice_mut(s![.., i_start..]);
-            if let Some(shape) = &envelope.shape {
-                let envelope = get_envelope(
-                    shape.clone(),
-                    envelope.width,
-                    envelope.plateau,
+            let envelope_samples = (!envelope.is_rectangular()).then(|| {
+                let samples = get_envelope(
+                    envelope.clone(),
                     index_offset,
                     sample_rate,
+                    options.oversampling,
                 );
-                let drag = drag * sample_rate.value();
-                if waveform.shape()[1] < envelope.len() {
-                    bail!("The pulse end time is out of bounds, try adjusting channel delay, length or schedule. end time: {}", t_start.value() + envelope.len() as f64 * dt.value());
+                if residual > 0.0 {
+                    Arc::new(delay::delay_envelope(&samples, residual))
+                } else {
+                    samples
                 }
-                mix_add_envelope(waveform, &envelope, amp, drag, phase0, dphase);
-            } else {
-                let plateau = envelope.plateau;
-                let i_plateau = (plateau.value() * sample_rate.value()).ceil() as usize;
-                if waveform.shape()[1] < i_plateau {
-                    bail!("The pulse end time is out of bounds, try adjusting channel delay, length or schedule. end time: {}", t_start.value() + plateau.value());
+            });
+            let n_samples = match &envelope_samples {
+                Some(samples) => samples.len(),
+                None => (envelope.plateau.value() * sample_rate.value()).ceil() as usize,
+            };
+            let i_end = i_first + n_samples as f64;
+            // The padding is clipped silently.
+            let i_last = i_end - pad as f64;
+            if (i_start < 0.0 || i_last > length as f64) && !options.allow_truncate {
+                bail!(
+                    "Pulse is out of range of the channel, try adjusting channel delay, length or schedule. start time: {}, end time: {}, start index: {}, end index: {}, channel length: {}",
+                    t_start.value(),
+                    t_start.value() + (i_last - i_start) * dt.value(),
+                    i_start,
+                    i_last,
+                    length
+                );
+            }
+            // Clip the pulse to the waveform if truncation is allowed.
+            if i_start < 0.0 || i_last > length as f64 {
+                truncated.push(time);
+            }
+            let skip = (-i_first).max(0.0) as usize;
+            let begin = i_first.max(0.0) as usize;
+            let end = i_end.clamp(0.0, length as f64) as usize;
+            if begin >= end {
+                continue;
+            }
+            let waveform = waveform.slice_mut(s![.., begin..end]);
+            match envelope_samples {
+                Some(samples) => {
+                    let drag = drag * sample_rate.value();
+                    let carrier = get_carrier(dphase, samples.len());
+                    let phaser = phase0.phaser();
+                    mix_add_envelope(
+                        waveform,
+                        &samples,
+                        &carrier,
+                        skip,
+                        amp * phaser,
+                        drag * phaser,
+                    );
+                }
+                None => {
+                    let phase0 = phase0.wrapping_add(dphase * skip as f64);
+                    mix_add_plateau(waveform, amp, phase0, dphase);
                 }
-                let waveform = waveform.slice_mut(s![.., ..i_plateau]);
-                mix_add_plateau(waveform, amp, phase0, dphase);
             }
         }
     }
-    Ok(())
+    Ok(truncated)
+}
+
+/// Fractional sample index of a pulse starting at `t_start`.
+fn start_index(
+    t_start: Time,
+    sample_rate: Frequency,
+    align_level: i32,
+    options: SampleOptions,
+) -> AlignedIndex {
+    if options.fractional_delay {
+        AlignedIndex::with_rounding(t_start, sample_rate, 0, Rounding::Floor)
+    } else {
+        AlignedIndex::new(t_start, sample_rate, align_level)
+    }
+    .unwrap()
+}
+
+/// Apply the predistortion, IQ matrix, offset, and filters of a channel to the
+/// waveform.
+///
+/// The predistortion acts on the mixed waveform before the IQ matrix. The
+/// offset is applied before the filters if `filter_offset` is `true` and after
+/// the filters otherwise.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn post_process_inplace(
+    waveform: &mut ArrayViewMut2<f64>,
+    predistortion: Option<&Predistortion>,
+    iq_matrix: Option<ArrayView2<f64>>,
+    offset: Option<ArrayView1<f64>>,
+    iir: Option<ArrayView2<f64>>,
+    fir: Option<ArrayView1<f64>>,
+    filter_offset: bool,
+) {
+    if let Some(predistortion) = predistortion {
+        predistortion.apply_inplace(waveform);
+    }
+    if let Some(iq_matrix) = iq_matrix {
+        apply_iq_inplace(waveform, iq_matrix);
+    }
+    if filter_offset {
+        if let Some(offset) = offset {
+            apply_offset_inplace(waveform, offset);
+        }
+    }
+    if let Some(iir) = iir {
+        apply_iir_inplace(waveform, iir);
+    }
+    if let Some(fir) = fir {
+        apply_fir_inplace(waveform, fir);
+    }
+    if !filter_offset {
+        if let Some(offset) = offset {
+            apply_offset_inplace(waveform, offset);
+        }
+    }
 }
 
 pub(crate) fn apply_iq_inplace(waveform: &mut ArrayViewMut2<f64>, iq_matrix: ArrayView2<f64>) {
@@ -487,3 +1397,631 @@ pub(crate) fn apply_iir_inplace(waveform: &mut ArrayViewMut2<f64>, sos: ArrayVie
 pub(crate) fn apply_fir_inplace(waveform: &mut ArrayViewMut2<f64>, taps: ArrayView1<f64>) {
     self::fir::fir_filter_inplace(waveform.view_mut(), taps)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulse_list_serde() {
+        let time_tolerance = Time::new(1e-12).unwrap();
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, time_tolerance);
+        for (shape, time) in [(Some(Shape::new_hann()), 0.0), (None, 100e-9)] {
+            builder.push(PushArgs {
+                envelope: Envelope::new(shape, Time::new(20e-9).unwrap(), Time::ZERO),
+                global_freq: Frequency::new(100e6).unwrap(),
+                local_freq: Frequency::ZERO,
+                time: Time::new(time).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 1e-9,
+                phase: Phase::new(0.25).unwrap(),
+                id: None,
+            });
+        }
+        let list = builder.build_with(|_| {});
+
+        let json = crate::codec::to_json(&list).unwrap();
+        let decoded: PulseList = crate::codec::from_json(&json).unwrap();
+        assert_eq!(decoded, list);
+
+        let bytes = crate::codec::to_msgpack(&list, None).unwrap();
+        let decoded: PulseList = crate::codec::from_msgpack(&bytes).unwrap();
+        assert_eq!(decoded, list);
+    }
+
+    #[test]
+    fn bin_summary() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        let width = Time::new(20e-9).unwrap();
+        for (shape, time, phase) in [
+            (Some(Shape::new_hann()), 0.0, 0.0),
+            (Some(Shape::new_hann()), 100e-9, 0.25),
+            (Some(Shape::new_hann()), 200e-9, 0.5),
+            (None, 300e-9, 0.0),
+        ] {
+            builder.push(PushArgs {
+                envelope: Envelope::new(shape, width, Time::ZERO),
+                global_freq: Frequency::new(100e6).unwrap(),
+                local_freq: Frequency::ZERO,
+                time: Time::new(time).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::new(phase).unwrap(),
+                id: None,
+            });
+        }
+        let bins = builder.build_with(|_| {}).bins();
+
+        let summary: Vec<_> = bins
+            .iter()
+            .map(|b| (b.shape.is_some(), b.width, b.plateau, b.count))
+            .collect();
+        assert_eq!(
+            summary,
+            [(true, width, Time::ZERO, 3), (false, Time::ZERO, width, 1)]
+        );
+        assert_eq!(bins[0].samples(Frequency::new(2e9).unwrap()), 40);
+    }
+
+    #[test]
+    fn coalesce_bins() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        for (freq, time) in [(0.0, 2e-9), (0.0, 1e-9), (10e6, 1e-9), (0.0, 2e-9 + 1e-15)] {
+            builder.push(PushArgs {
+                envelope: Envelope::new(None, Time::ZERO, Time::new(1e-9).unwrap()),
+                global_freq: Frequency::ZERO,
+                local_freq: Frequency::new(freq).unwrap(),
+                time: Time::new(time).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::ZERO,
+                id: None,
+            });
+        }
+        let mut coalesced = vec![];
+        let list = builder.build_with(|time| coalesced.push(time));
+
+        assert_eq!(coalesced.len(), 1);
+        let mut lengths: Vec<_> = list.items.values().map(Vec::len).collect();
+        lengths.sort_unstable();
+        assert_eq!(lengths, [1, 2]);
+        for pulses in list.items.values() {
+            assert!(pulses.windows(2).all(|w| w[0].0 < w[1].0));
+        }
+    }
+
+    #[test]
+    fn carrier_table() {
+        let carrier = get_carrier(Phase::new(0.3).unwrap(), 50);
+        assert_eq!(carrier.len(), 50);
+        for (k, c) in carrier.iter().enumerate() {
+            let expected = Phase::new(0.3 * k as f64).unwrap().phaser();
+            assert!((c - expected).norm() < 1e-12, "{}", k);
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        for time in [-2e-9, 3e-9] {
+            builder.push(PushArgs {
+                envelope: Envelope::new(None, Time::ZERO, Time::new(4e-9).unwrap()),
+                global_freq: Frequency::ZERO,
+                local_freq: Frequency::ZERO,
+                time: Time::new(time).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::ZERO,
+                id: None,
+            });
+        }
+        let list = builder.build_with(|_| {});
+        let name = ChannelId::new("xy");
+        let sample = |allow_truncate| {
+            let mut waveform = ndarray::Array2::zeros((1, 6));
+            let mut sampler = Sampler::new([(name, list.clone())].into());
+            sampler.add_channel(
+                name,
+                waveform.view_mut(),
+                Frequency::new(1e9).unwrap(),
+                Time::ZERO,
+                -10,
+                Complex64::ZERO,
+            );
+            sampler.set_allow_truncate(allow_truncate);
+            sampler
+                .sample(Time::new(1e-12).unwrap())
+                .map(|d| (waveform, d))
+        };
+
+        let message = format!("{:#}", sample(false).unwrap_err());
+        assert!(message.contains("channel 'xy'"), "{}", message);
+        assert!(message.contains("channel length: 6"), "{}", message);
+        let (waveform, diagnostics) = sample(true).unwrap();
+        assert_eq!(diagnostics.warnings().len(), 2);
+        assert_eq!(waveform.row(0).to_vec(), [0.5, 0.5, 0.0, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn crosstalk() {
+        let list = |time| {
+            let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+            builder.push(PushArgs {
+                envelope: Envelope::new(None, Time::ZERO, Time::new(2e-9).unwrap()),
+                global_freq: Frequency::ZERO,
+                local_freq: Frequency::ZERO,
+                time: Time::new(time).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::ZERO,
+                id: None,
+            });
+            builder.build_with(|_| {})
+        };
+        let names = [ChannelId::new("xy0"), ChannelId::new("xy1")];
+        let matrix = ndarray::array![[1.0, 0.5], [0.0, 1.0]];
+        let mut waveforms = [
+            ndarray::Array2::zeros((1, 4)),
+            ndarray::Array2::zeros((1, 4)),
+        ];
+        let mut sampler = Sampler::new([(names[0], list(0.0)), (names[1], list(1e-9))].into());
+        for (name, waveform) in names.iter().zip(&mut waveforms) {
+            sampler.add_channel(
+                *name,
+                waveform.view_mut(),
+                Frequency::new(1e9).unwrap(),
+                Time::ZERO,
+                -10,
+                Complex64::ZERO,
+            );
+        }
+        sampler.set_crosstalk(matrix.view(), names.to_vec());
+        sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+
+        assert_eq!(waveforms[0].row(0).to_vec(), [0.5, 0.75, 0.25, 0.0]);
+        assert_eq!(waveforms[1].row(0).to_vec(), [0.0, 0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn baseline() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        builder.push(PushArgs {
+            envelope: Envelope::new(None, Time::ZERO, Time::new(2e-9).unwrap()),
+            global_freq: Frequency::ZERO,
+            local_freq: Frequency::ZERO,
+            time: Time::new(1e-9).unwrap(),
+            amplitude: Amplitude::new(0.5).unwrap(),
+            drag_coef: 0.0,
+            phase: Phase::ZERO,
+            id: None,
+        });
+        let list = builder.build_with(|_| {});
+        let name = ChannelId::new("z");
+        let sample = |rows| {
+            let mut waveform = ndarray::Array2::zeros((rows, 4));
+            let mut sampler = Sampler::new([(name, list.clone())].into());
+            sampler.add_channel(
+                name,
+                waveform.view_mut(),
+                Frequency::new(1e9).unwrap(),
+                Time::ZERO,
+                -10,
+                Complex64::new(0.25, -0.125),
+            );
+            sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+            waveform
+        };
+
+        let complex = sample(2);
+        assert_eq!(complex.row(0).to_vec(), [0.25, 0.75, 0.75, 0.25]);
+        assert_eq!(complex.row(1).to_vec(), [-0.125; 4]);
+        let real = sample(1);
+        assert_eq!(real.row(0).to_vec(), [0.25, 0.75, 0.75, 0.25]);
+    }
+
+    #[test]
+    fn net_area() {
+        let pulse = |time, amplitude| PushArgs {
+            envelope: Envelope::new(None, Time::ZERO, Time::new(10e-9).unwrap()),
+            global_freq: Frequency::ZERO,
+            local_freq: Frequency::ZERO,
+            time: Time::new(time).unwrap(),
+            amplitude: Amplitude::new(amplitude).unwrap(),
+            drag_coef: 0.0,
+            phase: Phase::ZERO,
+            id: None,
+        };
+        let mut single = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        single.push(pulse(0.0, 0.5));
+        let mut paired = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        paired.push(pulse(0.0, 0.5));
+        paired.push(pulse(10e-9, -0.5));
+        let z = ChannelId::new("z");
+        let z2 = ChannelId::new("z2");
+        let mut waveforms = [
+            ndarray::Array2::zeros((1, 20)),
+            ndarray::Array2::zeros((1, 20)),
+        ];
+        let mut sampler = Sampler::new(
+            [
+                (z, single.build_with(|_| {})),
+                (z2, paired.build_with(|_| {})),
+            ]
+            .into(),
+        );
+        for (name, waveform) in [z, z2].into_iter().zip(&mut waveforms) {
+            sampler.add_channel(
+                name,
+                waveform.view_mut(),
+                Frequency::new(1e9).unwrap(),
+                Time::ZERO,
+                -10,
+                Complex64::new(0.1, 0.0),
+            );
+        }
+        sampler.set_idle_tones([(
+            z2,
+            IdleTone {
+                amplitude: Amplitude::new(0.25).unwrap(),
+                freq: Frequency::ZERO,
+                phase: Phase::ZERO,
+            },
+        )]);
+        sampler.set_net_zero([z, z2], Amplitude::new(1e-6).unwrap());
+        let diagnostics = sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+
+        // The baseline and the idle tone don't count towards the residual.
+        assert!(matches!(
+            diagnostics.warnings(),
+            [Warning::NetArea { channel, area }] if *channel == z && (area - 5e-9).abs() < 1e-15
+        ));
+    }
+
+    #[test]
+    fn idle_tone() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        builder.push(PushArgs {
+            envelope: Envelope::new(None, Time::ZERO, Time::new(2e-9).unwrap()),
+            global_freq: Frequency::ZERO,
+            local_freq: Frequency::ZERO,
+            time: Time::new(2e-9).unwrap(),
+            amplitude: Amplitude::new(0.5).unwrap(),
+            drag_coef: 0.0,
+            phase: Phase::ZERO,
+            id: None,
+        });
+        let name = ChannelId::new("xy");
+        let mut waveform = ndarray::Array2::zeros((2, 8));
+        let mut sampler = Sampler::new([(name, builder.build_with(|_| {}))].into());
+        sampler.add_channel(
+            name,
+            waveform.view_mut(),
+            Frequency::new(1e9).unwrap(),
+            Time::ZERO,
+            -10,
+            Complex64::ZERO,
+        );
+        sampler.set_idle_tones([(
+            name,
+            IdleTone {
+                amplitude: Amplitude::new(0.25).unwrap(),
+                freq: Frequency::new(250e6).unwrap(),
+                phase: Phase::ZERO,
+            },
+        )]);
+        sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+
+        // The tone keeps its phase across the pulse.
+        let expected = [
+            [0.25, 0.0, 0.5, 0.5, 0.25, 0.0, -0.25, 0.0],
+            [0.0, 0.25, 0.0, 0.0, 0.0, 0.25, 0.0, -0.25],
+        ];
+        for (row, expected) in waveform.rows().into_iter().zip(expected) {
+            for (&y, e) in row.iter().zip(expected) {
+                assert!(approx_eq!(f64, y, e, epsilon = 1e-12), "{y} != {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn bin_order() {
+        #[derive(Debug)]
+        struct Ramp;
+
+        impl crate::shape::CustomShape for Ramp {
+            fn sample_array(&self, x0: f64, dx: f64, array: &mut [f64]) {
+                for (i, y) in array.iter_mut().enumerate() {
+                    *y = x0 + i as f64 * dx + 0.5;
+                }
+            }
+        }
+
+        // Bins only differing in a custom shape are ordered by creation.
+        let custom = [0, 1].map(|_| Shape::new_custom(Arc::new(Ramp)));
+        let push = |builder: &mut PulseListBuilder, i: usize| {
+            let shape = match i {
+                0..=1 => custom[i].clone(),
+                _ => Shape::new_hann(),
+            };
+            let freq = if i < 2 { 0.0 } else { i as f64 * 10e6 };
+            builder.push(PushArgs {
+                envelope: Envelope::new(
+                    Some(shape),
+                    Time::new(4e-9).unwrap(),
+                    Time::new(i.max(2) as f64 * 1e-9).unwrap(),
+                ),
+                global_freq: Frequency::new(freq).unwrap(),
+                local_freq: Frequency::ZERO,
+                time: Time::ZERO,
+                amplitude: Amplitude::new(0.1 + 0.01 * i as f64).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::new(0.1 * i as f64).unwrap(),
+                id: None,
+            });
+        };
+        // Overlapping bins pushed in opposite orders.
+        let lists = [false, true].map(|reverse| {
+            let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+            let mut order: Vec<_> = (0..32).collect();
+            if reverse {
+                order.reverse();
+            }
+            order.into_iter().for_each(|i| push(&mut builder, i));
+            builder.build_with(|_| {})
+        });
+        let orders = lists.each_ref().map(|list| {
+            in_bin_order(&list.items)
+                .into_iter()
+                .map(|(bin, pulses)| (bin.clone(), pulses.clone()))
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(orders[0], orders[1]);
+
+        let waveforms = lists.map(|list| {
+            let name = ChannelId::new("xy");
+            let mut waveform = ndarray::Array2::zeros((2, 64));
+            let mut sampler = Sampler::new([(name, list)].into());
+            sampler.add_channel(
+                name,
+                waveform.view_mut(),
+                Frequency::new(1e9).unwrap(),
+                Time::ZERO,
+                -10,
+                Complex64::ZERO,
+            );
+            sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+            waveform
+        });
+        // Bitwise identical, not only approximately equal.
+        assert_eq!(waveforms[0], waveforms[1]);
+    }
+
+    #[test]
+    fn prewarm() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        for (i, width) in [4e-9, 4e-9, 8e-9].into_iter().enumerate() {
+            builder.push(PushArgs {
+                envelope: Envelope::new(
+                    Some(Shape::new_hann()),
+                    Time::new(width).unwrap(),
+                    Time::ZERO,
+                ),
+                global_freq: Frequency::ZERO,
+                local_freq: Frequency::new(50e6).unwrap(),
+                time: Time::new(i as f64 * 20e-9).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::ZERO,
+                id: None,
+            });
+        }
+        let list = builder.build_with(|_| {});
+        let name = ChannelId::new("xy");
+        let mut waveforms = [0, 1].map(|_| ndarray::Array2::zeros((2, 64)));
+        let [cold, warm] = &mut waveforms;
+        let mut sampler = Sampler::new([(name, list.clone())].into());
+        sampler.add_channel(
+            name,
+            warm.view_mut(),
+            Frequency::new(1e9).unwrap(),
+            Time::ZERO,
+            -10,
+            Complex64::ZERO,
+        );
+
+        // The first two pulses share the envelope.
+        assert_eq!(sampler.prewarm(), 2);
+        sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+
+        let mut sampler = Sampler::new([(name, list)].into());
+        sampler.add_channel(
+            name,
+            cold.view_mut(),
+            Frequency::new(1e9).unwrap(),
+            Time::ZERO,
+            -10,
+            Complex64::ZERO,
+        );
+        sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+        assert_eq!(waveforms[0], waveforms[1]);
+    }
+
+    #[test]
+    fn fractional_delay() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        builder.push(PushArgs {
+            envelope: Envelope::new(
+                Some(Shape::new_hann()),
+                Time::new(40e-9).unwrap(),
+                Time::ZERO,
+            ),
+            global_freq: Frequency::ZERO,
+            local_freq: Frequency::new(50e6).unwrap(),
+            time: Time::new(10.3e-9).unwrap(),
+            amplitude: Amplitude::new(0.5).unwrap(),
+            drag_coef: 0.0,
+            phase: Phase::ZERO,
+            id: None,
+        });
+        let list = builder.build_with(|_| {});
+        let name = ChannelId::new("xy");
+        let sample = |align_level, fractional_delay| {
+            let mut waveform = ndarray::Array2::zeros((2, 64));
+            let mut sampler = Sampler::new([(name, list.clone())].into());
+            sampler.add_channel(
+                name,
+                waveform.view_mut(),
+                Frequency::new(1e9).unwrap(),
+                Time::ZERO,
+                align_level,
+                Complex64::ZERO,
+            );
+            sampler.set_fractional_delay(fractional_delay);
+            sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+            waveform
+        };
+
+        // A fine alignment grid is close to the exact start time.
+        let reference = sample(-20, false);
+        let aligned = sample(0, false);
+        let delayed = sample(0, true);
+        let error =
+            |w: &ndarray::Array2<f64>| (w - &reference).iter().map(|x| x.abs()).fold(0.0, f64::max);
+        assert!(error(&delayed) < 1e-3, "{}", error(&delayed));
+        assert!(error(&aligned) > 10.0 * error(&delayed));
+    }
+
+    #[test]
+    fn oversampling() {
+        let list = |width: f64| {
+            let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+            builder.push(PushArgs {
+                envelope: Envelope::new(
+                    Some(Shape::new_hann()),
+                    Time::new(width).unwrap(),
+                    Time::ZERO,
+                ),
+                global_freq: Frequency::ZERO,
+                local_freq: Frequency::ZERO,
+                time: Time::new(20.3e-9).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::ZERO,
+                id: None,
+            });
+            builder.build_with(|_| {})
+        };
+        let name = ChannelId::new("xy");
+        let sample = |list: &PulseList, oversampling| {
+            let mut waveform = ndarray::Array2::zeros((1, 64));
+            let mut sampler = Sampler::new([(name, list.clone())].into());
+            sampler.add_channel(
+                name,
+                waveform.view_mut(),
+                Frequency::new(1e9).unwrap(),
+                Time::ZERO,
+                -20,
+                Complex64::ZERO,
+            );
+            sampler.set_oversampling(oversampling);
+            sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+            waveform
+        };
+
+        // Smooth envelopes pass the filter.
+        let long = list(30e-9);
+        let error = (sample(&long, 8) - sample(&long, 1))
+            .iter()
+            .map(|x| x.abs())
+            .fold(0.0, f64::max);
+        assert!(error < 1e-3, "{}", error);
+
+        // The filter spreads a short pulse but keeps its area.
+        let short = list(1.7e-9);
+        let waveform = sample(&short, 16);
+        let area = waveform.sum() * 1e-9;
+        assert!((area - 0.5 * 1.7e-9 / 2.0).abs() < 1e-3 * area, "{}", area);
+        assert!(waveform[[0, 19]].abs() > 1e-3);
+    }
+
+    #[test]
+    fn asymmetric_envelope() {
+        let t = |x: f64| Time::new(x * 1e-9).unwrap();
+        let hann = || Some(Shape::new_hann());
+        assert_eq!(
+            Envelope::asymmetric(hann(), t(10.0), t(5.0), hann(), t(10.0)),
+            Envelope::new(hann(), t(10.0), t(5.0))
+        );
+        // Rectangular edges are part of the plateau.
+        assert_eq!(
+            Envelope::asymmetric(None, t(10.0), t(5.0), None, t(20.0)),
+            Envelope::new(None, Time::ZERO, t(20.0))
+        );
+
+        let envelope = Envelope::asymmetric(hann(), t(10.0), Time::ZERO, hann(), t(30.0));
+        assert_eq!(envelope.duration(), t(20.0));
+        let sample_rate = Frequency::new(1e9).unwrap();
+        let index = AlignedIndex::new(Time::ZERO, sample_rate, -20).unwrap();
+        let samples = get_envelope(envelope, index, sample_rate, 1);
+        assert_eq!(samples.len(), 20);
+        let shape = Shape::new_hann();
+        for (i, &value) in samples.iter().enumerate() {
+            let x = if i < 5 {
+                (i as f64 - 5.0) / 10.0
+            } else {
+                (i as f64 - 5.0) / 30.0
+            };
+            let mut expected = [0.0];
+            shape.sample_array(x, 0.0, &mut expected);
+            assert!((value - expected[0]).abs() < 1e-12, "{} {}", i, value);
+        }
+    }
+
+    #[test]
+    fn merge_adjacent() {
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(1e-12).unwrap());
+        // Times in samples of 1 s to keep the sums exact. The local frequency
+        // advances the phase by a quarter turn every 8 samples.
+        for (time, phase) in [(0.0, 0.0), (8.0, 0.25), (16.0, 0.5), (32.0, 0.0)] {
+            builder.push(PushArgs {
+                envelope: Envelope::new(None, Time::new(8.0).unwrap(), Time::ZERO),
+                global_freq: Frequency::ZERO,
+                local_freq: Frequency::new(1.0 / 32.0).unwrap(),
+                time: Time::new(time).unwrap(),
+                amplitude: Amplitude::new(0.5).unwrap(),
+                drag_coef: 0.0,
+                phase: Phase::new(phase).unwrap(),
+                id: None,
+            });
+        }
+        let list = builder.build_with(|_| {});
+        let mut merged = list.clone();
+        let name = ChannelId::new("xy");
+        let sample = |list: &PulseList| {
+            let mut waveform = ndarray::Array2::zeros((2, 64));
+            let mut sampler = Sampler::new([(name, list.clone())].into());
+            sampler.add_channel(
+                name,
+                waveform.view_mut(),
+                Frequency::new(1.0).unwrap(),
+                Time::ZERO,
+                0,
+                Complex64::ZERO,
+            );
+            sampler.sample(Time::new(1e-12).unwrap()).unwrap();
+            waveform
+        };
+
+        let count = merged.merge_adjacent(Amplitude::new(1e-9).unwrap(), Time::new(1e-12).unwrap());
+
+        assert_eq!(count, 2);
+        let plateaus: Vec<_> = merged.bins().iter().map(|b| b.plateau.value()).collect();
+        assert_eq!(plateaus.len(), 2);
+        assert!(plateaus.contains(&24.0));
+        let error = (sample(&merged) - sample(&list))
+            .iter()
+            .map(|x| x.abs())
+            .fold(0.0, f64::max);
+        assert!(error < 1e-9, "{}", error);
+    }
+}
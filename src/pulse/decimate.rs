@@ -0,0 +1,61 @@
+//! Oversampled envelopes decimated to the channel rate.
+//!
+//! The envelope is sampled at an integer multiple of the channel rate and
+//! filtered with a Lanczos windowed sinc low-pass at the Nyquist frequency of
+//! the channel before every `factor`-th sample is kept. The filter taps are
+//! normalized so that the plateau of a pulse keeps its amplitude.
+
+use super::delay::{lanczos, LOBES};
+
+/// Number of channel samples added on each side of a decimated envelope to
+/// keep the spread of the filter.
+pub(super) const PAD: usize = LOBES;
+
+/// Number of fine samples on each side of a channel sample in the filter.
+pub(super) fn margin(factor: usize) -> usize {
+    PAD * factor
+}
+
+/// Decimate `fine` by `factor` to `length` samples.
+///
+/// Sample `n` of the result is centered at `fine[n * factor + margin(factor)]`,
+/// so `fine` should have at least `(length - 1) * factor + 2 * margin + 1`
+/// samples.
+pub(super) fn decimate(fine: &[f64], factor: usize, length: usize) -> Vec<f64> {
+    let taps = taps(factor);
+    (0..length)
+        .map(|n| {
+            taps.iter()
+                .zip(&fine[n * factor..])
+                .map(|(w, x)| w * x)
+                .sum()
+        })
+        .collect()
+}
+
+fn taps(factor: usize) -> Vec<f64> {
+    let m = margin(factor);
+    let mut taps: Vec<_> = (0..=2 * m)
+        .map(|k| lanczos((k as f64 - m as f64) / factor as f64))
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    taps.iter_mut().for_each(|w| *w /= sum);
+    taps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant() {
+        let factor = 4;
+        let length = 5;
+        let fine = vec![0.5; (length - 1) * factor + 2 * margin(factor) + 1];
+        let taps = taps(factor);
+        assert!(taps.iter().zip(taps.iter().rev()).all(|(a, b)| a == b));
+        for y in decimate(&fine, factor, length) {
+            assert!((y - 0.5).abs() < 1e-15);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Fractional delay of sampled envelopes.
+//!
+//! The envelope is sampled on the integer sample grid and shifted by the
+//! sub-sample residual with a Lanczos windowed sinc filter. The filter taps are
+//! normalized so that the plateau of a pulse keeps its amplitude.
+
+/// Number of lobes of the Lanczos window, the filter has `2 * LOBES` taps.
+pub(super) const LOBES: usize = 4;
+
+/// Delay the envelope by `delay` samples in `[0, 1)`.
+///
+/// The result is one sample longer than the input so that the tail of the
+/// shifted envelope is kept. Ringing outside of that range is dropped, which is
+/// negligible for envelopes vanishing at both ends.
+pub(super) fn delay_envelope(envelope: &[f64], delay: f64) -> Vec<f64> {
+    let taps = taps(delay);
+    let len = envelope.len() + 1;
+    (0..len)
+        .map(|n| {
+            // y[n] = sum_j taps[j] * x[n - j + LOBES - 1]
+            taps.iter()
+                .enumerate()
+                .filter_map(|(j, &w)| {
+                    let k = (n + LOBES - 1).checked_sub(j)?;
+                    envelope.get(k).map(|&x| w * x)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+fn taps(delay: f64) -> [f64; 2 * LOBES] {
+    let mut taps = [0.0; 2 * LOBES];
+    for (j, w) in taps.iter_mut().enumerate() {
+        *w = lanczos(j as f64 - (LOBES - 1) as f64 - delay);
+    }
+    let sum: f64 = taps.iter().sum();
+    taps.iter_mut().for_each(|w| *w /= sum);
+    taps
+}
+
+pub(super) fn lanczos(x: f64) -> f64 {
+    let a = LOBES as f64;
+    if x == 0.0 {
+        1.0
+    } else if x.abs() < a {
+        let px = std::f64::consts::PI * x;
+        a * px.sin() * (px / a).sin() / (px * px)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_delay() {
+        let envelope = [0.0, 0.5, 1.0, 0.5, 0.0];
+        let delayed = delay_envelope(&envelope, 0.0);
+        assert_eq!(delayed.len(), envelope.len() + 1);
+        for (a, b) in delayed.iter().zip(envelope) {
+            assert!((a - b).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn sub_sample_delay() {
+        // Samples of a slowly varying cosine bump delayed by a quarter sample.
+        let f = |t: f64| {
+            if (0.0..=64.0).contains(&t) {
+                0.5 * (1.0 - (std::f64::consts::TAU * t / 64.0).cos())
+            } else {
+                0.0
+            }
+        };
+        let envelope: Vec<_> = (0..=64).map(|i| f(i as f64)).collect();
+        let delayed = delay_envelope(&envelope, 0.25);
+        for (i, y) in delayed.iter().enumerate() {
+            assert!((y - f(i as f64 - 0.25)).abs() < 1e-3, "{}", i);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Amplitude-dependent predistortion of sampled waveforms.
+//!
+//! The correction maps the desired output amplitude to the DAC amplitude, e.g.
+//! to linearize the compression of an amplifier. It acts on the magnitude of
+//! each sample, so the phase of complex channels and the sign of real channels
+//! are kept.
+
+use anyhow::{bail, Result};
+use ndarray::{ArrayViewMut2, Axis};
+use serde::Deserialize;
+
+/// Monotone map from the desired amplitude to the DAC amplitude.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "PredistortionData")]
+pub(crate) enum Predistortion {
+    /// Piecewise linear through the points, starting at the origin.
+    /// Amplitudes above the last point continue the last segment.
+    Table { input: Vec<f64>, output: Vec<f64> },
+    /// `output = c[0] * x + c[1] * x^2 + ...`, without a constant term so
+    /// that zero stays zero.
+    Polynomial { coefficients: Vec<f64> },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum PredistortionData {
+    Table { input: Vec<f64>, output: Vec<f64> },
+    Polynomial { coefficients: Vec<f64> },
+}
+
+impl TryFrom<PredistortionData> for Predistortion {
+    type Error = anyhow::Error;
+
+    fn try_from(data: PredistortionData) -> Result<Self> {
+        match data {
+            PredistortionData::Table { input, output } => Self::table(input, output),
+            PredistortionData::Polynomial { coefficients } => Self::polynomial(coefficients),
+        }
+    }
+}
+
+impl Predistortion {
+    /// Lookup table of strictly increasing, non-negative amplitudes. The
+    /// origin is added if the table doesn't start at zero input.
+    pub(crate) fn table(mut input: Vec<f64>, mut output: Vec<f64>) -> Result<Self> {
+        if input.len() != output.len() || input.is_empty() {
+            bail!("Predistortion table needs input and output of the same non-zero length");
+        }
+        if input[0] != 0.0 {
+            input.insert(0, 0.0);
+            output.insert(0, 0.0);
+        } else if output[0] != 0.0 {
+            bail!("Predistortion table should map zero to zero");
+        }
+        if input.len() < 2 {
+            bail!("Predistortion table needs at least one non-zero point");
+        }
+        let increasing =
+            |v: &[f64]| v.iter().all(|x| x.is_finite()) && v.windows(2).all(|w| w[0] < w[1]);
+        if !(increasing(&input) && increasing(&output)) {
+            bail!("Predistortion table should be strictly increasing and non-negative");
+        }
+        Ok(Self::Table { input, output })
+    }
+
+    /// Polynomial with a positive slope at zero.
+    ///
+    /// Monotonicity is only checked at zero, the polynomial should be
+    /// increasing over the amplitudes of the channel.
+    pub(crate) fn polynomial(coefficients: Vec<f64>) -> Result<Self> {
+        if !coefficients.iter().all(|c| c.is_finite()) {
+            bail!("Predistortion coefficients should be finite");
+        }
+        if !coefficients.first().is_some_and(|&c| c > 0.0) {
+            bail!("Predistortion polynomial should have a positive linear coefficient");
+        }
+        Ok(Self::Polynomial { coefficients })
+    }
+
+    /// DAC amplitude of the non-negative amplitude `x`.
+    pub(crate) fn map(&self, x: f64) -> f64 {
+        match self {
+            Self::Table { input, output } => {
+                let i = input.partition_point(|&v| v <= x).clamp(1, input.len() - 1);
+                let (x0, x1, y0, y1) = (input[i - 1], input[i], output[i - 1], output[i]);
+                y0 + (x - x0) * (y1 - y0) / (x1 - x0)
+            }
+            Self::Polynomial { coefficients } => {
+                coefficients.iter().rev().fold(0.0, |acc, &c| (acc + c) * x)
+            }
+        }
+    }
+
+    /// Apply the map to the magnitude of each sample, the rows are I and Q for
+    /// complex channels.
+    pub(crate) fn apply_inplace(&self, waveform: &mut ArrayViewMut2<f64>) {
+        for mut col in waveform.axis_iter_mut(Axis(1)) {
+            let magnitude = col.iter().map(|v| v * v).sum::<f64>().sqrt();
+            if magnitude > 0.0 {
+                let scale = self.map(magnitude) / magnitude;
+                col.iter_mut().for_each(|v| *v *= scale);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::*;
+
+    #[test]
+    fn predistortion() {
+        let table = Predistortion::table(vec![0.5, 1.0], vec![0.6, 1.4]).unwrap();
+        assert!((table.map(0.25) - 0.3).abs() < 1e-12);
+        assert!((table.map(0.75) - 1.0).abs() < 1e-12);
+        // Extrapolated with the last segment.
+        assert!((table.map(1.5) - 2.2).abs() < 1e-12);
+
+        let polynomial = Predistortion::polynomial(vec![1.0, 0.5]).unwrap();
+        assert!((polynomial.map(0.4) - 0.48).abs() < 1e-12);
+
+        let mut complex = array![[0.3, 0.0, -0.5], [0.4, 0.0, 0.0]];
+        table.apply_inplace(&mut complex.view_mut());
+        let expected = array![[0.6 * 0.6, 0.0, -0.6], [0.6 * 0.8, 0.0, 0.0]];
+        let error = (&complex - &expected)
+            .iter()
+            .map(|x| x.abs())
+            .fold(0.0, f64::max);
+        assert!(error < 1e-12, "{}", complex);
+
+        let mut real = array![[-0.25, 0.25]];
+        polynomial.apply_inplace(&mut real.view_mut());
+        assert!((real[[0, 0]] + 0.28125).abs() < 1e-12);
+        assert!((real[[0, 1]] - 0.28125).abs() < 1e-12);
+
+        assert!(Predistortion::table(vec![0.5, 0.4], vec![0.6, 0.7]).is_err());
+        assert!(Predistortion::table(vec![0.0, 1.0], vec![0.1, 1.0]).is_err());
+        assert!(Predistortion::table(vec![0.0], vec![0.0]).is_err());
+        assert!(Predistortion::polynomial(vec![0.0, 1.0]).is_err());
+        let data: Predistortion = serde_json::from_str(r#"{"coefficients": [1.0]}"#).unwrap();
+        assert_eq!(
+            data,
+            Predistortion::Polynomial {
+                coefficients: vec![1.0]
+            }
+        );
+        assert!(
+            serde_json::from_str::<Predistortion>(r#"{"input": [1.0], "output": [0.5, 1.0]}"#)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,6669 @@
+//! Python bindings.
+//!
+//! Although Element struct may contains [`Py<Element>`] as children, it is not
+//! possible to create cyclic references because we don't allow mutate the
+//! children after creation.
+use std::{
+    borrow::Borrow,
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use hashbrown::{HashMap, HashSet};
+use ndarray::{Array2, ArrayView1, ArrayView2};
+use numpy::{
+    prelude::*, AllowTypeChange, Complex64, PyArray1, PyArray2, PyArrayLike1, PyArrayLike2,
+    PyReadonlyArray1, PyReadonlyArray2,
+};
+use pyo3::{
+    exceptions::{PyRuntimeError, PyTypeError, PyUserWarning, PyValueError},
+    prelude::*,
+    types::{DerefToPyAny, PyBytes, PyDict, PyTuple, PyType},
+    PyTypeInfo,
+};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+#[cfg(feature = "config")]
+use crate::config;
+#[cfg(feature = "simulate")]
+use crate::simulate;
+use crate::{
+    analysis, codec,
+    diagnostics::Diagnostics,
+    executor::{self, CompileKey, Executor},
+    io, lo,
+    pulse::{self, post_process_inplace, Sampler},
+    quant::{self, Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    schedule::{self, ElementCommonBuilder, ElementRef, Measure as _},
+    shape, trigger, Alignment, Direction, GridLength, GridLengthUnit, ScheduleError,
+};
+
+/// Channel configuration.
+///
+/// `align_level` is the time axis alignment granularity. With sampling interval
+/// :math:`\Delta t` and `align_level` :math:`n`, start of pulse is aligned to
+/// the nearest multiple of :math:`2^n \Delta t`.
+///
+/// Each channel can be either real or complex. If the channel is complex, the
+/// filter will be applied to both I and Q components. If the channel is real,
+/// `iq_matrix` will be ignored.
+///
+/// .. caution::
+///
+///     Crosstalk matrix will not be applied to offset.
+///
+/// Args:
+///     base_freq (float): Base frequency of the channel.
+///     sample_rate (float): Sample rate of the channel.
+///     length (int): Length of the waveform.
+///     delay (float): Delay of the channel. Defaults to 0.0.
+///     align_level (int): Time axis alignment granularity. Defaults to -10.
+///     baseline (complex): Level of the waveform when no pulse is playing. The
+///         pulses are added on top of it, and the imaginary part is ignored if
+///         the channel is real. Unlike `offset`, it is applied before the IQ
+///         matrix and the filters. Defaults to 0.
+///     ringdown (Ringdown | None): Compensating tail appended to every pulse.
+///         Defaults to ``None``.
+///     net_zero (bool): Whether to follow every pulse by an inverted copy,
+///         keeping the time integral of the waveform at zero, e.g. for flux
+///         channels behind a bias tee. The pulse and its copy share the
+///         arranged duration of the element, which has to be at least twice
+///         the pulse duration, and flexible pulses stretch to half of it. With
+///         ``ringdown``, only the copy is followed by a tail, which cancels the
+///         ring-down of both. A warning with the residual area of the pulses
+///         is emitted if it is not zero. Defaults to ``False``.
+///     idle_tone (IdleTone | None): Carrier played while no pulse of the
+///         channel is playing. Defaults to ``None``.
+///     time_grid (TimeGrid | None): Grid that the instructions on the channel
+///         are snapped to, overriding `time_grid` of :func:`generate_waveforms`.
+///         Instructions on several channels use the grid of the first channel
+///         with one. Defaults to ``None``.
+///     predistortion (Predistortion | None): Amplitude-dependent correction
+///         applied sample-wise to the mixed waveform before the IQ matrix, the
+///         offset, and the filters. Defaults to ``None``.
+///     iq_matrix (array_like[2, 2] | None): IQ matrix of the channel. Defaults
+///         to ``None``.
+///     offset (Sequence[float] | None): Offsets of the channel. The length of the
+///         sequence should be 2 if the channel is complex, or 1 if the channel is
+///         real. Defaults to ``None``.
+///     iir (array_like[N, 6] | None): IIR filter of the channel. The format of
+///         the array is ``[[b0, b1, b2, a0, a1, a2], ...]``, which is the same
+///         as `sos` parameter of :func:`scipy.signal.sosfilt`. Defaults to ``None``.
+///     fir (array_like[M] | None): FIR filter of the channel. Defaults to None.
+///     filter_offset (bool): Whether to apply filter to the offset. Defaults to
+///         ``False``.
+///     is_real (bool): Whether the channel is real. Defaults to ``False``.
+#[pyclass(get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Channel {
+    base_freq: Frequency,
+    sample_rate: Frequency,
+    length: usize,
+    delay: Time,
+    align_level: i32,
+    baseline: Complex64,
+    ringdown: Option<Ringdown>,
+    net_zero: bool,
+    idle_tone: Option<IdleTone>,
+    time_grid: Option<TimeGrid>,
+    predistortion: Option<Predistortion>,
+    iq_matrix: Option<Py<PyArray2<f64>>>,
+    offset: Option<Py<PyArray1<f64>>>,
+    iir: Option<Py<PyArray2<f64>>>,
+    fir: Option<Py<PyArray1<f64>>>,
+    filter_offset: bool,
+    is_real: bool,
+}
+
+#[pymethods]
+impl Channel {
+    #[new]
+    #[pyo3(signature = (
+        base_freq,
+        sample_rate,
+        length,
+        *,
+        delay=Time::ZERO,
+        align_level=-10,
+        baseline=Complex64::ZERO,
+        ringdown=None,
+        net_zero=false,
+        idle_tone=None,
+        time_grid=None,
+        predistortion=None,
+        iq_matrix=None,
+        offset=None,
+        iir=None,
+        fir=None,
+        filter_offset=false,
+        is_real=false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python,
+        base_freq: Frequency,
+        sample_rate: Frequency,
+        length: usize,
+        delay: Time,
+        align_level: i32,
+        baseline: Complex64,
+        ringdown: Option<Ringdown>,
+        net_zero: bool,
+        idle_tone: Option<IdleTone>,
+        time_grid: Option<TimeGrid>,
+        predistortion: Option<Predistortion>,
+        mut iq_matrix: Option<PyArrayLike2<f64, AllowTypeChange>>,
+        offset: Option<PyArrayLike1<f64, AllowTypeChange>>,
+        iir: Option<PyArrayLike2<f64, AllowTypeChange>>,
+        fir: Option<PyArrayLike1<f64, AllowTypeChange>>,
+        filter_offset: bool,
+        is_real: bool,
+    ) -> PyResult<Self> {
+        if is_real {
+            iq_matrix = None;
+        }
+        let iq_matrix = if let Some(iq_matrix) = iq_matrix {
+            if iq_matrix.shape() != [2, 2] {
+                return Err(PyValueError::new_err("iq_matrix should be a 2x2 matrix"));
+            }
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("write", false)?;
+            iq_matrix.getattr("setflags")?.call((), Some(&kwargs))?;
+            Some(Bound::clone(&iq_matrix).unbind())
+        } else {
+            None
+        };
+        let offset = if let Some(offset) = offset {
+            if !matches!((offset.len(), is_real), (1, true) | (2, false)) {
+                return Err(PyValueError::new_err(
+                    "offset length does not match is_real",
+                ));
+            }
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("write", false)?;
+            offset.getattr("setflags")?.call((), Some(&kwargs))?;
+            Some(Bound::clone(&offset).unbind())
+        } else {
+            None
+        };
+        let iir = if let Some(iir) = iir {
+            if !matches!(iir.shape(), [_, 6]) {
+                return Err(PyValueError::new_err("iir should be a Nx6 matrix"));
+            }
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("write", false)?;
+            iir.getattr("setflags")?.call((), Some(&kwargs))?;
+            Some(Bound::clone(&iir).unbind())
+        } else {
+            None
+        };
+        let fir = if let Some(fir) = fir {
+            let kwargs = PyDict::new_bound(py);
+            kwargs.set_item("write", false)?;
+            fir.getattr("setflags")?.call((), Some(&kwargs))?;
+            Some(Bound::clone(&fir).unbind())
+        } else {
+            None
+        };
+        Ok(Channel {
+            base_freq,
+            sample_rate,
+            length,
+            delay,
+            align_level,
+            baseline,
+            ringdown,
+            net_zero,
+            idle_tone,
+            time_grid,
+            predistortion,
+            iq_matrix,
+            offset,
+            iir,
+            fir,
+            filter_offset,
+            is_real,
+        })
+    }
+
+    fn __getnewargs_ex__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> NewArgsEx<'py, (Frequency, Frequency, usize)> {
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("delay", self.delay)?;
+        kwargs.set_item("align_level", self.align_level)?;
+        kwargs.set_item("baseline", self.baseline)?;
+        kwargs.set_item("ringdown", self.ringdown.map(|r| r.into_py(py)))?;
+        kwargs.set_item("net_zero", self.net_zero)?;
+        kwargs.set_item("idle_tone", self.idle_tone.map(|t| t.into_py(py)))?;
+        kwargs.set_item("time_grid", self.time_grid.map(|g| g.into_py(py)))?;
+        kwargs.set_item(
+            "predistortion",
+            self.predistortion.clone().map(|p| p.into_py(py)),
+        )?;
+        kwargs.set_item("iq_matrix", &self.iq_matrix)?;
+        kwargs.set_item("offset", &self.offset)?;
+        kwargs.set_item("iir", &self.iir)?;
+        kwargs.set_item("fir", &self.fir)?;
+        kwargs.set_item("filter_offset", self.filter_offset)?;
+        kwargs.set_item("is_real", self.is_real)?;
+        Ok(((self.base_freq, self.sample_rate, self.length), kwargs))
+    }
+}
+
+impl From<&Channel> for io::ChannelInfo {
+    fn from(c: &Channel) -> Self {
+        Self {
+            sample_rate: c.sample_rate.value(),
+            delay: c.delay.value(),
+            align_level: c.align_level,
+        }
+    }
+}
+
+#[pymethods]
+impl Alignment {
+    /// Convert the value to Alignment.
+    ///
+    /// The value can be one of the following:
+    ///
+    /// - :class:`Alignment`
+    /// - "end"
+    /// - "start"
+    /// - "center"
+    /// - "stretch"
+    ///
+    /// Args:
+    ///     obj (str | Alignment): The value to convert.
+    /// Returns:
+    ///     Alignment: The converted value.
+    /// Raises:
+    ///     ValueError: If the value cannot be converted to Alignment.
+    #[staticmethod]
+    fn convert(obj: &Bound<PyAny>) -> PyResult<Py<Self>> {
+        if let Ok(slf) = obj.extract() {
+            return Ok(slf);
+        }
+        if let Ok(s) = obj.extract() {
+            let alignment = match s {
+                "end" => Some(Alignment::End),
+                "start" => Some(Alignment::Start),
+                "center" => Some(Alignment::Center),
+                "stretch" => Some(Alignment::Stretch),
+                _ => None,
+            };
+            if let Some(alignment) = alignment {
+                return Py::new(obj.py(), alignment);
+            }
+        }
+        let msg = concat!(
+            "Failed to convert the value to Alignment. ",
+            "Must be Alignment or one of 'end', 'start', 'center', 'stretch'"
+        );
+        Err(PyValueError::new_err(msg))
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<VariantReduce<'py>> {
+        reduce_variant::<Self>(py, self)
+    }
+}
+
+/// Positional and keyword arguments of `__new__` for pickling.
+type NewArgsEx<'py, A> = PyResult<(A, Bound<'py, PyDict>)>;
+
+type VariantReduce<'py> = (Bound<'py, PyAny>, (Bound<'py, PyType>, String));
+
+/// Pickle an enum variant as `getattr(cls, name)`.
+fn reduce_variant<'py, T: PyTypeInfo>(
+    py: Python<'py>,
+    variant: &impl Debug,
+) -> PyResult<VariantReduce<'py>> {
+    let getattr = py.import_bound("builtins")?.getattr("getattr")?;
+    Ok((
+        getattr,
+        (py.get_type_bound::<T>(), format!("{:?}", variant)),
+    ))
+}
+
+fn extract_alignment(obj: &Bound<PyAny>) -> PyResult<Alignment> {
+    Alignment::convert(obj).and_then(|x| x.extract(obj.py()))
+}
+
+/// Base class for shapes.
+///
+/// Shapes are used to define the envelope of a pulse. Internally, the shape is
+/// represented as a function :math:`f(t)` defined on the interval :math:`t \in
+/// [-0.5, 0.5]`. The shape should be normalized such that :math:`f(\pm 0.5) = 0`
+/// and :math:`f(0) = 1`.
+///
+/// Following shapes are supported:
+///
+/// - :class:`Hann`: Hann window.
+/// - :class:`Interp`: Interpolated shape.
+/// - :class:`Expr`: Math expression.
+/// - :class:`Exponential`: Exponential rise and decay.
+/// - :class:`Sinc`: Sinc function.
+/// - :class:`ErfSquare`: Square pulse convolved with a Gaussian.
+/// - :class:`Normalized`: Another shape rescaled to unit peak, area, or RMS.
+/// - :class:`Custom`: Python callback.
+#[pyclass(subclass, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Shape;
+
+impl Shape {
+    fn get_rust_shape(slf: &Bound<Shape>) -> PyResult<shape::Shape> {
+        if slf.downcast::<Hann>().is_ok() {
+            return Ok(shape::Shape::new_hann());
+        }
+        if let Ok(interp) = slf.downcast::<Interp>() {
+            let interp = interp.get();
+            return Ok(shape::Shape::new_interp(
+                interp.knots.clone(),
+                interp.controls.clone(),
+                interp.degree,
+            )?);
+        }
+        if let Ok(expr) = slf.downcast::<Expr>() {
+            return Ok(shape::Shape::new_expr(&expr.get().expr)?);
+        }
+        if let Ok(normalized) = slf.downcast::<Normalized>() {
+            let normalized = normalized.get();
+            let shape = Self::get_rust_shape(normalized.shape.bind(slf.py()))?;
+            return Ok(shape.with_normalization(normalized.normalization)?);
+        }
+        if let Ok(sinc) = slf.downcast::<Sinc>() {
+            return Ok(shape::Shape::new_sinc(sinc.get().num_lobes)?);
+        }
+        if let Ok(erf_square) = slf.downcast::<ErfSquare>() {
+            return Ok(shape::Shape::new_erf_square(erf_square.get().rise_time)?);
+        }
+        if let Ok(exponential) = slf.downcast::<Exponential>() {
+            let exponential = exponential.get();
+            return Ok(shape::Shape::new_exponential(
+                &exponential.rise,
+                &exponential.decay,
+            )?);
+        }
+        if let Ok(custom) = slf.downcast::<Custom>() {
+            return Ok(custom.get().shape.clone());
+        }
+        Err(PyTypeError::new_err("Invalid shape type."))
+    }
+}
+
+/// A Hann shape.
+#[pyclass(extends=Shape, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Hann;
+
+#[pymethods]
+impl Hann {
+    #[new]
+    fn new() -> (Self, Shape) {
+        (Self, Shape)
+    }
+
+    fn __getnewargs__<'py>(&self, py: Python<'py>) -> Bound<'py, PyTuple> {
+        PyTuple::empty_bound(py)
+    }
+}
+
+/// An interpolated shape.
+///
+/// The interpolated shape use a B-spline. :func:`scipy.interpolate.make_interp_spline`
+/// can be used to calculate the parameters.
+///
+/// .. caution::
+///
+///     It's user's responsibility to ensure the b-spline parameters are valid and
+///     the shape is normalized such that :math:`f(\pm 0.5) = 0` and :math:`f(0) = 1`.
+///
+/// Args:
+///     knots (Sequence[float]): Knots of the B-spline.
+///     controls (Sequence[float]): Control points of the B-spline.
+///     degree (int): Degree of the B-spline.
+/// Example:
+///     .. code-block:: python
+///
+///         import numpy as np
+///         from scipy.interpolate import make_interp_spline
+///         from bosing import Interp
+///         x = np.linspace(0, np.pi, 10)
+///         y = np.sin(x)
+///         x = (x - x[0]) / (x[-1] - x[0]) - 0.5 # Normalize x to [-0.5, 0.5]
+///         spline = make_interp_spline(x, y, k=3)
+///         interp = Interp(spline.t, spline.c, spline.k)
+#[pyclass(extends=Shape, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Interp {
+    knots: Vec<f64>,
+    controls: Vec<f64>,
+    degree: usize,
+}
+
+#[pymethods]
+impl Interp {
+    #[new]
+    fn new(knots: Vec<f64>, controls: Vec<f64>, degree: usize) -> PyResult<(Self, Shape)> {
+        Ok((
+            Self {
+                knots,
+                controls,
+                degree,
+            },
+            Shape,
+        ))
+    }
+
+    fn __getnewargs__(&self) -> (Vec<f64>, Vec<f64>, usize) {
+        (self.knots.clone(), self.controls.clone(), self.degree)
+    }
+}
+
+/// A shape given as a math expression of ``x``.
+///
+/// The expression is compiled in Rust, so it is as fast as the built-in
+/// shapes and can be serialized with pulse lists. It supports numbers, ``x``,
+/// ``pi``, ``e``, the operators ``+``, ``-``, ``*``, ``/``, and ``^`` (or
+/// ``**``), parentheses, and the functions ``sin``, ``cos``, ``tan``,
+/// ``sinh``, ``cosh``, ``tanh``, ``exp``, ``ln``, ``log10``, ``sqrt``, and
+/// ``abs``.
+///
+/// Args:
+///     expr (str): Expression of the shape on :math:`x \in [-0.5, 0.5]`.
+/// Raises:
+///     ValueError: If the expression is invalid.
+/// Example:
+///     .. code-block:: python
+///
+///         from bosing import Expr
+///         gaussian = Expr("exp(-x^2 / (2 * 0.15^2))")
+#[pyclass(extends=Shape, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Expr {
+    expr: String,
+}
+
+#[pymethods]
+impl Expr {
+    #[new]
+    fn new(expr: String) -> PyResult<(Self, Shape)> {
+        shape::Shape::new_expr(&expr).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok((Self { expr }, Shape))
+    }
+
+    fn __getnewargs__(&self) -> (String,) {
+        (self.expr.clone(),)
+    }
+}
+
+/// A shape rescaled such that its peak, area, or RMS is one.
+///
+/// The quantity is evaluated on :math:`x \in [-0.5, 0.5]`, and the plateau of
+/// a pulse stays at one. With 'area', the integral of a pulse is the
+/// amplitude times the sum of width and plateau for any shape, so shapes can
+/// be swapped while conserving the rotation angle. 'rms' conserves the energy
+/// and 'peak' the peak power in the same way.
+///
+/// Args:
+///     shape (Shape): Shape to normalize.
+///     normalization (str): One of 'peak', 'area', 'rms'.
+/// Raises:
+///     ValueError: If the normalization is invalid or the quantity of the
+///         shape is zero.
+/// Example:
+///     .. code-block:: python
+///
+///         from bosing import Hann, Normalized
+///         hann = Normalized(Hann(), "area")
+#[pyclass(extends=Shape, frozen, module = "bosing")]
+#[derive(Debug)]
+struct Normalized {
+    shape: Py<Shape>,
+    normalization: shape::Normalization,
+}
+
+#[pymethods]
+impl Normalized {
+    #[new]
+    fn new(shape: &Bound<Shape>, normalization: &str) -> PyResult<(Self, Shape)> {
+        let normalization = shape::Normalization::from_name(normalization).ok_or_else(|| {
+            PyValueError::new_err(format!("Invalid normalization '{}'", normalization))
+        })?;
+        Shape::get_rust_shape(shape)?.with_normalization(normalization)?;
+        Ok((
+            Self {
+                shape: shape.clone().unbind(),
+                normalization,
+            },
+            Shape,
+        ))
+    }
+
+    #[getter]
+    fn shape(&self, py: Python<'_>) -> Py<Shape> {
+        self.shape.clone_ref(py)
+    }
+
+    #[getter]
+    fn normalization(&self) -> &'static str {
+        self.normalization.name()
+    }
+
+    fn __getnewargs__(&self, py: Python<'_>) -> (Py<Shape>, &'static str) {
+        (self.shape.clone_ref(py), self.normalization.name())
+    }
+}
+
+/// A sinc shape.
+///
+/// .. math::
+///
+///     f(x) = \frac{\sin(2 \pi n x)}{2 \pi n x}
+///
+/// where :math:`n` is `num_lobes`, so there are :math:`n - 1` side lobes on
+/// each side of the main lobe and the shape is zero at the edges.
+///
+/// Args:
+///     num_lobes (int): Number of zero crossings on each side.
+/// Raises:
+///     ValueError: If `num_lobes` is zero.
+#[pyclass(extends=Shape, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Sinc {
+    num_lobes: usize,
+}
+
+#[pymethods]
+impl Sinc {
+    #[new]
+    fn new(num_lobes: usize) -> PyResult<(Self, Shape)> {
+        shape::Shape::new_sinc(num_lobes).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok((Self { num_lobes }, Shape))
+    }
+
+    fn __getnewargs__(&self) -> (usize,) {
+        (self.num_lobes,)
+    }
+}
+
+/// A square pulse convolved with a Gaussian.
+///
+/// The edges are error functions centered half of `rise_time` from the edges
+/// of the shape with a standard deviation of a quarter of `rise_time`. The
+/// shape is shifted and rescaled to be zero at the edges and one at the
+/// center. Compared to a rectangular pulse with plateau, the spectrum decays
+/// much faster, which is useful for long readout pulses.
+///
+/// Args:
+///     rise_time (float): Length of each edge relative to the width of the
+///         pulse, in :math:`(0, 0.5]`.
+/// Raises:
+///     ValueError: If `rise_time` is out of range.
+#[pyclass(extends=Shape, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct ErfSquare {
+    rise_time: f64,
+}
+
+#[pymethods]
+impl ErfSquare {
+    #[new]
+    fn new(rise_time: f64) -> PyResult<(Self, Shape)> {
+        shape::Shape::new_erf_square(rise_time)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok((Self { rise_time }, Shape))
+    }
+
+    fn __getnewargs__(&self) -> (f64,) {
+        (self.rise_time,)
+    }
+}
+
+/// A shape with exponential rise and decay.
+///
+/// Each section is a sum of exponential terms given as ``(tau, weight)``
+/// pairs, with the time constants relative to the width of the pulse. A
+/// single float is a single exponential with that time constant, and two
+/// terms give a bi-exponential section, e.g. for flux pulses with the
+/// predistortion baked in. At distance :math:`s` from the edge, a section is
+///
+/// .. math::
+///
+///     f(s) = \frac{1 - \sum_i w_i e^{-s / \tau_i}}{1 - \sum_i w_i e^{-0.5 / \tau_i}}
+///
+/// with the weights normalized to a sum of one, so the shape is zero at the
+/// edges and one at the center. The shape is sampled analytically.
+///
+/// Args:
+///     rise (float | Sequence[tuple[float, float]]): Terms of the rise.
+///     decay (float | Sequence[tuple[float, float]] | None): Terms of the
+///         decay. Defaults to the rise.
+/// Raises:
+///     ValueError: If a section has no terms, a non-positive time constant,
+///         or weights summing to zero.
+/// Example:
+///     .. code-block:: python
+///
+///         from bosing import Exponential
+///         flux = Exponential(0.02, [(0.02, 0.8), (0.1, 0.2)])
+#[pyclass(extends=Shape, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Exponential {
+    rise: ExpTerms,
+    decay: ExpTerms,
+}
+
+/// `(tau, weight)` terms of a section of an :class:`Exponential`.
+type ExpTerms = Vec<(f64, f64)>;
+
+#[pymethods]
+impl Exponential {
+    #[new]
+    #[pyo3(signature = (rise, decay=None))]
+    fn new(rise: &Bound<PyAny>, decay: Option<&Bound<PyAny>>) -> PyResult<(Self, Shape)> {
+        let terms = |obj: &Bound<PyAny>| match obj.extract::<f64>() {
+            Ok(tau) => Ok(vec![(tau, 1.0)]),
+            Err(_) => obj.extract::<ExpTerms>(),
+        };
+        let rise = terms(rise)?;
+        let decay = decay
+            .map(terms)
+            .transpose()?
+            .unwrap_or_else(|| rise.clone());
+        shape::Shape::new_exponential(&rise, &decay)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok((Self { rise, decay }, Shape))
+    }
+
+    fn __getnewargs__(&self) -> (ExpTerms, ExpTerms) {
+        (self.rise.clone(), self.decay.clone())
+    }
+}
+
+/// A shape given as a vectorized Python callback.
+///
+/// The callback is called with an array of positions in :math:`[-0.5, 0.5]`
+/// and should return an array of the same length. It is called once per
+/// unique envelope, i.e. combination of width, plateau, sample rate, and
+/// sub-sample offset, and the result is cached. Reuse the same
+/// :class:`Custom` instance to share the cache between calls.
+///
+/// .. caution::
+///
+///     Custom shapes can't be serialized, e.g. with :meth:`PulseList.to_json`.
+///     If the callback raises during waveform generation, the error is
+///     reported as unraisable and the envelope is filled with ``nan``.
+///
+/// Args:
+///     func (Callable[[numpy.ndarray], array_like]): Envelope of the shape.
+/// Raises:
+///     ValueError: If a trial call of `func` doesn't return an array of the
+///         same length.
+/// Example:
+///     .. code-block:: python
+///
+///         import numpy as np
+///         from bosing import Custom
+///         cosine = Custom(lambda x: np.cos(np.pi * x))
+#[pyclass(extends=Shape, frozen, module = "bosing")]
+#[derive(Debug)]
+struct Custom {
+    shape: shape::Shape,
+    func: Py<PyAny>,
+}
+
+#[pymethods]
+impl Custom {
+    #[new]
+    fn new(py: Python<'_>, func: Py<PyAny>) -> PyResult<(Self, Shape)> {
+        let callback = ShapeCallback(func.clone_ref(py));
+        callback.call(py, -0.5, 0.1, 11)?;
+        let shape = shape::Shape::new_custom(Arc::new(callback));
+        Ok((Self { shape, func }, Shape))
+    }
+
+    #[getter]
+    fn func(&self, py: Python<'_>) -> Py<PyAny> {
+        self.func.clone_ref(py)
+    }
+
+    fn __getnewargs__(&self, py: Python<'_>) -> (Py<PyAny>,) {
+        (self.func.clone_ref(py),)
+    }
+}
+
+#[derive(Debug)]
+struct ShapeCallback(Py<PyAny>);
+
+impl ShapeCallback {
+    fn call(&self, py: Python<'_>, x0: f64, dx: f64, length: usize) -> PyResult<Vec<f64>> {
+        let x = PyArray1::from_iter_bound(py, (0..length).map(|i| x0 + i as f64 * dx));
+        let y = self.0.bind(py).call1((x,))?;
+        let y: PyArrayLike1<f64, AllowTypeChange> = y.extract()?;
+        if y.len() != length {
+            return Err(PyValueError::new_err(format!(
+                "Shape callback returned {} values for {} positions",
+                y.len(),
+                length
+            )));
+        }
+        Ok(y.as_array().to_vec())
+    }
+}
+
+impl shape::CustomShape for ShapeCallback {
+    fn sample_array(&self, x0: f64, dx: f64, array: &mut [f64]) {
+        Python::with_gil(|py| match self.call(py, x0, dx, array.len()) {
+            Ok(y) => array.copy_from_slice(&y),
+            Err(e) => {
+                e.write_unraisable_bound(py, Some(self.0.bind(py)));
+                array.fill(f64::NAN);
+            }
+        });
+    }
+}
+
+fn extract_margin(obj: &Bound<PyAny>) -> PyResult<(Time, Time)> {
+    if let Ok(v) = obj.extract() {
+        let t = Time::new(v)?;
+        return Ok((t, t));
+    }
+    if let Ok((v1, v2)) = obj.extract() {
+        let t1 = Time::new(v1)?;
+        let t2 = Time::new(v2)?;
+        return Ok((t1, t2));
+    }
+    let msg = "Failed to convert the value to (float, float).";
+    Err(PyValueError::new_err(msg))
+}
+
+/// Base class for schedule elements.
+///
+/// A schedule element is a node in the tree structure of a schedule similar to
+/// HTML elements. The design is inspired by `XAML in WPF / WinUI
+/// <https://learn.microsoft.com/en-us/windows/apps/design/layout/layouts-with-xaml>`_
+///
+/// Every element has the following properties:
+///
+/// - :attr:`margin`
+///     The margin of an element is a tuple of two floats representing the
+///     margin before and after the element. If :attr:`margin` is set to a
+///     single float, both sides use the same value.
+///
+///     Similar to margins in XAML, margins don't collapse. For example, if two
+///     elements have a margin of 10 and 20, the space between the two elements
+///     is 30, not 20.
+///
+/// - :attr:`alignment`
+///     The alignment of the element. Currently, this property takes effect only
+///     when the element is a child of a :class:`Grid` element.
+///
+/// - :attr:`phantom`
+///     Whether the element is a phantom element. Phantom elements are measured
+///     and arranged in the layout but do not add to the waveforms. Neither the
+///     phantom element nor its children emit pulses or frame changes, but the
+///     time range of the element is reserved on all of its channels. Use
+///     phantom elements as placeholders, e.g. for a measurement that is
+///     generated elsewhere, and query the reserved intervals with
+///     :meth:`reserved_intervals`.
+///
+/// - :attr:`duration`, :attr:`max_duration`, and :attr:`min_duration`
+///     Constraints on the duration of the element. When :attr:`duration`,
+///     :attr:`max_duration`, and :attr:`min_duration` are conflicting, the
+///     priority is as follows:
+///
+///     1. :attr:`min_duration`
+///     2. :attr:`max_duration`
+///     3. :attr:`duration`
+///
+///     When :attr:`duration` is not set, the duration is calculated such that
+///     the element occupies the minimum duration.
+///
+/// - :attr:`allow_oversize`
+///     Whether the element may be placed in a span shorter than its content.
+///     When it is ``None``, the ``allow_oversize`` argument of
+///     :func:`generate_waveforms` is used. This is useful for a deliberately
+///     clipped filler element in an otherwise strictly checked schedule.
+///
+/// There are two types of elements:
+///
+/// - Instruction elements:
+///     Elements that instruct the waveform generator to perform certain
+///     operations, such as playing a pulse or setting the phase of a channel.
+///
+///     - :class:`Play`: Play a pulse on a channel.
+///     - :class:`ShiftPhase`: Shift the phase of a channel.
+///     - :class:`SetPhase`: Set the phase of a channel.
+///     - :class:`ShiftFreq`: Shift the frequency of a channel.
+///     - :class:`SetFreq`: Set the frequency of a channel.
+///     - :class:`SwapPhase`: Swap the phase of two channels.
+///
+///     The timing information required by the waveform generator is calculated
+///     by the layout system.
+///
+/// - Layout elements:
+///     Elements that control the layout of child elements.
+///
+///     - :class:`Grid`: Grid layout.
+///     - :class:`Stack`: Stack layout.
+///     - :class:`Absolute`: Absolute layout.
+///     - :class:`Repeat`: Repeat element.
+///     - :class:`ScaleAmplitude`: Amplitude scaling element.
+///     - :class:`Barrier`: Barrier element.
+///
+/// Args:
+///     margin (float | tuple[float, float]): Margin of the element. Defaults to
+///         0.
+///     alignment (str | Alignment): Alignment of the element. The value can
+///         be :class:`Alignment` or one of 'end', 'start', 'center', 'stretch'.
+///         Defaults to :attr:`Alignment.End`.
+///     phantom (bool): Whether the element is a phantom element and should not
+///         add to waveforms. Defaults to ``False``.
+///     duration (float): Duration of the element. Defaults to ``None``.
+///     max_duration (float): Maximum duration of the element. Defaults to
+///         ``inf``.
+///     min_duration (float): Minimum duration of the element. Defaults to 0.
+///     allow_oversize (bool | None): Override of the global ``allow_oversize``
+///         option for this element. Defaults to ``None``.
+#[pyclass(subclass, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Element(ElementRef);
+
+#[pymethods]
+impl Element {
+    #[getter]
+    fn margin(&self) -> (Time, Time) {
+        self.0.common.margin()
+    }
+
+    #[getter]
+    fn alignment(&self) -> Alignment {
+        self.0.common.alignment()
+    }
+
+    #[getter]
+    fn phantom(&self) -> bool {
+        self.0.common.phantom()
+    }
+
+    #[getter]
+    fn duration(&self) -> Option<Time> {
+        self.0.common.duration()
+    }
+
+    #[getter]
+    fn max_duration(&self) -> Time {
+        self.0.common.max_duration()
+    }
+
+    #[getter]
+    fn min_duration(&self) -> Time {
+        self.0.common.min_duration()
+    }
+
+    #[getter]
+    fn allow_oversize(&self) -> Option<bool> {
+        self.0.common.allow_oversize()
+    }
+
+    fn __repr__(&self) -> String {
+        let channels: Vec<_> = self
+            .0
+            .channels()
+            .iter()
+            .map(|c| format!("'{}'", c))
+            .collect();
+        format!(
+            "<{} duration={} channels=[{}]>",
+            self.0.variant.name(),
+            self.0.measure().value(),
+            channels.join(", ")
+        )
+    }
+
+    /// Gantt chart of the schedule for Jupyter notebooks.
+    fn _repr_html_(&self) -> String {
+        io::svg::render_gantt(&schedule::flatten(&self.0))
+    }
+
+    /// Render the arranged schedule as a plain text timeline.
+    ///
+    /// Each channel is drawn as a line of `width` columns. Plays are drawn as
+    /// ``#``, phase operations as ``p``/``P``, frequency operations as
+    /// ``f``/``F``, phase swaps as ``s``, and barriers as ``|``. The last line
+    /// shows the time axis.
+    ///
+    /// Args:
+    ///     width (int): Number of columns of the timeline. Default is 80.
+    /// Returns:
+    ///     str: Rendered timeline.
+    #[pyo3(signature = (width=80))]
+    fn render_ascii(&self, width: usize) -> String {
+        schedule::render_ascii(&self.0, width)
+    }
+
+    /// Per-channel timing statistics of the arranged schedule.
+    ///
+    /// ``str(report)`` is a table for a quick check of the sequence
+    /// efficiency.
+    ///
+    /// Returns:
+    ///     TimingReport: Timing of each channel of the schedule.
+    fn timing_report(&self) -> TimingReport {
+        TimingReport(schedule::timing_report(&self.0))
+    }
+
+    /// Intervals reserved by phantom elements but without pulses.
+    ///
+    /// Each phantom element reserves its time range on all of its channels.
+    /// The intervals where pulses of non-phantom elements are played are
+    /// removed, so the returned intervals can be filled later.
+    ///
+    /// Returns:
+    ///     dict[str, list[tuple[float, float]]]: Sorted and merged ``(start,
+    ///     stop)`` intervals of each channel with reserved time.
+    fn reserved_intervals(&self) -> HashMap<ChannelId, Vec<(Time, Time)>> {
+        schedule::reserved_intervals(&self.0).into_iter().collect()
+    }
+
+    /// Render the element tree as a Graphviz DOT digraph.
+    ///
+    /// Each node shows the element kind, its parameters, the measured
+    /// duration, and the alignment. Edges from :class:`Absolute` and
+    /// :class:`Grid` are labeled with the placement of the child.
+    ///
+    /// Returns:
+    ///     str: DOT source.
+    fn to_dot(&self) -> String {
+        schedule::to_dot(&self.0)
+    }
+
+    /// Render the element tree as a Mermaid flowchart.
+    ///
+    /// The content of the nodes is the same as :meth:`to_dot`.
+    ///
+    /// Returns:
+    ///     str: Mermaid source.
+    fn to_mermaid(&self) -> String {
+        schedule::to_mermaid(&self.0)
+    }
+
+    /// Serialize the element tree to a JSON string.
+    ///
+    /// Sub-elements shared by multiple parents are serialized as separate
+    /// copies.
+    ///
+    /// Args:
+    ///     library (PulseLibrary | None): Pulse library to embed in the
+    ///         document for provenance. It can be read back with
+    ///         :meth:`PulseLibrary.from_json`. Defaults to no library.
+    /// Returns:
+    ///     str: JSON document with a schema version.
+    #[pyo3(signature = (*, library=None))]
+    fn to_json(&self, library: Option<&Bound<PulseLibrary>>) -> PyResult<String> {
+        self.0
+            .to_json(library.map(|l| &l.get().0))
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Deserialize an element tree from a JSON string.
+    ///
+    /// Args:
+    ///     s (str): JSON document created by :meth:`to_json`.
+    /// Returns:
+    ///     Element: Deserialized element with the corresponding subclass.
+    /// Raises:
+    ///     ValueError: If the document is invalid or the schema version is not
+    ///         supported.
+    #[staticmethod]
+    fn from_json(py: Python<'_>, s: &str) -> PyResult<Py<Element>> {
+        let element =
+            schedule::Element::from_json(s).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Element::to_py_object(py, Arc::new(element))
+    }
+
+    /// Serialize the element tree to MessagePack bytes.
+    ///
+    /// Sub-elements shared by multiple parents are serialized as separate
+    /// copies.
+    ///
+    /// Args:
+    ///     version (int | None): Format version supported by the reader.
+    ///         Defaults to the latest version.
+    /// Returns:
+    ///     bytes: MessagePack document with a format version.
+    /// Raises:
+    ///     ValueError: If the requested version is not supported or is older
+    ///         than the features used require.
+    #[pyo3(signature = (*, version=None))]
+    fn to_msgpack<'py>(
+        &self,
+        py: Python<'py>,
+        version: Option<u32>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self
+            .0
+            .to_msgpack(version)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyBytes::new_bound(py, &bytes))
+    }
+
+    /// Deserialize an element tree from MessagePack bytes.
+    ///
+    /// Args:
+    ///     data (bytes): MessagePack document created by :meth:`to_msgpack`.
+    /// Returns:
+    ///     Element: Deserialized element with the corresponding subclass.
+    /// Raises:
+    ///     ValueError: If the document is invalid or the format version is not
+    ///         supported.
+    #[staticmethod]
+    fn from_msgpack(py: Python<'_>, data: &[u8]) -> PyResult<Py<Element>> {
+        let element = schedule::Element::from_msgpack(data)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Element::to_py_object(py, Arc::new(element))
+    }
+
+    /// Replace a descendant element.
+    ///
+    /// Only the containers on the path are copied. The other sub-elements are
+    /// shared with this element together with their cached measurements, so
+    /// measuring the new tree only re-measures the path from the replaced
+    /// element to the root.
+    ///
+    /// Args:
+    ///     path (Sequence[int]): Child indices from this element down to the
+    ///         descendant. The only child of :class:`Repeat` has index 0. An
+    ///         empty path replaces this element.
+    ///     element (Element): New element.
+    /// Returns:
+    ///     Element: Root of the new element tree.
+    /// Raises:
+    ///     ValueError: If the path does not lead to an element.
+    fn replace(
+        slf: &Bound<'_, Self>,
+        path: Vec<usize>,
+        element: Py<Element>,
+    ) -> PyResult<Py<Element>> {
+        Element::replace_child(slf, &path, element)
+    }
+
+    /// Retarget the element tree to other channels.
+    ///
+    /// Every channel is replaced according to `mapping`, so the same logical
+    /// schedule can be played on different hardware channels. Sub-elements
+    /// shared by multiple parents stay shared in the new tree.
+    ///
+    /// Args:
+    ///     mapping (Mapping[str, str]): New channel of each channel. Channels
+    ///         not in the mapping are kept.
+    ///     channels (Iterable[str] | None): Allowed channels of the new tree.
+    ///         Defaults to no restriction.
+    /// Returns:
+    ///     Element: Root of the new element tree.
+    /// Raises:
+    ///     ValueError: If a channel of the new tree is not in `channels`.
+    #[pyo3(signature = (mapping, *, channels=None))]
+    fn remap_channels(
+        &self,
+        py: Python<'_>,
+        mapping: HashMap<String, String>,
+        channels: Option<Vec<String>>,
+    ) -> PyResult<Py<Element>> {
+        let mapping = mapping
+            .into_iter()
+            .map(|(k, v)| (ChannelId::new(k), ChannelId::new(v)))
+            .collect();
+        let channels: Option<HashSet<_>> =
+            channels.map(|c| c.into_iter().map(ChannelId::new).collect());
+        let element = schedule::remap_channels(&self.0, &mapping, channels.as_ref())?;
+        Element::to_py_object(py, element)
+    }
+
+    /// Expand channel groups to their member channels.
+    ///
+    /// A :class:`Play`, :class:`PlaySamples`, or frame instruction on a group
+    /// becomes an :class:`Absolute` layout with one copy per member at time
+    /// zero, which keeps the margin, alignment, and other common properties of
+    /// the original element. A :class:`Barrier` on a group waits for all
+    /// members. Groups can't be nested. Sub-elements shared by multiple
+    /// parents stay shared in the new tree.
+    ///
+    /// .. code-block:: python
+    ///
+    ///     schedule = Stack(ShiftPhase("all_xy", 0.25)).expand_groups(
+    ///         {"all_xy": ["q0.xy", "q1.xy"]}
+    ///     )
+    ///
+    /// Args:
+    ///     groups (Mapping[str, Sequence[str]]): Member channels of each group.
+    /// Returns:
+    ///     Element: Root of the new element tree.
+    /// Raises:
+    ///     ValueError: If the phase of a group is swapped with
+    ///         :class:`SwapPhase`.
+    fn expand_groups(
+        &self,
+        py: Python<'_>,
+        groups: HashMap<ChannelId, Vec<ChannelId>>,
+    ) -> PyResult<Py<Element>> {
+        let element = schedule::expand_groups(&self.0, &groups)?;
+        Element::to_py_object(py, element)
+    }
+
+    /// Template slots of the element tree.
+    ///
+    /// Returns:
+    ///     dict[str, list[str]]: Channels of each slot, see :class:`Barrier`.
+    ///         A name used by several slots has the channels of the first one.
+    fn slots(&self) -> HashMap<String, Vec<ChannelId>> {
+        schedule::slots(&self.0).into_iter().collect()
+    }
+
+    /// Fill the slots of a template with sub-schedules.
+    ///
+    /// Every :class:`Barrier` with a slot name in `fills` is replaced by the
+    /// fill element. The channels of the fill have to be the same as the
+    /// channels of the slot, and slots without channels accept any fill.
+    /// Slots without a fill are kept, so a template can be filled in several
+    /// steps. Sub-elements shared by multiple parents stay shared in the new
+    /// tree.
+    ///
+    /// .. code-block:: python
+    ///
+    ///     echo = Stack(x90, Barrier("q0.xy", slot="gate"), x90)
+    ///     schedule = echo.fill_slots({"gate": Play("q0.xy", "hann", 0.5, 40e-9)})
+    ///
+    /// Args:
+    ///     fills (Mapping[str, Element]): Element of each slot.
+    /// Returns:
+    ///     Element: Root of the new element tree.
+    /// Raises:
+    ///     ValueError: If a fill has no slot or its channels differ from the
+    ///         channels of the slot.
+    fn fill_slots(
+        &self,
+        py: Python<'_>,
+        fills: HashMap<String, Py<Element>>,
+    ) -> PyResult<Py<Element>> {
+        let fills = fills
+            .into_iter()
+            .map(|(k, v)| (k, v.get().0.clone()))
+            .collect();
+        let element = schedule::fill_slots(&self.0, &fills)?;
+        Element::to_py_object(py, element)
+    }
+
+    /// Fill the idle intervals of channels with a pulse.
+    ///
+    /// An interval of a channel is idle if no pulse is played on the channel,
+    /// from the start to the end of the arranged element. Each idle interval
+    /// gets a copy of `fill` on the channel with the plateau stretched to the
+    /// interval. Use a :class:`Play` without shape and with zero width to fill
+    /// with a constant level, e.g. a flux parking value.
+    ///
+    /// The fill pulses are played with the frequency and phase of the channel
+    /// at the end of the schedule, so this is meant for baseband channels.
+    ///
+    /// Args:
+    ///     channels (Iterable[str]): Channels to fill.
+    ///     fill (Play): Template of the fill pulse. The channel and plateau are
+    ///         ignored.
+    ///     min_gap (float): Idle intervals not longer than this are left empty.
+    ///         Intervals shorter than the width of `fill` are always left
+    ///         empty. Default is 1e-12.
+    /// Returns:
+    ///     Absolute: This element at time zero followed by the fill pulses.
+    #[pyo3(signature = (channels, fill, *, min_gap=Time::new(1e-12).unwrap()))]
+    fn fill_idle(
+        &self,
+        py: Python<'_>,
+        channels: Vec<String>,
+        fill: &Bound<'_, Play>,
+        min_gap: Time,
+    ) -> PyResult<Py<Element>> {
+        let channels: Vec<_> = channels.into_iter().map(ChannelId::new).collect();
+        let fill = Play::variant(fill);
+        let element = schedule::fill_idle(&self.0, &channels, fill, min_gap)?;
+        Element::to_py_object(py, element)
+    }
+
+    /// Fill in calibrated parameters of pulses.
+    ///
+    /// Every :class:`Play` with a calibration key gets the amplitude, drag
+    /// coefficient, and frequency stored for its channel and key. Parameters
+    /// not stored in the calibration are kept. Sub-elements shared by multiple
+    /// parents stay shared in the new tree.
+    ///
+    /// Args:
+    ///     calibrations (Calibrations): Calibrated parameters.
+    /// Returns:
+    ///     Element: Root of the new element tree.
+    /// Raises:
+    ///     ValueError: If a calibration key is not in `calibrations`.
+    fn resolve_calibrations(
+        &self,
+        py: Python<'_>,
+        calibrations: &Bound<'_, Calibrations>,
+    ) -> PyResult<Py<Element>> {
+        let element = schedule::resolve_calibrations(&self.0, &calibrations.get().0)?;
+        Element::to_py_object(py, element)
+    }
+
+    fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyBytes>,))> {
+        let from_msgpack = py.get_type_bound::<Element>().getattr("from_msgpack")?;
+        Ok((from_msgpack, (self.to_msgpack(py, None)?,)))
+    }
+}
+
+impl Element {
+    fn replace_child(
+        slf: &Bound<'_, Self>,
+        path: &[usize],
+        element: Py<Element>,
+    ) -> PyResult<Py<Element>> {
+        let Some((&index, rest)) = path.split_first() else {
+            return Ok(element);
+        };
+        let py = slf.py();
+        let rust_base = &slf.get().0;
+        let invalid = || ScheduleError::InvalidPath { index };
+        let base = |child: &Py<Element>| -> PyResult<Element> {
+            let variant = rust_base
+                .variant
+                .with_child(index, child.get().0.clone())?
+                .ok_or_else(invalid)?;
+            Ok(Element(Arc::new(schedule::Element::new(
+                rust_base.common.clone(),
+                variant,
+            ))))
+        };
+        match &rust_base.variant {
+            schedule::ElementVariant::Repeat(_) => {
+                let old = &slf.downcast::<Repeat>()?.get().child;
+                if index != 0 {
+                    return Err(invalid().into());
+                }
+                let child = Element::replace_child(old.bind(py), rest, element)?;
+                let base = base(&child)?;
+                upcast(py, Py::new(py, (Repeat { child }, base))?)
+            }
+            schedule::ElementVariant::Stack(_) => {
+                let mut children = slf.downcast::<Stack>()?.get().children.clone();
+                let old = children.get(index).ok_or_else(invalid)?;
+                let child = Element::replace_child(old.bind(py), rest, element)?;
+                let base = base(&child)?;
+                children[index] = child;
+                upcast(py, Py::new(py, (Stack { children }, base))?)
+            }
+            schedule::ElementVariant::Absolute(_) => {
+                let mut children = slf.downcast::<Absolute>()?.get().children.clone();
+                let old = &children.get(index).ok_or_else(invalid)?.element;
+                let child = Element::replace_child(old.bind(py), rest, element)?;
+                let base = base(&child)?;
+                children[index].element = child;
+                upcast(py, Py::new(py, (Absolute { children }, base))?)
+            }
+            schedule::ElementVariant::Grid(_) => {
+                let mut children = slf.downcast::<Grid>()?.get().children.clone();
+                let old = &children.get(index).ok_or_else(invalid)?.element;
+                let child = Element::replace_child(old.bind(py), rest, element)?;
+                let base = base(&child)?;
+                children[index].element = child;
+                upcast(py, Py::new(py, (Grid { children }, base))?)
+            }
+            schedule::ElementVariant::ScaleAmplitude(_) => {
+                let old = &slf.downcast::<ScaleAmplitude>()?.get().child;
+                if index != 0 {
+                    return Err(invalid().into());
+                }
+                let child = Element::replace_child(old.bind(py), rest, element)?;
+                let base = base(&child)?;
+                upcast(py, Py::new(py, (ScaleAmplitude { child }, base))?)
+            }
+            _ => Err(invalid().into()),
+        }
+    }
+
+    /// Wrap a rust element tree into python objects of the matching subclass.
+    fn to_py_object(py: Python<'_>, element: ElementRef) -> PyResult<Py<Element>> {
+        let base = Element(element.clone());
+        match &element.variant {
+            schedule::ElementVariant::Play(_) => upcast(py, Py::new(py, (Play, base))?),
+            schedule::ElementVariant::PlaySamples(_) => {
+                upcast(py, Py::new(py, (PlaySamples, base))?)
+            }
+            schedule::ElementVariant::ShiftPhase(_) => upcast(py, Py::new(py, (ShiftPhase, base))?),
+            schedule::ElementVariant::SetPhase(_) => upcast(py, Py::new(py, (SetPhase, base))?),
+            schedule::ElementVariant::ShiftFreq(_) => upcast(py, Py::new(py, (ShiftFreq, base))?),
+            schedule::ElementVariant::SetFreq(_) => upcast(py, Py::new(py, (SetFreq, base))?),
+            schedule::ElementVariant::SwapPhase(_) => upcast(py, Py::new(py, (SwapPhase, base))?),
+            schedule::ElementVariant::Barrier(_) => upcast(py, Py::new(py, (Barrier, base))?),
+            schedule::ElementVariant::Repeat(v) => {
+                let child = Element::to_py_object(py, v.child().clone())?;
+                upcast(py, Py::new(py, (Repeat { child }, base))?)
+            }
+            schedule::ElementVariant::Stack(v) => {
+                let children = v
+                    .children()
+                    .iter()
+                    .map(|c| Element::to_py_object(py, c.clone()))
+                    .collect::<PyResult<_>>()?;
+                upcast(py, Py::new(py, (Stack { children }, base))?)
+            }
+            schedule::ElementVariant::Absolute(v) => {
+                let children = v
+                    .children()
+                    .iter()
+                    .map(|e| {
+                        Ok(AbsoluteEntry {
+                            time: e.time(),
+                            element: Element::to_py_object(py, e.element().clone())?,
+                            from_end: e.is_from_end(),
+                        })
+                    })
+                    .collect::<PyResult<_>>()?;
+                upcast(py, Py::new(py, (Absolute { children }, base))?)
+            }
+            schedule::ElementVariant::Grid(v) => {
+                let children = v
+                    .children()
+                    .iter()
+                    .map(|e| {
+                        Ok(GridEntry {
+                            element: Element::to_py_object(py, e.element().clone())?,
+                            column: e.column(),
+                            span: e.span(),
+                        })
+                    })
+                    .collect::<PyResult<_>>()?;
+                upcast(py, Py::new(py, (Grid { children }, base))?)
+            }
+            schedule::ElementVariant::ScaleAmplitude(v) => {
+                let child = Element::to_py_object(py, v.child().clone())?;
+                upcast(py, Py::new(py, (ScaleAmplitude { child }, base))?)
+            }
+        }
+    }
+}
+
+fn upcast<T: pyo3::PyClass>(py: Python<'_>, obj: Py<T>) -> PyResult<Py<Element>> {
+    Ok(obj
+        .into_bound(py)
+        .into_any()
+        .downcast_into::<Element>()?
+        .unbind())
+}
+
+trait ElementSubclass: Sized + DerefToPyAny
+where
+    for<'a> &'a Self::Variant: TryFrom<&'a schedule::ElementVariant>,
+    for<'a> <&'a Self::Variant as TryFrom<&'a schedule::ElementVariant>>::Error: Debug,
+{
+    type Variant: Into<schedule::ElementVariant>;
+
+    fn variant<'a>(slf: &'a Bound<Self>) -> &'a Self::Variant {
+        slf.downcast::<Element>()
+            .expect("Self should be a subclass of Element")
+            .get()
+            .0
+            .variant
+            .borrow()
+            .try_into()
+            .expect("Element should have a valid variant")
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_element(
+        variant: Self::Variant,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<Element> {
+        let mut builder = ElementCommonBuilder::new();
+        if let Some(obj) = margin {
+            builder.margin(extract_margin(obj)?);
+        }
+        if let Some(obj) = alignment {
+            builder.alignment(extract_alignment(obj)?);
+        }
+        builder
+            .phantom(phantom)
+            .duration(duration)
+            .max_duration(max_duration)
+            .min_duration(min_duration)
+            .allow_oversize(allow_oversize);
+        let common = builder.build()?;
+        Ok(Element(Arc::new(schedule::Element::new(common, variant))))
+    }
+}
+
+/// A pulse play element.
+///
+/// Given the pulse envelope :math:`E(t)`, channel total frequency :math:`f_c`,
+/// and channel phase :math:`\phi_c`, the the final pulse :math:`P(t)` starts at
+/// :math:`t_0` with sideband will be
+///
+/// .. math::
+///
+///     E_d(t) = \left( 1 + i \alpha \frac{d}{dt} \right) E(t)
+///
+///     P(t) = E_d(t) \exp \big[ i 2 \pi (f_c t + f_p (t-t_0) + \phi_c + \phi_p) \big]
+///
+/// where :math:`\alpha` is the `drag_coef` parameter, :math:`f_p` is the
+/// `frequency` parameter, and :math:`\phi_p` is the `phase` parameter. The
+/// derivative is calculated using the central difference method. An exceptional
+/// case is when the pulse is a rectangular pulse. In this case, the drag
+/// coefficient is ignored.
+///
+/// If `flexible` is set to ``True``, the `plateau` parameter is ignored and the
+/// actual plateau length is determined by the duration of the element.
+///
+/// If `fall_shape_id` or `fall_width` is set, the falling edge of the pulse
+/// uses its own shape and width while the rising edge keeps `shape_id` and
+/// `width`. Each edge takes half of its width, so the pulse lasts
+/// ``(width + fall_width) / 2 + plateau``.
+///
+/// If `hold` is set to ``True``, the channel stays at the final value of the
+/// envelope after the pulse until the next pulse or barrier on the channel, or
+/// the end of the schedule. The level continues the carrier of the pulse and
+/// is not part of the duration of the element, so it does not delay other
+/// elements. Holds are only applied when generating waveforms, and are
+/// rejected on channels with ``net_zero`` set.
+///
+/// .. caution::
+///
+///     The unit of phase is number of cycles, not radians. For example, a phase
+///     of :math:`0.5` means a phase shift of :math:`\pi` radians.
+///
+/// Args:
+///     channel_id (str): Target channel ID.
+///     shape_id (str | None): Shape ID of the pulse. If ``None``, the pulse is
+///         a rectangular pulse.
+///     amplitude (float): Amplitude of the pulse.
+///     width (float): Width of the pulse.
+///     plateau (float): Plateau length of the pulse. Defaults to 0.
+///     drag_coef (float): Drag coefficient of the pulse. If the pulse is a
+///         rectangular pulse, the drag coefficient is ignored. Defaults to 0.
+///     frequency (float): Additional frequency of the pulse on top of channel
+///         base frequency and frequency shift. Defaults to 0.
+///     phase (float): Additional phase of the pulse in **cycles**. Defaults to
+///         0.
+///     flexible (bool): Whether the pulse has flexible plateau length. Defaults
+///         to ``False``.
+///     hold (bool): Whether to skip the falling edge and hold the plateau
+///         amplitude until the next pulse or barrier on the channel. Defaults
+///         to ``False``.
+///     phase_reference (str): Time the phase of the channel carrier
+///         :math:`f_c t` is referenced to, one of 'absolute' (time zero of the
+///         schedule), 'pulse' (start of the pulse), 'iteration' (start of the
+///         iteration of the innermost enclosing :class:`Repeat`). The
+///         additional frequency is always referenced to the pulse start.
+///         Defaults to 'absolute'.
+///     calibration (str | None): Gate name to look up calibrated amplitude,
+///         drag coefficient, and frequency of the pulse on the channel. See
+///         :meth:`Element.resolve_calibrations`. Defaults to ``None``.
+///     fall_shape_id (str | None): Shape ID of the falling edge. Defaults to
+///         ``None``, the shape of the rising edge.
+///     fall_width (float | None): Width of the falling edge. Defaults to
+///         ``None``, the width of the rising edge.
+#[pyclass(extends=Element, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Play;
+
+impl ElementSubclass for Play {
+    type Variant = schedule::Play;
+}
+
+#[pymethods]
+impl Play {
+    #[new]
+    #[pyo3(signature = (
+        channel_id,
+        shape_id,
+        amplitude,
+        width,
+        *,
+        plateau=Time::ZERO,
+        drag_coef=0.0,
+        frequency=Frequency::ZERO,
+        phase=Phase::ZERO,
+        flexible=false,
+        hold=false,
+        phase_reference="absolute",
+        calibration=None,
+        fall_shape_id=None,
+        fall_width=None,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        channel_id: ChannelId,
+        shape_id: Option<ShapeId>,
+        amplitude: Amplitude,
+        width: Time,
+        plateau: Time,
+        drag_coef: f64,
+        frequency: Frequency,
+        phase: Phase,
+        flexible: bool,
+        hold: bool,
+        phase_reference: &str,
+        calibration: Option<String>,
+        fall_shape_id: Option<ShapeId>,
+        fall_width: Option<Time>,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let variant = schedule::Play::new(channel_id, shape_id, amplitude, width)?
+            .with_plateau(plateau)?
+            .with_drag_coef(drag_coef)?
+            .with_frequency(frequency)?
+            .with_phase(phase)?
+            .with_flexible(flexible)
+            .with_hold(hold)
+            .with_phase_reference(
+                schedule::PhaseReference::from_name(phase_reference).ok_or_else(|| {
+                    PyValueError::new_err(format!("Invalid phase reference '{}'", phase_reference))
+                })?,
+            )
+            .with_calibration(calibration);
+        let fall =
+            (fall_shape_id.is_some() || fall_width.is_some()).then(|| schedule::FallingEdge {
+                shape_id: fall_shape_id,
+                width: fall_width.unwrap_or(width),
+            });
+        let variant = variant.with_fall(fall)?;
+        Ok((
+            Self,
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn channel_id<'a>(slf: &'a Bound<Self>) -> &'a ChannelId {
+        Self::variant(slf).channel_id()
+    }
+
+    #[getter]
+    fn shape_id<'a>(slf: &'a Bound<Self>) -> Option<&'a ShapeId> {
+        Self::variant(slf).shape_id()
+    }
+
+    #[getter]
+    fn amplitude(slf: &Bound<Self>) -> Amplitude {
+        Self::variant(slf).amplitude()
+    }
+
+    #[getter]
+    fn width(slf: &Bound<Self>) -> Time {
+        Self::variant(slf).width()
+    }
+
+    #[getter]
+    fn plateau(slf: &Bound<Self>) -> Time {
+        Self::variant(slf).plateau()
+    }
+
+    #[getter]
+    fn drag_coef(slf: &Bound<Self>) -> f64 {
+        Self::variant(slf).drag_coef()
+    }
+
+    #[getter]
+    fn frequency(slf: &Bound<Self>) -> Frequency {
+        Self::variant(slf).frequency()
+    }
+
+    #[getter]
+    fn phase(slf: &Bound<Self>) -> Phase {
+        Self::variant(slf).phase()
+    }
+
+    #[getter]
+    fn flexible(slf: &Bound<Self>) -> bool {
+        Self::variant(slf).flexible()
+    }
+
+    #[getter]
+    fn hold(slf: &Bound<Self>) -> bool {
+        Self::variant(slf).hold()
+    }
+
+    #[getter]
+    fn phase_reference(slf: &Bound<Self>) -> &'static str {
+        Self::variant(slf).phase_reference().name()
+    }
+
+    #[getter]
+    fn calibration<'a>(slf: &'a Bound<Self>) -> Option<&'a str> {
+        Self::variant(slf).calibration()
+    }
+
+    /// Shape ID of the falling edge, ``None`` for a rectangular edge.
+    #[getter]
+    fn fall_shape_id<'a>(slf: &'a Bound<Self>) -> Option<&'a ShapeId> {
+        let variant = Self::variant(slf);
+        variant
+            .fall()
+            .and_then(|fall| fall.shape_id.as_ref())
+            .or(variant.shape_id())
+    }
+
+    #[getter]
+    fn fall_width(slf: &Bound<Self>) -> Time {
+        let variant = Self::variant(slf);
+        variant.fall().map_or(variant.width(), |fall| fall.width)
+    }
+
+    /// Name and version of the :class:`PulseLibrary` entry the pulse was
+    /// created from, or ``None``.
+    #[getter]
+    fn pulse(slf: &Bound<Self>) -> Option<(String, u32)> {
+        Self::variant(slf)
+            .pulse_ref()
+            .map(|r| (r.name.clone(), r.version))
+    }
+}
+
+/// A sample array play element.
+///
+/// Play an arbitrary complex waveform, e.g. an optimal control pulse delivered
+/// as data, without a :class:`Shape`. Sample :math:`k` covers the interval
+/// :math:`[k, k+1) / f_s` from the start of the element, so the duration of
+/// the element is the number of samples divided by `sample_rate`. The samples
+/// are multiplied by `amplitude` and modulated the same way as the envelope of
+/// :class:`Play`.
+///
+/// If `sample_rate` differs from the channel sample rate, set `interpolate`
+/// to resample the array linearly between the sample centers. Otherwise each
+/// sample is held over its interval, which reproduces the samples exactly when
+/// the rates match and the start is aligned to the channel samples.
+///
+/// Args:
+///     channel_id (str): Target channel ID.
+///     samples (array_like): 1D complex sample array.
+///     sample_rate (float): Sample rate of `samples`.
+///     amplitude (float): Amplitude factor of the samples. Defaults to 1.
+///     frequency (float): Additional frequency of the pulse. Defaults to 0.
+///     phase (float): Additional phase of the pulse in **cycles**. Defaults to
+///         0.
+///     interpolate (bool): Whether to interpolate linearly between samples.
+///         Defaults to ``False``.
+#[pyclass(extends=Element, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct PlaySamples;
+
+impl ElementSubclass for PlaySamples {
+    type Variant = schedule::PlaySamples;
+}
+
+#[pymethods]
+impl PlaySamples {
+    #[new]
+    #[pyo3(signature = (
+        channel_id,
+        samples,
+        sample_rate,
+        *,
+        amplitude=Amplitude::new(1.0).unwrap(),
+        frequency=Frequency::ZERO,
+        phase=Phase::ZERO,
+        interpolate=false,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        channel_id: ChannelId,
+        samples: PyArrayLike1<Complex64, AllowTypeChange>,
+        sample_rate: Frequency,
+        amplitude: Amplitude,
+        frequency: Frequency,
+        phase: Phase,
+        interpolate: bool,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let samples = samples.as_array().iter().copied().collect();
+        let variant = schedule::PlaySamples::new(channel_id, samples, sample_rate)?
+            .with_amplitude(amplitude)?
+            .with_frequency(frequency)?
+            .with_phase(phase)?
+            .with_interpolate(interpolate);
+        Ok((
+            Self,
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn channel_id<'a>(slf: &'a Bound<Self>) -> &'a ChannelId {
+        Self::variant(slf).channel_id()
+    }
+
+    #[getter]
+    fn samples<'py>(slf: &Bound<'py, Self>) -> Bound<'py, PyArray1<Complex64>> {
+        PyArray1::from_slice_bound(slf.py(), Self::variant(slf).samples())
+    }
+
+    #[getter]
+    fn sample_rate(slf: &Bound<Self>) -> Frequency {
+        Self::variant(slf).sample_rate()
+    }
+
+    #[getter]
+    fn amplitude(slf: &Bound<Self>) -> Amplitude {
+        Self::variant(slf).amplitude()
+    }
+
+    #[getter]
+    fn frequency(slf: &Bound<Self>) -> Frequency {
+        Self::variant(slf).frequency()
+    }
+
+    #[getter]
+    fn phase(slf: &Bound<Self>) -> Phase {
+        Self::variant(slf).phase()
+    }
+
+    #[getter]
+    fn interpolate(slf: &Bound<Self>) -> bool {
+        Self::variant(slf).interpolate()
+    }
+}
+
+/// Named and versioned pulse definitions.
+///
+/// A library keeps calibrated pulses under names such as ``"q0_x90"``. Each
+/// registration adds a new version and old versions are kept, so schedules
+/// built against an older calibration can be reproduced. :class:`Play`
+/// elements created by :meth:`play` record the entry they use, and the library
+/// can be embedded in the schedule document with :meth:`Element.to_json`.
+///
+/// The library is immutable. :meth:`with_pulse` returns a new library.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone, Default)]
+struct PulseLibrary(schedule::PulseLibrary);
+
+#[pymethods]
+impl PulseLibrary {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a pulse definition.
+    ///
+    /// Args:
+    ///     name (str): Name of the pulse.
+    ///     shape_id (str | None): Shape ID of the pulse. If ``None``, the
+    ///         pulse is a rectangular pulse.
+    ///     amplitude (float): Amplitude of the pulse.
+    ///     width (float): Width of the pulse.
+    ///     plateau (float): Plateau length of the pulse. Defaults to 0.
+    ///     drag_coef (float): Drag coefficient of the pulse. Defaults to 0.
+    ///     version (int | None): Version of the entry. Defaults to the version
+    ///         after the latest one of `name`, starting at 1.
+    /// Returns:
+    ///     PulseLibrary: New library with the entry added.
+    /// Raises:
+    ///     ValueError: If the version is already registered or a parameter is
+    ///         invalid.
+    #[pyo3(signature = (
+        name,
+        shape_id,
+        amplitude,
+        width,
+        *,
+        plateau=Time::ZERO,
+        drag_coef=0.0,
+        version=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn with_pulse(
+        &self,
+        name: String,
+        shape_id: Option<ShapeId>,
+        amplitude: Amplitude,
+        width: Time,
+        plateau: Time,
+        drag_coef: f64,
+        version: Option<u32>,
+    ) -> PyResult<Self> {
+        let pulse = schedule::PulseDef::new(shape_id, amplitude, width)?
+            .with_plateau(plateau)?
+            .with_drag_coef(drag_coef)?;
+        let mut library = self.0.clone();
+        library.register(name, version, pulse)?;
+        Ok(Self(library))
+    }
+
+    /// Names and versions of the registered pulses.
+    ///
+    /// Returns:
+    ///     list[tuple[str, int]]: Entries sorted by name and version.
+    fn pulses(&self) -> Vec<(String, u32)> {
+        self.0.keys().map(|(n, v)| (n.to_string(), v)).collect()
+    }
+
+    /// Create a :class:`Play` element of a library pulse.
+    ///
+    /// Args:
+    ///     name (str): Name of the pulse.
+    ///     channel_id (str): Target channel ID.
+    ///     version (int | None): Version of the pulse. Defaults to the latest
+    ///         version.
+    ///     frequency (float): See :class:`Play`.
+    ///     phase (float): See :class:`Play`.
+    ///     flexible (bool): See :class:`Play`.
+    /// Returns:
+    ///     Play: Pulse play element referencing the library entry.
+    /// Raises:
+    ///     ValueError: If the pulse is not in the library.
+    #[pyo3(signature = (
+        name,
+        channel_id,
+        *,
+        version=None,
+        frequency=Frequency::ZERO,
+        phase=Phase::ZERO,
+        flexible=false,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn play(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        channel_id: ChannelId,
+        version: Option<u32>,
+        frequency: Frequency,
+        phase: Phase,
+        flexible: bool,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<Py<Play>> {
+        let variant = self
+            .0
+            .play(channel_id, name, version)?
+            .with_frequency(frequency)?
+            .with_phase(phase)?
+            .with_flexible(flexible);
+        let element = Play::build_element(
+            variant,
+            margin,
+            alignment,
+            phantom,
+            duration,
+            max_duration,
+            min_duration,
+            allow_oversize,
+        )?;
+        Py::new(py, (Play, element))
+    }
+
+    /// Serialize the library to a JSON string.
+    ///
+    /// Returns:
+    ///     str: JSON document with a schema version.
+    fn to_json(&self) -> PyResult<String> {
+        self.0
+            .to_json()
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Deserialize a library from a JSON string.
+    ///
+    /// Args:
+    ///     s (str): JSON document created by :meth:`to_json`, or a schedule
+    ///         document created by :meth:`Element.to_json` with a library.
+    /// Returns:
+    ///     PulseLibrary: Deserialized library.
+    /// Raises:
+    ///     ValueError: If the document is invalid or has no library.
+    #[staticmethod]
+    fn from_json(s: &str) -> PyResult<Self> {
+        schedule::PulseLibrary::from_json(s)
+            .map(Self)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyAny>, (String,))> {
+        let from_json = py.get_type_bound::<Self>().getattr("from_json")?;
+        Ok((from_json, (self.to_json()?,)))
+    }
+}
+
+/// Calibrated parameters of a gate on a channel.
+///
+/// Parameters that are ``None`` are left as written in the schedule.
+///
+/// Args:
+///     amplitude (float | None): Amplitude of the pulse.
+///     drag_coef (float | None): Drag coefficient of the pulse.
+///     frequency (float | None): Additional frequency of the pulse.
+///     timestamp (float): Time of the calibration in seconds since the Unix
+///         epoch. Defaults to 0.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone, Copy)]
+struct Calibration(schedule::Calibration);
+
+#[pymethods]
+impl Calibration {
+    #[new]
+    #[pyo3(signature = (*, amplitude=None, drag_coef=None, frequency=None, timestamp=0.0))]
+    fn new(
+        amplitude: Option<Amplitude>,
+        drag_coef: Option<f64>,
+        frequency: Option<Frequency>,
+        timestamp: f64,
+    ) -> PyResult<Self> {
+        let calibration = schedule::Calibration::new(timestamp)?
+            .with_amplitude(amplitude)?
+            .with_drag_coef(drag_coef)?
+            .with_frequency(frequency)?;
+        Ok(Self(calibration))
+    }
+
+    #[getter]
+    fn amplitude(&self) -> Option<Amplitude> {
+        self.0.amplitude()
+    }
+
+    #[getter]
+    fn drag_coef(&self) -> Option<f64> {
+        self.0.drag_coef()
+    }
+
+    #[getter]
+    fn frequency(&self) -> Option<Frequency> {
+        self.0.frequency()
+    }
+
+    #[getter]
+    fn timestamp(&self) -> f64 {
+        self.0.timestamp()
+    }
+
+    fn __getnewargs_ex__<'py>(&self, py: Python<'py>) -> NewArgsEx<'py, ()> {
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("amplitude", self.amplitude())?;
+        kwargs.set_item("drag_coef", self.drag_coef())?;
+        kwargs.set_item("frequency", self.frequency())?;
+        kwargs.set_item("timestamp", self.timestamp())?;
+        Ok(((), kwargs))
+    }
+}
+
+/// Calibrations keyed by channel and gate name.
+///
+/// If several calibrations of the same key are given, the one with the latest
+/// timestamp is kept.
+///
+/// Args:
+///     calibrations (Mapping[tuple[str, str], Calibration] | None):
+///         Calibrations keyed by channel ID and gate name. Defaults to empty.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Calibrations(schedule::Calibrations);
+
+#[pymethods]
+impl Calibrations {
+    #[new]
+    #[pyo3(signature = (calibrations=None))]
+    fn new(calibrations: Option<HashMap<(ChannelId, String), Calibration>>) -> Self {
+        let mut result = schedule::Calibrations::new();
+        for ((channel, gate), calibration) in calibrations.unwrap_or_default() {
+            result.insert(channel, gate, calibration.0);
+        }
+        Self(result)
+    }
+
+    /// Add a calibration.
+    ///
+    /// Args:
+    ///     channel_id (str): Channel ID.
+    ///     gate (str): Gate name.
+    ///     calibration (Calibration): Calibrated parameters.
+    /// Returns:
+    ///     Calibrations: New calibrations. The calibration is ignored if a
+    ///     newer one of the same key is stored.
+    fn with_calibration(
+        &self,
+        channel_id: ChannelId,
+        gate: String,
+        calibration: Calibration,
+    ) -> Self {
+        let mut result = self.0.clone();
+        result.insert(channel_id, gate, calibration.0);
+        Self(result)
+    }
+
+    /// Look up a calibration.
+    ///
+    /// Args:
+    ///     channel_id (str): Channel ID.
+    ///     gate (str): Gate name.
+    /// Returns:
+    ///     Calibration | None: Stored calibration.
+    fn get(&self, channel_id: ChannelId, gate: &str) -> Option<Calibration> {
+        self.0.get(channel_id, gate).copied().map(Calibration)
+    }
+
+    fn __getnewargs__(&self) -> (HashMap<(ChannelId, String), Calibration>,) {
+        let calibrations = self
+            .0
+            .iter()
+            .map(|(channel, gate, calibration)| {
+                ((channel, gate.to_string()), Calibration(*calibration))
+            })
+            .collect();
+        (calibrations,)
+    }
+}
+
+/// Content-addressed cache of element subtrees.
+///
+/// Layout elements measure their children once and keep the result. Trees that
+/// are built separately, e.g. loaded from documents or generated by functions
+/// called in a loop, don't share subtrees even if they are equal, so every
+/// copy is measured again. :meth:`intern` replaces every subtree equal to a
+/// cached one by the cached element, so libraries of gate blocks are measured
+/// once and reused by every schedule interned into the same cache.
+///
+/// Example:
+///     .. code-block:: python
+///
+///         cache = ElementCache()
+///         blocks = [cache.intern(make_block(q)) for q in qubits]
+///         schedule = cache.intern(Stack(*(make_block(q) for q in sequence)))
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Default)]
+struct ElementCache(Mutex<schedule::ElementCache>);
+
+#[pymethods]
+impl ElementCache {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Share the subtrees of an element with the cache.
+    ///
+    /// Args:
+    ///     element (Element): Root of the element tree.
+    /// Returns:
+    ///     Element: Equal element tree whose subtrees are shared with the
+    ///     cache.
+    fn intern(&self, py: Python<'_>, element: &Bound<'_, Element>) -> PyResult<Py<Element>> {
+        let interned = self
+            .0
+            .lock()
+            .expect("Cache should not be poisoned")
+            .intern(&element.get().0)?;
+        Element::to_py_object(py, interned)
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.lock().expect("Cache should not be poisoned").len()
+    }
+}
+
+/// A phase shift element.
+///
+/// Phase shift will be added to the channel phase offset :math:`\phi_c` and is
+/// time-independent.
+///
+/// .. caution::
+///
+///     The unit of phase is number of cycles, not radians. For example, a phase
+///     of :math:`0.5` means a phase shift of :math:`\pi` radians.
+///
+/// Args:
+///     channel_id (str): Target channel ID.
+///     phase (float): Phase shift in **cycles**.
+#[pyclass(extends=Element, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct ShiftPhase;
+
+impl ElementSubclass for ShiftPhase {
+    type Variant = schedule::ShiftPhase;
+}
+
+#[pymethods]
+impl ShiftPhase {
+    #[new]
+    #[pyo3(signature = (
+        channel_id,
+        phase,
+        *,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        channel_id: ChannelId,
+        phase: Phase,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let variant = schedule::ShiftPhase::new(channel_id, phase)?;
+        Ok((
+            Self,
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn channel_id<'a>(slf: &'a Bound<Self>) -> &'a ChannelId {
+        Self::variant(slf).channel_id()
+    }
+
+    #[getter]
+    fn phase(slf: &Bound<Self>) -> Phase {
+        Self::variant(slf).phase()
+    }
+}
+
+/// A phase set element.
+///
+/// Waveform generator treats the base frequency :math:`f_0` and the channel
+/// frequency shift :math:`\Delta f` differently. :math:`f_0` is never changed
+/// during the execution of the schedule, while :math:`\Delta f` can be changed
+/// by :class:`ShiftFreq` and :class:`SetFreq`. :class:`SetPhase` only considers
+/// :math:`\Delta f` part of the frequency. The channel phase offset
+/// :math:`\phi_c` will be adjusted such that
+///
+/// .. math:: \Delta f t + \phi_c = \phi
+///
+/// at the scheduled time point, where :math:`\phi` is the `phase` parameter.
+///
+/// .. caution::
+///
+///     The unit of phase is number of cycles, not radians. For example, a phase
+///     of :math:`0.5` means a phase shift of :math:`\pi` radians.
+///
+/// Args:
+///     channel_id (str): Target channel ID.
+///     phase (float): Target phase value in **cycles**.
+#[pyclass(extends=Element, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct SetPhase;
+
+impl ElementSubclass for SetPhase {
+    type Variant = schedule::SetPhase;
+}
+
+#[pymethods]
+impl SetPhase {
+    #[new]
+    #[pyo3(signature = (
+        channel_id,
+        phase,
+        *,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        channel_id: ChannelId,
+        phase: Phase,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let variant = schedule::SetPhase::new(channel_id, phase)?;
+        Ok((
+            Self,
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn channel_id<'a>(slf: &'a Bound<Self>) -> &'a ChannelId {
+        Self::variant(slf).channel_id()
+    }
+
+    #[getter]
+    fn phase(slf: &Bound<Self>) -> Phase {
+        Self::variant(slf).phase()
+    }
+}
+
+/// A frequency shift element.
+///
+/// Frequency shift will be added to the channel frequency shift :math:`\Delta
+/// f` and the channel phase offset :math:`\phi_c` will be adjusted such that
+/// the phase is continuous at the scheduled time point.
+///
+/// Args:
+///     channel_id (str): Target channel ID.
+///     frequency (float): Delta frequency.
+#[pyclass(extends=Element, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct ShiftFreq;
+
+impl ElementSubclass for ShiftFreq {
+    type Variant = schedule::ShiftFreq;
+}
+
+#[pymethods]
+impl ShiftFreq {
+    #[new]
+    #[pyo3(signature = (
+        channel_id,
+        frequency,
+        *,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        channel_id: ChannelId,
+        frequency: Frequency,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let variant = schedule::ShiftFreq::new(channel_id, frequency)?;
+        Ok((
+            Self,
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn channel_id<'a>(slf: &'a Bound<Self>) -> &'a ChannelId {
+        Self::variant(slf).channel_id()
+    }
+
+    #[getter]
+    fn frequency(slf: &Bound<Self>) -> Frequency {
+        Self::variant(slf).frequency()
+    }
+}
+
+/// A frequency set element.
+///
+/// The channel frequency shift :math:`\Delta f` will be set to the provided
+/// `frequency` parameter and the channel phase offset :math:`\phi_c` will be
+/// adjusted such that the phase is continuous at the scheduled time point.
+/// The channel base frequency :math:`f_0` will not be changed.
+///
+/// Args:
+///     channel_id (str): Target channel ID.
+///     frequency (float): Target frequency.
+#[pyclass(extends=Element, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct SetFreq;
+
+impl ElementSubclass for SetFreq {
+    type Variant = schedule::SetFreq;
+}
+
+#[pymethods]
+impl SetFreq {
+    #[new]
+    #[pyo3(signature = (
+        channel_id,
+        frequency,
+        *,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        channel_id: ChannelId,
+        frequency: Frequency,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let variant = schedule::SetFreq::new(channel_id, frequency)?;
+        Ok((
+            Self,
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn channel_id<'a>(slf: &'a Bound<Self>) -> &'a ChannelId {
+        Self::variant(slf).channel_id()
+    }
+
+    #[getter]
+    fn frequency(slf: &Bound<Self>) -> Frequency {
+        Self::variant(slf).frequency()
+    }
+}
+
+/// A phase swap element.
+///
+/// Different from :class:`SetPhase` and :class:`SetFreq`, both the channel
+/// base frequency :math:`f_0` and the channel frequency shift :math:`\Delta f`
+/// will be considered. At the scheduled time point, the phase to be swapped
+/// is calculated as
+///
+/// .. math:: \phi(t) = (f_0 + \Delta f) t + \phi_c
+///
+/// Args:
+///     channel_id1 (str): Target channel ID 1.
+///     channel_id2 (str): Target channel ID 2.
+#[pyclass(extends=Element, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct SwapPhase;
+
+impl ElementSubclass for SwapPhase {
+    type Variant = schedule::SwapPhase;
+}
+
+#[pymethods]
+impl SwapPhase {
+    #[new]
+    #[pyo3(signature = (
+        channel_id1,
+        channel_id2,
+        *,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        channel_id1: ChannelId,
+        channel_id2: ChannelId,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let variant = schedule::SwapPhase::new(channel_id1, channel_id2);
+        Ok((
+            Self,
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn channel_id1<'a>(slf: &'a Bound<Self>) -> &'a ChannelId {
+        Self::variant(slf).channel_id1()
+    }
+
+    #[getter]
+    fn channel_id2<'a>(slf: &'a Bound<Self>) -> &'a ChannelId {
+        Self::variant(slf).channel_id2()
+    }
+}
+
+/// A barrier element.
+///
+/// A barrier element is a no-op element. Useful for aligning elements on
+/// different channels and adding space between elements in a :class:`Stack`
+/// layout.
+///
+/// If no channel IDs are provided, the layout system will arrange the barrier
+/// element as if it occupies all channels in its parent.
+///
+/// A barrier with a positive `flex` weight is a flexible gap in a
+/// :class:`Stack`. When the stack is arranged in a longer duration than it
+/// needs, e.g. because of its `duration` or the ``"stretch"`` alignment, the
+/// extra duration is distributed to the flexible barriers of the stack
+/// proportionally to their weights, similar to ``flex-grow`` in CSS. Without
+/// flexible barriers, the extra duration collects at the end opposite to the
+/// stack direction.
+///
+/// A channel ID can be a channel group of :meth:`Element.expand_groups`, the
+/// barrier then waits for all members of the group. The idle time a barrier
+/// adds to the channels waiting for the others is reported by
+/// :meth:`Element.timing_report`.
+///
+/// A barrier with a `slot` name is a placeholder of a template, e.g. for the
+/// gate under test of an echo sequence. It is replaced by a sub-schedule on the
+/// same channels with :meth:`Element.fill_slots` and arranged like any other
+/// barrier until then.
+///
+/// Args:
+///     *channel_ids (str): Channel IDs. Defaults to empty.
+///     flex (float): Weight of the barrier in the distribution of extra
+///         duration of the parent :class:`Stack`. Defaults to 0.
+///     slot (str | None): Name of the template slot. Defaults to ``None``.
+#[pyclass(extends=Element, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Barrier;
+
+impl ElementSubclass for Barrier {
+    type Variant = schedule::Barrier;
+}
+
+#[pymethods]
+impl Barrier {
+    #[new]
+    #[pyo3(signature = (
+        *channel_ids,
+        flex=0.0,
+        slot=None,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        channel_ids: Vec<ChannelId>,
+        flex: f64,
+        slot: Option<String>,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let variant = schedule::Barrier::new(channel_ids)
+            .with_flex(flex)?
+            .with_slot(slot);
+        Ok((
+            Self,
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn channel_ids(slf: &Bound<Self>) -> Vec<ChannelId> {
+        Self::variant(slf).channel_ids().to_vec()
+    }
+
+    #[getter]
+    fn flex(slf: &Bound<Self>) -> f64 {
+        Self::variant(slf).flex()
+    }
+
+    #[getter]
+    fn slot<'a>(slf: &'a Bound<Self>) -> Option<&'a str> {
+        Self::variant(slf).slot()
+    }
+}
+
+/// A repeat element.
+///
+/// Repeat the child element multiple times with a spacing between repetitions.
+///
+/// The number of repetitions can be bound to a runtime variable of the
+/// sequencer with `count_var` for adaptive-length experiments. `count` is then
+/// the maximum number of repetitions, which determines the duration of the
+/// element. Backends that can't evaluate the variable unroll the maximum
+/// number of repetitions.
+///
+/// Args:
+///     child (Element): Child element to repeat.
+///     count (int): Number of repetitions.
+///     spacing (float): Spacing between repetitions. Defaults to 0.
+///     count_var (str | None): Name of the runtime variable holding the number
+///         of repetitions. Should be an identifier, and ``count`` should be
+///         non-zero if it is given. Defaults to ``None``.
+#[pyclass(extends=Element, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Repeat {
+    child: Py<Element>,
+}
+
+impl ElementSubclass for Repeat {
+    type Variant = schedule::Repeat;
+}
+
+#[pymethods]
+impl Repeat {
+    #[new]
+    #[pyo3(signature = (
+        child,
+        count,
+        spacing=Time::ZERO,
+        *,
+        count_var=None,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        child: Py<Element>,
+        count: usize,
+        spacing: Time,
+        count_var: Option<String>,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let rust_child = child.get().0.clone();
+        let variant = schedule::Repeat::new(rust_child, count)
+            .with_count_var(count_var)?
+            .with_spacing(spacing)?;
+        Ok((
+            Self { child },
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn count(slf: &Bound<Self>) -> usize {
+        Self::variant(slf).count()
+    }
+
+    #[getter]
+    fn count_var<'a>(slf: &'a Bound<Self>) -> Option<&'a str> {
+        Self::variant(slf).count_var()
+    }
+
+    #[getter]
+    fn spacing(slf: &Bound<Self>) -> Time {
+        Self::variant(slf).spacing()
+    }
+}
+
+/// Shots of a schedule separated by a relaxation delay.
+///
+/// :meth:`to_element` lowers the experiment to a :class:`Repeat` with one
+/// iteration per shot. Each shot takes the duration of the schedule plus
+/// `relaxation`, including the last one, so consecutive runs of the
+/// experiment are separated by the relaxation as well. Backends that keep
+/// loops, e.g. :func:`generate_seqc` with ``keep_loops=True``, emit a loop
+/// over the shots, and other backends unroll it.
+///
+/// Args:
+///     schedule (Element): Schedule of one shot.
+///     shots (int): Number of shots.
+///     relaxation (float): Delay after every shot. Defaults to 0.
+///     shots_var (str | None): Name of the runtime variable holding the number
+///         of shots, see :class:`Repeat`. `shots` is then the maximum number
+///         of shots. Defaults to ``None``.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug)]
+struct Experiment {
+    schedule: Py<Element>,
+    experiment: schedule::Experiment,
+}
+
+#[pymethods]
+impl Experiment {
+    #[new]
+    #[pyo3(signature = (schedule, shots, relaxation=Time::ZERO, *, shots_var=None))]
+    fn new(
+        schedule: Py<Element>,
+        shots: usize,
+        relaxation: Time,
+        shots_var: Option<String>,
+    ) -> PyResult<Self> {
+        let experiment = schedule::Experiment::new(schedule.get().0.clone(), shots)
+            .with_relaxation(relaxation)?
+            .with_shots_var(shots_var);
+        Ok(Self {
+            schedule,
+            experiment,
+        })
+    }
+
+    #[getter]
+    fn schedule(&self, py: Python<'_>) -> Py<Element> {
+        self.schedule.clone_ref(py)
+    }
+
+    #[getter]
+    fn shots(&self) -> usize {
+        self.experiment.shots()
+    }
+
+    #[getter]
+    fn relaxation(&self) -> Time {
+        self.experiment.relaxation()
+    }
+
+    #[getter]
+    fn shots_var(&self) -> Option<&str> {
+        self.experiment.shots_var()
+    }
+
+    /// Repeated schedule of all shots.
+    ///
+    /// Returns:
+    ///     Repeat: Repeat of the schedule with the relaxation as spacing and
+    ///         end margin.
+    fn to_element(&self, py: Python<'_>) -> PyResult<Py<Element>> {
+        let element = Element(Arc::new(self.experiment.to_element()?));
+        let child = self.schedule.clone_ref(py);
+        upcast(py, Py::new(py, (Repeat { child }, element))?)
+    }
+
+    fn __getnewargs_ex__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> NewArgsEx<'py, (Py<Element>, usize, Time)> {
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("shots_var", self.experiment.shots_var())?;
+        Ok((
+            (
+                self.schedule.clone_ref(py),
+                self.experiment.shots(),
+                self.experiment.relaxation(),
+            ),
+            kwargs,
+        ))
+    }
+}
+
+/// Shot ordering of a few fixed schedules.
+///
+/// For experiments that alternate between a few schedules shot-to-shot, e.g.
+/// interleaved randomized benchmarking, the schedules are compiled once as
+/// segments with :func:`generate_sequence_waveforms`, and backends with a
+/// sequencer play the segments in the order of :attr:`entries`. Equal
+/// schedules share a segment, and consecutive shots of the same segment are
+/// merged into one entry.
+///
+/// Args:
+///     schedules (Sequence[Element]): Schedules that can be played.
+///     order (Sequence[int]): Index into `schedules` of every shot.
+/// Raises:
+///     ValueError: If an index of `order` is out of range.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug)]
+struct SequenceTable {
+    schedules: Vec<Py<Element>>,
+    order: Vec<usize>,
+    table: schedule::SequenceTable,
+}
+
+#[pymethods]
+impl SequenceTable {
+    #[new]
+    fn new(schedules: Vec<Py<Element>>, order: Vec<usize>) -> PyResult<Self> {
+        let elements: Vec<_> = schedules.iter().map(|s| s.get().0.clone()).collect();
+        let table = schedule::SequenceTable::new(&elements, &order)?;
+        Ok(Self {
+            schedules,
+            order,
+            table,
+        })
+    }
+
+    /// Distinct schedules in the order of their first shot.
+    ///
+    /// Returns:
+    ///     list[Element]: Schedule of each segment.
+    #[getter]
+    fn segments(&self, py: Python<'_>) -> PyResult<Vec<Py<Element>>> {
+        self.table
+            .segments()
+            .iter()
+            .map(|s| Element::to_py_object(py, s.clone()))
+            .collect()
+    }
+
+    /// Segment index and number of consecutive shots of each entry.
+    ///
+    /// Returns:
+    ///     list[tuple[int, int]]: ``(segment, repeat)`` of each entry.
+    #[getter]
+    fn entries(&self) -> Vec<(usize, usize)> {
+        self.table
+            .entries()
+            .iter()
+            .map(|e| (e.segment, e.repeat))
+            .collect()
+    }
+
+    /// Total number of shots.
+    #[getter]
+    fn shots(&self) -> usize {
+        self.table.shots()
+    }
+
+    fn __getnewargs__(&self, py: Python<'_>) -> (Vec<Py<Element>>, Vec<usize>) {
+        let schedules = self.schedules.iter().map(|s| s.clone_ref(py)).collect();
+        (schedules, self.order.clone())
+    }
+}
+
+/// An amplitude scaling element.
+///
+/// Multiply the amplitudes of all :class:`Play` elements in the child by a
+/// factor. Nested scaling elements multiply their factors. The child is
+/// arranged as if it were not wrapped, so the timing of the schedule is not
+/// changed.
+///
+/// Args:
+///     child (Element): Child element to scale.
+///     factor (float): Amplitude factor.
+#[pyclass(extends=Element, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct ScaleAmplitude {
+    child: Py<Element>,
+}
+
+impl ElementSubclass for ScaleAmplitude {
+    type Variant = schedule::ScaleAmplitude;
+}
+
+#[pymethods]
+impl ScaleAmplitude {
+    #[new]
+    #[pyo3(signature = (
+        child,
+        factor,
+        *,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        child: Py<Element>,
+        factor: f64,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let rust_child = child.get().0.clone();
+        let variant = schedule::ScaleAmplitude::new(rust_child, factor)?;
+        Ok((
+            Self { child },
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    #[getter]
+    fn factor(slf: &Bound<Self>) -> f64 {
+        Self::variant(slf).factor()
+    }
+}
+
+#[pymethods]
+impl Direction {
+    /// Convert the value to Direction.
+    ///
+    /// The value can be:
+    ///
+    /// - :class:`Direction`
+    /// - str: 'backward' or 'forward'
+    ///
+    /// Args:
+    ///     obj (str | Direction): Value to convert.
+    /// Returns:
+    ///     Direction: Converted value.
+    /// Raises:
+    ///     ValueError: If the value cannot be converted.
+    #[staticmethod]
+    fn convert(obj: &Bound<PyAny>) -> PyResult<Py<Self>> {
+        if let Ok(slf) = obj.extract() {
+            return Ok(slf);
+        }
+        if let Ok(s) = obj.extract() {
+            let direction = match s {
+                "backward" => Some(Direction::Backward),
+                "forward" => Some(Direction::Forward),
+                _ => None,
+            };
+            if let Some(direction) = direction {
+                return Py::new(obj.py(), direction);
+            }
+        }
+        let msg = concat!(
+            "Failed to convert the value to Direction. ",
+            "Must be Direction or one of 'backward', 'forward'"
+        );
+        Err(PyValueError::new_err(msg))
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<VariantReduce<'py>> {
+        reduce_variant::<Self>(py, self)
+    }
+}
+
+fn extract_direction(obj: &Bound<PyAny>) -> PyResult<Direction> {
+    Direction::convert(obj).and_then(|x| x.extract(obj.py()))
+}
+
+/// A stack layout element.
+///
+/// Each child element occupies some channels and has a duration. Stack layout
+/// will put children as close as possible without changing the order of
+/// children with common channels. Two layout orders are available:
+/// :attr:`Direction.Backward` and :attr:`Direction.Forward`. The default order
+/// is :attr:`Direction.Backward`.
+///
+/// `padding` adds space inside the stack before and after the children.
+/// `child_margin` is the minimum margin of the children: a child with a
+/// smaller :attr:`Element.margin` is arranged as if it had `child_margin`,
+/// which avoids setting the margin of every pulse in dense gate trains.
+///
+/// Args:
+///     *children (Element): Child elements.
+///     direction (str | Direction): Layout order. Defaults to 'backward'.
+///     padding (float | tuple[float, float] | None): Padding inside the stack.
+///         Defaults to 0.
+///     child_margin (float | tuple[float, float] | None): Minimum margin of the
+///         children. Defaults to 0.
+#[pyclass(extends=Element, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Stack {
+    children: Vec<Py<Element>>,
+}
+
+impl ElementSubclass for Stack {
+    type Variant = schedule::Stack;
+}
+
+#[pymethods]
+impl Stack {
+    #[new]
+    #[pyo3(signature = (
+        *children,
+        direction=None,
+        padding=None,
+        child_margin=None,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        children: Vec<Py<Element>>,
+        direction: Option<&Bound<PyAny>>,
+        padding: Option<&Bound<PyAny>>,
+        child_margin: Option<&Bound<PyAny>>,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let rust_children = children.iter().map(|x| x.get().0.clone()).collect();
+        let variant = schedule::Stack::new().with_children(rust_children);
+        let variant = if let Some(obj) = direction {
+            variant.with_direction(extract_direction(obj)?)
+        } else {
+            variant
+        };
+        let variant = match padding {
+            Some(obj) => variant.with_padding(extract_margin(obj)?)?,
+            None => variant,
+        };
+        let variant = match child_margin {
+            Some(obj) => variant.with_child_margin(extract_margin(obj)?)?,
+            None => variant,
+        };
+        Ok((
+            Self { children },
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    /// Create a new stack layout with different children.
+    ///
+    /// Using this method may be more readable than specifying children in the
+    /// constructor.
+    ///
+    /// .. code-block:: python
+    ///
+    ///     stack = Stack(direction='forward').with_children(
+    ///         element1,
+    ///         element2,
+    ///     )
+    ///
+    /// Args:
+    ///     *children (Element): New child elements.
+    /// Returns:
+    ///     Stack: New stack layout.
+    #[pyo3(signature=(*children))]
+    fn with_children(slf: &Bound<Self>, children: Vec<Py<Element>>) -> PyResult<Py<Self>> {
+        let py = slf.py();
+        let rust_children = children.iter().map(|x| x.get().0.clone()).collect();
+        let rust_base = &slf.downcast::<Element>()?.get().0;
+        let common = rust_base.common.clone();
+        let variant = Self::variant(slf).clone().with_children(rust_children);
+        Py::new(
+            py,
+            (
+                Self { children },
+                Element(Arc::new(schedule::Element::new(common, variant))),
+            ),
+        )
+    }
+
+    #[getter]
+    fn direction(slf: &Bound<Self>) -> Direction {
+        Self::variant(slf).direction()
+    }
+
+    #[getter]
+    fn padding(slf: &Bound<Self>) -> (Time, Time) {
+        Self::variant(slf).padding()
+    }
+
+    #[getter]
+    fn child_margin(slf: &Bound<Self>) -> (Time, Time) {
+        Self::variant(slf).child_margin()
+    }
+}
+
+/// A child element with an absolute time in a absolute layout.
+///
+/// The time of each child element is relative to the start of the absolute
+/// layout. If `from_end` is ``True``, the time is relative to the end of the
+/// absolute layout instead and is usually negative, e.g. ``-1e-6`` places the
+/// child 1 µs before the end regardless of the duration of the layout. The
+/// child has to end inside the layout, so the time can be at most the negated
+/// duration of the child.
+///
+/// Args:
+///     time (float): Time relative to the start of the parent element.
+///     element (Element): Child element.
+///     from_end (bool): Whether the time is relative to the end of the parent
+///         element. Defaults to ``False``.
+/// Raises:
+///     ValueError: If the time is not finite, or the child would end after
+///         the end of the parent element.
+#[pyclass(get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct AbsoluteEntry {
+    time: Time,
+    element: Py<Element>,
+    from_end: bool,
+}
+
+#[pymethods]
+impl AbsoluteEntry {
+    #[new]
+    #[pyo3(signature = (time, element, *, from_end=false))]
+    fn new(time: Time, element: Py<Element>, from_end: bool) -> PyResult<Self> {
+        if !time.is_finite() {
+            return Err(PyValueError::new_err("Time must be finite"));
+        }
+        schedule::AbsoluteEntry::new(element.get().0.clone())
+            .with_time(time)?
+            .with_from_end(from_end)?;
+        Ok(AbsoluteEntry {
+            time,
+            element,
+            from_end,
+        })
+    }
+
+    /// Convert the value to AbsoluteEntry.
+    ///
+    /// the value can be:
+    ///
+    /// - AbsoluteEntry
+    /// - Element
+    /// - tuple[float, Element]: Time and element.
+    ///
+    /// Args:
+    ///     obj (AbsoluteEntry | Element | tuple[float, Element]): Value to convert.
+    /// Returns:
+    ///     AbsoluteEntry: Converted value.
+    /// Raises:
+    ///     ValueError: If the value cannot be converted.
+    #[staticmethod]
+    fn convert(obj: &Bound<PyAny>) -> PyResult<Py<Self>> {
+        let py = obj.py();
+        if let Ok(slf) = obj.extract() {
+            return Ok(slf);
+        }
+        if let Ok(element) = obj.extract() {
+            return Py::new(py, AbsoluteEntry::new(Time::ZERO, element, false)?);
+        }
+        if let Ok((time, element)) = obj.extract() {
+            return Py::new(py, AbsoluteEntry::new(time, element, false)?);
+        }
+        Err(PyValueError::new_err(
+            "Failed to convert the value to AbsoluteEntry",
+        ))
+    }
+
+    fn __getnewargs_ex__<'py>(&self, py: Python<'py>) -> NewArgsEx<'py, (Time, Py<Element>)> {
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("from_end", self.from_end)?;
+        Ok(((self.time, self.element.clone_ref(py)), kwargs))
+    }
+}
+
+fn extract_absolute_entry(obj: &Bound<PyAny>) -> PyResult<AbsoluteEntry> {
+    AbsoluteEntry::convert(obj).and_then(|x| x.extract(obj.py()))
+}
+
+/// An absolute layout element.
+///
+/// The child elements are arranged in absolute time. The time of each child
+/// element is relative to the start of the absolute schedule. The duration of
+/// the absolute schedule is the maximum end time of the child elements.
+///
+/// Entries with :attr:`AbsoluteEntry.from_end` set are placed relative to the
+/// end of the absolute schedule. They only require the duration to be long
+/// enough for them to start inside the schedule, and have to end at or before
+/// the end of it.
+///
+/// The `children` argument can be:
+///
+/// - AbsoluteEntry
+/// - Element
+/// - tuple[float, Element]: Time and element.
+///
+/// Args:
+///     *children (AbsoluteEntry | Element | tuple[float, Element]): Child elements.
+/// Example:
+///     .. code-block:: python
+///
+///         absolute = Absolute(
+///             element1,
+///             (1.0, element2),
+///             AbsoluteEntry(2.0, element3),
+///         )
+#[pyclass(extends=Element, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Absolute {
+    children: Vec<AbsoluteEntry>,
+}
+
+impl ElementSubclass for Absolute {
+    type Variant = schedule::Absolute;
+}
+
+#[pymethods]
+impl Absolute {
+    #[new]
+    #[pyo3(signature = (
+        *children,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python,
+        children: Vec<Py<PyAny>>,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let children: Vec<AbsoluteEntry> = children
+            .into_iter()
+            .map(|x| extract_absolute_entry(&x.into_bound(py)))
+            .collect::<PyResult<_>>()?;
+        let rust_children = children
+            .iter()
+            .map(|x| {
+                let element = x.element.get().0.clone();
+                Ok(schedule::AbsoluteEntry::new(element)
+                    .with_time(x.time)?
+                    .with_from_end(x.from_end)?)
+            })
+            .collect::<PyResult<_>>()?;
+        let variant = schedule::Absolute::new().with_children(rust_children);
+        Ok((
+            Self { children },
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    /// Create a new absolute schedule with different children.
+    ///
+    /// Using this method may be more readable than specifying children in the
+    /// constructor.
+    ///
+    /// .. code-block:: python
+    ///
+    ///     absolute = Absolute(duration=50e-6).with_children(
+    ///         element1,
+    ///         (100e-9, element2),
+    ///     )
+    ///
+    /// Args:
+    ///     *children (AbsoluteEntry | Element | tuple[float, Element]): New
+    ///         child elements.
+    /// Returns:
+    ///     Absolute: New absolute schedule.
+    #[pyo3(signature=(*children))]
+    fn with_children(slf: &Bound<Self>, children: Vec<Py<PyAny>>) -> PyResult<Py<Self>> {
+        let py = slf.py();
+        let children: Vec<_> = children
+            .into_iter()
+            .map(|x| extract_absolute_entry(&x.into_bound(py)))
+            .collect::<PyResult<_>>()?;
+        let rust_children = children
+            .iter()
+            .map(|x| {
+                let element = x.element.get().0.clone();
+                Ok(schedule::AbsoluteEntry::new(element)
+                    .with_time(x.time)?
+                    .with_from_end(x.from_end)?)
+            })
+            .collect::<PyResult<_>>()?;
+        let rust_base = &slf.downcast::<Element>()?.get().0;
+        let common = rust_base.common.clone();
+        let variant = Self::variant(slf).clone().with_children(rust_children);
+        Py::new(
+            py,
+            (
+                Self { children },
+                Element(Arc::new(schedule::Element::new(common, variant))),
+            ),
+        )
+    }
+}
+
+#[pymethods]
+impl GridLengthUnit {
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<VariantReduce<'py>> {
+        reduce_variant::<Self>(py, self)
+    }
+}
+
+#[pymethods]
+impl GridLength {
+    /// Create an automatic grid length.
+    ///
+    /// Returns:
+    ///     GridLength: Automatic grid length.
+    #[staticmethod]
+    #[pyo3(name = "auto")]
+    fn py_auto() -> Self {
+        GridLength::auto()
+    }
+
+    /// Create a ratio based grid length.
+    ///
+    /// Args:
+    ///     value (float): Ratio of the remaining duration.
+    /// Returns:
+    ///     GridLength: Ratio based grid length.
+    #[staticmethod]
+    #[pyo3(name = "star")]
+    fn py_star(value: f64) -> PyResult<Self> {
+        GridLength::star(value).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Create a fixed grid length.
+    ///
+    /// Args:
+    ///     value (float): Fixed length in seconds.
+    /// Returns:
+    ///     GridLength: Fixed grid length.
+    #[staticmethod]
+    #[pyo3(name = "fixed")]
+    fn py_fixed(value: f64) -> PyResult<Self> {
+        GridLength::fixed(value).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Convert the value to GridLength.
+    ///
+    /// The value can be:
+    ///
+    /// - GridLength
+    /// - float: Fixed length in seconds.
+    /// - 'auto': Auto length.
+    /// - 'x*': x stars.
+    /// - 'x': Fixed length in seconds.
+    /// - '*': 1 star.
+    ///
+    /// Args:
+    ///     obj (GridLength | float | str): Value to convert.
+    /// Returns:
+    ///     GridLength: Converted value.
+    /// Raises:
+    ///     ValueError: If the value cannot be converted.
+    #[staticmethod]
+    fn convert(obj: &Bound<PyAny>) -> PyResult<Py<Self>> {
+        let py = obj.py();
+        if let Ok(slf) = obj.extract() {
+            return Ok(slf);
+        }
+        if let Ok(v) = obj.extract() {
+            return Py::new(py, GridLength::py_fixed(v)?);
+        }
+        if let Ok(s) = obj.extract::<&str>() {
+            let length: GridLength = s
+                .parse()
+                .map_err(|e: anyhow::Error| PyValueError::new_err(e.to_string()))?;
+            return Py::new(py, length);
+        }
+        Err(PyValueError::new_err(
+            "Failed to convert the value to GridLength.",
+        ))
+    }
+
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyAny>, (String,))> {
+        let convert = py.get_type_bound::<Self>().getattr("convert")?;
+        Ok((convert, (self.to_string(),)))
+    }
+}
+
+fn extract_grid_length(obj: &Bound<PyAny>) -> PyResult<GridLength> {
+    GridLength::convert(obj).and_then(|x| x.extract(obj.py()))
+}
+
+/// A child element in a grid layout.
+///
+/// Args:
+///     element (Element): Child element.
+///     column (int): Column index.
+///     span (int): Column span.
+#[pyclass(get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct GridEntry {
+    element: Py<Element>,
+    column: usize,
+    span: usize,
+}
+
+#[pymethods]
+impl GridEntry {
+    #[new]
+    #[pyo3(signature = (element, column=0, span=1))]
+    fn new(element: Py<Element>, column: usize, span: usize) -> PyResult<Self> {
+        if span == 0 {
+            return Err(PyValueError::new_err("The span must be greater than 0."));
+        }
+        Ok(GridEntry {
+            element,
+            column,
+            span,
+        })
+    }
+
+    /// Convert the value to GridEntry.
+    ///
+    /// The value can be:
+    ///
+    /// - GridEntry
+    /// - Element
+    /// - tuple[Element, int]: Element and column.
+    /// - tuple[Element, int, int]: Element, column, and span.
+    ///
+    /// Args:
+    ///     obj (GridEntry | Element | tuple[Element, int] | tuple[Element, int, int]): Value to convert.
+    /// Returns:
+    ///     GridEntry: Converted value.
+    /// Raises:
+    ///     ValueError: If the value cannot be converted.
+    #[staticmethod]
+    fn convert(obj: &Bound<PyAny>) -> PyResult<Py<Self>> {
+        let py = obj.py();
+        if let Ok(slf) = obj.extract() {
+            return Ok(slf);
+        }
+        if let Ok(element) = obj.extract() {
+            return Py::new(py, GridEntry::new(element, 0, 1)?);
+        }
+        if let Ok((element, column)) = obj.extract() {
+            return Py::new(py, GridEntry::new(element, column, 1)?);
+        }
+        if let Ok((element, column, span)) = obj.extract() {
+            return Py::new(py, GridEntry::new(element, column, span)?);
+        }
+        Err(PyValueError::new_err(
+            "Failed to convert the value to GridEntry.",
+        ))
+    }
+
+    fn __getnewargs__(&self, py: Python<'_>) -> (Py<Element>, usize, usize) {
+        (self.element.clone_ref(py), self.column, self.span)
+    }
+}
+
+fn extract_grid_entry(obj: &Bound<PyAny>) -> PyResult<GridEntry> {
+    GridEntry::convert(obj).and_then(|x| x.extract(obj.py()))
+}
+
+/// A grid layout element.
+///
+/// A grid layout has multiple columns and each child element occupies some
+/// columns. The width of each column can be specified by :class:`GridLength`,
+/// which can be:
+///
+/// - Fixed length in seconds.
+/// - Auto length:
+///     The width is determined by the child element.
+///
+/// - Star length:
+///     The width id determined by remaining duration. For example, if there
+///     are two columns with 1* and 2* and the remaining duration is 300 ns,
+///     the width of the columns will be 100 ns and 200 ns.
+///
+/// Columns length can be specified with a simplified syntax:
+///
+/// - 'auto': Auto length.
+/// - 'x*': x stars.
+/// - 'x': Fixed length in seconds.
+/// - '*': 1 star.
+///
+/// If no columns are provided, the grid layout will have one column with '*'.
+///
+/// Children can be provided as:
+///
+/// - GridEntry
+/// - Element: The column index is 0 and the span is 1.
+/// - tuple[Element, int]: Element and column. The span is 1.
+/// - tuple[Element, int, int]: Element, column, and span.
+///
+/// Args:
+///     *children (GridEntry | Element | tuple[Element, int] | tuple[Element, int, int]): Child elements.
+///     columns (Iterable[GridLength | float | str]): Column lengths. Defaults to ['*'].
+///     padding (float | tuple[float, float] | None): Padding before the first
+///         and after the last column. Defaults to 0.
+///     child_margin (float | tuple[float, float] | None): Minimum margin of the
+///         children, see :class:`Stack`. Defaults to 0.
+/// Example:
+///     .. code-block:: python
+///
+///         grid = Grid(
+///             GridEntry(element1, 0, 1),
+///             (element2, 1),
+///             (element3, 2, 2),
+///             element4,
+///             columns=['auto', '1*', '2'],
+///         )
+#[pyclass(extends=Element, get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Grid {
+    children: Vec<GridEntry>,
+}
+
+impl ElementSubclass for Grid {
+    type Variant = schedule::Grid;
+}
+
+#[pymethods]
+impl Grid {
+    #[new]
+    #[pyo3(signature = (
+        *children,
+        columns=vec![],
+        padding=None,
+        child_margin=None,
+        margin=None,
+        alignment=None,
+        phantom=false,
+        duration=None,
+        max_duration=Time::INFINITY,
+        min_duration=Time::ZERO,
+        allow_oversize=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python,
+        children: Vec<Py<PyAny>>,
+        columns: Vec<Py<PyAny>>,
+        padding: Option<&Bound<PyAny>>,
+        child_margin: Option<&Bound<PyAny>>,
+        margin: Option<&Bound<PyAny>>,
+        alignment: Option<&Bound<PyAny>>,
+        phantom: bool,
+        duration: Option<Time>,
+        max_duration: Time,
+        min_duration: Time,
+        allow_oversize: Option<bool>,
+    ) -> PyResult<(Self, Element)> {
+        let children: Vec<_> = children
+            .into_iter()
+            .map(|x| extract_grid_entry(&x.into_bound(py)))
+            .collect::<PyResult<_>>()?;
+        let columns: Vec<_> = columns
+            .into_iter()
+            .map(|x| extract_grid_length(&x.into_bound(py)))
+            .collect::<PyResult<_>>()?;
+        let rust_children = children
+            .iter()
+            .map(|x| {
+                let element = x.element.get().0.clone();
+                schedule::GridEntry::new(element)
+                    .with_column(x.column)
+                    .with_span(x.span)
+                    .expect("Should be checked in GridEntry::new")
+            })
+            .collect();
+        let variant = schedule::Grid::new()
+            .with_children(rust_children)
+            .with_columns(columns);
+        let variant = match padding {
+            Some(obj) => variant.with_padding(extract_margin(obj)?)?,
+            None => variant,
+        };
+        let variant = match child_margin {
+            Some(obj) => variant.with_child_margin(extract_margin(obj)?)?,
+            None => variant,
+        };
+        Ok((
+            Self { children },
+            Self::build_element(
+                variant,
+                margin,
+                alignment,
+                phantom,
+                duration,
+                max_duration,
+                min_duration,
+                allow_oversize,
+            )?,
+        ))
+    }
+
+    /// Create a new grid schedule with different children.
+    ///
+    /// Using this method may be more readable than specifying children in the
+    /// constructor.
+    ///
+    /// .. code-block:: python
+    ///
+    ///     grid = Grid(columns=['auto', '*', 'auto']).with_children(
+    ///         element1,
+    ///         (element2, 2),
+    ///         (element3, 0, 3),
+    ///     )
+    ///
+    /// Args:
+    ///     *children (GridEntry | Element | tuple[Element, int] | tuple[Element, int, int]): New child elements.
+    /// Returns:
+    ///     Grid: New grid schedule.
+    #[pyo3(signature=(*children))]
+    fn with_children(slf: &Bound<Self>, children: Vec<Py<PyAny>>) -> PyResult<Py<Self>> {
+        let py = slf.py();
+        let children: Vec<_> = children
+            .into_iter()
+            .map(|x| extract_grid_entry(&x.into_bound(py)))
+            .collect::<PyResult<_>>()?;
+        let rust_children = children
+            .iter()
+            .map(|x| {
+                let element = x.element.get().0.clone();
+                schedule::GridEntry::new(element)
+                    .with_column(x.column)
+                    .with_span(x.span)
+                    .expect("Should be checked in GridEntry::new")
+            })
+            .collect();
+        let rust_base = &slf.downcast::<Element>()?.get().0;
+        let common = rust_base.common.clone();
+        let variant = Self::variant(slf).clone().with_children(rust_children);
+        Py::new(
+            py,
+            (
+                Self { children },
+                Element(Arc::new(schedule::Element::new(common, variant))),
+            ),
+        )
+    }
+
+    #[getter]
+    fn columns(slf: &Bound<Self>) -> Vec<GridLength> {
+        Self::variant(slf).columns().to_vec()
+    }
+
+    #[getter]
+    fn padding(slf: &Bound<Self>) -> (Time, Time) {
+        Self::variant(slf).padding()
+    }
+
+    #[getter]
+    fn child_margin(slf: &Bound<Self>) -> (Time, Time) {
+        Self::variant(slf).child_margin()
+    }
+}
+
+/// Time grid that instructions are snapped to.
+///
+/// The start and end of every instruction are rounded to multiples of `step`,
+/// e.g. the waveform granularity of an AWG. Non-empty spans shorter than
+/// `min_length` are extended, which only affects flexible :class:`Play`
+/// elements. A warning is emitted if an instruction is adjusted by more than
+/// the time tolerance, and an error is raised if the adjustments change the
+/// order of the instructions on a channel or across a :class:`Barrier`.
+///
+/// Args:
+///     step (float): Step of the grid.
+///     min_length (float): Minimum span of non-empty instructions. Defaults to
+///         0.
+///     rounding (str): Rounding policy, one of 'nearest', 'floor', 'ceil'.
+///         Defaults to 'nearest'.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone, Copy)]
+struct TimeGrid(executor::TimeGrid);
+
+#[pymethods]
+impl TimeGrid {
+    #[new]
+    #[pyo3(signature = (step, *, min_length=Time::ZERO, rounding="nearest"))]
+    fn new(step: Time, min_length: Time, rounding: &str) -> PyResult<Self> {
+        let rounding = quant::Rounding::from_name(rounding).ok_or_else(|| {
+            PyValueError::new_err(format!("Invalid rounding policy '{}'", rounding))
+        })?;
+        let grid = executor::TimeGrid::new(step, min_length, rounding)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self(grid))
+    }
+
+    #[getter]
+    fn step(&self) -> Time {
+        self.0.step()
+    }
+
+    #[getter]
+    fn min_length(&self) -> Time {
+        self.0.min_length()
+    }
+
+    #[getter]
+    fn rounding(&self) -> &'static str {
+        self.0.rounding().name()
+    }
+
+    fn __getnewargs_ex__<'py>(&self, py: Python<'py>) -> NewArgsEx<'py, (Time,)> {
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("min_length", self.min_length())?;
+        kwargs.set_item("rounding", self.rounding())?;
+        Ok(((self.step(),), kwargs))
+    }
+}
+
+/// Ring-down of a channel that is canceled after every pulse.
+///
+/// The channel is modeled as a single pole response with time constant `tau`,
+/// e.g. a resonator or a line that keeps ringing after the drive stops. A
+/// constant tail of `length` is appended to every pulse with the same
+/// frequency and phase. Its amplitude brings the ring-down state back to zero
+/// at the end of the tail, so the residual ringing vanishes after it. The
+/// amplitude only depends on the envelope of the pulse, and DRAG components
+/// are not compensated.
+///
+/// The tail is played after the pulse and assumes the channel is silent
+/// during it. Generating waveforms fails if a later pulse on the channel
+/// starts before the tail ends.
+///
+/// Args:
+///     tau (float): Time constant of the ring-down.
+///     length (float): Length of the tail. Shorter tails need larger
+///         amplitudes.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone, Copy)]
+struct Ringdown(executor::Ringdown);
+
+#[pymethods]
+impl Ringdown {
+    #[new]
+    #[pyo3(signature = (tau, length))]
+    fn new(tau: Time, length: Time) -> PyResult<Self> {
+        let ringdown = executor::Ringdown::new(tau, length)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self(ringdown))
+    }
+
+    #[getter]
+    fn tau(&self) -> Time {
+        self.0.tau()
+    }
+
+    #[getter]
+    fn length(&self) -> Time {
+        self.0.length()
+    }
+
+    fn __getnewargs__(&self) -> (Time, Time) {
+        (self.tau(), self.length())
+    }
+}
+
+/// Continuous carrier of a channel while none of its pulses is playing.
+///
+/// The tone is added by the sampler instead of the schedule, e.g. as a phase
+/// reference or to calibrate the LO leakage. Its phase follows `freq` from
+/// time zero of the channel including the delay, so it stays coherent across
+/// the idle periods. Pulses added by crosstalk don't interrupt the tone.
+///
+/// Args:
+///     amplitude (float): Amplitude of the tone.
+///     freq (float): Frequency of the tone, independent of the base frequency
+///         of the channel.
+///     phase (float): Phase at time zero in cycles. Defaults to 0.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone, Copy)]
+struct IdleTone(pulse::IdleTone);
+
+#[pymethods]
+impl IdleTone {
+    #[new]
+    #[pyo3(signature = (amplitude, freq, *, phase=Phase::ZERO))]
+    fn new(amplitude: Amplitude, freq: Frequency, phase: Phase) -> Self {
+        Self(pulse::IdleTone {
+            amplitude,
+            freq,
+            phase,
+        })
+    }
+
+    #[getter]
+    fn amplitude(&self) -> Amplitude {
+        self.0.amplitude
+    }
+
+    #[getter]
+    fn freq(&self) -> Frequency {
+        self.0.freq
+    }
+
+    #[getter]
+    fn phase(&self) -> Phase {
+        self.0.phase
+    }
+
+    fn __getnewargs_ex__<'py>(&self, py: Python<'py>) -> NewArgsEx<'py, (Amplitude, Frequency)> {
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("phase", self.phase())?;
+        Ok(((self.amplitude(), self.freq()), kwargs))
+    }
+}
+
+/// Amplitude-dependent predistortion of a channel.
+///
+/// Maps the desired amplitude to the DAC amplitude, e.g. to linearize the
+/// compression of an amplifier without post-processing the waveforms. The map
+/// is applied to the magnitude of each sample of the mixed waveform, so the
+/// phase of complex channels and the sign of real channels are kept.
+///
+/// The lookup table is interpolated linearly and starts at the origin, which is
+/// added if `input` doesn't start at zero. Amplitudes above the last point
+/// continue the last segment. Use :meth:`polynomial` for a polynomial map.
+///
+/// Args:
+///     input (Sequence[float]): Strictly increasing desired amplitudes.
+///     output (Sequence[float]): Strictly increasing DAC amplitudes.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Predistortion(pulse::Predistortion);
+
+#[pymethods]
+impl Predistortion {
+    #[new]
+    #[pyo3(signature = (input, output))]
+    fn new(input: Vec<f64>, output: Vec<f64>) -> PyResult<Self> {
+        let predistortion = pulse::Predistortion::table(input, output)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self(predistortion))
+    }
+
+    /// Polynomial predistortion without a constant term.
+    ///
+    /// The DAC amplitude is ``c[0] * x + c[1] * x**2 + ...``. Only the slope
+    /// at zero is checked, the polynomial should be increasing over the
+    /// amplitudes of the channel.
+    ///
+    /// Args:
+    ///     coefficients (Sequence[float]): Coefficients starting from the
+    ///         linear term, which should be positive.
+    /// Returns:
+    ///     Predistortion: The polynomial predistortion.
+    #[staticmethod]
+    fn polynomial(coefficients: Vec<f64>) -> PyResult<Self> {
+        let predistortion = pulse::Predistortion::polynomial(coefficients)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self(predistortion))
+    }
+
+    /// Desired amplitudes of the table including the origin, ``None`` for
+    /// polynomials.
+    #[getter]
+    fn input(&self) -> Option<Vec<f64>> {
+        match &self.0 {
+            pulse::Predistortion::Table { input, .. } => Some(input.clone()),
+            pulse::Predistortion::Polynomial { .. } => None,
+        }
+    }
+
+    /// DAC amplitudes of the table including the origin, ``None`` for
+    /// polynomials.
+    #[getter]
+    fn output(&self) -> Option<Vec<f64>> {
+        match &self.0 {
+            pulse::Predistortion::Table { output, .. } => Some(output.clone()),
+            pulse::Predistortion::Polynomial { .. } => None,
+        }
+    }
+
+    /// Coefficients of the polynomial, ``None`` for tables.
+    #[getter]
+    fn coefficients(&self) -> Option<Vec<f64>> {
+        match &self.0 {
+            pulse::Predistortion::Table { .. } => None,
+            pulse::Predistortion::Polynomial { coefficients } => Some(coefficients.clone()),
+        }
+    }
+
+    /// DAC amplitude of the desired amplitude.
+    ///
+    /// Args:
+    ///     amplitude (float): Non-negative desired amplitude.
+    /// Returns:
+    ///     float: DAC amplitude.
+    fn __call__(&self, amplitude: f64) -> f64 {
+        self.0.map(amplitude)
+    }
+
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(Bound<'py, PyAny>, Bound<'py, PyTuple>)> {
+        let py = slf.py();
+        let predistortion = slf.get();
+        Ok(match &predistortion.0 {
+            pulse::Predistortion::Table { input, output } => (
+                slf.get_type().into_any(),
+                PyTuple::new_bound(py, [input.to_object(py), output.to_object(py)]),
+            ),
+            pulse::Predistortion::Polynomial { coefficients } => (
+                slf.get_type().getattr("polynomial")?,
+                PyTuple::new_bound(py, [coefficients.to_object(py)]),
+            ),
+        })
+    }
+}
+
+/// Generate waveforms from a schedule.
+///
+/// .. caution::
+///
+///     Crosstalk matrix will not be applied to offset of the channels.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     shapes (Mapping[str, Shape]): Shapes used in the schedule.
+///     schedule (Element): Root element of the schedule.
+///     time_tolerance (float): Tolerance for time comparison. Default is 1e-12.
+///     amp_tolerance (float): Tolerance for amplitude comparison. Default is
+///         0.1 / 2^16.
+///     allow_oversize (bool): Allow oversize elements. Default is ``False``.
+///         Elements can override it with :attr:`Element.allow_oversize`.
+///     crosstalk (tuple[array_like, Sequence[str]] | None): Crosstalk matrix
+///         with corresponding channel ids. Default is ``None``.
+///     allow_truncate (bool): Clip pulses outside the waveform instead of
+///         raising an error. Default is ``False``.
+///     time_grid (TimeGrid | None): Grid that instructions are snapped to.
+///         Default is ``None``.
+///     fractional_delay (bool): Realize sub-sample offsets of shaped pulses by
+///         interpolating the envelope instead of aligning the pulse start to
+///         ``align_level`` of the channel. Default is ``False``.
+///     oversampling (int): Sample the envelopes of shaped pulses at this
+///         multiple of the channel rate and decimate them with an
+///         anti-aliasing filter, which reduces the staircase error of pulses
+///         only a few samples wide. The filtered envelope is clipped silently
+///         at the ends of the waveform. Default is 1, i.e. no oversampling.
+///     observer (Callable[[Element, float, float], None] | None): Called
+///         with each non-phantom element, its absolute start time, and its
+///         duration while the schedule is arranged, e.g. for logging or
+///         constraint checks. Margins are excluded and instructions are
+///         reported after snapping to the time grid. An exception raised by
+///         the observer is re-raised after arranging. Default is ``None``.
+///     cache (CompileCache | None): Cache of pulse lists keyed by the
+///         schedule and the other inputs. On a hit the schedule is not
+///         measured or arranged again, the cached warnings are emitted, and
+///         `observer` is not called. Default is ``None``.
+/// Returns:
+///     Dict[str, numpy.ndarray]: Waveforms of the channels. The key is the
+///         channel name and the value is the waveform. The shape of the
+///         waveform is ``(n, length)``, where ``n`` is 2 for complex waveform
+///         and 1 for real waveform.
+/// Raises:
+///     ValueError: If some input is invalid.
+///     TypeError: If some input has an invalid type.
+///     RuntimeError: If waveform generation fails.
+/// Warns:
+///     UserWarning: For non-fatal issues, e.g. dropped zero amplitude pulses,
+///         coalesced pulses, allowed oversize elements, truncated pulses, and
+///         instructions snapped to the time grid.
+/// Example:
+///     .. code-block:: python
+///
+///         from bosing import Barrier, Channel, Hann, Play, Stack, generate_waveforms
+///         channels = {"xy": Channel(30e6, 2e9, 1000)}
+///         shapes = {"hann": Hann()}
+///         schedule = Stack(duration=500e-9).with_children(
+///             Play(
+///                 channel_id="xy",
+///                 shape_id="hann",
+///                 amplitude=0.3,
+///                 width=100e-9,
+///                 plateau=200e-9,
+///             ),
+///             Barrier(duration=10e-9),
+///         )
+///         result = generate_waveforms(channels, shapes, schedule)
+#[pyfunction]
+#[pyo3(signature = (
+    channels,
+    shapes,
+    schedule,
+    *,
+    time_tolerance=Time::new(1e-12).unwrap(),
+    amp_tolerance=Amplitude::new(0.1 / 2f64.powi(16)).unwrap(),
+    allow_oversize=false,
+    crosstalk=None,
+    allow_truncate=false,
+    time_grid=None,
+    fractional_delay=false,
+    oversampling=1,
+    observer=None,
+    cache=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn generate_waveforms(
+    py: Python,
+    channels: HashMap<ChannelId, Channel>,
+    shapes: HashMap<ShapeId, Py<Shape>>,
+    schedule: Bound<Element>,
+    time_tolerance: Time,
+    amp_tolerance: Amplitude,
+    allow_oversize: bool,
+    crosstalk: Option<(PyArrayLike2<f64, AllowTypeChange>, Vec<ChannelId>)>,
+    allow_truncate: bool,
+    time_grid: Option<TimeGrid>,
+    fractional_delay: bool,
+    oversampling: usize,
+    observer: Option<Bound<PyAny>>,
+    cache: Option<Bound<CompileCache>>,
+) -> PyResult<HashMap<ChannelId, Py<PyArray2<f64>>>> {
+    check_crosstalk(&crosstalk)?;
+    let pulse_lists = build_pulse_lists(
+        py,
+        schedule,
+        &channels,
+        &shapes,
+        time_tolerance,
+        amp_tolerance,
+        allow_oversize,
+        time_grid,
+        observer,
+        cache.as_ref().map(|c| c.get()),
+    )?;
+    sample_and_post_process(
+        py,
+        &channels,
+        pulse_lists,
+        crosstalk.as_ref(),
+        time_tolerance,
+        amp_tolerance,
+        allow_truncate,
+        fractional_delay,
+        oversampling,
+    )
+}
+
+/// Generate the waveforms of the segments of a sequence table.
+///
+/// Every segment of `table` is compiled once, so interleaved sequences don't
+/// repeat the compilation of shared schedules. The arguments are the same as
+/// :func:`generate_waveforms`.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     shapes (Mapping[str, Shape]): Shapes used in the schedules.
+///     table (SequenceTable): Segments and shot ordering.
+/// Returns:
+///     list[dict[str, numpy.ndarray]]: Waveforms of each segment, in the order
+///         of :attr:`SequenceTable.segments`.
+/// Raises:
+///     ValueError: If some input is invalid.
+///     TypeError: If some input has an invalid type.
+///     RuntimeError: If waveform generation fails.
+#[pyfunction]
+#[pyo3(signature = (
+    channels,
+    shapes,
+    table,
+    *,
+    time_tolerance=Time::new(1e-12).unwrap(),
+    amp_tolerance=Amplitude::new(0.1 / 2f64.powi(16)).unwrap(),
+    allow_oversize=false,
+    crosstalk=None,
+    allow_truncate=false,
+    time_grid=None,
+    fractional_delay=false,
+    oversampling=1,
+))]
+#[allow(clippy::too_many_arguments)]
+fn generate_sequence_waveforms(
+    py: Python,
+    channels: HashMap<ChannelId, Channel>,
+    shapes: HashMap<ShapeId, Py<Shape>>,
+    table: &Bound<SequenceTable>,
+    time_tolerance: Time,
+    amp_tolerance: Amplitude,
+    allow_oversize: bool,
+    crosstalk: Option<(PyArrayLike2<f64, AllowTypeChange>, Vec<ChannelId>)>,
+    allow_truncate: bool,
+    time_grid: Option<TimeGrid>,
+    fractional_delay: bool,
+    oversampling: usize,
+) -> PyResult<Vec<HashMap<ChannelId, Py<PyArray2<f64>>>>> {
+    check_crosstalk(&crosstalk)?;
+    table
+        .get()
+        .segments(py)?
+        .into_iter()
+        .map(|segment| {
+            let pulse_lists = build_pulse_lists(
+                py,
+                segment.into_bound(py),
+                &channels,
+                &shapes,
+                time_tolerance,
+                amp_tolerance,
+                allow_oversize,
+                time_grid,
+                None,
+                None,
+            )?;
+            sample_and_post_process(
+                py,
+                &channels,
+                pulse_lists,
+                crosstalk.as_ref(),
+                time_tolerance,
+                amp_tolerance,
+                allow_truncate,
+                fractional_delay,
+                oversampling,
+            )
+        })
+        .collect()
+}
+
+/// Pulse list of a channel.
+///
+/// A pulse list is the intermediate representation between a schedule and the
+/// waveform of a channel. It contains envelopes, frequencies, phases, and start
+/// times of the pulses, but doesn't depend on the sample rate, delay, length,
+/// or filters of the channel. Pulse lists can be stored and sampled later with
+/// :func:`sample_pulse_lists` without rebuilding the schedule.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct PulseList(pulse::PulseList);
+
+#[pymethods]
+impl PulseList {
+    /// Serialize the pulse list to a JSON string.
+    ///
+    /// Returns:
+    ///     str: JSON document with a format version.
+    fn to_json(&self) -> PyResult<String> {
+        codec::to_json(&self.0).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Deserialize a pulse list from a JSON string.
+    ///
+    /// Args:
+    ///     s (str): JSON string created by :meth:`to_json`.
+    /// Returns:
+    ///     PulseList: Deserialized pulse list.
+    /// Raises:
+    ///     ValueError: If the string is not a valid pulse list or the format
+    ///         version is not supported.
+    #[staticmethod]
+    fn from_json(s: &str) -> PyResult<Self> {
+        codec::from_json(s)
+            .map(Self)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Serialize the pulse list to MessagePack bytes.
+    ///
+    /// Args:
+    ///     version (int | None): Format version supported by the reader.
+    ///         Defaults to the latest version.
+    /// Returns:
+    ///     bytes: MessagePack document with a format version.
+    /// Raises:
+    ///     ValueError: If the requested version is not supported or is older
+    ///         than the features used require.
+    #[pyo3(signature = (*, version=None))]
+    fn to_msgpack<'py>(
+        &self,
+        py: Python<'py>,
+        version: Option<u32>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = codec::to_msgpack(&self.0, version)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyBytes::new_bound(py, &bytes))
+    }
+
+    /// Deserialize a pulse list from MessagePack bytes.
+    ///
+    /// Args:
+    ///     data (bytes): MessagePack document created by :meth:`to_msgpack`.
+    /// Returns:
+    ///     PulseList: Deserialized pulse list.
+    /// Raises:
+    ///     ValueError: If the document is invalid or the format version is not
+    ///         supported.
+    #[staticmethod]
+    fn from_msgpack(data: &[u8]) -> PyResult<Self> {
+        codec::from_msgpack(data)
+            .map(Self)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Unique envelopes and frequencies of the pulse list.
+    ///
+    /// Pulses in the same bin only differ in start time and complex
+    /// amplitude, so a backend can store the envelope once and play it with
+    /// per-pulse amplitude and phase.
+    ///
+    /// Returns:
+    ///     list[PulseBin]: Bins with their numbers of pulses, the most frequent
+    ///     first.
+    fn bins(&self) -> Vec<PulseBin> {
+        self.0.bins().into_iter().map(PulseBin).collect()
+    }
+
+    /// Number of envelope samples with and without deduplication.
+    ///
+    /// Args:
+    ///     sample_rate (float): Sample rate of the channel.
+    /// Returns:
+    ///     tuple[int, int]: Samples of one envelope per bin and of one
+    ///     envelope per pulse.
+    fn sample_budget(&self, sample_rate: Frequency) -> (usize, usize) {
+        self.0.bins().iter().fold((0, 0), |(unique, total), b| {
+            let n = b.samples(sample_rate);
+            (unique + n, total + n * b.count)
+        })
+    }
+
+    /// Merge back-to-back rectangular pulses into longer plateaus.
+    ///
+    /// A pulse is merged into the previous one with the same frequencies if it
+    /// starts where the previous one ends and continues its amplitude and
+    /// phase. Long sequences of such pulses, e.g. in spectroscopy, then need
+    /// fewer bins and sample faster. Shaped pulses are not merged.
+    ///
+    /// Args:
+    ///     time_tolerance (float): Tolerance of the pulse boundaries. Defaults
+    ///         to 1e-12.
+    ///     amp_tolerance (float): Tolerance of the complex amplitudes. Defaults
+    ///         to 0.1 / 2^16.
+    /// Returns:
+    ///     tuple[PulseList, int]: Pulse list with merged pulses and the number
+    ///     of merged pulses.
+    #[pyo3(signature = (
+        *,
+        time_tolerance=Time::new(1e-12).unwrap(),
+        amp_tolerance=Amplitude::new(0.1 / 2f64.powi(16)).unwrap(),
+    ))]
+    fn merge_adjacent(&self, time_tolerance: Time, amp_tolerance: Amplitude) -> (Self, usize) {
+        let mut list = self.0.clone();
+        let merged = list.merge_adjacent(amp_tolerance, time_tolerance);
+        (Self(list), merged)
+    }
+
+    fn __reduce__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyBytes>,))> {
+        let from_msgpack = py.get_type_bound::<Self>().getattr("from_msgpack")?;
+        Ok((from_msgpack, (self.to_msgpack(py, None)?,)))
+    }
+}
+
+/// Pulses of a :class:`PulseList` sharing an envelope and frequencies.
+///
+/// Attributes:
+///     shape (str | None): JSON description of the shape, ``"custom"`` for
+///         :class:`Custom` shapes, and ``None`` for rectangular envelopes.
+///     width (float): Width of the shaped part of the envelope.
+///     plateau (float): Plateau length of the envelope.
+///     fall_shape (str | None): Shape of the falling edge, the same as `shape`
+///         for symmetric envelopes.
+///     fall_width (float): Width of the falling edge, the same as `width` for
+///         symmetric envelopes.
+///     global_freq (float): Frequency of the channel carrier.
+///     local_freq (float): Additional frequency of the pulses.
+///     count (int): Number of pulses in the bin.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct PulseBin(pulse::BinSummary);
+
+#[pymethods]
+impl PulseBin {
+    #[getter]
+    fn shape(&self) -> Option<String> {
+        Self::shape_json(self.0.shape.as_ref())
+    }
+
+    #[getter]
+    fn width(&self) -> Time {
+        self.0.width
+    }
+
+    #[getter]
+    fn plateau(&self) -> Time {
+        self.0.plateau
+    }
+
+    #[getter]
+    fn fall_shape(&self) -> Option<String> {
+        match &self.0.fall {
+            Some((shape, _)) => Self::shape_json(shape.as_ref()),
+            None => self.shape(),
+        }
+    }
+
+    #[getter]
+    fn fall_width(&self) -> Time {
+        self.0
+            .fall
+            .as_ref()
+            .map_or(self.0.width, |(_, width)| *width)
+    }
+
+    #[getter]
+    fn global_freq(&self) -> Frequency {
+        self.0.global_freq
+    }
+
+    #[getter]
+    fn local_freq(&self) -> Frequency {
+        self.0.local_freq
+    }
+
+    #[getter]
+    fn count(&self) -> usize {
+        self.0.count
+    }
+
+    /// Number of samples of one envelope.
+    ///
+    /// The sampled envelope can be one sample longer depending on the
+    /// sub-sample offset of the pulse.
+    ///
+    /// Args:
+    ///     sample_rate (float): Sample rate of the channel.
+    /// Returns:
+    ///     int: Number of samples.
+    fn samples(&self, sample_rate: Frequency) -> usize {
+        self.0.samples(sample_rate)
+    }
+}
+
+impl PulseBin {
+    fn shape_json(shape: Option<&shape::Shape>) -> Option<String> {
+        Some(serde_json::to_string(shape?).unwrap_or_else(|_| "custom".to_string()))
+    }
+}
+
+/// Generate pulse lists from a schedule.
+///
+/// This is the first stage of :func:`generate_waveforms`. Only the base
+/// frequencies of the channels are used in this stage.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     shapes (Mapping[str, Shape]): Shapes used in the schedule.
+///     schedule (Element): Root element of the schedule.
+///     time_tolerance (float): Tolerance for time comparison. Default is 1e-12.
+///     amp_tolerance (float): Tolerance for amplitude comparison. Default is
+///         0.1 / 2^16.
+///     allow_oversize (bool): Allow oversize elements. Default is ``False``.
+///         Elements can override it with :attr:`Element.allow_oversize`.
+///     time_grid (TimeGrid | None): Grid that instructions are snapped to.
+///         Default is ``None``.
+///     observer (Callable[[Element, float, float], None] | None): Called
+///         with each non-phantom element, its absolute start time, and its
+///         duration while the schedule is arranged, e.g. for logging or
+///         constraint checks. Margins are excluded and instructions are
+///         reported after snapping to the time grid. An exception raised by
+///         the observer is re-raised after arranging. Default is ``None``.
+///     cache (CompileCache | None): Cache of pulse lists keyed by the
+///         schedule and the other inputs. On a hit the schedule is not
+///         measured or arranged again, the cached warnings are emitted, and
+///         `observer` is not called. Default is ``None``.
+/// Returns:
+///     Dict[str, PulseList]: Pulse lists of the channels.
+/// Raises:
+///     ValueError: If some input is invalid.
+///     TypeError: If some input has an invalid type.
+///     RuntimeError: If pulse list generation fails.
+/// Warns:
+///     UserWarning: For non-fatal issues, e.g. dropped zero amplitude pulses,
+///         coalesced pulses, allowed oversize elements, and instructions
+///         snapped to the time grid.
+#[pyfunction]
+#[pyo3(signature = (
+    channels,
+    shapes,
+    schedule,
+    *,
+    time_tolerance=Time::new(1e-12).unwrap(),
+    amp_tolerance=Amplitude::new(0.1 / 2f64.powi(16)).unwrap(),
+    allow_oversize=false,
+    time_grid=None,
+    observer=None,
+    cache=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn generate_pulse_lists(
+    py: Python,
+    channels: HashMap<ChannelId, Channel>,
+    shapes: HashMap<ShapeId, Py<Shape>>,
+    schedule: Bound<Element>,
+    time_tolerance: Time,
+    amp_tolerance: Amplitude,
+    allow_oversize: bool,
+    time_grid: Option<TimeGrid>,
+    observer: Option<Bound<PyAny>>,
+    cache: Option<Bound<CompileCache>>,
+) -> PyResult<HashMap<ChannelId, PulseList>> {
+    let pulse_lists = build_pulse_lists(
+        py,
+        schedule,
+        &channels,
+        &shapes,
+        time_tolerance,
+        amp_tolerance,
+        allow_oversize,
+        time_grid,
+        observer,
+        cache.as_ref().map(|c| c.get()),
+    )?;
+    Ok(pulse_lists
+        .into_iter()
+        .map(|(n, l)| (n, PulseList(l)))
+        .collect())
+}
+
+/// Fingerprint of the first stage of :func:`generate_waveforms`.
+///
+/// The fingerprint combines the structure and parameters of the schedule, the
+/// channel and shape parameters used by :func:`generate_pulse_lists`, and the
+/// options. Equal inputs have equal fingerprints within a process, so it can
+/// be used as the key of a cache of compiled schedules. Custom shapes are
+/// compared by identity.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     shapes (Mapping[str, Shape]): Shapes used in the schedule.
+///     schedule (Element): Root element of the schedule.
+///     time_tolerance (float): Tolerance for time comparison. Default is 1e-12.
+///     amp_tolerance (float): Tolerance for amplitude comparison. Default is
+///         0.1 / 2^16.
+///     allow_oversize (bool): Allow oversize elements. Default is ``False``.
+///     time_grid (TimeGrid | None): Grid that instructions are snapped to.
+///         Default is ``None``.
+/// Returns:
+///     int: Fingerprint of the inputs.
+#[pyfunction]
+#[pyo3(signature = (
+    channels,
+    shapes,
+    schedule,
+    *,
+    time_tolerance=Time::new(1e-12).unwrap(),
+    amp_tolerance=Amplitude::new(0.1 / 2f64.powi(16)).unwrap(),
+    allow_oversize=false,
+    time_grid=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn schedule_fingerprint(
+    py: Python,
+    channels: HashMap<ChannelId, Channel>,
+    shapes: HashMap<ShapeId, Py<Shape>>,
+    schedule: &Bound<Element>,
+    time_tolerance: Time,
+    amp_tolerance: Amplitude,
+    allow_oversize: bool,
+    time_grid: Option<TimeGrid>,
+) -> PyResult<u64> {
+    let executor = new_executor(
+        py,
+        &channels,
+        &shapes,
+        time_tolerance,
+        amp_tolerance,
+        allow_oversize,
+        time_grid,
+    )?;
+    Ok(executor.fingerprint(&schedule.get().0))
+}
+
+/// Cache of pulse lists keyed by the compiled schedule and its inputs.
+///
+/// Pass the same cache to :func:`generate_waveforms` or
+/// :func:`generate_pulse_lists` to skip measuring and arranging when a schedule
+/// is compiled again with unchanged inputs, e.g. for repeated shots. Entries
+/// are looked up by :func:`schedule_fingerprint` and the schedule, channels,
+/// shapes, and options are compared on a hit, so a fingerprint collision is a
+/// cache miss.
+///
+/// The cache keeps the `maxsize` most recently used entries, the least
+/// recently used entry is evicted when a new one is added to a full cache.
+///
+/// Args:
+///     maxsize (int): Maximum number of cached schedules. Default is 64.
+///
+/// Example:
+///     .. code-block:: python
+///
+///         cache = CompileCache()
+///         for _ in range(shots):
+///             waveforms = generate_waveforms(channels, shapes, schedule, cache=cache)
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug)]
+struct CompileCache {
+    maxsize: usize,
+    /// Least recently used first.
+    entries: Mutex<VecDeque<(u64, CompileKey, Compiled)>>,
+}
+
+/// Pulse lists of a compiled schedule with the warnings of the compilation.
+type Compiled = (HashMap<ChannelId, pulse::PulseList>, Diagnostics);
+
+impl CompileCache {
+    fn lookup(&self, key: &CompileKey, fingerprint: u64) -> Option<Compiled> {
+        let mut entries = self.entries.lock().expect("Cache should not be poisoned");
+        let index = entries
+            .iter()
+            .position(|(f, k, _)| *f == fingerprint && k == key)?;
+        let entry = entries.remove(index).expect("Index should be valid");
+        let compiled = entry.2.clone();
+        entries.push_back(entry);
+        Some(compiled)
+    }
+
+    fn insert(
+        &self,
+        key: CompileKey,
+        fingerprint: u64,
+        pulse_lists: HashMap<ChannelId, pulse::PulseList>,
+        diagnostics: Diagnostics,
+    ) {
+        if self.maxsize == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("Cache should not be poisoned");
+        entries.retain(|(f, k, _)| !(*f == fingerprint && *k == key));
+        while entries.len() >= self.maxsize {
+            entries.pop_front();
+        }
+        entries.push_back((fingerprint, key, (pulse_lists, diagnostics)));
+    }
+}
+
+#[pymethods]
+impl CompileCache {
+    #[new]
+    #[pyo3(signature = (maxsize=64))]
+    fn new(maxsize: usize) -> Self {
+        Self {
+            maxsize,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    #[getter]
+    fn maxsize(&self) -> usize {
+        self.maxsize
+    }
+
+    /// Remove all cached pulse lists.
+    fn clear(&self) {
+        self.entries
+            .lock()
+            .expect("Cache should not be poisoned")
+            .clear();
+    }
+
+    fn __len__(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("Cache should not be poisoned")
+            .len()
+    }
+}
+
+/// Sample pulse lists into waveforms.
+///
+/// This is the second stage of :func:`generate_waveforms`. Every channel in
+/// `channels` should have a corresponding pulse list.
+///
+/// .. caution::
+///
+///     Crosstalk matrix will not be applied to offset of the channels.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     pulse_lists (Mapping[str, PulseList]): Pulse lists of the channels.
+///     time_tolerance (float): Tolerance for time comparison. Default is 1e-12.
+///     amp_tolerance (float): Tolerance of the residual area of net-zero
+///         channels, which is warned about if it exceeds one sample at this
+///         amplitude. Default is 0.1 / 2^16.
+///     crosstalk (tuple[array_like, Sequence[str]] | None): Crosstalk matrix
+///         with corresponding channel ids. Default is ``None``.
+///     allow_truncate (bool): Clip pulses outside the waveform instead of
+///         raising an error. Default is ``False``.
+///     fractional_delay (bool): Realize sub-sample offsets of shaped pulses by
+///         interpolating the envelope instead of aligning the pulse start to
+///         ``align_level`` of the channel. Default is ``False``.
+///     oversampling (int): Sample the envelopes of shaped pulses at this
+///         multiple of the channel rate and decimate them with an
+///         anti-aliasing filter, which reduces the staircase error of pulses
+///         only a few samples wide. The filtered envelope is clipped silently
+///         at the ends of the waveform. Default is 1, i.e. no oversampling.
+/// Returns:
+///     Dict[str, numpy.ndarray]: Waveforms of the channels. Same as
+///         :func:`generate_waveforms`.
+/// Raises:
+///     ValueError: If some input is invalid.
+///     RuntimeError: If sampling fails.
+/// Warns:
+///     UserWarning: If pulses are truncated.
+#[pyfunction]
+#[pyo3(signature = (
+    channels,
+    pulse_lists,
+    *,
+    time_tolerance=Time::new(1e-12).unwrap(),
+    amp_tolerance=Amplitude::new(0.1 / 2f64.powi(16)).unwrap(),
+    crosstalk=None,
+    allow_truncate=false,
+    fractional_delay=false,
+    oversampling=1,
+))]
+#[allow(clippy::too_many_arguments)]
+fn sample_pulse_lists(
+    py: Python,
+    channels: HashMap<ChannelId, Channel>,
+    pulse_lists: HashMap<ChannelId, Py<PulseList>>,
+    time_tolerance: Time,
+    amp_tolerance: Amplitude,
+    crosstalk: Option<(PyArrayLike2<f64, AllowTypeChange>, Vec<ChannelId>)>,
+    allow_truncate: bool,
+    fractional_delay: bool,
+    oversampling: usize,
+) -> PyResult<HashMap<ChannelId, Py<PyArray2<f64>>>> {
+    check_crosstalk(&crosstalk)?;
+    let pulse_lists = channels
+        .keys()
+        .map(|n| {
+            let list = pulse_lists.get(n).ok_or_else(|| {
+                PyValueError::new_err(format!("Pulse list not found for channel '{}'", n))
+            })?;
+            Ok((*n, list.get().0.clone()))
+        })
+        .collect::<PyResult<_>>()?;
+    sample_and_post_process(
+        py,
+        &channels,
+        pulse_lists,
+        crosstalk.as_ref(),
+        time_tolerance,
+        amp_tolerance,
+        allow_truncate,
+        fractional_delay,
+        oversampling,
+    )
+}
+
+/// Precompute the pulse envelopes needed to sample pulse lists.
+///
+/// Sampled envelopes are cached per process. Calling this function before a
+/// timing critical :func:`sample_pulse_lists` with the same arguments moves
+/// the envelope computation out of it, e.g. so the first shot of a sweep is
+/// not slower than the rest. Pulse lists of channels not in `channels` are
+/// ignored.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     pulse_lists (Mapping[str, PulseList]): Pulse lists of the channels.
+///     crosstalk (tuple[array_like, Sequence[str]] | None): Crosstalk matrix
+///         with corresponding channel ids. Default is ``None``.
+///     fractional_delay (bool): Same as :func:`sample_pulse_lists`. Default is
+///         ``False``.
+///     oversampling (int): Same as :func:`sample_pulse_lists`. Default is 1.
+/// Returns:
+///     int: Number of distinct envelopes.
+/// Raises:
+///     ValueError: If some input is invalid.
+#[pyfunction]
+#[pyo3(signature = (
+    channels,
+    pulse_lists,
+    *,
+    crosstalk=None,
+    fractional_delay=false,
+    oversampling=1,
+))]
+fn prewarm_pulse_lists(
+    py: Python,
+    channels: HashMap<ChannelId, Channel>,
+    pulse_lists: HashMap<ChannelId, Py<PulseList>>,
+    crosstalk: Option<(PyArrayLike2<f64, AllowTypeChange>, Vec<ChannelId>)>,
+    fractional_delay: bool,
+    oversampling: usize,
+) -> PyResult<usize> {
+    check_crosstalk(&crosstalk)?;
+    let pulse_lists = pulse_lists
+        .iter()
+        .map(|(n, list)| (*n, list.get().0.clone()))
+        .collect();
+    let crosstalk = crosstalk
+        .as_ref()
+        .map(|(crosstalk, names)| (crosstalk.as_array(), names));
+    // The envelopes don't depend on the waveforms, so empty ones are enough.
+    let mut waveforms: HashMap<_, _> = channels
+        .keys()
+        .map(|n| (*n, Array2::<f64>::zeros((2, 0))))
+        .collect();
+    let count = py.allow_threads(|| {
+        let mut sampler = Sampler::new(pulse_lists);
+        for (n, w) in waveforms.iter_mut() {
+            let c = &channels[n];
+            sampler.add_channel(
+                *n,
+                w.view_mut(),
+                c.sample_rate,
+                c.delay,
+                c.align_level,
+                c.baseline,
+            );
+        }
+        if let Some((crosstalk, names)) = &crosstalk {
+            sampler.set_crosstalk(crosstalk.view(), names.to_vec());
+        }
+        sampler.set_fractional_delay(fractional_delay);
+        sampler.set_oversampling(oversampling);
+        sampler.prewarm()
+    });
+    Ok(count)
+}
+
+/// Time integrals of a sampled waveform.
+///
+/// Attributes:
+///     energy (float): Integral of the squared magnitude of the waveform, e.g.
+///         for heating budgets.
+///     area (complex): Signed integral of the waveform, e.g. for the DC
+///         content of flux pulses. The imaginary part is the integral of the Q
+///         component, and zero for real channels.
+#[pyclass(get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone, Copy)]
+struct WaveformStats {
+    energy: f64,
+    area: Complex64,
+}
+
+/// Compute the energy and area of sampled waveforms.
+///
+/// The integrals are sums over the samples times the sampling interval of the
+/// channel, so they include the baseline, crosstalk, and filters of the
+/// waveforms as passed in.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     waveforms (Mapping[str, numpy.ndarray]): Waveforms returned by
+///         :func:`generate_waveforms`.
+/// Returns:
+///     Dict[str, WaveformStats]: Statistics of each waveform.
+/// Raises:
+///     ValueError: If a waveform has no corresponding channel.
+#[pyfunction]
+fn waveform_stats(
+    channels: HashMap<ChannelId, Channel>,
+    waveforms: HashMap<ChannelId, numpy::PyReadonlyArray2<f64>>,
+) -> PyResult<HashMap<ChannelId, WaveformStats>> {
+    waveforms
+        .iter()
+        .map(|(n, w)| {
+            let c = channels.get(n).ok_or_else(|| {
+                PyValueError::new_err(format!("Channel '{}' not found for waveform", n))
+            })?;
+            let analysis::WaveformStats { energy, area } =
+                analysis::waveform_stats(w.as_array(), c.sample_rate);
+            Ok((*n, WaveformStats { energy, area }))
+        })
+        .collect()
+}
+
+/// Generate matched filter integration weights from expected waveforms.
+///
+/// The weights of each channel are the complex conjugate of the expected
+/// readout waveform, so sampling the readout pulses with
+/// :func:`generate_waveforms` defines both the stimulus and the demodulation.
+/// The weights are scaled to a largest magnitude of one. With `decay`, they
+/// are additionally weighted by :math:`\exp(-t / \tau)`, where :math:`t` is
+/// the time from the start of the waveform, e.g. to favor the part of the
+/// signal before the qubit decays.
+///
+/// .. note::
+///
+///     The demodulation with ``sum(weights * signal)`` treats the rows as
+///     real and imaginary parts, i.e. ``weights[0] + 1j * weights[1]``. The
+///     weights of real channels have a single row.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     waveforms (Mapping[str, numpy.ndarray]): Expected waveforms of the
+///         acquisition channels, e.g. returned by :func:`generate_waveforms`.
+///     decay (float | None): Time constant :math:`\tau` of the exponential
+///         decay weighting. Defaults to ``None``.
+/// Returns:
+///     Dict[str, numpy.ndarray]: Integration weights of each channel with
+///         the shape of the waveform.
+/// Raises:
+///     ValueError: If a waveform has no corresponding channel or `decay` is
+///         not positive.
+#[pyfunction]
+#[pyo3(signature = (channels, waveforms, *, decay=None))]
+fn integration_weights<'py>(
+    py: Python<'py>,
+    channels: HashMap<ChannelId, Channel>,
+    waveforms: HashMap<ChannelId, numpy::PyReadonlyArray2<f64>>,
+    decay: Option<Time>,
+) -> PyResult<HashMap<ChannelId, Bound<'py, PyArray2<f64>>>> {
+    if decay.is_some_and(|d| d <= Time::ZERO) {
+        return Err(PyValueError::new_err("Decay time must be positive"));
+    }
+    waveforms
+        .iter()
+        .map(|(n, w)| {
+            let c = channels.get(n).ok_or_else(|| {
+                PyValueError::new_err(format!("Channel '{}' not found for waveform", n))
+            })?;
+            let weights = analysis::integration_weights(w.as_array(), c.sample_rate, decay);
+            Ok((*n, weights.into_pyarray_bound(py)))
+        })
+        .collect()
+}
+
+/// Resample a waveform to the sample rate of the hardware.
+///
+/// The conversion is a rational polyphase resampler with a Kaiser windowed
+/// sinc low-pass at the lower of the two Nyquist frequencies. The filter is
+/// centered, so the resampling adds no delay: sample ``n`` of the result is at
+/// ``n / target_rate`` like sample ``k`` of the input is at
+/// ``k / sample_rate``. Samples outside of the waveform are taken as zero.
+///
+/// Args:
+///     waveform (numpy.ndarray): Waveform of shape ``(n, length)``.
+///     sample_rate (float): Sample rate of `waveform` in Hz.
+///     target_rate (float): Sample rate of the result in Hz.
+/// Returns:
+///     numpy.ndarray: Waveform of shape ``(n, ceil(length * target_rate /
+///         sample_rate))``.
+/// Raises:
+///     ValueError: If the ratio of the rates is not a fraction with both
+///         terms at most 1000.
+#[pyfunction]
+fn resample<'py>(
+    py: Python<'py>,
+    waveform: numpy::PyReadonlyArray2<f64>,
+    sample_rate: Frequency,
+    target_rate: Frequency,
+) -> PyResult<Bound<'py, PyArray2<f64>>> {
+    let resampled = io::resample::resample(
+        waveform.as_array(),
+        sample_rate.value(),
+        target_rate.value(),
+    )
+    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(resampled.into_pyarray_bound(py))
+}
+
+/// Difference between two sampled waveforms.
+///
+/// Attributes:
+///     max_error (float): Largest magnitude of the sample difference.
+///     rms_error (float): Root mean square of the magnitude of the sample
+///         difference.
+///     shift (int): Delay of `b` relative to `a` in samples.
+///     within_tolerance (bool): Whether `max_error` is at most `amp_tol`.
+#[pyclass(get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone, Copy)]
+struct WaveformComparison {
+    max_error: f64,
+    rms_error: f64,
+    shift: isize,
+    within_tolerance: bool,
+}
+
+/// Compare two sampled waveforms of the same channel.
+///
+/// Useful to check that a refactor, a different `align_level`, or a hardware
+/// round trip reproduces the intended signal. `b` is compared with `a` delayed
+/// by every shift within `time_shift_search` samples in each direction, and
+/// the shift with the smallest squared error is reported. Samples shifted out
+/// of either waveform are compared with zero, so the waveforms can have
+/// different lengths.
+///
+/// Args:
+///     a (numpy.ndarray): Reference waveform with rows ``[I]`` or
+///         ``[I, Q]``.
+///     b (numpy.ndarray): Waveform to check, with the same rows as `a`.
+///     amp_tol (float): Tolerance of the maximum error.
+///     time_shift_search (int): Largest shift to search in samples. Default
+///         is 0.
+/// Returns:
+///     WaveformComparison: Errors at the best-fit shift.
+/// Raises:
+///     ValueError: If the waveforms have different numbers of rows.
+#[pyfunction]
+#[pyo3(signature = (a, b, amp_tol, time_shift_search=0))]
+fn compare_waveforms(
+    py: Python<'_>,
+    a: PyReadonlyArray2<f64>,
+    b: PyReadonlyArray2<f64>,
+    amp_tol: f64,
+    time_shift_search: usize,
+) -> PyResult<WaveformComparison> {
+    let (a, b) = (a.as_array(), b.as_array());
+    if a.nrows() != b.nrows() {
+        return Err(PyValueError::new_err(format!(
+            "Waveforms have {} and {} rows",
+            a.nrows(),
+            b.nrows()
+        )));
+    }
+    let analysis::WaveformComparison {
+        max_error,
+        rms_error,
+        shift,
+    } = py.allow_threads(|| analysis::compare_waveforms(a, b, time_shift_search));
+    Ok(WaveformComparison {
+        max_error,
+        rms_error,
+        shift,
+        within_tolerance: max_error <= amp_tol,
+    })
+}
+
+/// Plan the LO frequency of channels sharing an IQ up-converter.
+///
+/// Each channel plays at ``lo_freq + if_freq``. The LO is chosen such that
+/// the LO leakage and the images at ``lo_freq - if_freq`` of the channels are
+/// as far as possible from every drive frequency, with all intermediate
+/// frequencies within `max_if`. The intermediate frequencies are used as
+/// `base_freq` of the :class:`Channel` objects. Hardware configurations can
+/// plan the LO with an ``upconverters`` section instead, see
+/// :func:`load_hardware_config`.
+///
+/// Args:
+///     drive_freqs (Mapping[str, float]): Drive frequency of each channel.
+///     max_if (float): Largest magnitude of the intermediate frequencies.
+///     guard (float): Minimum distance between a spur and a drive frequency.
+///         Default is 0.
+/// Returns:
+///     tuple[float, dict[str, float]]: LO frequency and intermediate
+///         frequency of each channel.
+/// Raises:
+///     ValueError: If no LO frequency satisfies the constraints.
+#[pyfunction]
+#[pyo3(signature = (drive_freqs, max_if, guard=Frequency::ZERO))]
+fn plan_lo(
+    drive_freqs: HashMap<ChannelId, Frequency>,
+    max_if: Frequency,
+    guard: Frequency,
+) -> PyResult<(Frequency, HashMap<ChannelId, Frequency>)> {
+    let (names, freqs): (Vec<_>, Vec<_>) = drive_freqs.into_iter().unzip();
+    let plan =
+        lo::plan_lo(&freqs, max_if, guard).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok((plan.lo_freq, names.into_iter().zip(plan.if_freqs).collect()))
+}
+
+/// Plan the triggers of AWGs started by a shared trigger.
+///
+/// Each device starts its output `trigger_latency` after the trigger. Devices
+/// with a shorter latency wait for whole cycles of their sequencer clock, so
+/// that the outputs of all devices start together at the largest latency. The
+/// rest of the wait shorter than one cycle should be added to the `delay` of
+/// the :class:`Channel` objects of the device. Hardware configurations can
+/// plan the triggers with a ``devices`` section instead, see
+/// :func:`load_hardware_config`.
+///
+/// Args:
+///     devices (Mapping[str, tuple[float, float]]): Trigger latency and
+///         sequencer clock frequency of each device.
+///     time_tolerance (float): Tolerance of the lead delays to whole clock
+///         cycles. Defaults to 1e-12.
+/// Returns:
+///     dict[str, tuple[int, float]]: Clock cycles to wait after the trigger
+///         and the rest of the lead delay of each device.
+/// Raises:
+///     ValueError: If a latency is negative or a clock frequency is not
+///         positive.
+#[pyfunction]
+#[pyo3(signature = (devices, *, time_tolerance=Time::new(1e-12).unwrap()))]
+fn plan_triggers(
+    devices: HashMap<String, (Time, Frequency)>,
+    time_tolerance: Time,
+) -> PyResult<HashMap<String, (u64, Time)>> {
+    let (names, models): (Vec<_>, Vec<_>) = devices
+        .into_iter()
+        .map(|(n, (latency, clock))| (n, trigger::Device { latency, clock }))
+        .unzip();
+    let triggers = trigger::plan_triggers(&models, time_tolerance)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(names
+        .into_iter()
+        .zip(triggers)
+        .map(|(n, t)| (n, (t.wait_cycles, t.delay)))
+        .collect())
+}
+
+/// Trigger latency, clock frequency, and channels of a device.
+type DeviceArgs = (Time, Frequency, Vec<ChannelId>);
+
+/// Locate scheduled times in the outputs of the channels.
+///
+/// Each time is delayed by the `delay` of the channel and aligned to the grid
+/// given by `align_level` the same way as the start of a pulse, without
+/// `fractional_delay`. The result can be cross-checked against oscilloscope
+/// measurements during bring-up.
+///
+/// For channels of one of `devices`, the triggers are planned as in
+/// :func:`plan_triggers` and the `delay` of the channel should already include
+/// the lead delay of its device, as returned by :func:`load_hardware_config`.
+/// The row then also has the sequencer clock cycle of the first sample from
+/// the start of the output, and the time from the trigger.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     times (Iterable[tuple[str, float]]): Channel ID and scheduled time.
+///     devices (Mapping[str, tuple[float, float, Sequence[str]]] | None):
+///         Trigger latency, sequencer clock frequency, and channel IDs of each
+///         device. Defaults to ``None``.
+///     time_tolerance (float): Tolerance of the lead delays to whole clock
+///         cycles. Defaults to 1e-12.
+/// Returns:
+///     list[dict[str, Any]]: One row per time with keys ``channel``,
+///         ``time``, ``device``, ``index`` (aligned fractional sample index),
+///         ``sample`` (first sample at or after ``index``), ``cycle``, and
+///         ``after_trigger``. The last two are ``None`` without a device.
+/// Raises:
+///     ValueError: If a channel is not found, is in more than one device, or
+///         a device is invalid.
+#[pyfunction]
+#[pyo3(signature = (
+    channels,
+    times,
+    *,
+    devices=None,
+    time_tolerance=Time::new(1e-12).unwrap(),
+))]
+fn locate_times(
+    py: Python<'_>,
+    channels: HashMap<ChannelId, Channel>,
+    times: Vec<(ChannelId, Time)>,
+    devices: Option<HashMap<String, DeviceArgs>>,
+    time_tolerance: Time,
+) -> PyResult<Vec<Bound<'_, PyDict>>> {
+    let (names, devices): (Vec<_>, Vec<_>) = devices.unwrap_or_default().into_iter().unzip();
+    let models: Vec<_> = devices
+        .iter()
+        .map(|&(latency, clock, _)| trigger::Device { latency, clock })
+        .collect();
+    let triggers = trigger::plan_triggers(&models, time_tolerance)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let mut assigned = HashMap::new();
+    for (i, (_, _, members)) in devices.iter().enumerate() {
+        for channel in members {
+            if assigned.insert(*channel, i).is_some() {
+                return Err(PyValueError::new_err(format!(
+                    "Channel '{}' is in more than one device",
+                    channel
+                )));
+            }
+        }
+    }
+    times
+        .into_iter()
+        .map(|(channel, time)| {
+            let c = channels
+                .get(&channel)
+                .ok_or_else(|| PyValueError::new_err(format!("Channel '{}' not found", channel)))?;
+            let clock = trigger::ChannelClock {
+                sample_rate: c.sample_rate,
+                delay: c.delay,
+                align_level: c.align_level,
+            };
+            let device = assigned.get(&channel).copied();
+            let position = trigger::locate(time, clock, device.map(|i| (models[i], triggers[i])))
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            let row = PyDict::new_bound(py);
+            row.set_item("channel", channel)?;
+            row.set_item("time", time)?;
+            row.set_item("device", device.map(|i| &names[i]))?;
+            row.set_item("index", position.index.value())?;
+            row.set_item("sample", position.index.first_sample())?;
+            row.set_item("cycle", position.cycle)?;
+            row.set_item("after_trigger", position.after_trigger)?;
+            Ok(row)
+        })
+        .collect()
+}
+
+/// Build a frequency multiplexed readout pulse on a channel.
+///
+/// Each tone becomes a :class:`Play` with the shared `width`, `plateau`, and
+/// `drag_coef`, and the tones are placed at the start of an
+/// :class:`Absolute` layout. Since the additional frequency of a pulse is
+/// referenced to the pulse start, all tones start with their given phase at
+/// the same time and stay coherent with each other wherever the readout is
+/// arranged.
+///
+/// .. code-block:: python
+///
+///     readout = multiplex_readout(
+///         "m0",
+///         [(-120e6, 0.2, 0.0, "hann"), (35e6, 0.15, 0.25, "hann")],
+///         20e-9,
+///         plateau=2e-6,
+///     )
+///
+/// Args:
+///     channel_id (str): Target channel ID.
+///     tones (Iterable[tuple[float, float, float, str | None]]): Frequency
+///         relative to the channel, amplitude, phase in **cycles**, and shape
+///         ID of each tone. A shape ID of ``None`` is a rectangular pulse.
+///     width (float): Width of the pulses.
+///     plateau (float): Plateau length of the pulses. Defaults to 0.
+///     drag_coef (float): Drag coefficient of the pulses. Defaults to 0.
+/// Returns:
+///     Absolute: Layout with one pulse per tone.
+/// Raises:
+///     ValueError: If `tones` is empty or a parameter is invalid.
+#[pyfunction]
+#[pyo3(signature = (channel_id, tones, width, *, plateau=Time::ZERO, drag_coef=0.0))]
+fn multiplex_readout(
+    py: Python<'_>,
+    channel_id: ChannelId,
+    tones: Vec<(Frequency, Amplitude, Phase, Option<ShapeId>)>,
+    width: Time,
+    plateau: Time,
+    drag_coef: f64,
+) -> PyResult<Py<Element>> {
+    let tones = tones
+        .into_iter()
+        .map(
+            |(frequency, amplitude, phase, shape_id)| schedule::ReadoutTone {
+                frequency,
+                amplitude,
+                phase,
+                shape_id,
+            },
+        )
+        .collect();
+    let variant = schedule::multiplex_readout(channel_id, tones, width, plateau, drag_coef)?;
+    let common = ElementCommonBuilder::new().build()?;
+    Element::to_py_object(py, Arc::new(schedule::Element::new(common, variant)))
+}
+
+/// Timing of one channel of a schedule.
+///
+/// Attributes:
+///     channel (str): Channel name.
+///     pulses (int): Number of played pulses.
+///     busy (float): Time with at least one pulse playing on the channel.
+///     idle (float): Duration of the schedule minus `busy`.
+///     longest_gap (float): Longest interval without pulses, including the
+///         intervals before the first and after the last pulse.
+///     barrier_wait (float): Idle time added by :class:`Barrier` elements in
+///         :class:`Stack` waiting for other channels. Channels without barrier
+///         wait are on the critical path of the schedule.
+#[pyclass(get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone, Copy)]
+struct ChannelTiming {
+    channel: ChannelId,
+    pulses: usize,
+    busy: Time,
+    idle: Time,
+    longest_gap: Time,
+    barrier_wait: Time,
+}
+
+/// Timing report returned by :meth:`Element.timing_report`.
+///
+/// ``str(report)`` renders a table with one row per channel.
+///
+/// Attributes:
+///     duration (float): Duration of the schedule.
+///     channels (list[ChannelTiming]): Timing of each channel in order of
+///         appearance.
+#[pyclass(frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct TimingReport(schedule::TimingReport);
+
+#[pymethods]
+impl TimingReport {
+    #[getter]
+    fn duration(&self) -> Time {
+        self.0.duration
+    }
+
+    #[getter]
+    fn channels(&self) -> Vec<ChannelTiming> {
+        self.0
+            .channels
+            .iter()
+            .map(|c| ChannelTiming {
+                channel: c.channel,
+                pulses: c.pulses,
+                busy: c.busy,
+                idle: c.idle,
+                longest_gap: c.longest_gap,
+                barrier_wait: c.barrier_wait,
+            })
+            .collect()
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// Flatten a schedule into a list of timed instructions.
+///
+/// Each instruction is a leaf element placed on the absolute time axis.
+/// Phantom elements and barriers are skipped, and the plateau of flexible
+/// pulses is resolved from the arranged duration. The rows can be loaded into a
+/// dataframe directly, e.g. ``pandas.DataFrame(flatten_schedule(schedule))``.
+///
+/// If `keep_loops` is ``True``, :class:`Repeat` elements with more than one
+/// iteration or with a count variable are kept as rows of kind ``"Loop"`` with
+/// keys ``time``, ``count``, ``count_var``, ``period``, and ``body``.
+/// ``period`` is the time between the
+/// starts of consecutive iterations, and ``body`` is the list of rows of one
+/// iteration with times relative to its start.
+///
+/// Args:
+///     schedule (Element): Root element of the schedule.
+///     keep_loops (bool): Whether to keep loops instead of unrolling them.
+///         Default is ``False``.
+/// Returns:
+///     list[dict[str, Any]]: Instructions in tree traversal order. Each row has
+///         keys ``kind``, ``channel_id``, ``time``, and ``duration``, plus the
+///         parameters of the instruction kind.
+/// Raises:
+///     ValueError: If `keep_loops` is ``True`` and the body of a loop shifts
+///         the frequency of a channel, which changes later iterations.
+#[pyfunction]
+#[pyo3(signature = (schedule, *, keep_loops=false))]
+fn flatten_schedule<'py>(
+    py: Python<'py>,
+    schedule: &Bound<'py, Element>,
+    keep_loops: bool,
+) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    if keep_loops {
+        schedule::flatten_loops(&schedule.get().0)?
+            .into_iter()
+            .map(|s| segment_row(py, s))
+            .collect()
+    } else {
+        schedule::flatten(&schedule.get().0)
+            .into_iter()
+            .map(|i| instruction_row(py, i))
+            .collect()
+    }
+}
+
+fn segment_row(py: Python<'_>, segment: schedule::Segment) -> PyResult<Bound<'_, PyDict>> {
+    match segment {
+        schedule::Segment::Instruction(i) => instruction_row(py, i),
+        schedule::Segment::Loop {
+            time,
+            count,
+            count_var,
+            period,
+            body,
+        } => {
+            let row = PyDict::new_bound(py);
+            row.set_item("kind", "Loop")?;
+            row.set_item("time", time)?;
+            row.set_item("count", count)?;
+            row.set_item("count_var", count_var)?;
+            row.set_item("period", period)?;
+            let body = body
+                .into_iter()
+                .map(|s| segment_row(py, s))
+                .collect::<PyResult<Vec<_>>>()?;
+            row.set_item("body", body)?;
+            Ok(row)
+        }
+    }
+}
+
+fn instruction_row(
+    py: Python<'_>,
+    schedule::Instruction {
+        time,
+        duration,
+        kind,
+    }: schedule::Instruction,
+) -> PyResult<Bound<'_, PyDict>> {
+    let row = PyDict::new_bound(py);
+    row.set_item("kind", kind.name())?;
+    row.set_item("time", time)?;
+    row.set_item("duration", duration)?;
+    match kind {
+        schedule::InstructionKind::Play {
+            channel_id,
+            shape_id,
+            amplitude,
+            width,
+            plateau,
+            drag_coef,
+            frequency,
+            phase,
+            phase_reference,
+            fall,
+        } => {
+            row.set_item("channel_id", channel_id)?;
+            row.set_item("shape_id", shape_id.as_ref())?;
+            row.set_item("amplitude", amplitude)?;
+            row.set_item("width", width)?;
+            row.set_item("plateau", plateau)?;
+            row.set_item("drag_coef", drag_coef)?;
+            row.set_item("frequency", frequency)?;
+            row.set_item("phase", phase)?;
+            row.set_item("phase_reference", phase_reference.name())?;
+            if let Some(fall) = fall {
+                row.set_item(
+                    "fall_shape_id",
+                    fall.shape_id.as_ref().or(shape_id.as_ref()),
+                )?;
+                row.set_item("fall_width", fall.width)?;
+            }
+        }
+        schedule::InstructionKind::PlaySamples {
+            channel_id,
+            samples,
+            sample_rate,
+            amplitude,
+            frequency,
+            phase,
+            interpolate,
+        } => {
+            row.set_item("channel_id", channel_id)?;
+            row.set_item("samples", PyArray1::from_slice_bound(py, &samples))?;
+            row.set_item("sample_rate", sample_rate)?;
+            row.set_item("amplitude", amplitude)?;
+            row.set_item("frequency", frequency)?;
+            row.set_item("phase", phase)?;
+            row.set_item("interpolate", interpolate)?;
+        }
+        schedule::InstructionKind::ShiftPhase { channel_id, phase }
+        | schedule::InstructionKind::SetPhase { channel_id, phase } => {
+            row.set_item("channel_id", channel_id)?;
+            row.set_item("phase", phase)?;
+        }
+        schedule::InstructionKind::ShiftFreq {
+            channel_id,
+            frequency,
+        }
+        | schedule::InstructionKind::SetFreq {
+            channel_id,
+            frequency,
+        } => {
+            row.set_item("channel_id", channel_id)?;
+            row.set_item("frequency", frequency)?;
+        }
+        schedule::InstructionKind::SwapPhase {
+            channel_id1,
+            channel_id2,
+        } => {
+            row.set_item("channel_id", channel_id1)?;
+            row.set_item("channel_id2", channel_id2)?;
+        }
+    }
+    Ok(row)
+}
+
+/// Write the flattened schedule to a Parquet file.
+///
+/// The table has one row per instruction returned by :func:`flatten_schedule`.
+/// Columns that don't apply to the instruction kind are null.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``arrow`` feature.
+///
+/// Args:
+///     path (str | os.PathLike): Path of the file. Existing file will be
+///         overwritten.
+///     schedule (Element): Root element of the schedule.
+/// Raises:
+///     RuntimeError: If writing the file fails.
+#[cfg(feature = "arrow")]
+#[pyfunction]
+fn write_parquet(path: std::path::PathBuf, schedule: &Bound<Element>) -> PyResult<()> {
+    let instructions = schedule::flatten(&schedule.get().0);
+    io::arrow::write_parquet(path, &instructions)?;
+    Ok(())
+}
+
+/// Import a Qiskit pulse schedule.
+///
+/// The input is the pulse Qobj JSON form of a single experiment with keys
+/// ``dt``, ``pulse_library``, and ``instructions``. Gaussian family pulses are
+/// converted to :class:`Interp` shapes and sampled pulses are split into real
+/// and imaginary parts. ``setf`` instructions set the frequency relative to the
+/// channel base frequency, so channels should have zero base frequency to
+/// reproduce the absolute frequencies of Qiskit.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``qiskit`` feature.
+///
+/// Args:
+///     s (str): JSON string of the experiment.
+/// Returns:
+///     tuple[Element, dict[str, Shape]]: Root element of the schedule and the
+///         shapes used by the schedule.
+/// Raises:
+///     ValueError: If the schedule is invalid or contains unsupported pulses.
+#[cfg(feature = "qiskit")]
+#[pyfunction]
+#[allow(clippy::type_complexity)]
+fn import_qiskit_qobj(
+    py: Python<'_>,
+    s: &str,
+) -> PyResult<(Py<Element>, HashMap<ShapeId, Py<Interp>>)> {
+    let imported = io::qiskit::import_qobj(s).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let shapes = imported
+        .shapes
+        .into_iter()
+        .map(|(id, p)| {
+            let shape = Py::new(py, Interp::new(p.knots, p.controls, p.degree)?)?;
+            Ok((id, shape))
+        })
+        .collect::<PyResult<_>>()?;
+    let schedule = Element::to_py_object(py, Arc::new(imported.schedule))?;
+    Ok((schedule, shapes))
+}
+
+/// Generate Zurich Instruments sequencer code for a channel.
+///
+/// Each distinct pulse on the channel becomes a baseband waveform and each
+/// play is issued with ``executeTableEntry``. Phase shifts preceding a play are
+/// applied as phase increments of the command table entry. The carrier should
+/// be provided by the instrument oscillator, so the base frequency of the
+/// channel is ignored. Wave and file names are derived from `channel_id`, with
+/// characters that are not valid in sequencer identifiers escaped, e.g.
+/// ``'q0.xy'`` becomes ``'q0_2exy'``.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``seqc`` feature.
+///
+/// Args:
+///     schedule (Element): Root element of the schedule.
+///     channel_id (str): Channel to generate code for.
+///     channel (Channel): Information of the channel.
+///     shapes (Mapping[str, Shape]): Shapes used in the schedule.
+///     device (str): Target instrument, ``'hdawg'`` or ``'shfsg'``. Default is
+///         ``'hdawg'``.
+///     directory (str | os.PathLike | None): If given, the sequencer code,
+///         command table, and CSV waveform files are written to the directory.
+///     time_tolerance (float): Tolerance for time comparison. Default is 1e-12.
+///     keep_loops (bool): Whether to generate ``repeat`` blocks for
+///         :class:`Repeat` elements instead of unrolling them. See
+///         :func:`flatten_schedule`. Default is ``False``.
+/// Returns:
+///     tuple[str, str, dict[str, numpy.ndarray]]: Sequencer code, command
+///         table JSON, and waveforms of shape ``(2, length)``.
+/// Raises:
+///     ValueError: If the schedule can't be represented by the sequencer.
+#[cfg(feature = "seqc")]
+#[pyfunction]
+#[pyo3(signature = (
+    schedule,
+    channel_id,
+    channel,
+    shapes,
+    *,
+    device="hdawg",
+    directory=None,
+    time_tolerance=Time::new(1e-12).unwrap(),
+    keep_loops=false,
+))]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn generate_seqc(
+    py: Python<'_>,
+    schedule: &Bound<Element>,
+    channel_id: ChannelId,
+    channel: Channel,
+    shapes: HashMap<ShapeId, Py<Shape>>,
+    device: &str,
+    directory: Option<std::path::PathBuf>,
+    time_tolerance: Time,
+    keep_loops: bool,
+) -> PyResult<(String, String, HashMap<String, Py<PyArray2<f64>>>)> {
+    let device = match device {
+        "hdawg" => io::seqc::Device::Hdawg,
+        "shfsg" => io::seqc::Device::Shfsg,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "Unknown device '{}'",
+                device
+            )))
+        }
+    };
+    let shapes = shapes
+        .iter()
+        .map(|(n, s)| Ok((n.clone(), Shape::get_rust_shape(s.bind(py))?)))
+        .collect::<PyResult<_>>()?;
+    let segments = if keep_loops {
+        schedule::flatten_loops(&schedule.get().0)?
+    } else {
+        schedule::flatten(&schedule.get().0)
+            .into_iter()
+            .map(schedule::Segment::Instruction)
+            .collect()
+    };
+    let generator = io::seqc::SeqcGenerator::new(
+        channel_id.as_ref(),
+        device,
+        channel.sample_rate,
+        &shapes,
+        time_tolerance,
+    );
+    // Custom shapes acquire the GIL from the sampling threads.
+    let program = py
+        .allow_threads(|| generator.generate(&segments, &channel_id))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    if let Some(directory) = directory {
+        program.write_to_dir(directory, channel_id.as_ref())?;
+    }
+    let waves = program
+        .waves
+        .into_iter()
+        .map(|(n, w)| (n, w.into_pyarray_bound(py).unbind()))
+        .collect();
+    Ok((program.source, program.command_table, waves))
+}
+
+/// Write waveforms to an HDF5 file.
+///
+/// Each waveform is stored as a dataset under the ``/waveforms`` group with
+/// attributes ``sample_rate``, ``delay``, and ``align_level``. If `schedule`
+/// is given, its JSON representation is stored as the ``schedule`` attribute of
+/// the root group.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``hdf5`` feature.
+///
+/// Args:
+///     path (str | os.PathLike): Path of the file. Existing file will be
+///         overwritten.
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     waveforms (Mapping[str, numpy.ndarray]): Waveforms returned by
+///         :func:`generate_waveforms`.
+///     schedule (Element | None): Schedule used to generate the waveforms.
+///         Default is ``None``.
+/// Raises:
+///     ValueError: If a waveform has no corresponding channel.
+///     RuntimeError: If writing the file fails.
+#[cfg(feature = "hdf5")]
+#[pyfunction]
+#[pyo3(signature = (path, channels, waveforms, *, schedule=None))]
+fn write_hdf5(
+    path: std::path::PathBuf,
+    channels: HashMap<ChannelId, Channel>,
+    waveforms: HashMap<ChannelId, numpy::PyReadonlyArray2<f64>>,
+    schedule: Option<Bound<Element>>,
+) -> PyResult<()> {
+    let waveforms = waveforms
+        .iter()
+        .map(|(n, w)| {
+            let c = channels.get(n).ok_or_else(|| {
+                PyValueError::new_err(format!("Channel '{}' not found for waveform", n))
+            })?;
+            Ok(io::ChannelWaveform {
+                name: n.as_ref(),
+                info: c.into(),
+                data: w.as_array(),
+            })
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    let provenance = schedule.map(|s| s.get().0.to_json(None)).transpose()?;
+    io::hdf5::write_waveforms(
+        path,
+        &waveforms,
+        provenance.as_deref(),
+        io::Precision::Double,
+    )?;
+    Ok(())
+}
+
+/// Write a sampled waveform to a file readable by an AWG.
+///
+/// Supported formats are:
+///
+/// - ``"keysight_csv"``: Keysight CSV file. Complex waveforms are written as
+///   ``Y1`` and ``Y2`` columns.
+/// - ``"keysight_bin"``: Keysight M8190A 14-bit binary file with up to 2
+///   markers.
+/// - ``"tektronix_wfmx"``: Tektronix AWG70000 WFMX file with up to 4 markers.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``awg`` feature.
+///
+/// Args:
+///     path (str | os.PathLike): Path of the file. Existing file will be
+///         overwritten.
+///     waveform (numpy.ndarray): Waveform of shape ``(n, length)`` with values
+///         in the range [-1, 1]. Binary formats require ``n == 1``.
+///     format (str): File format.
+///     sample_rate (float): Sample rate in Hz.
+///     markers (numpy.ndarray | None): Boolean markers of shape
+///         ``(n_markers, length)`` with at most 8 markers. Default is ``None``.
+/// Raises:
+///     ValueError: If the format is unknown.
+///     RuntimeError: If the waveform is not supported by the format or writing
+///         the file fails.
+#[cfg(feature = "awg")]
+#[pyfunction]
+#[pyo3(signature = (path, waveform, format, *, sample_rate, markers=None))]
+fn write_awg_waveform(
+    path: std::path::PathBuf,
+    waveform: numpy::PyReadonlyArray2<f64>,
+    format: &str,
+    sample_rate: f64,
+    markers: Option<numpy::PyReadonlyArray2<bool>>,
+) -> PyResult<()> {
+    let format = match format {
+        "keysight_csv" => io::awg::AwgFormat::KeysightCsv,
+        "keysight_bin" => io::awg::AwgFormat::KeysightBin,
+        "tektronix_wfmx" => io::awg::AwgFormat::TektronixWfmx,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "Unknown AWG file format '{}'",
+                format
+            )))
+        }
+    };
+    io::awg::write_waveform(
+        path,
+        format,
+        waveform.as_array(),
+        markers.as_ref().map(|m| m.as_array()),
+        sample_rate,
+    )?;
+    Ok(())
+}
+
+/// Compute the power spectrum of a sampled waveform.
+///
+/// The waveform is windowed and zero-padded before the transform. The power
+/// is in dB relative to a full scale tone, so a complex tone of amplitude 1
+/// peaks at 0 dB regardless of the window and the padding.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``spectrum`` feature.
+///
+/// Args:
+///     waveform (array_like[N, M]): Waveform with rows I or I and Q, e.g. a
+///         value returned by :func:`generate_waveforms`.
+///     sample_rate (float): Sample rate of the waveform.
+///     window (str): Window function, one of 'rectangular', 'hann',
+///         'blackman'. Defaults to 'hann'.
+///     nfft (int | None): Length of the transform, a power of two not less
+///         than the waveform length. Defaults to the next power of two.
+/// Returns:
+///     tuple[numpy.ndarray, numpy.ndarray]: Frequencies in ascending order
+///         from :math:`-f_s/2`, and the power at each frequency in dB.
+/// Raises:
+///     ValueError: If the window or `nfft` is invalid.
+#[cfg(feature = "spectrum")]
+#[pyfunction]
+#[pyo3(signature = (waveform, sample_rate, *, window="hann", nfft=None))]
+#[allow(clippy::type_complexity)]
+fn compute_spectrum<'py>(
+    py: Python<'py>,
+    waveform: PyArrayLike2<f64, AllowTypeChange>,
+    sample_rate: Frequency,
+    window: &str,
+    nfft: Option<usize>,
+) -> PyResult<(Bound<'py, PyArray1<f64>>, Bound<'py, PyArray1<f64>>)> {
+    let spectrum = sample_spectrum(&waveform, sample_rate, window, nfft)?;
+    Ok((
+        PyArray1::from_vec_bound(py, spectrum.frequencies),
+        PyArray1::from_vec_bound(py, spectrum.power_db),
+    ))
+}
+
+/// Flag power above a threshold at given frequencies of a sampled waveform.
+///
+/// Useful to check the leakage of a drive to neighboring transitions. The
+/// power is computed by :func:`compute_spectrum`, and the peak within
+/// `bandwidth / 2` of each frequency, or at the nearest bin, is compared with
+/// the threshold.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``spectrum`` feature.
+///
+/// Args:
+///     waveform (array_like[N, M]): Waveform with rows I or I and Q.
+///     sample_rate (float): Sample rate of the waveform.
+///     frequencies (Sequence[float]): Frequencies to check. For complex
+///         waveforms, positive and negative frequencies are different.
+///     threshold (float): Threshold in dB relative to a full scale tone.
+///     bandwidth (float): Width of the band around each frequency. Defaults to
+///         0.
+///     window (str): Window function. Defaults to 'hann'.
+///     nfft (int | None): Length of the transform. Defaults to the next power
+///         of two.
+/// Returns:
+///     list[tuple[float, float]]: Frequencies above the threshold with their
+///         peak power in dB.
+/// Raises:
+///     ValueError: If the window or `nfft` is invalid.
+#[cfg(feature = "spectrum")]
+#[pyfunction]
+#[pyo3(signature = (
+    waveform,
+    sample_rate,
+    frequencies,
+    *,
+    threshold,
+    bandwidth=0.0,
+    window="hann",
+    nfft=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn find_leakage(
+    waveform: PyArrayLike2<f64, AllowTypeChange>,
+    sample_rate: Frequency,
+    frequencies: Vec<f64>,
+    threshold: f64,
+    bandwidth: f64,
+    window: &str,
+    nfft: Option<usize>,
+) -> PyResult<Vec<(f64, f64)>> {
+    let spectrum = sample_spectrum(&waveform, sample_rate, window, nfft)?;
+    Ok(spectrum
+        .leakage(&frequencies, bandwidth, threshold)
+        .into_iter()
+        .map(|l| (l.frequency, l.power_db))
+        .collect())
+}
+
+/// Warn about power of waveforms at sensitive frequencies.
+///
+/// The spectrum of each waveform is shifted by the LO of the channel and
+/// compared with the sensitive frequencies, e.g. neighbor qubits or two-level
+/// systems, as in :func:`find_leakage`. A :class:`UserWarning` is emitted for
+/// each frequency above the threshold. Frequencies outside the Nyquist band of
+/// a channel around its LO are skipped.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``spectrum`` feature.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     waveforms (Mapping[str, numpy.ndarray]): Waveforms returned by
+///         :func:`generate_waveforms`.
+///     frequencies (Mapping[str, float]): Name and frequency of the sensitive
+///         transitions.
+///     threshold (float): Threshold in dB relative to a full scale tone.
+///     lo_freqs (Mapping[str, float] | None): LO frequency of the up-converted
+///         channels, e.g. from :func:`plan_lo`. Defaults to no up-conversion.
+///     bandwidth (float): Width of the band around each frequency. Defaults to
+///         0.
+///     window (str): Window function. Defaults to 'hann'.
+/// Raises:
+///     ValueError: If a waveform has no corresponding channel or the window is
+///         invalid.
+#[cfg(feature = "spectrum")]
+#[pyfunction]
+#[pyo3(signature = (
+    channels,
+    waveforms,
+    frequencies,
+    *,
+    threshold,
+    lo_freqs=None,
+    bandwidth=Frequency::ZERO,
+    window="hann",
+))]
+#[allow(clippy::too_many_arguments)]
+fn check_leakage(
+    py: Python<'_>,
+    channels: HashMap<ChannelId, Channel>,
+    waveforms: HashMap<ChannelId, PyReadonlyArray2<f64>>,
+    frequencies: Vec<(String, Frequency)>,
+    threshold: f64,
+    lo_freqs: Option<HashMap<ChannelId, Frequency>>,
+    bandwidth: Frequency,
+    window: &str,
+) -> PyResult<()> {
+    let window = analysis::spectrum::Window::from_name(window)
+        .ok_or_else(|| PyValueError::new_err(format!("Invalid window '{}'", window)))?;
+    let check = analysis::spectrum::LeakageCheck {
+        frequencies,
+        threshold_db: threshold,
+        bandwidth,
+        window,
+    };
+    let lo_freqs = lo_freqs.unwrap_or_default();
+    let mut diagnostics = Diagnostics::new();
+    for (n, w) in &waveforms {
+        let c = channels.get(n).ok_or_else(|| {
+            PyValueError::new_err(format!("Channel '{}' not found for waveform", n))
+        })?;
+        let lo_freq = lo_freqs.get(n).copied().unwrap_or_default();
+        check
+            .check(*n, w.as_array(), c.sample_rate, lo_freq, &mut diagnostics)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    }
+    emit_warnings(py, &diagnostics)
+}
+
+#[cfg(feature = "spectrum")]
+fn sample_spectrum(
+    waveform: &PyArrayLike2<f64, AllowTypeChange>,
+    sample_rate: Frequency,
+    window: &str,
+    nfft: Option<usize>,
+) -> PyResult<analysis::spectrum::Spectrum> {
+    let window = analysis::spectrum::Window::from_name(window)
+        .ok_or_else(|| PyValueError::new_err(format!("Invalid window '{}'", window)))?;
+    analysis::spectrum::spectrum(waveform.as_array(), sample_rate, window, nfft)
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// A transmon of the device model of :func:`simulate`.
+///
+/// Frequencies are relative to the common rotating frame of the simulation,
+/// e.g. the LO shared by the drive channels, so a drive waveform modulated at
+/// `freq` is resonant with the transmon.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``simulate``
+///     feature.
+///
+/// Args:
+///     freq (float): Frequency of the 0-1 transition.
+///     anharmonicity (float): Anharmonicity, negative for transmons.
+///     levels (int): Number of levels. Defaults to 3.
+///     drive (str | None): Channel of the drive waveform. Defaults to
+///         ``None``.
+///     rabi_freq (float): Rabi frequency of the drive at unit amplitude.
+///         Defaults to 0.
+///     flux (str | None): Channel of the flux waveform. Defaults to ``None``.
+///     flux_sensitivity (float): Frequency shift at unit flux amplitude.
+///         Defaults to 0.
+///     t1 (float | None): Energy relaxation time. Defaults to ``None``.
+///     t2 (float | None): Dephasing time, at most ``2 * t1``. Defaults to
+///         ``None``.
+#[cfg(feature = "simulate")]
+#[pyclass(get_all, frozen, module = "bosing")]
+#[derive(Debug, Clone)]
+struct Transmon {
+    freq: Frequency,
+    anharmonicity: Frequency,
+    levels: usize,
+    drive: Option<ChannelId>,
+    rabi_freq: Frequency,
+    flux: Option<ChannelId>,
+    flux_sensitivity: Frequency,
+    t1: Option<Time>,
+    t2: Option<Time>,
+}
+
+#[cfg(feature = "simulate")]
+#[pymethods]
+impl Transmon {
+    #[new]
+    #[pyo3(signature = (
+        freq,
+        anharmonicity,
+        *,
+        levels=3,
+        drive=None,
+        rabi_freq=Frequency::ZERO,
+        flux=None,
+        flux_sensitivity=Frequency::ZERO,
+        t1=None,
+        t2=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        freq: Frequency,
+        anharmonicity: Frequency,
+        levels: usize,
+        drive: Option<ChannelId>,
+        rabi_freq: Frequency,
+        flux: Option<ChannelId>,
+        flux_sensitivity: Frequency,
+        t1: Option<Time>,
+        t2: Option<Time>,
+    ) -> Self {
+        Self {
+            freq,
+            anharmonicity,
+            levels,
+            drive,
+            rabi_freq,
+            flux,
+            flux_sensitivity,
+            t1,
+            t2,
+        }
+    }
+
+    fn __getnewargs_ex__<'py>(&self, py: Python<'py>) -> NewArgsEx<'py, (Frequency, Frequency)> {
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("levels", self.levels)?;
+        kwargs.set_item("drive", self.drive)?;
+        kwargs.set_item("rabi_freq", self.rabi_freq)?;
+        kwargs.set_item("flux", self.flux)?;
+        kwargs.set_item("flux_sensitivity", self.flux_sensitivity)?;
+        kwargs.set_item("t1", self.t1)?;
+        kwargs.set_item("t2", self.t2)?;
+        Ok(((self.freq, self.anharmonicity), kwargs))
+    }
+}
+
+/// Simulate transmons driven by sampled waveforms.
+///
+/// The transmons are Duffing oscillators with exchange couplings
+/// :math:`g (a_p^\dagger a_q + a_p a_q^\dagger)`. The drive waveform
+/// :math:`y(t)` of a transmon adds
+/// :math:`\Omega / 2 \, (y^*(t) a^\dagger + y(t) a)` and the flux waveform
+/// :math:`z(t)` shifts its frequency by :math:`s z(t)`, where :math:`\Omega`
+/// is `rabi_freq` and :math:`s` is `flux_sensitivity`. The waveforms are held
+/// constant over each sample. With finite T1 or T2 of any transmon, the
+/// Lindblad master equation is integrated instead of the Schrödinger equation.
+///
+/// The basis states are ordered with the first transmon as the most
+/// significant digit.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``simulate``
+///     feature.
+///
+/// Args:
+///     transmons (Sequence[Transmon]): Transmons of the device.
+///     channels (Mapping[str, Channel]): Information of the channels. All
+///         drive and flux channels should have the same sample rate.
+///     waveforms (Mapping[str, numpy.ndarray]): Waveforms returned by
+///         :func:`generate_waveforms`. Missing waveforms are zero.
+///     couplings (Iterable[tuple[int, int, float]]): Indices of the coupled
+///         transmons and the coupling strength. Defaults to no coupling.
+///     initial (numpy.ndarray | None): Initial ket or density matrix. Defaults
+///         to the ground state.
+///     substeps (int): Runge-Kutta steps per sample. Defaults to 4.
+/// Returns:
+///     numpy.ndarray: Final ket, or density matrix if the transmons are
+///     dissipative or `initial` is a density matrix.
+/// Raises:
+///     ValueError: If the device model, the channels, or the initial state is
+///         invalid.
+#[cfg(feature = "simulate")]
+#[pyfunction]
+#[pyo3(name = "simulate", signature = (
+    transmons,
+    channels,
+    waveforms,
+    *,
+    couplings=Vec::new(),
+    initial=None,
+    substeps=4,
+))]
+fn simulate_transmons<'py>(
+    py: Python<'py>,
+    transmons: Vec<Transmon>,
+    channels: HashMap<ChannelId, Channel>,
+    waveforms: HashMap<ChannelId, PyReadonlyArray2<f64>>,
+    couplings: Vec<(usize, usize, Frequency)>,
+    initial: Option<&Bound<'py, PyAny>>,
+    substeps: usize,
+) -> PyResult<Bound<'py, PyAny>> {
+    let mut sample_rate = None;
+    let mut waveform = |channel: ChannelId| -> PyResult<_> {
+        let c = channels
+            .get(&channel)
+            .ok_or_else(|| PyValueError::new_err(format!("Channel '{}' not found", channel)))?;
+        if *sample_rate.get_or_insert(c.sample_rate) != c.sample_rate {
+            return Err(PyValueError::new_err(
+                "Drive and flux channels should have the same sample rate",
+            ));
+        }
+        Ok(waveforms.get(&channel).map(|w| w.as_array()))
+    };
+    let mut drives = Vec::new();
+    let mut fluxes = Vec::new();
+    for (qubit, t) in transmons.iter().enumerate() {
+        if let Some(w) = t.drive.map(&mut waveform).transpose()?.flatten() {
+            drives.push(simulate::Drive {
+                qubit,
+                waveform: w,
+                rabi_freq: t.rabi_freq,
+            });
+        }
+        if let Some(w) = t.flux.map(&mut waveform).transpose()?.flatten() {
+            fluxes.push(simulate::Flux {
+                qubit,
+                waveform: w.index_axis_move(ndarray::Axis(0), 0),
+                sensitivity: t.flux_sensitivity,
+            });
+        }
+    }
+    let models: Vec<_> = transmons
+        .iter()
+        .map(|t| simulate::Transmon {
+            freq: t.freq,
+            anharmonicity: t.anharmonicity,
+            levels: t.levels,
+            t1: t.t1,
+            t2: t.t2,
+        })
+        .collect();
+    let couplings: Vec<_> = couplings
+        .into_iter()
+        .map(|(p, q, strength)| simulate::Coupling {
+            qubits: (p, q),
+            strength,
+        })
+        .collect();
+    let initial = match initial {
+        Some(obj) => match obj.extract::<PyReadonlyArray2<Complex64>>() {
+            Ok(rho) => simulate::State::Density(rho.as_array().to_owned()),
+            Err(_) => {
+                let psi: PyReadonlyArray1<Complex64> = obj.extract()?;
+                simulate::State::Ket(psi.as_array().to_owned())
+            }
+        },
+        None => {
+            let dim = models.iter().map(|t| t.levels).product::<usize>();
+            let mut psi = ndarray::Array1::zeros(dim);
+            psi[0] = Complex64::ONE;
+            simulate::State::Ket(psi)
+        }
+    };
+    let state = simulate::simulate(
+        &models,
+        &couplings,
+        &drives,
+        &fluxes,
+        sample_rate.unwrap_or(Frequency::ZERO),
+        initial,
+        substeps,
+    )
+    .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(match state {
+        simulate::State::Ket(psi) => psi.into_pyarray_bound(py).into_any(),
+        simulate::State::Density(rho) => rho.into_pyarray_bound(py).into_any(),
+    })
+}
+
+/// Render waveforms to an SVG document.
+///
+/// Each channel is drawn in its own panel, ordered by channel name. Complex
+/// waveforms are drawn as I, Q, and amplitude traces.
+///
+/// Args:
+///     channels (Mapping[str, Channel]): Information of the channels.
+///     waveforms (Mapping[str, numpy.ndarray]): Waveforms returned by
+///         :func:`generate_waveforms`.
+/// Returns:
+///     str: SVG document.
+/// Raises:
+///     ValueError: If a waveform has no corresponding channel.
+#[pyfunction]
+fn render_waveforms_svg(
+    channels: HashMap<ChannelId, Channel>,
+    waveforms: HashMap<ChannelId, numpy::PyReadonlyArray2<f64>>,
+) -> PyResult<String> {
+    let mut waveforms = waveforms
+        .iter()
+        .map(|(n, w)| {
+            let c = channels.get(n).ok_or_else(|| {
+                PyValueError::new_err(format!("Channel '{}' not found for waveform", n))
+            })?;
+            Ok(io::ChannelWaveform {
+                name: n.as_ref(),
+                info: c.into(),
+                data: w.as_array(),
+            })
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    waveforms.sort_by_key(|w| w.name);
+    Ok(io::svg::render_waveforms(&waveforms))
+}
+
+/// Render a schedule as a Gantt chart in an SVG document.
+///
+/// Each channel is drawn as a row. Plays are drawn as boxes and phase or
+/// frequency operations as vertical markers.
+///
+/// Args:
+///     schedule (Element): Root element of the schedule.
+/// Returns:
+///     str: SVG document.
+#[pyfunction]
+fn render_schedule_svg(schedule: &Bound<Element>) -> String {
+    let instructions = schedule::flatten(&schedule.get().0);
+    io::svg::render_gantt(&instructions)
+}
+
+/// Load the hardware configuration from a TOML or YAML file.
+///
+/// See the documentation of the ``config`` module of the rust crate for the
+/// format. The returned dictionary can be passed to
+/// :func:`generate_waveforms` directly.
+///
+/// Channels in an ``upconverters`` section get the planned intermediate
+/// frequency as `base_freq`. The LO frequencies are not part of the returned
+/// arguments, call :func:`plan_lo` with the same drive frequencies,
+/// `max_if`, and `guard` to get them.
+///
+/// Channels in a ``devices`` section have the part of the trigger lead delay
+/// shorter than one clock cycle added to `delay`. Call :func:`plan_triggers`
+/// to get the clock cycles each device waits after the trigger.
+///
+/// The ``leakage`` section is only used by the command line tool, call
+/// :func:`check_leakage` with the same frequencies instead.
+///
+/// Likewise, the ``groups`` section is only expanded by the command line tool.
+/// Call :meth:`Element.expand_groups` with the same groups before generating
+/// the waveforms.
+///
+/// .. note::
+///
+///     Only available when the package is built with the ``config`` feature.
+///
+/// Args:
+///     path (str | os.PathLike): Path of the configuration file. The format is
+///         determined by the extension, ``.toml``, ``.yaml``, or ``.yml``.
+/// Returns:
+///     dict[str, Any]: Keyword arguments of :func:`generate_waveforms` except
+///         ``schedule``.
+/// Raises:
+///     RuntimeError: If the file can't be read or the configuration is
+///         invalid.
+/// Example:
+///     .. code-block:: python
+///
+///         from bosing import generate_waveforms, load_hardware_config
+///         config = load_hardware_config("hardware.toml")
+///         result = generate_waveforms(schedule=schedule, **config)
+#[cfg(feature = "config")]
+#[pyfunction]
+fn load_hardware_config(py: Python, path: std::path::PathBuf) -> PyResult<Bound<PyDict>> {
+    fn readonly<T>(array: Bound<T>) -> PyResult<Py<T>> {
+        let kwargs = PyDict::new_bound(array.py());
+        kwargs.set_item("write", false)?;
+        array
+            .as_any()
+            .getattr("setflags")?
+            .call((), Some(&kwargs))?;
+        Ok(array.unbind())
+    }
+    let config = config::load(path)?;
+    let channels = PyDict::new_bound(py);
+    for (n, c) in &config.channels {
+        let channel = Channel {
+            base_freq: c.base_freq,
+            sample_rate: c.sample_rate,
+            length: c.length,
+            delay: c.delay,
+            align_level: c.align_level,
+            baseline: c.baseline,
+            ringdown: c.ringdown.map(Ringdown),
+            net_zero: c.net_zero,
+            idle_tone: c.idle_tone.map(IdleTone),
+            time_grid: c.time_grid.map(TimeGrid),
+            predistortion: c.predistortion.clone().map(Predistortion),
+            iq_matrix: c
+                .iq_matrix
+                .map(|m| readonly(PyArray2::from_owned_array_bound(py, ndarray::arr2(&m))))
+                .transpose()?,
+            offset: c
+                .offset
+                .as_ref()
+                .map(|x| readonly(PyArray1::from_slice_bound(py, x)))
+                .transpose()?,
+            iir: c
+                .iir
+                .as_ref()
+                .map(|x| readonly(PyArray2::from_owned_array_bound(py, ndarray::arr2(x))))
+                .transpose()?,
+            fir: c
+                .fir
+                .as_ref()
+                .map(|x| readonly(PyArray1::from_slice_bound(py, x)))
+                .transpose()?,
+            filter_offset: c.filter_offset,
+            is_real: c.is_real,
+        };
+        channels.set_item(n, channel.into_py(py))?;
+    }
+    let shapes = PyDict::new_bound(py);
+    for (n, s) in &config.shapes {
+        shapes.set_item(n, shape_from_config(py, s)?)?;
+    }
+    let crosstalk = config
+        .crosstalk
+        .map(|(m, n)| {
+            let names: Vec<_> = n.iter().map(|x| x.to_string()).collect();
+            PyResult::Ok((readonly(PyArray2::from_owned_array_bound(py, m))?, names))
+        })
+        .transpose()?;
+    let result = PyDict::new_bound(py);
+    result.set_item("channels", channels)?;
+    result.set_item("shapes", shapes)?;
+    result.set_item("time_tolerance", config.time_tolerance.value())?;
+    result.set_item("amp_tolerance", config.amp_tolerance.value())?;
+    result.set_item("allow_oversize", config.allow_oversize)?;
+    result.set_item("allow_truncate", config.allow_truncate)?;
+    result.set_item("fractional_delay", config.fractional_delay)?;
+    result.set_item("oversampling", config.oversampling)?;
+    result.set_item("crosstalk", crosstalk)?;
+    result.set_item(
+        "time_grid",
+        config.time_grid.map(|g| TimeGrid(g).into_py(py)),
+    )?;
+    Ok(result)
+}
+
+#[cfg(feature = "config")]
+/// Python shape object of a shape in a hardware configuration.
+fn shape_from_config(py: Python<'_>, config: &config::ShapeConfig) -> PyResult<Py<PyAny>> {
+    Ok(match config {
+        config::ShapeConfig::Hann => Py::new(py, Hann::new())?.into_any(),
+        config::ShapeConfig::Interp {
+            knots,
+            controls,
+            degree,
+        } => Py::new(py, Interp::new(knots.clone(), controls.clone(), *degree)?)?.into_any(),
+        config::ShapeConfig::Expr { expr } => Py::new(py, Expr::new(expr.clone())?)?.into_any(),
+        config::ShapeConfig::Sinc { num_lobes } => Py::new(py, Sinc::new(*num_lobes)?)?.into_any(),
+        config::ShapeConfig::ErfSquare { rise_time } => {
+            Py::new(py, ErfSquare::new(*rise_time)?)?.into_any()
+        }
+        config::ShapeConfig::Exponential { rise, decay } => {
+            let decay = decay.clone().unwrap_or_else(|| rise.clone());
+            shape::Shape::new_exponential(rise, &decay)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            let rise = rise.clone();
+            Py::new(py, (Exponential { rise, decay }, Shape))?.into_any()
+        }
+        config::ShapeConfig::Normalized {
+            shape,
+            normalization,
+        } => {
+            let shape = shape_from_config(py, shape)?;
+            let shape = shape.bind(py).downcast::<Shape>()?;
+            Py::new(py, Normalized::new(shape, normalization.name())?)?.into_any()
+        }
+    })
+}
+
+fn check_crosstalk(
+    crosstalk: &Option<(PyArrayLike2<f64, AllowTypeChange>, Vec<ChannelId>)>,
+) -> PyResult<()> {
+    if let Some((crosstalk, names)) = crosstalk {
+        let nl = names.len();
+        if crosstalk.shape() != [nl, nl] {
+            return Err(PyValueError::new_err(
+                "The size of the crosstalk matrix must be the same as the number of names.",
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_pulse_lists(
+    py: Python,
+    schedule: Bound<Element>,
+    channels: &HashMap<ChannelId, Channel>,
+    shapes: &HashMap<ShapeId, Py<Shape>>,
+    time_tolerance: Time,
+    amp_tolerance: Amplitude,
+    allow_oversize: bool,
+    time_grid: Option<TimeGrid>,
+    observer: Option<Bound<PyAny>>,
+    cache: Option<&CompileCache>,
+) -> PyResult<HashMap<ChannelId, pulse::PulseList>> {
+    let mut executor = new_executor(
+        py,
+        channels,
+        shapes,
+        time_tolerance,
+        amp_tolerance,
+        allow_oversize,
+        time_grid,
+    )?;
+    let schedule = &schedule.get().0;
+    let key = cache.map(|_| {
+        let key = executor.compile_key(schedule);
+        let fingerprint = key.fingerprint();
+        (key, fingerprint)
+    });
+    if let (Some(cache), Some((key, fingerprint))) = (cache, &key) {
+        if let Some((pulse_lists, diagnostics)) = cache.lookup(key, *fingerprint) {
+            emit_warnings(py, &diagnostics)?;
+            return Ok(pulse_lists);
+        }
+    }
+    if let Some(observer) = observer {
+        // The observer needs the GIL, so the executor can't release it.
+        let mut error = None;
+        executor
+            .execute_observed(schedule, |item, time_range| {
+                if error.is_some() {
+                    return;
+                }
+                if let Err(e) = Element::to_py_object(py, item.clone()).and_then(|element| {
+                    observer.call1((element, time_range.start, time_range.span))
+                }) {
+                    error = Some(e);
+                }
+            })
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if let Some(e) = error {
+            return Err(e);
+        }
+    } else {
+        py.allow_threads(|| {
+            executor
+                .execute(schedule)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+        })?;
+    }
+    let (pulse_lists, diagnostics) = executor.into_result();
+    emit_warnings(py, &diagnostics)?;
+    if let (Some(cache), Some((key, fingerprint))) = (cache, key) {
+        cache.insert(key, fingerprint, pulse_lists.clone(), diagnostics);
+    }
+    Ok(pulse_lists)
+}
+
+fn new_executor(
+    py: Python,
+    channels: &HashMap<ChannelId, Channel>,
+    shapes: &HashMap<ShapeId, Py<Shape>>,
+    time_tolerance: Time,
+    amp_tolerance: Amplitude,
+    allow_oversize: bool,
+    time_grid: Option<TimeGrid>,
+) -> PyResult<Executor> {
+    let mut executor = Executor::new(amp_tolerance, time_tolerance, allow_oversize);
+    executor.set_time_grid(time_grid.map(|g| g.0));
+    for (n, c) in channels {
+        executor.add_channel(*n, c.base_freq, c.ringdown.map(|r| r.0), c.net_zero);
+        executor.set_channel_time_grid(*n, c.time_grid.map(|g| g.0));
+    }
+    for (n, s) in shapes {
+        let s = s.bind(py);
+        executor.add_shape(n.clone(), Shape::get_rust_shape(s)?);
+    }
+    Ok(executor)
+}
+
+/// Sample and post-process the waveforms without holding the GIL.
+///
+/// The waveforms are sampled into arrays owned by Rust, which are handed over
+/// to numpy without copying.
+#[allow(clippy::too_many_arguments)]
+fn sample_and_post_process(
+    py: Python,
+    channels: &HashMap<ChannelId, Channel>,
+    pulse_lists: HashMap<ChannelId, pulse::PulseList>,
+    crosstalk: Option<&(PyArrayLike2<f64, AllowTypeChange>, Vec<ChannelId>)>,
+    time_tolerance: Time,
+    amp_tolerance: Amplitude,
+    allow_truncate: bool,
+    fractional_delay: bool,
+    oversampling: usize,
+) -> PyResult<HashMap<ChannelId, Py<PyArray2<f64>>>> {
+    let mut waveforms: HashMap<_, _> = channels
+        .iter()
+        .map(|(n, c)| {
+            let n_w = if c.is_real { 1 } else { 2 };
+            (*n, Array2::zeros((n_w, c.length)))
+        })
+        .collect();
+    let filters: HashMap<_, _> = channels
+        .iter()
+        .map(|(n, c)| (*n, Filters::new(py, c)))
+        .collect();
+    let filters: HashMap<_, _> = filters.iter().map(|(n, f)| (*n, f.views())).collect();
+    let crosstalk = crosstalk.map(|(crosstalk, names)| (crosstalk.as_array(), names));
+    let diagnostics = py.allow_threads(|| {
+        let mut sampler = Sampler::new(pulse_lists);
+        for (n, w) in waveforms.iter_mut() {
+            let c = &channels[n];
+            sampler.add_channel(
+                *n,
+                w.view_mut(),
+                c.sample_rate,
+                c.delay,
+                c.align_level,
+                c.baseline,
+            );
+        }
+        if let Some((crosstalk, names)) = &crosstalk {
+            sampler.set_crosstalk(crosstalk.view(), names.to_vec());
+        }
+        sampler.set_net_zero(
+            channels.iter().filter(|(_, c)| c.net_zero).map(|(n, _)| *n),
+            amp_tolerance,
+        );
+        sampler.set_idle_tones(
+            channels
+                .iter()
+                .filter_map(|(n, c)| Some((*n, c.idle_tone?.0))),
+        );
+        sampler.set_allow_truncate(allow_truncate);
+        sampler.set_fractional_delay(fractional_delay);
+        sampler.set_oversampling(oversampling);
+        let diagnostics = sampler.sample(time_tolerance)?;
+        #[cfg(feature = "parallel")]
+        let iter = waveforms.par_iter_mut();
+        #[cfg(not(feature = "parallel"))]
+        let iter = waveforms.iter_mut();
+        iter.for_each(|(n, w)| {
+            let (iq_matrix, offset, iir, fir) = filters[n];
+            let filter_offset = channels[n].filter_offset;
+            let predistortion = channels[n].predistortion.as_ref().map(|p| &p.0);
+            post_process_inplace(
+                &mut w.view_mut(),
+                predistortion,
+                iq_matrix,
+                offset,
+                iir,
+                fir,
+                filter_offset,
+            );
+        });
+        PyResult::Ok(diagnostics)
+    })?;
+    emit_warnings(py, &diagnostics)?;
+    Ok(waveforms
+        .into_iter()
+        .map(|(n, w)| (n, w.into_pyarray_bound(py).unbind()))
+        .collect())
+}
+
+/// Read-only borrows of the post-processing arrays of a channel.
+struct Filters<'py> {
+    iq_matrix: Option<PyReadonlyArray2<'py, f64>>,
+    offset: Option<PyReadonlyArray1<'py, f64>>,
+    iir: Option<PyReadonlyArray2<'py, f64>>,
+    fir: Option<PyReadonlyArray1<'py, f64>>,
+}
+
+type FilterViews<'a> = (
+    Option<ArrayView2<'a, f64>>,
+    Option<ArrayView1<'a, f64>>,
+    Option<ArrayView2<'a, f64>>,
+    Option<ArrayView1<'a, f64>>,
+);
+
+impl<'py> Filters<'py> {
+    fn new(py: Python<'py>, c: &Channel) -> Self {
+        Self {
+            iq_matrix: c.iq_matrix.as_ref().map(|x| x.bind(py).readonly()),
+            offset: c.offset.as_ref().map(|x| x.bind(py).readonly()),
+            iir: c.iir.as_ref().map(|x| x.bind(py).readonly()),
+            fir: c.fir.as_ref().map(|x| x.bind(py).readonly()),
+        }
+    }
+
+    fn views(&self) -> FilterViews<'_> {
+        (
+            self.iq_matrix.as_ref().map(|x| x.as_array()),
+            self.offset.as_ref().map(|x| x.as_array()),
+            self.iir.as_ref().map(|x| x.as_array()),
+            self.fir.as_ref().map(|x| x.as_array()),
+        )
+    }
+}
+
+fn emit_warnings(py: Python, diagnostics: &Diagnostics) -> PyResult<()> {
+    let category = py.get_type_bound::<PyUserWarning>();
+    for w in diagnostics.warnings() {
+        PyErr::warn_bound(py, &category, &w.to_string(), 1)?;
+    }
+    Ok(())
+}
+
+/// Generates microwave pulses for superconducting quantum computing
+/// experiments.
+///
+/// .. caution::
+///
+///     The unit of phase is number of cycles, not radians. For example, a phase
+///     of :math:`0.5` means a phase shift of :math:`\pi` radians.
+#[pymodule]
+fn bosing(_py: Python, m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<Absolute>()?;
+    m.add_class::<AbsoluteEntry>()?;
+    m.add_class::<Alignment>()?;
+    m.add_class::<Barrier>()?;
+    m.add_class::<Calibration>()?;
+    m.add_class::<Calibrations>()?;
+    m.add_class::<ElementCache>()?;
+    m.add_class::<CompileCache>()?;
+    m.add_class::<Channel>()?;
+    m.add_class::<ChannelTiming>()?;
+    m.add_class::<Direction>()?;
+    m.add_class::<Custom>()?;
+    m.add_class::<Element>()?;
+    m.add_class::<Experiment>()?;
+    m.add_class::<SequenceTable>()?;
+    m.add_class::<Expr>()?;
+    m.add_class::<Exponential>()?;
+    m.add_class::<Sinc>()?;
+    m.add_class::<ErfSquare>()?;
+    m.add_class::<Normalized>()?;
+    m.add_class::<Grid>()?;
+    m.add_class::<GridEntry>()?;
+    m.add_class::<GridLength>()?;
+    m.add_class::<GridLengthUnit>()?;
+    m.add_class::<Hann>()?;
+    m.add_class::<Interp>()?;
+    m.add_class::<Play>()?;
+    m.add_class::<PlaySamples>()?;
+    m.add_class::<PulseLibrary>()?;
+    m.add_class::<PulseList>()?;
+    m.add_class::<PulseBin>()?;
+    m.add_class::<Repeat>()?;
+    m.add_class::<ScaleAmplitude>()?;
+    m.add_class::<SetFreq>()?;
+    m.add_class::<SetPhase>()?;
+    m.add_class::<ShiftFreq>()?;
+    m.add_class::<ShiftPhase>()?;
+    m.add_class::<Shape>()?;
+    m.add_class::<Stack>()?;
+    m.add_class::<SwapPhase>()?;
+    m.add_class::<TimeGrid>()?;
+    m.add_class::<IdleTone>()?;
+    m.add_class::<Predistortion>()?;
+    m.add_class::<TimingReport>()?;
+    m.add_class::<Ringdown>()?;
+    m.add_class::<WaveformComparison>()?;
+    m.add_class::<WaveformStats>()?;
+    m.add_function(wrap_pyfunction!(flatten_schedule, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_pulse_lists, m)?)?;
+    m.add_function(wrap_pyfunction!(schedule_fingerprint, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_waveforms, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_sequence_waveforms, m)?)?;
+    m.add_function(wrap_pyfunction!(sample_pulse_lists, m)?)?;
+    m.add_function(wrap_pyfunction!(prewarm_pulse_lists, m)?)?;
+    m.add_function(wrap_pyfunction!(waveform_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_waveforms, m)?)?;
+    m.add_function(wrap_pyfunction!(integration_weights, m)?)?;
+    m.add_function(wrap_pyfunction!(resample, m)?)?;
+    m.add_function(wrap_pyfunction!(plan_lo, m)?)?;
+    m.add_function(wrap_pyfunction!(plan_triggers, m)?)?;
+    m.add_function(wrap_pyfunction!(locate_times, m)?)?;
+    m.add_function(wrap_pyfunction!(multiplex_readout, m)?)?;
+    #[cfg(feature = "hdf5")]
+    m.add_function(wrap_pyfunction!(write_hdf5, m)?)?;
+    #[cfg(feature = "seqc")]
+    m.add_function(wrap_pyfunction!(generate_seqc, m)?)?;
+    #[cfg(feature = "qiskit")]
+    m.add_function(wrap_pyfunction!(import_qiskit_qobj, m)?)?;
+    #[cfg(feature = "arrow")]
+    m.add_function(wrap_pyfunction!(write_parquet, m)?)?;
+    #[cfg(feature = "awg")]
+    m.add_function(wrap_pyfunction!(write_awg_waveform, m)?)?;
+    #[cfg(feature = "spectrum")]
+    m.add_function(wrap_pyfunction!(compute_spectrum, m)?)?;
+    #[cfg(feature = "spectrum")]
+    m.add_function(wrap_pyfunction!(find_leakage, m)?)?;
+    #[cfg(feature = "spectrum")]
+    m.add_function(wrap_pyfunction!(check_leakage, m)?)?;
+    #[cfg(feature = "simulate")]
+    m.add_class::<Transmon>()?;
+    #[cfg(feature = "simulate")]
+    m.add_function(wrap_pyfunction!(simulate_transmons, m)?)?;
+    m.add_function(wrap_pyfunction!(render_waveforms_svg, m)?)?;
+    m.add_function(wrap_pyfunction!(render_schedule_svg, m)?)?;
+    #[cfg(feature = "config")]
+    m.add_function(wrap_pyfunction!(load_hardware_config, m)?)?;
+    Ok(())
+}
@@ -4,10 +4,11 @@ use std::{
     sync::Arc,
 };
 
-use num::NumCast;
-use numpy::Complex64;
+use num::complex::Complex64;
 use ordered_float::NotNan;
+#[cfg(feature = "python")]
 use pyo3::{exceptions::PyValueError, prelude::*, types::PyFloat, IntoPy};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -18,42 +19,123 @@ pub(crate) enum Error {
     InfiniteValue,
 }
 
+#[cfg(feature = "exact-time")]
+mod exact;
+mod intern;
+
+/// Inner representation of [`Time`], integer attoseconds with the
+/// `exact-time` feature.
+#[cfg(not(feature = "exact-time"))]
+type TimeRepr = NotNan<f64>;
+#[cfg(feature = "exact-time")]
+type TimeRepr = exact::Attos;
+
+#[cfg(not(feature = "exact-time"))]
+const TIME_ZERO: TimeRepr = unsafe { NotNan::new_unchecked(0.0) };
+#[cfg(feature = "exact-time")]
+const TIME_ZERO: TimeRepr = exact::Attos::ZERO;
+
+#[cfg(not(feature = "exact-time"))]
+const TIME_INFINITY: TimeRepr = unsafe { NotNan::new_unchecked(f64::INFINITY) };
+#[cfg(feature = "exact-time")]
+const TIME_INFINITY: TimeRepr = exact::Attos::INFINITY;
+
+const F64_ZERO: NotNan<f64> = unsafe { NotNan::new_unchecked(0.0) };
+
 macro_rules! def_quant {
-    ($t:ident) => {
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
-        pub(crate) struct $t(NotNan<f64>);
+    ($t:ident, $r:ty) => {
+        #[derive(
+            Debug,
+            Clone,
+            Copy,
+            PartialEq,
+            Eq,
+            Hash,
+            PartialOrd,
+            Ord,
+            Default,
+            Serialize,
+            Deserialize,
+        )]
+        pub(crate) struct $t($r);
     };
 }
 
-def_quant!(Time);
-def_quant!(Frequency);
-def_quant!(Phase);
-def_quant!(Amplitude);
-
+def_quant!(Time, TimeRepr);
+def_quant!(Frequency, NotNan<f64>);
+def_quant!(Phase, NotNan<f64>);
+def_quant!(Amplitude, NotNan<f64>);
+
+/// Fractional sample index of a time point, aligned to a grid of
+/// `2^align_level` samples.
+///
+/// The sampler aligns the start of every pulse with [`Rounding::Ceil`]. The
+/// first sample of the pulse is [`first_sample`](Self::first_sample), and
+/// [`residual`](Self::residual) is the fractional delay of the envelope
+/// relative to that sample.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub(crate) struct AlignedIndex(NotNan<f64>);
+pub struct AlignedIndex(NotNan<f64>);
+
+/// Rounding policy of time grids and [`AlignedIndex`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Round to the nearest grid point, half away from zero.
+    #[default]
+    Nearest,
+    /// Round towards negative infinity.
+    Floor,
+    /// Round towards positive infinity.
+    Ceil,
+}
 
 macro_rules! def_id {
     ($t:ident) => {
-        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
         pub(crate) struct $t(Arc<str>);
     };
 }
 
-def_id!(ChannelId);
+/// Channel name interned in a process wide registry, so hashing and
+/// comparing are integer operations.
+///
+/// Ids are ordered by registration instead of by name.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct ChannelId(u32);
+
 def_id!(ShapeId);
 
 type Result<T> = std::result::Result<T, Error>;
 
 impl Time {
-    pub(crate) const INFINITY: Self = Self(unsafe { NotNan::new_unchecked(f64::INFINITY) });
+    pub(crate) const INFINITY: Self = Self(TIME_INFINITY);
 }
 
 impl Phase {
-    fn radians(&self) -> f64 {
+    pub(crate) fn radians(&self) -> f64 {
         self.value() * std::f64::consts::TAU
     }
 
+    /// The same phase in the range `[-0.5, 0.5)` turns.
+    ///
+    /// Accumulated phases grow with the frequency and time, wrapping them
+    /// keeps the precision of the phaser. Infinite phases are kept as is.
+    pub(crate) fn wrapped(self) -> Self {
+        let value = self.value();
+        if !value.is_finite() {
+            return self;
+        }
+        Self::new((value + 0.5).rem_euclid(1.0) - 0.5).expect("Should be a valid phase value")
+    }
+
+    pub(crate) fn wrapping_add(self, rhs: Self) -> Self {
+        (self + rhs).wrapped()
+    }
+
+    pub(crate) fn wrapping_sub(self, rhs: Self) -> Self {
+        (self - rhs).wrapped()
+    }
+
     pub(crate) fn phaser(&self) -> Complex64 {
         Complex64::from_polar(1.0, self.radians())
     }
@@ -65,17 +147,114 @@ impl Frequency {
     }
 }
 
+// Constructors for the frontends that don't use the canonical units of the
+// python API.
+impl Phase {
+    #[cfg(feature = "ffi")]
+    pub(crate) fn from_turns(value: f64) -> Result<Self> {
+        Self::new(value)
+    }
+
+    #[cfg(feature = "qiskit")]
+    pub(crate) fn from_radians(value: f64) -> Result<Self> {
+        Self::new(value / std::f64::consts::TAU)
+    }
+}
+
+impl std::fmt::Display for Time {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_si(self.value(), "s", f)
+    }
+}
+
+impl std::fmt::Display for Frequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt_si(self.value(), "Hz", f)
+    }
+}
+
+/// Format with an SI prefix such that the mantissa is in `[1, 1000)`. Without
+/// an explicit precision, the mantissa is rounded to 6 decimal places.
+fn fmt_si(value: f64, unit: &str, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    const PREFIXES: [&str; 9] = ["p", "n", "µ", "m", "", "k", "M", "G", "T"];
+    if value == 0.0 || !value.is_finite() {
+        return write!(f, "{} {}", value, unit);
+    }
+    let exp = ((value.abs().log10() / 3.0).floor() as i32).clamp(-4, 4);
+    let mantissa = value / 1000f64.powi(exp);
+    let prefix = PREFIXES[(exp + 4) as usize];
+    match f.precision() {
+        Some(p) => write!(f, "{:.*} {}{}", p, mantissa, prefix, unit),
+        None => {
+            let s = format!("{:.6}", mantissa);
+            let s = s.trim_end_matches('0').trim_end_matches('.');
+            write!(f, "{} {}{}", s, prefix, unit)
+        }
+    }
+}
+
+impl Rounding {
+    #[cfg(feature = "python")]
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Rounding::Nearest => "nearest",
+            Rounding::Floor => "floor",
+            Rounding::Ceil => "ceil",
+        }
+    }
+
+    #[cfg(feature = "python")]
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        [Rounding::Nearest, Rounding::Floor, Rounding::Ceil]
+            .into_iter()
+            .find(|r| r.name() == name)
+    }
+
+    pub(crate) fn apply(self, x: f64) -> f64 {
+        match self {
+            Rounding::Nearest => x.round(),
+            Rounding::Floor => x.floor(),
+            Rounding::Ceil => x.ceil(),
+        }
+    }
+}
+
 impl AlignedIndex {
+    /// Index used by the sampler, rounded up to the grid.
     pub(crate) fn new(time: Time, sample_rate: Frequency, align_level: i32) -> Result<Self> {
+        Self::with_rounding(time, sample_rate, align_level, Rounding::Ceil)
+    }
+
+    pub(crate) fn with_rounding(
+        time: Time,
+        sample_rate: Frequency,
+        align_level: i32,
+        rounding: Rounding,
+    ) -> Result<Self> {
         fn scaleb(x: f64, s: i32) -> f64 {
             x * (s as f64).exp2()
         }
         let scaled_sr = scaleb(sample_rate.value(), -align_level);
-        let i = (time.value() * scaled_sr).ceil();
+        let i = rounding.apply(time.value() * scaled_sr);
         let aligned_index = scaleb(i, align_level);
         Self::from_value(aligned_index)
     }
 
+    /// Align `time` in seconds to the grid of `2^align_level` samples at
+    /// `sample_rate` in Hz.
+    ///
+    /// Returns `None` if the index is NaN or infinite.
+    pub fn from_time(
+        time: f64,
+        sample_rate: f64,
+        align_level: i32,
+        rounding: Rounding,
+    ) -> Option<Self> {
+        let time = Time::new(time).ok()?;
+        let sample_rate = Frequency::new(sample_rate).ok()?;
+        Self::with_rounding(time, sample_rate, align_level, rounding).ok()
+    }
+
     fn from_value(value: f64) -> Result<Self> {
         if value.is_infinite() {
             return Err(Error::InfiniteValue);
@@ -83,16 +262,24 @@ impl AlignedIndex {
         Ok(Self(NotNan::new(value)?))
     }
 
-    pub(crate) fn value(&self) -> f64 {
+    /// The fractional index in samples.
+    pub fn value(&self) -> f64 {
         self.0.into_inner()
     }
 
-    pub(crate) fn ceil_to_usize(&self) -> Option<usize> {
-        <usize as NumCast>::from(self.0.ceil())
+    /// Index of the first sample at or after the aligned time.
+    pub fn first_sample(&self) -> i64 {
+        self.0.ceil() as i64
+    }
+
+    /// Distance from the aligned time to [`first_sample`](Self::first_sample)
+    /// in samples, in the range `[0, 1)`.
+    pub fn residual(&self) -> f64 {
+        self.0.ceil() - self.value()
     }
 
     pub(crate) fn index_offset(&self) -> Result<Self> {
-        Self::from_value(self.0.ceil() - self.0.into_inner())
+        Self::from_value(self.residual())
     }
 }
 
@@ -112,6 +299,7 @@ impl Mul<Frequency> for Time {
     }
 }
 
+#[cfg(feature = "python")]
 impl From<Error> for PyErr {
     fn from(err: Error) -> Self {
         PyValueError::new_err(err.to_string())
@@ -147,19 +335,20 @@ macro_rules! forward_ref_binop {
 }
 
 macro_rules! impl_quant {
-    ($t:ty) => {
+    ($t:ty, $r:ty, $zero:expr) => {
         impl $t {
             pub(crate) fn new(value: f64) -> Result<Self> {
-                Ok(Self(NotNan::new(value)?))
+                Ok(Self(<$r>::new(value)?))
             }
 
             pub(crate) fn value(&self) -> f64 {
                 self.0.into_inner()
             }
 
-            pub(crate) const ZERO: Self = Self(unsafe { NotNan::new_unchecked(0.0) });
+            pub(crate) const ZERO: Self = Self($zero);
         }
 
+        #[cfg(feature = "python")]
         impl<'py> FromPyObject<'py> for $t {
             fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
                 let value = ob.extract()?;
@@ -171,6 +360,7 @@ macro_rules! impl_quant {
             type Output = Self;
 
             fn add(self, rhs: Self) -> Self::Output {
+                debug_assert_not_nan(self.value() + rhs.value(), "+", self, rhs);
                 Self(self.0 + rhs.0)
             }
         }
@@ -179,6 +369,7 @@ macro_rules! impl_quant {
 
         impl AddAssign for $t {
             fn add_assign(&mut self, rhs: Self) {
+                debug_assert_not_nan(self.value() + rhs.value(), "+", *self, rhs);
                 self.0 += rhs.0;
             }
         }
@@ -187,6 +378,7 @@ macro_rules! impl_quant {
             type Output = Self;
 
             fn sub(self, rhs: Self) -> Self::Output {
+                debug_assert_not_nan(self.value() - rhs.value(), "-", self, rhs);
                 Self(self.0 - rhs.0)
             }
         }
@@ -195,6 +387,7 @@ macro_rules! impl_quant {
 
         impl SubAssign for $t {
             fn sub_assign(&mut self, rhs: Self) {
+                debug_assert_not_nan(self.value() - rhs.value(), "-", *self, rhs);
                 self.0 -= rhs.0;
             }
         }
@@ -211,6 +404,7 @@ macro_rules! impl_quant {
             type Output = Self;
 
             fn mul(self, rhs: f64) -> Self::Output {
+                debug_assert_not_nan(self.value() * rhs, "*", self, rhs);
                 Self(self.0 * rhs)
             }
         }
@@ -227,6 +421,7 @@ macro_rules! impl_quant {
             type Output = Self;
 
             fn div(self, rhs: f64) -> Self::Output {
+                debug_assert_not_nan(self.value() / rhs, "/", self, rhs);
                 Self(self.0 / rhs)
             }
         }
@@ -243,12 +438,20 @@ macro_rules! impl_quant {
             }
         }
 
+        #[cfg(feature = "python")]
         impl IntoPy<PyObject> for $t {
             fn into_py(self, py: Python) -> PyObject {
                 PyFloat::new_bound(py, self.value()).into()
             }
         }
 
+        #[cfg(feature = "python")]
+        impl ToPyObject for $t {
+            fn to_object(&self, py: Python) -> PyObject {
+                PyFloat::new_bound(py, self.value()).into()
+            }
+        }
+
         impl From<$t> for f64 {
             fn from(q: $t) -> Self {
                 q.value()
@@ -265,25 +468,118 @@ macro_rules! impl_quant {
     };
 }
 
-impl_quant!(Time);
-impl_quant!(Frequency);
-impl_quant!(Phase);
-impl_quant!(Amplitude);
+impl_quant!(Time, TimeRepr, TIME_ZERO);
+impl_quant!(Frequency, NotNan<f64>, F64_ZERO);
+impl_quant!(Phase, NotNan<f64>, F64_ZERO);
+impl_quant!(Amplitude, NotNan<f64>, F64_ZERO);
 
-macro_rules! impl_id {
+// The operators panic on NaN results such as `inf - inf`. The checked
+// variants are for rust callers that combine unvalidated values and want an
+// error instead.
+macro_rules! impl_checked {
     ($t:ty) => {
         impl $t {
-            pub(crate) fn new(name: impl Into<Arc<str>>) -> Self {
-                Self(name.into())
+            pub(crate) fn is_finite(&self) -> bool {
+                self.value().is_finite()
+            }
+
+            pub(crate) fn try_finite(self) -> Result<Self> {
+                if !self.is_finite() {
+                    return Err(Error::InfiniteValue);
+                }
+                Ok(self)
+            }
+
+            pub(crate) fn try_add(self, rhs: Self) -> Result<Self> {
+                check_nan(self.value() + rhs.value())?;
+                Ok(self + rhs)
+            }
+
+            pub(crate) fn try_sub(self, rhs: Self) -> Result<Self> {
+                check_nan(self.value() - rhs.value())?;
+                Ok(self - rhs)
+            }
+
+            pub(crate) fn try_mul(self, rhs: f64) -> Result<Self> {
+                check_nan(self.value() * rhs)?;
+                Ok(self * rhs)
             }
         }
+    };
+}
+
+impl_checked!(Time);
+impl_checked!(Frequency);
+
+fn check_nan(value: f64) -> Result<()> {
+    NotNan::new(value)?;
+    Ok(())
+}
+
+/// Report the operands of a NaN result in debug builds, before the inner
+/// representation panics without them.
+#[inline]
+fn debug_assert_not_nan(
+    value: f64,
+    op: &str,
+    lhs: impl std::fmt::Debug,
+    rhs: impl std::fmt::Debug,
+) {
+    debug_assert!(!value.is_nan(), "NaN result of {:?} {} {:?}", lhs, op, rhs);
+}
+
+impl ChannelId {
+    pub(crate) fn new(name: impl AsRef<str>) -> Self {
+        Self(intern::intern(name.as_ref()))
+    }
+}
+
+impl AsRef<str> for ChannelId {
+    fn as_ref(&self) -> &str {
+        intern::resolve(self.0)
+    }
+}
+
+impl std::fmt::Debug for ChannelId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ChannelId").field(&self.as_ref()).finish()
+    }
+}
+
+impl Serialize for ChannelId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_ref())
+    }
+}
 
+impl<'de> Deserialize<'de> for ChannelId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Self::new(name))
+    }
+}
+
+impl ShapeId {
+    pub(crate) fn new(name: impl Into<Arc<str>>) -> Self {
+        Self(name.into())
+    }
+}
+
+impl AsRef<str> for ShapeId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+macro_rules! impl_id {
+    ($t:ty) => {
         impl std::fmt::Display for $t {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                write!(f, "{}", self.0)
+                f.write_str(self.as_ref())
             }
         }
 
+        #[cfg(feature = "python")]
         impl<'py> FromPyObject<'py> for $t {
             fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
                 let name = ob.extract::<&str>()?;
@@ -291,15 +587,24 @@ macro_rules! impl_id {
             }
         }
 
+        #[cfg(feature = "python")]
         impl IntoPy<PyObject> for $t {
             fn into_py(self, py: Python) -> PyObject {
-                self.0.into_py(py)
+                self.as_ref().into_py(py)
             }
         }
 
+        #[cfg(feature = "python")]
         impl<'a> IntoPy<PyObject> for &'a $t {
             fn into_py(self, py: Python) -> PyObject {
-                self.0.to_object(py)
+                self.as_ref().into_py(py)
+            }
+        }
+
+        #[cfg(feature = "python")]
+        impl ToPyObject for $t {
+            fn to_object(&self, py: Python) -> PyObject {
+                self.as_ref().to_object(py)
             }
         }
     };
@@ -307,3 +612,118 @@ macro_rules! impl_id {
 
 impl_id!(ChannelId);
 impl_id!(ShapeId);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phase() {
+        let half = Phase::new(0.5).unwrap();
+        #[cfg(feature = "ffi")]
+        assert_eq!(Phase::from_turns(0.5).unwrap(), half);
+        #[cfg(feature = "qiskit")]
+        assert_eq!(Phase::from_radians(std::f64::consts::PI).unwrap(), half);
+        assert_eq!(half.wrapped(), Phase::new(-0.5).unwrap());
+        assert_eq!(
+            Phase::new(2.25).unwrap().wrapped(),
+            Phase::new(0.25).unwrap()
+        );
+        assert_eq!(
+            Phase::new(-0.75).unwrap().wrapped(),
+            Phase::new(0.25).unwrap()
+        );
+        assert_eq!(
+            half.wrapping_add(Phase::new(0.75).unwrap()),
+            Phase::new(0.25).unwrap()
+        );
+        assert_eq!(
+            Phase::ZERO.wrapping_sub(Phase::new(0.25).unwrap()),
+            Phase::new(-0.25).unwrap()
+        );
+        assert!((half.radians() - std::f64::consts::PI).abs() < 1e-15);
+    }
+
+    #[test]
+    fn channel_id() {
+        let id = ChannelId::new("xy0");
+        assert_eq!(id, ChannelId::new(String::from("xy0")));
+        assert_ne!(id, ChannelId::new("xy1"));
+        assert_eq!(id.as_ref(), "xy0");
+        assert_eq!(format!("{:?}", id), r#"ChannelId("xy0")"#);
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, r#""xy0""#);
+        assert_eq!(serde_json::from_str::<ChannelId>(&json).unwrap(), id);
+    }
+
+    #[test]
+    fn checked() {
+        let t = Time::new(1.0).unwrap();
+        assert_eq!(t.try_add(t).unwrap(), Time::new(2.0).unwrap());
+        assert_eq!(t.try_sub(t).unwrap(), Time::ZERO);
+        assert_eq!(t.try_mul(3.0).unwrap(), Time::new(3.0).unwrap());
+        assert_eq!(Time::INFINITY.try_add(t).unwrap(), Time::INFINITY);
+        assert!(!Time::INFINITY.is_finite());
+        assert!(matches!(
+            Time::INFINITY.try_sub(Time::INFINITY),
+            Err(Error::NanValue(_))
+        ));
+        assert!(matches!(
+            Time::INFINITY.try_mul(0.0),
+            Err(Error::NanValue(_))
+        ));
+        assert!(matches!(t.try_mul(f64::NAN), Err(Error::NanValue(_))));
+        assert!(matches!(
+            Time::INFINITY.try_finite(),
+            Err(Error::InfiniteValue)
+        ));
+
+        let f = Frequency::new(1e308).unwrap();
+        assert_eq!(f.try_sub(f).unwrap(), Frequency::ZERO);
+        assert_eq!(f.try_mul(0.5).unwrap(), Frequency::new(5e307).unwrap());
+        assert!(f.is_finite());
+        assert!(!f.try_add(f).unwrap().is_finite());
+        assert!(matches!(
+            f.try_mul(10.0).and_then(Frequency::try_finite),
+            Err(Error::InfiniteValue)
+        ));
+        let inf = Frequency::new(f64::INFINITY).unwrap();
+        assert!(matches!(inf.try_sub(inf), Err(Error::NanValue(_))));
+        assert!(matches!(inf.try_mul(0.0), Err(Error::NanValue(_))));
+    }
+
+    #[test]
+    #[should_panic(expected = "NaN")]
+    fn nan_operator() {
+        let f = Frequency::new(f64::INFINITY).unwrap();
+        let _ = f - f;
+    }
+
+    #[test]
+    fn aligned_index() {
+        let index = |rounding| AlignedIndex::from_time(1.3e-9, 1e9, -1, rounding).unwrap();
+        assert_eq!(index(Rounding::Ceil).value(), 1.5);
+        assert_eq!(index(Rounding::Floor).value(), 1.0);
+        assert_eq!(index(Rounding::Nearest).value(), 1.5);
+        assert_eq!(index(Rounding::Ceil).first_sample(), 2);
+        assert_eq!(index(Rounding::Ceil).residual(), 0.5);
+        assert_eq!(index(Rounding::Floor).residual(), 0.0);
+        assert_eq!(
+            index(Rounding::Ceil),
+            AlignedIndex::new(Time::new(1.3e-9).unwrap(), Frequency::new(1e9).unwrap(), -1)
+                .unwrap()
+        );
+        assert!(AlignedIndex::from_time(f64::NAN, 1e9, 0, Rounding::Ceil).is_none());
+        assert!(AlignedIndex::from_time(f64::INFINITY, 1e9, 0, Rounding::Ceil).is_none());
+    }
+
+    #[test]
+    fn si_display() {
+        assert_eq!(Time::new(1.3e-9).unwrap().to_string(), "1.3 ns");
+        assert_eq!(Time::new(-250e-6).unwrap().to_string(), "-250 µs");
+        assert_eq!(Time::ZERO.to_string(), "0 s");
+        assert_eq!(format!("{:.2}", Time::new(1e-12).unwrap()), "1.00 ps");
+        assert_eq!(Frequency::new(2e9).unwrap().to_string(), "2 GHz");
+        assert_eq!(Frequency::new(37e6).unwrap().to_string(), "37 MHz");
+    }
+}
@@ -0,0 +1,206 @@
+//! Integer representation of [`Time`](super::Time) for the `exact-time`
+//! feature.
+//!
+//! Times are stored as integer attoseconds, so sums and differences computed
+//! while arranging a schedule don't accumulate rounding errors, and the start
+//! of a pulse doesn't depend on how the schedule is nested. Scaling by a
+//! non-integer factor rounds to the nearest attosecond. Values larger than
+//! about 10^18 seconds are treated as infinite.
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+use ordered_float::FloatIsNan;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+const SCALE: f64 = 1e18;
+const LIMIT: f64 = 1e36;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub(crate) struct Attos(i128);
+
+impl Attos {
+    pub(crate) const ZERO: Self = Self(0);
+    pub(crate) const INFINITY: Self = Self(i128::MAX);
+    const NEG_INFINITY: Self = Self(i128::MIN);
+
+    pub(crate) fn new(value: f64) -> Result<Self, FloatIsNan> {
+        if value.is_nan() {
+            return Err(FloatIsNan);
+        }
+        let scaled = value * SCALE;
+        Ok(if scaled >= LIMIT {
+            Self::INFINITY
+        } else if scaled <= -LIMIT {
+            Self::NEG_INFINITY
+        } else {
+            Self(scaled.round() as i128)
+        })
+    }
+
+    pub(crate) fn into_inner(self) -> f64 {
+        match self {
+            Self::INFINITY => f64::INFINITY,
+            Self::NEG_INFINITY => f64::NEG_INFINITY,
+            Self(v) => v as f64 / SCALE,
+        }
+    }
+
+    fn is_infinite(self) -> bool {
+        self == Self::INFINITY || self == Self::NEG_INFINITY
+    }
+
+    /// Saturate integer results beyond the limit to infinity.
+    fn from_int(value: i128) -> Self {
+        const INT_LIMIT: i128 = LIMIT as i128;
+        if value >= INT_LIMIT {
+            Self::INFINITY
+        } else if value <= -INT_LIMIT {
+            Self::NEG_INFINITY
+        } else {
+            Self(value)
+        }
+    }
+
+    /// Attoseconds as a float, infinite values stay infinite.
+    fn to_scaled(self) -> f64 {
+        if self.is_infinite() {
+            self.into_inner()
+        } else {
+            self.0 as f64
+        }
+    }
+
+    /// Same as floating point arithmetic, except that NaN results panic like
+    /// [`ordered_float::NotNan`].
+    fn from_scaled(value: f64) -> Self {
+        Self::new(value / SCALE).expect("Time arithmetic should not produce NaN")
+    }
+}
+
+impl Add for Attos {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        match (self.is_infinite(), rhs.is_infinite()) {
+            (false, false) => Self::from_int(self.0 + rhs.0),
+            (true, true) if self != rhs => panic!("Time arithmetic should not produce NaN"),
+            (true, _) => self,
+            (false, true) => rhs,
+        }
+    }
+}
+
+impl AddAssign for Attos {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Neg for Attos {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Self::INFINITY => Self::NEG_INFINITY,
+            Self::NEG_INFINITY => Self::INFINITY,
+            Self(v) => Self(-v),
+        }
+    }
+}
+
+impl Sub for Attos {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self + -rhs
+    }
+}
+
+impl SubAssign for Attos {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<f64> for Attos {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        // Repeated elements are scaled by integer counts, keep them exact.
+        if !self.is_infinite() && rhs.fract() == 0.0 && rhs.abs() < 2f64.powi(53) {
+            return match self.0.checked_mul(rhs as i128) {
+                Some(v) => Self::from_int(v),
+                None if (self.0 < 0) == (rhs < 0.0) => Self::INFINITY,
+                None => Self::NEG_INFINITY,
+            };
+        }
+        Self::from_scaled(self.to_scaled() * rhs)
+    }
+}
+
+impl Div<f64> for Attos {
+    type Output = Self;
+
+    fn div(self, rhs: f64) -> Self::Output {
+        Self::from_scaled(self.to_scaled() / rhs)
+    }
+}
+
+impl Serialize for Attos {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.into_inner().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Attos {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = f64::deserialize(deserializer)?;
+        Self::new(value).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_sum() {
+        let t = |x| Attos::new(x).unwrap();
+        let sum = (0..10).fold(Attos::ZERO, |acc, _| acc + t(0.1e-9));
+
+        assert_eq!(sum, t(1e-9));
+        assert_eq!(t(0.1e-9) * 10.0, t(1e-9));
+        assert_eq!((t(3e-9) - t(1e-9)).into_inner(), 2e-9);
+        assert_eq!(t(f64::INFINITY) - t(1.0), Attos::INFINITY);
+        assert_eq!(-Attos::INFINITY, t(f64::NEG_INFINITY));
+        assert!(Attos::new(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn saturate_to_infinity() {
+        let t = |x| Attos::new(x).unwrap();
+
+        assert_eq!(t(1e17) * 2f64.powi(52), Attos::INFINITY);
+        assert_eq!(t(1e17) * -2f64.powi(52), Attos::NEG_INFINITY);
+        assert_eq!(t(-1e17) * 2f64.powi(52), Attos::NEG_INFINITY);
+        assert_eq!(t(1e17) * 100.0, Attos::INFINITY);
+        assert_eq!(t(0.9e18) + t(0.9e18), Attos::INFINITY);
+        assert_eq!(t(-0.9e18) - t(0.9e18), Attos::NEG_INFINITY);
+        assert_eq!(Attos::INFINITY / 1e3, Attos::INFINITY);
+        assert_eq!(Attos::INFINITY * 0.5, Attos::INFINITY);
+        assert_eq!(Attos::INFINITY * -2.5, Attos::NEG_INFINITY);
+        assert_eq!(Attos::NEG_INFINITY / 4.0, Attos::NEG_INFINITY);
+        assert_eq!(Attos::NEG_INFINITY / -1e3, Attos::INFINITY);
+    }
+
+    #[test]
+    #[should_panic(expected = "NaN")]
+    fn infinity_times_zero() {
+        let _ = Attos::INFINITY * 0.0;
+    }
+
+    #[test]
+    #[should_panic(expected = "NaN")]
+    fn infinity_over_infinity() {
+        let _ = Attos::INFINITY / f64::INFINITY;
+    }
+}
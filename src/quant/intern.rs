@@ -0,0 +1,65 @@
+//! Process wide registry of channel names for [`ChannelId`](super::ChannelId).
+//!
+//! Channel ids are hashed and compared in every step of scheduling and
+//! sampling, which dominates the run time of schedules with hundreds of
+//! channels if the names are hashed as strings. The registry maps each name to
+//! a small integer once. Names are never removed, the set of channel names
+//! used by a process is small and stable.
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+use hashbrown::HashMap;
+
+#[derive(Debug, Default)]
+struct Registry {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, u32>,
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Get the id of the name, registering it if it is new.
+pub(crate) fn intern(name: &str) -> u32 {
+    if let Some(&id) = registry()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .ids
+        .get(name)
+    {
+        return id;
+    }
+    let mut registry = registry().write().unwrap_or_else(PoisonError::into_inner);
+    if let Some(&id) = registry.ids.get(name) {
+        return id;
+    }
+    let id = u32::try_from(registry.names.len()).expect("Too many channel names");
+    let name: &'static str = Box::leak(name.into());
+    registry.names.push(name);
+    registry.ids.insert(name, id);
+    id
+}
+
+/// Get the name of an id returned by [`intern`].
+pub(crate) fn resolve(id: u32) -> &'static str {
+    registry()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .names[id as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_resolve() {
+        let a = intern("intern_test_a");
+        let b = intern("intern_test_b");
+        assert_ne!(a, b);
+        assert_eq!(intern(&String::from("intern_test_a")), a);
+        assert_eq!(resolve(a), "intern_test_a");
+        assert_eq!(resolve(b), "intern_test_b");
+    }
+}
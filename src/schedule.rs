@@ -1,16 +1,51 @@
 mod absolute;
+mod arena;
+mod arrange;
+mod ascii;
+#[cfg(feature = "python")]
+mod cache;
+#[cfg(feature = "python")]
+mod calibration;
+mod channel_set;
+#[cfg(feature = "python")]
+mod experiment;
+#[cfg(feature = "python")]
+mod fill;
+#[cfg(any(feature = "python", feature = "svg"))]
+mod flatten;
+#[cfg(feature = "python")]
+mod graph;
 mod grid;
+#[cfg(feature = "_frontend")]
+mod group;
+mod library;
 mod play;
+#[cfg(feature = "python")]
+mod readout;
+#[cfg(feature = "python")]
+mod remap;
 mod repeat;
+mod samples;
+mod scale;
+mod schema;
+#[cfg(feature = "python")]
+mod sequence;
 mod simple;
 mod stack;
+#[cfg(feature = "python")]
+mod template;
+#[cfg(feature = "python")]
+mod timing;
 
 use std::sync::Arc;
 
-use anyhow::{bail, Result};
-use hashbrown::HashSet;
 #[cfg(test)]
 use mockall::automock;
+#[cfg(feature = "python")]
+use pyo3::{exceptions::PyValueError, PyErr};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use thiserror::Error;
 
 use crate::{
     quant::{ChannelId, Time},
@@ -18,28 +53,178 @@ use crate::{
 };
 
 pub(crate) use absolute::{Absolute, AbsoluteEntry};
+pub(crate) use arena::{Arena, ArenaTree, Node, NodeId, Slot};
+pub(crate) use arrange::arrange_tree_inherited;
+pub(crate) use arrange::{arrange_children, arrange_tree};
+pub(crate) use ascii::render_ascii;
+#[cfg(feature = "python")]
+pub(crate) use cache::ElementCache;
+#[cfg(feature = "python")]
+pub(crate) use calibration::{resolve_calibrations, Calibration, Calibrations};
+pub(crate) use channel_set::ChannelSet;
+#[cfg(feature = "python")]
+pub(crate) use experiment::Experiment;
+#[cfg(feature = "python")]
+pub(crate) use fill::fill_idle;
+#[cfg(any(feature = "python", feature = "svg"))]
+pub(crate) use flatten::{flatten, Instruction, InstructionKind};
+#[cfg(feature = "python")]
+pub(crate) use flatten::{flatten_loops, Segment};
+#[cfg(feature = "python")]
+pub(crate) use graph::{to_dot, to_mermaid};
 pub(crate) use grid::{Grid, GridEntry};
-pub(crate) use play::Play;
+#[cfg(feature = "_frontend")]
+pub(crate) use group::expand_groups;
+#[cfg(any(feature = "python", test))]
+pub(crate) use library::PulseDef;
+pub(crate) use library::{PulseLibrary, PulseRef};
+pub(crate) use play::{FallingEdge, PhaseReference, Play};
+#[cfg(feature = "python")]
+pub(crate) use readout::{multiplex_readout, ReadoutTone};
+#[cfg(feature = "python")]
+pub(crate) use remap::remap_channels;
 pub(crate) use repeat::Repeat;
+pub(crate) use samples::PlaySamples;
+pub(crate) use scale::ScaleAmplitude;
+#[cfg(feature = "python")]
+pub(crate) use sequence::SequenceTable;
 pub(crate) use simple::{Barrier, SetFreq, SetPhase, ShiftFreq, ShiftPhase, SwapPhase};
 pub(crate) use stack::Stack;
-
+#[cfg(feature = "python")]
+pub(crate) use template::{fill_slots, slots};
+#[cfg(feature = "python")]
+pub(crate) use timing::{reserved_intervals, timing_report, TimingReport};
+
+/// Shared handle of an element.
+///
+/// The rust builders assemble trees in an [`Arena`] and convert them to
+/// element trees when they are first measured, see [`arena`].
 pub(crate) type ElementRef = Arc<Element>;
 
-#[derive(Debug, Clone)]
+/// Errors of constructing schedule elements.
+///
+/// Time values are in seconds.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ScheduleError {
+    /// A time quantity such as margin, duration, width, or spacing is not
+    /// finite or out of range.
+    #[error("Invalid {property} {value}")]
+    InvalidTime { property: &'static str, value: f64 },
+    /// A non-time quantity such as amplitude, frequency, or phase is not
+    /// finite.
+    #[error("Invalid {property} {value}")]
+    InvalidValue { property: &'static str, value: f64 },
+    /// The span of a grid entry is zero.
+    #[error("Span should be greater than 0")]
+    InvalidSpan,
+    /// The element is not of the expected variant.
+    #[error("Expected {expected} variant")]
+    VariantMismatch { expected: &'static str },
+    /// A path into the element tree does not lead to a child element.
+    #[error("No child at index {index}")]
+    InvalidPath { index: usize },
+    /// The handle does not refer to an element of the schedule.
+    #[error("Unknown element handle {0}")]
+    UnknownHandle(u64),
+    /// A channel of the element is not among the allowed channels.
+    #[error("Unknown channel {channel}")]
+    UnknownChannel { channel: String },
+    /// No pulse of the library has the requested name and version.
+    #[error("Unknown pulse {name} (version {version:?})")]
+    UnknownPulse { name: String, version: Option<u32> },
+    /// A pulse with the same name and version is already registered.
+    #[error("Pulse {name} version {version} is already registered")]
+    DuplicatePulse { name: String, version: u32 },
+    /// No calibration is stored for the calibration key of a pulse.
+    #[error("No calibration of gate {gate} on channel {channel}")]
+    UnknownCalibration { channel: String, gate: String },
+    /// The body of a loop changes the behavior of later iterations.
+    #[error("Repeat body shifts the frequency of channel {channel}")]
+    LoopVariant { channel: String },
+    /// The phases of a channel group can't be swapped.
+    #[error("Phase of channel group {group} can't be swapped")]
+    GroupSwap { group: String },
+    /// The shot order refers to a schedule that doesn't exist.
+    #[error("No schedule at index {index}")]
+    UnknownSchedule { index: usize },
+    /// No slot of the template has the name of a fill.
+    #[error("Unknown slot {slot}")]
+    UnknownSlot { slot: String },
+    /// The channels of a fill differ from the channels of its slot.
+    #[error("Slot {slot} expects channels [{expected}], got [{actual}]")]
+    SlotChannels {
+        slot: String,
+        expected: String,
+        actual: String,
+    },
+    /// A runtime variable name is not an identifier of the sequencer.
+    #[error("Invalid variable name {name:?}")]
+    InvalidVariable { name: String },
+    /// A repeat bound to a runtime variable has a maximum count of zero.
+    #[error("Repeat with count variable {name} should have a non-zero count")]
+    ZeroCountVar { name: String },
+    /// A multiplexed readout needs at least one tone.
+    #[error("No readout tones")]
+    NoReadoutTones,
+    /// The serialized schedule document is malformed or has an unsupported
+    /// version.
+    #[error("Invalid schedule document: {0}")]
+    Document(String),
+}
+
+pub(crate) type Result<T> = std::result::Result<T, ScheduleError>;
+
+/// Reject infinite time properties.
+fn finite_time(property: &'static str, value: Time) -> Result<Time> {
+    value.try_finite().map_err(|_| ScheduleError::InvalidTime {
+        property,
+        value: value.value(),
+    })
+}
+
+/// Reject infinite or negative time properties.
+fn non_negative_time(property: &'static str, value: Time) -> Result<Time> {
+    let value = finite_time(property, value)?;
+    if value < Time::ZERO {
+        return Err(ScheduleError::InvalidTime {
+            property,
+            value: value.value(),
+        });
+    }
+    Ok(value)
+}
+
+/// Reject infinite non-time properties.
+fn finite_value<T: Into<f64> + Copy>(property: &'static str, value: T) -> Result<T> {
+    let v = value.into();
+    if !v.is_finite() {
+        return Err(ScheduleError::InvalidValue { property, value: v });
+    }
+    Ok(value)
+}
+
+#[cfg(feature = "python")]
+impl From<ScheduleError> for PyErr {
+    fn from(err: ScheduleError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) struct Element {
     pub(crate) common: ElementCommon,
     pub(crate) variant: ElementVariant,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) struct ElementCommon {
-    margin: (Time, Time),
+    margin: Margin,
     alignment: Alignment,
     phantom: bool,
     duration: Option<Time>,
     max_duration: Time,
     min_duration: Time,
+    allow_oversize: Option<bool>,
 }
 
 #[derive(Debug, Clone)]
@@ -51,6 +236,16 @@ pub(crate) struct TimeRange {
     pub(crate) span: Time,
 }
 
+impl TimeRange {
+    /// Remove `margin` from the start and the end of the range.
+    fn shrink(self, (start, end): (Time, Time)) -> Self {
+        Self {
+            start: self.start + start,
+            span: self.span - start - end,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct Arranged<T> {
     pub(crate) item: T,
@@ -88,28 +283,41 @@ macro_rules! impl_variant {
         }
 
         impl TryFrom<ElementVariant> for $variant {
-            type Error = anyhow::Error;
+            type Error = ScheduleError;
 
-            fn try_from(value: ElementVariant) -> Result<Self, Self::Error> {
+            fn try_from(value: ElementVariant) -> Result<Self> {
                 match value {
                     ElementVariant::$variant(v) => Ok(v),
-                    _ => bail!("Expected {} variant", stringify!($variant)),
+                    _ => Err(ScheduleError::VariantMismatch {
+                        expected: stringify!($variant),
+                    }),
                 }
             }
         }
 
         impl<'a> TryFrom<&'a ElementVariant> for &'a $variant {
-            type Error = anyhow::Error;
+            type Error = ScheduleError;
 
-            fn try_from(value: &'a ElementVariant) -> Result<Self, Self::Error> {
+            fn try_from(value: &'a ElementVariant) -> Result<Self> {
                 match value {
                     ElementVariant::$variant(v) => Ok(v),
-                    _ => bail!("Expected {} variant", stringify!($variant)),
+                    _ => Err(ScheduleError::VariantMismatch {
+                        expected: stringify!($variant),
+                    }),
                 }
             }
         }
         )*
 
+        #[cfg(feature = "python")]
+        impl ElementVariant {
+            pub(crate) fn name(&self) -> &'static str {
+                match self {
+                    $(ElementVariant::$variant(_) => stringify!($variant),)*
+                }
+            }
+        }
+
         impl Measure for ElementVariant {
             fn measure(&self) -> Time {
                 match self {
@@ -127,10 +335,38 @@ macro_rules! impl_variant {
 }
 
 impl_variant!(
-    Play, ShiftPhase, SetPhase, ShiftFreq, SetFreq, SwapPhase, Barrier, Repeat, Stack, Absolute,
+    Play,
+    PlaySamples,
+    ShiftPhase,
+    SetPhase,
+    ShiftFreq,
+    SetFreq,
+    SwapPhase,
+    Barrier,
+    Repeat,
+    Stack,
+    Absolute,
     Grid,
+    ScaleAmplitude,
 );
 
+impl ElementVariant {
+    /// Copy of the container with the child at `index` replaced.
+    ///
+    /// Returns `None` if the variant has no child at `index`, and an error if
+    /// the new child doesn't fit its entry, see [`Absolute::with_child`].
+    pub(crate) fn with_child(&self, index: usize, child: ElementRef) -> Result<Option<Self>> {
+        Ok(match self {
+            ElementVariant::Repeat(v) => v.with_child(index, child).map(Into::into),
+            ElementVariant::Stack(v) => v.with_child(index, child).map(Into::into),
+            ElementVariant::Absolute(v) => v.with_child(index, child)?.map(Into::into),
+            ElementVariant::Grid(v) => v.with_child(index, child).map(Into::into),
+            ElementVariant::ScaleAmplitude(v) => v.with_child(index, child).map(Into::into),
+            _ => None,
+        })
+    }
+}
+
 impl Element {
     pub(crate) fn new(common: ElementCommon, variant: impl Into<ElementVariant>) -> Self {
         Self {
@@ -141,7 +377,7 @@ impl Element {
 
     pub(crate) fn inner_time_range(&self, time_range: TimeRange) -> TimeRange {
         let min_max = self.common.min_max_duration();
-        let inner_start = time_range.start + self.common.margin.0;
+        let inner_start = time_range.start + self.common.margin().0;
         let inner_span = min_max.clamp(time_range.span - self.common.total_margin());
         TimeRange {
             start: inner_start,
@@ -152,13 +388,18 @@ impl Element {
 
 impl ElementCommon {
     pub(crate) fn margin(&self) -> (Time, Time) {
-        self.margin
+        self.margin.get()
     }
 
     pub(crate) fn alignment(&self) -> Alignment {
         self.alignment
     }
 
+    /// Whether the element only reserves time.
+    ///
+    /// A phantom element is measured and arranged like any other element, so
+    /// it takes up time on its channels, but neither the element nor its
+    /// descendants emit instructions. See [`reserved_intervals`].
     pub(crate) fn phantom(&self) -> bool {
         self.phantom
     }
@@ -175,6 +416,11 @@ impl ElementCommon {
         self.min_duration
     }
 
+    /// Override of the global `allow_oversize` option for this element.
+    pub(crate) fn allow_oversize(&self) -> Option<bool> {
+        self.allow_oversize
+    }
+
     fn min_max_duration(&self) -> MinMax {
         let min_max = MinMax::new(self.min_duration, self.max_duration);
         let max = min_max.clamp(self.duration.unwrap_or(Time::INFINITY));
@@ -183,7 +429,8 @@ impl ElementCommon {
     }
 
     fn total_margin(&self) -> Time {
-        self.margin.0 + self.margin.1
+        let (start, end) = self.margin();
+        start + end
     }
 }
 
@@ -193,7 +440,7 @@ impl ElementCommonBuilder {
     }
 
     pub(crate) fn margin(&mut self, margin: (Time, Time)) -> &mut Self {
-        self.0.margin = margin;
+        self.0.margin = Margin::new(margin);
         self
     }
 
@@ -222,21 +469,26 @@ impl ElementCommonBuilder {
         self
     }
 
+    pub(crate) fn allow_oversize(&mut self, allow_oversize: Option<bool>) -> &mut Self {
+        self.0.allow_oversize = allow_oversize;
+        self
+    }
+
     pub(crate) fn validate(&self) -> Result<()> {
         let v = &self.0;
-        if !(v.margin.0.value().is_finite() && v.margin.1.value().is_finite()) {
-            bail!("Invalid margin {:?}", v.margin);
+        let (start, end) = v.margin();
+        for margin in [start, end] {
+            finite_time("margin", margin)?;
         }
         if let Some(v) = v.duration {
-            if !(v.value().is_finite() && v >= Time::ZERO) {
-                bail!("Invalid duration {:?}", v);
-            }
-        }
-        if !(v.min_duration.value().is_finite() && v.min_duration >= Time::ZERO) {
-            bail!("Invalid min_duration {:?}", v.min_duration);
+            non_negative_time("duration", v)?;
         }
+        non_negative_time("min_duration", v.min_duration)?;
         if v.max_duration < Time::ZERO {
-            bail!("Invalid max_duration {:?}", v.max_duration);
+            return Err(ScheduleError::InvalidTime {
+                property: "max_duration",
+                value: v.max_duration.value(),
+            });
         }
         Ok(())
     }
@@ -266,6 +518,7 @@ impl Default for ElementCommonBuilder {
             duration: None,
             max_duration: Time::INFINITY,
             min_duration: Default::default(),
+            allow_oversize: None,
         })
     }
 }
@@ -306,11 +559,85 @@ where
     }
 }
 
-fn merge_channel_ids<'a, I>(ids: I) -> Vec<ChannelId>
+/// Containers with at least this many children measure them in parallel.
+#[cfg(feature = "parallel")]
+const PARALLEL_MEASURE_THRESHOLD: usize = 1024;
+
+/// Measure the children of a container.
+///
+/// The children are independent of each other, so wide containers measure them
+/// in parallel. Only combining the results depends on the order.
+fn measure_children<T, F>(children: &[T], measure: F) -> Vec<Time>
+where
+    T: Sync,
+    F: Fn(&T) -> Time + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    if children.len() >= PARALLEL_MEASURE_THRESHOLD {
+        return children.par_iter().map(measure).collect();
+    }
+    children.iter().map(measure).collect()
+}
+
+/// Margin that a container adds around `element` so that the margin of the
+/// element is at least `child_margin`.
+fn extra_margin(element: &Element, child_margin: (Time, Time)) -> (Time, Time) {
+    let (start, end) = element.common.margin();
+    (
+        (child_margin.0 - start).max(Time::ZERO),
+        (child_margin.1 - end).max(Time::ZERO),
+    )
+}
+
+/// Reject infinite padding or child margins of containers.
+fn finite_margin(property: &'static str, margin: (Time, Time)) -> Result<Margin> {
+    finite_time(property, margin.0)?;
+    finite_time(property, margin.1)?;
+    Ok(Margin::new(margin))
+}
+
+/// Space before and after an element or the children of a container.
+///
+/// Most elements have no margin, so zero margins are stored without space for
+/// the two times.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Margin(Option<Box<(Time, Time)>>);
+
+impl Margin {
+    pub(crate) fn new(margin: (Time, Time)) -> Self {
+        if margin == (Time::ZERO, Time::ZERO) {
+            Self(None)
+        } else {
+            Self(Some(Box::new(margin)))
+        }
+    }
+
+    pub(crate) fn get(&self) -> (Time, Time) {
+        self.0.as_deref().copied().unwrap_or_default()
+    }
+}
+
+/// A child with the result of [`measure_children`].
+struct Measured<'a> {
+    element: &'a ElementRef,
+    span: Time,
+}
+
+impl Measure for Measured<'_> {
+    fn measure(&self) -> Time {
+        self.span
+    }
+
+    fn channels(&self) -> &[ChannelId] {
+        self.element.channels()
+    }
+}
+
+/// Union of the channels of the children, sorted by id.
+fn merge_channel_ids<'a, I>(ids: I) -> ChannelSet
 where
     I: IntoIterator,
     I::Item: IntoIterator<Item = &'a ChannelId>,
 {
-    let set = ids.into_iter().flatten().collect::<HashSet<_>>();
-    set.into_iter().cloned().collect()
+    ChannelSet::union(ids.into_iter().flatten().copied())
 }
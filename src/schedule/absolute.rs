@@ -1,10 +1,11 @@
 use std::sync::OnceLock;
 
-use anyhow::{bail, Result};
-
 use crate::{
     quant::{ChannelId, Time},
-    schedule::{merge_channel_ids, ElementRef, Measure},
+    schedule::{
+        finite_time, measure_children, merge_channel_ids, ChannelSet, ElementRef, Measure,
+        Measured, Result, ScheduleError,
+    },
 };
 
 use super::{Arrange, Arranged, TimeRange};
@@ -12,31 +13,66 @@ use super::{Arrange, Arranged, TimeRange};
 #[derive(Debug, Clone)]
 pub(crate) struct AbsoluteEntry {
     time: Time,
+    from_end: bool,
     element: ElementRef,
 }
 
 #[derive(Debug, Clone, Default)]
 pub(crate) struct Absolute {
     children: Vec<AbsoluteEntry>,
-    channel_ids: Vec<ChannelId>,
+    channel_ids: ChannelSet,
     measure_result: OnceLock<Time>,
+    content_hash: OnceLock<u64>,
 }
 
 impl AbsoluteEntry {
     pub(crate) fn new(element: ElementRef) -> Self {
         Self {
             time: Time::ZERO,
+            from_end: false,
             element,
         }
     }
 
     pub(crate) fn with_time(mut self, time: Time) -> Result<Self> {
-        if !time.value().is_finite() {
-            bail!("Invalid time {:?}", time);
+        self.time = finite_time("time", time)?;
+        self.check_from_end()
+    }
+
+    /// Measure the time from the end of the container instead of the start.
+    ///
+    /// The time has to be at most the negated duration of the element, so
+    /// that the element ends inside the container.
+    pub(crate) fn with_from_end(mut self, from_end: bool) -> Result<Self> {
+        self.from_end = from_end;
+        self.check_from_end()
+    }
+
+    fn with_element(&self, element: ElementRef) -> Result<Self> {
+        Self { element, ..*self }.check_from_end()
+    }
+
+    fn check_from_end(self) -> Result<Self> {
+        if self.from_end && self.time + self.element.measure() > Time::ZERO {
+            return Err(ScheduleError::InvalidTime {
+                property: "time",
+                value: self.time.value(),
+            });
         }
-        self.time = time;
         Ok(self)
     }
+
+    pub(crate) fn time(&self) -> Time {
+        self.time
+    }
+
+    pub(crate) fn is_from_end(&self) -> bool {
+        self.from_end
+    }
+
+    pub(crate) fn element(&self) -> &ElementRef {
+        &self.element
+    }
 }
 
 impl Absolute {
@@ -48,12 +84,63 @@ impl Absolute {
         let channel_ids = merge_channel_ids(children.iter().map(|e| e.element.variant.channels()));
         self.children = children;
         self.channel_ids = channel_ids;
+        self.measure_result.take();
+        self.content_hash.take();
         self
     }
 
+    /// Copy with the child at `index` replaced, `None` if there is no such
+    /// child.
+    ///
+    /// Fails if the new child placed from the end would end after the end of
+    /// the container.
+    pub(crate) fn with_child(&self, index: usize, child: ElementRef) -> Result<Option<Self>> {
+        let mut children = self.children.clone();
+        let Some(entry) = children.get_mut(index) else {
+            return Ok(None);
+        };
+        *entry = entry.with_element(child)?;
+        Ok(Some(Self::new().with_children(children)))
+    }
+
+    /// Copy with the children replaced by `f`, checked like
+    /// [`with_child`](Self::with_child).
+    #[cfg(feature = "_frontend")]
+    pub(crate) fn map_children(
+        &self,
+        mut f: impl FnMut(&ElementRef) -> ElementRef,
+    ) -> Result<Self> {
+        let children = self
+            .children
+            .iter()
+            .map(|e| e.with_element(f(&e.element)))
+            .collect::<Result<_>>()?;
+        Ok(Self::new().with_children(children))
+    }
+
+    pub(crate) fn children(&self) -> &[AbsoluteEntry] {
+        &self.children
+    }
+
+    /// Cache of [`ElementVariant::content_hash`](super::ElementVariant::content_hash).
+    pub(crate) fn content_hash(&self) -> &OnceLock<u64> {
+        &self.content_hash
+    }
+
     fn measure_result(&self) -> &Time {
-        self.measure_result
-            .get_or_init(|| measure_absolute(self.children.iter().map(|e| (&e.element, e.time))))
+        self.measure_result.get_or_init(|| {
+            let spans = measure_children(&self.children, |e| e.element.measure());
+            measure_absolute(self.children.iter().zip(spans).map(|(e, span)| {
+                (
+                    Measured {
+                        element: &e.element,
+                        span,
+                    },
+                    e.time,
+                    e.from_end,
+                )
+            }))
+        })
     }
 }
 
@@ -72,12 +159,18 @@ impl Arrange for Absolute {
         self.children.iter().map(
             move |AbsoluteEntry {
                       time: offset,
+                      from_end,
                       element,
                   }| {
+                let origin = if *from_end {
+                    time_range.start + time_range.span
+                } else {
+                    time_range.start
+                };
                 Arranged {
                     item: element,
                     time_range: TimeRange {
-                        start: time_range.start + offset,
+                        start: origin + offset,
                         span: element.measure(),
                     },
                 }
@@ -86,14 +179,90 @@ impl Arrange for Absolute {
     }
 }
 
+/// Children measured from the end need a duration of at least the negated
+/// offset so that they start inside the container, they end inside it by
+/// [`AbsoluteEntry::with_from_end`].
 fn measure_absolute<I, M>(children: I) -> Time
 where
-    I: IntoIterator<Item = (M, Time)>,
+    I: IntoIterator<Item = (M, Time, bool)>,
     M: Measure,
 {
     children
         .into_iter()
-        .map(|(child, offset)| offset + child.measure())
+        .map(|(child, offset, from_end)| {
+            if from_end {
+                -offset
+            } else {
+                offset + child.measure()
+            }
+        })
         .max()
         .unwrap_or(Time::ZERO)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        quant::Amplitude,
+        schedule::{Element, ElementCommonBuilder, Play},
+    };
+
+    #[test]
+    fn from_end() {
+        let t = |v| Time::new(v).unwrap();
+        let play = |channel| {
+            let play = Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(1.0).unwrap(),
+                t(10.0),
+            )
+            .unwrap();
+            Arc::new(Element::new(
+                ElementCommonBuilder::new().build().unwrap(),
+                play,
+            ))
+        };
+        let absolute = Absolute::new().with_children(vec![
+            AbsoluteEntry::new(play("a")).with_time(t(5.0)).unwrap(),
+            AbsoluteEntry::new(play("b"))
+                .with_time(t(-30.0))
+                .unwrap()
+                .with_from_end(true)
+                .unwrap(),
+        ]);
+        assert_eq!(absolute.measure(), t(30.0));
+
+        let starts: Vec<_> = absolute
+            .arrange(TimeRange {
+                start: t(100.0),
+                span: t(50.0),
+            })
+            .map(|a| a.time_range.start.value())
+            .collect();
+        assert_eq!(starts, [105.0, 120.0]);
+
+        // The element has to end inside the container.
+        let entry = |time| {
+            AbsoluteEntry::new(play("b"))
+                .with_time(t(time))
+                .unwrap()
+                .with_from_end(true)
+        };
+        assert!(entry(-10.0).is_ok());
+        assert!(matches!(
+            entry(-5.0),
+            Err(ScheduleError::InvalidTime {
+                property: "time",
+                value: -5.0
+            })
+        ));
+        assert!(entry(5.0).is_err());
+        let entry = entry(-10.0).unwrap();
+        assert!(entry.clone().with_time(t(-20.0)).is_ok());
+        assert!(entry.with_time(t(5.0)).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! Builder-side storage of element trees under construction.
+//!
+//! The rust builders store the nodes in one [`Arena`], with the children of a
+//! layout as a range of edges, instead of creating an [`ElementRef`] per node
+//! while the tree is assembled. Measuring, arranging, and compiling still run
+//! on element trees: the first of them converts the arena with one allocation
+//! per node, so only schedules that are built and dropped without being
+//! measured skip the per node allocations.
+use std::{ops::Range, sync::Arc};
+
+use crate::{
+    quant::Time,
+    schedule::{
+        finite_time, AbsoluteEntry, Element, ElementCommon, ElementRef, ElementVariant, GridEntry,
+        Result, ScheduleError,
+    },
+};
+
+/// Nodes of element trees.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Arena {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+/// Element tree with the descendants of the root stored in an [`Arena`].
+#[derive(Debug, Clone)]
+pub(crate) struct ArenaTree {
+    arena: Arena,
+    root: Node,
+}
+
+// Boxing the element would allocate per node again.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub(crate) enum Node {
+    /// An element. The children of a [`Stack`](super::Stack),
+    /// [`Absolute`](super::Absolute), or [`Grid`](super::Grid) without
+    /// children are the edges of the node.
+    Element {
+        common: ElementCommon,
+        variant: ElementVariant,
+        edges: Range<u32>,
+    },
+    /// A subtree that is already an element tree, e.g. because it was
+    /// measured. The measurement is shared with the other owners.
+    Tree(ElementRef),
+}
+
+/// Index of a node in an [`Arena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NodeId(u32);
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    node: NodeId,
+    slot: Slot,
+}
+
+/// Position of a child in its layout.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Slot {
+    Stack,
+    Absolute { time: Time, from_end: bool },
+    Grid { column: usize, span: usize },
+}
+
+impl Slot {
+    /// Slot of an [`AbsoluteEntry`]. Children placed from the end have to be
+    /// checked against their duration before they are added.
+    pub(crate) fn absolute(time: Time, from_end: bool) -> Result<Self> {
+        Ok(Self::Absolute {
+            time: finite_time("time", time)?,
+            from_end,
+        })
+    }
+
+    /// Slot of a [`GridEntry`].
+    pub(crate) fn grid(column: usize, span: usize) -> Result<Self> {
+        if span == 0 {
+            return Err(ScheduleError::InvalidSpan);
+        }
+        Ok(Self::Grid { column, span })
+    }
+}
+
+impl Node {
+    pub(crate) fn new(common: ElementCommon, variant: impl Into<ElementVariant>) -> Self {
+        Self::Element {
+            common,
+            variant: variant.into(),
+            edges: 0..0,
+        }
+    }
+
+    fn with_edge_offset(self, offset: u32) -> Self {
+        match self {
+            Self::Element {
+                common,
+                variant,
+                edges,
+            } => Self::Element {
+                common,
+                variant,
+                edges: edges.start + offset..edges.end + offset,
+            },
+            Self::Tree(_) => self,
+        }
+    }
+}
+
+impl Arena {
+    /// Move the nodes of `tree` into the arena and return the id of its root.
+    pub(crate) fn insert(&mut self, tree: ArenaTree) -> NodeId {
+        let ArenaTree { arena, root } = tree;
+        let node_offset = self.next_id().0;
+        let edge_offset = self.edges.len() as u32;
+        self.edges.extend(arena.edges.into_iter().map(|e| Edge {
+            node: NodeId(e.node.0 + node_offset),
+            slot: e.slot,
+        }));
+        self.nodes.extend(
+            arena
+                .nodes
+                .into_iter()
+                .chain([root])
+                .map(|n| n.with_edge_offset(edge_offset)),
+        );
+        NodeId(self.next_id().0 - 1)
+    }
+
+    /// Tree of a layout with the `children` in the arena.
+    ///
+    /// The layout has to be a [`Stack`](super::Stack),
+    /// [`Absolute`](super::Absolute), or [`Grid`](super::Grid) without
+    /// children.
+    pub(crate) fn into_tree(
+        mut self,
+        common: ElementCommon,
+        layout: impl Into<ElementVariant>,
+        children: &[(NodeId, Slot)],
+    ) -> ArenaTree {
+        let start = self.edges.len() as u32;
+        self.edges
+            .extend(children.iter().map(|&(node, slot)| Edge { node, slot }));
+        let root = Node::Element {
+            common,
+            variant: layout.into(),
+            edges: start..self.edges.len() as u32,
+        };
+        ArenaTree { arena: self, root }
+    }
+
+    fn next_id(&self) -> NodeId {
+        NodeId(u32::try_from(self.nodes.len()).expect("Too many elements"))
+    }
+
+    fn element(&self, node: &Node) -> ElementRef {
+        let (common, variant, edges) = match node {
+            Node::Element {
+                common,
+                variant,
+                edges,
+            } => (common, variant, edges),
+            Node::Tree(element) => return element.clone(),
+        };
+        let variant = if edges.is_empty() {
+            variant.clone()
+        } else {
+            let children = self.edges[edges.start as usize..edges.end as usize]
+                .iter()
+                .map(|e| (self.element(&self.nodes[e.node.0 as usize]), e.slot));
+            with_children(variant, children)
+        };
+        Arc::new(Element::new(common.clone(), variant))
+    }
+}
+
+impl ArenaTree {
+    pub(crate) fn leaf(node: Node) -> Self {
+        Self {
+            arena: Arena::default(),
+            root: node,
+        }
+    }
+
+    pub(crate) fn to_element(&self) -> ElementRef {
+        self.arena.element(&self.root)
+    }
+}
+
+/// Copy of a layout without children with the given children.
+fn with_children(
+    variant: &ElementVariant,
+    children: impl Iterator<Item = (ElementRef, Slot)>,
+) -> ElementVariant {
+    const CHECKED: &str = "Slots should be checked when the children are added";
+    match variant {
+        ElementVariant::Stack(v) => v
+            .clone()
+            .with_children(children.map(|(c, _)| c).collect())
+            .into(),
+        ElementVariant::Absolute(v) => v
+            .clone()
+            .with_children(
+                children
+                    .map(|(c, slot)| {
+                        let Slot::Absolute { time, from_end } = slot else {
+                            unreachable!("Children of absolute layouts have absolute slots");
+                        };
+                        AbsoluteEntry::new(c)
+                            .with_time(time)
+                            .and_then(|e| e.with_from_end(from_end))
+                            .expect(CHECKED)
+                    })
+                    .collect(),
+            )
+            .into(),
+        ElementVariant::Grid(v) => v
+            .clone()
+            .with_children(
+                children
+                    .map(|(c, slot)| {
+                        let Slot::Grid { column, span } = slot else {
+                            unreachable!("Children of grids have grid slots");
+                        };
+                        GridEntry::new(c)
+                            .with_column(column)
+                            .with_span(span)
+                            .expect(CHECKED)
+                    })
+                    .collect(),
+            )
+            .into(),
+        _ => unreachable!("Only layouts have children in the arena"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        quant::{Amplitude, ChannelId},
+        schedule::{Absolute, ElementCommonBuilder, Play, Stack},
+    };
+
+    #[test]
+    fn to_element() {
+        let t = |v| Time::new(v).unwrap();
+        let common = || ElementCommonBuilder::new().build().unwrap();
+        let play = |channel| {
+            Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(1.0).unwrap(),
+                t(10.0),
+            )
+            .unwrap()
+        };
+        let element = |variant: ElementVariant| Arc::new(Element::new(common(), variant));
+
+        let mut inner = Arena::default();
+        let children = [
+            (
+                inner.insert(ArenaTree::leaf(Node::new(common(), play("a")))),
+                Slot::absolute(t(5.0), false).unwrap(),
+            ),
+            (
+                inner.insert(ArenaTree::leaf(Node::Tree(element(play("b").into())))),
+                Slot::absolute(t(-10.0), true).unwrap(),
+            ),
+        ];
+        let inner = inner.into_tree(common(), Absolute::new(), &children);
+        let mut outer = Arena::default();
+        let children = [
+            (
+                outer.insert(ArenaTree::leaf(Node::new(common(), play("c")))),
+                Slot::Stack,
+            ),
+            (outer.insert(inner), Slot::Stack),
+        ];
+        let tree = outer.into_tree(common(), Stack::new(), &children);
+
+        let expected = element(
+            Stack::new()
+                .with_children(vec![
+                    element(play("c").into()),
+                    element(
+                        Absolute::new()
+                            .with_children(vec![
+                                AbsoluteEntry::new(element(play("a").into()))
+                                    .with_time(t(5.0))
+                                    .unwrap(),
+                                AbsoluteEntry::new(element(play("b").into()))
+                                    .with_time(t(-10.0))
+                                    .unwrap()
+                                    .with_from_end(true)
+                                    .unwrap(),
+                            ])
+                            .into(),
+                    ),
+                ])
+                .into(),
+        );
+        assert_eq!(tree.to_element(), expected);
+        assert!(Slot::absolute(Time::INFINITY, false).is_err());
+        assert!(Slot::grid(0, 0).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Arrangement of element trees on the time axis.
+use std::iter;
+
+use crate::{
+    quant::Time,
+    schedule::{Arrange as _, Arranged, ElementRef, ElementVariant, TimeRange},
+};
+
+#[derive(Debug)]
+enum IterVariant<S, A, G, R, C> {
+    Stack(S),
+    Absolute(A),
+    Grid(G),
+    Repeat(R),
+    ScaleAmplitude(C),
+}
+
+impl<S, A, G, R, C, T> Iterator for IterVariant<S, A, G, R, C>
+where
+    S: Iterator<Item = T>,
+    A: Iterator<Item = T>,
+    G: Iterator<Item = T>,
+    R: Iterator<Item = T>,
+    C: Iterator<Item = T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            IterVariant::Stack(s) => s.next(),
+            IterVariant::Absolute(a) => a.next(),
+            IterVariant::Grid(g) => g.next(),
+            IterVariant::Repeat(r) => r.next(),
+            IterVariant::ScaleAmplitude(c) => c.next(),
+        }
+    }
+}
+
+pub(crate) fn arrange_tree(
+    root: &ElementRef,
+    time_range: TimeRange,
+) -> impl Iterator<Item = Arranged<&ElementRef>> {
+    arrange_tree_scaled(root, time_range).map(|(arranged, _)| arranged)
+}
+
+/// State of an arranged element inherited from its ancestors.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Inherited {
+    /// Product of the factors of the `ScaleAmplitude` ancestors.
+    pub(crate) scale: f64,
+    /// Start of the iteration of the innermost `Repeat` ancestor, zero if
+    /// there is none.
+    pub(crate) iteration_start: Time,
+}
+
+/// Same as [`arrange_tree`], with the product of the factors of the
+/// `ScaleAmplitude` ancestors of each element.
+pub(crate) fn arrange_tree_scaled(
+    root: &ElementRef,
+    time_range: TimeRange,
+) -> impl Iterator<Item = (Arranged<&ElementRef>, f64)> {
+    arrange_tree_inherited(root, time_range)
+        .map(|(arranged, inherited)| (arranged, inherited.scale))
+}
+
+/// Same as [`arrange_tree`], with the [`Inherited`] state of each element.
+pub(crate) fn arrange_tree_inherited(
+    root: &ElementRef,
+    time_range: TimeRange,
+) -> impl Iterator<Item = (Arranged<&ElementRef>, Inherited)> {
+    let root = Arranged {
+        item: root,
+        time_range,
+    };
+    let inherited = Inherited {
+        scale: 1.0,
+        iteration_start: Time::ZERO,
+    };
+    pre_order_iter((root, inherited), |(arranged, inherited)| {
+        let is_repeat = matches!(arranged.item.variant, ElementVariant::Repeat(_));
+        let scale = match &arranged.item.variant {
+            ElementVariant::ScaleAmplitude(v) => inherited.scale * v.factor(),
+            _ => inherited.scale,
+        };
+        arrange_children(arranged).map(move |children| {
+            children.map(move |c| {
+                let iteration_start = if is_repeat {
+                    c.time_range.start
+                } else {
+                    inherited.iteration_start
+                };
+                let inherited = Inherited {
+                    scale,
+                    iteration_start,
+                };
+                (c, inherited)
+            })
+        })
+    })
+    .filter(|(Arranged { item, .. }, _)| !item.common.phantom())
+}
+
+/// Children of `item` arranged in its outer `time_range`, in the order of
+/// the child indices. `None` for leaves and phantom elements.
+pub(crate) fn arrange_children(
+    Arranged { item, time_range }: Arranged<&ElementRef>,
+) -> Option<impl Iterator<Item = Arranged<&ElementRef>>> {
+    if item.common.phantom() {
+        return None;
+    }
+    let time_range = item.inner_time_range(time_range);
+    match &item.variant {
+        ElementVariant::Repeat(r) => Some(IterVariant::Repeat(r.arrange(time_range))),
+        ElementVariant::Stack(s) => Some(IterVariant::Stack(s.arrange(time_range))),
+        ElementVariant::Absolute(a) => Some(IterVariant::Absolute(a.arrange(time_range))),
+        ElementVariant::Grid(g) => Some(IterVariant::Grid(g.arrange(time_range))),
+        ElementVariant::ScaleAmplitude(c) => {
+            Some(IterVariant::ScaleAmplitude(c.arrange(time_range)))
+        }
+        _ => None,
+    }
+}
+
+fn pre_order_iter<T, F, I>(root: T, mut children: F) -> impl Iterator<Item = T>
+where
+    F: FnMut(T) -> Option<I>,
+    I: Iterator<Item = T>,
+    T: Clone + Copy,
+{
+    let mut stack = Vec::with_capacity(16);
+    stack.extend(children(root));
+    iter::once(root).chain(iter::from_fn(move || loop {
+        let current_iter = stack.last_mut()?;
+        match current_iter.next() {
+            Some(i) => {
+                stack.extend(children(i));
+                return Some(i);
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_order() {
+        let node_children = vec![
+            vec![1, 2, 3],
+            vec![4, 5],
+            vec![6, 7],
+            vec![],
+            vec![8, 9],
+            vec![],
+            vec![10, 11],
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        ];
+        let expected = vec![0, 1, 4, 8, 9, 5, 2, 6, 10, 11, 7, 3];
+
+        let result = pre_order_iter(0, |i| node_children.get(i).map(|c| c.iter().copied()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(result, expected);
+    }
+}
@@ -0,0 +1,140 @@
+//! Plain text timeline of arranged element trees.
+use hashbrown::HashMap;
+
+use crate::{
+    quant::{ChannelId, Time},
+    schedule::{arrange_tree, ElementRef, ElementVariant, Measure, TimeRange},
+};
+
+const IDLE: char = '.';
+const PLAY: char = '#';
+const SHIFT_PHASE: char = 'p';
+const SET_PHASE: char = 'P';
+const SHIFT_FREQ: char = 'f';
+const SET_FREQ: char = 'F';
+const SWAP_PHASE: char = 's';
+const BARRIER: char = '|';
+
+/// Render the arranged tree as one line per channel with `width` columns.
+///
+/// Plays are drawn as `#`, phase operations as `p`/`P`, frequency operations
+/// as `f`/`F`, phase swaps as `s`, and barriers as `|`. Channels are ordered
+/// by first appearance. The last line shows the time axis.
+pub(crate) fn render_ascii(root: &ElementRef, width: usize) -> String {
+    let width = width.max(1);
+    let total = root.measure();
+    let time_range = TimeRange {
+        start: Time::ZERO,
+        span: total,
+    };
+    let scale = |t: Time| {
+        if total > Time::ZERO {
+            t.value() / total.value() * width as f64
+        } else {
+            0.0
+        }
+    };
+    let mut names: Vec<&ChannelId> = Vec::new();
+    let mut rows: HashMap<&ChannelId, Vec<char>> = HashMap::new();
+    let mut global_barriers = Vec::new();
+    let mut marks = Vec::new();
+    for arranged in arrange_tree(root, time_range) {
+        let TimeRange { start, span } = arranged.item.inner_time_range(arranged.time_range);
+        let variant = &arranged.item.variant;
+        let symbol = match variant {
+            ElementVariant::Play(_) | ElementVariant::PlaySamples(_) => PLAY,
+            ElementVariant::ShiftPhase(_) => SHIFT_PHASE,
+            ElementVariant::SetPhase(_) => SET_PHASE,
+            ElementVariant::ShiftFreq(_) => SHIFT_FREQ,
+            ElementVariant::SetFreq(_) => SET_FREQ,
+            ElementVariant::SwapPhase(_) => SWAP_PHASE,
+            ElementVariant::Barrier(_) if variant.channels().is_empty() => {
+                global_barriers.push(start);
+                continue;
+            }
+            ElementVariant::Barrier(_) => BARRIER,
+            _ => continue,
+        };
+        for channel in variant.channels() {
+            rows.entry(channel).or_insert_with(|| {
+                names.push(channel);
+                vec![IDLE; width]
+            });
+            marks.push((channel, symbol, start, span));
+        }
+    }
+    // Barriers without channels apply to all channels in the schedule.
+    for &start in &global_barriers {
+        for &channel in &names {
+            marks.push((channel, BARRIER, start, Time::ZERO));
+        }
+    }
+    // Later marks overwrite earlier ones, so draw plays first and phase or
+    // frequency operations last.
+    marks.sort_by_key(|&(_, symbol, _, _)| match symbol {
+        PLAY => 0,
+        BARRIER => 1,
+        _ => 2,
+    });
+    for (channel, symbol, start, span) in marks {
+        let first = (scale(start) as usize).min(width - 1);
+        let last = (scale(start + span).ceil() as usize)
+            .saturating_sub(1)
+            .clamp(first, width - 1);
+        rows.get_mut(channel).expect("Row should be created")[first..=last].fill(symbol);
+    }
+    let label_width = names.iter().map(|n| n.as_ref().len()).max().unwrap_or(0);
+    let mut lines: Vec<String> = names
+        .iter()
+        .map(|n| {
+            let row: String = rows[n].iter().collect();
+            format!("{:<label_width$} {}", n.as_ref(), row)
+        })
+        .collect();
+    let end = total.value().to_string();
+    lines.push(format!(
+        "{:label_width$} 0{:>end_width$}",
+        "",
+        end,
+        end_width = (width - 1).max(end.len())
+    ));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        quant::{Amplitude, Phase},
+        schedule::{Barrier, Element, ElementCommonBuilder, Play, ShiftPhase, Stack},
+    };
+
+    #[test]
+    fn timeline() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = |ch: &str| {
+            let play = Play::new(
+                ChannelId::new(ch),
+                None,
+                Amplitude::new(1.0).unwrap(),
+                Time::new(10.0).unwrap(),
+            )
+            .unwrap();
+            Arc::new(Element::new(common.clone(), play))
+        };
+        let shift = ShiftPhase::new(ChannelId::new("q1"), Phase::new(0.25).unwrap()).unwrap();
+        let shift = Arc::new(Element::new(common.clone(), shift));
+        let barrier = Arc::new(Element::new(common.clone(), Barrier::new(vec![])));
+        let root = Stack::new().with_children(vec![play("q0"), barrier, shift, play("q1")]);
+        let root = Arc::new(Element::new(common.clone(), root));
+
+        let result = render_ascii(&root, 8);
+
+        assert_eq!(
+            result,
+            ["q0 ####|...", "q1 ....p###", "   0     20"].join("\n")
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Content-addressed cache of element subtrees.
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+
+use crate::schedule::{Barrier, Element, ElementCommonBuilder, ElementRef, ElementVariant, Result};
+
+/// Cache of element subtrees keyed by their content.
+///
+/// Interning a tree replaces every subtree that is equal to a cached one by the
+/// cached element. Layout containers keep their measurement with the element,
+/// so equal gate blocks are measured once and the result is reused by every
+/// parent, also across schedules interned into the same cache.
+#[derive(Debug)]
+pub(crate) struct ElementCache {
+    ids: HashMap<Vec<u8>, usize>,
+    elements: Vec<ElementRef>,
+    /// Stand-in for the children in the key of a container.
+    placeholder: ElementRef,
+}
+
+impl ElementCache {
+    pub(crate) fn new() -> Self {
+        let common = ElementCommonBuilder::new()
+            .build()
+            .expect("Default common should be valid");
+        Self {
+            ids: HashMap::new(),
+            elements: Vec::new(),
+            placeholder: Arc::new(Element::new(common, Barrier::new(vec![]))),
+        }
+    }
+
+    /// Number of distinct subtrees in the cache.
+    pub(crate) fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Copy of the tree with equal subtrees shared with the cache.
+    pub(crate) fn intern(&mut self, root: &ElementRef) -> Result<ElementRef> {
+        let mut visited = HashMap::new();
+        let id = self.intern_id(root, &mut visited)?;
+        Ok(self.elements[id].clone())
+    }
+
+    fn intern_id(
+        &mut self,
+        element: &ElementRef,
+        visited: &mut HashMap<*const Element, usize>,
+    ) -> Result<usize> {
+        if let Some(&id) = visited.get(&Arc::as_ptr(element)) {
+            return Ok(id);
+        }
+        let child_ids = children(element)
+            .into_iter()
+            .map(|c| self.intern_id(c, visited))
+            .collect::<Result<Vec<_>>>()?;
+        // The key of a container is its own content with the children replaced
+        // by their ids, so computing all keys is linear in the tree size.
+        let shallow = self.with_children(element, |_| self.placeholder.clone())?;
+        let mut key = shallow.to_msgpack(None)?;
+        for id in &child_ids {
+            key.extend_from_slice(&id.to_le_bytes());
+        }
+        let id = match self.ids.get(&key) {
+            Some(&id) => id,
+            None => {
+                let mut canonical = child_ids.iter().map(|&id| self.elements[id].clone());
+                let unchanged = children(element)
+                    .into_iter()
+                    .zip(canonical.clone())
+                    .all(|(c, canonical)| Arc::ptr_eq(c, &canonical));
+                let element = if unchanged {
+                    element.clone()
+                } else {
+                    Arc::new(self.with_children(element, |_| {
+                        canonical.next().expect("Should have a canonical child")
+                    })?)
+                };
+                self.elements.push(element);
+                self.ids.insert(key, self.elements.len() - 1);
+                self.elements.len() - 1
+            }
+        };
+        visited.insert(Arc::as_ptr(element), id);
+        Ok(id)
+    }
+
+    /// Copy of `element` with its children replaced by `f`.
+    fn with_children(
+        &self,
+        element: &ElementRef,
+        mut f: impl FnMut(&ElementRef) -> ElementRef,
+    ) -> Result<Element> {
+        let variant: ElementVariant = match &element.variant {
+            ElementVariant::Stack(v) => v.map_children(f).into(),
+            ElementVariant::Absolute(v) => v.map_children(f)?.into(),
+            ElementVariant::Grid(v) => v.map_children(f).into(),
+            ElementVariant::Repeat(v) => v
+                .with_child(0, f(v.child()))
+                .expect("Should have a child")
+                .into(),
+            ElementVariant::ScaleAmplitude(v) => v
+                .with_child(0, f(v.child()))
+                .expect("Should have a child")
+                .into(),
+            v => v.clone(),
+        };
+        Ok(Element::new(element.common.clone(), variant))
+    }
+}
+
+impl Default for ElementCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn children(element: &ElementRef) -> Vec<&ElementRef> {
+    match &element.variant {
+        ElementVariant::Stack(v) => v.children().iter().collect(),
+        ElementVariant::Absolute(v) => v.children().iter().map(|e| e.element()).collect(),
+        ElementVariant::Grid(v) => v.children().iter().map(|e| e.element()).collect(),
+        ElementVariant::Repeat(v) => vec![v.child()],
+        ElementVariant::ScaleAmplitude(v) => vec![v.child()],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::assert_approx_eq;
+
+    use super::*;
+    use crate::{
+        quant::{Amplitude, ChannelId, Time},
+        schedule::{Measure, Play, Stack},
+    };
+
+    fn block(amplitude: f64) -> ElementRef {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = |channel| {
+            let play = Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(amplitude).unwrap(),
+                Time::new(10e-9).unwrap(),
+            )
+            .unwrap();
+            Arc::new(Element::new(common.clone(), play))
+        };
+        let stack = Stack::new().with_children(vec![play("q0"), play("q1")]);
+        Arc::new(Element::new(common.clone(), stack))
+    }
+
+    #[test]
+    fn intern() {
+        let mut cache = ElementCache::new();
+        let a = cache.intern(&block(0.5)).unwrap();
+        assert_eq!(cache.len(), 3);
+        let b = cache.intern(&block(0.5)).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(cache.len(), 3);
+        let c = cache.intern(&block(0.25)).unwrap();
+        assert!(!Arc::ptr_eq(&a, &c));
+
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let root = Arc::new(Element::new(
+            common,
+            Stack::new().with_children(vec![block(0.5), block(0.25), block(0.5)]),
+        ));
+        let root = cache.intern(&root).unwrap();
+        let ElementVariant::Stack(stack) = &root.variant else {
+            unreachable!()
+        };
+        let children = stack.children();
+        assert!(Arc::ptr_eq(&children[0], &a));
+        assert!(Arc::ptr_eq(&children[1], &c));
+        assert!(Arc::ptr_eq(&children[2], &a));
+        assert_approx_eq!(f64, root.measure().value(), 30e-9);
+    }
+}
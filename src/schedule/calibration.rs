@@ -0,0 +1,264 @@
+//! Resolution of symbolic calibration keys of pulses.
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+
+use crate::{
+    quant::{Amplitude, ChannelId, Frequency},
+    schedule::{finite_value, Element, ElementRef, ElementVariant, Play, Result, ScheduleError},
+};
+
+/// Calibrated parameters of a gate on a channel.
+///
+/// Parameters that are `None` are left as written in the schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Calibration {
+    amplitude: Option<Amplitude>,
+    drag_coef: Option<f64>,
+    frequency: Option<Frequency>,
+    timestamp: f64,
+}
+
+/// Calibrations keyed by channel and gate name.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Calibrations {
+    entries: HashMap<(ChannelId, String), Calibration>,
+}
+
+impl Calibration {
+    /// `timestamp` is the time of the calibration in seconds since the Unix
+    /// epoch.
+    pub(crate) fn new(timestamp: f64) -> Result<Self> {
+        Ok(Self {
+            timestamp: finite_value("timestamp", timestamp)?,
+            ..Self::default()
+        })
+    }
+
+    pub(crate) fn with_amplitude(mut self, amplitude: Option<Amplitude>) -> Result<Self> {
+        self.amplitude = amplitude
+            .map(|a| finite_value("amplitude", a))
+            .transpose()?;
+        Ok(self)
+    }
+
+    pub(crate) fn with_drag_coef(mut self, drag_coef: Option<f64>) -> Result<Self> {
+        self.drag_coef = drag_coef
+            .map(|d| finite_value("drag_coef", d))
+            .transpose()?;
+        Ok(self)
+    }
+
+    pub(crate) fn with_frequency(mut self, frequency: Option<Frequency>) -> Result<Self> {
+        self.frequency = frequency
+            .map(|f| finite_value("frequency", f))
+            .transpose()?;
+        Ok(self)
+    }
+
+    pub(crate) fn amplitude(&self) -> Option<Amplitude> {
+        self.amplitude
+    }
+
+    pub(crate) fn drag_coef(&self) -> Option<f64> {
+        self.drag_coef
+    }
+
+    pub(crate) fn frequency(&self) -> Option<Frequency> {
+        self.frequency
+    }
+
+    pub(crate) fn timestamp(&self) -> f64 {
+        self.timestamp
+    }
+
+    fn apply(&self, play: &Play) -> Result<Play> {
+        let mut play = play.clone();
+        if let Some(amplitude) = self.amplitude {
+            play = play.with_amplitude(amplitude)?;
+        }
+        if let Some(drag_coef) = self.drag_coef {
+            play = play.with_drag_coef(drag_coef)?;
+        }
+        if let Some(frequency) = self.frequency {
+            play = play.with_frequency(frequency)?;
+        }
+        Ok(play)
+    }
+}
+
+impl Calibrations {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a calibration unless a newer one of the same key is stored.
+    pub(crate) fn insert(&mut self, channel: ChannelId, gate: String, calibration: Calibration) {
+        let entry = self.entries.entry((channel, gate)).or_insert(calibration);
+        if calibration.timestamp >= entry.timestamp {
+            *entry = calibration;
+        }
+    }
+
+    pub(crate) fn get(&self, channel: ChannelId, gate: &str) -> Option<&Calibration> {
+        self.entries.get(&(channel, gate.to_string()))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (ChannelId, &str, &Calibration)> {
+        self.entries
+            .iter()
+            .map(|((channel, gate), calibration)| (*channel, gate.as_str(), calibration))
+    }
+}
+
+/// Copy of the tree with the parameters of pulses with a calibration key
+/// replaced by the stored calibrations.
+///
+/// Sub-elements shared by multiple parents stay shared in the copy. The keys
+/// are kept so that the tree can be resolved again with newer calibrations.
+pub(crate) fn resolve_calibrations(
+    root: &ElementRef,
+    calibrations: &Calibrations,
+) -> Result<ElementRef> {
+    let mut resolver = Resolver {
+        calibrations,
+        visited: HashMap::new(),
+        error: None,
+    };
+    let root = resolver.resolve(root);
+    match resolver.error {
+        Some(e) => Err(e),
+        None => Ok(root),
+    }
+}
+
+struct Resolver<'a> {
+    calibrations: &'a Calibrations,
+    visited: HashMap<*const Element, ElementRef>,
+    error: Option<ScheduleError>,
+}
+
+impl Resolver<'_> {
+    fn resolve(&mut self, element: &ElementRef) -> ElementRef {
+        if let Some(resolved) = self.visited.get(&Arc::as_ptr(element)) {
+            return resolved.clone();
+        }
+        let variant: ElementVariant = match &element.variant {
+            ElementVariant::Play(v) => match self.resolve_play(v) {
+                Ok(play) => play.into(),
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                    return element.clone();
+                }
+            },
+            ElementVariant::Stack(v) => v.map_children(|c| self.resolve(c)).into(),
+            ElementVariant::Absolute(v) => match v.map_children(|c| self.resolve(c)) {
+                Ok(v) => v.into(),
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                    return element.clone();
+                }
+            },
+            ElementVariant::Grid(v) => v.map_children(|c| self.resolve(c)).into(),
+            ElementVariant::Repeat(v) => v
+                .with_child(0, self.resolve(v.child()))
+                .expect("Should have a child")
+                .into(),
+            ElementVariant::ScaleAmplitude(v) => v
+                .with_child(0, self.resolve(v.child()))
+                .expect("Should have a child")
+                .into(),
+            _ => return element.clone(),
+        };
+        let resolved = Arc::new(Element::new(element.common.clone(), variant));
+        self.visited.insert(Arc::as_ptr(element), resolved.clone());
+        resolved
+    }
+
+    fn resolve_play(&self, play: &Play) -> Result<Play> {
+        let Some(gate) = play.calibration() else {
+            return Ok(play.clone());
+        };
+        let channel = *play.channel_id();
+        self.calibrations
+            .get(channel, gate)
+            .ok_or_else(|| ScheduleError::UnknownCalibration {
+                channel: channel.to_string(),
+                gate: gate.to_string(),
+            })?
+            .apply(play)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        quant::Time,
+        schedule::{ElementCommonBuilder, Repeat, Stack},
+    };
+
+    fn play(gate: Option<&str>) -> ElementRef {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = Play::new(
+            ChannelId::new("q0"),
+            None,
+            Amplitude::new(0.1).unwrap(),
+            Time::new(10e-9).unwrap(),
+        )
+        .unwrap()
+        .with_calibration(gate.map(Into::into));
+        Arc::new(Element::new(common, play))
+    }
+
+    fn calibration(amplitude: f64, timestamp: f64) -> Calibration {
+        Calibration::new(timestamp)
+            .unwrap()
+            .with_amplitude(Some(Amplitude::new(amplitude).unwrap()))
+            .unwrap()
+    }
+
+    #[test]
+    fn resolve() {
+        let mut calibrations = Calibrations::new();
+        calibrations.insert(ChannelId::new("q0"), "x".into(), calibration(0.5, 2.0));
+        calibrations.insert(ChannelId::new("q0"), "x".into(), calibration(0.3, 1.0));
+        let drag = Calibration::new(0.0)
+            .unwrap()
+            .with_drag_coef(Some(1e-9))
+            .unwrap();
+        calibrations.insert(ChannelId::new("q0"), "y".into(), drag);
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let shared = play(Some("x"));
+        let repeat = Arc::new(Element::new(common.clone(), Repeat::new(shared.clone(), 2)));
+        let stack = Stack::new().with_children(vec![shared, repeat, play(Some("y")), play(None)]);
+        let root = Arc::new(Element::new(common, stack));
+
+        let resolved = resolve_calibrations(&root, &calibrations).unwrap();
+
+        let ElementVariant::Stack(stack) = &resolved.variant else {
+            unreachable!()
+        };
+        let plays: Vec<_> = stack
+            .children()
+            .iter()
+            .filter_map(|c| match &c.variant {
+                ElementVariant::Play(v) => Some((v.amplitude().value(), v.drag_coef())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(plays, [(0.5, 0.0), (0.1, 1e-9), (0.1, 0.0)]);
+        let ElementVariant::Repeat(repeat) = &stack.children()[1].variant else {
+            unreachable!()
+        };
+        assert!(Arc::ptr_eq(repeat.child(), &stack.children()[0]));
+
+        assert_eq!(
+            resolve_calibrations(&play(Some("z")), &calibrations).unwrap_err(),
+            ScheduleError::UnknownCalibration {
+                channel: "q0".into(),
+                gate: "z".into()
+            }
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Channel sets of elements shared through a process wide registry.
+//!
+//! Containers store the union of the channels of their children. In large
+//! schedules most containers span the same few sets of channels, so equal sets
+//! are interned and every element holds a pointer to the shared slice. Sets
+//! that are no longer used by any element are dropped from the registry when
+//! it grows.
+use std::{
+    ops::Deref,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+};
+
+use hashbrown::HashSet;
+use smallvec::SmallVec;
+
+use crate::quant::ChannelId;
+
+/// Interned channels of an element. Empty sets are stored without a pointer.
+#[derive(Clone, Default)]
+pub(crate) struct ChannelSet(Option<Arc<[ChannelId]>>);
+
+#[derive(Debug, Default)]
+struct Registry {
+    sets: HashSet<Arc<[ChannelId]>>,
+    /// Number of sets after the last cleanup.
+    live: usize,
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+impl ChannelSet {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intern the channels in the given order.
+    pub(crate) fn from_slice(ids: &[ChannelId]) -> Self {
+        if ids.is_empty() {
+            return Self::new();
+        }
+        let mut registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(set) = registry.sets.get(ids) {
+            return Self(Some(set.clone()));
+        }
+        if registry.sets.len() >= 2 * registry.live.max(64) {
+            registry.sets.retain(|s| Arc::strong_count(s) > 1);
+            registry.live = registry.sets.len();
+        }
+        let set: Arc<[ChannelId]> = ids.into();
+        registry.sets.insert(set.clone());
+        Self(Some(set))
+    }
+
+    /// Intern the union of the channels, sorted by id.
+    pub(crate) fn union<I>(ids: I) -> Self
+    where
+        I: IntoIterator<Item = ChannelId>,
+    {
+        let mut ids: SmallVec<[ChannelId; 8]> = ids.into_iter().collect();
+        ids.sort_unstable();
+        ids.dedup();
+        Self::from_slice(&ids)
+    }
+}
+
+impl Deref for ChannelSet {
+    type Target = [ChannelId];
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_deref().unwrap_or_default()
+    }
+}
+
+impl PartialEq for ChannelSet {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ChannelSet {}
+
+impl std::fmt::Debug for ChannelSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared() {
+        let a = ChannelId::new("channel_set_a");
+        let b = ChannelId::new("channel_set_b");
+        let x = ChannelSet::union([b, a, b]);
+        let y = ChannelSet::union([a, b]);
+        assert_eq!(&*x, &[a, b]);
+        assert_eq!(x, y);
+        assert!(Arc::ptr_eq(x.0.as_ref().unwrap(), y.0.as_ref().unwrap()));
+        assert_ne!(x, ChannelSet::from_slice(&[b, a]));
+        assert!(ChannelSet::union([]).is_empty());
+    }
+}
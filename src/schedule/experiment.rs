@@ -0,0 +1,118 @@
+//! Repeated shots of a schedule.
+use crate::{
+    quant::Time,
+    schedule::{non_negative_time, Element, ElementCommonBuilder, ElementRef, Repeat, Result},
+};
+
+/// Shots of a schedule separated by a relaxation delay.
+///
+/// The experiment is lowered to a [`Repeat`], so backends that keep loops
+/// emit a loop over the shots and other backends unroll it.
+#[derive(Debug, Clone)]
+pub(crate) struct Experiment {
+    schedule: ElementRef,
+    shots: usize,
+    relaxation: Time,
+    shots_var: Option<String>,
+}
+
+impl Experiment {
+    pub(crate) fn new(schedule: ElementRef, shots: usize) -> Self {
+        Self {
+            schedule,
+            shots,
+            relaxation: Time::ZERO,
+            shots_var: None,
+        }
+    }
+
+    /// Delay after every shot, including the last one, so that consecutive
+    /// runs of the experiment are separated by it as well.
+    pub(crate) fn with_relaxation(mut self, relaxation: Time) -> Result<Self> {
+        self.relaxation = non_negative_time("relaxation", relaxation)?;
+        Ok(self)
+    }
+
+    /// Bind the number of shots to a runtime variable, see
+    /// [`Repeat::with_count_var`].
+    pub(crate) fn with_shots_var(mut self, shots_var: Option<String>) -> Self {
+        self.shots_var = shots_var;
+        self
+    }
+
+    pub(crate) fn shots(&self) -> usize {
+        self.shots
+    }
+
+    pub(crate) fn relaxation(&self) -> Time {
+        self.relaxation
+    }
+
+    pub(crate) fn shots_var(&self) -> Option<&str> {
+        self.shots_var.as_deref()
+    }
+
+    /// Repeat of the schedule with one iteration per shot.
+    ///
+    /// The relaxation is the spacing between the iterations and the end
+    /// margin of the repeat, so every shot takes the duration of the
+    /// schedule plus the relaxation.
+    pub(crate) fn to_element(&self) -> Result<Element> {
+        let repeat = Repeat::new(self.schedule.clone(), self.shots)
+            .with_count_var(self.shots_var.clone())?
+            .with_spacing(self.relaxation)?;
+        let common = ElementCommonBuilder::new()
+            .margin((Time::ZERO, self.relaxation))
+            .build()?;
+        Ok(Element::new(common, repeat))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use float_cmp::assert_approx_eq;
+
+    use super::*;
+    use crate::{
+        quant::{Amplitude, ChannelId},
+        schedule::{flatten, flatten_loops, Measure, Play, ScheduleError, Segment},
+    };
+
+    #[test]
+    fn shots() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = Play::new(
+            ChannelId::new("q0"),
+            None,
+            Amplitude::new(0.5).unwrap(),
+            Time::new(10.0).unwrap(),
+        )
+        .unwrap();
+        let schedule = Arc::new(Element::new(common, play));
+        let experiment = Experiment::new(schedule, 3)
+            .with_relaxation(Time::new(90.0).unwrap())
+            .unwrap();
+
+        let element = Arc::new(experiment.to_element().unwrap());
+
+        assert_approx_eq!(f64, element.measure().value(), 300.0);
+        let times: Vec<_> = flatten(&element).iter().map(|i| i.time.value()).collect();
+        assert_eq!(times, [0.0, 100.0, 200.0]);
+        let [Segment::Loop { count, period, .. }] = &flatten_loops(&element).unwrap()[..] else {
+            panic!("Expected a single loop");
+        };
+        assert_eq!(*count, 3);
+        assert_approx_eq!(f64, period.value(), 100.0);
+
+        let invalid = Experiment::new(element, 1).with_relaxation(Time::new(-1.0).unwrap());
+        assert!(matches!(
+            invalid,
+            Err(ScheduleError::InvalidTime {
+                property: "relaxation",
+                ..
+            })
+        ));
+    }
+}
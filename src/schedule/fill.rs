@@ -0,0 +1,119 @@
+//! Filling of idle intervals of channels.
+use std::sync::Arc;
+
+use crate::{
+    quant::{ChannelId, Time},
+    schedule::{
+        flatten,
+        timing::{busy_intervals, idle_gaps},
+        Absolute, AbsoluteEntry, Element, ElementCommonBuilder, ElementRef, Measure, Play, Result,
+    },
+};
+
+/// Fill the idle intervals of `channels` with copies of `fill`.
+///
+/// An interval of a channel is idle if no pulse is played on the channel, from
+/// the start to the end of the arranged schedule. Each idle interval longer
+/// than `min_gap` and the width of `fill` gets a copy of `fill` on the channel
+/// with the plateau stretched to the interval. The copies are played with the
+/// frequency and phase of the channel after the schedule, so this is meant for
+/// baseband levels such as flux parking values.
+///
+/// The result is an [`Absolute`] with the original schedule at time zero
+/// followed by the fill pulses.
+pub(crate) fn fill_idle(
+    root: &ElementRef,
+    channels: &[ChannelId],
+    fill: &Play,
+    min_gap: Time,
+) -> Result<ElementRef> {
+    let end = root.measure();
+    let instructions = flatten(root);
+    let common = ElementCommonBuilder::new().build()?;
+    let mut children = vec![AbsoluteEntry::new(root.clone())];
+    for &channel in channels {
+        let busy = busy_intervals(&instructions, channel);
+        let gaps = idle_gaps(&busy, end);
+        for (start, stop) in gaps {
+            let gap = stop - start;
+            if gap <= min_gap || gap < fill.edge_width() {
+                continue;
+            }
+            let play = fill
+                .map_channels(|_| channel)
+                .with_flexible(false)
+                .with_plateau(gap - fill.edge_width())?;
+            let element = Arc::new(Element::new(common.clone(), play));
+            children.push(AbsoluteEntry::new(element).with_time(start)?);
+        }
+    }
+    Ok(Arc::new(Element::new(
+        common,
+        Absolute::new().with_children(children),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        quant::{Amplitude, ShapeId},
+        schedule::{ElementVariant, Instruction, InstructionKind},
+    };
+
+    fn play(channel: &str, width: f64) -> ElementRef {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = Play::new(
+            ChannelId::new(channel),
+            Some(ShapeId::new("hann")),
+            Amplitude::new(0.5).unwrap(),
+            Time::new(width).unwrap(),
+        )
+        .unwrap();
+        Arc::new(Element::new(common, play))
+    }
+
+    #[test]
+    fn fill_gaps() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        // z: idle 0..30, busy 30..40. xy: busy 0..30, idle 30..40.
+        let absolute = Absolute::new().with_children(vec![
+            AbsoluteEntry::new(play("xy", 30.0)),
+            AbsoluteEntry::new(play("z", 10.0))
+                .with_time(Time::new(30.0).unwrap())
+                .unwrap(),
+        ]);
+        let root = Arc::new(Element::new(common, absolute));
+        let level = Play::new(
+            ChannelId::new("unused"),
+            None,
+            Amplitude::new(0.1).unwrap(),
+            Time::ZERO,
+        )
+        .unwrap();
+        let channels = [ChannelId::new("z"), ChannelId::new("xy")];
+        let filled = fill_idle(&root, &channels, &level, Time::ZERO).unwrap();
+
+        assert_eq!(filled.measure(), root.measure());
+        let ElementVariant::Absolute(absolute) = &filled.variant else {
+            unreachable!()
+        };
+        assert!(Arc::ptr_eq(absolute.children()[0].element(), &root));
+        let fills: Vec<_> = flatten(&filled)
+            .into_iter()
+            .filter_map(|Instruction { time, kind, .. }| match kind {
+                InstructionKind::Play {
+                    channel_id,
+                    shape_id: None,
+                    plateau,
+                    ..
+                } => Some((channel_id.to_string(), time.value(), plateau.value())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            fills,
+            [("z".to_string(), 0.0, 30.0), ("xy".to_string(), 30.0, 10.0)]
+        );
+    }
+}
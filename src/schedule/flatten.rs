@@ -0,0 +1,416 @@
+//! Flattening of element trees into timed instructions.
+use std::sync::Arc;
+
+use num::complex::Complex64;
+
+#[cfg(feature = "python")]
+use crate::schedule::{arrange::arrange_children, Result, ScheduleError};
+use crate::{
+    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    schedule::{
+        arrange::arrange_tree_scaled, Arranged, ElementRef, ElementVariant, FallingEdge, Measure,
+        PhaseReference, TimeRange,
+    },
+};
+
+/// A leaf element placed on the absolute time axis.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Instruction {
+    pub(crate) time: Time,
+    pub(crate) duration: Time,
+    pub(crate) kind: InstructionKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum InstructionKind {
+    Play {
+        channel_id: ChannelId,
+        shape_id: Option<ShapeId>,
+        amplitude: Amplitude,
+        width: Time,
+        plateau: Time,
+        drag_coef: f64,
+        frequency: Frequency,
+        phase: Phase,
+        phase_reference: PhaseReference,
+        /// Falling edge of asymmetric pulses, which last
+        /// `(width + fall.width) / 2 + plateau`.
+        fall: Option<FallingEdge>,
+    },
+    PlaySamples {
+        channel_id: ChannelId,
+        samples: Arc<[Complex64]>,
+        sample_rate: Frequency,
+        amplitude: Amplitude,
+        frequency: Frequency,
+        phase: Phase,
+        interpolate: bool,
+    },
+    ShiftPhase {
+        channel_id: ChannelId,
+        phase: Phase,
+    },
+    SetPhase {
+        channel_id: ChannelId,
+        phase: Phase,
+    },
+    ShiftFreq {
+        channel_id: ChannelId,
+        frequency: Frequency,
+    },
+    SetFreq {
+        channel_id: ChannelId,
+        frequency: Frequency,
+    },
+    SwapPhase {
+        channel_id1: ChannelId,
+        channel_id2: ChannelId,
+    },
+}
+
+#[cfg(feature = "python")]
+/// An instruction or a loop of instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Segment {
+    Instruction(Instruction),
+    Loop {
+        time: Time,
+        /// Maximum number of iterations if `count_var` is set.
+        count: usize,
+        /// Name of the runtime variable holding the number of iterations.
+        count_var: Option<String>,
+        /// Time between the starts of consecutive iterations.
+        period: Time,
+        /// Segments of one iteration with times relative to its start.
+        body: Vec<Segment>,
+    },
+}
+
+impl InstructionKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            InstructionKind::Play { .. } => "Play",
+            InstructionKind::PlaySamples { .. } => "PlaySamples",
+            InstructionKind::ShiftPhase { .. } => "ShiftPhase",
+            InstructionKind::SetPhase { .. } => "SetPhase",
+            InstructionKind::ShiftFreq { .. } => "ShiftFreq",
+            InstructionKind::SetFreq { .. } => "SetFreq",
+            InstructionKind::SwapPhase { .. } => "SwapPhase",
+        }
+    }
+}
+
+/// Flatten the element tree into instructions ordered by tree traversal.
+///
+/// Phantom elements and barriers are skipped. The plateau of flexible pulses
+/// is resolved from the arranged duration, and the amplitude of pulses
+/// includes the factors of their `ScaleAmplitude` ancestors. Repeats with a
+/// count variable are unrolled to their maximum count.
+pub(crate) fn flatten(root: &ElementRef) -> Vec<Instruction> {
+    let time_range = TimeRange {
+        start: Time::ZERO,
+        span: root.measure(),
+    };
+    arrange_tree_scaled(root, time_range)
+        .filter_map(|(arranged, scale)| instruction(arranged, scale))
+        .collect()
+}
+
+#[cfg(feature = "python")]
+/// Same as [`flatten`], but [`Repeat`](crate::schedule::Repeat) elements with
+/// more than one iteration or with a count variable are kept as
+/// [`Segment::Loop`].
+///
+/// The body of a loop is flattened once with times relative to the start of
+/// an iteration. The body has to be loop-invariant, i.e. every iteration
+/// behaves the same apart from the start time. Frequency shifts accumulate
+/// across iterations, so bodies with [`InstructionKind::ShiftFreq`] are
+/// rejected.
+pub(crate) fn flatten_loops(root: &ElementRef) -> Result<Vec<Segment>> {
+    let time_range = TimeRange {
+        start: Time::ZERO,
+        span: root.measure(),
+    };
+    let mut segments = Vec::new();
+    flatten_loops_into(
+        Arranged {
+            item: root,
+            time_range,
+        },
+        1.0,
+        &mut segments,
+    )?;
+    Ok(segments)
+}
+
+#[cfg(feature = "python")]
+fn flatten_loops_into(
+    arranged: Arranged<&ElementRef>,
+    scale: f64,
+    segments: &mut Vec<Segment>,
+) -> Result<()> {
+    let Arranged { item, time_range } = arranged;
+    if item.common.phantom() {
+        return Ok(());
+    }
+    if let ElementVariant::Repeat(r) = &item.variant {
+        if r.count() > 1 || r.count_var().is_some() {
+            let child_duration = r.child().measure();
+            let child = Arranged {
+                item: r.child(),
+                time_range: TimeRange {
+                    start: Time::ZERO,
+                    span: child_duration,
+                },
+            };
+            let mut body = Vec::new();
+            flatten_loops_into(child, scale, &mut body)?;
+            check_loop_invariant(&body)?;
+            segments.push(Segment::Loop {
+                time: item.inner_time_range(time_range).start,
+                count: r.count(),
+                count_var: r.count_var().map(Into::into),
+                period: child_duration + r.spacing(),
+                body,
+            });
+            return Ok(());
+        }
+    }
+    segments.extend(instruction(arranged, scale).map(Segment::Instruction));
+    let scale = match &item.variant {
+        ElementVariant::ScaleAmplitude(v) => scale * v.factor(),
+        _ => scale,
+    };
+    for child in arrange_children(arranged).into_iter().flatten() {
+        flatten_loops_into(child, scale, segments)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "python")]
+fn check_loop_invariant(body: &[Segment]) -> Result<()> {
+    for segment in body {
+        match segment {
+            Segment::Instruction(Instruction {
+                kind: InstructionKind::ShiftFreq { channel_id, .. },
+                ..
+            }) => {
+                return Err(ScheduleError::LoopVariant {
+                    channel: channel_id.to_string(),
+                })
+            }
+            Segment::Loop { body, .. } => check_loop_invariant(body)?,
+            Segment::Instruction(_) => (),
+        }
+    }
+    Ok(())
+}
+
+fn instruction(
+    Arranged { item, time_range }: Arranged<&ElementRef>,
+    scale: f64,
+) -> Option<Instruction> {
+    let TimeRange { start, span } = item.inner_time_range(time_range);
+    let kind = match &item.variant {
+        ElementVariant::Play(v) => InstructionKind::Play {
+            channel_id: *v.channel_id(),
+            shape_id: v.shape_id().cloned(),
+            amplitude: v.amplitude() * scale,
+            width: v.width(),
+            plateau: if v.flexible() {
+                span - v.edge_width()
+            } else {
+                v.plateau()
+            },
+            drag_coef: v.drag_coef(),
+            frequency: v.frequency(),
+            phase: v.phase(),
+            phase_reference: v.phase_reference(),
+            fall: v.fall().cloned(),
+        },
+        ElementVariant::PlaySamples(v) => InstructionKind::PlaySamples {
+            channel_id: *v.channel_id(),
+            samples: v.samples().clone(),
+            sample_rate: v.sample_rate(),
+            amplitude: v.amplitude() * scale,
+            frequency: v.frequency(),
+            phase: v.phase(),
+            interpolate: v.interpolate(),
+        },
+        ElementVariant::ShiftPhase(v) => InstructionKind::ShiftPhase {
+            channel_id: *v.channel_id(),
+            phase: v.phase(),
+        },
+        ElementVariant::SetPhase(v) => InstructionKind::SetPhase {
+            channel_id: *v.channel_id(),
+            phase: v.phase(),
+        },
+        ElementVariant::ShiftFreq(v) => InstructionKind::ShiftFreq {
+            channel_id: *v.channel_id(),
+            frequency: v.frequency(),
+        },
+        ElementVariant::SetFreq(v) => InstructionKind::SetFreq {
+            channel_id: *v.channel_id(),
+            frequency: v.frequency(),
+        },
+        ElementVariant::SwapPhase(v) => InstructionKind::SwapPhase {
+            channel_id1: *v.channel_id1(),
+            channel_id2: *v.channel_id2(),
+        },
+        _ => return None,
+    };
+    Some(Instruction {
+        time: start,
+        duration: span,
+        kind,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    #[cfg(feature = "python")]
+    use crate::schedule::ShiftFreq;
+    use crate::schedule::{
+        Element, ElementCommonBuilder, Play, Repeat, ScaleAmplitude, ShiftPhase, Stack,
+    };
+
+    #[test]
+    fn flatten_repeat() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let width = Time::new(10.0).unwrap();
+        let play = Play::new(
+            ChannelId::new("q0"),
+            None,
+            Amplitude::new(1.0).unwrap(),
+            width,
+        )
+        .unwrap()
+        .with_flexible(true);
+        let play = Arc::new(Element::new(common.clone(), play));
+        let shift = ShiftPhase::new(ChannelId::new("q0"), Phase::new(0.25).unwrap()).unwrap();
+        let shift = Arc::new(Element::new(common.clone(), shift));
+        let repeat = Repeat::new(play, 2)
+            .with_spacing(Time::new(5.0).unwrap())
+            .unwrap();
+        let repeat = Arc::new(Element::new(common.clone(), repeat));
+        let root = Stack::new().with_children(vec![repeat, shift]);
+        let root = Arc::new(Element::new(
+            ElementCommonBuilder::new()
+                .duration(Some(Time::new(40.0).unwrap()))
+                .build()
+                .unwrap(),
+            root,
+        ));
+
+        let result = flatten(&root);
+
+        let times: Vec<_> = result.iter().map(|i| i.time.value()).collect();
+        let names: Vec<_> = result.iter().map(|i| i.kind.name()).collect();
+        assert_eq!(times, vec![15.0, 30.0, 40.0]);
+        assert_eq!(names, vec!["Play", "Play", "ShiftPhase"]);
+        assert_eq!(result[0].duration, width);
+        let InstructionKind::Play { plateau, .. } = &result[0].kind else {
+            panic!("Expected Play instruction");
+        };
+        assert_eq!(*plateau, Time::ZERO);
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn flatten_loops_repeat() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = Play::new(
+            ChannelId::new("q0"),
+            None,
+            Amplitude::new(1.0).unwrap(),
+            Time::new(10.0).unwrap(),
+        )
+        .unwrap();
+        let play = Arc::new(Element::new(common.clone(), play));
+        let shift = ShiftPhase::new(ChannelId::new("q0"), Phase::new(0.25).unwrap()).unwrap();
+        let shift = Arc::new(Element::new(common.clone(), shift));
+        let body = Stack::new().with_children(vec![shift, play.clone()]);
+        let body = Arc::new(Element::new(common.clone(), body));
+        let repeat = Repeat::new(body, 3)
+            .with_spacing(Time::new(5.0).unwrap())
+            .unwrap();
+        let repeat = Arc::new(Element::new(common.clone(), repeat));
+        let scaled = ScaleAmplitude::new(repeat, 0.5).unwrap();
+        let scaled = Arc::new(Element::new(common.clone(), scaled));
+        let root = Stack::new().with_children(vec![play, scaled]);
+        let root = Arc::new(Element::new(common.clone(), root));
+
+        let result = flatten_loops(&root).unwrap();
+
+        assert_eq!(result.len(), 2);
+        let Segment::Loop {
+            time,
+            count,
+            period,
+            body,
+            ..
+        } = &result[1]
+        else {
+            panic!("Expected Loop segment");
+        };
+        assert_eq!((time.value(), *count, period.value()), (10.0, 3, 15.0));
+        let body: Vec<_> = body
+            .iter()
+            .map(|s| match s {
+                Segment::Instruction(i) => (i.time.value(), i.kind.name()),
+                Segment::Loop { .. } => panic!("Expected Instruction segment"),
+            })
+            .collect();
+        assert_eq!(body, [(0.0, "ShiftPhase"), (0.0, "Play")]);
+        let unrolled = flatten(&root);
+        assert_eq!(unrolled.len(), 7);
+
+        let shift_freq =
+            ShiftFreq::new(ChannelId::new("q0"), Frequency::new(1.0).unwrap()).unwrap();
+        let shift_freq = Arc::new(Element::new(common.clone(), shift_freq));
+        let repeat = Arc::new(Element::new(common, Repeat::new(shift_freq, 2)));
+        assert_eq!(
+            flatten_loops(&repeat).unwrap_err(),
+            ScheduleError::LoopVariant {
+                channel: "q0".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn flatten_scale_amplitude() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = |amplitude| {
+            let play = Play::new(
+                ChannelId::new("q0"),
+                None,
+                Amplitude::new(amplitude).unwrap(),
+                Time::new(10.0).unwrap(),
+            )
+            .unwrap();
+            Arc::new(Element::new(common.clone(), play))
+        };
+        let inner = ScaleAmplitude::new(play(0.5), 0.5).unwrap();
+        let inner = Arc::new(Element::new(common.clone(), inner));
+        let stack = Stack::new().with_children(vec![inner, play(0.5)]);
+        let stack = Arc::new(Element::new(common.clone(), stack));
+        let root = ScaleAmplitude::new(stack, -2.0).unwrap();
+        let root = Arc::new(Element::new(common.clone(), root));
+
+        let result = flatten(&root);
+
+        let times: Vec<_> = result.iter().map(|i| i.time.value()).collect();
+        let amplitudes: Vec<_> = result
+            .iter()
+            .map(|i| match &i.kind {
+                InstructionKind::Play { amplitude, .. } => amplitude.value(),
+                _ => panic!("Expected Play instruction"),
+            })
+            .collect();
+        assert_eq!(times, vec![0.0, 10.0]);
+        assert_eq!(amplitudes, vec![-0.5, -1.0]);
+    }
+}
@@ -0,0 +1,281 @@
+//! Graphviz and Mermaid dumps of element trees.
+use std::fmt::Write as _;
+
+use itertools::Itertools as _;
+
+use crate::{
+    quant::Time,
+    schedule::{Element, ElementVariant, Measure},
+};
+
+#[derive(Debug)]
+struct Node {
+    lines: Vec<String>,
+    parent: Option<Edge>,
+}
+
+#[derive(Debug)]
+struct Edge {
+    from: usize,
+    label: Option<String>,
+}
+
+/// Render the element tree as a Graphviz DOT digraph.
+///
+/// Sub-elements shared by multiple parents are rendered as separate nodes.
+pub(crate) fn to_dot(root: &Element) -> String {
+    let nodes = collect(root);
+    let mut dot = String::from("digraph schedule {\n    node [shape=box];\n");
+    for (i, node) in nodes.iter().enumerate() {
+        let label = node.lines.iter().map(|l| escape_dot(l)).join("\\n");
+        let _ = writeln!(dot, "    n{} [label=\"{}\"];", i, label);
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some(Edge { from, label }) = &node.parent {
+            match label {
+                Some(label) => {
+                    let _ = writeln!(
+                        dot,
+                        "    n{} -> n{} [label=\"{}\"];",
+                        from,
+                        i,
+                        escape_dot(label)
+                    );
+                }
+                None => {
+                    let _ = writeln!(dot, "    n{} -> n{};", from, i);
+                }
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Render the element tree as a Mermaid flowchart.
+///
+/// Sub-elements shared by multiple parents are rendered as separate nodes.
+pub(crate) fn to_mermaid(root: &Element) -> String {
+    let nodes = collect(root);
+    let mut mermaid = String::from("flowchart TD\n");
+    for (i, node) in nodes.iter().enumerate() {
+        let label = node.lines.iter().map(|l| escape_mermaid(l)).join("<br/>");
+        let _ = writeln!(mermaid, "    n{}[\"{}\"]", i, label);
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some(Edge { from, label }) = &node.parent {
+            match label {
+                Some(label) => {
+                    let _ = writeln!(
+                        mermaid,
+                        "    n{} -->|\"{}\"| n{}",
+                        from,
+                        escape_mermaid(label),
+                        i
+                    );
+                }
+                None => {
+                    let _ = writeln!(mermaid, "    n{} --> n{}", from, i);
+                }
+            }
+        }
+    }
+    mermaid
+}
+
+/// Collect nodes in pre-order.
+fn collect(root: &Element) -> Vec<Node> {
+    fn visit(element: &Element, parent: Option<Edge>, nodes: &mut Vec<Node>) {
+        let index = nodes.len();
+        nodes.push(Node {
+            lines: describe(element),
+            parent,
+        });
+        let edge = |label| Some(Edge { from: index, label });
+        match &element.variant {
+            ElementVariant::Stack(s) => {
+                for child in s.children() {
+                    visit(child, edge(None), nodes);
+                }
+            }
+            ElementVariant::Absolute(a) => {
+                for entry in a.children() {
+                    let label = if entry.is_from_end() {
+                        format!("time = end + {}", entry.time().value())
+                    } else {
+                        format!("time = {}", entry.time().value())
+                    };
+                    visit(entry.element(), edge(Some(label)), nodes);
+                }
+            }
+            ElementVariant::Grid(g) => {
+                for entry in g.children() {
+                    let label = format!("column = {}, span = {}", entry.column(), entry.span());
+                    visit(entry.element(), edge(Some(label)), nodes);
+                }
+            }
+            ElementVariant::Repeat(r) => visit(r.child(), edge(None), nodes),
+            ElementVariant::ScaleAmplitude(c) => visit(c.child(), edge(None), nodes),
+            _ => {}
+        }
+    }
+    let mut nodes = Vec::new();
+    visit(root, None, &mut nodes);
+    nodes
+}
+
+fn describe(element: &Element) -> Vec<String> {
+    let mut lines = match &element.variant {
+        ElementVariant::Play(v) => {
+            let mut lines = vec![
+                "Play".to_string(),
+                format!("channel = {}", v.channel_id()),
+                format!(
+                    "shape = {}",
+                    v.shape_id().map_or("None".to_string(), |s| s.to_string())
+                ),
+                format!("width = {}", v.width().value()),
+            ];
+            if let Some(fall) = v.fall() {
+                lines.push(format!("fall_width = {}", fall.width.value()));
+            }
+            lines
+        }
+        ElementVariant::PlaySamples(v) => vec![
+            "PlaySamples".to_string(),
+            format!("channel = {}", v.channel_id()),
+            format!("samples = {}", v.samples().len()),
+            format!("sample_rate = {}", v.sample_rate().value()),
+        ],
+        ElementVariant::ShiftPhase(v) => vec![
+            "ShiftPhase".to_string(),
+            format!("channel = {}", v.channel_id()),
+            format!("phase = {}", v.phase().value()),
+        ],
+        ElementVariant::SetPhase(v) => vec![
+            "SetPhase".to_string(),
+            format!("channel = {}", v.channel_id()),
+            format!("phase = {}", v.phase().value()),
+        ],
+        ElementVariant::ShiftFreq(v) => vec![
+            "ShiftFreq".to_string(),
+            format!("channel = {}", v.channel_id()),
+            format!("frequency = {}", v.frequency().value()),
+        ],
+        ElementVariant::SetFreq(v) => vec![
+            "SetFreq".to_string(),
+            format!("channel = {}", v.channel_id()),
+            format!("frequency = {}", v.frequency().value()),
+        ],
+        ElementVariant::SwapPhase(v) => vec![
+            "SwapPhase".to_string(),
+            format!("channels = {}, {}", v.channel_id1(), v.channel_id2()),
+        ],
+        ElementVariant::Barrier(v) => {
+            let mut lines = vec![
+                "Barrier".to_string(),
+                format!("channels = [{}]", v.channel_ids().iter().join(", ")),
+            ];
+            if v.flex() > 0.0 {
+                lines.push(format!("flex = {}", v.flex()));
+            }
+            if let Some(slot) = v.slot() {
+                lines.push(format!("slot = {}", slot));
+            }
+            lines
+        }
+        ElementVariant::Repeat(v) => vec![
+            "Repeat".to_string(),
+            match v.count_var() {
+                Some(var) => format!("count = {} (max {})", var, v.count()),
+                None => format!("count = {}", v.count()),
+            },
+            format!("spacing = {}", v.spacing().value()),
+        ],
+        ElementVariant::Stack(v) => vec![
+            "Stack".to_string(),
+            format!("direction = {:?}", v.direction()),
+        ],
+        ElementVariant::Absolute(_) => vec!["Absolute".to_string()],
+        ElementVariant::Grid(v) => vec![
+            "Grid".to_string(),
+            format!("columns = [{}]", v.columns().iter().join(", ")),
+        ],
+        ElementVariant::ScaleAmplitude(v) => vec![
+            "ScaleAmplitude".to_string(),
+            format!("factor = {}", v.factor()),
+        ],
+    };
+    let common = &element.common;
+    lines.push(format!("duration = {}", element.measure().value()));
+    lines.push(format!("alignment = {:?}", common.alignment()));
+    let (start, end) = common.margin();
+    if start != Time::ZERO || end != Time::ZERO {
+        lines.push(format!("margin = ({}, {})", start.value(), end.value()));
+    }
+    if common.phantom() {
+        lines.push("phantom".to_string());
+    }
+    lines
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        quant::{Amplitude, ChannelId},
+        schedule::{Absolute, AbsoluteEntry, ElementCommonBuilder, Play},
+    };
+
+    fn schedule() -> Element {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = Play::new(
+            ChannelId::new("q0"),
+            None,
+            Amplitude::new(1.0).unwrap(),
+            Time::new(10.0).unwrap(),
+        )
+        .unwrap();
+        let play = Arc::new(Element::new(common.clone(), play));
+        let entry = AbsoluteEntry::new(play)
+            .with_time(Time::new(5.0).unwrap())
+            .unwrap();
+        Element::new(common, Absolute::new().with_children(vec![entry]))
+    }
+
+    #[test]
+    fn dot() {
+        let result = to_dot(&schedule());
+
+        assert_eq!(
+            result,
+            concat!(
+                "digraph schedule {\n",
+                "    node [shape=box];\n",
+                "    n0 [label=\"Absolute\\nduration = 15\\nalignment = End\"];\n",
+                "    n1 [label=\"Play\\nchannel = q0\\nshape = None\\nwidth = 10\\nduration = 10\\nalignment = End\"];\n",
+                "    n0 -> n1 [label=\"time = 5\"];\n",
+                "}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn mermaid() {
+        let result = to_mermaid(&schedule());
+
+        assert!(result.starts_with("flowchart TD\n"));
+        assert!(result.contains("n0[\"Absolute<br/>duration = 15<br/>alignment = End\"]"));
+        assert!(result.contains("n0 -->|\"time = 5\"| n1"));
+    }
+}
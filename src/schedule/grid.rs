@@ -2,11 +2,12 @@ mod helper;
 
 use std::sync::OnceLock;
 
-use anyhow::{bail, Result};
-
 use crate::{
     quant::{ChannelId, Time},
-    schedule::{grid::helper::Helper, merge_channel_ids, Alignment, Arranged, ElementRef, Measure},
+    schedule::{
+        extra_margin, finite_margin, grid::helper::Helper, measure_children, merge_channel_ids,
+        Alignment, Arranged, ChannelSet, ElementRef, Margin, Measure, Result, ScheduleError,
+    },
     GridLength,
 };
 
@@ -23,12 +24,16 @@ pub(crate) struct GridEntry {
 pub(crate) struct Grid {
     children: Vec<GridEntry>,
     columns: Vec<GridLength>,
-    channel_ids: Vec<ChannelId>,
+    padding: Margin,
+    child_margin: Margin,
+    channel_ids: ChannelSet,
     measure_result: OnceLock<MeasureResult>,
+    content_hash: OnceLock<u64>,
 }
 
 #[derive(Debug, Clone)]
 struct MeasureResult {
+    /// Duration of the columns without the padding.
     total_duration: Time,
     column_sizes: Vec<Time>,
     child_durations: Vec<Time>,
@@ -56,11 +61,23 @@ impl GridEntry {
 
     pub(crate) fn with_span(mut self, span: usize) -> Result<Self> {
         if span == 0 {
-            bail!("Span should be greater than 0");
+            return Err(ScheduleError::InvalidSpan);
         }
         self.span = span;
         Ok(self)
     }
+
+    pub(crate) fn element(&self) -> &ElementRef {
+        &self.element
+    }
+
+    pub(crate) fn column(&self) -> usize {
+        self.column
+    }
+
+    pub(crate) fn span(&self) -> usize {
+        self.span
+    }
 }
 
 impl Grid {
@@ -75,29 +92,102 @@ impl Grid {
             self.columns = columns;
         }
         self.measure_result.take();
+        self.content_hash.take();
         self
     }
 
+    /// Space inside the grid before the first and after the last column.
+    pub(crate) fn with_padding(mut self, padding: (Time, Time)) -> Result<Self> {
+        self.padding = finite_margin("padding", padding)?;
+        self.measure_result.take();
+        self.content_hash.take();
+        Ok(self)
+    }
+
+    /// Minimum margin of the children. Children with a smaller margin are
+    /// arranged as if they had this margin.
+    pub(crate) fn with_child_margin(mut self, child_margin: (Time, Time)) -> Result<Self> {
+        self.child_margin = finite_margin("child_margin", child_margin)?;
+        self.measure_result.take();
+        self.content_hash.take();
+        Ok(self)
+    }
+
     pub(crate) fn with_children(mut self, children: Vec<GridEntry>) -> Self {
         let channel_ids = merge_channel_ids(children.iter().map(|e| e.element.variant.channels()));
         self.children = children;
         self.channel_ids = channel_ids;
         self.measure_result.take();
+        self.content_hash.take();
         self
     }
 
+    pub(crate) fn with_child(&self, index: usize, child: ElementRef) -> Option<Self> {
+        let mut children = self.children.clone();
+        children.get_mut(index)?.element = child;
+        Some(self.with_layout_children(children))
+    }
+
+    #[cfg(feature = "_frontend")]
+    pub(crate) fn map_children(&self, mut f: impl FnMut(&ElementRef) -> ElementRef) -> Self {
+        let children = self
+            .children
+            .iter()
+            .map(|e| GridEntry {
+                element: f(&e.element),
+                ..*e
+            })
+            .collect();
+        self.with_layout_children(children)
+    }
+
+    /// New grid with the layout properties of `self` and `children`.
+    fn with_layout_children(&self, children: Vec<GridEntry>) -> Self {
+        Self {
+            columns: self.columns.clone(),
+            padding: self.padding.clone(),
+            child_margin: self.child_margin.clone(),
+            ..Self::default()
+        }
+        .with_children(children)
+    }
+
+    pub(crate) fn children(&self) -> &[GridEntry] {
+        &self.children
+    }
+
+    /// Cache of [`ElementVariant::content_hash`](super::ElementVariant::content_hash).
+    pub(crate) fn content_hash(&self) -> &OnceLock<u64> {
+        &self.content_hash
+    }
+
     pub(crate) fn columns(&self) -> &[GridLength] {
         &self.columns
     }
 
+    pub(crate) fn padding(&self) -> (Time, Time) {
+        self.padding.get()
+    }
+
+    pub(crate) fn child_margin(&self) -> (Time, Time) {
+        self.child_margin.get()
+    }
+
     fn measure_result(&self) -> &MeasureResult {
         self.measure_result.get_or_init(|| {
+            let durations = measure_children(&self.children, |e| {
+                let (start, end) = extra_margin(&e.element, self.child_margin());
+                e.element.measure() + start + end
+            });
             measure_grid(
-                self.children.iter().map(|e| MeasureItem {
-                    duration: e.element.measure(),
-                    column: e.column,
-                    span: e.span,
-                }),
+                self.children
+                    .iter()
+                    .zip(durations)
+                    .map(|(e, duration)| MeasureItem {
+                        duration,
+                        column: e.column,
+                        span: e.span,
+                    }),
                 &self.columns,
             )
         })
@@ -109,8 +199,11 @@ impl Default for Grid {
         Self {
             children: vec![],
             columns: vec![GridLength::star(1.0).unwrap()],
-            channel_ids: vec![],
+            padding: Margin::default(),
+            child_margin: Margin::default(),
+            channel_ids: ChannelSet::new(),
             measure_result: OnceLock::new(),
+            content_hash: OnceLock::new(),
         }
     }
 }
@@ -118,7 +211,8 @@ impl Default for Grid {
 impl Measure for Grid {
     fn measure(&self) -> Time {
         let MeasureResult { total_duration, .. } = self.measure_result();
-        *total_duration
+        let (start, end) = self.padding();
+        *total_duration + start + end
     }
 
     fn channels(&self) -> &[ChannelId] {
@@ -128,6 +222,7 @@ impl Measure for Grid {
 
 impl Arrange for Grid {
     fn arrange(&self, time_range: TimeRange) -> impl Iterator<Item = Arranged<&ElementRef>> {
+        let time_range = time_range.shrink(self.padding());
         let MeasureResult {
             column_sizes,
             child_durations,
@@ -164,7 +259,7 @@ impl Arrange for Grid {
                 };
                 Arranged {
                     item: element,
-                    time_range: child_time_range,
+                    time_range: child_time_range.shrink(extra_margin(element, self.child_margin())),
                 }
             },
         )
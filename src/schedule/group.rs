@@ -0,0 +1,192 @@
+//! Expansion of channel groups to their member channels.
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+
+use crate::{
+    quant::ChannelId,
+    schedule::{
+        Absolute, AbsoluteEntry, Barrier, Element, ElementCommonBuilder, ElementRef,
+        ElementVariant, Result, ScheduleError,
+    },
+};
+
+/// Copy of the tree with the elements targeting a group expanded to its
+/// members.
+///
+/// A pulse or frame instruction on a group becomes an [`Absolute`] layout with
+/// one copy per member at time zero, which keeps the common properties of the
+/// original element. A barrier on a group waits for all members. Members are
+/// not expanded again, so groups can't be nested. Sub-elements shared by
+/// multiple parents stay shared in the copy.
+pub(crate) fn expand_groups(
+    root: &ElementRef,
+    groups: &HashMap<ChannelId, Vec<ChannelId>>,
+) -> Result<ElementRef> {
+    let mut expander = Expander {
+        groups,
+        visited: HashMap::new(),
+        error: None,
+    };
+    let root = expander.expand(root);
+    match expander.error {
+        Some(e) => Err(e),
+        None => Ok(root),
+    }
+}
+
+struct Expander<'a> {
+    groups: &'a HashMap<ChannelId, Vec<ChannelId>>,
+    visited: HashMap<*const Element, ElementRef>,
+    error: Option<ScheduleError>,
+}
+
+impl Expander<'_> {
+    fn expand(&mut self, element: &ElementRef) -> ElementRef {
+        if let Some(expanded) = self.visited.get(&Arc::as_ptr(element)) {
+            return expanded.clone();
+        }
+        let variant: ElementVariant = match &element.variant {
+            ElementVariant::Play(v) => self.broadcast(*v.channel_id(), |c| v.map_channels(|_| c)),
+            ElementVariant::PlaySamples(v) => {
+                self.broadcast(*v.channel_id(), |c| v.map_channels(|_| c))
+            }
+            ElementVariant::ShiftPhase(v) => {
+                self.broadcast(*v.channel_id(), |c| v.map_channels(|_| c))
+            }
+            ElementVariant::SetPhase(v) => {
+                self.broadcast(*v.channel_id(), |c| v.map_channels(|_| c))
+            }
+            ElementVariant::ShiftFreq(v) => {
+                self.broadcast(*v.channel_id(), |c| v.map_channels(|_| c))
+            }
+            ElementVariant::SetFreq(v) => {
+                self.broadcast(*v.channel_id(), |c| v.map_channels(|_| c))
+            }
+            ElementVariant::SwapPhase(v) => {
+                if let Some(group) = [v.channel_id1(), v.channel_id2()]
+                    .into_iter()
+                    .find(|c| self.groups.contains_key(*c))
+                {
+                    self.error.get_or_insert(ScheduleError::GroupSwap {
+                        group: group.to_string(),
+                    });
+                }
+                return element.clone();
+            }
+            ElementVariant::Barrier(v) => {
+                let channels = v
+                    .channel_ids()
+                    .iter()
+                    .flat_map(|c| match self.groups.get(c) {
+                        Some(members) => members.clone(),
+                        None => vec![*c],
+                    })
+                    .collect();
+                Barrier::new(channels)
+                    .with_flex(v.flex())
+                    .expect("Flex should be valid")
+                    .with_slot(v.slot().map(str::to_owned))
+                    .into()
+            }
+            ElementVariant::Stack(v) => v.map_children(|c| self.expand(c)).into(),
+            ElementVariant::Absolute(v) => match v.map_children(|c| self.expand(c)) {
+                Ok(v) => v.into(),
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                    return element.clone();
+                }
+            },
+            ElementVariant::Grid(v) => v.map_children(|c| self.expand(c)).into(),
+            ElementVariant::Repeat(v) => v
+                .with_child(0, self.expand(v.child()))
+                .expect("Should have a child")
+                .into(),
+            ElementVariant::ScaleAmplitude(v) => v
+                .with_child(0, self.expand(v.child()))
+                .expect("Should have a child")
+                .into(),
+        };
+        let expanded = Arc::new(Element::new(element.common.clone(), variant));
+        self.visited.insert(Arc::as_ptr(element), expanded.clone());
+        expanded
+    }
+
+    /// Copies of a single channel element for the members of `channel`, or
+    /// the element itself if `channel` is not a group.
+    fn broadcast<V: Into<ElementVariant>>(
+        &self,
+        channel: ChannelId,
+        on: impl Fn(ChannelId) -> V,
+    ) -> ElementVariant {
+        let Some(members) = self.groups.get(&channel) else {
+            return on(channel).into();
+        };
+        let common = ElementCommonBuilder::new()
+            .build()
+            .expect("Default common should be valid");
+        let children = members
+            .iter()
+            .map(|&m| AbsoluteEntry::new(Arc::new(Element::new(common.clone(), on(m)))))
+            .collect();
+        Absolute::new().with_children(children).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        quant::{Amplitude, Phase, Time},
+        schedule::{Measure, Play, ShiftPhase, Stack, SwapPhase},
+    };
+
+    #[test]
+    fn expand() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let element = |variant: ElementVariant| Arc::new(Element::new(common.clone(), variant));
+        let play = |channel| {
+            element(
+                Play::new(
+                    ChannelId::new(channel),
+                    None,
+                    Amplitude::new(0.5).unwrap(),
+                    Time::new(10e-9).unwrap(),
+                )
+                .unwrap()
+                .into(),
+            )
+        };
+        let shift = ShiftPhase::new(ChannelId::new("flux"), Phase::new(0.25).unwrap()).unwrap();
+        let root = element(
+            Stack::new()
+                .with_children(vec![play("flux"), element(shift.into()), play("z0")])
+                .into(),
+        );
+        let groups = HashMap::from([(
+            ChannelId::new("flux"),
+            vec![ChannelId::new("z0"), ChannelId::new("z1")],
+        )]);
+
+        let expanded = expand_groups(&root, &groups).unwrap();
+
+        let mut channels: Vec<_> = expanded.channels().iter().map(|c| c.to_string()).collect();
+        channels.sort();
+        assert_eq!(channels, ["z0", "z1"]);
+        // The play on z0 waits for the broadcast play.
+        assert_eq!(expanded.measure(), Time::new(20e-9).unwrap());
+        let ElementVariant::Stack(stack) = &expanded.variant else {
+            unreachable!()
+        };
+        let ElementVariant::Absolute(absolute) = &stack.children()[0].variant else {
+            unreachable!()
+        };
+        assert_eq!(absolute.children().len(), 2);
+
+        let swap = element(SwapPhase::new(ChannelId::new("flux"), ChannelId::new("z2")).into());
+        assert!(matches!(
+            expand_groups(&swap, &groups),
+            Err(ScheduleError::GroupSwap { group }) if group == "flux"
+        ));
+    }
+}
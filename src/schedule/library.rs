@@ -0,0 +1,242 @@
+//! Library of named and versioned pulse definitions.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::quant::{Amplitude, ShapeId, Time};
+#[cfg(any(feature = "python", test))]
+use crate::{
+    quant::ChannelId,
+    schedule::{finite_value, non_negative_time, Play, Result, ScheduleError},
+};
+
+/// Envelope parameters of a library pulse.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PulseDef {
+    shape_id: Option<ShapeId>,
+    amplitude: Amplitude,
+    width: Time,
+    plateau: Time,
+    drag_coef: f64,
+}
+
+/// Reference from a [`Play`] to the library entry it was created from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PulseRef {
+    pub(crate) name: String,
+    pub(crate) version: u32,
+}
+
+/// Pulse definitions keyed by name and version.
+///
+/// Versions of a name are kept side by side so that schedules built against an
+/// older calibration can still be reproduced.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PulseLibrary {
+    entries: BTreeMap<String, BTreeMap<u32, PulseDef>>,
+}
+
+/// Serialized form of a library entry.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct PulseEntryData {
+    name: String,
+    version: u32,
+    shape_id: Option<ShapeId>,
+    amplitude: Amplitude,
+    width: Time,
+    #[serde(default)]
+    plateau: Time,
+    #[serde(default)]
+    drag_coef: f64,
+}
+
+impl PulseDef {
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn new(
+        shape_id: Option<ShapeId>,
+        amplitude: Amplitude,
+        width: Time,
+    ) -> Result<Self> {
+        let amplitude = finite_value("amplitude", amplitude)?;
+        let width = non_negative_time("width", width)?;
+        Ok(Self {
+            shape_id,
+            amplitude,
+            width,
+            plateau: Time::ZERO,
+            drag_coef: 0.0,
+        })
+    }
+
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn with_plateau(mut self, plateau: Time) -> Result<Self> {
+        self.plateau = non_negative_time("plateau", plateau)?;
+        Ok(self)
+    }
+
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn with_drag_coef(mut self, drag_coef: f64) -> Result<Self> {
+        self.drag_coef = finite_value("drag_coef", drag_coef)?;
+        Ok(self)
+    }
+}
+
+impl PulseLibrary {
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `pulse` under `name`.
+    ///
+    /// Defaults to the version after the latest one of `name`, starting at 1.
+    /// Registered versions are never replaced.
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn register(
+        &mut self,
+        name: String,
+        version: Option<u32>,
+        pulse: PulseDef,
+    ) -> Result<u32> {
+        let versions = self.entries.entry(name.clone()).or_default();
+        let version =
+            version.unwrap_or_else(|| versions.last_key_value().map_or(1, |(&v, _)| v + 1));
+        if versions.contains_key(&version) {
+            return Err(ScheduleError::DuplicatePulse { name, version });
+        }
+        versions.insert(version, pulse);
+        Ok(version)
+    }
+
+    /// Look up a pulse. Defaults to the latest version.
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn get(&self, name: &str, version: Option<u32>) -> Result<(PulseRef, &PulseDef)> {
+        let unknown = || ScheduleError::UnknownPulse {
+            name: name.to_string(),
+            version,
+        };
+        let versions = self.entries.get(name).ok_or_else(unknown)?;
+        let (&version, pulse) = match version {
+            Some(v) => versions.get_key_value(&v),
+            None => versions.last_key_value(),
+        }
+        .ok_or_else(unknown)?;
+        let reference = PulseRef {
+            name: name.to_string(),
+            version,
+        };
+        Ok((reference, pulse))
+    }
+
+    /// Create a [`Play`] of a library pulse that records the entry it uses.
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn play(
+        &self,
+        channel_id: ChannelId,
+        name: &str,
+        version: Option<u32>,
+    ) -> Result<Play> {
+        let (reference, pulse) = self.get(name, version)?;
+        Ok(Play::new(
+            channel_id,
+            pulse.shape_id.clone(),
+            pulse.amplitude,
+            pulse.width,
+        )?
+        .with_plateau(pulse.plateau)?
+        .with_drag_coef(pulse.drag_coef)?
+        .with_pulse_ref(Some(reference)))
+    }
+
+    /// Names and versions of the registered pulses in ascending order.
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn keys(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.entries
+            .iter()
+            .flat_map(|(n, v)| v.keys().map(move |&v| (n.as_str(), v)))
+    }
+
+    pub(super) fn to_data(&self) -> Vec<PulseEntryData> {
+        self.entries
+            .iter()
+            .flat_map(|(name, versions)| {
+                versions.iter().map(|(&version, pulse)| PulseEntryData {
+                    name: name.clone(),
+                    version,
+                    shape_id: pulse.shape_id.clone(),
+                    amplitude: pulse.amplitude,
+                    width: pulse.width,
+                    plateau: pulse.plateau,
+                    drag_coef: pulse.drag_coef,
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(any(feature = "python", test))]
+    pub(super) fn from_data(data: Vec<PulseEntryData>) -> Result<Self> {
+        let mut library = Self::new();
+        for e in data {
+            let pulse = PulseDef::new(e.shape_id, e.amplitude, e.width)?
+                .with_plateau(e.plateau)?
+                .with_drag_coef(e.drag_coef)?;
+            library.register(e.name, Some(e.version), pulse)?;
+        }
+        Ok(library)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pulse(amplitude: f64) -> PulseDef {
+        PulseDef::new(
+            Some(ShapeId::new("hann")),
+            Amplitude::new(amplitude).unwrap(),
+            Time::new(20e-9).unwrap(),
+        )
+        .unwrap()
+        .with_drag_coef(0.5e-9)
+        .unwrap()
+    }
+
+    #[test]
+    fn versions() {
+        let mut library = PulseLibrary::new();
+        assert_eq!(library.register("x".into(), None, pulse(0.1)), Ok(1));
+        assert_eq!(library.register("x".into(), None, pulse(0.2)), Ok(2));
+        assert_eq!(library.register("x".into(), Some(5), pulse(0.3)), Ok(5));
+        assert_eq!(
+            library.register("x".into(), Some(2), pulse(0.4)),
+            Err(ScheduleError::DuplicatePulse {
+                name: "x".into(),
+                version: 2
+            })
+        );
+
+        let play = library.play(ChannelId::new("q0"), "x", None).unwrap();
+        assert_eq!(play.amplitude().value(), 0.3);
+        assert_eq!(play.drag_coef(), 0.5e-9);
+        assert_eq!(
+            play.pulse_ref(),
+            Some(&PulseRef {
+                name: "x".into(),
+                version: 5
+            })
+        );
+        let (_, old) = library.get("x", Some(1)).unwrap();
+        assert_eq!(old, &pulse(0.1));
+        assert!(matches!(
+            library.get("x", Some(3)),
+            Err(ScheduleError::UnknownPulse { .. })
+        ));
+        assert!(library.get("y", None).is_err());
+
+        let restored = PulseLibrary::from_data(library.to_data()).unwrap();
+        assert_eq!(
+            restored.keys().collect::<Vec<_>>(),
+            [("x", 1), ("x", 2), ("x", 5)]
+        );
+    }
+}
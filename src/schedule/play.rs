@@ -1,10 +1,40 @@
-use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
-    schedule::Measure,
+    schedule::{finite_value, non_negative_time, Measure, PulseRef, Result},
 };
 
+/// Time that the phase of the channel carrier under a pulse is referenced to.
+///
+/// The carrier of the channel frequency has the phase `f * (t - t_ref)`. The
+/// additional frequency of a pulse is always referenced to the pulse start.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PhaseReference {
+    /// Referenced to time zero of the schedule.
+    #[default]
+    Absolute,
+    /// Referenced to the start of the pulse.
+    Pulse,
+    /// Referenced to the start of the iteration of the innermost enclosing
+    /// `Repeat`, or to time zero outside of repeats.
+    Iteration,
+}
+
+/// Falling edge of a [`Play`] that differs from the rising edge, e.g. a
+/// slower fall of a flux pulse.
+///
+/// The fall is the second half of the shape stretched to `width`, so it lasts
+/// `width / 2`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct FallingEdge {
+    /// `None` to use the shape of the rising edge.
+    #[serde(default)]
+    pub(crate) shape_id: Option<ShapeId>,
+    pub(crate) width: Time,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Play {
     channel_id: [ChannelId; 1],
@@ -16,6 +46,11 @@ pub(crate) struct Play {
     frequency: Frequency,
     phase: Phase,
     flexible: bool,
+    hold: bool,
+    phase_reference: PhaseReference,
+    pulse_ref: Option<PulseRef>,
+    calibration: Option<String>,
+    fall: Option<FallingEdge>,
 }
 
 impl Play {
@@ -25,12 +60,8 @@ impl Play {
         amplitude: Amplitude,
         width: Time,
     ) -> Result<Self> {
-        if !amplitude.value().is_finite() {
-            bail!("Invalid amplitude {:?}", amplitude);
-        }
-        if !width.value().is_finite() || width.value() < 0.0 {
-            bail!("Invalid width {:?}", width);
-        }
+        let amplitude = finite_value("amplitude", amplitude)?;
+        let width = non_negative_time("width", width)?;
         Ok(Self {
             channel_id: [channel_id],
             shape_id,
@@ -41,38 +72,37 @@ impl Play {
             frequency: Frequency::ZERO,
             phase: Phase::ZERO,
             flexible: false,
+            hold: false,
+            phase_reference: PhaseReference::Absolute,
+            pulse_ref: None,
+            calibration: None,
+            fall: None,
         })
     }
 
+    #[cfg(feature = "python")]
+    pub(crate) fn with_amplitude(mut self, amplitude: Amplitude) -> Result<Self> {
+        self.amplitude = finite_value("amplitude", amplitude)?;
+        Ok(self)
+    }
+
     pub(crate) fn with_plateau(mut self, plateau: Time) -> Result<Self> {
-        if !plateau.value().is_finite() || plateau.value() < 0.0 {
-            bail!("Invalid plateau {:?}", plateau);
-        }
-        self.plateau = plateau;
+        self.plateau = non_negative_time("plateau", plateau)?;
         Ok(self)
     }
 
     pub(crate) fn with_drag_coef(mut self, drag_coef: f64) -> Result<Self> {
-        if !drag_coef.is_finite() {
-            bail!("Invalid drag_coef {}", drag_coef);
-        }
-        self.drag_coef = drag_coef;
+        self.drag_coef = finite_value("drag_coef", drag_coef)?;
         Ok(self)
     }
 
     pub(crate) fn with_frequency(mut self, frequency: Frequency) -> Result<Self> {
-        if !frequency.value().is_finite() {
-            bail!("Invalid frequency {:?}", frequency);
-        }
-        self.frequency = frequency;
+        self.frequency = finite_value("frequency", frequency)?;
         Ok(self)
     }
 
     pub(crate) fn with_phase(mut self, phase: Phase) -> Result<Self> {
-        if !phase.value().is_finite() {
-            bail!("Invalid phase {:?}", phase);
-        }
-        self.phase = phase;
+        self.phase = finite_value("phase", phase)?;
         Ok(self)
     }
 
@@ -81,6 +111,48 @@ impl Play {
         self
     }
 
+    /// Hold the channel at the final value of the envelope after the pulse,
+    /// until the next pulse or barrier on the channel or the end of the
+    /// schedule.
+    pub(crate) fn with_hold(mut self, hold: bool) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    pub(crate) fn with_phase_reference(mut self, phase_reference: PhaseReference) -> Self {
+        self.phase_reference = phase_reference;
+        self
+    }
+
+    /// Record the library entry the pulse was created from.
+    pub(crate) fn with_pulse_ref(mut self, pulse_ref: Option<PulseRef>) -> Self {
+        self.pulse_ref = pulse_ref;
+        self
+    }
+
+    /// Set the gate name used to look up calibrated parameters of the pulse.
+    pub(crate) fn with_calibration(mut self, calibration: Option<String>) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    /// Use a different falling edge, `shape_id` `None` keeps the shape of the
+    /// rising edge. The rising edge keeps `width` and the shape of the pulse.
+    pub(crate) fn with_fall(mut self, fall: Option<FallingEdge>) -> Result<Self> {
+        if let Some(fall) = &fall {
+            non_negative_time("fall_width", fall.width)?;
+        }
+        self.fall = fall;
+        Ok(self)
+    }
+
+    #[cfg(feature = "_frontend")]
+    pub(crate) fn map_channels(&self, f: impl Fn(ChannelId) -> ChannelId) -> Self {
+        let mut play = self.clone();
+        play.channel_id = [f(self.channel_id[0])];
+        play
+    }
+
     pub(crate) fn channel_id(&self) -> &ChannelId {
         &self.channel_id[0]
     }
@@ -116,6 +188,56 @@ impl Play {
     pub(crate) fn flexible(&self) -> bool {
         self.flexible
     }
+
+    pub(crate) fn hold(&self) -> bool {
+        self.hold
+    }
+
+    pub(crate) fn phase_reference(&self) -> PhaseReference {
+        self.phase_reference
+    }
+
+    pub(crate) fn pulse_ref(&self) -> Option<&PulseRef> {
+        self.pulse_ref.as_ref()
+    }
+
+    pub(crate) fn calibration(&self) -> Option<&str> {
+        self.calibration.as_deref()
+    }
+
+    pub(crate) fn fall(&self) -> Option<&FallingEdge> {
+        self.fall.as_ref()
+    }
+
+    /// Duration of the rising and falling edges together, which is `width`
+    /// for symmetric pulses.
+    pub(crate) fn edge_width(&self) -> Time {
+        match &self.fall {
+            Some(fall) => (self.width + fall.width) / 2.0,
+            None => self.width,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+impl PhaseReference {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            PhaseReference::Absolute => "absolute",
+            PhaseReference::Pulse => "pulse",
+            PhaseReference::Iteration => "iteration",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        [
+            PhaseReference::Absolute,
+            PhaseReference::Pulse,
+            PhaseReference::Iteration,
+        ]
+        .into_iter()
+        .find(|r| r.name() == name)
+    }
 }
 
 impl Measure for Play {
@@ -125,9 +247,9 @@ impl Measure for Play {
 
     fn measure(&self) -> Time {
         if self.flexible {
-            self.width
+            self.edge_width()
         } else {
-            self.width + self.plateau
+            self.edge_width() + self.plateau
         }
     }
 }
@@ -0,0 +1,138 @@
+//! Frequency multiplexed readout pulses.
+use std::sync::Arc;
+
+use crate::{
+    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    schedule::{
+        Absolute, AbsoluteEntry, Element, ElementCommonBuilder, Play, Result, ScheduleError,
+    },
+};
+
+/// Tone of a multiplexed readout, the frequency is relative to the channel.
+#[derive(Debug, Clone)]
+pub(crate) struct ReadoutTone {
+    pub(crate) frequency: Frequency,
+    pub(crate) amplitude: Amplitude,
+    pub(crate) phase: Phase,
+    pub(crate) shape_id: Option<ShapeId>,
+}
+
+/// One [`Play`] per tone with the shared envelope parameters, all placed at
+/// the start of an [`Absolute`].
+///
+/// The additional frequency of a pulse is referenced to the pulse start, so
+/// the tones start with their phases at the same time and stay coherent with
+/// each other wherever the readout is arranged.
+pub(crate) fn multiplex_readout(
+    channel_id: ChannelId,
+    tones: Vec<ReadoutTone>,
+    width: Time,
+    plateau: Time,
+    drag_coef: f64,
+) -> Result<Absolute> {
+    if tones.is_empty() {
+        return Err(ScheduleError::NoReadoutTones);
+    }
+    let children = tones
+        .into_iter()
+        .map(|tone| {
+            let play = Play::new(channel_id, tone.shape_id, tone.amplitude, width)?
+                .with_plateau(plateau)?
+                .with_drag_coef(drag_coef)?
+                .with_frequency(tone.frequency)?
+                .with_phase(tone.phase)?;
+            let common = ElementCommonBuilder::new().build()?;
+            Ok(AbsoluteEntry::new(Arc::new(Element::new(common, play))))
+        })
+        .collect::<Result<_>>()?;
+    Ok(Absolute::new().with_children(children))
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+    use num::complex::Complex64;
+
+    use super::*;
+    use crate::{executor::Executor, pulse::Sampler, schedule::ElementVariant};
+
+    #[test]
+    fn readout() {
+        let t = |v| Time::new(v).unwrap();
+        let name = ChannelId::new("m0");
+        let tone = |frequency, amplitude, phase| ReadoutTone {
+            frequency: Frequency::new(frequency).unwrap(),
+            amplitude: Amplitude::new(amplitude).unwrap(),
+            phase: Phase::new(phase).unwrap(),
+            shape_id: None,
+        };
+        let readout = multiplex_readout(
+            name,
+            vec![tone(-120e6, 0.2, 0.0), tone(35e6, 0.15, 0.25)],
+            t(10e-9),
+            t(40e-9),
+            0.0,
+        )
+        .unwrap();
+
+        let plays: Vec<_> = readout
+            .children()
+            .iter()
+            .map(|c| match &c.element().variant {
+                ElementVariant::Play(play) => play.clone(),
+                _ => panic!("Expected Play variant"),
+            })
+            .collect();
+        assert_eq!(plays.len(), 2);
+        assert!(plays
+            .iter()
+            .all(|p| p.width() == t(10e-9) && p.plateau() == t(40e-9) && *p.channel_id() == name));
+        assert_eq!(plays[1].frequency(), Frequency::new(35e6).unwrap());
+        assert_eq!(plays[1].phase(), Phase::new(0.25).unwrap());
+
+        // Arranged at an offset, the readout is the same waveform shifted.
+        let readout = Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            readout,
+        ));
+        let sample = |offset: usize| {
+            let root = Arc::new(Element::new(
+                ElementCommonBuilder::new().build().unwrap(),
+                Absolute::new().with_children(vec![AbsoluteEntry::new(readout.clone())
+                    .with_time(t(offset as f64 * 1e-9))
+                    .unwrap()]),
+            ));
+            let mut executor = Executor::new(Amplitude::new(1e-5).unwrap(), t(1e-12), false);
+            executor.add_channel(name, Frequency::new(10e6).unwrap(), None, false);
+            executor.execute(&root).unwrap();
+            let (lists, _) = executor.into_result();
+            let mut waveform = Array2::zeros((2, 100));
+            let mut sampler = Sampler::new(lists);
+            sampler.add_channel(
+                name,
+                waveform.view_mut(),
+                Frequency::new(1e9).unwrap(),
+                Time::ZERO,
+                -10,
+                Complex64::ZERO,
+            );
+            sampler.sample(t(1e-12)).unwrap();
+            waveform
+        };
+        let at_zero = sample(0);
+        let at_offset = sample(30);
+        assert!(at_zero.iter().any(|&v| v.abs() > 0.1));
+        // The carrier of the channel keeps running, only the tones restart.
+        let carrier = Complex64::from_polar(1.0, 2.0 * std::f64::consts::PI * 10e6 * 30e-9);
+        for i in 1..49 {
+            let a = Complex64::new(at_zero[[0, i]], at_zero[[1, i]]) * carrier;
+            let b = Complex64::new(at_offset[[0, i + 30]], at_offset[[1, i + 30]]);
+            assert!((a - b).norm() < 1e-3, "{} != {} at {}", a, b, i);
+        }
+
+        assert!(matches!(
+            multiplex_readout(name, vec![], t(10e-9), Time::ZERO, 0.0),
+            Err(ScheduleError::NoReadoutTones)
+        ));
+    }
+}
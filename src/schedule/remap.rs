@@ -0,0 +1,132 @@
+//! Rewriting of the channels of an element tree.
+use std::sync::Arc;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{
+    quant::ChannelId,
+    schedule::{Element, ElementRef, ElementVariant, Measure, Result, ScheduleError},
+};
+
+/// Copy of the tree with every channel replaced according to `mapping`.
+///
+/// Channels not in `mapping` are kept. Sub-elements shared by multiple parents
+/// stay shared in the copy. If `targets` is given, every channel of the result
+/// has to be one of them.
+pub(crate) fn remap_channels(
+    root: &ElementRef,
+    mapping: &HashMap<ChannelId, ChannelId>,
+    targets: Option<&HashSet<ChannelId>>,
+) -> Result<ElementRef> {
+    let mut remapper = Remapper {
+        mapping,
+        visited: HashMap::new(),
+        error: None,
+    };
+    let root = remapper.remap(root);
+    if let Some(e) = remapper.error {
+        return Err(e);
+    }
+    if let Some(targets) = targets {
+        if let Some(channel) = root.channels().iter().find(|c| !targets.contains(*c)) {
+            return Err(ScheduleError::UnknownChannel {
+                channel: channel.to_string(),
+            });
+        }
+    }
+    Ok(root)
+}
+
+struct Remapper<'a> {
+    mapping: &'a HashMap<ChannelId, ChannelId>,
+    visited: HashMap<*const Element, ElementRef>,
+    error: Option<ScheduleError>,
+}
+
+impl Remapper<'_> {
+    fn remap(&mut self, element: &ElementRef) -> ElementRef {
+        if let Some(remapped) = self.visited.get(&Arc::as_ptr(element)) {
+            return remapped.clone();
+        }
+        let mapping = self.mapping;
+        let f = |c: ChannelId| mapping.get(&c).copied().unwrap_or(c);
+        let variant: ElementVariant = match &element.variant {
+            ElementVariant::Play(v) => v.map_channels(f).into(),
+            ElementVariant::PlaySamples(v) => v.map_channels(f).into(),
+            ElementVariant::ShiftPhase(v) => v.map_channels(f).into(),
+            ElementVariant::SetPhase(v) => v.map_channels(f).into(),
+            ElementVariant::ShiftFreq(v) => v.map_channels(f).into(),
+            ElementVariant::SetFreq(v) => v.map_channels(f).into(),
+            ElementVariant::SwapPhase(v) => v.map_channels(f).into(),
+            ElementVariant::Barrier(v) => v.map_channels(f).into(),
+            ElementVariant::Stack(v) => v.map_children(|c| self.remap(c)).into(),
+            ElementVariant::Absolute(v) => match v.map_children(|c| self.remap(c)) {
+                Ok(v) => v.into(),
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                    return element.clone();
+                }
+            },
+            ElementVariant::Grid(v) => v.map_children(|c| self.remap(c)).into(),
+            ElementVariant::Repeat(v) => v
+                .with_child(0, self.remap(v.child()))
+                .expect("Should have a child")
+                .into(),
+            ElementVariant::ScaleAmplitude(v) => v
+                .with_child(0, self.remap(v.child()))
+                .expect("Should have a child")
+                .into(),
+        };
+        let remapped = Arc::new(Element::new(element.common.clone(), variant));
+        self.visited.insert(Arc::as_ptr(element), remapped.clone());
+        remapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        quant::{Amplitude, Phase, Time},
+        schedule::{ElementCommonBuilder, Play, Repeat, ShiftPhase, Stack},
+    };
+
+    #[test]
+    fn remap() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = Play::new(
+            ChannelId::new("q0.xy"),
+            None,
+            Amplitude::new(0.5).unwrap(),
+            Time::new(10e-9).unwrap(),
+        )
+        .unwrap();
+        let play = Arc::new(Element::new(common.clone(), play));
+        let shift = ShiftPhase::new(ChannelId::new("q1.xy"), Phase::new(0.25).unwrap()).unwrap();
+        let shift = Arc::new(Element::new(common.clone(), shift));
+        let repeat = Arc::new(Element::new(common.clone(), Repeat::new(play.clone(), 2)));
+        let stack = Stack::new().with_children(vec![play, repeat, shift]);
+        let root = Arc::new(Element::new(common, stack));
+
+        let mapping = HashMap::from([(ChannelId::new("q0.xy"), ChannelId::new("awg1.ch3"))]);
+        let remapped = remap_channels(&root, &mapping, None).unwrap();
+
+        let mut channels: Vec<_> = remapped.channels().iter().map(|c| c.to_string()).collect();
+        channels.sort();
+        assert_eq!(channels, ["awg1.ch3", "q1.xy"]);
+        let ElementVariant::Stack(stack) = &remapped.variant else {
+            unreachable!()
+        };
+        let ElementVariant::Repeat(repeat) = &stack.children()[1].variant else {
+            unreachable!()
+        };
+        assert!(Arc::ptr_eq(&stack.children()[0], repeat.child()));
+        assert_eq!(remapped.measure(), root.measure());
+
+        let targets = HashSet::from([ChannelId::new("awg1.ch3")]);
+        assert!(matches!(
+            remap_channels(&root, &mapping, Some(&targets)),
+            Err(ScheduleError::UnknownChannel { channel }) if channel == "q1.xy"
+        ));
+    }
+}
@@ -1,10 +1,8 @@
 use std::sync::OnceLock;
 
-use anyhow::{bail, Result};
-
 use crate::{
     quant::{ChannelId, Time},
-    schedule::{ElementRef, Measure},
+    schedule::{finite_time, ElementRef, Measure, Result, ScheduleError},
 };
 
 use super::{Arrange, Arranged, TimeRange};
@@ -13,8 +11,10 @@ use super::{Arrange, Arranged, TimeRange};
 pub(crate) struct Repeat {
     child: ElementRef,
     count: usize,
+    count_var: Option<String>,
     spacing: Time,
     measure_result: OnceLock<Time>,
+    content_hash: OnceLock<u64>,
 }
 
 impl Repeat {
@@ -22,29 +22,80 @@ impl Repeat {
         Self {
             child,
             count,
+            count_var: None,
             spacing: Time::ZERO,
             measure_result: OnceLock::new(),
+            content_hash: OnceLock::new(),
         }
     }
 
     pub(crate) fn with_spacing(mut self, spacing: Time) -> Result<Self> {
-        if !spacing.value().is_finite() {
-            bail!("Invalid spacing {:?}", spacing);
-        }
-        self.spacing = spacing;
+        self.spacing = finite_time("spacing", spacing)?;
         self.measure_result.take();
+        self.content_hash.take();
+        Ok(self)
+    }
+
+    /// Bind the number of repetitions to a runtime variable.
+    ///
+    /// `count` is then the maximum number of repetitions, which is used for
+    /// measuring and arranging, and has to be non-zero. The name is written
+    /// verbatim into generated sequencer code, so it has to be an identifier.
+    pub(crate) fn with_count_var(mut self, count_var: Option<String>) -> Result<Self> {
+        if let Some(name) = &count_var {
+            if !is_identifier(name) {
+                return Err(ScheduleError::InvalidVariable { name: name.clone() });
+            }
+            if self.count == 0 {
+                return Err(ScheduleError::ZeroCountVar { name: name.clone() });
+            }
+        }
+        self.count_var = count_var;
+        self.content_hash.take();
         Ok(self)
     }
 
+    pub(crate) fn with_child(&self, index: usize, child: ElementRef) -> Option<Self> {
+        (index == 0).then(|| Self {
+            child,
+            count: self.count,
+            count_var: self.count_var.clone(),
+            spacing: self.spacing,
+            measure_result: OnceLock::new(),
+            content_hash: OnceLock::new(),
+        })
+    }
+
+    pub(crate) fn child(&self) -> &ElementRef {
+        &self.child
+    }
+
+    /// Cache of [`ElementVariant::content_hash`](super::ElementVariant::content_hash).
+    pub(crate) fn content_hash(&self) -> &OnceLock<u64> {
+        &self.content_hash
+    }
+
     pub(crate) fn count(&self) -> usize {
         self.count
     }
 
+    pub(crate) fn count_var(&self) -> Option<&str> {
+        self.count_var.as_deref()
+    }
+
     pub(crate) fn spacing(&self) -> Time {
         self.spacing
     }
 }
 
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 impl Measure for Repeat {
     fn channels(&self) -> &[ChannelId] {
         self.child.channels()
@@ -79,3 +130,32 @@ impl Arrange for Repeat {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::schedule::{Barrier, Element, ElementCommonBuilder};
+
+    #[test]
+    fn count_var() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let child = Arc::new(Element::new(common, Barrier::new(vec![])));
+        let repeat =
+            |count, name: &str| Repeat::new(child.clone(), count).with_count_var(Some(name.into()));
+
+        assert_eq!(repeat(3, "n_shots").unwrap().count_var(), Some("n_shots"));
+        for name in ["", "2n", "q0.xy", "n - 1", "n;\nplayZero(1)"] {
+            assert!(matches!(
+                repeat(3, name),
+                Err(ScheduleError::InvalidVariable { .. })
+            ));
+        }
+        assert!(matches!(
+            repeat(0, "n"),
+            Err(ScheduleError::ZeroCountVar { name }) if name == "n"
+        ));
+        assert!(Repeat::new(child.clone(), 0).with_count_var(None).is_ok());
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use num::complex::Complex64;
+
+use crate::{
+    quant::{Amplitude, ChannelId, Frequency, Phase, Time},
+    schedule::{finite_value, Measure, Result, ScheduleError},
+};
+
+/// Play of a complex sample array instead of a shaped pulse.
+///
+/// Sample `k` covers the interval `[k, k + 1) / sample_rate` from the start of
+/// the element. The samples are multiplied by `amplitude` and modulated like
+/// the envelope of a [`Play`](crate::schedule::Play).
+#[derive(Debug, Clone)]
+pub(crate) struct PlaySamples {
+    channel_id: [ChannelId; 1],
+    samples: Arc<[Complex64]>,
+    sample_rate: Frequency,
+    amplitude: Amplitude,
+    frequency: Frequency,
+    phase: Phase,
+    interpolate: bool,
+}
+
+impl PlaySamples {
+    pub(crate) fn new(
+        channel_id: ChannelId,
+        samples: Arc<[Complex64]>,
+        sample_rate: Frequency,
+    ) -> Result<Self> {
+        if !(sample_rate.value().is_finite() && sample_rate.value() > 0.0) {
+            return Err(ScheduleError::InvalidValue {
+                property: "sample_rate",
+                value: sample_rate.value(),
+            });
+        }
+        if let Some(s) = samples.iter().find(|s| !s.is_finite()) {
+            let value = if s.re.is_finite() { s.im } else { s.re };
+            return Err(ScheduleError::InvalidValue {
+                property: "samples",
+                value,
+            });
+        }
+        Ok(Self {
+            channel_id: [channel_id],
+            samples,
+            sample_rate,
+            amplitude: Amplitude::new(1.0).expect("1 should be a valid amplitude"),
+            frequency: Frequency::ZERO,
+            phase: Phase::ZERO,
+            interpolate: false,
+        })
+    }
+
+    pub(crate) fn with_amplitude(mut self, amplitude: Amplitude) -> Result<Self> {
+        self.amplitude = finite_value("amplitude", amplitude)?;
+        Ok(self)
+    }
+
+    pub(crate) fn with_frequency(mut self, frequency: Frequency) -> Result<Self> {
+        self.frequency = finite_value("frequency", frequency)?;
+        Ok(self)
+    }
+
+    pub(crate) fn with_phase(mut self, phase: Phase) -> Result<Self> {
+        self.phase = finite_value("phase", phase)?;
+        Ok(self)
+    }
+
+    /// Interpolate linearly between the sample centers instead of holding each
+    /// sample, for sample arrays at a rate other than the channel rate.
+    pub(crate) fn with_interpolate(mut self, interpolate: bool) -> Self {
+        self.interpolate = interpolate;
+        self
+    }
+
+    #[cfg(feature = "_frontend")]
+    pub(crate) fn map_channels(&self, f: impl Fn(ChannelId) -> ChannelId) -> Self {
+        let mut play = self.clone();
+        play.channel_id = [f(self.channel_id[0])];
+        play
+    }
+
+    pub(crate) fn channel_id(&self) -> &ChannelId {
+        &self.channel_id[0]
+    }
+
+    pub(crate) fn samples(&self) -> &Arc<[Complex64]> {
+        &self.samples
+    }
+
+    pub(crate) fn sample_rate(&self) -> Frequency {
+        self.sample_rate
+    }
+
+    pub(crate) fn amplitude(&self) -> Amplitude {
+        self.amplitude
+    }
+
+    pub(crate) fn frequency(&self) -> Frequency {
+        self.frequency
+    }
+
+    pub(crate) fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub(crate) fn interpolate(&self) -> bool {
+        self.interpolate
+    }
+
+    pub(crate) fn width(&self) -> Time {
+        Time::new(self.samples.len() as f64 / self.sample_rate.value())
+            .expect("Sample count over a positive rate should be a valid time")
+    }
+}
+
+impl Measure for PlaySamples {
+    fn channels(&self) -> &[ChannelId] {
+        &self.channel_id
+    }
+
+    fn measure(&self) -> Time {
+        self.width()
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::OnceLock;
+
+use crate::{
+    quant::{ChannelId, Time},
+    schedule::{finite_value, ElementRef, Measure, Result},
+    Alignment,
+};
+
+use super::{Arrange, Arranged, TimeRange};
+
+/// Multiply the amplitudes of all [`Play`](super::Play) descendants.
+///
+/// The child is arranged as if the wrapper were not there, so wrapping an
+/// element never changes the timing of the schedule.
+#[derive(Debug, Clone)]
+pub(crate) struct ScaleAmplitude {
+    child: ElementRef,
+    factor: f64,
+    content_hash: OnceLock<u64>,
+}
+
+impl ScaleAmplitude {
+    pub(crate) fn new(child: ElementRef, factor: f64) -> Result<Self> {
+        let factor = finite_value("factor", factor)?;
+        Ok(Self {
+            child,
+            factor,
+            content_hash: OnceLock::new(),
+        })
+    }
+
+    pub(crate) fn with_child(&self, index: usize, child: ElementRef) -> Option<Self> {
+        (index == 0).then_some(Self {
+            child,
+            factor: self.factor,
+            content_hash: OnceLock::new(),
+        })
+    }
+
+    pub(crate) fn child(&self) -> &ElementRef {
+        &self.child
+    }
+
+    pub(crate) fn factor(&self) -> f64 {
+        self.factor
+    }
+
+    /// Cache of [`ElementVariant::content_hash`](super::ElementVariant::content_hash).
+    pub(crate) fn content_hash(&self) -> &OnceLock<u64> {
+        &self.content_hash
+    }
+}
+
+impl Measure for ScaleAmplitude {
+    fn channels(&self) -> &[ChannelId] {
+        self.child.channels()
+    }
+
+    fn measure(&self) -> Time {
+        self.child.measure()
+    }
+}
+
+impl Arrange for ScaleAmplitude {
+    fn arrange(&self, time_range: TimeRange) -> impl Iterator<Item = Arranged<&ElementRef>> {
+        let alignment = self.child.common.alignment();
+        let child_duration = match alignment {
+            Alignment::Stretch => time_range.span,
+            _ => self.child.measure(),
+        };
+        let offset = match alignment {
+            Alignment::End => time_range.span - child_duration,
+            Alignment::Center => (time_range.span - child_duration) / 2.0,
+            _ => Time::ZERO,
+        };
+        std::iter::once(Arranged {
+            item: &self.child,
+            time_range: TimeRange {
+                start: time_range.start + offset,
+                span: child_duration,
+            },
+        })
+    }
+}
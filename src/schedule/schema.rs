@@ -0,0 +1,1018 @@
+//! Serialization schema of element trees.
+//!
+//! The schema is decoupled from the in-memory representation so that measure
+//! caches and derived fields are not serialized, and deserialized values go
+//! through the same validation as the constructors.
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
+
+use num::complex::Complex64;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    codec::{self, Versioned as _},
+    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    schedule::{
+        Absolute, AbsoluteEntry, Barrier, Element, ElementCommonBuilder, ElementRef,
+        ElementVariant, FallingEdge, Grid, GridEntry, PhaseReference, Play, PlaySamples,
+        PulseLibrary, PulseRef, Repeat, Result, ScaleAmplitude, ScheduleError, SetFreq, SetPhase,
+        ShiftFreq, ShiftPhase, Stack, SwapPhase,
+    },
+    Alignment, Direction, GridLength,
+};
+
+use super::library::PulseEntryData;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Document<T> {
+    version: u32,
+    schedule: T,
+    /// Pulse library the schedule was built with, kept for provenance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    library: Option<Vec<PulseEntryData>>,
+}
+
+#[cfg(any(feature = "python", test))]
+#[derive(Debug, Serialize, Deserialize)]
+struct LibraryDocument {
+    version: u32,
+    library: Vec<PulseEntryData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ElementData {
+    #[serde(flatten)]
+    common: CommonData,
+    #[serde(flatten)]
+    variant: VariantData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct CommonData {
+    margin: (Time, Time),
+    alignment: Alignment,
+    phantom: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<Time>,
+    /// `None` for unbounded duration because JSON can't represent infinity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_duration: Option<Time>,
+    min_duration: Time,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_oversize: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum VariantData {
+    Play {
+        channel_id: ChannelId,
+        shape_id: Option<ShapeId>,
+        amplitude: Amplitude,
+        width: Time,
+        #[serde(default)]
+        plateau: Time,
+        #[serde(default)]
+        drag_coef: f64,
+        #[serde(default)]
+        frequency: Frequency,
+        #[serde(default)]
+        phase: Phase,
+        #[serde(default)]
+        flexible: bool,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        hold: bool,
+        #[serde(default, skip_serializing_if = "is_default")]
+        phase_reference: PhaseReference,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pulse: Option<PulseRef>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        calibration: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fall: Option<FallingEdge>,
+    },
+    PlaySamples {
+        channel_id: ChannelId,
+        /// Pairs of real and imaginary parts.
+        samples: Vec<[f64; 2]>,
+        sample_rate: Frequency,
+        amplitude: Amplitude,
+        #[serde(default)]
+        frequency: Frequency,
+        #[serde(default)]
+        phase: Phase,
+        #[serde(default)]
+        interpolate: bool,
+    },
+    ShiftPhase {
+        channel_id: ChannelId,
+        phase: Phase,
+    },
+    SetPhase {
+        channel_id: ChannelId,
+        phase: Phase,
+    },
+    ShiftFreq {
+        channel_id: ChannelId,
+        frequency: Frequency,
+    },
+    SetFreq {
+        channel_id: ChannelId,
+        frequency: Frequency,
+    },
+    SwapPhase {
+        channel_id1: ChannelId,
+        channel_id2: ChannelId,
+    },
+    Barrier {
+        #[serde(default)]
+        channel_ids: Vec<ChannelId>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        flex: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        slot: Option<String>,
+    },
+    Repeat {
+        child: Box<ElementData>,
+        count: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        count_var: Option<String>,
+        #[serde(default)]
+        spacing: Time,
+    },
+    Stack {
+        #[serde(default)]
+        children: Vec<ElementData>,
+        #[serde(default = "default_direction")]
+        direction: Direction,
+        #[serde(default, skip_serializing_if = "is_default")]
+        padding: (Time, Time),
+        #[serde(default, skip_serializing_if = "is_default")]
+        child_margin: (Time, Time),
+    },
+    Absolute {
+        #[serde(default)]
+        children: Vec<AbsoluteEntryData>,
+    },
+    Grid {
+        #[serde(default)]
+        children: Vec<GridEntryData>,
+        #[serde(default)]
+        columns: Vec<GridLength>,
+        #[serde(default, skip_serializing_if = "is_default")]
+        padding: (Time, Time),
+        #[serde(default, skip_serializing_if = "is_default")]
+        child_margin: (Time, Time),
+    },
+    ScaleAmplitude {
+        child: Box<ElementData>,
+        factor: f64,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AbsoluteEntryData {
+    #[serde(default)]
+    time: Time,
+    #[serde(default, skip_serializing_if = "is_default")]
+    from_end: bool,
+    element: ElementData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GridEntryData {
+    element: ElementData,
+    #[serde(default)]
+    column: usize,
+    #[serde(default = "default_span")]
+    span: usize,
+}
+
+fn default_direction() -> Direction {
+    Direction::Backward
+}
+
+fn default_span() -> usize {
+    1
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl codec::Versioned for ElementData {
+    /// Version 2 if the element or one of its descendants uses a field of
+    /// version 2 with a non-default value.
+    fn required_version(&self) -> u32 {
+        let children: Vec<&ElementData> = match &self.variant {
+            VariantData::Repeat { child, .. } | VariantData::ScaleAmplitude { child, .. } => {
+                vec![child]
+            }
+            VariantData::Stack { children, .. } => children.iter().collect(),
+            VariantData::Absolute { children } => children.iter().map(|c| &c.element).collect(),
+            VariantData::Grid { children, .. } => children.iter().map(|c| &c.element).collect(),
+            _ => vec![],
+        };
+        let own = match &self.variant {
+            VariantData::Play {
+                hold,
+                phase_reference,
+                pulse,
+                calibration,
+                fall,
+                ..
+            } => {
+                *hold
+                    || !is_default(phase_reference)
+                    || pulse.is_some()
+                    || calibration.is_some()
+                    || fall.is_some()
+            }
+            VariantData::PlaySamples { .. } | VariantData::ScaleAmplitude { .. } => true,
+            VariantData::Barrier { flex, slot, .. } => *flex != 0.0 || slot.is_some(),
+            VariantData::Repeat { count_var, .. } => count_var.is_some(),
+            VariantData::Stack {
+                padding,
+                child_margin,
+                ..
+            }
+            | VariantData::Grid {
+                padding,
+                child_margin,
+                ..
+            } => !is_default(padding) || !is_default(child_margin),
+            VariantData::Absolute { children } => children.iter().any(|c| c.from_end),
+            _ => false,
+        } || self.common.allow_oversize.is_some();
+        let own = if own { 2 } else { codec::MIN_VERSION };
+        children
+            .into_iter()
+            .map(codec::Versioned::required_version)
+            .fold(own, u32::max)
+    }
+}
+
+impl Element {
+    /// Serialize the element tree to a versioned JSON document.
+    ///
+    /// `library` is embedded in the document for provenance if given.
+    pub(crate) fn to_json(&self, library: Option<&PulseLibrary>) -> Result<String> {
+        let document = Document {
+            version: codec::CURRENT_VERSION,
+            schedule: ElementData::from(self),
+            library: library.map(PulseLibrary::to_data),
+        };
+        serde_json::to_string(&document).map_err(document_error)
+    }
+
+    /// Deserialize an element tree from a versioned JSON document.
+    pub(crate) fn from_json(s: &str) -> Result<Self> {
+        let document: Document<serde_json::Value> =
+            serde_json::from_str(s).map_err(document_error)?;
+        codec::check_version(document.version).map_err(document_error)?;
+        let data: ElementData =
+            serde_json::from_value(document.schedule).map_err(document_error)?;
+        codec::check_required(document.version, data.required_version()).map_err(document_error)?;
+        data.try_into()
+    }
+
+    /// Serialize the element tree to a versioned MessagePack document.
+    ///
+    /// `version` is the format version requested by the reader. Defaults to the
+    /// latest version. Trees using fields of a newer version than the
+    /// requested one are rejected.
+    pub(crate) fn to_msgpack(&self, version: Option<u32>) -> Result<Vec<u8>> {
+        codec::to_msgpack(&ElementData::from(self), version).map_err(document_error)
+    }
+
+    /// Deserialize an element tree from a versioned MessagePack document.
+    #[cfg(any(feature = "python", feature = "grpc", test))]
+    pub(crate) fn from_msgpack(bytes: &[u8]) -> Result<Self> {
+        codec::from_msgpack::<ElementData>(bytes)
+            .map_err(document_error)?
+            .try_into()
+    }
+}
+
+#[cfg(any(feature = "python", test))]
+impl PulseLibrary {
+    /// Serialize the library to a versioned JSON document.
+    pub(crate) fn to_json(&self) -> Result<String> {
+        let document = LibraryDocument {
+            version: codec::CURRENT_VERSION,
+            library: self.to_data(),
+        };
+        serde_json::to_string(&document).map_err(document_error)
+    }
+
+    /// Deserialize a library from a versioned JSON document.
+    ///
+    /// Schedule documents with an embedded library are accepted as well.
+    pub(crate) fn from_json(s: &str) -> Result<Self> {
+        let document: LibraryDocument = serde_json::from_str(s).map_err(document_error)?;
+        codec::check_version(document.version).map_err(document_error)?;
+        Self::from_data(document.library)
+    }
+}
+
+fn document_error(e: impl Into<anyhow::Error>) -> ScheduleError {
+    ScheduleError::Document(format!("{:#}", e.into()))
+}
+
+impl Default for CommonData {
+    fn default() -> Self {
+        Self {
+            margin: (Time::ZERO, Time::ZERO),
+            alignment: Alignment::End,
+            phantom: false,
+            duration: None,
+            max_duration: None,
+            min_duration: Time::ZERO,
+            allow_oversize: None,
+        }
+    }
+}
+
+impl From<&Element> for ElementData {
+    fn from(element: &Element) -> Self {
+        let common = &element.common;
+        let max_duration = common.max_duration();
+        let common = CommonData {
+            margin: common.margin(),
+            alignment: common.alignment(),
+            phantom: common.phantom(),
+            duration: common.duration(),
+            max_duration: (max_duration != Time::INFINITY).then_some(max_duration),
+            min_duration: common.min_duration(),
+            allow_oversize: common.allow_oversize(),
+        };
+        Self {
+            common,
+            variant: (&element.variant).into(),
+        }
+    }
+}
+
+impl From<&ElementVariant> for VariantData {
+    fn from(variant: &ElementVariant) -> Self {
+        match variant {
+            ElementVariant::Play(v) => VariantData::Play {
+                channel_id: *v.channel_id(),
+                shape_id: v.shape_id().cloned(),
+                amplitude: v.amplitude(),
+                width: v.width(),
+                plateau: v.plateau(),
+                drag_coef: v.drag_coef(),
+                frequency: v.frequency(),
+                phase: v.phase(),
+                flexible: v.flexible(),
+                hold: v.hold(),
+                phase_reference: v.phase_reference(),
+                pulse: v.pulse_ref().cloned(),
+                calibration: v.calibration().map(Into::into),
+                fall: v.fall().cloned(),
+            },
+            ElementVariant::PlaySamples(v) => VariantData::PlaySamples {
+                channel_id: *v.channel_id(),
+                samples: v.samples().iter().map(|s| [s.re, s.im]).collect(),
+                sample_rate: v.sample_rate(),
+                amplitude: v.amplitude(),
+                frequency: v.frequency(),
+                phase: v.phase(),
+                interpolate: v.interpolate(),
+            },
+            ElementVariant::ShiftPhase(v) => VariantData::ShiftPhase {
+                channel_id: *v.channel_id(),
+                phase: v.phase(),
+            },
+            ElementVariant::SetPhase(v) => VariantData::SetPhase {
+                channel_id: *v.channel_id(),
+                phase: v.phase(),
+            },
+            ElementVariant::ShiftFreq(v) => VariantData::ShiftFreq {
+                channel_id: *v.channel_id(),
+                frequency: v.frequency(),
+            },
+            ElementVariant::SetFreq(v) => VariantData::SetFreq {
+                channel_id: *v.channel_id(),
+                frequency: v.frequency(),
+            },
+            ElementVariant::SwapPhase(v) => VariantData::SwapPhase {
+                channel_id1: *v.channel_id1(),
+                channel_id2: *v.channel_id2(),
+            },
+            ElementVariant::Barrier(v) => VariantData::Barrier {
+                channel_ids: v.channel_ids().to_vec(),
+                flex: v.flex(),
+                slot: v.slot().map(str::to_owned),
+            },
+            ElementVariant::Repeat(v) => VariantData::Repeat {
+                child: Box::new(v.child().as_ref().into()),
+                count: v.count(),
+                count_var: v.count_var().map(Into::into),
+                spacing: v.spacing(),
+            },
+            ElementVariant::Stack(v) => VariantData::Stack {
+                children: v.children().iter().map(|c| c.as_ref().into()).collect(),
+                direction: v.direction(),
+                padding: v.padding(),
+                child_margin: v.child_margin(),
+            },
+            ElementVariant::Absolute(v) => VariantData::Absolute {
+                children: v
+                    .children()
+                    .iter()
+                    .map(|e| AbsoluteEntryData {
+                        time: e.time(),
+                        from_end: e.is_from_end(),
+                        element: e.element().as_ref().into(),
+                    })
+                    .collect(),
+            },
+            ElementVariant::Grid(v) => VariantData::Grid {
+                children: v
+                    .children()
+                    .iter()
+                    .map(|e| GridEntryData {
+                        element: e.element().as_ref().into(),
+                        column: e.column(),
+                        span: e.span(),
+                    })
+                    .collect(),
+                columns: v.columns().to_vec(),
+                padding: v.padding(),
+                child_margin: v.child_margin(),
+            },
+            ElementVariant::ScaleAmplitude(v) => VariantData::ScaleAmplitude {
+                child: Box::new(v.child().as_ref().into()),
+                factor: v.factor(),
+            },
+        }
+    }
+}
+
+/// Parameters of a container variant without its children, with the
+/// parameters of the entries placing the children.
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum ShallowData<'a> {
+    Repeat {
+        count: usize,
+        count_var: Option<&'a str>,
+        spacing: Time,
+    },
+    Stack {
+        direction: Direction,
+        padding: (Time, Time),
+        child_margin: (Time, Time),
+    },
+    Absolute {
+        entries: Vec<(Time, bool)>,
+    },
+    Grid {
+        columns: &'a [GridLength],
+        padding: (Time, Time),
+        child_margin: (Time, Time),
+        entries: Vec<(usize, usize)>,
+    },
+    ScaleAmplitude {
+        factor: f64,
+    },
+}
+
+impl ElementVariant {
+    /// Encoding of the parameters of the variant, and its children.
+    ///
+    /// Leaves are encoded whole, containers without the children so that
+    /// they are compared through the cached hashes of the children. Caches
+    /// are not part of the schema, so variants with the same encoding and
+    /// equal children behave the same.
+    fn shallow(&self) -> (Vec<u8>, Vec<&ElementRef>) {
+        let (data, children) = match self {
+            ElementVariant::Repeat(v) => (
+                ShallowData::Repeat {
+                    count: v.count(),
+                    count_var: v.count_var(),
+                    spacing: v.spacing(),
+                },
+                vec![v.child()],
+            ),
+            ElementVariant::Stack(v) => (
+                ShallowData::Stack {
+                    direction: v.direction(),
+                    padding: v.padding(),
+                    child_margin: v.child_margin(),
+                },
+                v.children().iter().collect(),
+            ),
+            ElementVariant::Absolute(v) => (
+                ShallowData::Absolute {
+                    entries: v
+                        .children()
+                        .iter()
+                        .map(|e| (e.time(), e.is_from_end()))
+                        .collect(),
+                },
+                v.children().iter().map(|e| e.element()).collect(),
+            ),
+            ElementVariant::Grid(v) => (
+                ShallowData::Grid {
+                    columns: v.columns(),
+                    padding: v.padding(),
+                    child_margin: v.child_margin(),
+                    entries: v
+                        .children()
+                        .iter()
+                        .map(|e| (e.column(), e.span()))
+                        .collect(),
+                },
+                v.children().iter().map(|e| e.element()).collect(),
+            ),
+            ElementVariant::ScaleAmplitude(v) => (
+                ShallowData::ScaleAmplitude { factor: v.factor() },
+                vec![v.child()],
+            ),
+            leaf => {
+                let data = rmp_serde::to_vec_named(&VariantData::from(leaf))
+                    .expect("Variant data should serialize");
+                return (data, vec![]);
+            }
+        };
+        let data = rmp_serde::to_vec_named(&data).expect("Variant data should serialize");
+        (data, children)
+    }
+
+    /// Hash of the parameters and the children of the variant.
+    ///
+    /// Containers compute it once from the cached hashes of the children.
+    pub(crate) fn content_hash(&self) -> u64 {
+        let compute = || {
+            let (data, children) = self.shallow();
+            let mut hasher = DefaultHasher::new();
+            data.hash(&mut hasher);
+            children.hash(&mut hasher);
+            hasher.finish()
+        };
+        let cache = match self {
+            ElementVariant::Repeat(v) => v.content_hash(),
+            ElementVariant::Stack(v) => v.content_hash(),
+            ElementVariant::Absolute(v) => v.content_hash(),
+            ElementVariant::Grid(v) => v.content_hash(),
+            ElementVariant::ScaleAmplitude(v) => v.content_hash(),
+            _ => return compute(),
+        };
+        *cache.get_or_init(compute)
+    }
+}
+
+/// Structural equality: variants are equal if their parameters and children
+/// are equal, regardless of whether the children are shared.
+impl PartialEq for ElementVariant {
+    fn eq(&self, other: &Self) -> bool {
+        if std::ptr::eq(self, other) {
+            return true;
+        }
+        if self.content_hash() != other.content_hash() {
+            return false;
+        }
+        let (data, children) = self.shallow();
+        let (other_data, other_children) = other.shallow();
+        data == other_data
+            && children.len() == other_children.len()
+            && children
+                .iter()
+                .zip(&other_children)
+                .all(|(a, b)| Arc::ptr_eq(a, b) || a == b)
+    }
+}
+
+impl Eq for ElementVariant {}
+
+impl Hash for ElementVariant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.content_hash().hash(state);
+    }
+}
+
+impl TryFrom<ElementData> for Element {
+    type Error = ScheduleError;
+
+    fn try_from(data: ElementData) -> Result<Self> {
+        let ElementData { common, variant } = data;
+        let common = ElementCommonBuilder::new()
+            .margin(common.margin)
+            .alignment(common.alignment)
+            .phantom(common.phantom)
+            .duration(common.duration)
+            .max_duration(common.max_duration.unwrap_or(Time::INFINITY))
+            .min_duration(common.min_duration)
+            .allow_oversize(common.allow_oversize)
+            .build()?;
+        let variant: ElementVariant = match variant {
+            VariantData::Play {
+                channel_id,
+                shape_id,
+                amplitude,
+                width,
+                plateau,
+                drag_coef,
+                frequency,
+                phase,
+                flexible,
+                hold,
+                phase_reference,
+                pulse,
+                calibration,
+                fall,
+            } => Play::new(channel_id, shape_id, amplitude, width)?
+                .with_plateau(plateau)?
+                .with_drag_coef(drag_coef)?
+                .with_frequency(frequency)?
+                .with_phase(phase)?
+                .with_flexible(flexible)
+                .with_hold(hold)
+                .with_phase_reference(phase_reference)
+                .with_pulse_ref(pulse)
+                .with_calibration(calibration)
+                .with_fall(fall)?
+                .into(),
+            VariantData::PlaySamples {
+                channel_id,
+                samples,
+                sample_rate,
+                amplitude,
+                frequency,
+                phase,
+                interpolate,
+            } => {
+                let samples = samples
+                    .into_iter()
+                    .map(|[re, im]| Complex64::new(re, im))
+                    .collect();
+                PlaySamples::new(channel_id, samples, sample_rate)?
+                    .with_amplitude(amplitude)?
+                    .with_frequency(frequency)?
+                    .with_phase(phase)?
+                    .with_interpolate(interpolate)
+                    .into()
+            }
+            VariantData::ShiftPhase { channel_id, phase } => {
+                ShiftPhase::new(channel_id, phase)?.into()
+            }
+            VariantData::SetPhase { channel_id, phase } => SetPhase::new(channel_id, phase)?.into(),
+            VariantData::ShiftFreq {
+                channel_id,
+                frequency,
+            } => ShiftFreq::new(channel_id, frequency)?.into(),
+            VariantData::SetFreq {
+                channel_id,
+                frequency,
+            } => SetFreq::new(channel_id, frequency)?.into(),
+            VariantData::SwapPhase {
+                channel_id1,
+                channel_id2,
+            } => SwapPhase::new(channel_id1, channel_id2).into(),
+            VariantData::Barrier {
+                channel_ids,
+                flex,
+                slot,
+            } => Barrier::new(channel_ids)
+                .with_flex(flex)?
+                .with_slot(slot)
+                .into(),
+            VariantData::Repeat {
+                child,
+                count,
+                count_var,
+                spacing,
+            } => Repeat::new(Arc::new((*child).try_into()?), count)
+                .with_count_var(count_var)?
+                .with_spacing(spacing)?
+                .into(),
+            VariantData::Stack {
+                children,
+                direction,
+                padding,
+                child_margin,
+            } => {
+                let children = children
+                    .into_iter()
+                    .map(|c| Ok(Arc::new(c.try_into()?)))
+                    .collect::<Result<_>>()?;
+                Stack::new()
+                    .with_children(children)
+                    .with_direction(direction)
+                    .with_padding(padding)?
+                    .with_child_margin(child_margin)?
+                    .into()
+            }
+            VariantData::Absolute { children } => {
+                let children = children
+                    .into_iter()
+                    .map(|e| {
+                        AbsoluteEntry::new(Arc::new(e.element.try_into()?))
+                            .with_time(e.time)?
+                            .with_from_end(e.from_end)
+                    })
+                    .collect::<Result<_>>()?;
+                Absolute::new().with_children(children).into()
+            }
+            VariantData::Grid {
+                children,
+                columns,
+                padding,
+                child_margin,
+            } => {
+                let children = children
+                    .into_iter()
+                    .map(|e| {
+                        GridEntry::new(Arc::new(e.element.try_into()?))
+                            .with_column(e.column)
+                            .with_span(e.span)
+                    })
+                    .collect::<Result<_>>()?;
+                Grid::new()
+                    .with_children(children)
+                    .with_columns(columns)
+                    .with_padding(padding)?
+                    .with_child_margin(child_margin)?
+                    .into()
+            }
+            VariantData::ScaleAmplitude { child, factor } => {
+                ScaleAmplitude::new(Arc::new((*child).try_into()?), factor)?.into()
+            }
+        };
+        Ok(Element::new(common, variant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(channel: &str) -> Arc<Element> {
+        let variant = Play::new(
+            ChannelId::new(channel),
+            Some(ShapeId::new("hann")),
+            Amplitude::new(0.5).unwrap(),
+            Time::new(20e-9).unwrap(),
+        )
+        .unwrap()
+        .with_drag_coef(1e-9)
+        .unwrap()
+        .with_phase_reference(PhaseReference::Iteration);
+        let common = ElementCommonBuilder::new()
+            .margin((Time::new(1e-9).unwrap(), Time::ZERO))
+            .build()
+            .unwrap();
+        Arc::new(Element::new(common, variant))
+    }
+
+    #[test]
+    fn round_trip() {
+        let grid = Grid::new()
+            .with_columns(vec!["auto".parse().unwrap(), "2*".parse().unwrap()])
+            .with_children(vec![GridEntry::new(play("q0")).with_column(1)])
+            .with_padding((Time::new(2e-9).unwrap(), Time::ZERO))
+            .unwrap();
+        let absolute = Absolute::new().with_children(vec![
+            AbsoluteEntry::new(play("q1"))
+                .with_time(Time::new(10e-9).unwrap())
+                .unwrap(),
+            AbsoluteEntry::new(play("q0"))
+                .with_time(Time::new(-30e-9).unwrap())
+                .unwrap()
+                .with_from_end(true)
+                .unwrap(),
+        ]);
+        let repeat = Repeat::new(play("q0"), 3)
+            .with_count_var(Some("n".to_string()))
+            .unwrap()
+            .with_spacing(Time::new(5e-9).unwrap())
+            .unwrap();
+        let stack = Stack::new()
+            .with_direction(Direction::Forward)
+            .with_children(vec![
+                Arc::new(Element::new(
+                    ElementCommonBuilder::new().build().unwrap(),
+                    grid,
+                )),
+                Arc::new(Element::new(
+                    ElementCommonBuilder::new()
+                        .duration(Some(Time::new(1e-6).unwrap()))
+                        .build()
+                        .unwrap(),
+                    absolute,
+                )),
+                Arc::new(Element::new(
+                    ElementCommonBuilder::new().build().unwrap(),
+                    repeat,
+                )),
+                Arc::new(Element::new(
+                    ElementCommonBuilder::new().build().unwrap(),
+                    Barrier::new(vec![ChannelId::new("q0")])
+                        .with_flex(2.0)
+                        .unwrap(),
+                )),
+                Arc::new(Element::new(
+                    ElementCommonBuilder::new().build().unwrap(),
+                    ScaleAmplitude::new(play("q1"), 0.5).unwrap(),
+                )),
+            ])
+            .with_child_margin((Time::new(1e-9).unwrap(), Time::new(1e-9).unwrap()))
+            .unwrap();
+        let root = Element::new(
+            ElementCommonBuilder::new()
+                .max_duration(Time::new(2e-6).unwrap())
+                .build()
+                .unwrap(),
+            stack,
+        );
+
+        let json = root.to_json(None).unwrap();
+        let decoded = Element::from_json(&json).unwrap();
+        assert_eq!(decoded.to_json(None).unwrap(), json);
+
+        let bytes = root.to_msgpack(None).unwrap();
+        let decoded = Element::from_msgpack(&bytes).unwrap();
+        assert_eq!(decoded.to_json(None).unwrap(), json);
+    }
+
+    #[test]
+    fn structural_eq() {
+        use std::hash::BuildHasher;
+
+        let stack = |children| {
+            Arc::new(Element::new(
+                ElementCommonBuilder::new().build().unwrap(),
+                Stack::new().with_children(children),
+            ))
+        };
+        let shared = play("q0");
+        let a = stack(vec![shared.clone(), shared]);
+        let b = stack(vec![play("q0"), play("q0")]);
+        let c = stack(vec![play("q0"), play("q1")]);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        let hasher = hashbrown::hash_map::DefaultHashBuilder::default();
+        assert_eq!(hasher.hash_one(&a), hasher.hash_one(&b));
+        // The hash of a container is cached after the first comparison.
+        let ElementVariant::Stack(v) = &a.variant else {
+            unreachable!()
+        };
+        assert_eq!(v.content_hash().get(), Some(&a.variant.content_hash()));
+
+        // Parameters of the entries are part of the container.
+        let absolute = |time| {
+            ElementVariant::from(Absolute::new().with_children(vec![
+                AbsoluteEntry::new(play("q0"))
+                    .with_time(Time::new(time).unwrap())
+                    .unwrap(),
+            ]))
+        };
+        assert_eq!(absolute(1e-9), absolute(1e-9));
+        assert_ne!(absolute(1e-9), absolute(2e-9));
+    }
+
+    #[test]
+    fn defaults() {
+        let json = r#"{
+            "version": 1,
+            "schedule": {
+                "type": "Stack",
+                "children": [
+                    {"type": "Play", "channel_id": "q0", "shape_id": null, "amplitude": 0.1, "width": 1e-8}
+                ]
+            }
+        }"#;
+
+        let element = Element::from_json(json).unwrap();
+
+        assert_eq!(element.common.max_duration(), Time::INFINITY);
+        let ElementVariant::Stack(stack) = &element.variant else {
+            panic!("Expected Stack variant");
+        };
+        assert_eq!(stack.direction(), Direction::Backward);
+        assert_eq!(stack.children().len(), 1);
+    }
+
+    #[test]
+    fn library_provenance() {
+        let mut library = PulseLibrary::new();
+        let pulse = crate::schedule::PulseDef::new(
+            Some(ShapeId::new("hann")),
+            Amplitude::new(0.3).unwrap(),
+            Time::new(20e-9).unwrap(),
+        )
+        .unwrap();
+        library.register("x90".into(), Some(3), pulse).unwrap();
+        let play = library.play(ChannelId::new("q0"), "x90", None).unwrap();
+        let root = Element::new(ElementCommonBuilder::new().build().unwrap(), play);
+
+        let json = root.to_json(Some(&library)).unwrap();
+        let ElementVariant::Play(decoded) = Element::from_json(&json).unwrap().variant else {
+            panic!("Expected Play variant");
+        };
+        assert_eq!(
+            decoded.pulse_ref(),
+            Some(&PulseRef {
+                name: "x90".into(),
+                version: 3
+            })
+        );
+        let embedded = PulseLibrary::from_json(&json).unwrap();
+        assert_eq!(embedded.to_json().unwrap(), library.to_json().unwrap());
+        assert!(!root.to_json(None).unwrap().contains("library"));
+    }
+
+    #[test]
+    fn invalid_document() {
+        let unsupported = r#"{"version": 3, "schedule": {"type": "Barrier"}}"#;
+        assert!(matches!(
+            Element::from_json(unsupported),
+            Err(ScheduleError::Document(_))
+        ));
+        let negative_width = r#"{
+            "version": 1,
+            "schedule": {"type": "Play", "channel_id": "q0", "shape_id": null, "amplitude": 0.1, "width": -1.0}
+        }"#;
+        assert_eq!(
+            Element::from_json(negative_width).unwrap_err(),
+            ScheduleError::InvalidTime {
+                property: "width",
+                value: -1.0
+            }
+        );
+        let repeat = |count, count_var| {
+            format!(
+                r#"{{
+                    "version": 2,
+                    "schedule": {{"type": "Repeat", "child": {{"type": "Barrier"}}, "count": {}, "count_var": "{}"}}
+                }}"#,
+                count, count_var
+            )
+        };
+        assert!(Element::from_json(&repeat(2, "n")).is_ok());
+        assert!(matches!(
+            Element::from_json(&repeat(2, "n); playZero(32")),
+            Err(ScheduleError::InvalidVariable { .. })
+        ));
+        assert!(matches!(
+            Element::from_json(&repeat(0, "n")),
+            Err(ScheduleError::ZeroCountVar { .. })
+        ));
+    }
+
+    #[test]
+    fn required_version() {
+        let plain = Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            Stack::new().with_children(vec![Arc::new(Element::new(
+                ElementCommonBuilder::new().build().unwrap(),
+                Barrier::new(vec![ChannelId::new("q0")]),
+            ))]),
+        );
+        let json = plain.to_json(None).unwrap();
+        assert!(!json.contains("padding") && !json.contains("phase_reference"));
+        assert!(Element::from_msgpack(&plain.to_msgpack(Some(1)).unwrap()).is_ok());
+
+        let hold = Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            Play::new(
+                ChannelId::new("q0"),
+                None,
+                Amplitude::new(0.1).unwrap(),
+                Time::new(1e-8).unwrap(),
+            )
+            .unwrap()
+            .with_hold(true),
+        ));
+        let nested = Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            Stack::new().with_children(vec![hold]),
+        );
+        assert!(nested
+            .to_json(None)
+            .unwrap()
+            .starts_with(r#"{"version":2,"#));
+        assert!(matches!(
+            nested.to_msgpack(Some(1)),
+            Err(ScheduleError::Document(_))
+        ));
+        assert!(nested.to_msgpack(Some(2)).is_ok());
+
+        let downgraded =
+            nested
+                .to_json(None)
+                .unwrap()
+                .replacen(r#""version":2"#, r#""version":1"#, 1);
+        assert!(matches!(
+            Element::from_json(&downgraded),
+            Err(ScheduleError::Document(_))
+        ));
+    }
+}
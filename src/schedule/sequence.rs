@@ -0,0 +1,123 @@
+//! Sequence tables of interleaved schedules.
+use hashbrown::HashMap;
+
+use crate::schedule::{ElementRef, Result, ScheduleError};
+
+/// Entry of a [`SequenceTable`], `repeat` consecutive shots of a segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SequenceEntry {
+    pub(crate) segment: usize,
+    pub(crate) repeat: usize,
+}
+
+/// Shot ordering of a few fixed schedules, e.g. for interleaved randomized
+/// benchmarking.
+///
+/// Equal schedules share a segment, so every segment is compiled once and
+/// backends with a sequencer play the segments in the order of the entries.
+/// Consecutive shots of the same segment are merged into one entry.
+#[derive(Debug, Clone)]
+pub(crate) struct SequenceTable {
+    segments: Vec<ElementRef>,
+    entries: Vec<SequenceEntry>,
+}
+
+impl SequenceTable {
+    /// Table playing `schedules[order[0]]`, `schedules[order[1]]`, and so on.
+    ///
+    /// Segments are in the order of their first shot and schedules that are
+    /// never played are dropped.
+    pub(crate) fn new(schedules: &[ElementRef], order: &[usize]) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut ids = HashMap::new();
+        let mut entries: Vec<SequenceEntry> = Vec::new();
+        for &index in order {
+            let schedule = schedules
+                .get(index)
+                .ok_or(ScheduleError::UnknownSchedule { index })?;
+            let segment = *ids.entry(schedule).or_insert_with(|| {
+                segments.push(schedule.clone());
+                segments.len() - 1
+            });
+            match entries.last_mut() {
+                Some(last) if last.segment == segment => last.repeat += 1,
+                _ => entries.push(SequenceEntry { segment, repeat: 1 }),
+            }
+        }
+        Ok(Self { segments, entries })
+    }
+
+    pub(crate) fn segments(&self) -> &[ElementRef] {
+        &self.segments
+    }
+
+    pub(crate) fn entries(&self) -> &[SequenceEntry] {
+        &self.entries
+    }
+
+    /// Total number of shots.
+    pub(crate) fn shots(&self) -> usize {
+        self.entries.iter().map(|e| e.repeat).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        quant::{Amplitude, ChannelId, Time},
+        schedule::{Element, ElementCommonBuilder, Play},
+    };
+
+    #[test]
+    fn table() {
+        let play = |amplitude| {
+            let play = Play::new(
+                ChannelId::new("q0"),
+                None,
+                Amplitude::new(amplitude).unwrap(),
+                Time::new(10e-9).unwrap(),
+            )
+            .unwrap();
+            Arc::new(Element::new(
+                ElementCommonBuilder::new().build().unwrap(),
+                play,
+            ))
+        };
+        // The first and last schedule are equal, the third one is not played.
+        let schedules = [play(0.5), play(0.25), play(0.1), play(0.5)];
+
+        let table = SequenceTable::new(&schedules, &[1, 0, 3, 1, 0]).unwrap();
+
+        assert_eq!(table.segments().len(), 2);
+        assert!(Arc::ptr_eq(&table.segments()[0], &schedules[1]));
+        assert_eq!(
+            table.entries(),
+            [
+                SequenceEntry {
+                    segment: 0,
+                    repeat: 1
+                },
+                SequenceEntry {
+                    segment: 1,
+                    repeat: 2
+                },
+                SequenceEntry {
+                    segment: 0,
+                    repeat: 1
+                },
+                SequenceEntry {
+                    segment: 1,
+                    repeat: 1
+                },
+            ]
+        );
+        assert_eq!(table.shots(), 5);
+        assert!(matches!(
+            SequenceTable::new(&schedules, &[4]),
+            Err(ScheduleError::UnknownSchedule { index: 4 })
+        ));
+    }
+}
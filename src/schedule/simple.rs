@@ -1,8 +1,6 @@
-use anyhow::{bail, Result};
-
 use crate::{
     quant::{ChannelId, Frequency, Phase, Time},
-    schedule::Measure,
+    schedule::{finite_value, ChannelSet, Measure, Result, ScheduleError},
 };
 
 #[derive(Debug, Clone)]
@@ -36,14 +34,14 @@ pub(crate) struct SwapPhase {
 
 #[derive(Debug, Clone)]
 pub(crate) struct Barrier {
-    channel_ids: Vec<ChannelId>,
+    channel_ids: ChannelSet,
+    flex: f64,
+    slot: Option<String>,
 }
 
 impl ShiftPhase {
     pub(crate) fn new(channel_id: ChannelId, phase: Phase) -> Result<Self> {
-        if !phase.value().is_finite() {
-            bail!("Invalid phase {:?}", phase);
-        }
+        let phase = finite_value("phase", phase)?;
         Ok(Self {
             channel_ids: [channel_id],
             phase,
@@ -61,9 +59,7 @@ impl ShiftPhase {
 
 impl SetPhase {
     pub(crate) fn new(channel_id: ChannelId, phase: Phase) -> Result<Self> {
-        if !phase.value().is_finite() {
-            bail!("Invalid phase {:?}", phase);
-        }
+        let phase = finite_value("phase", phase)?;
         Ok(Self {
             channel_ids: [channel_id],
             phase,
@@ -81,9 +77,7 @@ impl SetPhase {
 
 impl ShiftFreq {
     pub(crate) fn new(channel_id: ChannelId, frequency: Frequency) -> Result<Self> {
-        if !frequency.value().is_finite() {
-            bail!("Invalid frequency {:?}", frequency);
-        }
+        let frequency = finite_value("frequency", frequency)?;
         Ok(Self {
             channel_ids: [channel_id],
             frequency,
@@ -101,9 +95,7 @@ impl ShiftFreq {
 
 impl SetFreq {
     pub(crate) fn new(channel_id: ChannelId, frequency: Frequency) -> Result<Self> {
-        if !frequency.value().is_finite() {
-            bail!("Invalid frequency {:?}", frequency);
-        }
+        let frequency = finite_value("frequency", frequency)?;
         Ok(Self {
             channel_ids: [channel_id],
             frequency,
@@ -137,14 +129,79 @@ impl SwapPhase {
 
 impl Barrier {
     pub(crate) fn new(channel_ids: Vec<ChannelId>) -> Self {
-        Self { channel_ids }
+        Self {
+            channel_ids: ChannelSet::from_slice(&channel_ids),
+            flex: 0.0,
+            slot: None,
+        }
+    }
+
+    /// Weight of the barrier in the distribution of the slack of a stretched
+    /// [`Stack`](crate::schedule::Stack) parent, like `flex-grow` in CSS.
+    pub(crate) fn with_flex(mut self, flex: f64) -> Result<Self> {
+        if !(flex.is_finite() && flex >= 0.0) {
+            return Err(ScheduleError::InvalidValue {
+                property: "flex",
+                value: flex,
+            });
+        }
+        self.flex = flex;
+        Ok(self)
+    }
+
+    /// Mark the barrier as the placeholder of a template, see
+    /// [`fill_slots`](crate::schedule::fill_slots).
+    pub(crate) fn with_slot(mut self, slot: Option<String>) -> Self {
+        self.slot = slot;
+        self
     }
 
     pub(crate) fn channel_ids(&self) -> &[ChannelId] {
         &self.channel_ids
     }
+
+    pub(crate) fn flex(&self) -> f64 {
+        self.flex
+    }
+
+    pub(crate) fn slot(&self) -> Option<&str> {
+        self.slot.as_deref()
+    }
+
+    #[cfg(feature = "python")]
+    pub(crate) fn map_channels(&self, f: impl Fn(ChannelId) -> ChannelId) -> Self {
+        Self {
+            channel_ids: ChannelSet::union(self.channel_ids.iter().map(|&c| f(c))),
+            flex: self.flex,
+            slot: self.slot.clone(),
+        }
+    }
 }
 
+#[cfg(feature = "_frontend")]
+macro_rules! impl_map_channels {
+    ($t:ty) => {
+        impl $t {
+            pub(crate) fn map_channels(&self, f: impl Fn(ChannelId) -> ChannelId) -> Self {
+                let mut v = self.clone();
+                v.channel_ids = self.channel_ids.map(f);
+                v
+            }
+        }
+    };
+}
+
+#[cfg(feature = "_frontend")]
+impl_map_channels!(ShiftPhase);
+#[cfg(feature = "_frontend")]
+impl_map_channels!(SetPhase);
+#[cfg(feature = "_frontend")]
+impl_map_channels!(ShiftFreq);
+#[cfg(feature = "_frontend")]
+impl_map_channels!(SetFreq);
+#[cfg(feature = "python")]
+impl_map_channels!(SwapPhase);
+
 macro_rules! impl_measure {
     ($t:ty) => {
         impl Measure for $t {
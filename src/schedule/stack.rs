@@ -1,10 +1,16 @@
 mod helper;
 
-use std::sync::OnceLock;
+use std::{borrow::Cow, sync::OnceLock};
 
+use hashbrown::HashMap;
+
+use crate::diagnostics::BarrierWait;
 use crate::{
     quant::{ChannelId, Time},
-    schedule::{merge_channel_ids, stack::helper::Helper, Arranged, ElementRef, Measure},
+    schedule::{
+        extra_margin, finite_margin, measure_children, merge_channel_ids, stack::helper::Helper,
+        Arranged, ChannelSet, ElementRef, ElementVariant, Margin, Measure, Measured, Result,
+    },
     Direction,
 };
 
@@ -14,12 +20,16 @@ use super::{Arrange, TimeRange};
 pub(crate) struct Stack {
     children: Vec<ElementRef>,
     direction: Direction,
-    channel_ids: Vec<ChannelId>,
+    padding: Margin,
+    child_margin: Margin,
+    channel_ids: ChannelSet,
     measure_result: OnceLock<MeasureResult>,
+    content_hash: OnceLock<u64>,
 }
 
 #[derive(Debug, Clone)]
 struct MeasureResult {
+    /// Duration of the children without the padding.
     total_duration: Time,
     child_timings: Vec<TimeRange>,
 }
@@ -32,24 +42,165 @@ impl Stack {
     pub(crate) fn with_direction(mut self, direction: Direction) -> Self {
         self.direction = direction;
         self.measure_result.take();
+        self.content_hash.take();
         self
     }
 
+    /// Space inside the stack before and after the children.
+    pub(crate) fn with_padding(mut self, padding: (Time, Time)) -> Result<Self> {
+        self.padding = finite_margin("padding", padding)?;
+        self.measure_result.take();
+        self.content_hash.take();
+        Ok(self)
+    }
+
+    /// Minimum margin of the children. Children with a smaller margin are
+    /// arranged as if they had this margin.
+    pub(crate) fn with_child_margin(mut self, child_margin: (Time, Time)) -> Result<Self> {
+        self.child_margin = finite_margin("child_margin", child_margin)?;
+        self.measure_result.take();
+        self.content_hash.take();
+        Ok(self)
+    }
+
     pub(crate) fn with_children(mut self, children: Vec<ElementRef>) -> Self {
         let channel_ids = merge_channel_ids(children.iter().map(|e| e.channels()));
         self.children = children;
         self.channel_ids = channel_ids;
         self.measure_result.take();
+        self.content_hash.take();
         self
     }
 
+    pub(crate) fn with_child(&self, index: usize, child: ElementRef) -> Option<Self> {
+        let mut children = self.children.clone();
+        *children.get_mut(index)? = child;
+        Some(self.with_layout_children(children))
+    }
+
+    #[cfg(feature = "_frontend")]
+    pub(crate) fn map_children(&self, f: impl FnMut(&ElementRef) -> ElementRef) -> Self {
+        self.with_layout_children(self.children.iter().map(f).collect())
+    }
+
+    /// New stack with the layout properties of `self` and `children`.
+    fn with_layout_children(&self, children: Vec<ElementRef>) -> Self {
+        Self {
+            direction: self.direction,
+            padding: self.padding.clone(),
+            child_margin: self.child_margin.clone(),
+            ..Self::default()
+        }
+        .with_children(children)
+    }
+
+    pub(crate) fn children(&self) -> &[ElementRef] {
+        &self.children
+    }
+
+    /// Cache of [`ElementVariant::content_hash`](super::ElementVariant::content_hash).
+    pub(crate) fn content_hash(&self) -> &OnceLock<u64> {
+        &self.content_hash
+    }
+
     pub(crate) fn direction(&self) -> Direction {
         self.direction
     }
 
+    pub(crate) fn padding(&self) -> (Time, Time) {
+        self.padding.get()
+    }
+
+    pub(crate) fn child_margin(&self) -> (Time, Time) {
+        self.child_margin.get()
+    }
+
     fn measure_result(&self) -> &MeasureResult {
-        self.measure_result
-            .get_or_init(|| measure_stack(&self.children, &self.channel_ids, self.direction))
+        self.measure_result.get_or_init(|| {
+            let spans = measure_children(&self.children, |c| {
+                let (start, end) = extra_margin(c, self.child_margin());
+                c.measure() + start + end
+            });
+            let children = self
+                .children
+                .iter()
+                .zip(spans)
+                .map(|(element, span)| Measured { element, span });
+            measure_stack(children, &self.channel_ids, self.direction)
+        })
+    }
+
+    /// Child timings when the children are arranged in `span`.
+    ///
+    /// The slack beyond the measured duration is distributed to the barrier
+    /// children with a positive flex weight, proportionally to the weights,
+    /// and the children are stacked again. Without flexible barriers all the
+    /// slack collects at the end opposite to the direction.
+    fn timings(&self, span: Time) -> Cow<'_, [TimeRange]> {
+        let MeasureResult {
+            total_duration,
+            child_timings,
+        } = self.measure_result();
+        let flex = |element: &ElementRef| match &element.variant {
+            ElementVariant::Barrier(v) if !element.common.phantom() => v.flex(),
+            _ => 0.0,
+        };
+        let total_flex: f64 = self.children.iter().map(flex).sum();
+        let slack = span - *total_duration;
+        if !(slack > Time::ZERO && total_flex > 0.0) {
+            return Cow::Borrowed(child_timings);
+        }
+        let children = self
+            .children
+            .iter()
+            .zip(child_timings)
+            .map(|(element, timing)| Measured {
+                element,
+                span: timing.span + slack * (flex(element) / total_flex),
+            });
+        Cow::Owned(measure_stack(children, &self.channel_ids, self.direction).child_timings)
+    }
+}
+
+impl Stack {
+    /// Idle time the barrier children add to their channels when the stack
+    /// is arranged in `time_range`.
+    ///
+    /// A barrier starts when the last of its channels is free. The other
+    /// channels wait from their last use in the stack, or from the start of
+    /// the stack, until the barrier. A barrier without channels waits for all
+    /// channels of the stack. Slack given to flexible barriers counts as wait.
+    pub(crate) fn barrier_waits(&self, time_range: TimeRange) -> Vec<BarrierWait> {
+        let child_timings = self.timings(time_range.shrink(self.padding()).span);
+        let arranged: Vec<_> = self.arrange(time_range).collect();
+        let order: Vec<_> = match self.direction {
+            Direction::Forward => (0..self.children.len()).collect(),
+            Direction::Backward => (0..self.children.len()).rev().collect(),
+        };
+        let mut usage: HashMap<ChannelId, Time> = HashMap::new();
+        let mut waits = Vec::new();
+        for i in order {
+            let child = &self.children[i];
+            let TimeRange { start, span } = child_timings[i];
+            let channels = match child.channels() {
+                [] => &self.channel_ids[..],
+                channels => channels,
+            };
+            if matches!(child.variant, ElementVariant::Barrier(_)) && !child.common.phantom() {
+                waits.extend(channels.iter().filter_map(|&channel| {
+                    let wait = start - usage.get(&channel).copied().unwrap_or_default();
+                    (wait > Time::ZERO).then_some(BarrierWait {
+                        channel,
+                        time: arranged[i].time_range.start,
+                        wait,
+                    })
+                }));
+            }
+            for &channel in channels {
+                usage.insert(channel, start + span);
+            }
+        }
+        waits
     }
 }
 
@@ -58,8 +209,11 @@ impl Default for Stack {
         Self {
             children: vec![],
             direction: Direction::Backward,
-            channel_ids: vec![],
+            padding: Margin::default(),
+            child_margin: Margin::default(),
+            channel_ids: ChannelSet::new(),
             measure_result: OnceLock::new(),
+            content_hash: OnceLock::new(),
         }
     }
 }
@@ -67,7 +221,8 @@ impl Default for Stack {
 impl Measure for Stack {
     fn measure(&self) -> Time {
         let MeasureResult { total_duration, .. } = self.measure_result();
-        *total_duration
+        let (start, end) = self.padding();
+        *total_duration + start + end
     }
 
     fn channels(&self) -> &[ChannelId] {
@@ -77,31 +232,28 @@ impl Measure for Stack {
 
 impl Arrange for Stack {
     fn arrange(&self, time_range: TimeRange) -> impl Iterator<Item = Arranged<&ElementRef>> {
-        let MeasureResult { child_timings, .. } = self.measure_result();
-        self.children.iter().zip(child_timings).map(
-            move |(
-                item,
-                &TimeRange {
-                    start: child_start,
-                    span: child_span,
-                },
-            )| {
-                let final_start = match self.direction {
-                    Direction::Forward => time_range.start + child_start,
-                    Direction::Backward => {
-                        time_range.start + time_range.span - child_start - child_span
-                    }
-                };
-                let child_time_range = TimeRange {
-                    start: final_start,
-                    span: child_span,
-                };
-                Arranged {
-                    item,
-                    time_range: child_time_range,
+        let time_range = time_range.shrink(self.padding());
+        let child_timings = self.timings(time_range.span);
+        self.children.iter().enumerate().map(move |(i, item)| {
+            let TimeRange {
+                start: child_start,
+                span: child_span,
+            } = child_timings[i];
+            let final_start = match self.direction {
+                Direction::Forward => time_range.start + child_start,
+                Direction::Backward => {
+                    time_range.start + time_range.span - child_start - child_span
                 }
-            },
-        )
+            };
+            let child_time_range = TimeRange {
+                start: final_start,
+                span: child_span,
+            };
+            Arranged {
+                item,
+                time_range: child_time_range.shrink(extra_margin(item, self.child_margin())),
+            }
+        })
     }
 }
 
@@ -144,10 +296,15 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use test_case::test_case;
 
     use super::*;
-    use crate::schedule::MockMeasure;
+    use crate::{
+        quant::Amplitude,
+        schedule::{Barrier, Element, ElementCommonBuilder, ElementVariant, MockMeasure, Play},
+    };
 
     #[test_case(Direction::Forward; "forward")]
     #[test_case(Direction::Backward; "backward")]
@@ -246,4 +403,197 @@ mod tests {
             mock
         }
     }
+
+    #[test]
+    fn wide_stack() {
+        let names = ["a", "b", "c"];
+        let mut usage = [0.0; 3];
+        let children = (0..3000)
+            .map(|i| {
+                let duration = (i % 7) as f64;
+                usage[i % 3] += duration;
+                let play = Play::new(
+                    ChannelId::new(names[i % 3]),
+                    None,
+                    Amplitude::new(1.0).unwrap(),
+                    Time::new(duration).unwrap(),
+                )
+                .unwrap();
+                Arc::new(Element::new(
+                    ElementCommonBuilder::new().build().unwrap(),
+                    play,
+                ))
+            })
+            .collect();
+
+        let stack = Stack::new().with_children(children);
+
+        let expected = usage.into_iter().fold(0.0, f64::max);
+        assert!((stack.measure().value() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn replace_child() {
+        let play = |channel: &str, duration: f64| {
+            let play = Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(1.0).unwrap(),
+                Time::new(duration).unwrap(),
+            )
+            .unwrap();
+            Arc::new(Element::new(
+                ElementCommonBuilder::new().build().unwrap(),
+                play,
+            ))
+        };
+        let inner = Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            Stack::new().with_children(vec![play("a", 1.0), play("a", 2.0)]),
+        ));
+        let stack = Stack::new().with_children(vec![inner.clone(), play("b", 4.0)]);
+        assert_eq!(stack.measure(), Time::new(4.0).unwrap());
+
+        let replaced = stack.with_child(1, play("a", 5.0)).unwrap();
+        assert!(Arc::ptr_eq(&replaced.children()[0], &inner));
+        let ElementVariant::Stack(v) = &inner.variant else {
+            unreachable!()
+        };
+        assert!(v.measure_result.get().is_some());
+        assert_eq!(replaced.measure(), Time::new(8.0).unwrap());
+        assert_eq!(replaced.channels(), [ChannelId::new("a")]);
+        assert!(stack.with_child(2, play("a", 1.0)).is_none());
+    }
+
+    #[test_case(Direction::Forward, &[0.0, 10.0, 20.0, 30.0, 60.0]; "forward")]
+    #[test_case(Direction::Backward, &[0.0, 10.0, 20.0, 30.0, 60.0]; "backward")]
+    fn flex_barriers(direction: Direction, starts: &[f64]) {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = Arc::new(Element::new(
+            common.clone(),
+            Play::new(
+                ChannelId::new("a"),
+                None,
+                Amplitude::new(1.0).unwrap(),
+                Time::new(10.0).unwrap(),
+            )
+            .unwrap(),
+        ));
+        let barrier = |flex| {
+            Arc::new(Element::new(
+                common.clone(),
+                Barrier::new(vec![]).with_flex(flex).unwrap(),
+            ))
+        };
+        let stack = Stack::new().with_direction(direction).with_children(vec![
+            play.clone(),
+            barrier(1.0),
+            play.clone(),
+            barrier(3.0),
+            play,
+        ]);
+        assert_eq!(stack.measure(), Time::new(30.0).unwrap());
+
+        let arranged: Vec<_> = stack
+            .arrange(TimeRange {
+                start: Time::ZERO,
+                span: Time::new(70.0).unwrap(),
+            })
+            .map(|a| a.time_range.start.value())
+            .collect();
+        assert_eq!(arranged, starts);
+        assert!(Barrier::new(vec![]).with_flex(-1.0).is_err());
+    }
+
+    #[test_case(Direction::Forward, 30.0; "forward")]
+    #[test_case(Direction::Backward, 20.0; "backward")]
+    fn barrier_waits(direction: Direction, time: f64) {
+        let t = |v| Time::new(v).unwrap();
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = |channel: &str, duration| {
+            let play = Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(1.0).unwrap(),
+                t(duration),
+            )
+            .unwrap();
+            Arc::new(Element::new(common.clone(), play))
+        };
+        let barrier = Arc::new(Element::new(common.clone(), Barrier::new(vec![])));
+        // a: 10 + 20, b: 30, c: 5, then 20 on every channel after the barrier.
+        let stack = Stack::new().with_direction(direction).with_children(vec![
+            play("a", 10.0),
+            play("a", 20.0),
+            play("b", 25.0),
+            play("c", 5.0),
+            barrier,
+            play("a", 20.0),
+            play("b", 20.0),
+            play("c", 20.0),
+        ]);
+        let stack = match direction {
+            Direction::Forward => stack,
+            // Mirror the children so the barrier waits for the same channels.
+            Direction::Backward => {
+                let mut children = stack.children().to_vec();
+                children.reverse();
+                stack.with_layout_children(children)
+            }
+        };
+
+        let waits = stack.barrier_waits(TimeRange {
+            start: Time::ZERO,
+            span: stack.measure(),
+        });
+
+        let waits: Vec<_> = waits
+            .into_iter()
+            .map(|w| (w.channel.to_string(), w.time.value(), w.wait.value()))
+            .collect();
+        assert_eq!(
+            waits
+                .iter()
+                .map(|(c, _, w)| (c.as_str(), *w))
+                .collect::<Vec<_>>(),
+            [("b", 5.0), ("c", 25.0)]
+        );
+        assert!(waits.iter().all(|(_, t, _)| *t == time));
+    }
+
+    #[test]
+    fn padding_and_child_margin() {
+        let t = |v| Time::new(v).unwrap();
+        let play = |margin| {
+            let common = ElementCommonBuilder::new().margin(margin).build().unwrap();
+            let play = Play::new(
+                ChannelId::new("a"),
+                None,
+                Amplitude::new(1.0).unwrap(),
+                t(10.0),
+            )
+            .unwrap();
+            Arc::new(Element::new(common, play))
+        };
+        let stack = Stack::new()
+            .with_direction(Direction::Forward)
+            .with_children(vec![play((t(0.0), t(0.0))), play((t(3.0), t(0.0)))])
+            .with_padding((t(5.0), t(5.0)))
+            .unwrap()
+            .with_child_margin((t(2.0), t(2.0)))
+            .unwrap();
+        assert_eq!(stack.measure(), t(39.0));
+
+        let arranged: Vec<_> = stack
+            .arrange(TimeRange {
+                start: Time::ZERO,
+                span: t(39.0),
+            })
+            .map(|a| (a.time_range.start.value(), a.time_range.span.value()))
+            .collect();
+        assert_eq!(arranged, [(7.0, 10.0), (19.0, 13.0)]);
+        let replaced = stack.with_child(0, play((t(0.0), t(0.0)))).unwrap();
+        assert_eq!(replaced.measure(), t(39.0));
+        assert!(Stack::new().with_padding((Time::INFINITY, t(0.0))).is_err());
+    }
 }
@@ -49,7 +49,7 @@ impl<'a> Helper<'a> {
             ChannelUsage::Single(v) => *v = new_duration,
             ChannelUsage::Multiple(d) => {
                 for ch in channels {
-                    d.insert(ch.clone(), new_duration);
+                    d.insert(*ch, new_duration);
                 }
             }
         };
@@ -84,22 +84,19 @@ mod tests {
             .collect::<Vec<_>>();
         let mut helper = Helper::new(&channels);
         assert_eq!(helper.get_usage(&[]), Time::ZERO);
-        assert_eq!(helper.get_usage(&[channels[0].clone()]), Time::ZERO);
+        assert_eq!(helper.get_usage(&[channels[0]]), Time::ZERO);
 
         let t1 = Time::new(10.0).unwrap();
         helper.update_usage(t1, &[]);
         assert_eq!(helper.get_usage(&[]), t1);
-        assert_eq!(helper.get_usage(&[channels[0].clone()]), t1);
+        assert_eq!(helper.get_usage(&[channels[0]]), t1);
 
         let t2 = Time::new(20.0).unwrap();
-        helper.update_usage(t2, &[channels[0].clone()]);
+        helper.update_usage(t2, &[channels[0]]);
         assert_eq!(helper.get_usage(&[]), t2);
-        assert_eq!(helper.get_usage(&[channels[0].clone()]), t2);
-        assert_eq!(helper.get_usage(&[channels[1].clone()]), t1);
-        assert_eq!(
-            helper.get_usage(&[channels[0].clone(), channels[1].clone()]),
-            t2
-        );
+        assert_eq!(helper.get_usage(&[channels[0]]), t2);
+        assert_eq!(helper.get_usage(&[channels[1]]), t1);
+        assert_eq!(helper.get_usage(&[channels[0], channels[1]]), t2);
         assert_eq!(helper.into_max_usage(), t2);
     }
 }
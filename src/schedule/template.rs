@@ -0,0 +1,214 @@
+//! Templates with named slots filled by sub-schedules.
+//!
+//! A slot is a [`Barrier`](crate::schedule::Barrier) with a slot name, e.g.
+//! the gate under test in an echo or randomized benchmarking template. Until
+//! it is filled the slot is arranged like any other barrier.
+use std::sync::Arc;
+
+use hashbrown::{HashMap, HashSet};
+use itertools::Itertools;
+
+use crate::{
+    quant::ChannelId,
+    schedule::{Element, ElementRef, ElementVariant, Measure, Result, ScheduleError},
+};
+
+/// Slot names with their channels in the order of the tree.
+///
+/// A name used by several slots is listed once with the channels of the first
+/// slot.
+pub(crate) fn slots(root: &ElementRef) -> Vec<(String, Vec<ChannelId>)> {
+    fn visit(
+        element: &ElementRef,
+        visited: &mut HashSet<*const Element>,
+        slots: &mut Vec<(String, Vec<ChannelId>)>,
+    ) {
+        if !visited.insert(Arc::as_ptr(element)) {
+            return;
+        }
+        match &element.variant {
+            ElementVariant::Barrier(v) => {
+                if let Some(slot) = v.slot() {
+                    if slots.iter().all(|(s, _)| s != slot) {
+                        slots.push((slot.to_owned(), v.channel_ids().to_vec()));
+                    }
+                }
+            }
+            ElementVariant::Stack(v) => v.children().iter().for_each(|c| visit(c, visited, slots)),
+            ElementVariant::Absolute(v) => v
+                .children()
+                .iter()
+                .for_each(|c| visit(c.element(), visited, slots)),
+            ElementVariant::Grid(v) => v
+                .children()
+                .iter()
+                .for_each(|c| visit(c.element(), visited, slots)),
+            ElementVariant::Repeat(v) => visit(v.child(), visited, slots),
+            ElementVariant::ScaleAmplitude(v) => visit(v.child(), visited, slots),
+            _ => {}
+        }
+    }
+    let mut slots = Vec::new();
+    visit(root, &mut HashSet::new(), &mut slots);
+    slots
+}
+
+/// Copy of the tree with the slots replaced by the elements of `fills`.
+///
+/// The channels of a fill have to be the same as the channels of its slot,
+/// slots without channels accept any fill. Slots without a fill are kept, so
+/// a template can be filled in several steps. Sub-elements shared by multiple
+/// parents stay shared in the copy.
+pub(crate) fn fill_slots(
+    root: &ElementRef,
+    fills: &HashMap<String, ElementRef>,
+) -> Result<ElementRef> {
+    let names = slots(root);
+    if let Some(slot) = fills
+        .keys()
+        .sorted()
+        .find(|k| names.iter().all(|(s, _)| s != *k))
+    {
+        return Err(ScheduleError::UnknownSlot { slot: slot.clone() });
+    }
+    let mut filler = Filler {
+        fills,
+        visited: HashMap::new(),
+        error: None,
+    };
+    let root = filler.fill(root);
+    match filler.error {
+        Some(e) => Err(e),
+        None => Ok(root),
+    }
+}
+
+struct Filler<'a> {
+    fills: &'a HashMap<String, ElementRef>,
+    visited: HashMap<*const Element, ElementRef>,
+    error: Option<ScheduleError>,
+}
+
+impl Filler<'_> {
+    fn fill(&mut self, element: &ElementRef) -> ElementRef {
+        if let Some(filled) = self.visited.get(&Arc::as_ptr(element)) {
+            return filled.clone();
+        }
+        let variant: ElementVariant = match &element.variant {
+            ElementVariant::Barrier(v) => {
+                let Some(fill) = v.slot().and_then(|s| self.fills.get(s)) else {
+                    return element.clone();
+                };
+                let expected = channel_set(v.channel_ids());
+                let actual = channel_set(fill.channels());
+                if !expected.is_empty() && expected != actual {
+                    self.error.get_or_insert(ScheduleError::SlotChannels {
+                        slot: v.slot().expect("Should be a slot").to_owned(),
+                        expected: expected.iter().join(", "),
+                        actual: actual.iter().join(", "),
+                    });
+                }
+                self.visited.insert(Arc::as_ptr(element), fill.clone());
+                return fill.clone();
+            }
+            ElementVariant::Stack(v) => v.map_children(|c| self.fill(c)).into(),
+            ElementVariant::Absolute(v) => match v.map_children(|c| self.fill(c)) {
+                Ok(v) => v.into(),
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                    return element.clone();
+                }
+            },
+            ElementVariant::Grid(v) => v.map_children(|c| self.fill(c)).into(),
+            ElementVariant::Repeat(v) => v
+                .with_child(0, self.fill(v.child()))
+                .expect("Should have a child")
+                .into(),
+            ElementVariant::ScaleAmplitude(v) => v
+                .with_child(0, self.fill(v.child()))
+                .expect("Should have a child")
+                .into(),
+            _ => return element.clone(),
+        };
+        let filled = Arc::new(Element::new(element.common.clone(), variant));
+        self.visited.insert(Arc::as_ptr(element), filled.clone());
+        filled
+    }
+}
+
+fn channel_set(channels: &[ChannelId]) -> Vec<ChannelId> {
+    channels.iter().copied().sorted().dedup().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        quant::{Amplitude, Time},
+        schedule::{Absolute, AbsoluteEntry, Barrier, ElementCommonBuilder, Play, Repeat, Stack},
+    };
+
+    #[test]
+    fn fill() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let element = |variant: ElementVariant| Arc::new(Element::new(common.clone(), variant));
+        let play = |channel: &str| {
+            let play = Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(0.5).unwrap(),
+                Time::new(10e-9).unwrap(),
+            )
+            .unwrap();
+            element(play.into())
+        };
+        let q0 = ChannelId::new("q0.xy");
+        let slot = element(
+            Barrier::new(vec![q0])
+                .with_slot(Some("gate".to_string()))
+                .into(),
+        );
+        let echo = element(
+            Stack::new()
+                .with_children(vec![play("q0.xy"), slot.clone(), play("q0.xy")])
+                .into(),
+        );
+        let root = element(Stack::new().with_children(vec![echo.clone(), echo]).into());
+        assert_eq!(slots(&root), [("gate".to_string(), vec![q0])]);
+
+        let gate = element(Repeat::new(play("q0.xy"), 2).into());
+        let fills = HashMap::from([("gate".to_string(), gate.clone())]);
+        let filled = fill_slots(&root, &fills).unwrap();
+        assert!(slots(&filled).is_empty());
+        assert_eq!(filled.measure(), Time::new(80e-9).unwrap());
+        let ElementVariant::Stack(stack) = &filled.variant else {
+            unreachable!()
+        };
+        // The shared echo stays shared.
+        assert!(Arc::ptr_eq(&stack.children()[0], &stack.children()[1]));
+
+        let fills = HashMap::from([("gate".to_string(), play("q1.xy"))]);
+        assert!(matches!(
+            fill_slots(&root, &fills),
+            Err(ScheduleError::SlotChannels { slot, .. }) if slot == "gate"
+        ));
+        let fills = HashMap::from([("other".to_string(), gate)]);
+        assert!(matches!(
+            fill_slots(&root, &fills),
+            Err(ScheduleError::UnknownSlot { slot }) if slot == "other"
+        ));
+
+        // A slot placed from the end can't be filled past the end of the
+        // layout.
+        let slot = AbsoluteEntry::new(slot).with_from_end(true).unwrap();
+        let root = element(Absolute::new().with_children(vec![slot]).into());
+        let fills = HashMap::from([("gate".to_string(), play("q0.xy"))]);
+        assert!(matches!(
+            fill_slots(&root, &fills),
+            Err(ScheduleError::InvalidTime {
+                property: "time",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,413 @@
+//! Per-channel timing statistics of arranged schedules.
+use std::fmt;
+
+use crate::{
+    diagnostics::Diagnostics,
+    quant::{ChannelId, Time},
+    schedule::{
+        arrange_children, flatten, Arranged, ElementRef, ElementVariant, Instruction,
+        InstructionKind, Measure, TimeRange,
+    },
+};
+
+/// Timing of one channel over the whole schedule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ChannelTiming {
+    pub(crate) channel: ChannelId,
+    /// Number of plays, including sampled plays.
+    pub(crate) pulses: usize,
+    /// Length of the union of the pulse intervals.
+    pub(crate) busy: Time,
+    /// Schedule duration minus `busy`.
+    pub(crate) idle: Time,
+    /// Longest interval without pulses, including the intervals before the
+    /// first and after the last pulse.
+    pub(crate) longest_gap: Time,
+    /// Idle time added by barriers waiting for other channels. Channels
+    /// without barrier wait are on the critical path.
+    pub(crate) barrier_wait: Time,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TimingReport {
+    pub(crate) duration: Time,
+    /// Channels in the order of [`Measure::channels`] of the root.
+    pub(crate) channels: Vec<ChannelTiming>,
+}
+
+/// Busy time, idle time, and pulse count of every channel of `root`.
+pub(crate) fn timing_report(root: &ElementRef) -> TimingReport {
+    let duration = root.measure();
+    let instructions = flatten(root);
+    let mut diagnostics = Diagnostics::new();
+    collect_barrier_waits(
+        Arranged {
+            item: root,
+            time_range: TimeRange {
+                start: Time::ZERO,
+                span: duration,
+            },
+        },
+        &mut diagnostics,
+    );
+    let barrier_waits = diagnostics.barrier_wait_totals();
+    let channels = root
+        .channels()
+        .iter()
+        .map(|&channel| {
+            let busy = busy_intervals(&instructions, channel);
+            let gaps = idle_gaps(&busy, duration);
+            let idle = gaps.iter().map(|&(start, stop)| stop - start).sum();
+            ChannelTiming {
+                channel,
+                pulses: busy.len(),
+                busy: duration - idle,
+                idle,
+                longest_gap: gaps
+                    .iter()
+                    .map(|&(start, stop)| stop - start)
+                    .max()
+                    .unwrap_or(Time::ZERO),
+                barrier_wait: barrier_waits
+                    .iter()
+                    .find(|(c, _)| *c == channel)
+                    .map_or(Time::ZERO, |(_, wait)| *wait),
+            }
+        })
+        .collect();
+    TimingReport { duration, channels }
+}
+
+/// Sorted `(start, stop)` intervals of the pulses played on `channel`.
+pub(super) fn busy_intervals(
+    instructions: &[Instruction],
+    channel: ChannelId,
+) -> Vec<(Time, Time)> {
+    let mut busy: Vec<_> = instructions
+        .iter()
+        .filter_map(|i| match &i.kind {
+            InstructionKind::Play {
+                channel_id,
+                width,
+                plateau,
+                fall,
+                ..
+            } if *channel_id == channel => {
+                let edges = fall.as_ref().map_or(*width, |f| (*width + f.width) / 2.0);
+                Some((i.time, i.time + edges + *plateau))
+            }
+            InstructionKind::PlaySamples {
+                channel_id,
+                samples,
+                sample_rate,
+                ..
+            } if *channel_id == channel => {
+                let width = Time::new(samples.len() as f64 / sample_rate.value()).ok()?;
+                Some((i.time, i.time + width))
+            }
+            _ => None,
+        })
+        .collect();
+    busy.sort_unstable();
+    busy
+}
+
+/// Intervals between zero and `end` not covered by the sorted `busy`
+/// intervals.
+pub(super) fn idle_gaps(busy: &[(Time, Time)], end: Time) -> Vec<(Time, Time)> {
+    let mut gaps = Vec::new();
+    let mut cursor = Time::ZERO;
+    for &(start, stop) in busy {
+        if start > cursor {
+            gaps.push((cursor, start));
+        }
+        cursor = cursor.max(stop);
+    }
+    if end > cursor {
+        gaps.push((cursor, end));
+    }
+    gaps
+}
+
+/// Intervals reserved by phantom elements but without pulses, per channel.
+///
+/// A phantom element reserves its inner time range on all of its channels.
+/// The intervals where a pulse of a non-phantom element is played are removed,
+/// so the rest can be filled later without overlapping existing pulses.
+/// Channels are in the order of [`Measure::channels`] of the root and
+/// channels without reserved intervals are omitted.
+pub(crate) fn reserved_intervals(root: &ElementRef) -> Vec<(ChannelId, Vec<(Time, Time)>)> {
+    let mut reserved = Vec::new();
+    collect_phantoms(
+        Arranged {
+            item: root,
+            time_range: TimeRange {
+                start: Time::ZERO,
+                span: root.measure(),
+            },
+        },
+        &mut reserved,
+    );
+    let instructions = flatten(root);
+    root.channels()
+        .iter()
+        .filter_map(|&channel| {
+            let mut intervals: Vec<_> = reserved
+                .iter()
+                .filter(|(c, _, _)| *c == channel)
+                .map(|&(_, start, stop)| (start, stop))
+                .collect();
+            intervals.sort_unstable();
+            let busy = busy_intervals(&instructions, channel);
+            let empty: Vec<_> = intervals
+                .into_iter()
+                .flat_map(|(start, stop)| {
+                    // Gaps of the busy intervals clipped to `start..stop`.
+                    idle_gaps(&busy, stop)
+                        .into_iter()
+                        .filter_map(move |(a, b)| {
+                            let a = a.max(start);
+                            (b > a).then_some((a, b))
+                        })
+                })
+                .fold(
+                    Vec::new(),
+                    |mut merged: Vec<(Time, Time)>, (start, stop)| {
+                        match merged.last_mut() {
+                            Some(last) if start <= last.1 => last.1 = last.1.max(stop),
+                            _ => merged.push((start, stop)),
+                        }
+                        merged
+                    },
+                );
+            (!empty.is_empty()).then_some((channel, empty))
+        })
+        .collect()
+}
+
+fn collect_phantoms(arranged: Arranged<&ElementRef>, reserved: &mut Vec<(ChannelId, Time, Time)>) {
+    let item = arranged.item;
+    if item.common.phantom() {
+        let TimeRange { start, span } = item.inner_time_range(arranged.time_range);
+        reserved.extend(item.channels().iter().map(|&c| (c, start, start + span)));
+        return;
+    }
+    for child in arrange_children(arranged).into_iter().flatten() {
+        collect_phantoms(child, reserved);
+    }
+}
+
+fn collect_barrier_waits(arranged: Arranged<&ElementRef>, diagnostics: &mut Diagnostics) {
+    let item = arranged.item;
+    if let ElementVariant::Stack(v) = &item.variant {
+        if !item.common.phantom() {
+            for wait in v.barrier_waits(item.inner_time_range(arranged.time_range)) {
+                diagnostics.record_barrier_wait(wait);
+            }
+        }
+    }
+    for child in arrange_children(arranged).into_iter().flatten() {
+        collect_barrier_waits(child, diagnostics);
+    }
+}
+
+impl fmt::Display for TimingReport {
+    /// Table with one row per channel and the total duration.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = [
+            "channel",
+            "pulses",
+            "busy",
+            "idle",
+            "longest gap",
+            "barrier wait",
+        ];
+        let rows: Vec<[String; 6]> = self
+            .channels
+            .iter()
+            .map(|c| {
+                [
+                    c.channel.to_string(),
+                    c.pulses.to_string(),
+                    c.busy.to_string(),
+                    c.idle.to_string(),
+                    c.longest_gap.to_string(),
+                    c.barrier_wait.to_string(),
+                ]
+            })
+            .collect();
+        let mut widths = header.map(|h| h.chars().count());
+        for row in &rows {
+            for (w, cell) in widths.iter_mut().zip(row) {
+                *w = (*w).max(cell.chars().count());
+            }
+        }
+        let header = header.map(String::from);
+        for row in std::iter::once(&header).chain(&rows) {
+            // Channel names are left aligned, numbers right aligned.
+            write!(f, "{:<w$}", row[0], w = widths[0])?;
+            for (cell, w) in row.iter().zip(widths).skip(1) {
+                write!(f, "  {:>w$}", cell, w = w)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "duration: {}", self.duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        quant::Amplitude,
+        schedule::{
+            Absolute, AbsoluteEntry, Barrier, Element, ElementCommon, ElementCommonBuilder, Play,
+            Repeat, Stack,
+        },
+        Direction,
+    };
+
+    #[test]
+    fn report() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let play = |channel: &str, time: f64, width: f64| {
+            let play = Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(0.5).unwrap(),
+                Time::new(width).unwrap(),
+            )
+            .unwrap();
+            AbsoluteEntry::new(Arc::new(Element::new(common.clone(), play)))
+                .with_time(Time::new(time).unwrap())
+                .unwrap()
+        };
+        // xy: 0..20 and 10..30 overlap, idle 30..100. z: idle 0..40 and 50..100.
+        let absolute = Absolute::new().with_children(vec![
+            play("xy", 0.0, 20.0),
+            play("xy", 10.0, 20.0),
+            play("z", 40.0, 10.0),
+            play("z", 90.0, 10.0),
+        ]);
+        let root = Arc::new(Element::new(common, absolute));
+
+        let report = timing_report(&root);
+
+        let time = |t| Time::new(t).unwrap();
+        assert_eq!(report.duration, time(100.0));
+        assert_eq!(
+            report.channels,
+            [
+                ChannelTiming {
+                    channel: ChannelId::new("xy"),
+                    pulses: 2,
+                    busy: time(30.0),
+                    idle: time(70.0),
+                    longest_gap: time(70.0),
+                    barrier_wait: Time::ZERO,
+                },
+                ChannelTiming {
+                    channel: ChannelId::new("z"),
+                    pulses: 2,
+                    busy: time(20.0),
+                    idle: time(80.0),
+                    longest_gap: time(40.0),
+                    barrier_wait: Time::ZERO,
+                },
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            [
+                "channel  pulses  busy  idle  longest gap  barrier wait",
+                "xy            2  30 s  70 s         70 s           0 s",
+                "z             2  20 s  80 s         40 s           0 s",
+                "duration: 100 s",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn barrier_wait() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let element = |variant: ElementVariant| Arc::new(Element::new(common.clone(), variant));
+        let play = |channel: &str, width: f64| {
+            let play = Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(0.5).unwrap(),
+                Time::new(width).unwrap(),
+            )
+            .unwrap();
+            element(play.into())
+        };
+        let barrier = element(Barrier::new(vec![]).into());
+        // xy waits 30 for z at each of the two barriers of the repeated body.
+        let body = element(
+            Stack::new()
+                .with_direction(Direction::Forward)
+                .with_children(vec![play("xy", 10.0), play("z", 40.0), barrier])
+                .into(),
+        );
+        let root = element(Repeat::new(body, 2).into());
+
+        let report = timing_report(&root);
+
+        let waits: Vec<_> = report
+            .channels
+            .iter()
+            .map(|c| (c.channel.to_string(), c.barrier_wait.value()))
+            .collect();
+        assert_eq!(waits, [("xy".to_string(), 60.0), ("z".to_string(), 0.0)]);
+    }
+
+    #[test]
+    fn reserved() {
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let phantom = {
+            let mut builder = ElementCommonBuilder::new();
+            builder.phantom(true);
+            builder.build().unwrap()
+        };
+        let play = |common: &ElementCommon, channel: &str, width: f64| {
+            let play = Play::new(
+                ChannelId::new(channel),
+                None,
+                Amplitude::new(0.5).unwrap(),
+                Time::new(width).unwrap(),
+            )
+            .unwrap();
+            Arc::new(Element::new(common.clone(), play))
+        };
+        let entry = |element, time: f64| {
+            AbsoluteEntry::new(element)
+                .with_time(Time::new(time).unwrap())
+                .unwrap()
+        };
+        // The phantom reserves xy 10..50, partly covered by a pulse at 20..30.
+        // The phantom play on z is not played.
+        let absolute = Absolute::new().with_children(vec![
+            entry(play(&phantom, "xy", 40.0), 10.0),
+            entry(play(&common, "xy", 10.0), 20.0),
+            entry(play(&phantom, "z", 10.0), 0.0),
+            entry(play(&common, "m", 5.0), 0.0),
+        ]);
+        let root = Arc::new(Element::new(common.clone(), absolute));
+
+        let time = |t| Time::new(t).unwrap();
+        assert_eq!(
+            reserved_intervals(&root),
+            [
+                (
+                    ChannelId::new("xy"),
+                    vec![(time(10.0), time(20.0)), (time(30.0), time(50.0))]
+                ),
+                (ChannelId::new("z"), vec![(time(0.0), time(10.0))]),
+            ]
+        );
+        assert_eq!(timing_report(&root).channels[1].pulses, 0);
+    }
+}
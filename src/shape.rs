@@ -1,18 +1,52 @@
-use std::{hash::Hash, sync::Arc};
+mod expr;
+
+#[cfg(any(feature = "_frontend", test))]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{fmt::Debug, hash::Hash, sync::Arc};
 
 use anyhow::{bail, Result};
 use bspline::BSpline;
 use cached::proc_macro::cached;
 use ordered_float::NotNan;
+use serde::{ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+use self::expr::Expr;
 
 /// A shape that can be used to modulate the amplitude of a signal.
 ///
 /// The shape is defined in the range \[-0.5, 0.5\].
 ///
 /// Internally, shape instances are cached such that we can compare and hash
-/// by instance address.
+/// by instance address. A shape is serialized as the parameters used to create
+/// it, and deserialization goes through the same cache.
 #[derive(Debug, Clone)]
-pub(crate) struct Shape(Arc<ShapeVariant>);
+pub(crate) struct Shape(Arc<ShapeInstance>);
+
+#[derive(Debug)]
+struct ShapeInstance {
+    /// `None` for custom shapes, which can't be serialized.
+    key: Option<ShapeKey>,
+    variant: ShapeVariant,
+    /// Creation sequence of custom shapes, zero for shapes with a key.
+    seq: u64,
+}
+
+/// Next creation sequence of custom shapes.
+#[cfg(any(feature = "_frontend", test))]
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Deterministic order of shapes, see [`Shape::order_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct ShapeOrder<'a>(Option<&'a ShapeKey>, u64);
+
+/// Envelope defined outside of the crate, e.g. by a Python callback.
+///
+/// Custom shapes are compared by instance, so a shape should be created once
+/// and reused to share the envelope cache.
+pub(crate) trait CustomShape: Debug + Send + Sync {
+    /// Sample the shape at `x0 + i * dx` for every element of `array`.
+    fn sample_array(&self, x0: f64, dx: f64, array: &mut [f64]);
+}
 
 impl Shape {
     pub(crate) fn new_hann() -> Self {
@@ -28,12 +62,140 @@ impl Shape {
             .into_iter()
             .map(NotNan::new)
             .collect::<Result<_, _>>()?;
-        let key = ShapeKey::Interp(knots, controls, degree);
+        Self::from_key(ShapeKey::Interp {
+            knots,
+            controls,
+            degree,
+        })
+    }
+
+    /// Shape given as a math expression of `x`, see [`expr`] for the syntax.
+    #[cfg(any(feature = "_frontend", test))]
+    pub(crate) fn new_expr(expr: &str) -> Result<Self> {
+        Self::from_key(ShapeKey::Expr {
+            expr: expr.to_string(),
+        })
+    }
+
+    /// Sinc with `num_lobes` zero crossings on each side of the main lobe, so
+    /// `num_lobes - 1` side lobes, and the last zero crossing at the edge.
+    #[cfg(any(feature = "_frontend", test))]
+    pub(crate) fn new_sinc(num_lobes: usize) -> Result<Self> {
+        Self::from_key(ShapeKey::Sinc { num_lobes })
+    }
+
+    /// Square pulse convolved with a Gaussian, `rise_time` is the length of
+    /// each edge relative to the width of the pulse. See [`ErfSquare`] for the
+    /// normalization.
+    #[cfg(any(feature = "_frontend", test))]
+    pub(crate) fn new_erf_square(rise_time: f64) -> Result<Self> {
+        Self::from_key(ShapeKey::ErfSquare {
+            rise_time: NotNan::new(rise_time)?,
+        })
+    }
+
+    /// Exponential rise and decay, commonly used for flux pulses with the
+    /// predistortion baked in.
+    ///
+    /// Each section is a sum of `(tau, weight)` terms with the time constants
+    /// relative to the width of the pulse. A single term gives an exponential
+    /// and two terms a bi-exponential section. See [`Exponential`] for the
+    /// normalization.
+    #[cfg(any(feature = "_frontend", test))]
+    pub(crate) fn new_exponential(rise: &[(f64, f64)], decay: &[(f64, f64)]) -> Result<Self> {
+        let terms = |terms: &[(f64, f64)]| {
+            terms
+                .iter()
+                .map(|&(tau, weight)| Ok([NotNan::new(tau)?, NotNan::new(weight)?]))
+                .collect::<Result<Vec<_>>>()
+        };
+        Self::from_key(ShapeKey::Exponential {
+            rise: terms(rise)?,
+            decay: terms(decay)?,
+        })
+    }
+
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn new_custom(shape: Arc<dyn CustomShape>) -> Self {
+        Self(Arc::new(ShapeInstance {
+            key: None,
+            variant: Custom(shape).into(),
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+        }))
+    }
+
+    /// Shape through equally spaced samples covering the whole range.
+    ///
+    /// Without `interpolate`, each sample is held over its interval. Otherwise
+    /// the shape is linear between the sample centers and flat in the outer
+    /// half intervals.
+    pub(crate) fn new_samples(values: &[f64], interpolate: bool) -> Result<Self> {
+        let n = values.len();
+        if n == 0 {
+            bail!("Empty sample array");
+        }
+        let edges = (0..=n).map(|i| i as f64 / n as f64 - 0.5);
+        if !interpolate || n == 1 {
+            return Self::new_interp(edges.collect(), values.to_vec(), 0);
+        }
+        let centers = (0..n).map(|i| (i as f64 + 0.5) / n as f64 - 0.5);
+        let knots = [-0.5, -0.5]
+            .into_iter()
+            .chain(centers)
+            .chain([0.5, 0.5])
+            .collect();
+        let controls = [values[0]]
+            .into_iter()
+            .chain(values.iter().copied())
+            .chain([values[n - 1]])
+            .collect();
+        Self::new_interp(knots, controls, 1)
+    }
+
+    /// Rescale the shape according to `normalization`.
+    ///
+    /// The plateau of an envelope stays at one, so with [`Normalization::Area`]
+    /// the area of a pulse is `amplitude * (width + plateau)` for any shape.
+    #[cfg(any(feature = "_frontend", test))]
+    pub(crate) fn with_normalization(&self, normalization: Normalization) -> Result<Self> {
+        match &self.0.key {
+            Some(key) => Self::from_key(ShapeKey::Normalized {
+                shape: Box::new(key.clone()),
+                normalization,
+            }),
+            None => Ok(Self(Arc::new(ShapeInstance {
+                key: None,
+                variant: Normalized::new(self.clone(), normalization)?.into(),
+                seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+            }))),
+        }
+    }
+
+    /// Hash of the shape parameters, so equal shapes built separately hash
+    /// the same. Custom shapes are hashed by identity.
+    #[cfg(feature = "python")]
+    pub(crate) fn hash_content<H: std::hash::Hasher>(&self, state: &mut H) {
+        match &self.0.key {
+            Some(key) => key.hash(state),
+            None => self.hash(state),
+        }
+    }
+
+    /// Order by the shape parameters, custom shapes by creation sequence.
+    ///
+    /// Unlike the address used for hashing, the order doesn't change between
+    /// runs of the same program.
+    pub(crate) fn order_key(&self) -> ShapeOrder<'_> {
+        ShapeOrder(self.0.key.as_ref(), self.0.seq)
+    }
+
+    fn from_key(key: ShapeKey) -> Result<Self> {
+        key.validate()?;
         Ok(Self(get_shape_instance(key)))
     }
 
     pub(crate) fn sample_array(&self, x0: f64, dx: f64, array: &mut [f64]) {
-        self.0.sample_array(x0, dx, array);
+        self.0.variant.sample_array(x0, dx, array);
     }
 }
 
@@ -51,25 +213,165 @@ impl PartialEq for Shape {
 
 impl Eq for Shape {}
 
+impl Serialize for Shape {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.0.key {
+            Some(key) => key.serialize(serializer),
+            None => Err(S::Error::custom("Custom shapes can't be serialized")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Shape {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = ShapeKey::deserialize(deserializer)?;
+        Self::from_key(key).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Quantity of a shape that is scaled to one on \[-0.5, 0.5\].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Normalization {
+    /// Maximum absolute value, conserves the peak power.
+    Peak,
+    /// Integral, conserves the rotation angle.
+    Area,
+    /// Root mean square, conserves the energy.
+    Rms,
+}
+
+impl Normalization {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Normalization::Peak => "peak",
+            Normalization::Area => "area",
+            Normalization::Rms => "rms",
+        }
+    }
+
+    #[cfg(any(feature = "python", test))]
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        [Normalization::Peak, Normalization::Area, Normalization::Rms]
+            .into_iter()
+            .find(|n| n.name() == name)
+    }
+}
+
 type HashableArray = Vec<NotNan<f64>>;
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(tag = "type")]
 enum ShapeKey {
     Hann,
-    Interp(HashableArray, HashableArray, usize),
+    Interp {
+        knots: HashableArray,
+        controls: HashableArray,
+        degree: usize,
+    },
+    Expr {
+        expr: String,
+    },
+    Exponential {
+        rise: Vec<[NotNan<f64>; 2]>,
+        decay: Vec<[NotNan<f64>; 2]>,
+    },
+    Sinc {
+        num_lobes: usize,
+    },
+    ErfSquare {
+        rise_time: NotNan<f64>,
+    },
+    Normalized {
+        shape: Box<ShapeKey>,
+        normalization: Normalization,
+    },
+}
+
+impl ShapeKey {
+    fn validate(&self) -> Result<()> {
+        if let ShapeKey::Interp {
+            knots,
+            controls,
+            degree,
+        } = self
+        {
+            if controls.len() <= *degree {
+                bail!("Too few control points for degree {}", degree);
+            }
+            let expected = controls.len() + degree + 1;
+            if knots.len() != expected {
+                bail!(
+                    "Invalid number of knots, got {}, expected {}",
+                    knots.len(),
+                    expected
+                );
+            }
+        }
+        if let ShapeKey::Expr { expr } = self {
+            Expr::parse(expr)?;
+        }
+        if let ShapeKey::Exponential { rise, decay } = self {
+            for terms in [rise, decay] {
+                Section::new(terms)?;
+            }
+        }
+        if let ShapeKey::Sinc { num_lobes: 0 } = self {
+            bail!("Sinc shape needs at least one lobe");
+        }
+        if let ShapeKey::Normalized {
+            shape,
+            normalization,
+        } = self
+        {
+            shape.validate()?;
+            let shape = Shape(get_shape_instance(*shape.clone()));
+            Normalized::new(shape, *normalization)?;
+        }
+        if let ShapeKey::ErfSquare { rise_time } = self {
+            if !(rise_time.into_inner() > 0.0 && rise_time.into_inner() <= 0.5) {
+                bail!("Invalid rise time {}, should be in (0, 0.5]", rise_time);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cached(size = 128)]
-fn get_shape_instance(a: ShapeKey) -> Arc<ShapeVariant> {
-    let variant = match a {
+fn get_shape_instance(a: ShapeKey) -> Arc<ShapeInstance> {
+    let variant = match &a {
         ShapeKey::Hann => Hann.into(),
-        ShapeKey::Interp(t, c, k) => {
-            let t = t.into_iter().map(|v| v.into()).collect();
-            let c = c.into_iter().map(|v| v.into()).collect();
-            Interp::new(t, c, k).into()
+        ShapeKey::Interp {
+            knots,
+            controls,
+            degree,
+        } => {
+            let t = knots.iter().map(|v| v.into_inner()).collect();
+            let c = controls.iter().map(|v| v.into_inner()).collect();
+            Interp::new(t, c, *degree).into()
+        }
+        ShapeKey::Expr { expr } => Expr::parse(expr)
+            .expect("Validated expression should parse")
+            .into(),
+        ShapeKey::Exponential { rise, decay } => Exponential {
+            rise: Section::new(rise).expect("Validated section should be valid"),
+            decay: Section::new(decay).expect("Validated section should be valid"),
         }
+        .into(),
+        ShapeKey::Sinc { num_lobes } => Sinc(*num_lobes).into(),
+        ShapeKey::ErfSquare { rise_time } => ErfSquare::new(rise_time.into_inner()).into(),
+        ShapeKey::Normalized {
+            shape,
+            normalization,
+        } => Normalized::new(Shape(get_shape_instance(*shape.clone())), *normalization)
+            .expect("Validated normalization should be valid")
+            .into(),
     };
-    Arc::new(variant)
+    Arc::new(ShapeInstance {
+        key: Some(a),
+        variant,
+        seq: 0,
+    })
 }
 
 trait ShapeTrait {
@@ -110,6 +412,203 @@ impl ShapeTrait for Interp {
     }
 }
 
+impl ShapeTrait for Expr {
+    fn sample(&self, x: f64) -> f64 {
+        self.eval(x)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Sinc(usize);
+
+impl ShapeTrait for Sinc {
+    fn sample(&self, x: f64) -> f64 {
+        if !(-0.5..=0.5).contains(&x) {
+            return 0.0;
+        }
+        let t = 2.0 * std::f64::consts::PI * self.0 as f64 * x;
+        if t == 0.0 {
+            1.0
+        } else {
+            t.sin() / t
+        }
+    }
+}
+
+/// Sum of two error function steps with centers half the rise time from the
+/// edges and standard deviation a quarter of the rise time.
+///
+/// The offset at the edges is subtracted and the result rescaled, so the shape
+/// is zero at the edges and one at the center.
+#[derive(Debug, Clone)]
+struct ErfSquare {
+    center: f64,
+    scale: f64,
+    offset: f64,
+    norm: f64,
+}
+
+impl ErfSquare {
+    fn new(rise_time: f64) -> Self {
+        let mut shape = Self {
+            center: 0.5 - rise_time / 2.0,
+            scale: 1.0 / (std::f64::consts::SQRT_2 * rise_time / 4.0),
+            offset: 0.0,
+            norm: 1.0,
+        };
+        let offset = shape.sample(0.5);
+        shape.offset = offset;
+        shape.norm = shape.sample(0.0);
+        shape
+    }
+}
+
+impl ShapeTrait for ErfSquare {
+    fn sample(&self, x: f64) -> f64 {
+        if !(-0.5..=0.5).contains(&x) {
+            return 0.0;
+        }
+        let step = |x: f64| 0.5 * libm::erf(x * self.scale);
+        (step(x + self.center) - step(x - self.center) - self.offset) / self.norm
+    }
+}
+
+/// Rise on \[-0.5, 0\] and decay on \[0, 0.5\], both sampled analytically.
+///
+/// At distance `s` from the edge, a section with terms `(tau_i, w_i)` is
+/// `(1 - sum w_i exp(-s / tau_i)) / (1 - sum w_i exp(-0.5 / tau_i))` with the
+/// weights normalized to a sum of one, so the shape is zero at the edges and
+/// one at the center.
+#[derive(Debug, Clone)]
+struct Exponential {
+    rise: Section,
+    decay: Section,
+}
+
+#[derive(Debug, Clone)]
+struct Section {
+    terms: Vec<(f64, f64)>,
+    norm: f64,
+}
+
+impl Section {
+    fn new(terms: &[[NotNan<f64>; 2]]) -> Result<Self> {
+        if terms.is_empty() {
+            bail!("Exponential section without terms");
+        }
+        if let Some([tau, _]) = terms
+            .iter()
+            .find(|[tau, _]| !(tau.is_finite() && tau.into_inner() > 0.0))
+        {
+            bail!("Invalid exponential time constant {}", tau);
+        }
+        let total: f64 = terms.iter().map(|[_, w]| w.into_inner()).sum();
+        if !total.is_finite() || total == 0.0 {
+            bail!("Weights of exponential terms should have a non-zero sum");
+        }
+        let terms: Vec<_> = terms
+            .iter()
+            .map(|[tau, w]| (tau.into_inner(), w.into_inner() / total))
+            .collect();
+        let mut section = Self { terms, norm: 1.0 };
+        let norm = section.eval(0.5);
+        if !norm.is_normal() {
+            bail!("Exponential section doesn't reach a non-zero value at the center");
+        }
+        section.norm = norm;
+        Ok(section)
+    }
+
+    /// Value at distance `s` from the edge.
+    fn eval(&self, s: f64) -> f64 {
+        let decayed: f64 = self
+            .terms
+            .iter()
+            .map(|&(tau, w)| w * (-s / tau).exp())
+            .sum();
+        (1.0 - decayed) / self.norm
+    }
+}
+
+impl ShapeTrait for Exponential {
+    fn sample(&self, x: f64) -> f64 {
+        if !(-0.5..=0.5).contains(&x) {
+            return 0.0;
+        }
+        if x < 0.0 {
+            self.rise.eval(x + 0.5)
+        } else {
+            self.decay.eval(0.5 - x)
+        }
+    }
+}
+
+/// Shape multiplied by the factor that brings its [`Normalization`] quantity
+/// to one.
+#[derive(Debug, Clone)]
+struct Normalized {
+    shape: Shape,
+    scale: f64,
+}
+
+impl Normalized {
+    /// Number of midpoints to estimate the quantity with.
+    const POINTS: usize = 4096;
+
+    fn new(shape: Shape, normalization: Normalization) -> Result<Self> {
+        let n = Self::POINTS;
+        let dx = 1.0 / n as f64;
+        let mut y = vec![0.0; n];
+        shape.sample_array(-0.5 + dx / 2.0, dx, &mut y);
+        let value = match normalization {
+            Normalization::Peak => y.iter().fold(0.0, |m: f64, v| m.max(v.abs())),
+            Normalization::Area => y.iter().sum::<f64>() * dx,
+            Normalization::Rms => (y.iter().map(|v| v * v).sum::<f64>() * dx).sqrt(),
+        };
+        if !value.is_normal() {
+            bail!(
+                "Can't normalize shape with {} {}",
+                normalization.name(),
+                value
+            );
+        }
+        Ok(Self {
+            shape,
+            scale: 1.0 / value,
+        })
+    }
+}
+
+impl ShapeTrait for Normalized {
+    fn sample(&self, x: f64) -> f64 {
+        let mut y = [0.0];
+        self.shape.sample_array(x, 0.0, &mut y);
+        y[0] * self.scale
+    }
+
+    fn sample_array(&self, x0: f64, dx: f64, array: &mut [f64]) {
+        self.shape.sample_array(x0, dx, array);
+        for y in array {
+            *y *= self.scale;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Custom(Arc<dyn CustomShape>);
+
+impl ShapeTrait for Custom {
+    fn sample(&self, x: f64) -> f64 {
+        let mut y = [0.0];
+        self.0.sample_array(x, 0.0, &mut y);
+        y[0]
+    }
+
+    fn sample_array(&self, x0: f64, dx: f64, array: &mut [f64]) {
+        self.0.sample_array(x0, dx, array);
+    }
+}
+
 macro_rules! impl_variant {
     ($($variant:ident),*$(,)?) => {
 #[derive(Debug, Clone)]
@@ -163,7 +662,16 @@ impl ShapeTrait for ShapeVariant {
     };
 }
 
-impl_variant!(Hann, Interp);
+impl_variant!(
+    Hann,
+    Interp,
+    Expr,
+    Exponential,
+    Sinc,
+    ErfSquare,
+    Normalized,
+    Custom,
+);
 
 #[cfg(test)]
 mod tests {
@@ -251,6 +759,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_samples() {
+        let values = [1.0, -1.0, 0.5];
+        let mut held = [0.0; 3];
+        Shape::new_samples(&values, false)
+            .unwrap()
+            .sample_array(-1.0 / 3.0, 1.0 / 3.0, &mut held);
+        assert_eq!(held, values);
+
+        let mut interpolated = [0.0; 4];
+        Shape::new_samples(&values, true)
+            .unwrap()
+            .sample_array(-0.5, 0.25, &mut interpolated);
+        assert_approx_eq!(f64, interpolated[0], 1.0);
+        assert_approx_eq!(f64, interpolated[2], -1.0);
+        assert_approx_eq!(f64, interpolated[3], 0.125);
+        assert!(Shape::new_samples(&[], false).is_err());
+    }
+
     #[test]
     fn test_shape_eq() {
         let h1 = Shape::new_hann();
@@ -283,4 +810,152 @@ mod tests {
         assert_eq!(i1, i2);
         assert_ne!(h1, i1);
     }
+
+    #[test]
+    fn test_shape_serde() {
+        let knots = vec![-0.5, -0.5, 0.0, 0.5, 0.5];
+        let controls = vec![0.0, 1.0, 0.0];
+        let hann = Shape::new_hann();
+        let interp = Shape::new_interp(knots, controls, 1).unwrap();
+
+        let json = serde_json::to_string(&[&hann, &interp]).unwrap();
+        let [h, i]: [Shape; 2] = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(h, hann);
+        assert_eq!(i, interp);
+    }
+
+    #[test]
+    fn test_invalid_interp() {
+        assert!(Shape::new_interp(vec![-0.5, 0.5], vec![0.0], 1).is_err());
+        assert!(Shape::new_interp(vec![-0.5, 0.0, 0.5], vec![0.0, 1.0], 1).is_err());
+        let json = r#"{"type":"Interp","knots":[-0.5,0.5],"controls":[0.0],"degree":1}"#;
+        assert!(serde_json::from_str::<Shape>(json).is_err());
+    }
+
+    #[test]
+    fn test_expr_and_custom() {
+        #[derive(Debug)]
+        struct Ramp;
+
+        impl CustomShape for Ramp {
+            fn sample_array(&self, x0: f64, dx: f64, array: &mut [f64]) {
+                for (i, y) in array.iter_mut().enumerate() {
+                    *y = x0 + i as f64 * dx + 0.5;
+                }
+            }
+        }
+
+        let expr = Shape::new_expr("cos(pi * x)").unwrap();
+        let mut y = [0.0; 3];
+        expr.sample_array(-0.5, 0.5, &mut y);
+        assert_approx_eq!(f64, y[0], 0.0, epsilon = 1e-15);
+        assert_approx_eq!(f64, y[1], 1.0);
+        assert_eq!(expr, Shape::new_expr("cos(pi * x)").unwrap());
+        let json = serde_json::to_string(&expr).unwrap();
+        assert_eq!(serde_json::from_str::<Shape>(&json).unwrap(), expr);
+        assert!(Shape::new_expr("cos(pi * y)").is_err());
+
+        let custom = Shape::new_custom(Arc::new(Ramp));
+        custom.sample_array(-0.5, 0.5, &mut y);
+        assert_eq!(y, [0.0, 0.5, 1.0]);
+        let other = Shape::new_custom(Arc::new(Ramp));
+        assert_ne!(custom, other);
+        assert!(serde_json::to_string(&custom).is_err());
+        // Custom shapes are ordered by creation, before all shapes with a key.
+        assert!(custom.order_key() < other.order_key());
+        assert!(other.order_key() < expr.order_key());
+        assert!(Shape::new_hann().order_key() < expr.order_key());
+    }
+
+    #[test]
+    fn test_exponential() {
+        let rise = [(0.1, 1.0)];
+        let decay = [(0.05, 0.75), (0.2, 0.25)];
+        let shape = Shape::new_exponential(&rise, &decay).unwrap();
+        let mut y = [0.0; 5];
+        shape.sample_array(-0.5, 0.25, &mut y);
+        assert_approx_eq!(f64, y[0], 0.0);
+        assert_approx_eq!(f64, y[1], (1.0 - (-2.5f64).exp()) / (1.0 - (-5.0f64).exp()));
+        assert_approx_eq!(f64, y[2], 1.0);
+        let decayed = |s: f64| 0.75 * (-s / 0.05).exp() + 0.25 * (-s / 0.2).exp();
+        assert_approx_eq!(f64, y[3], (1.0 - decayed(0.25)) / (1.0 - decayed(0.5)));
+        assert_approx_eq!(f64, y[4], 0.0);
+
+        // Weights are normalized, but the cache is keyed by the given terms.
+        let scaled = Shape::new_exponential(&rise, &[(0.05, 3.0), (0.2, 1.0)]).unwrap();
+        let mut z = [0.0; 5];
+        scaled.sample_array(-0.5, 0.25, &mut z);
+        assert_eq!(z, y);
+        assert_ne!(scaled, shape);
+        assert_eq!(Shape::new_exponential(&rise, &decay).unwrap(), shape);
+        let json = serde_json::to_string(&shape).unwrap();
+        assert_eq!(serde_json::from_str::<Shape>(&json).unwrap(), shape);
+
+        assert!(Shape::new_exponential(&[], &decay).is_err());
+        assert!(Shape::new_exponential(&[(0.0, 1.0)], &decay).is_err());
+        assert!(Shape::new_exponential(&[(0.1, 1.0), (0.2, -1.0)], &decay).is_err());
+    }
+
+    #[test]
+    fn test_sinc_and_erf_square() {
+        let sinc = Shape::new_sinc(2).unwrap();
+        let mut y = [0.0; 9];
+        sinc.sample_array(-0.5, 0.125, &mut y);
+        assert_approx_eq!(f64, y[4], 1.0);
+        for i in [0, 2, 6, 8] {
+            assert_approx_eq!(f64, y[i], 0.0, epsilon = 1e-15);
+        }
+        assert_approx_eq!(f64, y[3], 2.0 / std::f64::consts::PI);
+        assert!(Shape::new_sinc(0).is_err());
+
+        let erf_square = Shape::new_erf_square(0.1).unwrap();
+        erf_square.sample_array(-0.5, 0.125, &mut y);
+        assert_approx_eq!(f64, y[0], 0.0, epsilon = 1e-15);
+        assert_approx_eq!(f64, y[8], 0.0, epsilon = 1e-15);
+        assert_approx_eq!(f64, y[4], 1.0);
+        // The plateau is flat away from the edges.
+        assert!(y[2..7].iter().all(|&v| (v - 1.0).abs() < 1e-12));
+        // The step centers are half the rise time from the edges.
+        let mut edge = [0.0; 3];
+        erf_square.sample_array(-0.475, 0.025, &mut edge);
+        assert!(edge[0] > 0.0 && edge[0] < edge[1] && edge[1] < edge[2]);
+        assert!((0.45..0.5).contains(&edge[1]));
+        assert!(Shape::new_erf_square(0.0).is_err());
+        assert!(Shape::new_erf_square(0.6).is_err());
+
+        let json = serde_json::to_string(&[&sinc, &erf_square]).unwrap();
+        let [s, e]: [Shape; 2] = serde_json::from_str(&json).unwrap();
+        assert_eq!(s, sinc);
+        assert_eq!(e, erf_square);
+    }
+
+    #[test]
+    fn test_normalization() {
+        let hann = Shape::new_hann();
+        let area = hann.with_normalization(Normalization::Area).unwrap();
+        let rms = hann.with_normalization(Normalization::Rms).unwrap();
+        let peak = Shape::new_expr("0.5 * cos(pi * x)")
+            .unwrap()
+            .with_normalization(Normalization::Peak)
+            .unwrap();
+        let mut y = [0.0; 3];
+        // Hann has an area of 1/2 and a mean square of 3/8.
+        area.sample_array(-0.25, 0.25, &mut y);
+        assert_approx_eq!(f64, y[1], 2.0, epsilon = 1e-9);
+        rms.sample_array(-0.25, 0.25, &mut y);
+        assert_approx_eq!(f64, y[1], (8.0f64 / 3.0).sqrt(), epsilon = 1e-9);
+        peak.sample_array(-0.25, 0.25, &mut y);
+        assert_approx_eq!(f64, y[1], 1.0, epsilon = 1e-6);
+
+        assert_eq!(hann.with_normalization(Normalization::Area).unwrap(), area);
+        let json = serde_json::to_string(&area).unwrap();
+        assert_eq!(serde_json::from_str::<Shape>(&json).unwrap(), area);
+        let msgpack = rmp_serde::to_vec(&rms).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Shape>(&msgpack).unwrap(), rms);
+
+        let zero = Shape::new_expr("x").unwrap();
+        assert!(zero.with_normalization(Normalization::Area).is_err());
+        assert_eq!(Normalization::from_name("rms"), Some(Normalization::Rms));
+    }
 }
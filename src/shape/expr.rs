@@ -0,0 +1,311 @@
+//! Envelope given as a math expression of `x`.
+//!
+//! The grammar is the usual one of calculators:
+//!
+//! ```text
+//! expr  := term (("+" | "-") term)*
+//! term  := unary (("*" | "/") unary)*
+//! unary := "-" unary | power
+//! power := atom (("^" | "**") unary)?
+//! atom  := number | "x" | "pi" | "e" | name "(" expr ")" | "(" expr ")"
+//! ```
+//!
+//! Functions are `sin`, `cos`, `tan`, `sinh`, `cosh`, `tanh`, `exp`, `ln`,
+//! `log10`, `sqrt`, and `abs`.
+use anyhow::{anyhow, bail, Result};
+
+#[derive(Debug, Clone)]
+pub(super) struct Expr(Node);
+
+#[derive(Debug, Clone)]
+enum Node {
+    Const(f64),
+    X,
+    Neg(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Log10,
+    Sqrt,
+    Abs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Number(f64),
+    Name(&'a str),
+    Op(char),
+    Pow,
+    LParen,
+    RParen,
+}
+
+impl Expr {
+    pub(super) fn parse(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let node = parser.expr()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected token {:?} in shape expression '{}'", token, s);
+        }
+        Ok(Self(node))
+    }
+
+    pub(super) fn eval(&self, x: f64) -> f64 {
+        self.0.eval(x)
+    }
+}
+
+impl Node {
+    fn eval(&self, x: f64) -> f64 {
+        match self {
+            Node::Const(v) => *v,
+            Node::X => x,
+            Node::Neg(a) => -a.eval(x),
+            Node::Binary(op, a, b) => {
+                let (a, b) = (a.eval(x), b.eval(x));
+                match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                    BinaryOp::Pow => a.powf(b),
+                }
+            }
+            Node::Call(f, a) => {
+                let a = a.eval(x);
+                match f {
+                    Function::Sin => a.sin(),
+                    Function::Cos => a.cos(),
+                    Function::Tan => a.tan(),
+                    Function::Sinh => a.sinh(),
+                    Function::Cosh => a.cosh(),
+                    Function::Tanh => a.tanh(),
+                    Function::Exp => a.exp(),
+                    Function::Ln => a.ln(),
+                    Function::Log10 => a.log10(),
+                    Function::Sqrt => a.sqrt(),
+                    Function::Abs => a.abs(),
+                }
+            }
+        }
+    }
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sin" => Some(Self::Sin),
+            "cos" => Some(Self::Cos),
+            "tan" => Some(Self::Tan),
+            "sinh" => Some(Self::Sinh),
+            "cosh" => Some(Self::Cosh),
+            "tanh" => Some(Self::Tanh),
+            "exp" => Some(Self::Exp),
+            "ln" => Some(Self::Ln),
+            "log10" => Some(Self::Log10),
+            "sqrt" => Some(Self::Sqrt),
+            "abs" => Some(Self::Abs),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = match c {
+            '0'..='9' | '.' => {
+                let len = number_len(rest);
+                let value = rest[..len].parse().map_err(|_| {
+                    anyhow!("Invalid number '{}' in shape expression", &rest[..len])
+                })?;
+                (Token::Number(value), len)
+            }
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (Token::Name(&rest[..len]), len)
+            }
+            '*' if rest.starts_with("**") => (Token::Pow, 2),
+            '^' => (Token::Pow, 1),
+            '+' | '-' | '*' | '/' => (Token::Op(c), 1),
+            '(' => (Token::LParen, 1),
+            ')' => (Token::RParen, 1),
+            _ => bail!("Unexpected character '{}' in shape expression '{}'", c, s),
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Length of the number literal at the start of `s`, e.g. `1.5e-3`.
+fn number_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let digits = |i: usize| {
+        bytes[i..]
+            .iter()
+            .position(|b| !b.is_ascii_digit())
+            .map_or(bytes.len(), |n| i + n)
+    };
+    let mut len = digits(0);
+    if bytes.get(len) == Some(&b'.') {
+        len = digits(len + 1);
+    }
+    if matches!(bytes.get(len), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(len + 1), Some(b'+' | b'-')));
+        let end = digits(len + 1 + sign);
+        if end > len + 1 + sign {
+            len = end;
+        }
+    }
+    len
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<Token<'a>> {
+        let token = self
+            .peek()
+            .ok_or_else(|| anyhow!("Unexpected end of shape expression"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let token = self.next()?;
+        if token != expected {
+            bail!(
+                "Expected {:?} in shape expression, got {:?}",
+                expected,
+                token
+            );
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self) -> Result<Node> {
+        let mut node = self.term()?;
+        while let Some(Token::Op(c @ ('+' | '-'))) = self.peek() {
+            self.pos += 1;
+            let op = if c == '+' {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+        Ok(node)
+    }
+
+    fn term(&mut self) -> Result<Node> {
+        let mut node = self.unary()?;
+        while let Some(Token::Op(c @ ('*' | '/'))) = self.peek() {
+            self.pos += 1;
+            let op = if c == '*' {
+                BinaryOp::Mul
+            } else {
+                BinaryOp::Div
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        if self.peek() == Some(Token::Op('-')) {
+            self.pos += 1;
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Node> {
+        let base = self.atom()?;
+        if self.peek() == Some(Token::Pow) {
+            self.pos += 1;
+            let exponent = self.unary()?;
+            return Ok(Node::Binary(
+                BinaryOp::Pow,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        match self.next()? {
+            Token::Number(v) => Ok(Node::Const(v)),
+            Token::Name("x") => Ok(Node::X),
+            Token::Name("pi") => Ok(Node::Const(std::f64::consts::PI)),
+            Token::Name("e") => Ok(Node::Const(std::f64::consts::E)),
+            Token::Name(name) => {
+                let f = Function::from_name(name)
+                    .ok_or_else(|| anyhow!("Unknown name '{}' in shape expression", name))?;
+                self.expect(Token::LParen)?;
+                let arg = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(Node::Call(f, Box::new(arg)))
+            }
+            Token::LParen => {
+                let node = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(node)
+            }
+            token => bail!("Unexpected token {:?} in shape expression", token),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::assert_approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn eval() {
+        let eval = |s: &str, x: f64| Expr::parse(s).unwrap().eval(x);
+        assert_approx_eq!(f64, eval("1 - 2 * 3", 0.0), -5.0);
+        assert_approx_eq!(f64, eval("-x^2", 3.0), -9.0);
+        assert_approx_eq!(f64, eval("2 ** 3 ** 2", 0.0), 512.0);
+        assert_approx_eq!(f64, eval("exp(-x**2 / (2 * 0.2e0^2))", 0.0), 1.0);
+        assert_approx_eq!(f64, eval("cos(pi * x)^2", 0.5), 0.0, epsilon = 1e-15);
+        assert!(Expr::parse("2 * y").is_err());
+        assert!(Expr::parse("sin x").is_err());
+        assert!(Expr::parse("(1 + x").is_err());
+        assert!(Expr::parse("1 + x)").is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! Time-domain simulation of transmons driven by sampled waveforms.
+//!
+//! The transmons are Duffing oscillators truncated to a few levels with
+//! exchange couplings. All frequencies are relative to a common rotating
+//! frame, e.g. the LO shared by the drive channels, so a drive waveform
+//! modulated at `f` is resonant with a transmon at `f`. The Hamiltonian in Hz
+//! is
+//!
+//! ```text
+//! H(t) = sum_q (f_q + s_q z_q(t)) n_q + alpha_q / 2 n_q (n_q - 1)
+//!        + Omega_q / 2 (conj(y_q(t)) a_q^+ + y_q(t) a_q)
+//!      + sum_(q, p) g_qp (a_q^+ a_p + a_q a_p^+)
+//! ```
+//!
+//! where `y_q` is the complex drive waveform and `z_q` the flux waveform of
+//! transmon `q`. The waveforms are held constant over each sample. States are
+//! kets, or density matrices if any transmon has a finite T1 or T2.
+use anyhow::{bail, Result};
+use ndarray::{linalg::kron, Array1, Array2, ArrayView1, ArrayView2};
+use num::complex::Complex64;
+
+use crate::quant::{Frequency, Time};
+
+/// A transmon of the device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Transmon {
+    /// Frequency of the 0-1 transition relative to the rotating frame.
+    pub(crate) freq: Frequency,
+    pub(crate) anharmonicity: Frequency,
+    pub(crate) levels: usize,
+    pub(crate) t1: Option<Time>,
+    pub(crate) t2: Option<Time>,
+}
+
+/// Exchange coupling of two transmons.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Coupling {
+    pub(crate) qubits: (usize, usize),
+    pub(crate) strength: Frequency,
+}
+
+/// Complex drive waveform with rows `[I]` or `[I, Q]` of a transmon.
+#[derive(Debug, Clone)]
+pub(crate) struct Drive<'a> {
+    pub(crate) qubit: usize,
+    pub(crate) waveform: ArrayView2<'a, f64>,
+    /// Rabi frequency at unit amplitude.
+    pub(crate) rabi_freq: Frequency,
+}
+
+/// Real flux waveform of a transmon.
+#[derive(Debug, Clone)]
+pub(crate) struct Flux<'a> {
+    pub(crate) qubit: usize,
+    pub(crate) waveform: ArrayView1<'a, f64>,
+    /// Frequency shift at unit amplitude.
+    pub(crate) sensitivity: Frequency,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum State {
+    Ket(Array1<Complex64>),
+    Density(Array2<Complex64>),
+}
+
+type Operator = Array2<Complex64>;
+
+/// Operators of the device in the product space of the transmons, the first
+/// transmon being the most significant.
+#[derive(Debug)]
+struct Model {
+    static_h: Operator,
+    lowering: Vec<Operator>,
+    number: Vec<Operator>,
+    collapse: Vec<Operator>,
+}
+
+impl Model {
+    fn new(transmons: &[Transmon], couplings: &[Coupling]) -> Result<Self> {
+        let dims: Vec<_> = transmons.iter().map(|t| t.levels).collect();
+        let dim = dims.iter().product::<usize>();
+        let embed = |q: usize, op: &Operator| {
+            dims.iter()
+                .enumerate()
+                .fold(Array2::eye(1), |acc: Operator, (i, &d)| {
+                    if i == q {
+                        kron(&acc, op)
+                    } else {
+                        kron(&acc, &Array2::eye(d))
+                    }
+                })
+        };
+        let mut static_h = Array2::zeros((dim, dim));
+        let mut lowering = Vec::new();
+        let mut number = Vec::new();
+        let mut collapse = Vec::new();
+        for (q, t) in transmons.iter().enumerate() {
+            if t.levels < 2 {
+                bail!("Transmon {} should have at least 2 levels", q);
+            }
+            let a = embed(q, &lowering_operator(t.levels));
+            let n = a.t().mapv(|x| x.conj()).dot(&a);
+            let identity = Array2::<Complex64>::eye(dim);
+            static_h = static_h
+                + &n * t.freq.value()
+                + n.dot(&(&n - &identity)) * (t.anharmonicity.value() / 2.0);
+            let gamma1 = match t.t1 {
+                Some(t1) if t1 <= Time::ZERO => bail!("T1 of transmon {} should be positive", q),
+                Some(t1) => 1.0 / t1.value(),
+                None => 0.0,
+            };
+            if gamma1 > 0.0 {
+                collapse.push(&a * gamma1.sqrt());
+            }
+            if let Some(t2) = t.t2 {
+                let gamma_phi = 1.0 / t2.value() - gamma1 / 2.0;
+                if !(t2 > Time::ZERO && gamma_phi >= 0.0) {
+                    bail!("T2 of transmon {} should be positive and at most 2 T1", q);
+                }
+                if gamma_phi > 0.0 {
+                    collapse.push(&n * (2.0 * gamma_phi).sqrt());
+                }
+            }
+            lowering.push(a);
+            number.push(n);
+        }
+        for c in couplings {
+            let (p, q) = c.qubits;
+            if p == q || p >= transmons.len() || q >= transmons.len() {
+                bail!("Invalid coupling between transmons {} and {}", p, q);
+            }
+            let exchange = dagger(&lowering[p]).dot(&lowering[q]);
+            static_h = static_h + (&exchange + &dagger(&exchange)) * c.strength.value();
+        }
+        Ok(Self {
+            static_h,
+            lowering,
+            number,
+            collapse,
+        })
+    }
+
+    fn dim(&self) -> usize {
+        self.static_h.nrows()
+    }
+
+    /// Time derivative of the state under `h`.
+    fn derivative(&self, h: &Operator, state: &State) -> State {
+        let i2pi = Complex64::new(0.0, -2.0 * std::f64::consts::PI);
+        match state {
+            State::Ket(psi) => State::Ket(h.dot(psi) * i2pi),
+            State::Density(rho) => {
+                let mut d = (h.dot(rho) - rho.dot(h)) * i2pi;
+                for l in &self.collapse {
+                    let ld = dagger(l);
+                    let ldl = ld.dot(l);
+                    d = d + l.dot(rho).dot(&ld) - (ldl.dot(rho) + rho.dot(&ldl)) * 0.5;
+                }
+                State::Density(d)
+            }
+        }
+    }
+}
+
+fn lowering_operator(levels: usize) -> Operator {
+    Array2::from_shape_fn((levels, levels), |(i, j)| {
+        if j == i + 1 {
+            Complex64::from((j as f64).sqrt())
+        } else {
+            Complex64::ZERO
+        }
+    })
+}
+
+fn dagger(op: &Operator) -> Operator {
+    op.t().mapv(|x| x.conj())
+}
+
+impl State {
+    fn axpy(&self, k: f64, other: &State) -> State {
+        match (self, other) {
+            (State::Ket(x), State::Ket(y)) => State::Ket(x + &(y * k)),
+            (State::Density(x), State::Density(y)) => State::Density(x + &(y * k)),
+            _ => unreachable!("States should have the same kind"),
+        }
+    }
+
+    fn dim(&self) -> usize {
+        match self {
+            State::Ket(psi) => psi.len(),
+            State::Density(rho) => rho.nrows(),
+        }
+    }
+}
+
+/// Evolve `initial` over the length of the longest waveform.
+///
+/// Each sample is integrated with `substeps` fourth order Runge-Kutta steps.
+/// Waveforms shorter than the simulation are zero after their end. A ket is
+/// converted to a density matrix if the transmons are dissipative.
+pub(crate) fn simulate(
+    transmons: &[Transmon],
+    couplings: &[Coupling],
+    drives: &[Drive],
+    fluxes: &[Flux],
+    sample_rate: Frequency,
+    initial: State,
+    substeps: usize,
+) -> Result<State> {
+    let model = Model::new(transmons, couplings)?;
+    if initial.dim() != model.dim() {
+        bail!(
+            "Initial state has dimension {}, expected {}",
+            initial.dim(),
+            model.dim()
+        );
+    }
+    if drives
+        .iter()
+        .map(|d| d.qubit)
+        .chain(fluxes.iter().map(|f| f.qubit))
+        .any(|q| q >= transmons.len())
+    {
+        bail!("Waveform of an unknown transmon");
+    }
+    let mut state = match initial {
+        State::Ket(psi) if !model.collapse.is_empty() => {
+            let rho =
+                Array2::from_shape_fn((psi.len(), psi.len()), |(i, j)| psi[i] * psi[j].conj());
+            State::Density(rho)
+        }
+        state => state,
+    };
+    let length = drives
+        .iter()
+        .map(|d| d.waveform.ncols())
+        .chain(fluxes.iter().map(|f| f.waveform.len()))
+        .max()
+        .unwrap_or(0);
+    let substeps = substeps.max(1);
+    let h_step = sample_rate.dt().value() / substeps as f64;
+    for n in 0..length {
+        let mut h = model.static_h.clone();
+        for d in drives {
+            let y = Complex64::new(
+                d.waveform.get((0, n)).copied().unwrap_or(0.0),
+                d.waveform.get((1, n)).copied().unwrap_or(0.0),
+            );
+            let a = &model.lowering[d.qubit];
+            let half = d.rabi_freq.value() / 2.0;
+            h = h + dagger(a) * (y.conj() * half) + a * (y * half);
+        }
+        for f in fluxes {
+            let z = f.waveform.get(n).copied().unwrap_or(0.0);
+            h = h + &model.number[f.qubit] * (z * f.sensitivity.value());
+        }
+        for _ in 0..substeps {
+            let k1 = model.derivative(&h, &state);
+            let k2 = model.derivative(&h, &state.axpy(h_step / 2.0, &k1));
+            let k3 = model.derivative(&h, &state.axpy(h_step / 2.0, &k2));
+            let k4 = model.derivative(&h, &state.axpy(h_step, &k3));
+            state = state
+                .axpy(h_step / 6.0, &k1)
+                .axpy(h_step / 3.0, &k2)
+                .axpy(h_step / 3.0, &k3)
+                .axpy(h_step / 6.0, &k4);
+        }
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array};
+
+    use super::*;
+
+    fn populations(state: &State) -> Array1<f64> {
+        match state {
+            State::Ket(psi) => psi.mapv(|x| x.norm_sqr()),
+            State::Density(rho) => rho.diag().mapv(|x| x.re),
+        }
+    }
+
+    fn transmon(levels: usize, t1: Option<f64>) -> Transmon {
+        Transmon {
+            freq: Frequency::ZERO,
+            anharmonicity: Frequency::new(-200e6).unwrap(),
+            levels,
+            t1: t1.map(|t| Time::new(t).unwrap()),
+            t2: None,
+        }
+    }
+
+    #[test]
+    fn rabi() {
+        let sample_rate = Frequency::new(1e9).unwrap();
+        // 10 MHz Rabi frequency for 50 ns is a pi pulse.
+        let waveform = Array::from_elem((2, 50), 0.0) + array![[1.0], [0.0]];
+        let drive = Drive {
+            qubit: 0,
+            waveform: waveform.view(),
+            rabi_freq: Frequency::new(10e6).unwrap(),
+        };
+        let ground = State::Ket(array![Complex64::ONE, Complex64::ZERO]);
+
+        let state = simulate(
+            &[transmon(2, None)],
+            &[],
+            &[drive],
+            &[],
+            sample_rate,
+            ground,
+            4,
+        )
+        .unwrap();
+
+        let populations = populations(&state);
+        assert!((populations[1] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn decay() {
+        let sample_rate = Frequency::new(1e9).unwrap();
+        let flux = Array1::zeros(100);
+        let flux = Flux {
+            qubit: 0,
+            waveform: flux.view(),
+            sensitivity: Frequency::ZERO,
+        };
+        let excited = State::Ket(array![Complex64::ZERO, Complex64::ONE, Complex64::ZERO]);
+
+        let state = simulate(
+            &[transmon(3, Some(100e-9))],
+            &[],
+            &[],
+            &[flux],
+            sample_rate,
+            excited,
+            1,
+        )
+        .unwrap();
+
+        assert!(matches!(state, State::Density(_)));
+        let populations = populations(&state);
+        assert!((populations[1] - (-1.0f64).exp()).abs() < 1e-6);
+        assert!((populations.sum() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn swap() {
+        let sample_rate = Frequency::new(1e9).unwrap();
+        // Resonant exchange of 5 MHz swaps an excitation in 50 ns.
+        let coupling = Coupling {
+            qubits: (0, 1),
+            strength: Frequency::new(5e6).unwrap(),
+        };
+        let mut initial = Array1::zeros(4);
+        initial[2] = Complex64::ONE;
+
+        let state = simulate(
+            &[transmon(2, None), transmon(2, None)],
+            &[coupling],
+            &[],
+            &[Flux {
+                qubit: 1,
+                waveform: Array1::zeros(50).view(),
+                sensitivity: Frequency::ZERO,
+            }],
+            sample_rate,
+            State::Ket(initial),
+            4,
+        )
+        .unwrap();
+
+        assert!((populations(&state)[1] - 1.0).abs() < 1e-6);
+        assert!(simulate(
+            &[transmon(2, None)],
+            &[coupling],
+            &[],
+            &[],
+            sample_rate,
+            State::Ket(array![Complex64::ONE, Complex64::ZERO]),
+            1,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! Property-based testing utilities.
+//!
+//! The strategies generate random but valid inputs in the public formats of
+//! the crate, so downstream crates can fuzz their integration code against
+//! the real scheduler:
+//!
+//! - [`schedule`]: schedule documents as written by `Element.to_json`.
+//! - [`interp_shape`]: `(knots, controls, degree)` of an interpolated shape.
+//! - [`pulse_list`]: pulse lists as written by `PulseList.to_json`.
+//!
+//! The generated schedules use the channels in [`CHANNELS`] and the shapes in
+//! [`SHAPES`], and don't have duration constraints, so they never contain
+//! oversize elements. The invariant checkers return an error describing the
+//! first violation.
+//!
+//! Golden waveform snapshots are provided by [`snapshot`].
+pub mod snapshot;
+
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Result};
+use hashbrown::HashMap;
+use ndarray::Array2;
+use num::complex::Complex64;
+use proptest::{collection::vec, prelude::*, sample};
+
+use crate::{
+    pulse::{Envelope, PulseListBuilder, PushArgs, Sampler},
+    quant::{Amplitude, ChannelId, Frequency, Phase, ShapeId, Time},
+    schedule::{
+        arrange_tree, Absolute, AbsoluteEntry, Barrier, Element, ElementCommon,
+        ElementCommonBuilder, ElementRef, ElementVariant, Measure, Play, Repeat, ScaleAmplitude,
+        SetFreq, ShiftPhase, Stack, TimeRange,
+    },
+    shape::Shape,
+    Alignment, Direction,
+};
+
+/// Channels used by the generated schedules and pulse lists.
+pub const CHANNELS: [&str; 2] = ["xy0", "xy1"];
+
+/// Shapes used by the generated schedules. All of them are Hann shapes.
+pub const SHAPES: [&str; 1] = ["hann"];
+
+const TIME_TOLERANCE: f64 = 1e-12;
+
+/// Random schedule documents.
+pub fn schedule() -> impl Strategy<Value = String> {
+    element().prop_map(|e| {
+        e.to_json(None)
+            .expect("Generated schedule should serialize")
+    })
+}
+
+/// Random clamped B-spline shapes that vanish at both ends.
+pub fn interp_shape() -> impl Strategy<Value = (Vec<f64>, Vec<f64>, usize)> {
+    (1usize..=3)
+        .prop_flat_map(|degree| (Just(degree), vec(0.0..1.0, degree + 1..8)))
+        .prop_map(|(degree, mut controls)| {
+            let n = controls.len();
+            controls[0] = 0.0;
+            controls[n - 1] = 0.0;
+            let interior = n - degree - 1;
+            let knots = std::iter::repeat_n(0.0, degree + 1)
+                .chain((1..=interior).map(|i| i as f64 / (interior + 1) as f64))
+                .chain(std::iter::repeat_n(1.0, degree + 1))
+                .collect();
+            (knots, controls, degree)
+        })
+}
+
+/// Random pulse list documents.
+pub fn pulse_list() -> impl Strategy<Value = String> {
+    let pulse = (
+        prop::option::of(Just(Shape::new_hann())),
+        time(0.0..100e-9),
+        time(0.0..100e-9),
+        -1e8..1e8,
+        time(0.0..1e-6),
+        -1.0..1.0,
+        -1e-9..1e-9,
+        -1.0..1.0,
+    );
+    vec(pulse, 0..16).prop_map(|pulses| {
+        let mut builder = PulseListBuilder::new(
+            Amplitude::new(0.1 / 2f64.powi(16)).unwrap(),
+            Time::new(TIME_TOLERANCE).unwrap(),
+        );
+        for (shape, width, plateau, freq, time, amplitude, drag_coef, phase) in pulses {
+            builder.push(PushArgs {
+                envelope: Envelope::new(shape, width, plateau),
+                global_freq: Frequency::ZERO,
+                local_freq: Frequency::new(freq).unwrap(),
+                time,
+                amplitude: Amplitude::new(amplitude).unwrap(),
+                drag_coef,
+                phase: Phase::new(phase).unwrap(),
+                id: None,
+            });
+        }
+        let list = builder.build_with(|_| {});
+        crate::codec::to_json(&list).expect("Generated pulse list should serialize")
+    })
+}
+
+/// Check that every element of the schedule document is arranged inside the
+/// total duration and in a span not shorter than its content.
+pub fn check_duration_consistency(schedule: &str) -> Result<()> {
+    let root = Arc::new(Element::from_json(schedule)?);
+    let total = root.measure();
+    let tolerance = Time::new(TIME_TOLERANCE).unwrap();
+    let time_range = TimeRange {
+        start: Time::ZERO,
+        span: total,
+    };
+    for arranged in arrange_tree(&root, time_range) {
+        let inner = arranged.item.inner_time_range(arranged.time_range);
+        let end = inner.start + inner.span;
+        ensure!(
+            inner.start >= -tolerance && end <= total + tolerance,
+            "Element at {} with span {} is outside the total duration {}",
+            inner.start.value(),
+            inner.span.value(),
+            total.value()
+        );
+        let required = arranged.item.variant.measure();
+        ensure!(
+            required <= inner.span + tolerance,
+            "Element at {} requires {} but is arranged in {}",
+            inner.start.value(),
+            required.value(),
+            inner.span.value()
+        );
+    }
+    Ok(())
+}
+
+/// Check the energy of a sampled Hann pulse against the analytic value
+/// `amplitude^2 * (plateau + 3 / 8 * width)`.
+///
+/// The allowed error is the energy of two samples at full amplitude, which
+/// covers the discretization at both edges.
+pub fn check_sampled_energy(
+    amplitude: f64,
+    width: f64,
+    plateau: f64,
+    sample_rate: f64,
+) -> Result<()> {
+    let name = ChannelId::new(CHANNELS[0]);
+    let sample_rate = Frequency::new(sample_rate)?;
+    let dt = sample_rate.dt();
+    let width = Time::new(width)?;
+    let plateau = Time::new(plateau)?;
+    let mut builder = PulseListBuilder::new(Amplitude::ZERO, Time::new(TIME_TOLERANCE)?);
+    builder.push(PushArgs {
+        envelope: Envelope::new(Some(Shape::new_hann()), width, plateau),
+        global_freq: Frequency::ZERO,
+        local_freq: Frequency::ZERO,
+        time: dt,
+        amplitude: Amplitude::new(amplitude)?,
+        drag_coef: 0.0,
+        phase: Phase::ZERO,
+        id: None,
+    });
+    let list = builder.build_with(|_| {});
+    let length = ((width + plateau).value() * sample_rate.value()).ceil() as usize + 3;
+    let mut waveform = Array2::zeros((2, length));
+    let mut sampler = Sampler::new(HashMap::from([(name, list)]));
+    sampler.add_channel(
+        name,
+        waveform.view_mut(),
+        sample_rate,
+        Time::ZERO,
+        -10,
+        Complex64::ZERO,
+    );
+    sampler.sample(Time::new(TIME_TOLERANCE)?)?;
+    let energy = waveform.iter().map(|x| x * x).sum::<f64>() * dt.value();
+    let expected = amplitude.powi(2) * (plateau.value() + 0.375 * width.value());
+    let tolerance = amplitude.powi(2) * 2.0 * dt.value() + 1e-9 * expected;
+    if (energy - expected).abs() > tolerance {
+        bail!(
+            "Sampled energy {} differs from the analytic value {} by more than {}",
+            energy,
+            expected,
+            tolerance
+        );
+    }
+    Ok(())
+}
+
+fn time(range: std::ops::Range<f64>) -> impl Strategy<Value = Time> {
+    range.prop_map(|x| Time::new(x).unwrap())
+}
+
+fn channel() -> impl Strategy<Value = ChannelId> {
+    sample::select(&CHANNELS[..]).prop_map(ChannelId::new)
+}
+
+fn common() -> impl Strategy<Value = ElementCommon> {
+    let alignment = prop_oneof![
+        Just(Alignment::End),
+        Just(Alignment::Start),
+        Just(Alignment::Center),
+        Just(Alignment::Stretch),
+    ];
+    (time(0.0..10e-9), time(0.0..10e-9), alignment).prop_map(|(before, after, alignment)| {
+        ElementCommonBuilder::new()
+            .margin((before, after))
+            .alignment(alignment)
+            .build()
+            .unwrap()
+    })
+}
+
+fn leaf() -> impl Strategy<Value = ElementRef> {
+    let play = (
+        channel(),
+        prop::option::of(sample::select(&SHAPES[..])),
+        -1.0..1.0,
+        time(0.0..100e-9),
+        time(0.0..100e-9),
+        any::<bool>(),
+    )
+        .prop_map(|(channel, shape, amplitude, width, plateau, flexible)| {
+            let play = Play::new(
+                channel,
+                shape.map(ShapeId::new),
+                Amplitude::new(amplitude).unwrap(),
+                width,
+            )
+            .and_then(|p| p.with_plateau(plateau))
+            .unwrap()
+            .with_flexible(flexible);
+            ElementVariant::from(play)
+        });
+    let shift_phase = (channel(), -1.0..1.0)
+        .prop_map(|(c, p)| ShiftPhase::new(c, Phase::new(p).unwrap()).unwrap().into());
+    let set_freq = (channel(), -1e8..1e8)
+        .prop_map(|(c, f)| SetFreq::new(c, Frequency::new(f).unwrap()).unwrap().into());
+    let barrier = sample::subsequence(CHANNELS.to_vec(), 0..=CHANNELS.len())
+        .prop_map(|c| Barrier::new(c.into_iter().map(ChannelId::new).collect()).into());
+    (common(), prop_oneof![play, shift_phase, set_freq, barrier])
+        .prop_map(|(common, variant)| Arc::new(Element::new(common, variant)))
+}
+
+fn element() -> impl Strategy<Value = ElementRef> {
+    leaf().prop_recursive(4, 32, 4, |inner| {
+        let direction = prop_oneof![Just(Direction::Backward), Just(Direction::Forward)];
+        let stack = (common(), direction, vec(inner.clone(), 0..4)).prop_map(
+            |(common, direction, children)| {
+                let stack = Stack::new()
+                    .with_direction(direction)
+                    .with_children(children);
+                Arc::new(Element::new(common, stack))
+            },
+        );
+        let absolute = (common(), vec((inner.clone(), time(0.0..100e-9)), 0..4)).prop_map(
+            |(common, children)| {
+                let children = children
+                    .into_iter()
+                    .map(|(e, t)| AbsoluteEntry::new(e).with_time(t).unwrap())
+                    .collect();
+                let absolute = Absolute::new().with_children(children);
+                Arc::new(Element::new(common, absolute))
+            },
+        );
+        let repeat = (common(), inner.clone(), 0usize..4, time(0.0..10e-9)).prop_map(
+            |(common, child, count, spacing)| {
+                let repeat = Repeat::new(child, count).with_spacing(spacing).unwrap();
+                Arc::new(Element::new(common, repeat))
+            },
+        );
+        let scale = (common(), inner, -2.0..2.0).prop_map(|(common, child, factor)| {
+            let scale = ScaleAmplitude::new(child, factor).unwrap();
+            Arc::new(Element::new(common, scale))
+        });
+        prop_oneof![stack, absolute, repeat, scale]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pulse::PulseList;
+
+    proptest! {
+        #[test]
+        fn generated_inputs_are_valid(
+            schedule in schedule(),
+            (knots, controls, degree) in interp_shape(),
+            list in pulse_list(),
+        ) {
+            check_duration_consistency(&schedule).unwrap();
+            Shape::new_interp(knots, controls, degree).unwrap();
+            crate::codec::from_json::<PulseList>(&list).unwrap();
+        }
+
+        #[test]
+        fn sampled_energy(
+            amplitude in -1.0..1.0,
+            width in 0.0..100e-9,
+            plateau in 0.0..100e-9,
+        ) {
+            check_sampled_energy(amplitude, width, plateau, 2e9).unwrap();
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Golden waveform snapshots.
+//!
+//! A snapshot file stores the samples of every channel together with a hash
+//! of their bit patterns. Comparison succeeds if the hashes are equal, or if
+//! the shapes are equal and no sample differs by more than `epsilon`, so the
+//! same file can verify refactors bit-for-bit or within a tolerance.
+//!
+//! Missing or mismatching snapshots are rewritten instead of compared when the
+//! environment variable `BOSING_UPDATE_SNAPSHOTS` is set to `1`.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use ndarray::ArrayView2;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable that enables rewriting snapshot files.
+pub const UPDATE_ENV: &str = "BOSING_UPDATE_SNAPSHOTS";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ChannelSnapshot {
+    shape: (usize, usize),
+    hash: String,
+    samples: Vec<f64>,
+}
+
+/// Hash of the shape and the bit patterns of the samples.
+///
+/// FNV-1a is used because it is stable across releases and platforms.
+pub fn hash_waveform(waveform: ArrayView2<f64>) -> String {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    let (rows, cols) = waveform.dim();
+    let bytes = [rows as u64, cols as u64]
+        .into_iter()
+        .chain(waveform.iter().map(|x| x.to_bits()))
+        .flat_map(u64::to_le_bytes);
+    let hash = bytes.fold(OFFSET, |h, b| (h ^ u64::from(b)).wrapping_mul(PRIME));
+    format!("{:016x}", hash)
+}
+
+/// Compare the waveforms with the snapshot file, or write the file if
+/// [`UPDATE_ENV`] is set.
+pub fn assert_snapshot<'a>(
+    path: impl AsRef<Path>,
+    waveforms: impl IntoIterator<Item = (&'a str, ArrayView2<'a, f64>)>,
+    epsilon: f64,
+) -> Result<()> {
+    let path = path.as_ref();
+    let actual: BTreeMap<_, _> = waveforms
+        .into_iter()
+        .map(|(name, waveform)| {
+            let snapshot = ChannelSnapshot {
+                shape: waveform.dim(),
+                hash: hash_waveform(waveform),
+                samples: waveform.iter().copied().collect(),
+            };
+            (name.to_string(), snapshot)
+        })
+        .collect();
+    if std::env::var(UPDATE_ENV).is_ok_and(|v| v == "1") {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_string_pretty(&actual)?;
+        fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+        return Ok(());
+    }
+    let content = fs::read_to_string(path).with_context(|| {
+        format!(
+            "Failed to read snapshot {}, set {}=1 to create it",
+            path.display(),
+            UPDATE_ENV
+        )
+    })?;
+    let expected: BTreeMap<String, ChannelSnapshot> = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid snapshot {}", path.display()))?;
+    if !expected.keys().eq(actual.keys()) {
+        bail!(
+            "Channels {:?} differ from the snapshot {:?}",
+            actual.keys().collect::<Vec<_>>(),
+            expected.keys().collect::<Vec<_>>()
+        );
+    }
+    for (name, a) in &actual {
+        let e = &expected[name];
+        if a.hash == e.hash {
+            continue;
+        }
+        if a.shape != e.shape {
+            bail!(
+                "Shape {:?} of channel '{}' differs from the snapshot {:?}",
+                a.shape,
+                name,
+                e.shape
+            );
+        }
+        let (index, diff) = a
+            .samples
+            .iter()
+            .zip(&e.samples)
+            .map(|(x, y)| (x - y).abs())
+            .enumerate()
+            .fold((0, 0.0), |max, (i, d)| if d > max.1 { (i, d) } else { max });
+        if diff > epsilon {
+            bail!(
+                "Sample {} of channel '{}' differs from the snapshot by {}, epsilon: {}",
+                index,
+                name,
+                diff,
+                epsilon
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+    use ndarray::Array2;
+    use num::complex::Complex64;
+
+    use super::*;
+    use crate::{
+        pulse::{Envelope, PulseListBuilder, PushArgs, Sampler},
+        quant::{Amplitude, ChannelId, Frequency, Phase, Time},
+        shape::Shape,
+    };
+
+    /// Pulses with sub-sample start times, drag, plateaus, and detuning to
+    /// cover `mix_add_envelope` and `AlignedIndex`.
+    fn reference_waveform() -> Array2<f64> {
+        let t = |x| Time::new(x).unwrap();
+        let interp = Shape::new_interp(
+            vec![0.0, 0.0, 0.0, 0.5, 1.0, 1.0, 1.0],
+            vec![0.0, 1.0, 0.5, 0.0],
+            2,
+        )
+        .unwrap();
+        let mut builder = PulseListBuilder::new(Amplitude::ZERO, t(1e-12));
+        for (shape, width, plateau, time, amplitude, drag_coef, freq) in [
+            (
+                Some(Shape::new_hann()),
+                10e-9,
+                5e-9,
+                1.3e-9,
+                0.5,
+                1e-9,
+                100e6,
+            ),
+            (None, 7.7e-9, 0.0, 20.1e-9, -0.3, 0.0, 0.0),
+            (Some(interp), 6.2e-9, 0.0, 28.05e-9, 0.8, -0.5e-9, -37e6),
+        ] {
+            builder.push(PushArgs {
+                envelope: Envelope::new(shape, t(width), t(plateau)),
+                global_freq: Frequency::new(20e6).unwrap(),
+                local_freq: Frequency::new(freq).unwrap(),
+                time: t(time),
+                amplitude: Amplitude::new(amplitude).unwrap(),
+                drag_coef,
+                phase: Phase::new(0.125).unwrap(),
+                id: None,
+            });
+        }
+        let name = ChannelId::new("xy");
+        let mut waveform = Array2::zeros((2, 80));
+        let mut sampler = Sampler::new(HashMap::from([(name, builder.build_with(|_| {}))]));
+        sampler.add_channel(
+            name,
+            waveform.view_mut(),
+            Frequency::new(2e9).unwrap(),
+            t(0.4e-9),
+            -3,
+            Complex64::ZERO,
+        );
+        sampler.sample(t(1e-12)).unwrap();
+        waveform
+    }
+
+    #[test]
+    fn reference() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/snapshots/reference.json"
+        );
+        let waveform = reference_waveform();
+
+        assert_snapshot(path, [("xy", waveform.view())], 1e-12).unwrap();
+    }
+
+    #[test]
+    fn tolerance() {
+        let path =
+            std::env::temp_dir().join(format!("bosing-snapshot-{}.json", std::process::id()));
+        let waveform = reference_waveform();
+        let content = serde_json::json!({
+            "xy": {
+                "shape": waveform.dim(),
+                "hash": "",
+                "samples": waveform.iter().map(|x| x + 1e-13).collect::<Vec<_>>(),
+            }
+        });
+        fs::write(&path, content.to_string()).unwrap();
+
+        assert!(assert_snapshot(&path, [("xy", waveform.view())], 1e-12).is_ok());
+        assert!(assert_snapshot(&path, [("xy", waveform.view())], 0.0).is_err());
+        assert!(assert_snapshot(&path, [("z", waveform.view())], 1e-12).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
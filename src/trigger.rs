@@ -0,0 +1,189 @@
+//! Alignment of several AWGs started by a shared trigger.
+//!
+//! Each AWG starts its output a fixed latency after the trigger and counts
+//! time in cycles of its own sequencer clock. To start all outputs together,
+//! a device with a shorter latency waits for whole clock cycles after the
+//! trigger, and the lead delay shorter than one cycle is added to the delay of
+//! its channels.
+//!
+//! [`locate`] goes the other way and finds where a scheduled time ends up in
+//! the output of a channel, e.g. to compare with oscilloscope measurements.
+use anyhow::{bail, Result};
+
+#[cfg(any(feature = "python", test))]
+use crate::quant::AlignedIndex;
+use crate::quant::{Frequency, Time};
+
+/// Trigger latency and sequencer clock of an AWG.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Device {
+    pub(crate) latency: Time,
+    pub(crate) clock: Frequency,
+}
+
+/// Wait of a device after the trigger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Trigger {
+    /// Whole clock cycles the sequencer waits before playing.
+    pub(crate) wait_cycles: u64,
+    /// Rest of the lead delay, added to the delay of the channels.
+    pub(crate) delay: Time,
+}
+
+/// Plan the triggers of `devices` such that their outputs start together, at
+/// the latency of the slowest device after the trigger.
+///
+/// Lead delays within `time_tolerance` of a whole number of clock cycles are
+/// waited for completely. The triggers are in the order of the devices.
+pub(crate) fn plan_triggers(devices: &[Device], time_tolerance: Time) -> Result<Vec<Trigger>> {
+    for device in devices {
+        if !(device.latency.is_finite() && device.latency >= Time::ZERO) {
+            bail!("Invalid trigger latency {}", device.latency);
+        }
+        if !(device.clock.value().is_finite() && device.clock > Frequency::ZERO) {
+            bail!("Invalid clock frequency {}", device.clock);
+        }
+    }
+    let Some(start) = devices.iter().map(|d| d.latency).max() else {
+        return Ok(Vec::new());
+    };
+    let triggers = devices
+        .iter()
+        .map(|d| {
+            let lead = start - d.latency;
+            let clock = d.clock.value();
+            let wait_cycles = ((lead + time_tolerance).value() * clock).floor() as u64;
+            let delay = (lead - Time::new(wait_cycles as f64 / clock)?).max(Time::ZERO);
+            Ok(Trigger { wait_cycles, delay })
+        })
+        .collect::<Result<_>>()?;
+    Ok(triggers)
+}
+
+/// Sampling parameters of a channel.
+#[cfg(any(feature = "python", test))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ChannelClock {
+    pub(crate) sample_rate: Frequency,
+    /// Delay of the channel including the lead delay of its trigger.
+    pub(crate) delay: Time,
+    pub(crate) align_level: i32,
+}
+
+/// Position of a scheduled time in the output of a channel.
+#[cfg(any(feature = "python", test))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SamplePosition {
+    /// Delayed time aligned to the grid of the channel, as used by the
+    /// sampler for the start of a pulse.
+    pub(crate) index: AlignedIndex,
+    /// Sequencer clock cycle containing the first sample, counted from the
+    /// start of the output. `None` without a device.
+    pub(crate) cycle: Option<i64>,
+    /// Time from the trigger to the aligned time. `None` without a device.
+    pub(crate) after_trigger: Option<Time>,
+}
+
+/// Locate the scheduled `time` in the output of a channel of `device`, which
+/// waits for `trigger` before the output starts.
+#[cfg(any(feature = "python", test))]
+pub(crate) fn locate(
+    time: Time,
+    channel: ChannelClock,
+    device: Option<(Device, Trigger)>,
+) -> Result<SamplePosition> {
+    let index = AlignedIndex::new(
+        time + channel.delay,
+        channel.sample_rate,
+        channel.align_level,
+    )?;
+    let sample_rate = channel.sample_rate.value();
+    let (cycle, after_trigger) = match device {
+        Some((device, trigger)) => {
+            let clock = device.clock.value();
+            let cycle = (index.first_sample() as f64 / sample_rate * clock).floor() as i64;
+            let output_start = device.latency + Time::new(trigger.wait_cycles as f64 / clock)?;
+            let after_trigger = output_start + Time::new(index.value() / sample_rate)?;
+            (Some(cycle), Some(after_trigger))
+        }
+        None => (None, None),
+    };
+    Ok(SamplePosition {
+        index,
+        cycle,
+        after_trigger,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::assert_approx_eq;
+
+    use super::*;
+
+    #[test]
+    fn plan() {
+        let device = |latency, clock| Device {
+            latency: Time::new(latency).unwrap(),
+            clock: Frequency::new(clock).unwrap(),
+        };
+        let devices = [
+            device(300e-9, 250e6),
+            device(210e-9, 250e6),
+            device(100e-9, 300e6),
+        ];
+
+        let triggers = plan_triggers(&devices, Time::new(1e-12).unwrap()).unwrap();
+
+        assert_eq!(triggers[0].wait_cycles, 0);
+        assert_eq!(triggers[0].delay, Time::ZERO);
+        // 90 ns is 22 cycles of 4 ns and 2 ns.
+        assert_eq!(triggers[1].wait_cycles, 22);
+        assert_approx_eq!(f64, triggers[1].delay.value(), 2e-9, epsilon = 1e-15);
+        // 200 ns is exactly 60 cycles of 3.33 ns.
+        assert_eq!(triggers[2].wait_cycles, 60);
+        assert_approx_eq!(f64, triggers[2].delay.value(), 0.0, epsilon = 1e-15);
+        assert!(plan_triggers(&[device(-1e-9, 250e6)], Time::ZERO).is_err());
+        assert!(plan_triggers(&[], Time::ZERO).unwrap().is_empty());
+    }
+
+    #[test]
+    fn locate_times() {
+        let t = |x: f64| Time::new(x * 1e-9).unwrap();
+        let device = Device {
+            latency: t(210.0),
+            clock: Frequency::new(250e6).unwrap(),
+        };
+        // Waits 22 cycles and 2 ns to start with a device of 300 ns latency,
+        // the channel has another 1 ns of delay.
+        let trigger = Trigger {
+            wait_cycles: 22,
+            delay: t(2.0),
+        };
+        let channel = ChannelClock {
+            sample_rate: Frequency::new(2e9).unwrap(),
+            delay: t(3.0),
+            align_level: -2,
+        };
+
+        let position = locate(t(10.0), channel, Some((device, trigger))).unwrap();
+        assert_eq!(position.index.value(), 26.0);
+        assert_eq!(position.cycle, Some(3));
+        assert_approx_eq!(
+            f64,
+            position.after_trigger.unwrap().value(),
+            311e-9,
+            epsilon = 1e-15
+        );
+
+        // 26.2 samples are aligned up to a quarter sample.
+        let position = locate(t(10.1), channel, Some((device, trigger))).unwrap();
+        assert_eq!(position.index.value(), 26.25);
+        assert_eq!(position.index.first_sample(), 27);
+        assert_eq!(position.cycle, Some(3));
+
+        let position = locate(t(10.0), channel, None).unwrap();
+        assert_eq!(position.index.value(), 26.0);
+        assert_eq!(position.after_trigger, None);
+    }
+}
@@ -0,0 +1,350 @@
+//! Dry-run validation of a schedule against a hardware configuration.
+//!
+//! The schedule is measured and arranged but not sampled, so the checks are
+//! cheap enough to run on every edit.
+use std::fmt;
+
+use hashbrown::HashSet;
+
+use crate::{
+    config::HardwareConfig,
+    quant::{Amplitude, ChannelId, ShapeId, Time},
+    schedule::{arrange_tree_inherited, ElementRef, ElementVariant, Measure, Play, TimeRange},
+};
+
+/// Whether an issue prevents the schedule from being sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found by [`validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Issue {
+    /// The channel is not in the configuration.
+    UnknownChannel { channel: ChannelId },
+    /// The shape is not in the configuration.
+    UnknownShape { shape: ShapeId },
+    /// An element is placed in a span shorter than its content.
+    Oversize { required: Time, available: Time },
+    /// The pulse duration is not a multiple of the alignment step of the
+    /// channel.
+    OffGrid {
+        channel: ChannelId,
+        time: Time,
+        duration: Time,
+        step: Time,
+    },
+    /// The absolute amplitude on top of the baseline of the channel exceeds
+    /// the full scale of 1.
+    AmplitudeOutOfRange {
+        channel: ChannelId,
+        time: Time,
+        amplitude: Amplitude,
+    },
+    /// The pulse is outside the waveform after the channel delay.
+    OutOfRange { channel: ChannelId, time: Time },
+}
+
+/// An issue with its severity under the given configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Diagnostic {
+    pub(crate) severity: Severity,
+    pub(crate) issue: Issue,
+}
+
+/// Check the schedule against the configuration without sampling.
+///
+/// Issues that would make `generate_waveforms` fail are reported as errors,
+/// the others as warnings. `allow_oversize` and `allow_truncate` in the
+/// configuration downgrade the corresponding issues to warnings.
+pub(crate) fn validate(schedule: &ElementRef, config: &HardwareConfig) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        config,
+        unknown_channels: HashSet::new(),
+        unknown_shapes: HashSet::new(),
+        diagnostics: Vec::new(),
+    };
+    let time_range = TimeRange {
+        start: Time::ZERO,
+        span: schedule.measure(),
+    };
+    for (arranged, inherited) in arrange_tree_inherited(schedule, time_range) {
+        let item = arranged.item;
+        let time_range = item.inner_time_range(arranged.time_range);
+        let required = item.variant.measure();
+        if required > time_range.span + config.time_tolerance {
+            validator.report(
+                validator.severity(
+                    item.common
+                        .allow_oversize()
+                        .unwrap_or(config.allow_oversize),
+                ),
+                Issue::Oversize {
+                    required,
+                    available: time_range.span,
+                },
+            );
+        }
+        match &item.variant {
+            ElementVariant::Play(play) => validator.check_play(play, time_range, inherited.scale),
+            ElementVariant::Repeat(_)
+            | ElementVariant::ScaleAmplitude(_)
+            | ElementVariant::Stack(_)
+            | ElementVariant::Absolute(_)
+            | ElementVariant::Grid(_) => {}
+            variant => {
+                for channel in variant.channels() {
+                    validator.check_channel(channel);
+                }
+            }
+        }
+    }
+    validator.diagnostics
+}
+
+struct Validator<'a> {
+    config: &'a HardwareConfig,
+    unknown_channels: HashSet<ChannelId>,
+    unknown_shapes: HashSet<ShapeId>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn severity(&self, allowed: bool) -> Severity {
+        if allowed {
+            Severity::Warning
+        } else {
+            Severity::Error
+        }
+    }
+
+    fn report(&mut self, severity: Severity, issue: Issue) {
+        self.diagnostics.push(Diagnostic { severity, issue });
+    }
+
+    /// Report a missing channel only once.
+    fn check_channel(&mut self, channel: &ChannelId) -> bool {
+        let found = self.config.channels.contains_key(channel.as_ref());
+        if !found && self.unknown_channels.insert(*channel) {
+            self.report(Severity::Error, Issue::UnknownChannel { channel: *channel });
+        }
+        found
+    }
+
+    fn check_play(&mut self, play: &Play, time_range: TimeRange, scale: f64) {
+        if let Some(shape) = play.shape_id() {
+            if !self.config.shapes.contains_key(shape.as_ref())
+                && self.unknown_shapes.insert(shape.clone())
+            {
+                self.report(
+                    Severity::Error,
+                    Issue::UnknownShape {
+                        shape: shape.clone(),
+                    },
+                );
+            }
+        }
+        let channel = play.channel_id();
+        if !self.check_channel(channel) {
+            return;
+        }
+        let c = &self.config.channels[channel.as_ref()];
+        let time = time_range.start;
+        let plateau = if play.flexible() {
+            time_range.span - play.edge_width()
+        } else {
+            play.plateau()
+        };
+        let duration = play.edge_width() + plateau;
+        let step = c.sample_rate.dt() * f64::from(c.align_level).exp2();
+        let steps = duration.value() / step.value();
+        if (steps - steps.round()).abs() * step.value() > self.config.time_tolerance.value() {
+            self.report(
+                Severity::Warning,
+                Issue::OffGrid {
+                    channel: *channel,
+                    time,
+                    duration,
+                    step,
+                },
+            );
+        }
+        let amplitude = play.amplitude() * scale;
+        if amplitude.value().abs() + c.baseline.norm() > 1.0 {
+            self.report(
+                Severity::Warning,
+                Issue::AmplitudeOutOfRange {
+                    channel: *channel,
+                    time,
+                    amplitude,
+                },
+            );
+        }
+        let start = time + c.delay;
+        let end = start + duration;
+        let total = c.sample_rate.dt() * c.length as f64;
+        let tolerance = self.config.time_tolerance;
+        if start < -tolerance || end > total + tolerance {
+            self.report(
+                self.severity(self.config.allow_truncate),
+                Issue::OutOfRange {
+                    channel: *channel,
+                    time,
+                },
+            );
+        }
+    }
+}
+
+impl Diagnostic {
+    pub(crate) fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: ")?,
+            Severity::Error => write!(f, "error: ")?,
+        }
+        match &self.issue {
+            Issue::UnknownChannel { channel } => write!(f, "Channel '{}' not found", channel),
+            Issue::UnknownShape { shape } => write!(f, "Shape '{}' not found", shape),
+            Issue::Oversize {
+                required,
+                available,
+            } => write!(
+                f,
+                "Oversize element, required duration: {}, available duration: {}",
+                required, available
+            ),
+            Issue::OffGrid {
+                channel,
+                time,
+                duration,
+                step,
+            } => write!(
+                f,
+                "Pulse duration {} on channel '{}' at {} is not a multiple of {}",
+                duration, channel, time, step
+            ),
+            Issue::AmplitudeOutOfRange {
+                channel,
+                time,
+                amplitude,
+            } => write!(
+                f,
+                "Amplitude {} on channel '{}' at {} exceeds full scale",
+                amplitude.value(),
+                channel,
+                time
+            ),
+            Issue::OutOfRange { channel, time } => write!(
+                f,
+                "Pulse on channel '{}' at {} is outside the waveform",
+                channel, time
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use num::complex::Complex64;
+
+    use super::*;
+    use crate::{
+        config::ChannelConfig,
+        quant::Frequency,
+        schedule::{Element, ElementCommonBuilder, Stack},
+    };
+
+    fn play(channel: &str, amplitude: f64, width: f64) -> ElementRef {
+        let play = Play::new(
+            ChannelId::new(channel),
+            Some(ShapeId::new("hann")),
+            Amplitude::new(amplitude).unwrap(),
+            Time::new(width).unwrap(),
+        )
+        .unwrap();
+        Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            play,
+        ))
+    }
+
+    #[test]
+    fn issues() {
+        let channel = ChannelConfig {
+            base_freq: Frequency::ZERO,
+            sample_rate: Frequency::new(1e9).unwrap(),
+            length: 10,
+            delay: Time::ZERO,
+            align_level: 0,
+            baseline: Complex64::ZERO,
+            ringdown: None,
+            net_zero: false,
+            idle_tone: None,
+            predistortion: None,
+            iq_matrix: None,
+            offset: None,
+            iir: None,
+            fir: None,
+            filter_offset: false,
+            is_real: false,
+            time_grid: None,
+            memory: None,
+            export_sample_rate: None,
+        };
+        let config = HardwareConfig {
+            time_tolerance: Time::new(1e-12).unwrap(),
+            amp_tolerance: Amplitude::new(0.0).unwrap(),
+            allow_oversize: false,
+            allow_truncate: false,
+            fractional_delay: false,
+            oversampling: 1,
+            channels: BTreeMap::from([("xy".to_string(), channel)]),
+            shapes: BTreeMap::new(),
+            crosstalk: None,
+            time_grid: None,
+            lo_freqs: BTreeMap::new(),
+            channel_lo_freqs: BTreeMap::new(),
+            devices: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            leakage: None,
+        };
+        let schedule = Arc::new(Element::new(
+            ElementCommonBuilder::new().build().unwrap(),
+            Stack::new().with_children(vec![
+                play("xy", 1.5, 4.5e-9),
+                play("xy", 0.5, 8e-9),
+                play("z", 0.5, 4e-9),
+            ]),
+        ));
+
+        let diagnostics = validate(&schedule, &config);
+        let issues: Vec<_> = diagnostics.iter().map(|d| &d.issue).collect();
+
+        assert!(diagnostics.iter().any(Diagnostic::is_error));
+        assert!(issues.contains(&&Issue::UnknownChannel {
+            channel: ChannelId::new("z")
+        }));
+        assert_eq!(
+            issues
+                .iter()
+                .filter(|i| matches!(i, Issue::UnknownShape { .. }))
+                .count(),
+            1
+        );
+        assert!(issues.iter().any(|i| matches!(i, Issue::OffGrid { .. })));
+        assert!(issues
+            .iter()
+            .any(|i| matches!(i, Issue::AmplitudeOutOfRange { .. })));
+        assert!(issues.iter().any(|i| matches!(i, Issue::OutOfRange { .. })));
+    }
+}
@@ -0,0 +1,262 @@
+//! JavaScript API for previewing schedules in the browser.
+//!
+//! Build the package with `wasm-pack build --target web --no-default-features
+//! --features wasm`. The waveforms are sampled by the same code as the python
+//! API, single threaded without the `parallel` feature.
+//!
+//! The schedule is a JSON document written by `Element.to_json`. The channels,
+//! shapes, and options are described by a JSON request:
+//!
+//! ```json
+//! {
+//!     "channels": [
+//!         {"name": "xy0", "base_freq": 100e6, "sample_rate": 2e9, "length": 1000}
+//!     ],
+//!     "shapes": [
+//!         {"name": "hann", "type": "hann"},
+//!         {"name": "drag", "type": "interp", "knots": [], "controls": [], "degree": 3}
+//!     ],
+//!     "options": {"time_tolerance": 1e-12, "allow_oversize": false}
+//! }
+//! ```
+//!
+//! `delay`, `align_level`, `baseline`, `net_zero`, and `is_real` of a channel
+//! are optional with the defaults of the python `Channel`. The options are
+//! the keyword arguments of the python `generate_waveforms`.
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use hashbrown::HashMap;
+use ndarray::Array2;
+use num::complex::Complex64;
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    pipeline::{self, ChannelSpec, Filters, Options},
+    quant::{ChannelId, Frequency, ShapeId, Time},
+    schedule::Element,
+    shape::Shape,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    channels: Vec<ChannelData>,
+    #[serde(default)]
+    shapes: Vec<ShapeData>,
+    #[serde(default)]
+    options: Options,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChannelData {
+    name: ChannelId,
+    #[serde(default)]
+    base_freq: Frequency,
+    sample_rate: Frequency,
+    length: usize,
+    #[serde(default)]
+    delay: Time,
+    #[serde(default = "default_align_level")]
+    align_level: i32,
+    #[serde(default)]
+    baseline: [f64; 2],
+    #[serde(default)]
+    net_zero: bool,
+    #[serde(default)]
+    is_real: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeData {
+    Hann {
+        name: ShapeId,
+    },
+    Interp {
+        name: ShapeId,
+        knots: Vec<f64>,
+        controls: Vec<f64>,
+        degree: usize,
+    },
+}
+
+fn default_align_level() -> i32 {
+    -10
+}
+
+/// Sampled waveforms of the channels.
+///
+/// Real channels have no quadrature samples.
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct Waveforms {
+    channels: Vec<String>,
+    waveforms: HashMap<ChannelId, Array2<f64>>,
+    warnings: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl Waveforms {
+    /// Names of the channels in the order of the request.
+    #[wasm_bindgen(getter)]
+    pub fn channels(&self) -> Vec<String> {
+        self.channels.clone()
+    }
+
+    /// Non-fatal warnings of the compilation and the sampling.
+    #[wasm_bindgen(getter)]
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.clone()
+    }
+
+    /// In-phase samples of the channel.
+    pub fn i(&self, channel: &str) -> Result<Vec<f64>, JsError> {
+        self.row(channel, 0).map_err(to_js_error)
+    }
+
+    /// Quadrature samples of the channel, empty for real channels.
+    pub fn q(&self, channel: &str) -> Result<Vec<f64>, JsError> {
+        self.row(channel, 1).map_err(to_js_error)
+    }
+}
+
+impl Waveforms {
+    fn row(&self, channel: &str, index: usize) -> Result<Vec<f64>> {
+        let waveform = self
+            .waveforms
+            .get(&ChannelId::new(channel))
+            .with_context(|| format!("Unknown channel {}", channel))?;
+        if index >= waveform.nrows() {
+            return Ok(Vec::new());
+        }
+        Ok(waveform.row(index).to_vec())
+    }
+}
+
+/// Sample the waveforms of a schedule.
+///
+/// `schedule` is a JSON document written by `Element.to_json` and `request`
+/// describes the channels, shapes, and options, see the module docs.
+#[wasm_bindgen(js_name = generateWaveforms)]
+pub fn generate_waveforms(schedule: &str, request: &str) -> Result<Waveforms, JsError> {
+    sample(schedule, request).map_err(to_js_error)
+}
+
+fn to_js_error(err: anyhow::Error) -> JsError {
+    JsError::new(&format!("{:#}", err))
+}
+
+fn sample(schedule: &str, request: &str) -> Result<Waveforms> {
+    let schedule = Arc::new(Element::from_json(schedule)?);
+    let request: Request = serde_json::from_str(request).context("Invalid request")?;
+    let channels = request
+        .channels
+        .iter()
+        .map(|c| {
+            if c.sample_rate.value() <= 0.0 {
+                bail!("sample_rate of channel {} should be positive", c.name);
+            }
+            Ok(ChannelSpec {
+                name: c.name,
+                base_freq: c.base_freq,
+                sample_rate: c.sample_rate,
+                delay: c.delay,
+                align_level: c.align_level,
+                baseline: Complex64::new(c.baseline[0], c.baseline[1]),
+                ringdown: None,
+                net_zero: c.net_zero,
+                idle_tone: None,
+                length: c.length,
+                is_real: c.is_real,
+                time_grid: None,
+                memory: None,
+                filters: Filters::default(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let shapes = request
+        .shapes
+        .into_iter()
+        .map(|s| {
+            Ok(match s {
+                ShapeData::Hann { name } => (name, Shape::new_hann()),
+                ShapeData::Interp {
+                    name,
+                    knots,
+                    controls,
+                    degree,
+                } => (name, Shape::new_interp(knots, controls, degree)?),
+            })
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    let (waveforms, diagnostics) =
+        pipeline::generate_waveforms(&schedule, &channels, &shapes, None, request.options)?;
+    Ok(Waveforms {
+        channels: request
+            .channels
+            .iter()
+            .map(|c| c.name.to_string())
+            .collect(),
+        waveforms,
+        warnings: diagnostics
+            .warnings()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        quant::Amplitude,
+        schedule::{ElementCommonBuilder, Play, Stack},
+    };
+
+    fn schedule() -> String {
+        let play = Play::new(
+            ChannelId::new("xy0"),
+            Some(ShapeId::new("hann")),
+            Amplitude::new(0.5).unwrap(),
+            Time::new(20e-9).unwrap(),
+        )
+        .unwrap();
+        let common = ElementCommonBuilder::new().build().unwrap();
+        let stack = Stack::new().with_children(vec![Arc::new(Element::new(common.clone(), play))]);
+        Element::new(common, stack).to_json(None).unwrap()
+    }
+
+    #[test]
+    fn sample_schedule() {
+        let request = r#"{
+            "channels": [
+                {"name": "xy0", "base_freq": 100e6, "sample_rate": 2e9, "length": 100},
+                {"name": "z0", "sample_rate": 2e9, "length": 100, "is_real": true}
+            ],
+            "shapes": [{"name": "hann", "type": "hann"}]
+        }"#;
+
+        let waveforms = sample(&schedule(), request).unwrap();
+
+        assert_eq!(waveforms.channels(), ["xy0", "z0"]);
+        let i = waveforms.row("xy0", 0).unwrap();
+        assert_eq!(i.len(), 100);
+        assert!(i.iter().any(|&x| x != 0.0));
+        assert_eq!(waveforms.row("xy0", 1).unwrap().len(), 100);
+        assert!(waveforms.row("z0", 1).unwrap().is_empty());
+        assert!(waveforms.row("q0", 0).is_err());
+    }
+
+    #[test]
+    fn invalid_request() {
+        let unknown_field = r#"{"channels": [], "bogus": 1}"#;
+        assert!(sample(&schedule(), unknown_field).is_err());
+        let zero_rate = r#"{"channels": [{"name": "xy0", "sample_rate": 0.0, "length": 1}]}"#;
+        assert!(sample(&schedule(), zero_rate).is_err());
+        assert!(sample("{}", r#"{"channels": []}"#).is_err());
+    }
+}
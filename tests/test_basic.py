@@ -1,4 +1,5 @@
 import numpy as np
+import pytest
 
 import bosing
 
@@ -43,3 +44,59 @@ def test_mixing():
     w2 = w2 * np.exp(1j * (2 * np.pi * freq * np.arange(1000) / 2e9))
 
     assert np.allclose(w1, w2)
+
+
+def test_pickle():
+    import copy
+    import pickle
+
+    schedule = bosing.Stack(
+        bosing.Play("xy", "hann", 0.3, 100e-9),
+        bosing.Grid(bosing.Barrier("xy"), columns=["auto", "1*"]),
+        alignment="center",
+    )
+    channel = bosing.Channel(30e6, 2e9, 1000, ringdown=bosing.Ringdown(10e-9, 20e-9), iq_matrix=[[1, 0], [0, 1]])
+    loaded = pickle.loads(pickle.dumps(schedule))
+    assert isinstance(loaded, bosing.Stack)
+    assert loaded.to_json() == schedule.to_json()
+    assert copy.deepcopy(bosing.Alignment.Center) == bosing.Alignment.Center
+    channels = {"xy": channel}
+    shapes = {"hann": bosing.Interp([-0.5, -0.5, 0.5, 0.5], [0, 1], 1)}
+    expected = bosing.generate_waveforms(channels, shapes, schedule)
+    channels, shapes = pickle.loads(pickle.dumps((channels, shapes)))
+    result = bosing.generate_waveforms(channels, shapes, schedule)
+    np.testing.assert_array_equal(result["xy"], expected["xy"])
+
+
+def test_repeat_count_var():
+    child = bosing.Play("xy", "hann", 0.3, 100e-9)
+    assert bosing.Repeat(child, 3, count_var="n").count_var == "n"
+    with pytest.raises(ValueError):
+        bosing.Repeat(child, 3, count_var="q0.xy")
+    with pytest.raises(ValueError):
+        bosing.Repeat(child, 0, count_var="n")
+
+
+def test_compile_cache():
+    channels = {"xy": bosing.Channel(0, 2e9, 1000)}
+    shapes = {"hann": bosing.Hann()}
+    cache = bosing.CompileCache(maxsize=2)
+    results = []
+    for amplitude in [0.1, 0.2, 0.3, 0.3]:
+        schedule = bosing.Stack(bosing.Play("xy", "hann", amplitude, 100e-9))
+        results.append(bosing.generate_waveforms(channels, shapes, schedule, cache=cache)["xy"])
+    assert len(cache) == 2
+    assert cache.maxsize == 2
+    np.testing.assert_array_equal(results[2], results[3])
+    assert not np.array_equal(results[1], results[2])
+
+
+def test_multiplex_readout():
+    tones = [(-120e6, 0.2, 0.0, "hann"), (35e6, 0.15, 0.25, None)]
+    readout = bosing.multiplex_readout("m0", tones, 20e-9, plateau=1e-6)
+    assert isinstance(readout, bosing.Absolute)
+    plays = [entry.element for entry in readout.children]
+    assert [p.frequency for p in plays] == [-120e6, 35e6]
+    assert all(p.width == 20e-9 and p.plateau == 1e-6 for p in plays)
+    with pytest.raises(ValueError):
+        bosing.multiplex_readout("m0", [], 20e-9)